@@ -8,7 +8,7 @@ import { Breadcrumb } from "./components/layout/breadcrumb";
 import UppyUploader from "./components/uppy-uploader";
 import SharePage from "./components/share-page";
 import { filePickerTriggerAtom } from "./state/uppy";
-import { apiPath } from "./utils";
+import { apiPath, errorMessage } from "./utils";
 import { lsdirDataAtom } from "./state/drive";
 
 const { Content } = Layout;
@@ -27,7 +27,7 @@ function AppContent() {
         method: "MKCOL",
       });
       if (!(res.status >= 200 && res.status < 300)) {
-        throw new Error((await res.text()) || `Invalid status ${res.status}`);
+        throw new Error(await errorMessage(res));
       }
       // Navigate to the new folder
       const folderPath = location.pathname.endsWith("/")
@@ -1,4 +1,4 @@
-import { useState, useEffect, useTransition } from "react";
+import { useState, useEffect, useMemo, useTransition } from "react";
 import {
   Table,
   Button,
@@ -11,6 +11,7 @@ import {
   Row,
   Col,
   Dropdown,
+  Tag,
 } from "antd";
 import type { ColumnsType } from "antd/es/table";
 import type { MenuProps } from "antd";
@@ -30,20 +31,25 @@ import {
   ShareAltOutlined,
   MoreOutlined,
   EditOutlined,
+  CopyOutlined,
   FolderFilled,
+  WarningFilled,
 } from "@ant-design/icons";
-import { formatMtime, formatFileSize, formatDirSize, filePath } from "../utils";
+import { formatMtime, formatFileSize, formatDirSize, filePath, t } from "../utils";
 import Provenance from "./provenance";
 import { useLocation, useNavigate } from "react-router-dom";
 import FilePreviewDrawer from "./file-preview-drawer";
 import { useAtomValue, useSetAtom } from "jotai";
+import { loadable } from "jotai/utils";
 import {
   createShareLinkAtom,
   deleteFileAtom,
   moveFileAtom,
+  copyFileAtom,
   checkFileExistsAtom,
   getShareInfoAtom,
   deleteShareLinkAtom,
+  stampStatusAtomFamily,
   type ShareInfoItem,
 } from "../state/rest";
 import { lsdirAtom, useRefreshData } from "../state/drive";
@@ -55,20 +61,48 @@ export interface PathItem {
   size: number;
   sha256?: string;
   visibility?: "private" | "public";
+  partial?: boolean;
+  encryption?: {
+    scheme: "age" | "gpg";
+    recipients?: string[];
+  };
   provenance?: {
     events: Array<Record<string, unknown>>;
   };
-  stamp_status?: {
-    success: boolean;
-    results?: {
-      bitcoin: {
-        timestamp: number;
-        height: number;
-      };
-    };
-    error?: string;
-    sha256_hex?: string;
-  };
+}
+
+function VerificationStamps({ file }: { file: PathItem }) {
+  const statusLoadable = useAtomValue(
+    useMemo(() => loadable(stampStatusAtomFamily(file.name)), [file.name])
+  );
+  const status =
+    statusLoadable.state === "hasData" ? statusLoadable.data : null;
+  const stamps = (
+    <Provenance
+      file={{
+        type: "uploaded",
+        filePath: file.name,
+      }}
+      cachedResult={{
+        status: status?.success ? "verified" : "pending",
+        sha256_hex: status?.sha256_hex || "",
+        verified_chain: "bitcoin",
+        verified_timestamp: status?.results?.bitcoin.timestamp || 0,
+        verified_height: status?.results?.bitcoin.height || 0,
+      }}
+    />
+  );
+  if (!status?.modified_since_mint) {
+    return stamps;
+  }
+  return (
+    <Space size={4}>
+      <Tooltip title="Modified on disk since it was minted, the provenance refers to earlier content">
+        <WarningFilled style={{ color: "#faad14" }} />
+      </Tooltip>
+      {stamps}
+    </Space>
+  );
 }
 
 interface FilesTableProps {}
@@ -88,6 +122,7 @@ export default function FilesTable({}: FilesTableProps) {
   const createShareLink = useSetAtom(createShareLinkAtom);
   const deleteFile = useSetAtom(deleteFileAtom);
   const moveFile = useSetAtom(moveFileAtom);
+  const copyFile = useSetAtom(copyFileAtom);
   const checkFileExists = useSetAtom(checkFileExistsAtom);
   const getShareInfo = useSetAtom(getShareInfoAtom);
   const deleteShareLink = useSetAtom(deleteShareLinkAtom);
@@ -197,24 +232,9 @@ export default function FilesTable({}: FilesTableProps) {
     return <FileFilled className={iconClass} />;
   };
 
-  const renderVerificationStamps = (file: PathItem) => {
-    return (
-      <Provenance
-        file={{
-          type: "uploaded",
-          filePath: file.name,
-        }}
-        cachedResult={{
-          status: file.stamp_status?.success ? "verified" : "pending",
-          sha256_hex: file.stamp_status?.sha256_hex || "",
-          verified_chain: "bitcoin",
-          verified_timestamp:
-            file.stamp_status?.results?.bitcoin.timestamp || 0,
-          verified_height: file.stamp_status?.results?.bitcoin.height || 0,
-        }}
-      />
-    );
-  };
+  const renderVerificationStamps = (file: PathItem) => (
+    <VerificationStamps file={file} />
+  );
 
   const handleShare = async (file: PathItem) => {
     setSharingFile(file);
@@ -272,12 +292,12 @@ export default function FilesTable({}: FilesTableProps) {
 
   const handleDeleteShareLink = async (shareId: string) => {
     Modal.confirm({
-      title: "Remove share link",
+      title: t("remove_share_link", "Remove share link"),
       content:
         "Are you sure you want to remove this share link? People with this link will no longer be able to access the file.",
-      okText: "Remove",
+      okText: t("remove", "Remove"),
       okType: "danger",
-      cancelText: "Cancel",
+      cancelText: t("cancel", "Cancel"),
       onOk: async () => {
         try {
           await deleteShareLink(shareId);
@@ -296,11 +316,11 @@ export default function FilesTable({}: FilesTableProps) {
   const handleDelete = async (file: PathItem) => {
     const displayName = getBasename(file.name);
     Modal.confirm({
-      title: "Delete file",
+      title: t("delete_file", "Delete file"),
       content: `Are you sure you want to delete "${displayName}"?`,
-      okText: "Delete",
+      okText: t("delete", "Delete"),
       okType: "danger",
-      cancelText: "Cancel",
+      cancelText: t("cancel", "Cancel"),
       onOk: async () => {
         try {
           await deleteFile(file.name);
@@ -377,6 +397,39 @@ export default function FilesTable({}: FilesTableProps) {
     }
   };
 
+  // Copy next to the original as "name copy.ext"
+  const handleDuplicate = async (file: PathItem) => {
+    const isDir = file.path_type.endsWith("Dir");
+    const currentFilePath = decodeURIComponent(
+      file.name.includes("/")
+        ? "/" + file.name
+        : location.pathname +
+            (location.pathname.endsWith("/") ? "" : "/") +
+            encodeURIComponent(file.name)
+    );
+    const name = getBasename(file.name);
+    const dot = name.lastIndexOf(".");
+    const copyName =
+      !isDir && dot > 0
+        ? `${name.substring(0, dot)} copy${name.substring(dot)}`
+        : `${name} copy`;
+    const destinationPath =
+      currentFilePath.substring(0, currentFilePath.lastIndexOf("/") + 1) +
+      copyName;
+
+    try {
+      await copyFile({ fileName: currentFilePath, destinationPath });
+      message.success(`Copied to "${copyName}"`);
+      refresh();
+    } catch (err) {
+      const error = err as Error;
+      Modal.error({
+        title: "Copy failed",
+        content: `Cannot copy "${name}": ${error.message}`,
+      });
+    }
+  };
+
   const handleMove = async (file: PathItem, newPath?: string | null) => {
     // Build the current file path
     // If file.name contains slashes (like in search results: "PDFs/file.pdf"),
@@ -430,7 +483,7 @@ export default function FilesTable({}: FilesTableProps) {
               "A file already exists at this location. Do you want to override it?",
             okText: "Override",
             okType: "danger",
-            cancelText: "Cancel",
+            cancelText: t("cancel", "Cancel"),
             onOk: async () => {
               try {
                 await moveFile({ fileName: currentFilePath, destinationUrl });
@@ -461,10 +514,10 @@ export default function FilesTable({}: FilesTableProps) {
     if (!newPath) {
       let movePath = currentFilePath;
       Modal.confirm({
-        title: "Move file",
+        title: t("move_file", "Move file"),
         content: (
           <Input
-            placeholder="Enter new path"
+            placeholder={t("enter_new_path", "Enter new path")}
             defaultValue={currentFilePath}
             onChange={(e) => {
               movePath = e.target.value;
@@ -476,8 +529,8 @@ export default function FilesTable({}: FilesTableProps) {
             autoFocus
           />
         ),
-        okText: "Move",
-        cancelText: "Cancel",
+        okText: t("move", "Move"),
+        cancelText: t("cancel", "Cancel"),
         onOk: () => {
           performMove(movePath);
         },
@@ -622,7 +675,7 @@ export default function FilesTable({}: FilesTableProps) {
           download
           onClick={(e) => e.stopPropagation()}
         >
-          {isDir ? "Download as zip" : "Download"}
+          {isDir ? t("download_zip", "Download as zip") : t("download", "Download")}
         </a>
       ),
     });
@@ -631,17 +684,27 @@ export default function FilesTable({}: FilesTableProps) {
     items.push({
       key: "rename",
       icon: <EditOutlined />,
-      label: "Rename",
+      label: t("rename", "Rename"),
       onClick: () => {
         handleRename(file);
       },
     });
 
+    // Duplicate
+    items.push({
+      key: "duplicate",
+      icon: <CopyOutlined />,
+      label: t("duplicate", "Duplicate"),
+      onClick: () => {
+        handleDuplicate(file);
+      },
+    });
+
     // Move
     items.push({
       key: "move",
       icon: <DragOutlined />,
-      label: "Move",
+      label: t("move", "Move"),
       onClick: () => {
         handleMove(file);
       },
@@ -651,7 +714,7 @@ export default function FilesTable({}: FilesTableProps) {
     items.push({
       key: "delete",
       icon: <DeleteOutlined />,
-      label: "Delete",
+      label: t("delete", "Delete"),
       danger: true,
       onClick: () => {
         handleDelete(file);
@@ -673,7 +736,7 @@ export default function FilesTable({}: FilesTableProps) {
       icon: <DownloadOutlined />,
       label: (
         <a href={path + (isDir ? "?zip" : "?download")} download>
-          {isDir ? "Download as zip" : "Download"}
+          {isDir ? t("download_zip", "Download as zip") : t("download", "Download")}
         </a>
       ),
     });
@@ -683,7 +746,7 @@ export default function FilesTable({}: FilesTableProps) {
       items.push({
         key: "share",
         icon: <ShareAltOutlined />,
-        label: "Share",
+        label: t("share", "Share"),
         onClick: () => handleShare(file),
       });
     }
@@ -692,15 +755,23 @@ export default function FilesTable({}: FilesTableProps) {
     items.push({
       key: "rename",
       icon: <EditOutlined />,
-      label: "Rename",
+      label: t("rename", "Rename"),
       onClick: () => handleRename(file),
     });
 
+    // Duplicate
+    items.push({
+      key: "duplicate",
+      icon: <CopyOutlined />,
+      label: t("duplicate", "Duplicate"),
+      onClick: () => handleDuplicate(file),
+    });
+
     // Move
     items.push({
       key: "move",
       icon: <DragOutlined />,
-      label: "Move",
+      label: t("move", "Move"),
       onClick: () => handleMove(file),
     });
 
@@ -708,7 +779,7 @@ export default function FilesTable({}: FilesTableProps) {
     items.push({
       key: "delete",
       icon: <DeleteOutlined />,
-      label: "Delete",
+      label: t("delete", "Delete"),
       danger: true,
       onClick: () => handleDelete(file),
     });
@@ -820,7 +891,7 @@ export default function FilesTable({}: FilesTableProps) {
 
   const columns: ColumnsType<PathItem> = [
     {
-      title: "Name",
+      title: t("name", "Name"),
       dataIndex: "name",
       key: "name",
       ellipsis: true,
@@ -833,6 +904,22 @@ export default function FilesTable({}: FilesTableProps) {
               {getFileIcon(file)}
             </div>
             <span className="text-gray-900">{displayName}</span>
+            {file.partial && (
+              <Tooltip title="Upload in progress or interrupted">
+                <Tag color="orange">{t("incomplete", "Incomplete")}</Tag>
+              </Tooltip>
+            )}
+            {file.encryption && (
+              <Tooltip
+                title={
+                  file.encryption.recipients?.length
+                    ? `Encrypted for ${file.encryption.recipients.join(", ")}`
+                    : "Encrypted before upload"
+                }
+              >
+                <Tag color="purple">{file.encryption.scheme}</Tag>
+              </Tooltip>
+            )}
           </Space>
         );
       },
@@ -853,7 +940,7 @@ export default function FilesTable({}: FilesTableProps) {
       },
     },
     {
-      title: "Verification",
+      title: t("verification", "Verification"),
       key: "verification",
       width: 150,
       align: "center",
@@ -863,7 +950,7 @@ export default function FilesTable({}: FilesTableProps) {
       },
     },
     {
-      title: "Who can access",
+      title: t("who_can_access", "Who can access"),
       key: "visibility",
       width: 160,
       align: "center",
@@ -878,13 +965,15 @@ export default function FilesTable({}: FilesTableProps) {
               visibility === "public" ? "text-green-600" : "text-gray-500"
             }
           >
-            {visibility === "public" ? "Anyone with the link" : "Only you"}
+            {visibility === "public"
+              ? t("anyone_with_link", "Anyone with the link")
+              : t("only_you", "Only you")}
           </span>
         );
       },
     },
     {
-      title: "Size",
+      title: t("size", "Size"),
       key: "size",
       width: 120,
       align: "right",
@@ -897,7 +986,7 @@ export default function FilesTable({}: FilesTableProps) {
       },
     },
     {
-      title: "Modified",
+      title: t("modified", "Modified"),
       dataIndex: "mtime",
       key: "mtime",
       width: 180,
@@ -907,7 +996,7 @@ export default function FilesTable({}: FilesTableProps) {
       ),
     },
     {
-      title: "Actions",
+      title: t("actions", "Actions"),
       key: "actions",
       width: 120,
       align: "right",
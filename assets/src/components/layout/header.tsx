@@ -10,6 +10,7 @@ import {
   MoreOutlined,
 } from "@ant-design/icons";
 import NodeLogo from "../vectors/node-logo.js";
+import { brand, t } from "../../utils";
 
 const { Header: AntHeader } = Layout;
 
@@ -78,18 +79,25 @@ export function Header({
 
         {/* Logo/Brand */}
         <div className="flex items-center gap-2">
-          <div className="flex items-center justify-center w-8 h-8 rounded-lg bg-blue-500">
-            <NodeLogo />
+          <div
+            className="flex items-center justify-center w-8 h-8 rounded-lg bg-blue-500 overflow-hidden"
+            style={brand.themeColor ? { backgroundColor: brand.themeColor } : undefined}
+          >
+            {brand.logo ? (
+              <img src={brand.logo} alt={brand.name} className="w-full h-full object-contain" />
+            ) : (
+              <NodeLogo />
+            )}
           </div>
           <span className="font-semibold text-base hidden sm:inline">
-            Node Drive
+            {brand.name}
           </span>
         </div>
 
         {/* Desktop Search Bar */}
         <div className="hidden md:flex flex-1 max-w-[500px]">
           <Input
-            placeholder="Search files and folders..."
+            placeholder={t("search_files", "Search files and folders...")}
             prefix={<SearchOutlined />}
             value={searchQuery}
             onChange={(e) => setSearchQuery(e.target.value)}
@@ -102,7 +110,7 @@ export function Header({
         <div className="md:hidden flex-1 flex justify-end">
           {showMobileSearch ? (
             <Input
-              placeholder="Search..."
+              placeholder={`${t("search", "Search")}...`}
               prefix={<SearchOutlined />}
               value={searchQuery}
               onChange={(e) => setSearchQuery(e.target.value)}
@@ -154,7 +162,7 @@ export function Header({
 
       {/* Mobile Drawer */}
       <Drawer
-        title="Menu"
+        title={t("menu", "Menu")}
         placement="left"
         onClose={() => setMobileDrawerOpen(false)}
         open={mobileDrawerOpen}
@@ -44,6 +44,8 @@ const UppyUploader = ({ auth, onAuthRequired }: UppyUploaderProps) => {
           return apiPath(fileName);
         },
         method: "PUT",
+        // Lets the upload be followed at /__dufs__/uploads/<id> while it runs
+        headers: (file) => ({ "X-Upload-Id": String(file.meta.uploadId) }),
         formData: false,
         fieldName: "file",
         allowedMetaFields: [],
@@ -112,7 +114,8 @@ const UppyUploader = ({ auth, onAuthRequired }: UppyUploaderProps) => {
 
   // Track when files are added or removed
   useEffect(() => {
-    const handleFileAdded = () => {
+    const handleFileAdded = (file: { id: string }) => {
+      uppy.setFileMeta(file.id, { uploadId: crypto.randomUUID() });
       setHasFiles(uppy.getFiles().length > 0);
       if (auth) {
         onAuthRequired().catch((err) => {
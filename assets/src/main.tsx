@@ -1,7 +1,9 @@
 import React from "react";
 import { createRoot } from "react-dom/client";
 import { BrowserRouter } from "react-router-dom";
+import { ConfigProvider } from "antd";
 import App from "./app";
+import { brand } from "./utils";
 import "antd/dist/reset.css";
 import "./main.css";
 
@@ -17,9 +19,15 @@ window.addEventListener("DOMContentLoaded", () => {
   const root = createRoot(rootElement);
   root.render(
     <React.StrictMode>
-      <BrowserRouter>
-        <App />
-      </BrowserRouter>
+      <ConfigProvider
+        theme={
+          brand.themeColor ? { token: { colorPrimary: brand.themeColor } } : undefined
+        }
+      >
+        <BrowserRouter>
+          <App />
+        </BrowserRouter>
+      </ConfigProvider>
     </React.StrictMode>
   );
 });
@@ -1,6 +1,6 @@
 import { atomFamily, atomWithRefresh } from "jotai/utils";
 import { atom, useSetAtom } from "jotai";
-import { fetchJsonWithError } from "../utils";
+import { fetchJsonWithError, setLocale } from "../utils";
 import { LsDirData } from "../type";
 import { useLocation } from "react-router-dom";
 
@@ -10,6 +10,7 @@ export const lsdirDataAtom = atomFamily((path: string) =>
       `/api/${path}`,
       `Failed to fetch data`
     );
+    setLocale(data.locale);
     return data;
   })
 );
@@ -10,6 +10,29 @@ import {
 } from "../utils";
 import { lsdirDataAtom } from "./drive";
 
+// ----- Stamp Status Fetching -----
+export interface StampStatus {
+  success: boolean;
+  results?: {
+    bitcoin: {
+      timestamp: number;
+      height: number;
+    };
+  };
+  error?: string;
+  sha256_hex?: string;
+  modified_since_mint?: boolean;
+}
+
+// Listings only summarize stamping, each file's status is fetched on its own
+export const stampStatusAtomFamily = atomFamily((fileName: string) =>
+  atom(async () => {
+    return await fetchJson<StampStatus>(
+      apiPath(fileName) + "?stamp-status"
+    ).catch(() => null);
+  })
+);
+
 // ----- Share Info Fetching -----
 export interface ShareInfo {
   share_id: string;
@@ -20,17 +43,7 @@ export interface ShareInfo {
   owner_pubkey_hex: string;
   share_signature_hex: string;
   is_active: boolean;
-  stamp_status?: {
-    success: boolean;
-    results?: {
-      bitcoin: {
-        timestamp: number;
-        height: number;
-      };
-    };
-    error?: string;
-    sha256_hex?: string;
-  };
+  stamp_status?: StampStatus;
 }
 
 export const shareInfoAtomFamily = atomFamily((shareId: string) =>
@@ -150,6 +163,25 @@ export const moveFileAtom = atom(
   }
 );
 
+// Copy/duplicate file or folder, cloning its provenance
+interface CopyFileParams {
+  fileName: string; // Absolute path like "/PDFs/file.pdf"
+  destinationPath: string; // Absolute path like "/PDFs/file copy.pdf"
+}
+
+export const copyFileAtom = atom(
+  null,
+  async (_get, set, params: CopyFileParams) => {
+    await fetchMutation(
+      `/api${params.fileName}?copy=${encodeURIComponent(params.destinationPath)}`,
+      { method: "POST" }
+    );
+
+    // Refresh main data after copy
+    set(lsdirDataAtom(params.destinationPath));
+  }
+);
+
 // Check if file exists (HEAD request)
 export const checkFileExistsAtom = atom(
   null,
@@ -68,4 +68,32 @@ export interface LsDirData {
   user: string;
   dir_exists: boolean;
   editable: string;
+  stamps?: StampSummary;
+  brand: Brand;
+  locale?: Locale;
+}
+
+export interface StampSummary {
+  n_confirmed: number;
+  n_pending: number;
+}
+
+/**
+ * Body of error responses from the API and `__dufs__` routes
+ */
+export interface ApiError {
+  code: string;
+  message: string;
+  details: unknown;
+}
+
+export interface Locale {
+  lang: string;
+  strings: Record<string, string>;
+}
+
+export interface Brand {
+  name: string;
+  logo?: string;
+  theme_color?: string;
 }
@@ -1,3 +1,5 @@
+import type { ApiError } from "../type";
+
 /**
  * Fetch JSON from URL with automatic error handling
  */
@@ -72,8 +74,21 @@ export async function fetchMutation(
   }
 }
 
+/**
+ * Message of an error response, from its `{code, message, details}` body
+ */
+export async function errorMessage(res: Response): Promise<string> {
+  const text = await res.text();
+  try {
+    const error: ApiError = JSON.parse(text);
+    return error.message || `Invalid status ${res.status}`;
+  } catch {
+    return text || `Invalid status ${res.status}`;
+  }
+}
+
 export async function assertResOK(res: Response): Promise<void> {
   if (!(res.status >= 200 && res.status < 300)) {
-    throw new Error((await res.text()) || `Invalid status ${res.status}`);
+    throw new Error(await errorMessage(res));
   }
 }
@@ -0,0 +1,22 @@
+/**
+ * Read a branding value the server filled into a `<meta>` tag, ignoring
+ * placeholders left in by the dev server
+ */
+function brandMeta(name: string): string | undefined {
+  const content = document
+    .querySelector<HTMLMetaElement>(`meta[name="${name}"]`)
+    ?.content.trim();
+  if (!content || content.startsWith("__")) {
+    return undefined;
+  }
+  return content;
+}
+
+/**
+ * White-labeling from `--brand-name`, `--brand-logo` and `--theme-color`
+ */
+export const brand = {
+  name: brandMeta("application-name") ?? "Node Drive",
+  logo: brandMeta("brand-logo"),
+  themeColor: brandMeta("theme-color"),
+};
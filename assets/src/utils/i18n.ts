@@ -0,0 +1,23 @@
+import type { Locale } from "../type";
+
+/**
+ * Strings of the web UI from the server, in the language of `--lang` or the
+ * browser's `Accept-Language`
+ */
+let strings: Record<string, string> = {};
+
+export function setLocale(locale?: Locale) {
+  if (!locale) {
+    return;
+  }
+  strings = locale.strings;
+  document.documentElement.lang = locale.lang;
+}
+
+/**
+ * A string of the web UI, or its English `fallback` until the first listing
+ * has arrived
+ */
+export function t(key: string, fallback: string): string {
+  return strings[key] ?? fallback;
+}
@@ -4,4 +4,5 @@ export * from './file';
 export * from './path';
 export * from './format';
 export * from './api';
-export * from './string';
\ No newline at end of file
+export * from './string';export * from './brand';
+export * from './i18n';
@@ -1,3 +1,4 @@
+use std::io::Write;
 use std::path::Path;
 use std::process::Command;
 
@@ -49,5 +50,42 @@ fn main() {
         panic!("pnpm build failed");
     }
 
+    precompress(&assets_dir.join("dist"));
+
     println!("cargo:warning=Frontend assets built successfully");
 }
+
+/// Write a Brotli `<file>.br` next to each text asset, served in its place
+/// to clients that accept it
+fn precompress(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            precompress(&path);
+            continue;
+        }
+        let compressible = matches!(
+            path.extension().and_then(|v| v.to_str()),
+            Some("js" | "css" | "html" | "svg" | "json" | "map")
+        );
+        if !compressible {
+            continue;
+        }
+        let Ok(data) = std::fs::read(&path) else {
+            continue;
+        };
+        let mut target = path.as_os_str().to_owned();
+        target.push(".br");
+        let written = std::fs::File::create(&target).and_then(|file| {
+            let mut writer = brotli::CompressorWriter::new(file, 4096, 11, 22);
+            writer.write_all(&data)?;
+            writer.flush()
+        });
+        if written.is_err() {
+            println!("cargo:warning=Failed to precompress {}", path.display());
+        }
+    }
+}
@@ -1,17 +1,33 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use async_zip::Compression;
 use clap::builder::{PossibleValue, PossibleValuesParser};
 use clap::{value_parser, Arg, ArgAction, ArgMatches, Command, ValueEnum};
 use clap_complete::{generate, Generator, Shell};
+use hyper::{header::HeaderName, Method};
 use serde::{Deserialize, Deserializer};
 use smart_default::SmartDefault;
+use std::collections::BTreeMap;
 use std::env;
 use std::net::IpAddr;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use crate::auth::AccessControl;
+use crate::error_reporter::ErrorReporter;
 use crate::http_logger::HttpLogger;
-use crate::utils::encode_uri;
+use crate::i18n::Locale;
+use crate::ip_filter::IpList;
+use crate::logger::{LogRotation, RotateInterval};
+use crate::oidc::OidcConfig;
+use crate::provenance::DbEncryption;
+use crate::quota::QuotaPolicy;
+use crate::retention::{parse_age, RetentionPolicy};
+use crate::upload_types::{ExtensionList, TypeLimits};
+use crate::utils::{encode_uri, parse_size};
+
+/// Environment variable holding the provenance database key, which is never
+/// taken on the command line where other users could read it
+pub const PROVENANCE_DB_KEY_ENV: &str = "DUFS_PROVENANCE_DB_KEY";
 
 pub fn build_cli() -> Command {
     let app = Command::new(env!("CARGO_CRATE_NAME"))
@@ -22,12 +38,15 @@ pub fn build_cli() -> Command {
             " - ",
             env!("CARGO_PKG_REPOSITORY")
         ))
+        // `node-drive <serve-path>... gc` ends the serve paths at `gc`
+        .subcommand_precedence_over_arg(true)
         .arg(
             Arg::new("serve-path")
                 .env("DUFS_SERVE_PATH")
 				.hide_env(true)
                 .value_parser(value_parser!(PathBuf))
-                .help("Specific path to serve [default: .]"),
+                .num_args(1..)
+                .help("Specific path to serve [default: .], or several as /<name>:<path> mounts"),
         )
         .arg(
             Arg::new("config")
@@ -36,7 +55,7 @@ pub fn build_cli() -> Command {
                 .short('c')
                 .long("config")
                 .value_parser(value_parser!(PathBuf))
-                .help("Specify configuration file")
+                .help("Specify configuration file, YAML or TOML (*.toml), reloaded on SIGHUP")
                 .value_name("file"),
         )
         .arg(
@@ -45,7 +64,7 @@ pub fn build_cli() -> Command {
 				.hide_env(true)
                 .short('b')
                 .long("bind")
-                .help("Specify bind address or unix socket")
+                .help("Specify bind address or unix socket, e.g. unix:/run/node-drive.sock")
                 .action(ArgAction::Append)
                 .value_delimiter(',')
                 .value_name("addrs"),
@@ -98,6 +117,172 @@ pub fn build_cli() -> Command {
                 .value_parser(PossibleValuesParser::new(["basic", "digest"]))
                 .default_value("digest")
         )
+        .arg(
+            Arg::new("auth-exec")
+                .env("DUFS_AUTH_EXEC")
+                .hide_env(true)
+                .long("auth-exec")
+                .value_name("path")
+                .value_parser(value_parser!(PathBuf))
+                .help("Check logins of users not in --auth with this command, which prints the paths they may access"),
+        )
+        .arg(
+            Arg::new("oidc-issuer")
+                .env("DUFS_OIDC_ISSUER")
+                .hide_env(true)
+                .long("oidc-issuer")
+                .value_name("url")
+                .help("Also let users log in with this OpenID Connect provider"),
+        )
+        .arg(
+            Arg::new("oidc-client-id")
+                .env("DUFS_OIDC_CLIENT_ID")
+                .hide_env(true)
+                .long("oidc-client-id")
+                .value_name("id")
+                .help("Client ID registered with the OpenID Connect provider"),
+        )
+        .arg(
+            Arg::new("oidc-client-secret")
+                .env("DUFS_OIDC_CLIENT_SECRET")
+                .hide_env(true)
+                .long("oidc-client-secret")
+                .value_name("secret")
+                .help("Client secret, for providers that don't accept public clients"),
+        )
+        .arg(
+            Arg::new("oidc-claim")
+                .env("DUFS_OIDC_CLAIM")
+                .hide_env(true)
+                .long("oidc-claim")
+                .value_name("claim")
+                .help("ID token claim naming the --auth user, may be a list like groups [default: preferred_username]"),
+        )
+        .arg(
+            Arg::new("retention")
+                .env("DUFS_RETENTION")
+                .hide_env(true)
+                .long("retention")
+                .help("Auto-delete files older than an age, e.g. /incoming:30d,/tmp:12h")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .value_name("rules"),
+        )
+        .arg(
+            Arg::new("quota")
+                .env("DUFS_QUOTA")
+                .hide_env(true)
+                .long("quota")
+                .help("Limit the storage used under a path or by a user, e.g. /dir1:10GB,@alice:1GB")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .value_name("rules"),
+        )
+        .arg(
+            Arg::new("trash-dir")
+                .env("DUFS_TRASH_DIR")
+                .hide_env(true)
+                .long("trash-dir")
+                .value_name("dir")
+                .value_parser(value_parser!(PathBuf))
+                .help("Move deleted files into this directory instead of removing them"),
+        )
+        .arg(
+            Arg::new("trash-retention")
+                .env("DUFS_TRASH_RETENTION")
+                .hide_env(true)
+                .long("trash-retention")
+                .value_name("age")
+                .help("Purge trash items deleted longer ago than an age, e.g. 30d"),
+        )
+        .arg(
+            Arg::new("allow-anonymous-drop")
+                .env("DUFS_ALLOW_ANONYMOUS_DROP")
+                .hide_env(true)
+                .long("allow-anonymous-drop")
+                .help("Let anyone upload new files into a directory they can't list or read, e.g. /incoming")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .value_name("dir"),
+        )
+        .arg(
+            Arg::new("anonymous-drop-limit")
+                .env("DUFS_ANONYMOUS_DROP_LIMIT")
+                .hide_env(true)
+                .long("anonymous-drop-limit")
+                .value_name("size")
+                .help("Bytes each client IP may drop per day, e.g. 500M [default: 100M]"),
+        )
+        .arg(
+            Arg::new("max-upload-size")
+                .env("DUFS_MAX_UPLOAD_SIZE")
+                .hide_env(true)
+                .long("max-upload-size")
+                .value_name("size")
+                .help("Largest file, archive or bundle that may be uploaded, e.g. 10G"),
+        )
+        .arg(
+            Arg::new("allow-upload-types")
+                .env("DUFS_ALLOW_UPLOAD_TYPES")
+                .hide_env(true)
+                .long("allow-upload-types")
+                .help("Only accept uploads with these file extensions, e.g. jpg,png,pdf")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .value_name("exts"),
+        )
+        .arg(
+            Arg::new("deny-upload-types")
+                .env("DUFS_DENY_UPLOAD_TYPES")
+                .hide_env(true)
+                .long("deny-upload-types")
+                .help("Refuse uploads with these file extensions, e.g. exe,bat,msi")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .value_name("exts"),
+        )
+        .arg(
+            Arg::new("upload-type-limits")
+                .env("DUFS_UPLOAD_TYPE_LIMITS")
+                .hide_env(true)
+                .long("upload-type-limits")
+                .help("Largest upload per file extension, e.g. mp4:2G,mov:2G")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .value_name("rules"),
+        )
+        .arg(
+            Arg::new("durable-writes")
+                .env("DUFS_DURABLE_WRITES")
+                .hide_env(true)
+                .long("durable-writes")
+                .action(ArgAction::SetTrue)
+                .help("Flush uploads to disk before answering them"),
+        )
+        .arg(
+            Arg::new("partial-upload-retention")
+                .env("DUFS_PARTIAL_UPLOAD_RETENTION")
+                .hide_env(true)
+                .long("partial-upload-retention")
+                .value_name("age")
+                .help("Remove interrupted uploads not resumed within an age [default: 1d]"),
+        )
+        .arg(
+            Arg::new("index")
+                .env("DUFS_INDEX")
+                .hide_env(true)
+                .long("index")
+                .action(ArgAction::SetTrue)
+                .help("Keep a search index of file names, updated by watching the filesystem"),
+        )
+        .arg(
+            Arg::new("dir-cache")
+                .env("DUFS_DIR_CACHE")
+                .hide_env(true)
+                .long("dir-cache")
+                .action(ArgAction::SetTrue)
+                .help("Cache directory listings in memory, invalidated by watching the filesystem"),
+        )
         .arg(
             Arg::new("allow-upload")
                 .env("DUFS_ALLOW_UPLOAD")
@@ -146,6 +331,36 @@ pub fn build_cli() -> Command {
                 .action(ArgAction::SetTrue)
                 .help("Enable CORS, sets `Access-Control-Allow-Origin: *`"),
         )
+        .arg(
+            Arg::new("cors-origins")
+                .env("DUFS_CORS_ORIGINS")
+                .hide_env(true)
+                .long("cors-origins")
+                .help("Enable CORS for these origins only, with credentials, e.g. https://app.example.com; `*` lets others in without credentials")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .value_name("origins"),
+        )
+        .arg(
+            Arg::new("cors-methods")
+                .env("DUFS_CORS_METHODS")
+                .hide_env(true)
+                .long("cors-methods")
+                .help("Methods allowed in cross-origin requests, e.g. GET,PUT")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .value_name("methods"),
+        )
+        .arg(
+            Arg::new("cors-headers")
+                .env("DUFS_CORS_HEADERS")
+                .hide_env(true)
+                .long("cors-headers")
+                .help("Request headers allowed in cross-origin requests, e.g. Authorization,Content-Type")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .value_name("headers"),
+        )
         .arg(
             Arg::new("render-index")
                 .env("DUFS_RENDER_INDEX")
@@ -162,6 +377,55 @@ pub fn build_cli() -> Command {
                 .action(ArgAction::SetTrue)
                 .help("Serve index.html when requesting a directory, returns directory listing if not found index.html"),
         )
+        .arg(
+            Arg::new("render-readme")
+                .env("DUFS_RENDER_README")
+                .hide_env(true)
+                .long("render-readme")
+                .action(ArgAction::SetTrue)
+                .help("Add the README.md of a directory to its listing, rendered to HTML"),
+        )
+        .arg(
+            Arg::new("error-pages")
+                .env("DUFS_ERROR_PAGES")
+                .hide_env(true)
+                .long("error-pages")
+                .value_name("dir")
+                .value_parser(value_parser!(PathBuf))
+                .help("Serve browsers <status>.html pages from this directory on errors, e.g. 404.html"),
+        )
+        .arg(
+            Arg::new("brand-name")
+                .env("DUFS_BRAND_NAME")
+                .hide_env(true)
+                .long("brand-name")
+                .value_name("name")
+                .help("Name shown in the web UI instead of Node Drive"),
+        )
+        .arg(
+            Arg::new("brand-logo")
+                .env("DUFS_BRAND_LOGO")
+                .hide_env(true)
+                .long("brand-logo")
+                .value_name("url")
+                .help("URL of a logo shown in the web UI"),
+        )
+        .arg(
+            Arg::new("theme-color")
+                .env("DUFS_THEME_COLOR")
+                .hide_env(true)
+                .long("theme-color")
+                .value_name("color")
+                .help("Accent color of the web UI, e.g. #0b5fff"),
+        )
+        .arg(
+            Arg::new("lang")
+                .env("DUFS_LANG")
+                .hide_env(true)
+                .long("lang")
+                .value_name("lang")
+                .help("Language of the web UI, e.g. de [default: from the browser]"),
+        )
         .arg(
             Arg::new("render-spa")
                 .env("DUFS_RENDER_SPA")
@@ -176,7 +440,7 @@ pub fn build_cli() -> Command {
                 .hide_env(true)
                 .long("log-format")
                 .value_name("format")
-                .help("Customize http log format"),
+                .help("Customize http log format, or `json` for one JSON object per line"),
         )
         .arg(
             Arg::new("log-file")
@@ -187,6 +451,40 @@ pub fn build_cli() -> Command {
                 .value_parser(value_parser!(PathBuf))
                 .help("Specify the file to save logs to, other than stdout/stderr"),
         )
+        .arg(
+            Arg::new("log-rotate-size")
+                .env("DUFS_LOG_ROTATE_SIZE")
+                .hide_env(true)
+                .long("log-rotate-size")
+                .value_name("size")
+                .help("Rotate the log file once it would grow past a size, e.g. 10M"),
+        )
+        .arg(
+            Arg::new("log-rotate-interval")
+                .env("DUFS_LOG_ROTATE_INTERVAL")
+                .hide_env(true)
+                .long("log-rotate-interval")
+                .value_name("interval")
+                .value_parser(clap::builder::EnumValueParser::<RotateInterval>::new())
+                .help("Start a new log file every hour or day"),
+        )
+        .arg(
+            Arg::new("log-keep")
+                .env("DUFS_LOG_KEEP")
+                .hide_env(true)
+                .long("log-keep")
+                .value_name("count")
+                .value_parser(value_parser!(usize))
+                .help("Number of rotated log files to keep [default: 5]"),
+        )
+        .arg(
+            Arg::new("error-report-url")
+                .env("DUFS_ERROR_REPORT_URL")
+                .hide_env(true)
+                .long("error-report-url")
+                .value_name("url")
+                .help("Report internal server errors to a Sentry DSN or a webhook url"),
+        )
         .arg(
             Arg::new("compress")
                 .env("DUFS_COMPRESS")
@@ -196,12 +494,272 @@ pub fn build_cli() -> Command {
                 .value_name("level")
                 .help("Set zip compress level [default: low]")
         )
+        .arg(
+            Arg::new("max-bandwidth")
+                .env("DUFS_MAX_BANDWIDTH")
+                .hide_env(true)
+                .long("max-bandwidth")
+                .value_name("rate")
+                .help("Limit each download to a rate in bytes per second, e.g. 512K or 10M"),
+        )
+        .arg(
+            Arg::new("max-range-streams")
+                .env("DUFS_MAX_RANGE_STREAMS")
+                .hide_env(true)
+                .long("max-range-streams")
+                .value_name("count")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("Serve at most this many Range requests for the same file at once, queueing the rest"),
+        )
+        .arg(
+            Arg::new("rate-limit")
+                .env("DUFS_RATE_LIMIT")
+                .hide_env(true)
+                .long("rate-limit")
+                .value_name("rps")
+                .value_parser(value_parser!(u32).range(1..))
+                .help("Limit the requests per second from each client IP, answering 429 beyond it"),
+        )
+        .arg(
+            Arg::new("allow-ip")
+                .env("DUFS_ALLOW_IP")
+                .hide_env(true)
+                .long("allow-ip")
+                .help("Only serve clients from these addresses or networks, e.g. 10.0.0.0/8,::1")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .value_name("cidrs"),
+        )
+        .arg(
+            Arg::new("deny-ip")
+                .env("DUFS_DENY_IP")
+                .hide_env(true)
+                .long("deny-ip")
+                .help("Refuse clients from these addresses or networks, e.g. 203.0.113.0/24")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .value_name("cidrs"),
+        )
+        .arg(
+            Arg::new("trusted-proxies")
+                .env("DUFS_TRUSTED_PROXIES")
+                .hide_env(true)
+                .long("trusted-proxies")
+                .help("Take the client address from X-Forwarded-For or Forwarded on requests from these proxies, e.g. 127.0.0.1")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .value_name("cidrs"),
+        )
+        .arg(
+            Arg::new("user-rate-limit")
+                .env("DUFS_USER_RATE_LIMIT")
+                .hide_env(true)
+                .long("user-rate-limit")
+                .value_name("rps")
+                .value_parser(value_parser!(u32).range(1..))
+                .help("Limit the requests per second of each authenticated user"),
+        )
+        .arg(
+            Arg::new("max-concurrent-uploads")
+                .env("DUFS_MAX_CONCURRENT_UPLOADS")
+                .hide_env(true)
+                .long("max-concurrent-uploads")
+                .value_name("count")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("Limit the uploads in progress per client IP and per user"),
+        )
+        .arg(
+            Arg::new("max-login-failures")
+                .env("DUFS_MAX_LOGIN_FAILURES")
+                .hide_env(true)
+                .long("max-login-failures")
+                .value_name("count")
+                .value_parser(value_parser!(u32))
+                .help("Lock a user out of a client IP for a while after this many failed logins in a row, 0 to never [default: 10]"),
+        )
+        .arg(
+            Arg::new("propfind-max-entries")
+                .env("DUFS_PROPFIND_MAX_ENTRIES")
+                .hide_env(true)
+                .long("propfind-max-entries")
+                .value_name("count")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("Answer WebDAV PROPFIND with Depth: infinity for folders of up to this many entries"),
+        )
+        .arg(
+            Arg::new("shutdown-timeout")
+                .env("DUFS_SHUTDOWN_TIMEOUT")
+                .hide_env(true)
+                .long("shutdown-timeout")
+                .value_name("secs")
+                .value_parser(value_parser!(u64))
+                .help("Seconds to let in-flight requests finish after SIGTERM or Ctrl-C [default: 30]"),
+        )
+        .arg(
+            Arg::new("provenance-local-db")
+                .env("DUFS_PROVENANCE_LOCAL_DB")
+                .hide_env(true)
+                .long("provenance-local-db")
+                .value_name("path")
+                .value_parser(value_parser!(PathBuf))
+                .help("SQLite database for the shares, annotations and keys of this instance when the provenance chain is in Postgres [default: provenance.db]"),
+        )
+        .arg(
+            Arg::new("provenance-db-key-file")
+                .env("DUFS_PROVENANCE_DB_KEY_FILE")
+                .hide_env(true)
+                .long("provenance-db-key-file")
+                .value_name("file")
+                .value_parser(value_parser!(PathBuf))
+                .help("Read the provenance database encryption key from a file (requires SQLCipher build)"),
+        )
+        .arg(
+            Arg::new("provenance-db-rekey-file")
+                .env("DUFS_PROVENANCE_DB_REKEY_FILE")
+                .hide_env(true)
+                .long("provenance-db-rekey-file")
+                .value_name("file")
+                .value_parser(value_parser!(PathBuf))
+                .help("Re-encrypt the provenance database with the key in this file on startup"),
+        )
+        .arg(
+            Arg::new("provenance-backup-dir")
+                .env("DUFS_PROVENANCE_BACKUP_DIR")
+                .hide_env(true)
+                .long("provenance-backup-dir")
+                .value_name("dir")
+                .value_parser(value_parser!(PathBuf))
+                .help("Periodically snapshot the provenance database into this directory"),
+        )
+        .arg(
+            Arg::new("provenance-backup-interval")
+                .env("DUFS_PROVENANCE_BACKUP_INTERVAL")
+                .hide_env(true)
+                .long("provenance-backup-interval")
+                .value_name("mins")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("Minutes between provenance database snapshots [default: 1440]"),
+        )
+        .arg(
+            Arg::new("provenance-backup-keep")
+                .env("DUFS_PROVENANCE_BACKUP_KEEP")
+                .hide_env(true)
+                .long("provenance-backup-keep")
+                .value_name("count")
+                .value_parser(value_parser!(usize))
+                .help("Number of provenance database snapshots to keep [default: 7]"),
+        )
+        .arg(
+            Arg::new("provenance-anchor-interval")
+                .env("DUFS_PROVENANCE_ANCHOR_INTERVAL")
+                .hide_env(true)
+                .long("provenance-anchor-interval")
+                .value_name("mins")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("Minutes between OpenTimestamps anchors of the merkle root over all events"),
+        )
+        .arg(
+            Arg::new("integrity-scan-interval")
+                .env("DUFS_INTEGRITY_SCAN_INTERVAL")
+                .hide_env(true)
+                .long("integrity-scan-interval")
+                .value_name("mins")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("Minutes between scans for files modified since they were minted"),
+        )
+        .arg(
+            Arg::new("ots-batch-interval")
+                .env("DUFS_OTS_BATCH_INTERVAL")
+                .hide_env(true)
+                .long("ots-batch-interval")
+                .value_name("secs")
+                .value_parser(value_parser!(u64))
+                .help("Seconds to gather digests into one OpenTimestamps calendar submission [default: 0]"),
+        )
+        .arg(
+            Arg::new("ots-offline")
+                .env("DUFS_OTS_OFFLINE")
+                .hide_env(true)
+                .long("ots-offline")
+                .action(ArgAction::SetTrue)
+                .help("Don't wait for OpenTimestamps calendars on upload, queue the digests for stamping in the background"),
+        )
+        .arg(
+            Arg::new("dedup-dir")
+                .env("DUFS_DEDUP_DIR")
+                .hide_env(true)
+                .long("dedup-dir")
+                .value_name("dir")
+                .value_parser(value_parser!(PathBuf))
+                .help("Split uploads into chunks stored once by content hash in this directory"),
+        )
+        .arg(
+            Arg::new("tenant-db-dir")
+                .env("DUFS_TENANT_DB_DIR")
+                .hide_env(true)
+                .long("tenant-db-dir")
+                .value_name("dir")
+                .value_parser(value_parser!(PathBuf))
+                .help("Give each top-level directory its own provenance database, shares and signing key, stored in this directory"),
+        )
+        .arg(
+            Arg::new("signing-key")
+                .env("DUFS_SIGNING_KEY_FILE")
+                .hide_env(true)
+                .long("signing-key")
+                .value_name("file")
+                .value_parser(value_parser!(PathBuf))
+                .help("Read the hex-encoded secp256k1 signing key from a file, generating it if missing"),
+        )
+        .arg(
+            Arg::new("signing-key-hex")
+                .hide(true)
+                .env("DUFS_SIGNING_KEY")
+                .hide_env(true)
+                .long("signing-key-hex")
+                .value_name("key"),
+        )
+        .arg(
+            Arg::new("rotate-signing-key")
+                .env("DUFS_ROTATE_SIGNING_KEY")
+                .hide_env(true)
+                .long("rotate-signing-key")
+                .action(ArgAction::SetTrue)
+                .help("Replace the signing key with a new one on startup, retiring the old public key"),
+        )
         .arg(
             Arg::new("completions")
                 .long("completions")
                 .value_name("shell")
                 .value_parser(value_parser!(Shell))
                 .help("Print shell completion script for <shell>"),
+        )
+        .subcommand(
+            Command::new("gc")
+                .about("Remove provenance rows about files that no longer exist, then exit")
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .help("Only report the rows that would be removed"),
+                ),
+        )
+        .subcommand(
+            Command::new("provenance")
+                .about("Maintain the provenance database, then exit")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("backfill")
+                        .about("Mint the files that have no provenance events yet")
+                        .arg(
+                            Arg::new("jobs")
+                                .long("jobs")
+                                .short('j')
+                                .value_name("count")
+                                .value_parser(value_parser!(u64).range(1..))
+                                .help("Number of files to hash at once [default: number of CPUs]"),
+                        ),
+                ),
         );
 
     #[cfg(feature = "tls")]
@@ -222,32 +780,33 @@ pub fn build_cli() -> Command {
                 .long("tls-key")
                 .value_name("path")
                 .value_parser(value_parser!(PathBuf))
-                .help("Path to the SSL/TLS certificate's private key"),
-        )
-        .arg(
-            Arg::new("provenance-db")
-                .env("DUFS_PROVENANCE_DB")
-                .hide_env(true)
-                .long("provenance-db")
-                .value_name("path")
-                .value_parser(value_parser!(PathBuf))
-                .help("Path to SQLite database for provenance data [default: provenance.db]"),
+                .help("Path to the SSL/TLS certificate's private key, reloaded with the certificate on SIGHUP"),
         );
 
-    app
+    app.arg(
+        Arg::new("provenance-db")
+            .env("DUFS_PROVENANCE_DB")
+            .hide_env(true)
+            .long("provenance-db")
+            .value_name("path")
+            .value_parser(value_parser!(PathBuf))
+            .help("Path to SQLite database for provenance data, or a postgres:// URL to share the provenance chain between instances [default: provenance.db]"),
+    )
 }
 
 pub fn print_completions<G: Generator>(gen: G, cmd: &mut Command) {
     generate(gen, cmd, cmd.get_name().to_string(), &mut std::io::stdout());
 }
 
-#[derive(Debug, Deserialize, SmartDefault, PartialEq)]
+#[derive(Debug, Clone, Deserialize, SmartDefault, PartialEq)]
 #[serde(default)]
 #[serde(rename_all = "kebab-case")]
 pub struct Args {
     #[serde(default = "default_serve_path")]
     #[default(default_serve_path())]
     pub serve_path: PathBuf,
+    /// Directories served under `/<name>/` instead of the serve path
+    pub mounts: BTreeMap<String, PathBuf>,
     #[serde(deserialize_with = "deserialize_bind_addrs")]
     #[serde(rename = "bind")]
     #[serde(default = "default_addrs")]
@@ -265,6 +824,37 @@ pub struct Args {
     pub hidden: Vec<String>,
     #[serde(deserialize_with = "deserialize_access_control")]
     pub auth: AccessControl,
+    pub auth_exec: Option<PathBuf>,
+    pub oidc_issuer: Option<String>,
+    pub oidc_client_id: Option<String>,
+    pub oidc_client_secret: Option<String>,
+    #[default("preferred_username".to_string())]
+    pub oidc_claim: String,
+    #[serde(deserialize_with = "deserialize_retention_policy")]
+    pub retention: RetentionPolicy,
+    #[serde(deserialize_with = "deserialize_quota_policy")]
+    pub quota: QuotaPolicy,
+    pub trash_dir: Option<PathBuf>,
+    #[serde(default, deserialize_with = "deserialize_age")]
+    pub trash_retention: Option<Duration>,
+    /// Directories anonymous clients may upload new files into, like `incoming`
+    #[serde(deserialize_with = "deserialize_string_or_vec")]
+    pub allow_anonymous_drop: Vec<String>,
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub anonymous_drop_limit: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub max_upload_size: Option<u64>,
+    #[serde(deserialize_with = "deserialize_extension_list")]
+    pub allow_upload_types: ExtensionList,
+    #[serde(deserialize_with = "deserialize_extension_list")]
+    pub deny_upload_types: ExtensionList,
+    #[serde(deserialize_with = "deserialize_type_limits")]
+    pub upload_type_limits: TypeLimits,
+    pub durable_writes: bool,
+    #[serde(default, deserialize_with = "deserialize_age")]
+    pub partial_upload_retention: Option<Duration>,
+    pub index: bool,
+    pub dir_cache: bool,
     #[default(true)]
     pub allow_upload: bool,
     #[default(true)]
@@ -278,17 +868,76 @@ pub struct Args {
     pub render_index: bool,
     pub render_spa: bool,
     pub render_try_index: bool,
+    pub render_readme: bool,
+    pub error_pages: Option<PathBuf>,
+    pub brand_name: Option<String>,
+    pub brand_logo: Option<String>,
+    pub theme_color: Option<String>,
+    pub lang: Option<String>,
     pub enable_cors: bool,
+    /// Origins like `https://app.example.com` that may send credentials
+    #[serde(deserialize_with = "deserialize_string_or_vec")]
+    pub cors_origins: Vec<String>,
+    #[serde(deserialize_with = "deserialize_string_or_vec")]
+    pub cors_methods: Vec<String>,
+    #[serde(deserialize_with = "deserialize_string_or_vec")]
+    pub cors_headers: Vec<String>,
     #[serde(deserialize_with = "deserialize_log_http")]
     #[serde(rename = "log-format")]
     pub http_logger: HttpLogger,
     pub log_file: Option<PathBuf>,
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub log_rotate_size: Option<u64>,
+    pub log_rotate_interval: Option<RotateInterval>,
+    #[default(5)]
+    pub log_keep: usize,
+    #[serde(deserialize_with = "deserialize_error_reporter")]
+    #[serde(rename = "error-report-url")]
+    pub error_reporter: Option<ErrorReporter>,
     pub compress: Compress,
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub max_bandwidth: Option<u64>,
+    pub max_range_streams: Option<usize>,
+    pub rate_limit: Option<u32>,
+    pub user_rate_limit: Option<u32>,
+    #[serde(deserialize_with = "deserialize_ip_list")]
+    pub allow_ip: IpList,
+    #[serde(deserialize_with = "deserialize_ip_list")]
+    pub deny_ip: IpList,
+    #[serde(deserialize_with = "deserialize_ip_list")]
+    pub trusted_proxies: IpList,
+    pub max_concurrent_uploads: Option<usize>,
+    #[default(10)]
+    pub max_login_failures: u32,
+    pub propfind_max_entries: Option<usize>,
+    #[default(30)]
+    pub shutdown_timeout: u64,
     pub tls_cert: Option<PathBuf>,
     pub tls_key: Option<PathBuf>,
     #[serde(default = "default_provenance_db")]
     #[default(default_provenance_db())]
     pub provenance_db: Option<PathBuf>,
+    pub provenance_local_db: Option<PathBuf>,
+    pub provenance_db_key_file: Option<PathBuf>,
+    pub provenance_db_rekey_file: Option<PathBuf>,
+    #[serde(skip)]
+    pub provenance_db_encryption: DbEncryption,
+    pub provenance_backup_dir: Option<PathBuf>,
+    #[default(1440)]
+    pub provenance_backup_interval: u64,
+    #[default(7)]
+    pub provenance_backup_keep: usize,
+    pub provenance_anchor_interval: Option<u64>,
+    pub integrity_scan_interval: Option<u64>,
+    pub ots_batch_interval: u64,
+    pub ots_offline: bool,
+    pub dedup_dir: Option<PathBuf>,
+    pub tenant_db_dir: Option<PathBuf>,
+    #[serde(rename = "signing-key")]
+    pub signing_key_file: Option<PathBuf>,
+    #[serde(skip)]
+    pub signing_key: Option<String>,
+    pub rotate_signing_key: bool,
 }
 
 impl Args {
@@ -302,15 +951,32 @@ impl Args {
         if let Some(config_path) = matches.get_one::<PathBuf>("config") {
             let contents = std::fs::read_to_string(config_path)
                 .with_context(|| format!("Failed to read config at {}", config_path.display()))?;
-            args = serde_yaml::from_str(&contents)
-                .with_context(|| format!("Failed to load config at {}", config_path.display()))?;
+            let context = || format!("Failed to load config at {}", config_path.display());
+            args = match config_path.extension().and_then(|v| v.to_str()) {
+                Some("toml") => toml::from_str(&contents).with_context(context)?,
+                _ => serde_yaml::from_str(&contents).with_context(context)?,
+            };
         }
 
-        if let Some(path) = matches.get_one::<PathBuf>("serve-path") {
-            args.serve_path.clone_from(path)
+        if let Some(paths) = matches.get_many::<PathBuf>("serve-path") {
+            let paths: Vec<_> = paths.collect();
+            match paths.as_slice() {
+                [path] if parse_mount(path).is_none() => args.serve_path.clone_from(path),
+                _ => {
+                    for path in paths {
+                        let (name, path) = parse_mount(path).ok_or_else(|| {
+                            anyhow!("Invalid mount `{}`, e.g. /data:/srv/data", path.display())
+                        })?;
+                        args.mounts.insert(name, path);
+                    }
+                }
+            }
         }
 
-        args.serve_path = Self::sanitize_path(args.serve_path)?;
+        if let Some(path_prefix) = matches.get_one::<String>("path-prefix") {
+            args.path_prefix.clone_from(path_prefix)
+        }
+        args.resolve_paths()?;
 
         if let Some(port) = matches.get_one::<u16>("port") {
             args.port = *port
@@ -321,18 +987,6 @@ impl Args {
             args.addrs = BindAddr::parse_addrs(&addrs)?;
         }
 
-        args.path_is_file = args.serve_path.metadata()?.is_file();
-        if let Some(path_prefix) = matches.get_one::<String>("path-prefix") {
-            args.path_prefix.clone_from(path_prefix)
-        }
-        args.path_prefix = args.path_prefix.trim_matches('/').to_string();
-
-        args.uri_prefix = if args.path_prefix.is_empty() {
-            "/".to_owned()
-        } else {
-            format!("/{}/", &encode_uri(&args.path_prefix))
-        };
-
         if let Some(hidden) = matches.get_many::<String>("hidden") {
             args.hidden = hidden.cloned().collect();
         } else {
@@ -347,11 +1001,155 @@ impl Args {
         if !args.enable_cors {
             args.enable_cors = matches.get_flag("enable-cors");
         }
+        if let Some(origins) = matches.get_many::<String>("cors-origins") {
+            args.cors_origins = origins.cloned().collect();
+        }
+        for origin in args.cors_origins.iter_mut() {
+            let trimmed = origin.trim().trim_end_matches('/');
+            let valid = trimmed == "*"
+                || trimmed.split_once("://").is_some_and(|(scheme, host)| {
+                    matches!(scheme, "http" | "https") && !host.is_empty() && !host.contains('/')
+                });
+            if !valid {
+                bail!("Invalid CORS origin `{origin}`, e.g. https://app.example.com");
+            }
+            *origin = trimmed.to_string();
+        }
+        if let Some(methods) = matches.get_many::<String>("cors-methods") {
+            args.cors_methods = methods.cloned().collect();
+        }
+        for method in args.cors_methods.iter_mut() {
+            *method = method.trim().to_ascii_uppercase();
+            if Method::from_bytes(method.as_bytes()).is_err() {
+                bail!("Invalid CORS method `{method}`, e.g. GET");
+            }
+        }
+        if let Some(headers) = matches.get_many::<String>("cors-headers") {
+            args.cors_headers = headers.cloned().collect();
+        }
+        for header in args.cors_headers.iter_mut() {
+            *header = header.trim().to_string();
+            if header != "*" && HeaderName::from_bytes(header.as_bytes()).is_err() {
+                bail!("Invalid CORS header `{header}`, e.g. Authorization");
+            }
+        }
+        if !args.cors_origins.is_empty()
+            || !args.cors_methods.is_empty()
+            || !args.cors_headers.is_empty()
+        {
+            args.enable_cors = true;
+        }
+
+        if !args.durable_writes {
+            args.durable_writes = matches.get_flag("durable-writes");
+        }
+
+        if !args.index {
+            args.index = matches.get_flag("index");
+        }
+
+        if !args.dir_cache {
+            args.dir_cache = matches.get_flag("dir-cache");
+        }
 
         if let Some(rules) = matches.get_many::<String>("auth") {
             let rules: Vec<_> = rules.map(|v| v.as_str()).collect();
             args.auth = AccessControl::new(&rules)?;
         }
+        if let Some(command) = matches.get_one::<PathBuf>("auth-exec") {
+            args.auth_exec = Some(command.clone());
+        }
+        if args.auth_exec.is_some() {
+            args.auth.allow_external_users();
+        }
+        if let Some(issuer) = matches.get_one::<String>("oidc-issuer") {
+            args.oidc_issuer = Some(issuer.clone());
+        }
+        if let Some(client_id) = matches.get_one::<String>("oidc-client-id") {
+            args.oidc_client_id = Some(client_id.clone());
+        }
+        if let Some(secret) = matches.get_one::<String>("oidc-client-secret") {
+            args.oidc_client_secret = Some(secret.clone());
+        }
+        if let Some(claim) = matches.get_one::<String>("oidc-claim") {
+            args.oidc_claim = claim.clone();
+        }
+        if args.oidc_issuer.is_some() {
+            if args.oidc_client_id.is_none() {
+                bail!("--oidc-issuer requires --oidc-client-id");
+            }
+            if !args.auth.has_users() {
+                bail!("--oidc-issuer requires --auth rules for the users to log in as");
+            }
+        }
+        if let Some(rules) = matches.get_many::<String>("retention") {
+            let rules: Vec<_> = rules.map(|v| v.as_str()).collect();
+            args.retention = RetentionPolicy::new(&rules)?;
+        }
+        if let Some(rules) = matches.get_many::<String>("quota") {
+            let rules: Vec<_> = rules.map(|v| v.as_str()).collect();
+            args.quota = QuotaPolicy::new(&rules)?;
+        }
+        if let Some(dir) = matches.get_one::<PathBuf>("trash-dir") {
+            args.trash_dir = Some(dir.clone());
+        }
+        if let Some(age) = matches.get_one::<String>("trash-retention") {
+            args.trash_retention = Some(
+                parse_age(age)
+                    .ok_or_else(|| anyhow!("Invalid trash retention `{age}`, e.g. 30d"))?,
+            );
+        }
+        if let Some(age) = matches.get_one::<String>("partial-upload-retention") {
+            args.partial_upload_retention = Some(
+                parse_age(age)
+                    .ok_or_else(|| anyhow!("Invalid partial upload retention `{age}`, e.g. 1d"))?,
+            );
+        }
+        if args.trash_retention.is_some() && args.trash_dir.is_none() {
+            bail!("--trash-retention requires --trash-dir");
+        }
+        if let Some(dirs) = matches.get_many::<String>("allow-anonymous-drop") {
+            args.allow_anonymous_drop = dirs.cloned().collect();
+        }
+        for dir in args.allow_anonymous_drop.iter_mut() {
+            let trimmed = dir.trim_matches('/');
+            if trimmed.is_empty()
+                || trimmed
+                    .split('/')
+                    .any(|v| v.is_empty() || v == "." || v == "..")
+            {
+                bail!("Invalid anonymous drop directory `{dir}`, e.g. /incoming");
+            }
+            *dir = trimmed.to_string();
+        }
+        if let Some(size) = matches.get_one::<String>("anonymous-drop-limit") {
+            args.anonymous_drop_limit = Some(
+                parse_size(size)
+                    .filter(|v| *v > 0)
+                    .ok_or_else(|| anyhow!("Invalid anonymous drop limit `{size}`, e.g. 100M"))?,
+            );
+        }
+
+        if let Some(size) = matches.get_one::<String>("max-upload-size") {
+            args.max_upload_size = Some(
+                parse_size(size)
+                    .filter(|v| *v > 0)
+                    .ok_or_else(|| anyhow!("Invalid max upload size `{size}`, e.g. 10G"))?,
+            );
+        }
+        if let Some(exts) = matches.get_many::<String>("allow-upload-types") {
+            let exts: Vec<_> = exts.map(|v| v.as_str()).collect();
+            args.allow_upload_types = ExtensionList::new(&exts)?;
+        }
+        if let Some(exts) = matches.get_many::<String>("deny-upload-types") {
+            let exts: Vec<_> = exts.map(|v| v.as_str()).collect();
+            args.deny_upload_types = ExtensionList::new(&exts)?;
+        }
+        if let Some(rules) = matches.get_many::<String>("upload-type-limits") {
+            let rules: Vec<_> = rules.map(|v| v.as_str()).collect();
+            args.upload_type_limits = TypeLimits::new(&rules)?;
+        }
+
         if !args.allow_upload {
             args.allow_upload = true;
         }
@@ -379,6 +1177,40 @@ impl Args {
             args.render_spa = matches.get_flag("render-spa");
         }
 
+        if !args.render_readme {
+            args.render_readme = matches.get_flag("render-readme");
+        }
+
+        if let Some(dir) = matches.get_one::<PathBuf>("error-pages") {
+            args.error_pages = Some(dir.clone());
+        }
+
+        if let Some(name) = matches.get_one::<String>("brand-name") {
+            args.brand_name = Some(name.clone());
+        }
+        if let Some(logo) = matches.get_one::<String>("brand-logo") {
+            args.brand_logo = Some(logo.clone());
+        }
+        if let Some(color) = matches.get_one::<String>("theme-color") {
+            args.theme_color = Some(color.clone());
+        }
+        if let Some(color) = &args.theme_color {
+            if !is_hex_color(color) {
+                bail!("Invalid theme color `{color}`, e.g. #0b5fff");
+            }
+        }
+        if let Some(lang) = matches.get_one::<String>("lang") {
+            args.lang = Some(lang.clone());
+        }
+        if let Some(lang) = &args.lang {
+            if Locale::get(lang).is_none() {
+                bail!(
+                    "Unsupported language `{lang}`, expected one of {}",
+                    Locale::langs().join(", ")
+                );
+            }
+        }
+
         if let Some(log_format) = matches.get_one::<String>("log-format") {
             args.http_logger = log_format.parse()?;
         }
@@ -387,10 +1219,71 @@ impl Args {
             args.log_file = Some(log_file.clone());
         }
 
+        if let Some(size) = matches.get_one::<String>("log-rotate-size") {
+            args.log_rotate_size = Some(
+                parse_size(size)
+                    .filter(|v| *v > 0)
+                    .ok_or_else(|| anyhow!("Invalid log rotate size `{size}`, e.g. 10M"))?,
+            );
+        }
+
+        if let Some(interval) = matches.get_one::<RotateInterval>("log-rotate-interval") {
+            args.log_rotate_interval = Some(*interval);
+        }
+
+        if let Some(keep) = matches.get_one::<usize>("log-keep") {
+            args.log_keep = *keep;
+        }
+
+        if let Some(url) = matches.get_one::<String>("error-report-url") {
+            args.error_reporter = Some(url.parse()?);
+        }
+
         if let Some(compress) = matches.get_one::<Compress>("compress") {
             args.compress = *compress;
         }
 
+        if let Some(rate) = matches.get_one::<String>("max-bandwidth") {
+            args.max_bandwidth = Some(
+                parse_size(rate)
+                    .filter(|v| *v > 0)
+                    .ok_or_else(|| anyhow!("Invalid bandwidth `{rate}`, e.g. 512K or 10M"))?,
+            );
+        }
+        if let Some(count) = matches.get_one::<u64>("max-range-streams") {
+            args.max_range_streams = Some(*count as usize);
+        }
+        if let Some(rps) = matches.get_one::<u32>("rate-limit") {
+            args.rate_limit = Some(*rps);
+        }
+        if let Some(rps) = matches.get_one::<u32>("user-rate-limit") {
+            args.user_rate_limit = Some(*rps);
+        }
+        if let Some(cidrs) = matches.get_many::<String>("allow-ip") {
+            let cidrs: Vec<_> = cidrs.map(|v| v.as_str()).collect();
+            args.allow_ip = IpList::new(&cidrs)?;
+        }
+        if let Some(cidrs) = matches.get_many::<String>("deny-ip") {
+            let cidrs: Vec<_> = cidrs.map(|v| v.as_str()).collect();
+            args.deny_ip = IpList::new(&cidrs)?;
+        }
+        if let Some(cidrs) = matches.get_many::<String>("trusted-proxies") {
+            let cidrs: Vec<_> = cidrs.map(|v| v.as_str()).collect();
+            args.trusted_proxies = IpList::new(&cidrs)?;
+        }
+        if let Some(count) = matches.get_one::<u64>("max-concurrent-uploads") {
+            args.max_concurrent_uploads = Some(*count as usize);
+        }
+        if let Some(count) = matches.get_one::<u32>("max-login-failures") {
+            args.max_login_failures = *count;
+        }
+        if let Some(count) = matches.get_one::<u64>("propfind-max-entries") {
+            args.propfind_max_entries = Some(*count as usize);
+        }
+        if let Some(secs) = matches.get_one::<u64>("shutdown-timeout") {
+            args.shutdown_timeout = *secs;
+        }
+
         #[cfg(feature = "tls")]
         {
             if let Some(tls_cert) = matches.get_one::<PathBuf>("tls-cert") {
@@ -418,9 +1311,191 @@ impl Args {
             args.provenance_db = Some(provenance_db.clone());
         }
 
+        if let Some(path) = matches.get_one::<PathBuf>("provenance-local-db") {
+            args.provenance_local_db = Some(path.clone());
+        }
+
+        if let Some(file) = matches.get_one::<PathBuf>("provenance-db-key-file") {
+            args.provenance_db_key_file = Some(file.clone());
+        }
+
+        if let Some(file) = matches.get_one::<PathBuf>("provenance-db-rekey-file") {
+            args.provenance_db_rekey_file = Some(file.clone());
+        }
+
+        args.provenance_db_encryption = DbEncryption {
+            key: match env::var(PROVENANCE_DB_KEY_ENV) {
+                Ok(key) => Some(key),
+                Err(_) => args
+                    .provenance_db_key_file
+                    .as_deref()
+                    .map(read_key_file)
+                    .transpose()?,
+            },
+            rekey: args
+                .provenance_db_rekey_file
+                .as_deref()
+                .map(read_key_file)
+                .transpose()?,
+        };
+
+        if let Some(dir) = matches.get_one::<PathBuf>("provenance-backup-dir") {
+            args.provenance_backup_dir = Some(dir.clone());
+        }
+
+        if let Some(interval) = matches.get_one::<u64>("provenance-backup-interval") {
+            args.provenance_backup_interval = *interval;
+        }
+
+        if let Some(keep) = matches.get_one::<usize>("provenance-backup-keep") {
+            args.provenance_backup_keep = *keep;
+        }
+
+        if let Some(interval) = matches.get_one::<u64>("provenance-anchor-interval") {
+            args.provenance_anchor_interval = Some(*interval);
+        }
+
+        if let Some(interval) = matches.get_one::<u64>("integrity-scan-interval") {
+            args.integrity_scan_interval = Some(*interval);
+        }
+
+        if let Some(secs) = matches.get_one::<u64>("ots-batch-interval") {
+            args.ots_batch_interval = *secs;
+        }
+
+        if !args.ots_offline {
+            args.ots_offline = matches.get_flag("ots-offline");
+        }
+
+        if let Some(dir) = matches.get_one::<PathBuf>("dedup-dir") {
+            args.dedup_dir = Some(dir.clone());
+        }
+
+        if let Some(dir) = matches.get_one::<PathBuf>("tenant-db-dir") {
+            args.tenant_db_dir = Some(dir.clone());
+        }
+        if args.tenant_db_dir.is_some() && args.path_is_file {
+            bail!("--tenant-db-dir requires serving a directory");
+        }
+        if args.tenant_db_dir.is_some() && !args.mounts.is_empty() {
+            bail!("--tenant-db-dir can't be used with mounts");
+        }
+
+        if let Some(file) = matches.get_one::<PathBuf>("signing-key") {
+            args.signing_key_file = Some(file.clone());
+        }
+        args.signing_key = matches.get_one::<String>("signing-key-hex").cloned();
+        if !args.rotate_signing_key {
+            args.rotate_signing_key = matches.get_flag("rotate-signing-key");
+        }
+        if args.rotate_signing_key && args.signing_key.is_some() {
+            bail!("Can't rotate a signing key supplied through DUFS_SIGNING_KEY");
+        }
+
         Ok(args)
     }
 
+    /// Check the serve path and mounts, and work out `path_is_file` and
+    /// `uri_prefix` from them and `path_prefix`
+    pub fn resolve_paths(&mut self) -> Result<()> {
+        self.serve_path = Self::sanitize_path(&self.serve_path)?;
+        for (name, path) in self.mounts.iter_mut() {
+            if name.is_empty()
+                || name.contains(['/', '\\'])
+                || name.starts_with("__dufs")
+                || RESERVED_MOUNT_NAMES.contains(&name.as_str())
+            {
+                bail!("Invalid mount name `{name}`");
+            }
+            *path = Self::sanitize_path(&path)?;
+            if !path.is_dir() {
+                bail!("Mount `{name}` must be a directory");
+            }
+        }
+
+        self.path_is_file = self.serve_path.metadata()?.is_file();
+        self.path_prefix = self.path_prefix.trim_matches('/').to_string();
+        self.uri_prefix = if self.path_prefix.is_empty() {
+            "/".to_owned()
+        } else {
+            format!("/{}/", &encode_uri(&self.path_prefix))
+        };
+        Ok(())
+    }
+
+    /// Settings of the server for the mount `name`, serving `path` under
+    /// `/<name>/` with the `--auth` rules below `/<name>`
+    pub fn for_mount(&self, name: &str, path: &Path) -> Args {
+        let mut args = self.clone();
+        args.serve_path = path.to_path_buf();
+        args.mounts.clear();
+        args.path_prefix = match self.path_prefix.as_str() {
+            "" => name.to_string(),
+            prefix => format!("{prefix}/{name}"),
+        };
+        args.uri_prefix = format!("{}{}/", self.uri_prefix, encode_uri(name));
+        args.auth = self.auth.scoped(name);
+        args
+    }
+
+    /// Whether files named `name` pass `--allow-upload-types` and
+    /// `--deny-upload-types`
+    pub fn upload_type_allowed(&self, name: &str) -> bool {
+        (self.allow_upload_types.is_empty() || self.allow_upload_types.matches(name))
+            && !self.deny_upload_types.matches(name)
+    }
+
+    /// The largest upload of a file named `name`, the lower of
+    /// `--max-upload-size` and its `--upload-type-limits`
+    pub fn upload_limit(&self, name: &str) -> Option<u64> {
+        match (
+            self.max_upload_size,
+            self.upload_type_limits.limit_for(name),
+        ) {
+            (Some(max), Some(limit)) => Some(max.min(limit)),
+            (max, limit) => max.or(limit),
+        }
+    }
+
+    /// Whether `--allow-ip` and `--deny-ip` let `ip` in
+    pub fn ip_allowed(&self, ip: IpAddr) -> bool {
+        (self.allow_ip.is_empty() || self.allow_ip.contains(ip)) && !self.deny_ip.contains(ip)
+    }
+
+    /// When the `--log-file` is rotated
+    pub fn log_rotation(&self) -> LogRotation {
+        LogRotation {
+            max_size: self.log_rotate_size,
+            interval: self.log_rotate_interval,
+            keep: self.log_keep,
+        }
+    }
+
+    /// The Postgres database keeping the provenance chain, when `--provenance-db` is a URL
+    pub fn provenance_db_url(&self) -> Option<&str> {
+        let db = self.provenance_db.as_ref()?.to_str()?;
+        (db.starts_with("postgres://") || db.starts_with("postgresql://")).then_some(db)
+    }
+
+    /// The SQLite database file for provenance data
+    pub fn provenance_db_path(&self) -> PathBuf {
+        let path = match self.provenance_db_url() {
+            Some(_) => self.provenance_local_db.as_ref(),
+            None => self.provenance_db.as_ref(),
+        };
+        path.cloned().unwrap_or_else(|| "provenance.db".into())
+    }
+
+    /// OpenID Connect settings, when `--oidc-issuer` is set
+    pub fn oidc_config(&self) -> Option<OidcConfig> {
+        Some(OidcConfig {
+            issuer: self.oidc_issuer.clone()?,
+            client_id: self.oidc_client_id.clone()?,
+            client_secret: self.oidc_client_secret.clone(),
+            claim: self.oidc_claim.clone(),
+        })
+    }
+
     fn sanitize_path<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
         let path = path.as_ref();
         if !path.exists() {
@@ -441,6 +1516,9 @@ pub enum BindAddr {
     IpAddr(IpAddr),
     #[cfg(unix)]
     SocketPath(String),
+    /// Listening socket inherited through systemd socket activation
+    #[cfg(unix)]
+    Fd(std::os::fd::RawFd),
 }
 
 impl BindAddr {
@@ -449,6 +1527,16 @@ impl BindAddr {
         #[cfg(not(unix))]
         let mut invalid_addrs = vec![];
         for addr in addrs {
+            if let Some(path) = addr.strip_prefix("unix:") {
+                #[cfg(unix)]
+                bind_addrs.push(BindAddr::SocketPath(path.to_string()));
+                #[cfg(not(unix))]
+                {
+                    let _ = path;
+                    invalid_addrs.push(*addr);
+                }
+                continue;
+            }
             match addr.parse::<IpAddr>() {
                 Ok(v) => {
                     bind_addrs.push(BindAddr::IpAddr(v));
@@ -469,21 +1557,73 @@ impl BindAddr {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+/// The addresses to bind, leaving out IP versions without a local interface,
+/// and the addresses to print
+pub fn check_addrs(args: &Args) -> Result<(Vec<BindAddr>, Vec<BindAddr>)> {
+    let mut new_addrs = vec![];
+    let mut print_addrs = vec![];
+    let (ipv4_addrs, ipv6_addrs) = interface_addrs()?;
+    for bind_addr in args.addrs.iter() {
+        match bind_addr {
+            BindAddr::IpAddr(ip) => match &ip {
+                IpAddr::V4(_) => {
+                    if !ipv4_addrs.is_empty() {
+                        new_addrs.push(bind_addr.clone());
+                        if ip.is_unspecified() {
+                            print_addrs.extend(ipv4_addrs.clone());
+                        } else {
+                            print_addrs.push(bind_addr.clone());
+                        }
+                    }
+                }
+                IpAddr::V6(_) => {
+                    if !ipv6_addrs.is_empty() {
+                        new_addrs.push(bind_addr.clone());
+                        if ip.is_unspecified() {
+                            print_addrs.extend(ipv6_addrs.clone());
+                        } else {
+                            print_addrs.push(bind_addr.clone())
+                        }
+                    }
+                }
+            },
+            #[cfg(unix)]
+            _ => {
+                new_addrs.push(bind_addr.clone());
+                print_addrs.push(bind_addr.clone())
+            }
+        }
+    }
+    print_addrs.sort_unstable();
+    Ok((new_addrs, print_addrs))
+}
+
+fn interface_addrs() -> Result<(Vec<BindAddr>, Vec<BindAddr>)> {
+    let (mut ipv4_addrs, mut ipv6_addrs) = (vec![], vec![]);
+    let ifaces =
+        if_addrs::get_if_addrs().with_context(|| "Failed to get local interface addresses")?;
+    for iface in ifaces.into_iter() {
+        let ip = iface.ip();
+        if ip.is_ipv4() {
+            ipv4_addrs.push(BindAddr::IpAddr(ip))
+        }
+        if ip.is_ipv6() {
+            ipv6_addrs.push(BindAddr::IpAddr(ip))
+        }
+    }
+    Ok((ipv4_addrs, ipv6_addrs))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Compress {
     None,
+    #[default]
     Low,
     Medium,
     High,
 }
 
-impl Default for Compress {
-    fn default() -> Self {
-        Self::Low
-    }
-}
-
 impl ValueEnum for Compress {
     fn value_variants<'a>() -> &'a [Self] {
         &[Self::None, Self::Low, Self::Medium, Self::High]
@@ -534,8 +1674,9 @@ where
         where
             S: serde::de::SeqAccess<'de>,
         {
-            let addrs: Vec<&'de str> =
+            let addrs: Vec<String> =
                 Deserialize::deserialize(serde::de::value::SeqAccessDeserializer::new(seq))?;
+            let addrs: Vec<&str> = addrs.iter().map(|v| v.as_str()).collect();
             BindAddr::parse_addrs(&addrs).map_err(serde::de::Error::custom)
         }
     }
@@ -578,10 +1719,77 @@ fn deserialize_access_control<'de, D>(deserializer: D) -> Result<AccessControl,
 where
     D: Deserializer<'de>,
 {
-    let rules: Vec<&str> = Vec::deserialize(deserializer)?;
+    let rules: Vec<String> = Vec::deserialize(deserializer)?;
+    let rules: Vec<&str> = rules.iter().map(|v| v.as_str()).collect();
     AccessControl::new(&rules).map_err(serde::de::Error::custom)
 }
 
+fn deserialize_retention_policy<'de, D>(deserializer: D) -> Result<RetentionPolicy, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let rules = deserialize_string_or_vec(deserializer)?;
+    let rules: Vec<&str> = rules.iter().flat_map(|v| v.split(',')).collect();
+    RetentionPolicy::new(&rules).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_quota_policy<'de, D>(deserializer: D) -> Result<QuotaPolicy, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let rules = deserialize_string_or_vec(deserializer)?;
+    let rules: Vec<&str> = rules.iter().flat_map(|v| v.split(',')).collect();
+    QuotaPolicy::new(&rules).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_extension_list<'de, D>(deserializer: D) -> Result<ExtensionList, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let exts = deserialize_string_or_vec(deserializer)?;
+    let exts: Vec<&str> = exts.iter().flat_map(|v| v.split(',')).collect();
+    ExtensionList::new(&exts).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_type_limits<'de, D>(deserializer: D) -> Result<TypeLimits, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let rules = deserialize_string_or_vec(deserializer)?;
+    let rules: Vec<&str> = rules.iter().flat_map(|v| v.split(',')).collect();
+    TypeLimits::new(&rules).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_ip_list<'de, D>(deserializer: D) -> Result<IpList, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let cidrs = deserialize_string_or_vec(deserializer)?;
+    let cidrs: Vec<&str> = cidrs.iter().flat_map(|v| v.split(',')).collect();
+    IpList::new(&cidrs).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_age<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: String = Deserialize::deserialize(deserializer)?;
+    parse_age(&value)
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom(format!("Invalid age `{value}`")))
+}
+
+fn deserialize_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: String = Deserialize::deserialize(deserializer)?;
+    parse_size(&value)
+        .filter(|v| *v > 0)
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom(format!("Invalid size `{value}`")))
+}
+
 fn deserialize_log_http<'de, D>(deserializer: D) -> Result<HttpLogger, D::Error>
 where
     D: Deserializer<'de>,
@@ -590,6 +1798,52 @@ where
     value.parse().map_err(serde::de::Error::custom)
 }
 
+fn deserialize_error_reporter<'de, D>(deserializer: D) -> Result<Option<ErrorReporter>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: String = Deserialize::deserialize(deserializer)?;
+    value.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+fn read_key_file(path: &Path) -> Result<String> {
+    let key = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read key file `{}`", path.display()))?;
+    let key = key.trim();
+    if key.is_empty() {
+        bail!("Key file `{}` is empty", path.display());
+    }
+    Ok(key.to_string())
+}
+
+/// Top-level paths the server routes itself, which can't name a mount
+const RESERVED_MOUNT_NAMES: &[&str] = &[
+    ".",
+    "..",
+    ".provenance",
+    ".well-known",
+    "api",
+    "assets",
+    "chunks",
+    "share",
+];
+
+/// A `/<name>:<path>` mount, None for a plain path
+fn parse_mount(value: &Path) -> Option<(String, PathBuf)> {
+    let (name, path) = value.to_str()?.strip_prefix('/')?.split_once(':')?;
+    if name.contains('/') || path.is_empty() {
+        return None;
+    }
+    Some((name.to_string(), PathBuf::from(path)))
+}
+
+/// A CSS hex color such as `#0b5fff` or `#fff`
+fn is_hex_color(value: &str) -> bool {
+    value.strip_prefix('#').is_some_and(|hex| {
+        matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    })
+}
+
 fn default_serve_path() -> PathBuf {
     PathBuf::from(".")
 }
@@ -640,6 +1894,36 @@ mod tests {
         assert_eq!(args.hidden, ["tmp", "*.log", "*.lock"]);
     }
 
+    #[test]
+    fn test_args_mounts() {
+        let tmpdir = assert_fs::TempDir::new().unwrap();
+        tmpdir.child("a/file").touch().unwrap();
+        let data = tmpdir.path().join("a");
+        let media = format!("/media:{}", tmpdir.to_string_lossy());
+        let matches = build_cli()
+            .try_get_matches_from(vec!["", &format!("/data:{}", data.display()), &media])
+            .unwrap();
+        let args = Args::parse(matches).unwrap();
+        assert_eq!(
+            args.mounts.keys().collect::<Vec<_>>(),
+            ["data", "media"].iter().collect::<Vec<_>>()
+        );
+        let mount = args.for_mount("data", &args.mounts["data"]);
+        assert_eq!(mount.serve_path, Args::sanitize_path(&data).unwrap());
+        assert_eq!(mount.uri_prefix, "/data/");
+        assert!(mount.mounts.is_empty());
+
+        for value in [
+            "/api:/tmp",
+            &tmpdir.path().join("a/file:x").to_string_lossy(),
+        ] {
+            let matches = build_cli()
+                .try_get_matches_from(vec!["", value, &media])
+                .unwrap();
+            assert!(Args::parse(matches).is_err());
+        }
+    }
+
     #[test]
     fn test_args_from_cli2() {
         let cli = build_cli();
@@ -652,6 +1936,27 @@ mod tests {
         assert_eq!(args.hidden, ["tmp", "*.log", "*.lock"]);
     }
 
+    #[test]
+    fn test_provenance_db_key_not_in_argv() {
+        let tmpdir = assert_fs::TempDir::new().unwrap();
+        let key_file = tmpdir.child("db.key");
+        key_file.write_str("secret\n").unwrap();
+        let cli = build_cli();
+        assert!(cli
+            .clone()
+            .try_get_matches_from(vec!["", "--provenance-db-key", "secret"])
+            .is_err());
+        let matches = cli
+            .try_get_matches_from(vec![
+                "",
+                "--provenance-db-key-file",
+                key_file.path().to_str().unwrap(),
+            ])
+            .unwrap();
+        let args = Args::parse(matches).unwrap();
+        assert_eq!(args.provenance_db_encryption.key.as_deref(), Some("secret"));
+    }
+
     #[test]
     fn test_args_from_empty_config_file() {
         let tmpdir = assert_fs::TempDir::new().unwrap();
@@ -729,4 +2034,29 @@ hidden:
         );
         assert_eq!(args.hidden, ["tmp", "*.log", "*.lock"]);
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_parse_unix_socket_addrs() {
+        let addrs =
+            BindAddr::parse_addrs(&["127.0.0.1", "unix:/run/node-drive.sock", "/tmp/a.sock"])
+                .unwrap();
+        assert_eq!(
+            addrs,
+            vec![
+                BindAddr::IpAddr("127.0.0.1".parse().unwrap()),
+                BindAddr::SocketPath("/run/node-drive.sock".into()),
+                BindAddr::SocketPath("/tmp/a.sock".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_hex_color() {
+        assert!(is_hex_color("#0b5fff"));
+        assert!(is_hex_color("#FFF"));
+        assert!(!is_hex_color("0b5fff"));
+        assert!(!is_hex_color("#0b5ff"));
+        assert!(!is_hex_color("red"));
+    }
 }
@@ -5,13 +5,15 @@ use base64::{engine::general_purpose::STANDARD, Engine as _};
 use ed25519_dalek::{ed25519::signature::SignerMut, Signature, SigningKey};
 use headers::HeaderValue;
 use hyper::{header::WWW_AUTHENTICATE, Method};
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 use lazy_static::lazy_static;
 use md5::Context;
 use sha2::{Digest, Sha256};
 use std::{
+    cmp::Ordering,
     collections::HashMap,
     path::{Path, PathBuf},
+    time::Duration,
 };
 use uuid::Uuid;
 
@@ -34,6 +36,7 @@ pub struct AccessControl {
     use_hashed_password: bool,
     users: IndexMap<String, (String, AccessPaths)>,
     anonymous: Option<AccessPaths>,
+    external_users: bool,
 }
 
 impl Default for AccessControl {
@@ -43,6 +46,7 @@ impl Default for AccessControl {
             use_hashed_password: false,
             users: IndexMap::new(),
             anonymous: Some(AccessPaths::new(AccessPerm::ReadWrite)),
+            external_users: false,
         }
     }
 }
@@ -99,17 +103,62 @@ impl AccessControl {
             use_hashed_password,
             users,
             anonymous,
+            external_users: false,
         })
     }
 
+    /// Let users missing from the rules log in, e.g. through `--auth-exec`.
+    /// Without rules, anonymous users lose access.
+    pub fn allow_external_users(&mut self) {
+        if self.empty {
+            self.empty = false;
+            self.anonymous = None;
+        }
+        self.external_users = true;
+    }
+
+    /// The rules below the top-level directory `name`, for a server that
+    /// serves it as its root
+    pub fn scoped(&self, name: &str) -> Self {
+        Self {
+            empty: self.empty,
+            use_hashed_password: self.use_hashed_password,
+            users: self
+                .users
+                .iter()
+                .map(|(user, (pass, ap))| {
+                    let ap = ap.find(name).unwrap_or_default();
+                    (user.clone(), (pass.clone(), ap))
+                })
+                .collect(),
+            anonymous: self.anonymous.as_ref().and_then(|v| v.find(name)),
+            external_users: self.external_users,
+        }
+    }
+
     pub fn has_users(&self) -> bool {
-        !self.users.is_empty()
+        !self.users.is_empty() || self.external_users
     }
 
-    pub fn guard(
+    pub fn has_user(&self, user: &str) -> bool {
+        self.users.contains_key(user)
+    }
+
+    /// Whether `pass` is the password of `user`
+    pub fn check_password(&self, user: &str, pass: &str) -> bool {
+        self.users
+            .get(user)
+            .is_some_and(|(auth_pass, _)| password_matches(pass, auth_pass))
+    }
+
+    /// The user of a request and their access to `path`, checking permissions
+    /// as if the request used `perm_method`, e.g. for writes that only need
+    /// read access to the target path.
+    pub fn guard_as(
         &self,
         path: &str,
         method: &Method,
+        perm_method: &Method,
         authorization: Option<&HeaderValue>,
         token: Option<&String>,
         guard_options: bool,
@@ -118,10 +167,11 @@ impl AccessControl {
             return (None, Some(AccessPaths::new(AccessPerm::ReadWrite)));
         }
 
-        if method == Method::GET {
-            if let Some(token) = token {
-                if let Ok((user, ap)) = self.verify_token(token, path) {
-                    return (Some(user), ap.guard(path, method));
+        if let Some(token) = token {
+            if let Ok((user, ap)) = self.verify_token(token) {
+                // Only the path of the link and what is under it
+                if ap.find(path).is_some_and(|v| !v.perm().indexonly()) {
+                    return (Some(user), ap.guard(path, perm_method));
                 }
             }
         }
@@ -133,7 +183,7 @@ impl AccessControl {
                         return (Some(user), Some(AccessPaths::new(AccessPerm::ReadOnly)));
                     }
                     if check_auth(authorization, method.as_str(), &user, pass).is_some() {
-                        return (Some(user), ap.guard(path, method));
+                        return (Some(user), ap.guard(path, perm_method));
                     }
                 }
             }
@@ -146,31 +196,98 @@ impl AccessControl {
         }
 
         if let Some(ap) = self.anonymous.as_ref() {
-            return (None, ap.guard(path, method));
+            return (None, ap.guard(path, perm_method));
         }
 
         (None, None)
     }
 
-    pub fn generate_token(&self, path: &str, user: &str) -> Result<String> {
+    /// Access of `user`, signed in by other means than a password (e.g. OIDC)
+    pub fn guard_session(
+        &self,
+        user: &str,
+        path: &str,
+        perm_method: &Method,
+    ) -> (Option<String>, Option<AccessPaths>) {
+        match self.users.get(user) {
+            Some((_, ap)) => (Some(user.to_string()), ap.guard(path, perm_method)),
+            None => (None, None),
+        }
+    }
+
+    /// Access of `user`, missing from the rules but let in to `paths` by other
+    /// means (`--auth-exec`), along with what anonymous users may access
+    pub fn guard_external(
+        &self,
+        user: &str,
+        mut paths: AccessPaths,
+        path: &str,
+        method: &Method,
+        perm_method: &Method,
+    ) -> (Option<String>, Option<AccessPaths>) {
+        if method == Method::OPTIONS {
+            return (
+                Some(user.to_string()),
+                Some(AccessPaths::new(AccessPerm::ReadOnly)),
+            );
+        }
+        if let Some(anonymous) = &self.anonymous {
+            paths.union(anonymous);
+        }
+        (Some(user.to_string()), paths.guard(path, perm_method))
+    }
+
+    /// Access of `user` through an API token, limited to the token's `scopes`
+    pub fn guard_scoped(
+        &self,
+        user: &str,
+        scopes: &AccessPaths,
+        path: &str,
+        perm_method: &Method,
+    ) -> (Option<String>, Option<AccessPaths>) {
+        match self.users.get(user) {
+            Some((_, ap)) => (
+                Some(user.to_string()),
+                ap.intersect(scopes).guard(path, perm_method),
+            ),
+            None => (None, None),
+        }
+    }
+
+    /// Signed `?token=` link granting `grant` (`ro`, `rw` or `w`) to `path`
+    /// and everything under it, within the rules of `user`, until `expires_in`
+    /// has passed. Changing the password of the user revokes their links.
+    pub fn generate_token(
+        &self,
+        path: &str,
+        user: &str,
+        grant: &str,
+        expires_in: Option<Duration>,
+    ) -> Result<String> {
         let (pass, _) = self
             .users
             .get(user)
             .ok_or_else(|| anyhow!("Not found user '{user}'"))?;
-        let exp = unix_now().as_millis() as u64 + TOKEN_EXPIRATION;
-        let message = format!("{path}:{exp}");
+        if parse_grant(grant).is_none() {
+            bail!("Invalid permission '{grant}'");
+        }
+        let expires_in = expires_in.map_or(TOKEN_EXPIRATION, |v| v.as_millis() as u64);
+        let exp = unix_now().as_millis() as u64 + expires_in;
+        let scope = format!("{grant}\0{user}\0{path}");
+        let message = format!("{exp}:{scope}");
         let mut signing_key = derive_secret_key(user, pass);
         let sig = signing_key.sign(message.as_bytes()).to_bytes();
 
-        let mut raw = Vec::with_capacity(64 + 8 + user.len());
+        let mut raw = Vec::with_capacity(64 + 8 + scope.len());
         raw.extend_from_slice(&sig);
         raw.extend_from_slice(&exp.to_be_bytes());
-        raw.extend_from_slice(user.as_bytes());
+        raw.extend_from_slice(scope.as_bytes());
 
         Ok(hex::encode(raw))
     }
 
-    fn verify_token<'a>(&'a self, token: &str, path: &str) -> Result<(String, &'a AccessPaths)> {
+    /// The user of a `?token=` link and what it grants
+    fn verify_token(&self, token: &str) -> Result<(String, AccessPaths)> {
         let raw = hex::decode(token)?;
 
         if raw.len() < 72 {
@@ -179,14 +296,20 @@ impl AccessControl {
 
         let sig_bytes = &raw[..64];
         let exp_bytes = &raw[64..72];
-        let user_bytes = &raw[72..];
+        let scope_bytes = &raw[72..];
 
         let exp = u64::from_be_bytes(exp_bytes.try_into()?);
         if unix_now().as_millis() as u64 > exp {
             bail!("Token expired");
         }
 
-        let user = std::str::from_utf8(user_bytes)?;
+        let scope = std::str::from_utf8(scope_bytes)?;
+        let mut parts = scope.splitn(3, '\0');
+        let (Some(grant), Some(user), Some(path)) = (parts.next(), parts.next(), parts.next())
+        else {
+            bail!("Invalid token");
+        };
+        let (perm, limits) = parse_grant(grant).ok_or_else(|| anyhow!("Invalid token"))?;
         let (pass, ap) = self
             .users
             .get(user)
@@ -194,15 +317,19 @@ impl AccessControl {
 
         let sig = Signature::from_bytes(&<[u8; 64]>::try_from(sig_bytes)?);
 
-        let message = format!("{path}:{exp}");
+        let message = format!("{exp}:{scope}");
         derive_secret_key(user, pass).verify(message.as_bytes(), &sig)?;
-        Ok((user.to_string(), ap))
+
+        let mut granted = AccessPaths::default();
+        granted.add(path, perm, limits);
+        Ok((user.to_string(), ap.intersect(&granted)))
     }
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct AccessPaths {
     perm: AccessPerm,
+    limits: AccessLimits,
     children: IndexMap<String, AccessPaths>,
 }
 
@@ -214,26 +341,59 @@ impl AccessPaths {
         }
     }
 
+    fn granted(perm: AccessPerm, limits: AccessLimits) -> Self {
+        Self {
+            perm,
+            limits,
+            ..Default::default()
+        }
+    }
+
     pub fn perm(&self) -> AccessPerm {
         self.perm
     }
 
-    pub fn set_perm(&mut self, perm: AccessPerm) {
-        if self.perm < perm {
+    pub fn limits(&self) -> AccessLimits {
+        self.limits
+    }
+
+    /// Grant `perm` with `limits` unless the current grant is higher. Of two
+    /// grants of the same permission, only the limits both have are kept.
+    fn set_grant(&mut self, perm: AccessPerm, limits: AccessLimits) {
+        let limits = match self.perm.cmp(&perm) {
+            Ordering::Greater => return,
+            Ordering::Equal => self.limits.common(&limits),
+            Ordering::Less => limits,
+        };
+        if (self.perm, self.limits) != (perm, limits) {
             self.perm = perm;
-            self.recursively_purge_children(perm);
+            self.limits = limits;
+            self.recursively_purge_children(perm, limits);
         }
     }
 
     pub fn merge(&mut self, paths: &str) -> Option<()> {
+        let mut grants: Vec<(&str, AccessPerm, AccessLimits)> = vec![];
         for item in paths.trim_matches(',').split(',') {
-            let (path, perm) = match item.split_once(':') {
-                None => (item, AccessPerm::ReadOnly),
-                Some((path, "ro")) => (path, AccessPerm::ReadOnly),
-                Some((path, "rw")) => (path, AccessPerm::ReadWrite),
-                _ => return None,
+            // Limits like `noarchive` follow the path they apply to
+            if !item.starts_with('/') {
+                if let Some((_, _, limits)) = grants.last_mut() {
+                    if limits.add(item) {
+                        continue;
+                    }
+                }
+            }
+            let (path, perm, limits) = match item.split_once(':') {
+                None => (item, AccessPerm::ReadOnly, AccessLimits::default()),
+                Some((path, grant)) => {
+                    let (perm, limits) = parse_grant(grant)?;
+                    (path, perm, limits)
+                }
             };
-            self.add(path, perm);
+            grants.push((path, perm, limits));
+        }
+        for (path, perm, limits) in grants {
+            self.add(path, perm, limits);
         }
         Some(())
     }
@@ -246,38 +406,109 @@ impl AccessPaths {
         Some(target)
     }
 
-    fn recursively_purge_children(&mut self, perm: AccessPerm) {
+    /// Paths accessible through both `self` and `other`, each with the lower
+    /// of the two permissions and the limits of both
+    pub fn intersect(&self, other: &AccessPaths) -> AccessPaths {
+        let none = (AccessPerm::IndexOnly, AccessLimits::default());
+        Self::intersect_impl(Some(self), none, Some(other), none).unwrap_or_default()
+    }
+
+    fn intersect_impl(
+        a: Option<&AccessPaths>,
+        a_grant: (AccessPerm, AccessLimits),
+        b: Option<&AccessPaths>,
+        b_grant: (AccessPerm, AccessLimits),
+    ) -> Option<AccessPaths> {
+        let effective = |node: Option<&AccessPaths>, inherited| match node {
+            Some(node) if !node.perm.indexonly() => (node.perm, node.limits),
+            _ => inherited,
+        };
+        let (a_grant, b_grant) = (effective(a, a_grant), effective(b, b_grant));
+        if (a.is_none() && a_grant.0.indexonly()) || (b.is_none() && b_grant.0.indexonly()) {
+            return None;
+        }
+        let perm = a_grant.0.min(b_grant.0);
+        let limits = a_grant.1.either(&b_grant.1);
+        let names: IndexSet<&String> = a
+            .into_iter()
+            .chain(b)
+            .flat_map(|node| node.children.keys())
+            .collect();
+        let mut output = AccessPaths::granted(perm, limits);
+        for name in names {
+            let child = Self::intersect_impl(
+                a.and_then(|v| v.children.get(name)),
+                a_grant,
+                b.and_then(|v| v.children.get(name)),
+                b_grant,
+            );
+            // Children only widen the access of their parent
+            if let Some(mut child) = child {
+                if child.covered_by(perm, &limits) {
+                    child.perm = AccessPerm::IndexOnly;
+                    child.limits = AccessLimits::default();
+                }
+                if !child.perm.indexonly() || !child.children.is_empty() {
+                    output.children.insert(name.clone(), child);
+                }
+            }
+        }
+        Some(output)
+    }
+
+    /// Add the paths of `other`, keeping the higher of the two permissions
+    pub fn union(&mut self, other: &AccessPaths) {
+        other.union_impl(self, &mut vec![]);
+    }
+
+    fn union_impl<'a>(&'a self, output: &mut AccessPaths, parts: &mut Vec<&'a str>) {
+        if !self.perm.indexonly() {
+            output.add_impl(parts, self.perm, self.limits);
+        }
+        for (name, child) in self.children.iter() {
+            parts.push(name);
+            child.union_impl(output, parts);
+            parts.pop();
+        }
+    }
+
+    /// Whether a grant of `perm` with `limits` allows all this node does
+    fn covered_by(&self, perm: AccessPerm, limits: &AccessLimits) -> bool {
+        self.perm <= perm && limits.within(&self.limits)
+    }
+
+    fn recursively_purge_children(&mut self, perm: AccessPerm, limits: AccessLimits) {
         self.children.retain(|_, child| {
-            if child.perm <= perm {
+            if child.covered_by(perm, &limits) {
                 false
             } else {
-                child.recursively_purge_children(perm);
+                child.recursively_purge_children(perm, limits);
                 true
             }
         });
     }
 
-    fn add(&mut self, path: &str, perm: AccessPerm) {
+    fn add(&mut self, path: &str, perm: AccessPerm, limits: AccessLimits) {
         let path = path.trim_matches('/');
         if path.is_empty() {
-            self.set_perm(perm);
+            self.set_grant(perm, limits);
         } else {
             let parts: Vec<&str> = path.split('/').collect();
-            self.add_impl(&parts, perm);
+            self.add_impl(&parts, perm, limits);
         }
     }
 
-    fn add_impl(&mut self, parts: &[&str], perm: AccessPerm) {
+    fn add_impl(&mut self, parts: &[&str], perm: AccessPerm, limits: AccessLimits) {
         let parts_len = parts.len();
         if parts_len == 0 {
-            self.set_perm(perm);
+            self.set_grant(perm, limits);
             return;
         }
-        if self.perm >= perm {
+        if AccessPaths::granted(perm, limits).covered_by(self.perm, &self.limits) {
             return;
         }
         let child = self.children.entry(parts[0].to_string()).or_default();
-        child.add_impl(&parts[1..], perm)
+        child.add_impl(&parts[1..], perm, limits)
     }
 
     pub fn find(&self, path: &str) -> Option<AccessPaths> {
@@ -286,20 +517,20 @@ impl AccessPaths {
             .split('/')
             .filter(|v| !v.is_empty())
             .collect();
-        self.find_impl(&parts, self.perm)
+        self.find_impl(&parts, (self.perm, self.limits))
     }
 
-    fn find_impl(&self, parts: &[&str], perm: AccessPerm) -> Option<AccessPaths> {
-        let perm = if !self.perm.indexonly() {
-            self.perm
+    fn find_impl(&self, parts: &[&str], grant: (AccessPerm, AccessLimits)) -> Option<AccessPaths> {
+        let (perm, limits) = if !self.perm.indexonly() {
+            (self.perm, self.limits)
         } else {
-            perm
+            grant
         };
         if parts.is_empty() {
             if perm.indexonly() {
                 return Some(self.clone());
             } else {
-                return Some(AccessPaths::new(perm));
+                return Some(AccessPaths::granted(perm, limits));
             }
         }
         let child = match self.children.get(parts[0]) {
@@ -308,11 +539,11 @@ impl AccessPaths {
                 if perm.indexonly() {
                     return None;
                 } else {
-                    return Some(AccessPaths::new(perm));
+                    return Some(AccessPaths::granted(perm, limits));
                 }
             }
         };
-        child.find_impl(&parts[1..], perm)
+        child.find_impl(&parts[1..], (perm, limits))
     }
 
     pub fn child_names(&self) -> Vec<&String> {
@@ -340,6 +571,91 @@ impl AccessPaths {
     }
 }
 
+/// What a grant leaves out: `:w` grants uploads only, and `nodelete`,
+/// `nowebdav` or `noarchive` after a path take those away, e.g.
+/// `/public:ro,noarchive`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccessLimits {
+    /// Uploads of new files, without reading, overwriting or deleting
+    pub upload_only: bool,
+    pub no_delete: bool,
+    pub no_webdav: bool,
+    pub no_archive: bool,
+}
+
+impl AccessLimits {
+    const UPLOAD_ONLY: Self = Self {
+        upload_only: true,
+        no_delete: false,
+        no_webdav: false,
+        no_archive: false,
+    };
+
+    fn add(&mut self, name: &str) -> bool {
+        match name {
+            "nodelete" => self.no_delete = true,
+            "nowebdav" => self.no_webdav = true,
+            "noarchive" => self.no_archive = true,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Whether `other` has every limit of `self`
+    fn within(&self, other: &AccessLimits) -> bool {
+        (!self.upload_only || other.upload_only)
+            && (!self.no_delete || other.no_delete)
+            && (!self.no_webdav || other.no_webdav)
+            && (!self.no_archive || other.no_archive)
+    }
+
+    /// The limits both have, for the wider of two grants
+    fn common(&self, other: &AccessLimits) -> Self {
+        Self {
+            upload_only: self.upload_only && other.upload_only,
+            no_delete: self.no_delete && other.no_delete,
+            no_webdav: self.no_webdav && other.no_webdav,
+            no_archive: self.no_archive && other.no_archive,
+        }
+    }
+
+    /// The limits of either, for the narrower of two grants
+    fn either(&self, other: &AccessLimits) -> Self {
+        Self {
+            upload_only: self.upload_only || other.upload_only,
+            no_delete: self.no_delete || other.no_delete,
+            no_webdav: self.no_webdav || other.no_webdav,
+            no_archive: self.no_archive || other.no_archive,
+        }
+    }
+
+    /// Whether the grant leaves `method` allowed, `archive` for zip and tar
+    /// downloads
+    pub fn allow(&self, method: &Method, archive: bool) -> bool {
+        let method = method.as_str();
+        if self.upload_only
+            && !matches!(
+                method,
+                "PUT" | "PATCH" | "MKCOL" | "OPTIONS" | "CHECKAUTH" | "LOGOUT"
+            )
+        {
+            return false;
+        }
+        if self.no_delete && matches!(method, "DELETE" | "MOVE") {
+            return false;
+        }
+        if self.no_webdav
+            && matches!(
+                method,
+                "PROPFIND" | "PROPPATCH" | "MKCOL" | "COPY" | "MOVE" | "LOCK" | "UNLOCK"
+            )
+        {
+            return false;
+        }
+        !(self.no_archive && archive)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub enum AccessPerm {
     #[default]
@@ -358,8 +674,19 @@ impl AccessPerm {
     }
 }
 
+/// Permission and limits of `ro` (or `r`), `rw` or upload-only `w`
+pub fn parse_grant(value: &str) -> Option<(AccessPerm, AccessLimits)> {
+    match value {
+        "ro" | "r" => Some((AccessPerm::ReadOnly, AccessLimits::default())),
+        "rw" => Some((AccessPerm::ReadWrite, AccessLimits::default())),
+        "w" => Some((AccessPerm::ReadWrite, AccessLimits::UPLOAD_ONLY)),
+        _ => None,
+    }
+}
+
 pub fn www_authenticate(res: &mut Response, args: &Args) -> Result<()> {
-    if args.auth.use_hashed_password {
+    // `--auth-exec` needs the password itself, which Digest doesn't send
+    if args.auth.use_hashed_password || args.auth_exec.is_some() {
         let basic = HeaderValue::from_str(&format!("Basic realm=\"{REALM}\""))?;
         res.headers_mut().insert(WWW_AUTHENTICATE, basic);
     } else {
@@ -388,6 +715,22 @@ pub fn get_auth_user(authorization: &HeaderValue) -> Option<String> {
     }
 }
 
+/// The user and password of Basic credentials
+pub fn get_basic_credentials(authorization: &HeaderValue) -> Option<(String, String)> {
+    let value = strip_prefix(authorization.as_bytes(), b"Basic ")?;
+    let value: Vec<u8> = STANDARD.decode(value).ok()?;
+    let (user, pass) = std::str::from_utf8(&value).ok()?.split_once(':')?;
+    Some((user.to_string(), pass.to_string()))
+}
+
+fn password_matches(pass: &str, auth_pass: &str) -> bool {
+    if auth_pass.starts_with("$6$") {
+        sha_crypt::sha512_check(pass, auth_pass).is_ok()
+    } else {
+        pass == auth_pass
+    }
+}
+
 pub fn check_auth(
     authorization: &HeaderValue,
     method: &str,
@@ -402,15 +745,7 @@ pub fn check_auth(
             return None;
         }
 
-        if auth_pass.starts_with("$6$") {
-            if let Ok(()) = sha_crypt::sha512_check(pass, auth_pass) {
-                return Some(());
-            }
-        } else if pass == auth_pass {
-            return Some(());
-        }
-
-        None
+        password_matches(pass, auth_pass).then_some(())
     } else if let Some(value) = strip_prefix(authorization.as_bytes(), b"Digest ") {
         let digest_map = to_headermap(value).ok()?;
         if let (Some(username), Some(nonce), Some(user_response)) = (
@@ -520,7 +855,7 @@ fn validate_nonce(nonce: &[u8]) -> Result<bool> {
     bail!("invalid nonce");
 }
 
-fn is_readonly_method(method: &Method) -> bool {
+pub fn is_readonly_method(method: &Method) -> bool {
     method == Method::GET
         || method == Method::OPTIONS
         || method == Method::HEAD
@@ -663,12 +998,28 @@ mod tests {
     #[test]
     fn test_access_paths() {
         let mut paths = AccessPaths::default();
-        paths.add("/dir1", AccessPerm::ReadWrite);
-        paths.add("/dir2/dir21", AccessPerm::ReadWrite);
-        paths.add("/dir2/dir21/dir211", AccessPerm::ReadOnly);
-        paths.add("/dir2/dir22", AccessPerm::ReadOnly);
-        paths.add("/dir2/dir22/dir221", AccessPerm::ReadWrite);
-        paths.add("/dir2/dir23/dir231", AccessPerm::ReadWrite);
+        paths.add("/dir1", AccessPerm::ReadWrite, AccessLimits::default());
+        paths.add(
+            "/dir2/dir21",
+            AccessPerm::ReadWrite,
+            AccessLimits::default(),
+        );
+        paths.add(
+            "/dir2/dir21/dir211",
+            AccessPerm::ReadOnly,
+            AccessLimits::default(),
+        );
+        paths.add("/dir2/dir22", AccessPerm::ReadOnly, AccessLimits::default());
+        paths.add(
+            "/dir2/dir22/dir221",
+            AccessPerm::ReadWrite,
+            AccessLimits::default(),
+        );
+        paths.add(
+            "/dir2/dir23/dir231",
+            AccessPerm::ReadWrite,
+            AccessLimits::default(),
+        );
         assert_eq!(
             paths.entry_paths(Path::new("/tmp")),
             [
@@ -722,4 +1073,91 @@ mod tests {
             Some(AccessPaths::new(AccessPerm::ReadWrite))
         );
     }
+
+    #[test]
+    fn test_intersect_access_paths() {
+        let mut user = AccessPaths::default();
+        user.merge("/dir1,/dir2:rw").unwrap();
+        let mut scopes = AccessPaths::default();
+        scopes.merge("/dir1:rw,/dir2/sub,/dir3").unwrap();
+        let paths = user.intersect(&scopes);
+        assert_eq!(
+            paths.find("dir1/file"),
+            Some(AccessPaths::new(AccessPerm::ReadOnly))
+        );
+        assert_eq!(
+            paths.find("dir2/sub/file"),
+            Some(AccessPaths::new(AccessPerm::ReadOnly))
+        );
+        assert_eq!(paths.find("dir2/file"), None);
+        assert_eq!(paths.find("dir3"), None);
+        assert_eq!(paths.child_names(), ["dir1", "dir2"]);
+
+        let mut all = AccessPaths::new(AccessPerm::ReadWrite);
+        all.merge("/").unwrap();
+        assert_eq!(all.intersect(&scopes), scopes);
+    }
+
+    #[test]
+    fn test_union_access_paths() {
+        let mut paths = AccessPaths::default();
+        paths.merge("/dir1,/dir2/sub:rw").unwrap();
+        let mut other = AccessPaths::default();
+        other.merge("/dir1:rw,/dir2,/dir3").unwrap();
+        paths.union(&other);
+        assert_eq!(
+            paths.find("dir1/file"),
+            Some(AccessPaths::new(AccessPerm::ReadWrite))
+        );
+        assert_eq!(
+            paths.find("dir2/file"),
+            Some(AccessPaths::new(AccessPerm::ReadOnly))
+        );
+        assert_eq!(
+            paths.find("dir2/sub/file"),
+            Some(AccessPaths::new(AccessPerm::ReadWrite))
+        );
+        assert_eq!(
+            paths.find("dir3"),
+            Some(AccessPaths::new(AccessPerm::ReadOnly))
+        );
+        assert_eq!(paths.find("dir4"), None);
+    }
+
+    #[test]
+    fn test_access_limits() {
+        let mut paths = AccessPaths::default();
+        paths
+            .merge("/inbox:w,/public:r,noarchive,nodelete,/docs:rw,nowebdav")
+            .unwrap();
+        let limits_of = |paths: &AccessPaths, path: &str| paths.find(path).unwrap().limits();
+        let limits = |path: &str| limits_of(&paths, path);
+        assert_eq!(paths.find("inbox/a").unwrap().perm(), AccessPerm::ReadWrite);
+        assert!(limits("inbox/a").upload_only);
+        assert!(limits("public/a").no_archive && limits("public/a").no_delete);
+        assert!(!limits("public/a").no_webdav);
+        assert!(limits("docs").no_webdav);
+
+        assert!(limits("inbox").allow(&Method::PUT, false));
+        assert!(!limits("inbox").allow(&Method::GET, false));
+        assert!(!limits("inbox").allow(&Method::DELETE, false));
+        assert!(limits("public").allow(&Method::GET, false));
+        assert!(!limits("public").allow(&Method::GET, true));
+        assert!(!limits("docs").allow(&Method::from_bytes(b"PROPFIND").unwrap(), false));
+        assert!(limits("docs").allow(&Method::DELETE, false));
+
+        // Limits start a path when they aren't known
+        assert!(AccessPaths::default().merge("/docs,other").is_some());
+
+        // The wider grant wins
+        paths.merge("/public:ro,/docs/sub:rw").unwrap();
+        assert_eq!(limits_of(&paths, "public"), AccessLimits::default());
+        assert_eq!(limits_of(&paths, "docs/sub"), AccessLimits::default());
+        assert!(limits_of(&paths, "docs/other").no_webdav);
+
+        let mut scopes = AccessPaths::default();
+        scopes.merge("/docs:rw,noarchive").unwrap();
+        let limits = paths.intersect(&scopes).find("docs/sub").unwrap().limits();
+        assert!(limits.no_archive && !limits.no_webdav);
+    }
 }
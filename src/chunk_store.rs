@@ -0,0 +1,409 @@
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use futures_util::{stream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Mutex, OnceLock};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, BufReader};
+use tokio_util::io::StreamReader;
+
+/// First line of a file replaced by its chunk list
+const MAGIC: &[u8] = b"node-drive-chunks/v1\n";
+
+/// Files are split into chunks of this size, smaller files are kept as they are
+pub const CHUNK_SIZE: u64 = 1024 * 1024;
+
+const STATS_FILE: &str = "stats.json";
+
+/// Files are read in many places that don't see the server, and there is one
+/// store for all of them, set up on startup.
+static STORE: OnceLock<ChunkStore> = OnceLock::new();
+
+/// Content read back from chunks
+pub type ChunkStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send + Sync>>;
+
+/// Content-addressed store of file chunks, see `--dedup-dir`.
+///
+/// An uploaded file is split into chunks kept as `<dir>/<ab>/<sha256>`, and
+/// replaced by the list of its chunks. The list is padded with a hole to the
+/// size of the content, so listings, quotas and `Content-Length` see the real
+/// size while the disk only holds the list.
+#[derive(Debug)]
+pub struct ChunkStore {
+    dir: PathBuf,
+    /// Serializes updates of the stats file
+    stats: Mutex<()>,
+}
+
+/// Chunks making up a file's content, in order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChunkList {
+    pub size: u64,
+    pub chunks: Vec<String>,
+}
+
+/// Running totals of the files split into chunks
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct DedupStats {
+    pub files: u64,
+    pub bytes: u64,
+    /// Bytes whose chunks were stored already
+    pub deduplicated_bytes: u64,
+}
+
+/// What the store holds, for the stats endpoint
+#[derive(Debug, Serialize)]
+pub struct DedupUsage {
+    #[serde(flatten)]
+    pub totals: DedupStats,
+    pub chunks: u64,
+    /// Bytes the chunks take on disk
+    pub stored_bytes: u64,
+}
+
+impl ChunkStore {
+    /// Set up the store in `dir` for the whole process
+    pub fn install(dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create dedup dir `{}`", dir.display()))?;
+        let dir = std::fs::canonicalize(dir)?;
+        let _ = STORE.set(ChunkStore {
+            dir,
+            stats: Mutex::new(()),
+        });
+        Ok(())
+    }
+
+    pub fn get() -> Option<&'static ChunkStore> {
+        STORE.get()
+    }
+
+    /// Replace the file at `path` with its chunk list, storing the chunks the
+    /// store doesn't hold yet. Files smaller than a chunk are left alone.
+    pub fn store(&self, path: &Path) -> Result<Option<ChunkList>> {
+        let mut file = std::fs::File::open(path)?;
+        let meta = file.metadata()?;
+        let size = meta.len();
+        if size < CHUNK_SIZE || read_list(&mut file, size)?.is_some() {
+            return Ok(None);
+        }
+        file.rewind()?;
+
+        let mut chunks = vec![];
+        let mut deduplicated_bytes = 0;
+        let mut buffer = vec![0; CHUNK_SIZE as usize];
+        loop {
+            let len = read_full(&mut file, &mut buffer)?;
+            if len == 0 {
+                break;
+            }
+            let hash = hex::encode(Sha256::digest(&buffer[..len]));
+            let chunk_path = self.chunk_path(&hash);
+            if chunk_path.exists() {
+                deduplicated_bytes += len as u64;
+            } else {
+                let parent = chunk_path.parent().unwrap_or(&self.dir);
+                std::fs::create_dir_all(parent)?;
+                let tmp = tmp_path(parent);
+                std::fs::write(&tmp, &buffer[..len])?;
+                std::fs::rename(&tmp, &chunk_path)?;
+            }
+            chunks.push(hash);
+        }
+        let list = ChunkList { size, chunks };
+
+        // Swap in the list at once, readers see either the content or the list
+        let parent = path.parent().context("File without a parent dir")?;
+        let tmp = tmp_path(parent);
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(MAGIC)?;
+        serde_json::to_writer(&mut file, &list)?;
+        file.write_all(b"\n")?;
+        file.set_len(size)?;
+        file.set_permissions(meta.permissions())?;
+        drop(file);
+        std::fs::rename(&tmp, path)?;
+
+        self.update_stats(|stats| {
+            stats.files += 1;
+            stats.bytes += size;
+            stats.deduplicated_bytes += deduplicated_bytes;
+        })?;
+        Ok(Some(list))
+    }
+
+    /// Put the content back in place of the chunk list at `path`, before
+    /// writing into the file
+    pub async fn restore(&self, path: &Path) -> Result<()> {
+        let Some(list) = chunk_list(path).await? else {
+            return Ok(());
+        };
+        let parent = path.parent().context("File without a parent dir")?;
+        let tmp = tmp_path(parent);
+        let mut file = tokio::fs::File::create(&tmp).await?;
+        let mut reader = StreamReader::new(self.stream(&list, 0, list.size));
+        tokio::io::copy(&mut reader, &mut file).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+
+    /// `len` bytes of the content from `start`
+    pub fn stream(&self, list: &ChunkList, start: u64, len: u64) -> ChunkStream {
+        let end = (start + len).min(list.size);
+        let first = (start / CHUNK_SIZE) as usize;
+        let last = end.div_ceil(CHUNK_SIZE) as usize;
+        let parts: Vec<_> = list.chunks[first.min(list.chunks.len())..last.min(list.chunks.len())]
+            .iter()
+            .enumerate()
+            .map(|(i, hash)| {
+                let offset = (first + i) as u64 * CHUNK_SIZE;
+                let from = start.saturating_sub(offset) as usize;
+                let to = (end - offset).min(CHUNK_SIZE) as usize;
+                (self.chunk_path(hash), from, to)
+            })
+            .collect();
+        Box::pin(stream::iter(parts).then(|(path, from, to)| async move {
+            let data = Bytes::from(tokio::fs::read(&path).await?);
+            match data.get(from..to) {
+                Some(_) => Ok(data.slice(from..to)),
+                None => Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("Chunk `{}` is truncated", path.display()),
+                )),
+            }
+        }))
+    }
+
+    /// Totals of the store, along with its chunks and their size on disk
+    pub fn usage(&self) -> Result<DedupUsage> {
+        let totals = self.read_stats()?;
+        let mut chunks = 0;
+        let mut stored_bytes = 0;
+        for entry in walkdir::WalkDir::new(&self.dir).min_depth(2).max_depth(2) {
+            let entry = entry?;
+            if entry.file_type().is_file() {
+                chunks += 1;
+                stored_bytes += entry.metadata()?.len();
+            }
+        }
+        Ok(DedupUsage {
+            totals,
+            chunks,
+            stored_bytes,
+        })
+    }
+
+    fn chunk_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(&hash[..2]).join(hash)
+    }
+
+    fn read_stats(&self) -> Result<DedupStats> {
+        match std::fs::read(self.dir.join(STATS_FILE)) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(DedupStats::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn update_stats(&self, update: impl FnOnce(&mut DedupStats)) -> Result<()> {
+        let _guard = self.stats.lock().unwrap();
+        let mut stats = self.read_stats()?;
+        update(&mut stats);
+        let tmp = tmp_path(&self.dir);
+        std::fs::write(&tmp, serde_json::to_vec(&stats)?)?;
+        std::fs::rename(&tmp, self.dir.join(STATS_FILE))?;
+        Ok(())
+    }
+}
+
+/// The chunk list held by the file at `path`, None for regular files or
+/// without a store
+pub async fn chunk_list(path: &Path) -> Result<Option<ChunkList>> {
+    if STORE.get().is_none() {
+        return Ok(None);
+    }
+    let mut file = tokio::fs::File::open(path).await?;
+    let size = file.metadata().await?.len();
+    read_list_async(&mut file, size).await
+}
+
+/// Reader of the content of the file at `path`, reassembling chunk lists
+pub async fn open(path: &Path) -> io::Result<Pin<Box<dyn AsyncRead + Send + Sync>>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    if let Some(store) = STORE.get() {
+        let size = file.metadata().await?.len();
+        if let Some(list) = read_list_async(&mut file, size)
+            .await
+            .map_err(io::Error::other)?
+        {
+            return Ok(Box::pin(StreamReader::new(
+                store.stream(&list, 0, list.size),
+            )));
+        }
+    }
+    Ok(Box::pin(file))
+}
+
+/// Blocking `open`
+pub fn open_sync(path: &Path) -> io::Result<Box<dyn Read + Send>> {
+    let mut file = std::fs::File::open(path)?;
+    if let Some(store) = STORE.get() {
+        let size = file.metadata()?.len();
+        if let Some(list) = read_list(&mut file, size).map_err(io::Error::other)? {
+            let chunks = list.chunks.into_iter().map(|hash| store.chunk_path(&hash));
+            return Ok(Box::new(ChunkReader {
+                chunks: Box::new(chunks),
+                current: None,
+            }));
+        }
+    }
+    Ok(Box::new(file))
+}
+
+/// Read a chunk list from the start of `file`, rewinding it when the file
+/// holds its content
+pub async fn read_list_async(file: &mut tokio::fs::File, size: u64) -> Result<Option<ChunkList>> {
+    if size < CHUNK_SIZE {
+        return Ok(None);
+    }
+    let mut magic = [0; MAGIC.len()];
+    let is_list = file.read_exact(&mut magic).await.is_ok() && magic == MAGIC;
+    if !is_list {
+        file.rewind().await?;
+        return Ok(None);
+    }
+    let mut line = vec![];
+    BufReader::new(&mut *file)
+        .read_until(b'\n', &mut line)
+        .await?;
+    file.rewind().await?;
+    Ok(parse_list(&line, size))
+}
+
+fn read_list(file: &mut std::fs::File, size: u64) -> Result<Option<ChunkList>> {
+    if size < CHUNK_SIZE {
+        return Ok(None);
+    }
+    let mut magic = [0; MAGIC.len()];
+    let is_list = file.read_exact(&mut magic).is_ok() && magic == MAGIC;
+    if !is_list {
+        file.rewind()?;
+        return Ok(None);
+    }
+    let mut line = vec![];
+    io::BufReader::new(&mut *file).read_until(b'\n', &mut line)?;
+    file.rewind()?;
+    Ok(parse_list(&line, size))
+}
+
+/// Only lists matching the file's size and naming well-formed chunks count,
+/// anything else is content that happens to start like a list
+fn parse_list(line: &[u8], size: u64) -> Option<ChunkList> {
+    let list: ChunkList = serde_json::from_slice(line).ok()?;
+    let well_formed = list.size == size
+        && list.chunks.len() as u64 == size.div_ceil(CHUNK_SIZE)
+        && list.chunks.iter().all(|hash| {
+            hash.len() == 64 && hash.bytes().all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f'))
+        });
+    well_formed.then_some(list)
+}
+
+/// Scratch file next to its destination, renamed over it once written
+fn tmp_path(dir: &Path) -> PathBuf {
+    dir.join(format!(".chunks-{}", uuid::Uuid::new_v4()))
+}
+
+fn read_full(file: &mut std::fs::File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match file.read(&mut buffer[filled..])? {
+            0 => break,
+            len => filled += len,
+        }
+    }
+    Ok(filled)
+}
+
+trait Rewind {
+    fn rewind(&mut self) -> io::Result<()>;
+}
+
+impl Rewind for std::fs::File {
+    fn rewind(&mut self) -> io::Result<()> {
+        io::Seek::rewind(self)
+    }
+}
+
+/// Blocking reader over chunk files, one after the other
+struct ChunkReader {
+    chunks: Box<dyn Iterator<Item = PathBuf> + Send>,
+    current: Option<std::fs::File>,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(file) = &mut self.current {
+                match file.read(buf)? {
+                    0 => self.current = None,
+                    len => return Ok(len),
+                }
+            }
+            match self.chunks.next() {
+                Some(path) => self.current = Some(std::fs::File::open(path)?),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_store_and_read() -> Result<()> {
+        let dir = assert_fs::TempDir::new()?;
+        ChunkStore::install(&dir.path().join("chunks"))?;
+        let store = ChunkStore::get().unwrap();
+
+        let content: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|v| (v % 251) as u8).collect();
+        let (a, b) = (dir.path().join("a.bin"), dir.path().join("b.bin"));
+        std::fs::write(&a, &content)?;
+        std::fs::write(&b, &content)?;
+        assert!(store.store(&a)?.is_some());
+        assert!(store.store(&b)?.is_some());
+        // Already a list
+        assert!(store.store(&b)?.is_none());
+
+        assert_eq!(std::fs::metadata(&a)?.len(), content.len() as u64);
+        let mut read = vec![];
+        open(&a).await?.read_to_end(&mut read).await?;
+        assert_eq!(read, content);
+        let mut read = vec![];
+        open_sync(&b)?.read_to_end(&mut read)?;
+        assert_eq!(read, content);
+
+        let list = chunk_list(&a).await?.unwrap();
+        let mut reader = StreamReader::new(store.stream(&list, CHUNK_SIZE - 5, 20));
+        let mut range = vec![];
+        reader.read_to_end(&mut range).await?;
+        assert_eq!(
+            range,
+            &content[CHUNK_SIZE as usize - 5..CHUNK_SIZE as usize + 15]
+        );
+
+        let usage = store.usage()?;
+        assert_eq!(usage.totals.files, 2);
+        assert_eq!(usage.totals.deduplicated_bytes, content.len() as u64);
+        assert_eq!(usage.chunks, 3);
+
+        store.restore(&b).await?;
+        assert_eq!(std::fs::read(&b)?, content);
+        Ok(())
+    }
+}
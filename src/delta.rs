@@ -0,0 +1,280 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::io::SeekFrom;
+use tokio::io::{
+    self, AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt,
+};
+
+/// First bytes of a delta, followed by its block size as a big-endian u32
+pub const MAGIC: &[u8; 4] = b"NDD1";
+
+pub const DEFAULT_BLOCK_SIZE: u32 = 64 * 1024;
+pub const MIN_BLOCK_SIZE: u32 = 1024;
+pub const MAX_BLOCK_SIZE: u32 = 8 * 1024 * 1024;
+
+/// Largest literal a single op may carry
+const MAX_LITERAL: u32 = 16 * 1024 * 1024;
+
+/// `0x01`, block index (u64) and block count (u32): copy blocks of the base
+const OP_COPY: u8 = 1;
+/// `0x02`, length (u32) and that many bytes: new content
+const OP_LITERAL: u8 = 2;
+
+/// Checksums of a file's blocks, for a client to find what it can reuse
+#[derive(Debug, Serialize)]
+pub struct Signature {
+    pub size: u64,
+    pub block_size: u32,
+    pub blocks: Vec<BlockSignature>,
+}
+
+/// The weak checksum of a block rolls over the client's data to find
+/// candidates, and the sha256 confirms them
+#[derive(Debug, Serialize)]
+pub struct BlockSignature {
+    pub weak: u32,
+    pub strong: String,
+}
+
+/// What a delta reused and what it carried
+#[derive(Debug, Default, Serialize)]
+pub struct DeltaStats {
+    pub size: u64,
+    pub copied_bytes: u64,
+    pub literal_bytes: u64,
+}
+
+/// The rolling checksum of rsync over a block
+pub fn weak_checksum(data: &[u8]) -> u32 {
+    let len = data.len() as u32;
+    let (mut a, mut b) = (0u32, 0u32);
+    for (i, &byte) in data.iter().enumerate() {
+        a = a.wrapping_add(byte as u32);
+        b = b.wrapping_add((len - i as u32).wrapping_mul(byte as u32));
+    }
+    (a & 0xffff) | (b << 16)
+}
+
+pub async fn signature<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    block_size: u32,
+) -> io::Result<Signature> {
+    let mut buf = vec![0; block_size as usize];
+    let mut blocks = vec![];
+    let mut size = 0;
+    loop {
+        let len = read_block(reader, &mut buf).await?;
+        if len == 0 {
+            break;
+        }
+        size += len as u64;
+        blocks.push(BlockSignature {
+            weak: weak_checksum(&buf[..len]),
+            strong: format!("{:x}", Sha256::digest(&buf[..len])),
+        });
+        if len < buf.len() {
+            break;
+        }
+    }
+    Ok(Signature {
+        size,
+        block_size,
+        blocks,
+    })
+}
+
+/// Rebuild a file from `base` and a delta read from `delta`, writing it to
+/// `output`. None when the result would go over `max_size`.
+pub async fn apply<B, D, W>(
+    base: &mut B,
+    base_size: u64,
+    delta: &mut D,
+    output: &mut W,
+    max_size: u64,
+) -> Result<Option<DeltaStats>>
+where
+    B: AsyncRead + AsyncSeek + Unpin,
+    D: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut magic = [0; 4];
+    delta.read_exact(&mut magic).await?;
+    if &magic != MAGIC {
+        bail!("Not a delta");
+    }
+    let block_size = delta.read_u32().await?;
+    if !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size) {
+        bail!("Invalid block size {block_size}");
+    }
+
+    let mut stats = DeltaStats::default();
+    let mut op = [0; 1];
+    while delta.read(&mut op).await? > 0 {
+        let len = match op[0] {
+            OP_COPY => {
+                let index = delta.read_u64().await?;
+                let count = delta.read_u32().await?;
+                let start = index.saturating_mul(block_size as u64);
+                if start >= base_size {
+                    bail!("Block {index} is past the end of the file");
+                }
+                let len = (count as u64 * block_size as u64).min(base_size - start);
+                if stats.size + len > max_size {
+                    return Ok(None);
+                }
+                base.seek(SeekFrom::Start(start)).await?;
+                let copied = io::copy(&mut (&mut *base).take(len), output).await?;
+                if copied != len {
+                    bail!("The file is shorter than its signature");
+                }
+                stats.copied_bytes += len;
+                len
+            }
+            OP_LITERAL => {
+                let len = delta.read_u32().await?;
+                if len > MAX_LITERAL {
+                    bail!("Literal of {len} bytes is too large");
+                }
+                if stats.size + len as u64 > max_size {
+                    return Ok(None);
+                }
+                let mut data = vec![0; len as usize];
+                delta.read_exact(&mut data).await?;
+                output.write_all(&data).await?;
+                stats.literal_bytes += len as u64;
+                len as u64
+            }
+            v => bail!("Unknown delta op {v}"),
+        };
+        stats.size += len;
+    }
+    output.flush().await?;
+    Ok(Some(stats))
+}
+
+/// Fill `buf` unless the reader ends first, returning the bytes read
+async fn read_block<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]).await? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    /// What a client does: reuse the blocks found in `data`, send the rest
+    fn diff(signature: &Signature, data: &[u8]) -> Vec<u8> {
+        let block_size = signature.block_size as usize;
+        let mut blocks: HashMap<u32, Vec<(usize, &str)>> = HashMap::new();
+        for (index, block) in signature.blocks.iter().enumerate() {
+            blocks
+                .entry(block.weak)
+                .or_default()
+                .push((index, &block.strong));
+        }
+        let mut delta = MAGIC.to_vec();
+        delta.extend_from_slice(&signature.block_size.to_be_bytes());
+        let mut literal = vec![];
+        let flush = |delta: &mut Vec<u8>, literal: &mut Vec<u8>| {
+            if !literal.is_empty() {
+                delta.push(OP_LITERAL);
+                delta.extend_from_slice(&(literal.len() as u32).to_be_bytes());
+                delta.append(literal);
+            }
+        };
+        let mut pos = 0;
+        while pos < data.len() {
+            let end = (pos + block_size).min(data.len());
+            let window = &data[pos..end];
+            let found = blocks.get(&weak_checksum(window)).and_then(|candidates| {
+                let strong = format!("{:x}", Sha256::digest(window));
+                candidates.iter().find(|(_, v)| *v == strong).map(|v| v.0)
+            });
+            match found {
+                Some(index) => {
+                    flush(&mut delta, &mut literal);
+                    delta.push(OP_COPY);
+                    delta.extend_from_slice(&(index as u64).to_be_bytes());
+                    delta.extend_from_slice(&1u32.to_be_bytes());
+                    pos = end;
+                }
+                None => {
+                    literal.push(data[pos]);
+                    pos += 1;
+                }
+            }
+        }
+        flush(&mut delta, &mut literal);
+        delta
+    }
+
+    #[tokio::test]
+    async fn test_delta_roundtrip() {
+        let base: Vec<u8> = (0..10_000u32).map(|v| (v * 7 % 251) as u8).collect();
+        let mut data = base.clone();
+        data.splice(3000..3000, b"inserted".iter().copied());
+        data.truncate(9000);
+        data.extend_from_slice(b"tail");
+
+        let signature = signature(&mut Cursor::new(&base), MIN_BLOCK_SIZE)
+            .await
+            .unwrap();
+        assert_eq!(signature.size, base.len() as u64);
+        assert_eq!(signature.blocks.len(), 10);
+
+        let delta = diff(&signature, &data);
+        assert!(delta.len() < data.len() / 2);
+        let mut output = vec![];
+        let stats = apply(
+            &mut Cursor::new(&base),
+            base.len() as u64,
+            &mut Cursor::new(&delta),
+            &mut output,
+            u64::MAX,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(output, data);
+        assert_eq!(stats.size, data.len() as u64);
+        assert!(stats.literal_bytes < 2 * MIN_BLOCK_SIZE as u64);
+
+        let limited = apply(
+            &mut Cursor::new(&base),
+            base.len() as u64,
+            &mut Cursor::new(&delta),
+            &mut vec![],
+            100,
+        )
+        .await
+        .unwrap();
+        assert!(limited.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_delta_rejects_bad_copy() {
+        let mut delta = MAGIC.to_vec();
+        delta.extend_from_slice(&MIN_BLOCK_SIZE.to_be_bytes());
+        delta.push(OP_COPY);
+        delta.extend_from_slice(&5u64.to_be_bytes());
+        delta.extend_from_slice(&1u32.to_be_bytes());
+        let ret = apply(
+            &mut Cursor::new(vec![0u8; 100]),
+            100,
+            &mut Cursor::new(delta),
+            &mut vec![],
+            u64::MAX,
+        )
+        .await;
+        assert!(ret.is_err());
+    }
+}
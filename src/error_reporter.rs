@@ -0,0 +1,253 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{SecondsFormat, Utc};
+use hyper::HeaderMap;
+use reqwest::Url;
+use serde_json::{json, Value};
+use std::backtrace::BacktraceStatus;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Headers that must never leave the server in an error report. The referer
+/// can hold the token of the page the request came from.
+const REDACTED_HEADERS: [&str; 4] = ["authorization", "cookie", "proxy-authorization", "referer"];
+
+const REDACTED: &str = "[redacted]";
+
+const REPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Destination for internal server errors.
+///
+/// A Sentry DSN (`https://<key>@<host>/<project>`) is reported through the
+/// Sentry store API; any other URL receives the report as a plain JSON POST.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorReporter {
+    Sentry { store_url: Url, public_key: String },
+    Webhook(Url),
+}
+
+/// Request details captured before the request is handed to the handlers.
+#[derive(Debug, Clone)]
+pub struct ErrorContext {
+    pub method: String,
+    pub uri: String,
+    pub headers: Vec<(String, String)>,
+    pub remote_addr: Option<SocketAddr>,
+}
+
+impl ErrorContext {
+    pub fn new(
+        method: &hyper::Method,
+        uri: &hyper::Uri,
+        headers: &HeaderMap,
+        remote_addr: Option<SocketAddr>,
+    ) -> Self {
+        let headers = headers
+            .iter()
+            .filter(|(name, _)| !REDACTED_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| {
+                value
+                    .to_str()
+                    .ok()
+                    .map(|v| (name.to_string(), v.to_string()))
+            })
+            .collect();
+        Self {
+            method: method.to_string(),
+            uri: redact_uri(uri),
+            headers,
+            remote_addr,
+        }
+    }
+}
+
+/// The path and query flags of `uri` without anything that could grant access,
+/// i.e. query values such as `?token=` and the token of a `/share/` link
+fn redact_uri(uri: &hyper::Uri) -> String {
+    let mut path = uri.path().to_string();
+    if let Some(rest) = path.strip_prefix("/share/") {
+        let tail = rest.find('/').map(|i| &rest[i..]).unwrap_or_default();
+        path = format!("/share/{REDACTED}{tail}");
+    }
+    let Some(query) = uri.query() else {
+        return path;
+    };
+    let query: Vec<_> = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) => format!("{key}={REDACTED}"),
+            None => pair.to_string(),
+        })
+        .collect();
+    format!("{path}?{}", query.join("&"))
+}
+
+impl ErrorReporter {
+    /// Send the report in the background so the failing request is not delayed.
+    pub fn report(&self, err: &anyhow::Error, ctx: ErrorContext) {
+        let payload = match self {
+            ErrorReporter::Sentry { .. } => sentry_event(err, &ctx),
+            ErrorReporter::Webhook(_) => webhook_payload(err, &ctx),
+        };
+        let reporter = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = reporter.send(payload).await {
+                warn!("Failed to report error, {err}");
+            }
+        });
+    }
+
+    async fn send(&self, payload: Value) -> Result<()> {
+        let client = reqwest::Client::builder().timeout(REPORT_TIMEOUT).build()?;
+        let req = match self {
+            ErrorReporter::Sentry {
+                store_url,
+                public_key,
+            } => client.post(store_url.clone()).header(
+                "X-Sentry-Auth",
+                format!(
+                    "Sentry sentry_version=7, sentry_key={public_key}, sentry_client={}/{}",
+                    env!("CARGO_CRATE_NAME"),
+                    env!("CARGO_PKG_VERSION")
+                ),
+            ),
+            ErrorReporter::Webhook(url) => client.post(url.clone()),
+        };
+        let res = req.json(&payload).send().await?;
+        if !res.status().is_success() {
+            bail!("{} responded with {}", self.host(), res.status());
+        }
+        Ok(())
+    }
+
+    fn host(&self) -> &str {
+        let url = match self {
+            ErrorReporter::Sentry { store_url, .. } => store_url,
+            ErrorReporter::Webhook(url) => url,
+        };
+        url.host_str().unwrap_or_default()
+    }
+}
+
+impl FromStr for ErrorReporter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let url = Url::parse(s).map_err(|err| anyhow!("Invalid error report url `{s}`, {err}"))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            bail!("Invalid error report url `{s}`, only http(s) is supported");
+        }
+        // A DSN carries the public key as username and the project id as the last segment
+        let project_id = url
+            .path_segments()
+            .and_then(|mut v| v.next_back())
+            .filter(|v| !v.is_empty() && v.chars().all(|c| c.is_ascii_digit()));
+        match (url.username(), project_id) {
+            ("", _) | (_, None) => Ok(ErrorReporter::Webhook(url)),
+            (public_key, Some(project_id)) => {
+                let prefix = url.path().trim_end_matches(project_id);
+                let mut store_url = url.clone();
+                store_url.set_path(&format!("{prefix}api/{project_id}/store/"));
+                let _ = store_url.set_username("");
+                let _ = store_url.set_password(None);
+                Ok(ErrorReporter::Sentry {
+                    store_url,
+                    public_key: public_key.to_string(),
+                })
+            }
+        }
+    }
+}
+
+fn backtrace_text(err: &anyhow::Error) -> Option<String> {
+    let backtrace = err.backtrace();
+    match backtrace.status() {
+        BacktraceStatus::Captured => Some(backtrace.to_string()),
+        _ => None,
+    }
+}
+
+fn webhook_payload(err: &anyhow::Error, ctx: &ErrorContext) -> Value {
+    json!({
+        "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        "server": format!("{}/{}", env!("CARGO_CRATE_NAME"), env!("CARGO_PKG_VERSION")),
+        "message": err.to_string(),
+        "causes": err.chain().skip(1).map(|v| v.to_string()).collect::<Vec<_>>(),
+        "backtrace": backtrace_text(err),
+        "request": {
+            "method": ctx.method,
+            "uri": ctx.uri,
+            "headers": ctx.headers,
+            "remote_addr": ctx.remote_addr.map(|v| v.ip().to_string()),
+        },
+    })
+}
+
+fn sentry_event(err: &anyhow::Error, ctx: &ErrorContext) -> Value {
+    // Sentry lists chained exceptions from the root cause to the outermost error
+    let exceptions: Vec<Value> = err
+        .chain()
+        .rev()
+        .map(|v| json!({ "type": "Error", "value": v.to_string() }))
+        .collect();
+    json!({
+        "event_id": Uuid::new_v4().simple().to_string(),
+        "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        "platform": "other",
+        "level": "error",
+        "logger": env!("CARGO_CRATE_NAME"),
+        "release": format!("{}@{}", env!("CARGO_CRATE_NAME"), env!("CARGO_PKG_VERSION")),
+        "exception": { "values": exceptions },
+        "request": {
+            "method": ctx.method,
+            "url": ctx.uri,
+            "headers": ctx.headers,
+        },
+        "user": { "ip_address": ctx.remote_addr.map(|v| v.ip().to_string()) },
+        "extra": { "backtrace": backtrace_text(err) },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sentry_dsn() {
+        let reporter: ErrorReporter = "https://abc123@o1.ingest.sentry.io/42".parse().unwrap();
+        assert_eq!(
+            reporter,
+            ErrorReporter::Sentry {
+                store_url: Url::parse("https://o1.ingest.sentry.io/api/42/store/").unwrap(),
+                public_key: "abc123".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_redact_uri() {
+        let redact = |uri: &str| redact_uri(&uri.parse().unwrap());
+        assert_eq!(redact("/dir/a.txt"), "/dir/a.txt");
+        assert_eq!(
+            redact("/dir/a.txt?token=secret&zip"),
+            "/dir/a.txt?token=[redacted]&zip"
+        );
+        assert_eq!(
+            redact("/share/abc123/download"),
+            "/share/[redacted]/download"
+        );
+        assert_eq!(redact("/share/abc123"), "/share/[redacted]");
+    }
+
+    #[test]
+    fn test_parse_webhook() {
+        let reporter: ErrorReporter = "http://localhost:9000/hooks/errors".parse().unwrap();
+        assert_eq!(
+            reporter,
+            ErrorReporter::Webhook(Url::parse("http://localhost:9000/hooks/errors").unwrap())
+        );
+        assert!("ftp://example.com/1".parse::<ErrorReporter>().is_err());
+        assert!("not a url".parse::<ErrorReporter>().is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use chrono::Utc;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+/// How many changes a slow subscriber may fall behind before it misses some.
+const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Upload,
+    Mkdir,
+    Delete,
+    Copy,
+    Move,
+    Provenance,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Upload => "upload",
+            Self::Mkdir => "mkdir",
+            Self::Delete => "delete",
+            Self::Copy => "copy",
+            Self::Move => "move",
+            Self::Provenance => "provenance",
+        }
+    }
+}
+
+/// A change to the served tree, with paths relative to the serve root.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeEvent {
+    pub kind: ChangeKind,
+    pub path: String,
+    /// Destination of a copy or move
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    /// Event action (mint, transfer, ...) for provenance changes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    pub at: String,
+}
+
+impl ChangeEvent {
+    pub fn new(kind: ChangeKind, path: String) -> Self {
+        Self {
+            kind,
+            path,
+            to: None,
+            action: None,
+            at: Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Whether the event touches `prefix` or anything below it
+    pub fn is_under(&self, prefix: &str) -> bool {
+        let prefix = prefix.trim_end_matches('/');
+        let matches = |path: &str| {
+            path == prefix
+                || path
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        };
+        matches(&self.path) || self.to.as_deref().is_some_and(matches)
+    }
+}
+
+/// The provenance action a request took, left on its response for the access log.
+#[derive(Debug, Clone)]
+pub struct ProvenanceAction(pub String);
+
+/// Fan-out of change events to every connected listener.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<ChangeEvent>,
+    closed: CancellationToken,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            tx,
+            closed: CancellationToken::new(),
+        }
+    }
+}
+
+impl EventBus {
+    pub fn publish(&self, event: ChangeEvent) {
+        // Nobody listening is not an error
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Tell listeners to hang up, e.g. because the server is shutting down
+    pub fn close(&self) {
+        self.closed.cancel();
+    }
+
+    /// Resolves once the bus is closed
+    pub async fn closed(&self) {
+        self.closed.cancelled().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_under() {
+        let mut event = ChangeEvent::new(ChangeKind::Move, "/dir1/a.txt".into());
+        assert!(event.is_under("/"));
+        assert!(event.is_under("/dir1"));
+        assert!(event.is_under("/dir1/"));
+        assert!(!event.is_under("/dir"));
+        assert!(!event.is_under("/dir2"));
+        event.to = Some("/dir2/a.txt".into());
+        assert!(event.is_under("/dir2"));
+    }
+}
@@ -16,7 +16,7 @@ pub struct FileInfo {
 /// Compute SHA-256 hash of a file's contents
 /// This is the canonical implementation used throughout the codebase
 pub async fn sha256_file_hash(path: &Path) -> Result<String> {
-    let mut file = fs::File::open(path).await?;
+    let mut file = crate::chunk_store::open(path).await?;
     let mut hasher = Sha256::new();
     let mut buffer = [0u8; 8192];
 
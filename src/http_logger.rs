@@ -1,12 +1,28 @@
+use chrono::{Local, SecondsFormat};
+use serde_json::{Map, Value};
 use std::{collections::HashMap, str::FromStr};
 
-use crate::{auth::get_auth_user, server::Request, utils::decode_uri};
+use crate::{
+    auth::get_auth_user,
+    server::{Request, Route},
+    utils::decode_uri,
+};
 
 pub const DEFAULT_LOG_FORMAT: &str = r#"$remote_addr "$request" $status"#;
 
+/// `--log-format` value switching to one JSON object per line
+pub const JSON_LOG_FORMAT: &str = "json";
+
+/// Log target of access entries, which are already JSON in the JSON format
+pub const ACCESS_LOG_TARGET: &str = "access";
+
+/// Fields logged as numbers in the JSON format
+const NUMERIC_FIELDS: &[&str] = &["status", "latency_ms", "bytes_received", "bytes_sent"];
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct HttpLogger {
     elements: Vec<LogElement>,
+    json: bool,
 }
 
 impl Default for HttpLogger {
@@ -23,8 +39,38 @@ enum LogElement {
 }
 
 impl HttpLogger {
+    pub fn is_json(&self) -> bool {
+        self.json
+    }
+
+    /// Whether requests to `route` are logged: the API in the template format,
+    /// everything but SPA assets in the JSON one
+    pub fn logs(&self, route: Route) -> bool {
+        match self.json {
+            true => route != Route::Asset,
+            false => matches!(route, Route::Api(_)),
+        }
+    }
+
     pub fn data(&self, req: &Request) -> HashMap<String, String> {
         let mut data = HashMap::default();
+        if self.json {
+            let uri = req.uri().to_string();
+            let uri = decode_uri(&uri).map(|s| s.to_string()).unwrap_or(uri);
+            data.insert("method".to_string(), req.method().to_string());
+            data.insert("uri".to_string(), uri);
+            if let Some(user) = req.headers().get("authorization").and_then(get_auth_user) {
+                data.insert("user".to_string(), user);
+            }
+            if let Some(value) = req
+                .headers()
+                .get("user-agent")
+                .and_then(|v| v.to_str().ok())
+            {
+                data.insert("user_agent".to_string(), value.to_string());
+            }
+            return data;
+        }
         for element in self.elements.iter() {
             match element {
                 LogElement::Variable(name) => match name.as_str() {
@@ -54,6 +100,9 @@ impl HttpLogger {
     }
 
     pub fn log(&self, data: &HashMap<String, String>, err: Option<String>) {
+        if self.json {
+            return self.log_json(data, err);
+        }
         if self.elements.is_empty() {
             return;
         }
@@ -71,11 +120,45 @@ impl HttpLogger {
             None => info!("{output}"),
         }
     }
+
+    fn log_json(&self, data: &HashMap<String, String>, err: Option<String>) {
+        let mut entry = Map::new();
+        entry.insert(
+            "time".to_string(),
+            Local::now()
+                .to_rfc3339_opts(SecondsFormat::Secs, true)
+                .into(),
+        );
+        let level = if err.is_some() { "ERROR" } else { "INFO" };
+        entry.insert("level".to_string(), level.into());
+        let mut fields: Vec<_> = data.iter().collect();
+        fields.sort();
+        for (name, value) in fields {
+            let value = match value.parse::<u64>() {
+                Ok(v) if NUMERIC_FIELDS.contains(&name.as_str()) => v.into(),
+                _ => Value::String(value.clone()),
+            };
+            entry.insert(name.clone(), value);
+        }
+        match err {
+            Some(err) => {
+                entry.insert("error".to_string(), err.into());
+                error!(target: ACCESS_LOG_TARGET, "{}", Value::Object(entry));
+            }
+            None => info!(target: ACCESS_LOG_TARGET, "{}", Value::Object(entry)),
+        }
+    }
 }
 
 impl FromStr for HttpLogger {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == JSON_LOG_FORMAT {
+            return Ok(Self {
+                elements: vec![],
+                json: true,
+            });
+        }
         let mut elements = vec![];
         let mut is_var = false;
         let mut cache = String::new();
@@ -101,6 +184,9 @@ impl FromStr for HttpLogger {
         if !cache.is_empty() {
             elements.push(LogElement::Literal(cache.to_string()));
         }
-        Ok(Self { elements })
+        Ok(Self {
+            elements,
+            json: false,
+        })
     }
 }
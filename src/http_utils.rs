@@ -3,10 +3,13 @@ use futures_util::Stream;
 use http_body_util::{combinators::BoxBody, BodyExt, Full};
 use hyper::body::{Body, Incoming};
 use std::{
+    future::Future,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 use tokio::io::AsyncRead;
+use tokio::time::{Instant, Sleep};
 use tokio_util::io::poll_read_buf;
 
 #[derive(Debug)]
@@ -48,12 +51,13 @@ pin_project_lite::pin_project! {
 }
 
 impl<R> LengthLimitedStream<R> {
-    pub fn new(reader: R, limit: usize) -> Self {
+    /// Stream at most `limit` bytes of `reader`, reading `capacity` bytes at a time
+    pub fn with_capacity(reader: R, limit: usize, capacity: usize) -> Self {
         Self {
             reader: Some(reader),
             remaining: limit,
             buf: BytesMut::new(),
-            capacity: 4096,
+            capacity,
         }
     }
 }
@@ -98,6 +102,55 @@ impl<R: AsyncRead> Stream for LengthLimitedStream<R> {
     }
 }
 
+pin_project_lite::pin_project! {
+    /// Paces a stream of chunks to at most `rate` bytes per second on average.
+    pub struct ThrottledStream<S> {
+        #[pin]
+        inner: S,
+        #[pin]
+        delay: Option<Sleep>,
+        rate: u64,
+        started: Instant,
+        sent: u64,
+    }
+}
+
+impl<S> ThrottledStream<S> {
+    pub fn new(inner: S, rate: u64) -> Self {
+        Self {
+            inner,
+            delay: None,
+            rate,
+            started: Instant::now(),
+            sent: 0,
+        }
+    }
+}
+
+impl<S: Stream<Item = std::io::Result<Bytes>>> Stream for ThrottledStream<S> {
+    type Item = std::io::Result<Bytes>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        if let Some(delay) = this.delay.as_mut().as_pin_mut() {
+            futures_util::ready!(delay.poll(cx));
+            this.delay.set(None);
+        }
+
+        let item = futures_util::ready!(this.inner.poll_next(cx));
+        if let Some(Ok(chunk)) = &item {
+            *this.sent += chunk.len() as u64;
+            // Hold the next chunk back until the average rate is met again
+            let due =
+                *this.started + Duration::from_secs_f64(*this.sent as f64 / *this.rate as f64);
+            if due > Instant::now() {
+                this.delay.set(Some(tokio::time::sleep_until(due)));
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
 pub fn body_full(content: impl Into<hyper::body::Bytes>) -> BoxBody<Bytes, anyhow::Error> {
     Full::new(content.into())
         .map_err(anyhow::Error::new)
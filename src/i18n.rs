@@ -0,0 +1,114 @@
+use hyper::header::HeaderValue;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+pub const DEFAULT_LANG: &str = "en";
+
+/// Strings of the web UI by language, embedded in the binary. English comes
+/// first and fills in strings missing from the other tables.
+const LOCALES: &[(&str, &str)] = &[
+    (DEFAULT_LANG, include_str!("locales/en.json")),
+    ("de", include_str!("locales/de.json")),
+    ("es", include_str!("locales/es.json")),
+    ("fr", include_str!("locales/fr.json")),
+];
+
+lazy_static! {
+    static ref STRINGS: BTreeMap<&'static str, BTreeMap<String, String>> = LOCALES
+        .iter()
+        .map(|(lang, raw)| {
+            let mut strings = parse_strings(LOCALES[0].1);
+            strings.extend(parse_strings(raw));
+            (*lang, strings)
+        })
+        .collect();
+}
+
+fn parse_strings(raw: &str) -> BTreeMap<String, String> {
+    serde_json::from_str(raw).expect("Invalid locale strings")
+}
+
+/// Strings of the web UI in one language, for `IndexData` and `EditData`
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Locale {
+    pub lang: &'static str,
+    pub strings: &'static BTreeMap<String, String>,
+}
+
+impl Locale {
+    /// `--lang` if set, else the first language of `Accept-Language` there
+    /// are strings for, else English
+    pub fn select(lang: Option<&str>, accept_language: Option<&HeaderValue>) -> Self {
+        let accepted = accept_language
+            .and_then(|v| v.to_str().ok())
+            .map(accepted_langs)
+            .unwrap_or_default();
+        lang.into_iter()
+            .chain(accepted)
+            .find_map(Self::get)
+            .unwrap_or_else(|| Self::get(DEFAULT_LANG).expect("Missing default locale"))
+    }
+
+    /// Strings of a language tag like `de` or `de-CH`
+    pub fn get(tag: &str) -> Option<Self> {
+        let tag = tag.trim().to_ascii_lowercase();
+        let primary = tag.split('-').next().unwrap_or_default();
+        let (lang, strings) = STRINGS
+            .get_key_value(tag.as_str())
+            .or_else(|| STRINGS.get_key_value(primary))?;
+        Some(Self { lang, strings })
+    }
+
+    pub fn langs() -> Vec<&'static str> {
+        LOCALES.iter().map(|(lang, _)| *lang).collect()
+    }
+}
+
+/// Language tags of an `Accept-Language` value, most preferred first
+fn accepted_langs(value: &str) -> Vec<&str> {
+    let mut langs: Vec<(&str, f32)> = value
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let tag = parts.next()?.trim();
+            let q = parts
+                .find_map(|v| v.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |v| v.parse().ok())?;
+            (!tag.is_empty() && tag != "*" && q > 0.0).then_some((tag, q))
+        })
+        .collect();
+    // Stable, so tags of the same weight keep their order
+    langs.sort_by(|a, b| b.1.total_cmp(&a.1));
+    langs.into_iter().map(|(tag, _)| tag).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_strings() {
+        let keys: Vec<_> = parse_strings(LOCALES[0].1).into_keys().collect();
+        for (lang, raw) in LOCALES {
+            let strings = parse_strings(raw);
+            assert!(
+                strings.keys().all(|k| keys.contains(k)),
+                "Unknown strings in {lang}"
+            );
+            assert_eq!(Locale::get(lang).unwrap().strings.len(), keys.len());
+        }
+    }
+
+    #[test]
+    fn test_select_locale() {
+        let select = |lang: Option<&str>, accept: &'static str| {
+            Locale::select(lang, Some(&HeaderValue::from_static(accept))).lang
+        };
+        assert_eq!(select(None, "de-CH, fr;q=0.9, en;q=0.8"), "de");
+        assert_eq!(select(None, "ja, en;q=0.5, fr;q=0.7"), "fr");
+        assert_eq!(select(None, "fr;q=0, *"), "en");
+        assert_eq!(select(Some("es"), "de"), "es");
+        assert_eq!(Locale::select(None, None).lang, "en");
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+use crate::file_utils;
+use crate::provenance::{IntegrityCheck, ProvenanceDb};
+
+/// Compare the files under `roots` with their artifacts every `interval`
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntegrityScanner {
+    pub roots: Vec<PathBuf>,
+    pub interval: Duration,
+}
+
+impl IntegrityScanner {
+    pub fn spawn(self, db: ProvenanceDb) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            loop {
+                ticker.tick().await;
+                let roots = self.roots.clone();
+                let files = tokio::task::spawn_blocking(move || files(&roots)).await;
+                let Ok(files) = files else {
+                    continue;
+                };
+                let mut modified = 0;
+                for path in files {
+                    match check(&db, &path).await {
+                        Ok(Some(true)) => modified += 1,
+                        Ok(_) => {}
+                        Err(err) => warn!("Failed to check {}, {err}", path.display()),
+                    }
+                }
+                if modified > 0 {
+                    warn!("{modified} files no longer match their recorded hash");
+                }
+            }
+        });
+    }
+}
+
+/// Check `path` in the background, for files being served
+pub fn spawn_check(db: ProvenanceDb, path: PathBuf) {
+    tokio::spawn(async move {
+        if let Err(err) = check(&db, &path).await {
+            warn!("Failed to check {}, {err}", path.display());
+        }
+    });
+}
+
+/// Whether `path` differs from the content its artifact records, None for
+/// files without an artifact. The file is only hashed again once its
+/// modification time or size changed since the last check.
+pub async fn check(db: &ProvenanceDb, path: &Path) -> Result<Option<bool>> {
+    let path_str = path
+        .to_str()
+        .ok_or_else(|| anyhow!("Invalid UTF-8 in path"))?
+        .to_string();
+    let lookup_path = path_str.clone();
+    let (artifact, last_check) = db
+        .run(move |db| {
+            let artifact = db.get_artifact_by_path(&lookup_path)?;
+            Ok((artifact, db.get_integrity_check(&lookup_path)?))
+        })
+        .await?;
+    let Some((_, artifact)) = artifact else {
+        return Ok(None);
+    };
+    let meta = tokio::fs::metadata(path).await?;
+    let mtime = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |v| v.as_millis() as i64);
+    let size = meta.len() as i64;
+    let sha256_hex = match &last_check {
+        Some(last) if last.mtime == mtime && last.size == size => last.sha256_hex.clone(),
+        _ => file_utils::sha256_file_hash(path).await?,
+    };
+    let modified = sha256_hex != artifact.sha256_hex;
+    let check = IntegrityCheck {
+        mtime,
+        size,
+        sha256_hex,
+        modified,
+    };
+    if last_check.as_ref() != Some(&check) {
+        if modified {
+            warn!(
+                "{} was modified since it was minted, its artifact records {}",
+                path.display(),
+                artifact.sha256_hex
+            );
+        }
+        db.run(move |db| db.record_integrity_check(&path_str, &check))
+            .await?;
+    }
+    Ok(Some(modified))
+}
+
+fn files(roots: &[PathBuf]) -> Vec<PathBuf> {
+    roots
+        .iter()
+        .flat_map(|root| WalkDir::new(root).follow_links(true))
+        .flatten()
+        .filter(|v| v.file_type().is_file())
+        .map(|v| v.into_path())
+        .collect()
+}
@@ -0,0 +1,167 @@
+use anyhow::{anyhow, Result};
+use hyper::header::{HeaderMap, HeaderValue};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+
+/// Addresses and networks like `10.0.0.0/8`, `::1` or `192.168.1.7`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IpList {
+    nets: Vec<IpNet>,
+}
+
+impl IpList {
+    pub fn new(values: &[&str]) -> Result<Self> {
+        let nets = values
+            .iter()
+            .map(|v| {
+                let v = v.trim();
+                v.parse::<IpNet>()
+                    .or_else(|_| v.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| anyhow!("Invalid IP address or CIDR `{v}`, e.g. 10.0.0.0/8"))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { nets })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nets.is_empty()
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.nets.iter().any(|v| v.contains(&ip))
+    }
+}
+
+/// The address of the client behind `peer`
+///
+/// Requests from `trusted` proxies name the client in `Forwarded` or, without
+/// it, `X-Forwarded-For`. Hops are read from the right, skipping trusted
+/// proxies, so clients can't pose as another address by sending the header
+/// themselves. The port is the peer's, as proxies don't pass it on.
+pub fn client_addr(
+    trusted: &IpList,
+    peer: SocketAddr,
+    headers: &HeaderMap<HeaderValue>,
+) -> SocketAddr {
+    if !trusted.contains(peer.ip()) {
+        return peer;
+    }
+    let hops = match forwarded_for(headers) {
+        Some(hops) => hops,
+        None => x_forwarded_for(headers),
+    };
+    let mut client = peer.ip();
+    for hop in hops.iter().rev() {
+        match hop {
+            Some(ip) => client = *ip,
+            // Obfuscated or unknown hops end the trusted chain
+            None => break,
+        }
+        if !trusted.contains(client) {
+            break;
+        }
+    }
+    SocketAddr::new(client, peer.port())
+}
+
+/// The `for=` of each element of RFC 7239 `Forwarded`, None without the header
+fn forwarded_for(headers: &HeaderMap<HeaderValue>) -> Option<Vec<Option<IpAddr>>> {
+    let mut values = headers.get_all("forwarded").iter().peekable();
+    values.peek()?;
+    let hops = values
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.split_once('=')?;
+                key.trim()
+                    .eq_ignore_ascii_case("for")
+                    .then(|| parse_node(value))
+            })?
+        })
+        .collect();
+    Some(hops)
+}
+
+fn x_forwarded_for(headers: &HeaderMap<HeaderValue>) -> Vec<Option<IpAddr>> {
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(parse_node)
+        .collect()
+}
+
+/// An address as proxies write it: `1.2.3.4`, `1.2.3.4:80`, `"[::1]:80"`
+fn parse_node(value: &str) -> Option<IpAddr> {
+    let value = value.trim().trim_matches('"');
+    if let Ok(ip) = value.parse() {
+        return Some(ip);
+    }
+    if let Ok(addr) = value.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+    value
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .and_then(|v| v.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap<HeaderValue> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_ip_list() {
+        let list = IpList::new(&["10.0.0.0/8", "::1", "192.168.1.7"]).unwrap();
+        assert!(list.contains("10.1.2.3".parse().unwrap()));
+        assert!(list.contains("::1".parse().unwrap()));
+        assert!(list.contains("::ffff:192.168.1.7".parse().unwrap()));
+        assert!(!list.contains("192.168.1.8".parse().unwrap()));
+        assert!(IpList::new(&["10.0.0.0/33"]).is_err());
+        assert!(IpList::new(&["localhost"]).is_err());
+    }
+
+    #[test]
+    fn test_client_addr() {
+        let trusted = IpList::new(&["10.0.0.0/8"]).unwrap();
+        let proxy: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+        let ip = |headers| client_addr(&trusted, proxy, &headers).ip().to_string();
+
+        assert_eq!(ip(headers(&[("x-forwarded-for", "1.1.1.1")])), "1.1.1.1");
+        // 1.1.1.1 made up the first hop, the proxies added the others
+        assert_eq!(
+            ip(headers(&[(
+                "x-forwarded-for",
+                "6.6.6.6, 1.1.1.1, 10.0.0.2"
+            )])),
+            "1.1.1.1"
+        );
+        assert_eq!(
+            ip(headers(&[
+                (
+                    "forwarded",
+                    r#"for=6.6.6.6, for="[2001:db8::1]:443";proto=https"#
+                ),
+                ("x-forwarded-for", "7.7.7.7"),
+            ])),
+            "2001:db8::1"
+        );
+        assert_eq!(ip(headers(&[("forwarded", "for=unknown")])), "10.0.0.1");
+        assert_eq!(ip(headers(&[])), "10.0.0.1");
+
+        let peer: SocketAddr = "1.1.1.1:4000".parse().unwrap();
+        let spoofed = headers(&[("x-forwarded-for", "10.0.0.5")]);
+        assert_eq!(client_addr(&trusted, peer, &spoofed), peer);
+    }
+}
@@ -0,0 +1,86 @@
+//! Node-drive as a library, for serving files with their provenance from
+//! another Rust application.
+//!
+//! [`ServerBuilder`] takes the settings of the command line in code and builds
+//! a [`NodeDrive`], which is both a `tower` and a hyper service:
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use hyper_util::rt::{TokioExecutor, TokioIo};
+//! use hyper_util::server::conn::auto::Builder;
+//!
+//! let drive = node_drive::ServerBuilder::new("/srv/files")
+//!     .auth("admin:pass@/:rw")
+//!     .provenance_db("/var/lib/node-drive/provenance.db")
+//!     .build()?;
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:5000").await?;
+//! loop {
+//!     let (stream, _) = listener.accept().await?;
+//!     let drive = drive.clone();
+//!     tokio::spawn(async move {
+//!         let builder = Builder::new(TokioExecutor::new());
+//!         builder.serve_connection(TokioIo::new(stream), drive).await
+//!     });
+//! }
+//! # }
+//! ```
+
+#[macro_use]
+extern crate log;
+
+#[doc(hidden)]
+pub mod args;
+mod auth;
+mod chunk_store;
+mod delta;
+mod error_reporter;
+mod events;
+mod file_utils;
+mod http_logger;
+mod http_utils;
+mod i18n;
+mod integrity;
+mod ip_filter;
+#[doc(hidden)]
+pub mod logger;
+mod markdown;
+mod oidc;
+mod ots_aggregator;
+mod ots_stamper;
+mod ots_upgrader;
+mod provenance;
+mod provenance_anchor;
+#[doc(hidden)]
+pub mod provenance_backfill;
+mod provenance_backup;
+#[doc(hidden)]
+pub mod provenance_gc;
+mod provenance_migrations;
+#[cfg(feature = "postgres")]
+mod provenance_postgres;
+mod provenance_store;
+mod provenance_utils;
+mod quota;
+#[doc(hidden)]
+pub mod reload;
+mod retention;
+mod search_index;
+#[doc(hidden)]
+pub mod server;
+mod sessions;
+mod signing_keys;
+#[cfg(unix)]
+#[doc(hidden)]
+pub mod socket_activation;
+#[cfg(feature = "tls")]
+#[doc(hidden)]
+pub mod tls;
+mod trash;
+mod upload_types;
+mod utils;
+
+pub use crate::server::{
+    BoxFuture, Middleware, Next, NodeDrive, Request, Response, Route, ServerBuilder,
+};
+
+use crate::args::Args;
@@ -1,13 +1,151 @@
 use anyhow::{Context, Result};
-use chrono::{Local, SecondsFormat};
+use chrono::{DateTime, Local, SecondsFormat};
+use clap::{builder::PossibleValue, ValueEnum};
 use log::{Level, LevelFilter, Metadata, Record};
-use std::fs::{File, OpenOptions};
+use serde::Deserialize;
+use std::fs::{self, File, OpenOptions};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
+use crate::http_logger::ACCESS_LOG_TARGET;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RotateInterval {
+    Hourly,
+    Daily,
+}
+
+impl ValueEnum for RotateInterval {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Hourly, Self::Daily]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self {
+            RotateInterval::Hourly => PossibleValue::new("hourly"),
+            RotateInterval::Daily => PossibleValue::new("daily"),
+        })
+    }
+}
+
+impl RotateInterval {
+    /// Name of the period `time` falls in, a new one starts a new file
+    fn period(self, time: DateTime<Local>) -> String {
+        match self {
+            RotateInterval::Hourly => time.format("%Y%m%d%H").to_string(),
+            RotateInterval::Daily => time.format("%Y%m%d").to_string(),
+        }
+    }
+}
+
+/// When the log file is rotated to `<file>.1`, `<file>.2`, ...
+#[derive(Debug, Clone, Default)]
+pub struct LogRotation {
+    pub max_size: Option<u64>,
+    pub interval: Option<RotateInterval>,
+    /// Rotated files kept, older ones are removed
+    pub keep: usize,
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    period: Option<String>,
+    rotation: LogRotation,
+}
+
+impl LogFile {
+    fn open(path: PathBuf, rotation: LogRotation) -> Result<Self> {
+        let file = open_append(&path)?;
+        let meta = file.metadata()?;
+        // A file left from an earlier period is rotated on the first write
+        let period = rotation.interval.map(|interval| {
+            let modified = meta
+                .modified()
+                .map(DateTime::from)
+                .unwrap_or_else(|_| Local::now());
+            interval.period(modified)
+        });
+        Ok(Self {
+            path,
+            size: meta.len(),
+            file,
+            period,
+            rotation,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) {
+        let len = line.len() as u64 + 1;
+        let period = self.rotation.interval.map(|v| v.period(Local::now()));
+        let full = self
+            .rotation
+            .max_size
+            .is_some_and(|max_size| self.size > 0 && self.size + len > max_size);
+        if full || period != self.period {
+            if let Err(err) = self.rotate() {
+                eprintln!("Failed to rotate '{}', {err}", self.path.display());
+            }
+            self.period = period;
+        }
+        if writeln!(self.file, "{line}").is_ok() {
+            self.size += len;
+        }
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        let rotated = |i: usize| {
+            let mut name = self.path.as_os_str().to_owned();
+            name.push(format!(".{i}"));
+            PathBuf::from(name)
+        };
+        if self.rotation.keep > 0 {
+            for i in (1..self.rotation.keep).rev() {
+                let _ = fs::rename(rotated(i), rotated(i + 1));
+            }
+            fs::rename(&self.path, rotated(1))?;
+        } else {
+            fs::remove_file(&self.path)?;
+        }
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open the log file at '{}'", path.display()))
+}
+
 struct SimpleLogger {
-    file: Option<Mutex<File>>,
+    file: Option<Mutex<LogFile>>,
+    json: bool,
+}
+
+impl SimpleLogger {
+    fn format(&self, record: &Record) -> String {
+        let timestamp = Local::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        if !self.json {
+            return format!("{} {} - {}", timestamp, record.level(), record.args());
+        }
+        // Access entries are JSON objects already
+        if record.target() == ACCESS_LOG_TARGET {
+            return record.args().to_string();
+        }
+        serde_json::json!({
+            "time": timestamp,
+            "level": record.level().as_str(),
+            "message": record.args().to_string(),
+        })
+        .to_string()
+    }
 }
 
 impl log::Log for SimpleLogger {
@@ -17,12 +155,11 @@ impl log::Log for SimpleLogger {
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
-            let timestamp = Local::now().to_rfc3339_opts(SecondsFormat::Secs, true);
-            let text = format!("{} {} - {}", timestamp, record.level(), record.args());
+            let text = self.format(record);
             match &self.file {
                 Some(file) => {
                     if let Ok(mut file) = file.lock() {
-                        let _ = writeln!(file, "{text}");
+                        file.write_line(&text);
                     }
                 }
                 None => {
@@ -39,23 +176,39 @@ impl log::Log for SimpleLogger {
     fn flush(&self) {}
 }
 
-pub fn init(log_file: Option<PathBuf>) -> Result<()> {
+pub fn init(log_file: Option<PathBuf>, rotation: LogRotation, json: bool) -> Result<()> {
     let file = match log_file {
         None => None,
-        Some(log_file) => {
-            let file = OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(&log_file)
-                .with_context(|| {
-                    format!("Failed to open the log file at '{}'", log_file.display())
-                })?;
-            Some(Mutex::new(file))
-        }
+        Some(log_file) => Some(Mutex::new(LogFile::open(log_file, rotation)?)),
     };
-    let logger = SimpleLogger { file };
+    let logger = SimpleLogger { file, json };
     log::set_boxed_logger(Box::new(logger))
         .map(|_| log::set_max_level(LevelFilter::Info))
         .with_context(|| "Failed to init logger")?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate_by_size() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("access.log");
+        let rotation = LogRotation {
+            max_size: Some(10),
+            interval: None,
+            keep: 2,
+        };
+        let mut file = LogFile::open(path.clone(), rotation).unwrap();
+        for line in ["first", "second", "third", "fourth"] {
+            file.write_line(line);
+        }
+        let read = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("access.log"), "fourth\n");
+        assert_eq!(read("access.log.1"), "third\n");
+        assert_eq!(read("access.log.2"), "second\n");
+        assert!(!dir.path().join("access.log.3").exists());
+    }
+}
@@ -1,25 +1,17 @@
-mod args;
-mod auth;
-mod file_utils;
-mod http_logger;
-mod http_utils;
-mod logger;
-mod ots_stamper;
-mod provenance;
-mod provenance_utils;
-mod server;
-mod utils;
-
 #[macro_use]
 extern crate log;
 
-use crate::args::{build_cli, print_completions, Args};
-use crate::server::Server;
+use node_drive::args::{build_cli, check_addrs, print_completions, Args, BindAddr};
+use node_drive::reload::ReloadableServer;
+use node_drive::server::Server;
+#[cfg(unix)]
+use node_drive::socket_activation::{self, ActivatedListener};
 #[cfg(feature = "tls")]
-use crate::utils::{load_certs, load_private_key};
+use node_drive::tls::ReloadableCert;
+use node_drive::{logger, provenance_backfill, provenance_gc};
 
 use anyhow::{anyhow, Context, Result};
-use args::BindAddr;
+use clap::ArgMatches;
 use clap_complete::Shell;
 use futures_util::future::join_all;
 
@@ -38,7 +30,8 @@ use std::time::Duration;
 use tokio::time::timeout;
 use tokio::{net::TcpListener, task::JoinHandle};
 #[cfg(feature = "tls")]
-use tokio_rustls::{rustls::ServerConfig, TlsAcceptor};
+use tokio_rustls::TlsAcceptor;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
 use tower::ServiceBuilder;
 use tower_http::compression::CompressionLayer;
 
@@ -51,13 +44,42 @@ async fn main() -> Result<()> {
         print_completions(*generator, &mut cmd);
         return Ok(());
     }
-    let mut args = Args::parse(matches)?;
-    logger::init(args.log_file.clone()).map_err(|e| anyhow!("Failed to init logger, {e}"))?;
+    if let Some(("gc", gc_matches)) = matches.subcommand() {
+        let args = Args::parse(matches.clone())?;
+        return provenance_gc::run(&args, gc_matches.get_flag("dry-run")).await;
+    }
+    if let Some(("provenance", provenance_matches)) = matches.subcommand() {
+        if let Some(("backfill", backfill_matches)) = provenance_matches.subcommand() {
+            let args = Args::parse(matches.clone())?;
+            let jobs = backfill_matches
+                .get_one::<u64>("jobs")
+                .map(|v| *v as usize)
+                .unwrap_or_else(provenance_backfill::default_jobs);
+            return provenance_backfill::run(&args, jobs).await;
+        }
+    }
+    let mut args = Args::parse(matches.clone())?;
+    #[cfg(unix)]
+    {
+        // Started by systemd: serve the sockets it passed instead of binding our own
+        let fds = socket_activation::listen_fds()?;
+        if !fds.is_empty() {
+            args.addrs = fds.into_iter().map(BindAddr::Fd).collect();
+        }
+    }
+    logger::init(
+        args.log_file.clone(),
+        args.log_rotation(),
+        args.http_logger.is_json(),
+    )
+    .map_err(|e| anyhow!("Failed to init logger, {e}"))?;
     let (new_addrs, print_addrs) = check_addrs(&args)?;
     args.addrs = new_addrs;
     let running = Arc::new(AtomicBool::new(true));
+    let shutdown = Shutdown::default();
+    let shutdown_timeout = Duration::from_secs(args.shutdown_timeout);
     let listening = print_listening(&args, &print_addrs)?;
-    let handles = serve(args, running.clone())?;
+    let (handles, server_handle) = serve(args, matches, running.clone(), shutdown.clone())?;
     println!("{listening}");
 
     tokio::select! {
@@ -70,82 +92,125 @@ async fn main() -> Result<()> {
             Ok(())
         },
         _ = shutdown_signal() => {
+            // Stop accepting, let in-flight requests (and their OTS stamps) finish
+            let server = server_handle.current();
+            shutdown.token.cancel();
+            server.close_event_streams();
+            shutdown.connections.close();
+            if timeout(shutdown_timeout, shutdown.connections.wait()).await.is_err() {
+                warn!(
+                    "Requests still in flight after {}s, shutting down anyway",
+                    shutdown_timeout.as_secs()
+                );
+            }
             running.store(false, Ordering::SeqCst);
-            Ok(())
+            server
+                .flush_provenance()
+                .map_err(|e| anyhow!("Failed to flush the provenance database, {e}"))
         },
     }
 }
 
-fn serve(args: Args, running: Arc<AtomicBool>) -> Result<Vec<JoinHandle<()>>> {
+/// Lets connections know the server is shutting down and keeps track of the
+/// ones still open
+#[derive(Clone, Default)]
+struct Shutdown {
+    token: CancellationToken,
+    connections: TaskTracker,
+}
+
+fn serve(
+    args: Args,
+    matches: ArgMatches,
+    running: Arc<AtomicBool>,
+    shutdown: Shutdown,
+) -> Result<(Vec<JoinHandle<()>>, Arc<ReloadableServer>)> {
     let addrs = args.addrs.clone();
     let port = args.port;
-    let tls_config = (args.tls_cert.clone(), args.tls_key.clone());
-    let server_handle = Arc::new(Server::init(args, running)?);
+    #[cfg(feature = "tls")]
+    let tls_acceptor = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert_file), Some(key_file)) => {
+            let cert = Arc::new(ReloadableCert::load(cert_file, key_file)?);
+            #[cfg(unix)]
+            cert.clone().reload_on_sighup()?;
+            Some(TlsAcceptor::from(cert.server_config()))
+        }
+        _ => None,
+    };
+    #[cfg(unix)]
+    let has_config = matches.contains_id("config");
+    let server_handle = Arc::new(ReloadableServer::new(Server::init(args, running)?, matches));
+    #[cfg(unix)]
+    if has_config {
+        server_handle.clone().reload_on_sighup()?;
+    }
+    let serve_tcp = |listener: TcpListener| -> JoinHandle<()> {
+        let server_handle = server_handle.clone();
+        let shutdown = shutdown.clone();
+        #[cfg(feature = "tls")]
+        if let Some(tls_acceptor) = tls_acceptor.clone() {
+            let handshake_timeout = Duration::from_secs(10);
+            return tokio::spawn(async move {
+                loop {
+                    let (stream, addr) = tokio::select! {
+                        _ = shutdown.token.cancelled() => break,
+                        ret = listener.accept() => match ret {
+                            Ok(v) => v,
+                            Err(_) => continue,
+                        },
+                    };
+                    let Some(stream) = timeout(handshake_timeout, tls_acceptor.accept(stream))
+                        .await
+                        .ok()
+                        .and_then(|v| v.ok())
+                    else {
+                        continue;
+                    };
+                    let stream = TokioIo::new(stream);
+                    shutdown.spawn(server_handle.clone(), stream, Some(addr));
+                }
+            });
+        }
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, addr) = tokio::select! {
+                    _ = shutdown.token.cancelled() => break,
+                    ret = listener.accept() => match ret {
+                        Ok(v) => v,
+                        Err(_) => continue,
+                    },
+                };
+                let stream = TokioIo::new(stream);
+                shutdown.spawn(server_handle.clone(), stream, Some(addr));
+            }
+        })
+    };
+    #[cfg(unix)]
+    let serve_unix = |listener: tokio::net::UnixListener| -> JoinHandle<()> {
+        let server_handle = server_handle.clone();
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            loop {
+                let stream = tokio::select! {
+                    _ = shutdown.token.cancelled() => break,
+                    ret = listener.accept() => match ret {
+                        Ok((stream, _addr)) => stream,
+                        Err(_) => continue,
+                    },
+                };
+                let stream = TokioIo::new(stream);
+                shutdown.spawn(server_handle.clone(), stream, None);
+            }
+        })
+    };
     let mut handles = vec![];
     for bind_addr in addrs.iter() {
-        let server_handle = server_handle.clone();
         match bind_addr {
             BindAddr::IpAddr(ip) => {
                 let listener = create_listener(SocketAddr::new(*ip, port))
                     .with_context(|| format!("Failed to bind `{ip}:{port}`"))?;
-
-                match &tls_config {
-                    #[cfg(feature = "tls")]
-                    (Some(cert_file), Some(key_file)) => {
-                        let certs = load_certs(cert_file)?;
-                        let key = load_private_key(key_file)?;
-                        let mut config = ServerConfig::builder()
-                            .with_no_client_auth()
-                            .with_single_cert(certs, key)?;
-                        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
-                        let config = Arc::new(config);
-                        let tls_accepter = TlsAcceptor::from(config);
-                        let handshake_timeout = Duration::from_secs(10);
-
-                        let handle = tokio::spawn(async move {
-                            loop {
-                                let Ok((stream, addr)) = listener.accept().await else {
-                                    continue;
-                                };
-                                let Some(stream) =
-                                    timeout(handshake_timeout, tls_accepter.accept(stream))
-                                        .await
-                                        .ok()
-                                        .and_then(|v| v.ok())
-                                else {
-                                    continue;
-                                };
-                                let stream = TokioIo::new(stream);
-                                tokio::spawn(handle_stream(
-                                    server_handle.clone(),
-                                    stream,
-                                    Some(addr),
-                                ));
-                            }
-                        });
-
-                        handles.push(handle);
-                    }
-                    (None, None) => {
-                        let handle = tokio::spawn(async move {
-                            loop {
-                                let Ok((stream, addr)) = listener.accept().await else {
-                                    continue;
-                                };
-                                let stream = TokioIo::new(stream);
-                                tokio::spawn(handle_stream(
-                                    server_handle.clone(),
-                                    stream,
-                                    Some(addr),
-                                ));
-                            }
-                        });
-                        handles.push(handle);
-                    }
-                    _ => {
-                        unreachable!()
-                    }
-                };
+                handles.push(serve_tcp(listener));
             }
             #[cfg(unix)]
             BindAddr::SocketPath(path) => {
@@ -162,30 +227,41 @@ fn serve(args: Args, running: Arc<AtomicBool>) -> Result<Vec<JoinHandle<()>>> {
                 };
                 let listener = tokio::net::UnixListener::bind(socket_path)
                     .with_context(|| format!("Failed to bind `{path}`"))?;
-                let handle = tokio::spawn(async move {
-                    loop {
-                        let Ok((stream, _addr)) = listener.accept().await else {
-                            continue;
-                        };
-                        let stream = TokioIo::new(stream);
-                        tokio::spawn(handle_stream(server_handle.clone(), stream, None));
-                    }
-                });
-
-                handles.push(handle);
+                handles.push(serve_unix(listener));
             }
+            #[cfg(unix)]
+            BindAddr::Fd(fd) => match socket_activation::take_listener(*fd)? {
+                ActivatedListener::Tcp(listener) => handles.push(serve_tcp(listener)),
+                ActivatedListener::Unix(listener) => handles.push(serve_unix(listener)),
+            },
         }
     }
-    Ok(handles)
+    Ok((handles, server_handle))
+}
+
+impl Shutdown {
+    /// Serve a connection until it closes, or until it finishes its
+    /// in-flight request once shutdown starts
+    fn spawn<T>(&self, handle: Arc<ReloadableServer>, stream: TokioIo<T>, addr: Option<SocketAddr>)
+    where
+        T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+    {
+        self.connections
+            .spawn(handle_stream(handle, stream, addr, self.token.clone()));
+    }
 }
 
-async fn handle_stream<T>(handle: Arc<Server>, stream: TokioIo<T>, addr: Option<SocketAddr>)
-where
+async fn handle_stream<T>(
+    handle: Arc<ReloadableServer>,
+    stream: TokioIo<T>,
+    addr: Option<SocketAddr>,
+    shutdown: CancellationToken,
+) where
     T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
 {
     // Create service with compression support
     let svc = tower::service_fn(move |req: Request<Incoming>| {
-        let handle = handle.clone();
+        let handle = handle.current();
         async move { handle.call(req, addr).await }
     });
 
@@ -197,10 +273,17 @@ where
     // Convert tower service to hyper service
     let hyper_service = TowerToHyperService::new(compressed_svc);
 
-    match Builder::new(TokioExecutor::new())
-        .serve_connection_with_upgrades(stream, hyper_service)
-        .await
-    {
+    let builder = Builder::new(TokioExecutor::new());
+    let conn = builder.serve_connection_with_upgrades(stream, hyper_service);
+    tokio::pin!(conn);
+    let ret = tokio::select! {
+        ret = conn.as_mut() => ret,
+        _ = shutdown.cancelled() => {
+            conn.as_mut().graceful_shutdown();
+            conn.await
+        }
+    };
+    match ret {
         Ok(()) => {}
         Err(_err) => {
             // This error only appears when the client doesn't send a request and terminate the connection.
@@ -225,63 +308,13 @@ fn create_listener(addr: SocketAddr) -> Result<TcpListener> {
     Ok(listener)
 }
 
-fn check_addrs(args: &Args) -> Result<(Vec<BindAddr>, Vec<BindAddr>)> {
-    let mut new_addrs = vec![];
-    let mut print_addrs = vec![];
-    let (ipv4_addrs, ipv6_addrs) = interface_addrs()?;
-    for bind_addr in args.addrs.iter() {
-        match bind_addr {
-            BindAddr::IpAddr(ip) => match &ip {
-                IpAddr::V4(_) => {
-                    if !ipv4_addrs.is_empty() {
-                        new_addrs.push(bind_addr.clone());
-                        if ip.is_unspecified() {
-                            print_addrs.extend(ipv4_addrs.clone());
-                        } else {
-                            print_addrs.push(bind_addr.clone());
-                        }
-                    }
-                }
-                IpAddr::V6(_) => {
-                    if !ipv6_addrs.is_empty() {
-                        new_addrs.push(bind_addr.clone());
-                        if ip.is_unspecified() {
-                            print_addrs.extend(ipv6_addrs.clone());
-                        } else {
-                            print_addrs.push(bind_addr.clone())
-                        }
-                    }
-                }
-            },
-            #[cfg(unix)]
-            _ => {
-                new_addrs.push(bind_addr.clone());
-                print_addrs.push(bind_addr.clone())
-            }
-        }
-    }
-    print_addrs.sort_unstable();
-    Ok((new_addrs, print_addrs))
-}
-
-fn interface_addrs() -> Result<(Vec<BindAddr>, Vec<BindAddr>)> {
-    let (mut ipv4_addrs, mut ipv6_addrs) = (vec![], vec![]);
-    let ifaces =
-        if_addrs::get_if_addrs().with_context(|| "Failed to get local interface addresses")?;
-    for iface in ifaces.into_iter() {
-        let ip = iface.ip();
-        if ip.is_ipv4() {
-            ipv4_addrs.push(BindAddr::IpAddr(ip))
-        }
-        if ip.is_ipv6() {
-            ipv6_addrs.push(BindAddr::IpAddr(ip))
-        }
-    }
-    Ok((ipv4_addrs, ipv6_addrs))
-}
-
 fn print_listening(args: &Args, print_addrs: &[BindAddr]) -> Result<String> {
     let mut output = String::new();
+    let protocol = if args.tls_cert.is_some() {
+        "https"
+    } else {
+        "http"
+    };
     let urls = print_addrs
         .iter()
         .map(|bind_addr| match bind_addr {
@@ -290,15 +323,19 @@ fn print_listening(args: &Args, print_addrs: &[BindAddr]) -> Result<String> {
                     IpAddr::V4(_) => format!("{}:{}", addr, args.port),
                     IpAddr::V6(_) => format!("[{}]:{}", addr, args.port),
                 };
-                let protocol = if args.tls_cert.is_some() {
-                    "https"
-                } else {
-                    "http"
-                };
                 format!("{}://{}{}", protocol, addr, args.uri_prefix)
             }
             #[cfg(unix)]
             BindAddr::SocketPath(path) => path.to_string(),
+            #[cfg(unix)]
+            BindAddr::Fd(fd) => match socket_activation::local_addr(*fd) {
+                Some(addr) => match (addr.as_socket(), addr.as_pathname()) {
+                    (Some(addr), _) => format!("{}://{}{}", protocol, addr, args.uri_prefix),
+                    (None, Some(path)) => path.display().to_string(),
+                    (None, None) => format!("fd {fd}"),
+                },
+                None => format!("fd {fd}"),
+            },
         })
         .collect::<Vec<_>>();
 
@@ -317,7 +354,22 @@ fn print_listening(args: &Args, print_addrs: &[BindAddr]) -> Result<String> {
 }
 
 async fn shutdown_signal() {
-    tokio::signal::ctrl_c()
-        .await
-        .expect("Failed to install CTRL+C signal handler")
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install CTRL+C signal handler")
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM signal handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
 }
@@ -0,0 +1,66 @@
+use pulldown_cmark::{html, Options, Parser};
+use std::sync::OnceLock;
+use syntect::{highlighting::ThemeSet, html::highlighted_html_for_string, parsing::SyntaxSet};
+
+const HIGHLIGHT_THEME: &str = "InspiredGitHub";
+
+static SYNTAXES: OnceLock<(SyntaxSet, ThemeSet)> = OnceLock::new();
+
+/// Render GitHub flavored `markdown` to HTML, sanitized so it is safe to
+/// put on a page: scripts, event handlers and `javascript:` links are dropped
+pub fn render(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+    let mut output = String::new();
+    html::push_html(&mut output, Parser::new_ext(markdown, options));
+    ammonia::clean(&output)
+}
+
+/// Highlight source `code` as a `<pre>` block with inline styles, picking
+/// the language from the file `extension` or a shebang line
+///
+/// Returns `None` when the language isn't recognized.
+pub fn highlight(code: &str, extension: &str) -> Option<String> {
+    let (syntaxes, themes) = SYNTAXES.get_or_init(|| {
+        (
+            SyntaxSet::load_defaults_newlines(),
+            ThemeSet::load_defaults(),
+        )
+    });
+    let syntax = syntaxes
+        .find_syntax_by_extension(extension)
+        .or_else(|| syntaxes.find_syntax_by_first_line(code))?;
+    highlighted_html_for_string(code, syntaxes, syntax, &themes.themes[HIGHLIGHT_THEME]).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render("# Title"), "<h1>Title</h1>\n");
+        assert_eq!(
+            render("| a |\n|---|\n| b |"),
+            "<table><thead><tr><th>a</th></tr></thead><tbody>\n<tr><td>b</td></tr>\n</tbody></table>\n"
+        );
+        let html = render(
+            "<script>alert(1)</script>\n\n[x](javascript:alert(1)) <img src=x onerror=alert(1)>",
+        );
+        assert!(!html.contains("script"));
+        assert!(!html.contains("javascript"));
+        assert!(!html.contains("onerror"));
+    }
+
+    #[test]
+    fn test_highlight() {
+        let html = highlight("fn main() { let a = \"<b>\"; }\n", "rs").unwrap();
+        assert!(html.starts_with("<pre style="));
+        assert!(html.contains("&lt;b&gt;"));
+        assert!(!html.contains("<b>"));
+        assert!(highlight("#!/bin/sh\necho hi\n", "").is_some());
+        assert!(highlight("plain words", "unknown").is_none());
+    }
+}
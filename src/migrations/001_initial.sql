@@ -0,0 +1,215 @@
+-- Schema as it stood when versioned migrations were introduced. Tables use
+-- IF NOT EXISTS so databases created before then are adopted as they are.
+
+CREATE TABLE IF NOT EXISTS artifacts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    file_path TEXT NOT NULL UNIQUE,
+    sha256_hex TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    verified_chain TEXT,
+    verified_timestamp INTEGER,
+    verified_height INTEGER,
+    last_check_at TEXT,
+    visibility TEXT NOT NULL DEFAULT 'private' CHECK(visibility IN ('private', 'public'))
+);
+
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    artifact_id INTEGER NOT NULL,
+    index_num INTEGER NOT NULL,
+    action TEXT NOT NULL CHECK(action IN ('mint', 'transfer')),
+    artifact_sha256_hex TEXT NOT NULL,
+    prev_event_hash_hex TEXT,
+    issued_at TEXT NOT NULL,
+    event_hash_hex TEXT NOT NULL UNIQUE,
+    ots_proof_b64 TEXT NOT NULL,
+    FOREIGN KEY (artifact_id) REFERENCES artifacts(id) ON DELETE CASCADE,
+    UNIQUE(artifact_id, index_num)
+);
+
+CREATE TABLE IF NOT EXISTS event_actors (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_id INTEGER NOT NULL,
+    role TEXT NOT NULL CHECK(role IN ('creator', 'prev_owner', 'new_owner')),
+    pubkey_hex TEXT NOT NULL,
+    FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS event_signatures (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_id INTEGER NOT NULL,
+    role TEXT NOT NULL CHECK(role IN ('creator', 'prev_owner', 'new_owner')),
+    signature_hex TEXT NOT NULL,
+    FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_artifacts_sha256 ON artifacts(sha256_hex);
+
+CREATE INDEX IF NOT EXISTS idx_artifacts_file_path ON artifacts(file_path);
+
+CREATE INDEX IF NOT EXISTS idx_events_artifact ON events(artifact_id);
+
+CREATE INDEX IF NOT EXISTS idx_event_actors_event ON event_actors(event_id);
+
+CREATE INDEX IF NOT EXISTS idx_event_signatures_event ON event_signatures(event_id);
+
+-- Create shares table for file sharing functionality
+CREATE TABLE IF NOT EXISTS shares (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    share_id TEXT NOT NULL UNIQUE,
+    file_path TEXT NOT NULL,
+    file_sha256_hex TEXT NOT NULL,
+    artifact_id INTEGER,
+    created_at TEXT NOT NULL,
+    shared_by TEXT,
+    owner_pubkey_hex TEXT NOT NULL,
+    share_signature_hex TEXT NOT NULL,
+    is_active INTEGER NOT NULL DEFAULT 1,
+    FOREIGN KEY (artifact_id) REFERENCES artifacts(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_shares_share_id ON shares(share_id);
+
+CREATE INDEX IF NOT EXISTS idx_shares_file_path ON shares(file_path);
+
+-- Create share_downloads table to track distribution chain
+CREATE TABLE IF NOT EXISTS share_downloads (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    share_id TEXT NOT NULL,
+    downloaded_at TEXT NOT NULL,
+    downloaded_by TEXT,
+    redistributor_pubkey_hex TEXT,
+    redistributor_signature_hex TEXT,
+    FOREIGN KEY (share_id) REFERENCES shares(share_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_share_downloads_share_id ON share_downloads(share_id);
+
+-- Free-form annotations attached to files
+CREATE TABLE IF NOT EXISTS file_metadata (
+    file_path TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    updated_by TEXT,
+    PRIMARY KEY (file_path, key)
+);
+
+CREATE TABLE IF NOT EXISTS file_comments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    file_path TEXT NOT NULL,
+    author TEXT,
+    body TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_file_comments_file_path ON file_comments(file_path);
+
+CREATE TABLE IF NOT EXISTS file_tags (
+    file_path TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (file_path, tag)
+);
+
+CREATE INDEX IF NOT EXISTS idx_file_tags_tag ON file_tags(tag);
+
+-- Per-path grants on top of the --auth rules, perm is 'ro' or 'rw'
+CREATE TABLE IF NOT EXISTS acl_entries (
+    file_path TEXT NOT NULL,
+    user TEXT NOT NULL,
+    perm TEXT NOT NULL,
+    granted_by TEXT,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (file_path, user)
+);
+
+-- Values owned by this database rather than the process, e.g. a tenant's keypair
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+-- Public key each authenticated user mints with instead of the server key
+CREATE TABLE IF NOT EXISTS user_keys (
+    user TEXT PRIMARY KEY,
+    public_key_hex TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- Every public key that has signed events, so rotated keys stay discoverable
+CREATE TABLE IF NOT EXISTS signing_keys (
+    public_key_hex TEXT PRIMARY KEY,
+    activated_at TEXT NOT NULL,
+    retired_at TEXT
+);
+
+-- Explicit visibility of a path, unset paths inherit from their parent
+CREATE TABLE IF NOT EXISTS path_visibility (
+    file_path TEXT PRIMARY KEY,
+    visibility TEXT NOT NULL CHECK(visibility IN ('private', 'public')),
+    updated_at TEXT NOT NULL,
+    updated_by TEXT
+);
+
+-- Bytes moved in and out of the drive, for usage statistics
+CREATE TABLE IF NOT EXISTS transfers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    file_path TEXT NOT NULL,
+    direction TEXT NOT NULL,
+    bytes INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_transfers_created_at ON transfers(created_at);
+
+CREATE TABLE IF NOT EXISTS favorites (
+    user TEXT NOT NULL,
+    file_path TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (user, file_path)
+);
+
+-- Who uploaded each file, for `--quota @<user>:<size>`
+CREATE TABLE IF NOT EXISTS file_owners (
+    file_path TEXT PRIMARY KEY,
+    user TEXT NOT NULL,
+    size INTEGER NOT NULL
+);
+
+-- WebDAV dead properties set with PROPPATCH
+CREATE TABLE IF NOT EXISTS dead_properties (
+    file_path TEXT NOT NULL,
+    namespace TEXT NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (file_path, namespace, name)
+);
+
+-- API tokens, keeping only a hash of their secret
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id TEXT PRIMARY KEY,
+    secret_sha256_hex TEXT NOT NULL,
+    user TEXT NOT NULL,
+    name TEXT,
+    scopes TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at INTEGER
+);
+
+-- Name index for `--index`, the trigram FTS table serves `LIKE '%q%'`
+CREATE TABLE IF NOT EXISTS search_entries (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    is_dir INTEGER NOT NULL
+);
+CREATE VIRTUAL TABLE IF NOT EXISTS search_names USING fts5(
+    name, content = 'search_entries', content_rowid = 'id', tokenize = 'trigram'
+);
+CREATE TRIGGER IF NOT EXISTS search_entries_ai AFTER INSERT ON search_entries BEGIN
+    INSERT INTO search_names (rowid, name) VALUES (new.id, new.name);
+END;
+CREATE TRIGGER IF NOT EXISTS search_entries_ad AFTER DELETE ON search_entries BEGIN
+    INSERT INTO search_names (search_names, rowid, name) VALUES ('delete', old.id, old.name);
+END;
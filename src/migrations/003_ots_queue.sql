@@ -0,0 +1,9 @@
+-- Digests of events recorded without a calendar response, stamped once the
+-- calendars can be reached
+CREATE TABLE ots_queue (
+    artifact_id INTEGER NOT NULL,
+    event_index INTEGER NOT NULL,
+    digest_hex TEXT NOT NULL,
+    queued_at TEXT NOT NULL,
+    PRIMARY KEY (artifact_id, event_index)
+);
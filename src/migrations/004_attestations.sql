@@ -0,0 +1,12 @@
+-- Detached signatures of third parties over an artifact's content
+CREATE TABLE attestations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    artifact_id INTEGER NOT NULL,
+    artifact_sha256_hex TEXT NOT NULL,
+    algorithm TEXT NOT NULL CHECK(algorithm IN ('secp256k1', 'ed25519')),
+    pubkey_hex TEXT NOT NULL,
+    signature_hex TEXT NOT NULL,
+    signed_at TEXT NOT NULL,
+    FOREIGN KEY (artifact_id) REFERENCES artifacts(id) ON DELETE CASCADE,
+    UNIQUE(artifact_id, artifact_sha256_hex, pubkey_hex)
+);
@@ -0,0 +1,55 @@
+-- Copies carry the events of their source, so event hashes are unique per
+-- artifact rather than overall, and `copy` joins the actions. SQLite can't
+-- change constraints: the tables are rebuilt under new names, and renaming
+-- `events_new` points the rebuilt actors and signatures at `events`.
+
+CREATE TABLE events_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    artifact_id INTEGER NOT NULL,
+    index_num INTEGER NOT NULL,
+    action TEXT NOT NULL CHECK(action IN ('mint', 'transfer', 'copy')),
+    artifact_sha256_hex TEXT NOT NULL,
+    prev_event_hash_hex TEXT,
+    issued_at TEXT NOT NULL,
+    event_hash_hex TEXT NOT NULL,
+    ots_proof_b64 TEXT NOT NULL,
+    FOREIGN KEY (artifact_id) REFERENCES artifacts(id) ON DELETE CASCADE,
+    UNIQUE(artifact_id, index_num),
+    UNIQUE(artifact_id, event_hash_hex)
+);
+INSERT INTO events_new (id, artifact_id, index_num, action, artifact_sha256_hex, prev_event_hash_hex, issued_at, event_hash_hex, ots_proof_b64)
+SELECT id, artifact_id, index_num, action, artifact_sha256_hex, prev_event_hash_hex, issued_at, event_hash_hex, ots_proof_b64 FROM events;
+
+CREATE TABLE event_actors_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_id INTEGER NOT NULL,
+    role TEXT NOT NULL CHECK(role IN ('creator', 'prev_owner', 'new_owner')),
+    pubkey_hex TEXT NOT NULL,
+    FOREIGN KEY (event_id) REFERENCES events_new(id) ON DELETE CASCADE
+);
+INSERT INTO event_actors_new (id, event_id, role, pubkey_hex)
+SELECT id, event_id, role, pubkey_hex FROM event_actors;
+
+CREATE TABLE event_signatures_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_id INTEGER NOT NULL,
+    role TEXT NOT NULL CHECK(role IN ('creator', 'prev_owner', 'new_owner')),
+    signature_hex TEXT NOT NULL,
+    FOREIGN KEY (event_id) REFERENCES events_new(id) ON DELETE CASCADE
+);
+INSERT INTO event_signatures_new (id, event_id, role, signature_hex)
+SELECT id, event_id, role, signature_hex FROM event_signatures;
+
+DROP TABLE event_actors;
+DROP TABLE event_signatures;
+DROP TABLE events;
+
+ALTER TABLE events_new RENAME TO events;
+ALTER TABLE event_actors_new RENAME TO event_actors;
+ALTER TABLE event_signatures_new RENAME TO event_signatures;
+
+CREATE INDEX idx_events_artifact ON events(artifact_id);
+
+CREATE INDEX idx_event_actors_event ON event_actors(event_id);
+
+CREATE INDEX idx_event_signatures_event ON event_signatures(event_id);
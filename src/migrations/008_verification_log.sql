@@ -0,0 +1,12 @@
+-- Who fetched an artifact's manifest or OTS proof, or verified a proof
+CREATE TABLE IF NOT EXISTS verification_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    file_path TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK(kind IN ('manifest', 'ots', 'verify')),
+    requester_ip TEXT,
+    requester_user TEXT,
+    success INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_verification_log_file_path ON verification_log(file_path);
@@ -0,0 +1,9 @@
+-- Merkle roots over every event hash, stamped so the whole chain is
+-- timestamped at once
+CREATE TABLE IF NOT EXISTS provenance_anchors (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    merkle_root_hex TEXT NOT NULL,
+    event_count INTEGER NOT NULL,
+    ots_proof_b64 TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
@@ -0,0 +1,10 @@
+-- Last hash of each minted file as found on disk, checked again once its
+-- modification time or size changes
+CREATE TABLE IF NOT EXISTS integrity_checks (
+    file_path TEXT PRIMARY KEY,
+    mtime INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    sha256_hex TEXT NOT NULL,
+    modified INTEGER NOT NULL,
+    checked_at INTEGER NOT NULL
+);
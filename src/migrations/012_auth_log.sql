@@ -0,0 +1,12 @@
+-- Password logins with Basic or Digest auth. Successes are logged once an
+-- hour per user and address, failures every time.
+CREATE TABLE IF NOT EXISTS auth_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user TEXT NOT NULL,
+    ip TEXT,
+    path TEXT NOT NULL,
+    outcome TEXT NOT NULL CHECK(outcome IN ('success', 'failure', 'locked')),
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_auth_log_user ON auth_log(user);
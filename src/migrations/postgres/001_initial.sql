@@ -0,0 +1,47 @@
+-- The provenance chain shared by instances using a Postgres `--provenance-db`.
+-- Paths compare with the "C" collation so subtree ranges follow byte order.
+
+CREATE TABLE IF NOT EXISTS artifacts (
+    id BIGSERIAL PRIMARY KEY,
+    file_path TEXT COLLATE "C" NOT NULL UNIQUE,
+    sha256_hex TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    verified_chain TEXT,
+    verified_timestamp BIGINT,
+    verified_height BIGINT,
+    last_check_at TEXT,
+    visibility TEXT NOT NULL DEFAULT 'private' CHECK(visibility IN ('private', 'public'))
+);
+
+CREATE TABLE IF NOT EXISTS events (
+    id BIGSERIAL PRIMARY KEY,
+    artifact_id BIGINT NOT NULL REFERENCES artifacts(id) ON DELETE CASCADE,
+    index_num INTEGER NOT NULL,
+    action TEXT NOT NULL CHECK(action IN ('mint', 'transfer')),
+    artifact_sha256_hex TEXT NOT NULL,
+    prev_event_hash_hex TEXT,
+    issued_at TEXT NOT NULL,
+    event_hash_hex TEXT NOT NULL UNIQUE,
+    ots_proof_b64 TEXT NOT NULL,
+    UNIQUE(artifact_id, index_num)
+);
+
+CREATE TABLE IF NOT EXISTS event_actors (
+    id BIGSERIAL PRIMARY KEY,
+    event_id BIGINT NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    role TEXT NOT NULL CHECK(role IN ('creator', 'prev_owner', 'new_owner')),
+    pubkey_hex TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS event_signatures (
+    id BIGSERIAL PRIMARY KEY,
+    event_id BIGINT NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    role TEXT NOT NULL CHECK(role IN ('creator', 'prev_owner', 'new_owner')),
+    signature_hex TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_artifacts_sha256 ON artifacts(sha256_hex);
+
+CREATE INDEX IF NOT EXISTS idx_event_actors_event ON event_actors(event_id);
+
+CREATE INDEX IF NOT EXISTS idx_event_signatures_event ON event_signatures(event_id);
@@ -0,0 +1,12 @@
+-- Detached signatures of third parties over an artifact's content
+
+CREATE TABLE IF NOT EXISTS attestations (
+    id BIGSERIAL PRIMARY KEY,
+    artifact_id BIGINT NOT NULL REFERENCES artifacts(id) ON DELETE CASCADE,
+    artifact_sha256_hex TEXT NOT NULL,
+    algorithm TEXT NOT NULL CHECK(algorithm IN ('secp256k1', 'ed25519')),
+    pubkey_hex TEXT NOT NULL,
+    signature_hex TEXT NOT NULL,
+    signed_at TEXT NOT NULL,
+    UNIQUE(artifact_id, artifact_sha256_hex, pubkey_hex)
+);
@@ -0,0 +1,8 @@
+-- Copies carry the events of their source, so event hashes are unique per
+-- artifact rather than overall, and `copy` joins the actions
+
+ALTER TABLE events DROP CONSTRAINT IF EXISTS events_action_check;
+ALTER TABLE events ADD CONSTRAINT events_action_check CHECK (action IN ('mint', 'transfer', 'copy'));
+
+ALTER TABLE events DROP CONSTRAINT IF EXISTS events_event_hash_hex_key;
+ALTER TABLE events ADD CONSTRAINT events_artifact_id_event_hash_hex_key UNIQUE (artifact_id, event_hash_hex);
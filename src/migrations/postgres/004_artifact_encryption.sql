@@ -0,0 +1,3 @@
+-- Client-side encryption declared by the uploader, as JSON
+
+ALTER TABLE artifacts ADD COLUMN IF NOT EXISTS encryption TEXT;
@@ -0,0 +1,275 @@
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+use crate::auth::AccessControl;
+use crate::utils::unix_now;
+
+/// How long a login may take at the provider
+pub const LOGIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Provider settings, from `--oidc-issuer`, `--oidc-client-id`,
+/// `--oidc-client-secret` and `--oidc-claim`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    /// Claim naming the user of the `--auth` rules, may be a list (e.g. `groups`)
+    pub claim: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// A login sent to the provider, waiting for its callback
+#[derive(Debug)]
+struct PendingLogin {
+    nonce: String,
+    code_verifier: String,
+    redirect_uri: String,
+    return_to: String,
+    started: Instant,
+}
+
+/// OpenID Connect login (authorization code flow with PKCE), turning the ID
+/// token claims into a user of the `--auth` rules kept in a signed cookie
+#[derive(Clone)]
+pub struct Oidc {
+    config: OidcConfig,
+    client: reqwest::Client,
+    discovery: Arc<OnceCell<Discovery>>,
+    pending: Arc<Mutex<HashMap<String, PendingLogin>>>,
+}
+
+impl Oidc {
+    pub fn new(config: OidcConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            config,
+            client,
+            discovery: Default::default(),
+            pending: Default::default(),
+        })
+    }
+
+    async fn discovery(&self) -> Result<&Discovery> {
+        self.discovery
+            .get_or_try_init(|| async {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.config.issuer.trim_end_matches('/')
+                );
+                let discovery: Discovery = self
+                    .client
+                    .get(&url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await
+                    .with_context(|| format!("Invalid OIDC discovery document at {url}"))?;
+                if discovery.issuer.trim_end_matches('/')
+                    != self.config.issuer.trim_end_matches('/')
+                {
+                    bail!("OIDC issuer mismatch, got `{}`", discovery.issuer);
+                }
+                Ok(discovery)
+            })
+            .await
+    }
+
+    /// URL of the provider's login page, coming back to `redirect_uri` and
+    /// then to the local path `return_to`, with the `state_binding` the
+    /// browser starting the login must hand back to complete it
+    pub async fn login_url(&self, redirect_uri: &str, return_to: &str) -> Result<(String, String)> {
+        let discovery = self.discovery().await?;
+        let state = random_token();
+        let nonce = random_token();
+        let code_verifier = random_token();
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let url = reqwest::Url::parse_with_params(
+            &discovery.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", redirect_uri),
+                ("scope", "openid profile email"),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+                ("code_challenge", code_challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )?;
+
+        let binding = state_binding(&state);
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, v| v.started.elapsed() < LOGIN_TIMEOUT);
+        pending.insert(
+            state,
+            PendingLogin {
+                nonce,
+                code_verifier,
+                redirect_uri: redirect_uri.to_string(),
+                return_to: return_to.to_string(),
+                started: Instant::now(),
+            },
+        );
+        Ok((url.to_string(), binding))
+    }
+
+    /// Finish the login of `state` with the provider's `code`, returning the
+    /// ID token claims and where to send the user back to. `binding` is what
+    /// the browser kept of `login_url`, so a callback URL made for someone
+    /// else's browser can't sign it in.
+    pub async fn complete_login(
+        &self,
+        code: &str,
+        state: &str,
+        binding: Option<&str>,
+    ) -> Result<(Value, String)> {
+        if binding != Some(state_binding(state).as_str()) {
+            bail!("Login started in another browser");
+        }
+        let login = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(state)
+            .filter(|v| v.started.elapsed() < LOGIN_TIMEOUT)
+            .ok_or_else(|| anyhow!("Unknown or expired login"))?;
+        let discovery = self.discovery().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", login.redirect_uri.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", login.code_verifier.as_str()),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        let token: TokenResponse = self
+            .client
+            .post(&discovery.token_endpoint)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Invalid OIDC token response")?;
+
+        // The ID token comes straight from the token endpoint, so its claims
+        // are checked without verifying its signature (OIDC Core 3.1.3.7)
+        let claims = decode_claims(&token.id_token)?;
+        if claims["iss"].as_str().map(|v| v.trim_end_matches('/'))
+            != Some(discovery.issuer.trim_end_matches('/'))
+        {
+            bail!("ID token from another issuer");
+        }
+        let audience_ok = match &claims["aud"] {
+            Value::String(aud) => aud == &self.config.client_id,
+            Value::Array(auds) => auds.iter().any(|v| v == &self.config.client_id),
+            _ => false,
+        };
+        if !audience_ok {
+            bail!("ID token for another client");
+        }
+        if claims["exp"].as_u64().unwrap_or_default() < unix_now().as_secs() {
+            bail!("ID token expired");
+        }
+        if claims["nonce"].as_str() != Some(login.nonce.as_str()) {
+            bail!("ID token nonce mismatch");
+        }
+        Ok((claims, login.return_to))
+    }
+
+    /// The `--auth` user named by the configured claim; for a list, the first
+    /// value that is a user
+    pub fn user_from_claims(&self, claims: &Value, auth: &AccessControl) -> Option<String> {
+        let names: Vec<&str> = match &claims[&self.config.claim] {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(|v| v.as_str()).collect(),
+            _ => vec![],
+        };
+        names
+            .into_iter()
+            .find(|name| auth.has_user(name))
+            .map(|v| v.to_string())
+    }
+}
+
+/// Claims of a JWT, without checking its signature
+fn decode_claims(jwt: &str) -> Result<Value> {
+    let payload = jwt
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| anyhow!("Invalid ID token"))?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .context("Invalid ID token")?;
+    serde_json::from_slice(&payload).context("Invalid ID token")
+}
+
+/// What the browser keeps of a login's `state`, a hash so the cookie
+/// holding it can't be replayed as the state itself
+pub fn state_binding(state: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(state.as_bytes()))
+}
+
+fn random_token() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn oidc(claim: &str) -> Oidc {
+        Oidc::new(OidcConfig {
+            issuer: "https://id.example.com".into(),
+            client_id: "drive".into(),
+            client_secret: None,
+            claim: claim.into(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_user_from_claims() {
+        let auth = AccessControl::new(&["admin:pass@/:rw", "editors:pass@/docs:rw"]).unwrap();
+        let claims = serde_json::json!({
+            "preferred_username": "bob",
+            "groups": ["staff", "editors"],
+        });
+        assert_eq!(
+            oidc("preferred_username").user_from_claims(&claims, &auth),
+            None
+        );
+        assert_eq!(
+            oidc("groups").user_from_claims(&claims, &auth).as_deref(),
+            Some("editors")
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::ots_stamper;
+use crate::provenance::{ProvenanceDb, QueuedDigest};
+
+/// Most digests stamped under one merkle root
+const MAX_BATCH_SIZE: usize = 4096;
+
+/// How often the digests queued in a database are retried
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Proof recorded for events whose digest waits in the stamping queue
+pub const QUEUED_PROOF: &[u8] = b"PLACEHOLDER_OTS_PROOF";
+
+type StampRequest = (Vec<u8>, oneshot::Sender<Result<Vec<u8>, String>>);
+
+/// Queue that stamps digests in batches, with one calendar submission per batch.
+///
+/// Digests queued while a batch is being submitted, or within the batch
+/// interval, share the next merkle root.
+#[derive(Debug, Clone)]
+pub struct OtsAggregator {
+    queue: mpsc::UnboundedSender<StampRequest>,
+    /// Leave every digest to the stamping queue, see `--ots-offline`
+    offline: bool,
+}
+
+impl OtsAggregator {
+    /// Start stamping, waiting `interval` after the first digest of a batch for more.
+    pub fn spawn(interval: Duration, offline: bool) -> Self {
+        let (queue, mut pending) = mpsc::unbounded_channel::<StampRequest>();
+        tokio::spawn(async move {
+            while let Some(first) = pending.recv().await {
+                if !interval.is_zero() {
+                    tokio::time::sleep(interval).await;
+                }
+                let mut batch = vec![first];
+                while batch.len() < MAX_BATCH_SIZE {
+                    match pending.try_recv() {
+                        Ok(request) => batch.push(request),
+                        Err(_) => break,
+                    }
+                }
+                let (digests, replies): (Vec<_>, Vec<_>) = batch.into_iter().unzip();
+                match ots_stamper::create_timestamps(&digests).await {
+                    Ok(proofs) => {
+                        if digests.len() > 1 {
+                            info!("Stamped {} digests under one merkle root", digests.len());
+                        }
+                        for (reply, proof) in replies.into_iter().zip(proofs) {
+                            let _ = reply.send(Ok(proof));
+                        }
+                    }
+                    Err(err) => {
+                        let err = err.to_string();
+                        for reply in replies {
+                            let _ = reply.send(Err(err.clone()));
+                        }
+                    }
+                }
+            }
+        });
+        Self { queue, offline }
+    }
+
+    /// Stamp `digest` with the next batch and return its serialized proof.
+    ///
+    /// None when offline or the calendars can't be reached, the event is then
+    /// recorded with `QUEUED_PROOF` and its digest queued in the database.
+    pub async fn try_stamp(&self, digest: Vec<u8>) -> Option<Vec<u8>> {
+        if self.offline {
+            return None;
+        }
+        self.stamp(digest)
+            .await
+            .inspect_err(|err| {
+                warn!("Failed to create OTS proof, queued for stamping later: {err}")
+            })
+            .ok()
+    }
+
+    /// Stamp a queued event in the background, it stays queued if that fails.
+    pub fn spawn_stamp(&self, db: ProvenanceDb, queued: QueuedDigest) {
+        let ots = self.clone();
+        tokio::spawn(async move {
+            let Ok(digest) = hex::decode(&queued.digest_hex) else {
+                return;
+            };
+            if let Some(proof) = ots.try_stamp(digest).await {
+                let stamped = [(queued, STANDARD.encode(proof))];
+                if let Err(err) = db.run(move |db| db.complete_queued_ots(&stamped)).await {
+                    warn!("Failed to record OTS proof, {err}");
+                }
+            }
+        });
+    }
+
+    async fn stamp(&self, digest: Vec<u8>) -> Result<Vec<u8>> {
+        let (reply, proof) = oneshot::channel();
+        self.queue
+            .send((digest, reply))
+            .map_err(|_| anyhow!("OTS stamping has stopped"))?;
+        proof
+            .await
+            .map_err(|_| anyhow!("OTS stamping has stopped"))?
+            .map_err(|err| anyhow!(err))
+    }
+}
+
+/// Stamp the digests queued in `db` every minute, once the calendars answer.
+pub fn spawn_queue_worker(db: ProvenanceDb) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(RETRY_INTERVAL);
+        loop {
+            ticker.tick().await;
+            match stamp_queued(&db).await {
+                Ok(0) => {}
+                Ok(count) => info!("Stamped {count} queued digests"),
+                Err(err) => warn!("Failed to stamp queued digests, {err}"),
+            }
+        }
+    });
+}
+
+async fn stamp_queued(db: &ProvenanceDb) -> Result<usize> {
+    let queued = db.run(|db| db.queued_ots_digests(MAX_BATCH_SIZE)).await?;
+    if queued.is_empty() {
+        return Ok(0);
+    }
+    let digests = queued
+        .iter()
+        .map(|v| hex::decode(&v.digest_hex))
+        .collect::<Result<Vec<_>, _>>()?;
+    let proofs = ots_stamper::create_timestamps(&digests).await?;
+    let stamped: Vec<_> = queued
+        .into_iter()
+        .zip(proofs)
+        .map(|(queued, proof)| (queued, STANDARD.encode(proof)))
+        .collect();
+    let count = stamped.len();
+    db.run(move |db| db.complete_queued_ots(&stamped)).await?;
+    Ok(count)
+}
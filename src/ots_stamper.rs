@@ -9,7 +9,6 @@ use opentimestamps::{
 };
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 use std::io::Cursor;
 
 const DEFAULT_CALENDAR_URLS: &[&str] = &[
@@ -42,24 +41,26 @@ pub struct VerificationResponse {
     pub upgraded_ots_b64: Option<String>,
 }
 
-/// Creates an OpenTimestamps proof by contacting calendar servers
-pub async fn create_timestamp(digest: &[u8]) -> Result<Vec<u8>> {
-    // Add random nonce (16 bytes) to the digest
-    // Generate nonce before any await points to avoid Send issues
-    let nonce: [u8; 16] = {
+/// Creates OpenTimestamps proofs for several digests with one calendar submission
+///
+/// Every digest gets its own nonce, the nonced hashes are the leaves of a merkle
+/// tree whose root goes to the calendars, and each proof holds the path from
+/// its digest up to that root.
+pub async fn create_timestamps(digests: &[Vec<u8>]) -> Result<Vec<Vec<u8>>> {
+    // Add random nonce (16 bytes) to each digest
+    // Generate nonces before any await points to avoid Send issues
+    let nonces: Vec<[u8; 16]> = {
         let mut rng = rand::thread_rng();
-        rng.gen()
+        digests.iter().map(|_| rng.gen()).collect()
     }; // rng is dropped here, before any await
 
-    // Create timestamp with nonce
-    let mut nonce_digest = Vec::new();
-    nonce_digest.extend_from_slice(digest);
-    nonce_digest.extend_from_slice(&nonce);
-
-    // Hash the nonce-appended digest
-    let mut hasher = Sha256::new();
-    hasher.update(&nonce_digest);
-    let merkle_root = hasher.finalize();
+    // Hash the nonce-appended digests
+    let leaves: Vec<Vec<u8>> = digests
+        .iter()
+        .zip(&nonces)
+        .map(|(digest, nonce)| Op::Sha256.execute(&Op::Append(nonce.to_vec()).execute(digest)))
+        .collect();
+    let (merkle_root, paths) = merkle_paths(&leaves);
 
     // Submit to calendar servers
     let mut timestamp_data = None;
@@ -104,38 +105,88 @@ pub async fn create_timestamp(digest: &[u8]) -> Result<Vec<u8>> {
         parsed.timestamp
     };
 
-    // Build the complete timestamp starting from the original digest
-    // Step 1: Append nonce operation
-    let append_step = Step {
-        data: StepData::Op(Op::Append(nonce.to_vec())),
-        output: nonce_digest.to_vec(),
-        next: vec![
-            // Step 2: SHA256 hash operation
-            Step {
-                data: StepData::Op(Op::Sha256),
-                output: merkle_root.to_vec(),
-                next: vec![calendar_timestamp.first_step],
-            },
-        ],
-    };
+    digests
+        .iter()
+        .zip(nonces)
+        .zip(paths)
+        .map(|((digest, nonce), path)| {
+            // Append the nonce and hash, then climb the merkle tree to the root
+            let mut ops = vec![Op::Append(nonce.to_vec()), Op::Sha256];
+            ops.extend(path);
+
+            // Create the detached timestamp file
+            let detached_timestamp = DetachedTimestampFile {
+                digest_type: DigestType::Sha256,
+                timestamp: Timestamp {
+                    start_digest: digest.clone(),
+                    first_step: chain_steps(digest, &ops, calendar_timestamp.first_step.clone()),
+                },
+            };
 
-    // Create the complete timestamp
-    let timestamp = Timestamp {
-        start_digest: digest.to_vec(),
-        first_step: append_step,
-    };
+            // Serialize to bytes
+            let mut result = Vec::new();
+            detached_timestamp.to_writer(&mut result)?;
+            Ok(result)
+        })
+        .collect()
+}
 
-    // Create the detached timestamp file
-    let detached_timestamp = DetachedTimestampFile {
-        digest_type: DigestType::Sha256,
-        timestamp,
-    };
+/// Merkle root of `leaves`, paired the same way as the digests of a batch
+pub fn merkle_root(leaves: &[Vec<u8>]) -> Vec<u8> {
+    merkle_paths(leaves).0
+}
 
-    // Serialize to bytes
-    let mut result = Vec::new();
-    detached_timestamp.to_writer(&mut result)?;
+/// Merkle root of `leaves`, and for each leaf the operations leading from it
+/// to the root
+///
+/// Adjacent nodes are paired as `sha256(left || right)`, an odd node out is
+/// carried up a level unchanged, like the `ots` client does.
+fn merkle_paths(leaves: &[Vec<u8>]) -> (Vec<u8>, Vec<Vec<Op>>) {
+    let mut paths = vec![Vec::new(); leaves.len()];
+    // Nodes of the current level, with the leaves below each of them
+    let mut level: Vec<(Vec<u8>, Vec<usize>)> = leaves
+        .iter()
+        .enumerate()
+        .map(|(i, leaf)| (leaf.clone(), vec![i]))
+        .collect();
+    while level.len() > 1 {
+        let mut next = Vec::with_capacity(level.len().div_ceil(2));
+        let mut nodes = level.into_iter();
+        while let Some((left, mut left_leaves)) = nodes.next() {
+            let Some((right, right_leaves)) = nodes.next() else {
+                next.push((left, left_leaves));
+                break;
+            };
+            for &i in &left_leaves {
+                paths[i].extend([Op::Append(right.clone()), Op::Sha256]);
+            }
+            for &i in &right_leaves {
+                paths[i].extend([Op::Prepend(left.clone()), Op::Sha256]);
+            }
+            let node = Op::Sha256.execute(&Op::Append(right).execute(&left));
+            left_leaves.extend(right_leaves);
+            next.push((node, left_leaves));
+        }
+        level = next;
+    }
+    let root = level.pop().map(|(node, _)| node).unwrap_or_default();
+    (root, paths)
+}
 
-    Ok(result)
+/// Nest `ops` applied to `input` into a chain of steps ending with `last`
+fn chain_steps(input: &[u8], ops: &[Op], last: Step) -> Step {
+    match ops.split_first() {
+        Some((op, rest)) => {
+            let output = op.execute(input);
+            let next = chain_steps(&output, rest, last);
+            Step {
+                data: StepData::Op(op.clone()),
+                output,
+                next: vec![next],
+            }
+        }
+        None => last,
+    }
 }
 
 /// Submit digest to a calendar server and return the timestamp
@@ -622,3 +673,21 @@ async fn verify_bitcoin_attestation(height: u64, step: &Step) -> Result<Verifica
         height: block.height,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merkle_paths() {
+        for count in 1..=7u8 {
+            let leaves: Vec<Vec<u8>> = (0..count).map(|i| Op::Sha256.execute(&[i])).collect();
+            let (root, paths) = merkle_paths(&leaves);
+            assert_eq!(paths.len(), leaves.len());
+            for (leaf, path) in leaves.iter().zip(&paths) {
+                let node = path.iter().fold(leaf.clone(), |node, op| op.execute(&node));
+                assert_eq!(node, root);
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::Utc;
+use std::time::Duration;
+
+use crate::ots_aggregator::QUEUED_PROOF;
+use crate::ots_stamper;
+use crate::provenance::ProvenanceDb;
+
+/// How often pending proofs are looked for
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Time left to the calendars between two checks of the same proof
+const RECHECK_AFTER: chrono::Duration = chrono::Duration::minutes(5);
+
+/// Most artifacts checked per round
+const MAX_BATCH_SIZE: usize = 256;
+
+/// Upgrade pending OTS proofs in the background until they are confirmed on
+/// a chain. This is the only place calendars and block explorers are asked
+/// about existing proofs, requests just read what it recorded.
+pub fn spawn_upgrade_worker(db: ProvenanceDb) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            match upgrade_pending(&db).await {
+                Ok(0) => {}
+                Ok(count) => info!("Confirmed {count} OTS proofs"),
+                Err(err) => warn!("Failed to upgrade OTS proofs, {err}"),
+            }
+        }
+    });
+}
+
+/// Check the pending proofs not checked lately, returning how many got confirmed
+async fn upgrade_pending(db: &ProvenanceDb) -> Result<usize> {
+    let checked_before = (Utc::now() - RECHECK_AFTER).to_rfc3339();
+    let pending = db
+        .run(move |db| db.unconfirmed_artifacts(&checked_before, MAX_BATCH_SIZE))
+        .await?;
+    let mut confirmed = 0;
+    for (artifact_id, path) in pending {
+        match upgrade(db, artifact_id, path.clone()).await {
+            Ok(true) => confirmed += 1,
+            Ok(false) => {}
+            Err(err) => warn!("Failed to upgrade the OTS proof of {path}, {err}"),
+        }
+    }
+    Ok(confirmed)
+}
+
+/// Verify the latest proof of an artifact, saving the upgraded proof and the
+/// attestation when the calendars have them
+async fn upgrade(db: &ProvenanceDb, artifact_id: i64, path: String) -> Result<bool> {
+    let Some(manifest) = db.run(move |db| db.get_manifest_by_path(&path)).await? else {
+        return Ok(false);
+    };
+    let Some(latest_event) = manifest.events.last() else {
+        return Ok(false);
+    };
+    // Queued digests haven't reached a calendar yet
+    if latest_event.ots_proof_b64 == STANDARD.encode(QUEUED_PROOF) {
+        return Ok(false);
+    }
+    let event_index = (manifest.events.len() - 1) as u32;
+    db.run(move |db| db.update_last_check_at(artifact_id))
+        .await?;
+
+    let Ok(verification) = ots_stamper::verify_timestamp(
+        &latest_event.ots_proof_b64,
+        &latest_event.artifact_sha256_hex,
+    )
+    .await
+    else {
+        // Still pending
+        return Ok(false);
+    };
+    let result = verification.results.into_iter().next();
+    let confirmed = result.is_some();
+    db.run(move |db| match (verification.upgraded_ots_b64, result) {
+        (Some(upgraded), Some(result)) => db.update_ots_proof_and_verification(
+            artifact_id,
+            event_index,
+            &upgraded,
+            &result.chain,
+            result.timestamp as i64,
+            result.height,
+        ),
+        (Some(upgraded), None) => db.update_ots_proof(artifact_id, event_index, &upgraded),
+        (None, Some(result)) => db.update_verification_result(
+            artifact_id,
+            &result.chain,
+            result.timestamp as i64,
+            result.height,
+        ),
+        (None, None) => Ok(()),
+    })
+    .await?;
+    Ok(confirmed)
+}
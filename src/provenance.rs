@@ -1,15 +1,81 @@
-use anyhow::Result;
-use rusqlite::{params, Connection};
+use anyhow::{anyhow, bail, Context, Result};
+use rusqlite::{backup::Backup, params, Connection, OpenFlags, OptionalExtension};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex, MutexGuard,
+};
+use std::time::Duration;
+
+use crate::provenance_migrations::migrate;
+use crate::provenance_store::ProvenanceStore;
+
+/// Apply the SQLCipher key and optional rekey, returning the key now in effect
+fn unlock(conn: &Connection, encryption: &DbEncryption) -> Result<Option<String>> {
+    if encryption.key.is_none() && encryption.rekey.is_none() {
+        return Ok(None);
+    }
+    if !cfg!(feature = "sqlcipher") {
+        bail!("Encrypted provenance database requires building with the `sqlcipher` feature");
+    }
+    let key = encryption
+        .key
+        .as_deref()
+        .ok_or_else(|| anyhow!("Rekeying the provenance database requires the current key"))?;
+    conn.pragma_update(None, "key", key)?;
+    // SQLCipher only checks the key once the first page is read
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .context("Failed to unlock provenance database, is the key correct?")?;
+    match &encryption.rekey {
+        Some(rekey) => {
+            conn.pragma_update(None, "rekey", rekey)?;
+            info!("Rekeyed provenance database");
+            Ok(Some(rekey.clone()))
+        }
+        None => Ok(Some(key.to_string())),
+    }
+}
+
+/// Open a read-only connection to the database at `path`
+fn open_reader(path: &Path, key: Option<&str>) -> Result<Connection> {
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX
+            | OpenFlags::SQLITE_OPEN_URI,
+    )?;
+    if let Some(key) = key {
+        conn.pragma_update(None, "key", key)?;
+    }
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    Ok(conn)
+}
 
-/// Static server keypair for signing (to be replaced with proper key management later)
-/// This is a demo keypair - in production, use a securely stored key
-pub const SERVER_PRIVATE_KEY_HEX: &str =
-    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
-pub const SERVER_PUBLIC_KEY_HEX: &str =
-    "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+/// Create an empty file at `path` that only this user may read
+#[cfg(unix)]
+fn create_private_file(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn create_private_file(path: &Path) -> std::io::Result<()> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    Ok(())
+}
 
 /// Provenance manifest following provenance.manifest/v1 spec
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,13 +84,17 @@ pub struct Manifest {
     pub manifest_type: String,
     pub artifact: Artifact,
     pub events: Vec<Event>,
+    /// Detached signatures of third parties over the artifact
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attestations: Vec<Attestation>,
+    /// The file on disk no longer has the recorded content
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub modified_since_mint: bool,
 }
 
 /// Artifact metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Artifact {
-    #[serde(skip)]
-    pub file_path: PathBuf,
     pub sha256_hex: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub verified_chain: Option<String>,
@@ -33,9 +103,27 @@ pub struct Artifact {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub verified_height: Option<u64>,
     #[serde(skip)]
-    pub last_check_at: Option<String>,
-    #[serde(skip)]
     pub visibility: String, // "private" or "public"
+    /// Set when the uploader encrypted the file before sending it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<Encryption>,
+}
+
+/// Client-side encryption of an artifact, as declared by its uploader. The
+/// server never sees the plaintext, only checks the file looks encrypted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Encryption {
+    pub scheme: EncryptionScheme,
+    /// Fingerprints of the keys able to decrypt the file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EncryptionScheme {
+    Age,
+    Gpg,
 }
 
 /// Provenance event following provenance.event/v1 spec
@@ -60,6 +148,8 @@ pub struct Event {
 pub enum EventAction {
     Mint,
     Transfer,
+    /// Server-side copy, continuing the lineage of the source on the copy
+    Copy,
 }
 
 /// Actors involved in an event
@@ -84,6 +174,61 @@ pub struct Signatures {
     pub new_owner_sig_hex: Option<String>,
 }
 
+/// Detached signature over an artifact's SHA-256 by the holder of `pubkey_hex`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attestation {
+    pub algorithm: SignatureAlgorithm,
+    pub pubkey_hex: String,
+    pub signature_hex: String,
+    pub artifact_sha256_hex: String,
+    pub signed_at: String,
+}
+
+impl Attestation {
+    /// Whether the signature is valid over `artifact_sha256_hex`
+    pub fn verify(&self) -> Result<bool> {
+        match self.algorithm {
+            SignatureAlgorithm::Secp256k1 => verify_event_signature(
+                &self.artifact_sha256_hex,
+                &self.signature_hex,
+                &self.pubkey_hex,
+            ),
+            SignatureAlgorithm::Ed25519 => verify_ed25519_signature(
+                &self.artifact_sha256_hex,
+                &self.signature_hex,
+                &self.pubkey_hex,
+            ),
+        }
+    }
+}
+
+/// Signature algorithm of an attestation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureAlgorithm {
+    /// DER-encoded ECDSA over a compressed public key, like event signatures
+    #[default]
+    Secp256k1,
+    Ed25519,
+}
+
+impl SignatureAlgorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Secp256k1 => "secp256k1",
+            Self::Ed25519 => "ed25519",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "secp256k1" => Some(Self::Secp256k1),
+            "ed25519" => Some(Self::Ed25519),
+            _ => None,
+        }
+    }
+}
+
 /// Arguments for inserting a provenance event
 pub struct InsertEventArgs<'a> {
     pub artifact_id: i64,
@@ -98,161 +243,1745 @@ pub struct InsertEventArgs<'a> {
     pub signatures: &'a Signatures,
 }
 
+/// At-rest encryption keys for the database file (SQLCipher)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DbEncryption {
+    pub key: Option<String>,
+    /// Replaces `key` once the database has been unlocked
+    pub rekey: Option<String>,
+}
+
+/// A secp256k1 keypair that signs mint events and shares
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerKeypair {
+    pub private_key_hex: String,
+    pub public_key_hex: String,
+}
+
+impl ServerKeypair {
+    pub fn generate() -> Self {
+        let (secret_key, public_key) = secp256k1::generate_keypair(&mut rand::thread_rng());
+        Self {
+            private_key_hex: hex::encode(secret_key.secret_bytes()),
+            public_key_hex: hex::encode(public_key.serialize()),
+        }
+    }
+
+    /// Derive the keypair from a hex-encoded secp256k1 private key
+    pub fn from_private_key_hex(private_key_hex: &str) -> Result<Self> {
+        let bytes = hex::decode(private_key_hex.trim())
+            .map_err(|_| anyhow!("Signing key must be a hex-encoded secp256k1 private key"))?;
+        let secret_key = secp256k1::SecretKey::from_slice(&bytes)
+            .map_err(|e| anyhow!("Invalid signing key: {e}"))?;
+        let public_key = secret_key.public_key(secp256k1::SECP256K1);
+        Ok(Self {
+            private_key_hex: hex::encode(secret_key.secret_bytes()),
+            public_key_hex: hex::encode(public_key.serialize()),
+        })
+    }
+}
+
+/// A public key that has signed for this database, the active one has no `retired_at`
+#[derive(Debug, Clone, Serialize)]
+pub struct SigningKeyRecord {
+    pub public_key_hex: String,
+    pub activated_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retired_at: Option<String>,
+}
+
+/// Read-only connections kept open next to the writer
+const READ_POOL_SIZE: usize = 4;
+
+/// How long a connection waits for a lock held by another one before failing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Thread-safe database connection wrapper
+///
+/// Writes go through one connection, reads through a small pool of read-only
+/// ones, which WAL mode lets run alongside a write.
 #[derive(Clone)]
 pub struct ProvenanceDb {
     conn: Arc<Mutex<Connection>>,
+    readers: Arc<ReadPool>,
+    chain: Arc<dyn ProvenanceStore>,
     db_path: Arc<PathBuf>,
+    key: Option<Arc<str>>,
+    /// Whether the chain lives in another database, see `with_chain`
+    external_chain: bool,
+}
+
+#[derive(Default)]
+struct ReadPool {
+    conns: Vec<Mutex<Connection>>,
+    next: AtomicUsize,
+}
+
+impl ReadPool {
+    /// A read-only connection, preferring one nobody is using
+    ///
+    /// Falls back to `writer` when there is no pool.
+    fn get<'a>(&'a self, writer: &'a Mutex<Connection>) -> MutexGuard<'a, Connection> {
+        if self.conns.is_empty() {
+            return writer.lock().unwrap();
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        for i in 0..self.conns.len() {
+            if let Ok(conn) = self.conns[(start + i) % self.conns.len()].try_lock() {
+                return conn;
+            }
+        }
+        self.conns[start % self.conns.len()].lock().unwrap()
+    }
+}
+
+/// The provenance chain kept in the SQLite database itself
+struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+    readers: Arc<ReadPool>,
+}
+
+impl SqliteStore {
+    fn reader(&self) -> MutexGuard<'_, Connection> {
+        self.readers.get(&self.conn)
+    }
 }
 
 impl ProvenanceDb {
-    /// Initialize database with schema
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+    /// Open the database, unlocking (and optionally rekeying) it first and
+    /// applying pending schema migrations
+    pub fn new<P: AsRef<Path>>(path: P, encryption: &DbEncryption) -> Result<Self> {
         let db_path = path.as_ref().to_path_buf();
-        let conn = Connection::open(&db_path)?;
+        let mut conn = Connection::open(&db_path)?;
+        let key = unlock(&conn, encryption)?;
 
         // Enable foreign key constraints
         conn.execute("PRAGMA foreign_keys = ON", [])?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // In-memory databases stay in `memory` mode and can't be shared by a pool
+        let journal_mode: String =
+            conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get(0))?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+
+        migrate(&mut conn)?;
+
+        let mut readers = ReadPool::default();
+        if journal_mode.eq_ignore_ascii_case("wal") {
+            for _ in 0..READ_POOL_SIZE {
+                readers
+                    .conns
+                    .push(Mutex::new(open_reader(&db_path, key.as_deref())?));
+            }
+        }
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS artifacts (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                file_path TEXT NOT NULL UNIQUE,
-                sha256_hex TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                verified_chain TEXT,
-                verified_timestamp INTEGER,
-                verified_height INTEGER,
-                last_check_at TEXT,
-                visibility TEXT NOT NULL DEFAULT 'private' CHECK(visibility IN ('private', 'public'))
-            )",
-            [],
-        )?;
+        let conn = Arc::new(Mutex::new(conn));
+        let readers = Arc::new(readers);
+        Ok(Self {
+            chain: Arc::new(SqliteStore {
+                conn: conn.clone(),
+                readers: readers.clone(),
+            }),
+            conn,
+            readers,
+            db_path: Arc::new(db_path),
+            key: key.map(Arc::from),
+            external_chain: false,
+        })
+    }
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS events (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                artifact_id INTEGER NOT NULL,
-                index_num INTEGER NOT NULL,
-                action TEXT NOT NULL CHECK(action IN ('mint', 'transfer')),
-                artifact_sha256_hex TEXT NOT NULL,
-                prev_event_hash_hex TEXT,
-                issued_at TEXT NOT NULL,
-                event_hash_hex TEXT NOT NULL UNIQUE,
-                ots_proof_b64 TEXT NOT NULL,
-                FOREIGN KEY (artifact_id) REFERENCES artifacts(id) ON DELETE CASCADE,
-                UNIQUE(artifact_id, index_num)
-            )",
-            [],
-        )?;
+    /// Keep the provenance chain in `chain` instead of this database
+    pub fn with_chain(mut self, chain: Arc<dyn ProvenanceStore>) -> Self {
+        self.chain = chain;
+        self.external_chain = true;
+        self
+    }
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS event_actors (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                event_id INTEGER NOT NULL,
-                role TEXT NOT NULL CHECK(role IN ('creator', 'prev_owner', 'new_owner')),
-                pubkey_hex TEXT NOT NULL,
-                FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE
-            )",
-            [],
-        )?;
+    /// Run blocking database work `f` off the async executor
+    pub async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&ProvenanceDb) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || f(&db)).await?
+    }
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS event_signatures (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                event_id INTEGER NOT NULL,
-                role TEXT NOT NULL CHECK(role IN ('creator', 'prev_owner', 'new_owner')),
-                signature_hex TEXT NOT NULL,
-                FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE
-            )",
-            [],
-        )?;
+    fn reader(&self) -> MutexGuard<'_, Connection> {
+        self.readers.get(&self.conn)
+    }
 
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_artifacts_sha256 ON artifacts(sha256_hex)",
-            [],
-        )?;
+    /// Get the database file path
+    pub fn get_db_path(&self) -> &Path {
+        &self.db_path
+    }
 
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_artifacts_file_path ON artifacts(file_path)",
-            [],
-        )?;
+    /// Write a consistent copy of the database to `dest` using the SQLite online backup API
+    ///
+    /// Snapshots of an encrypted database are encrypted with the same key.
+    pub fn backup_to(&self, dest: &Path) -> Result<()> {
+        let conn = self.reader();
+        let mut dest = Connection::open(dest)?;
+        if let Some(key) = &self.key {
+            dest.pragma_update(None, "key", key.as_ref())?;
+        }
+        let backup = Backup::new(&conn, &mut dest)?;
+        backup.run_to_completion(100, Duration::ZERO, None)?;
+        Ok(())
+    }
 
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_events_artifact ON events(artifact_id)",
-            [],
-        )?;
+    /// An open copy of the database from `backup_to`, for downloads. The copy
+    /// is written next to the database file, readable by this user only, and
+    /// unlinked before it's returned or when the backup fails, so nothing is
+    /// left behind whatever happens to the request that asked for it.
+    pub fn snapshot(&self) -> Result<std::fs::File> {
+        let dir = self
+            .db_path
+            .parent()
+            .filter(|v| !v.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let name = self
+            .db_path
+            .file_name()
+            .and_then(|v| v.to_str())
+            .unwrap_or("provenance.db");
+        let path = dir.join(format!(".{name}.{}.snapshot", uuid::Uuid::new_v4()));
+        create_private_file(&path)
+            .with_context(|| format!("Failed to create snapshot `{}`", path.display()))?;
+        let file = self
+            .backup_to(&path)
+            .and_then(|_| Ok(std::fs::File::open(&path)?));
+        // The open handle keeps the snapshot readable once it is unlinked
+        let _ = std::fs::remove_file(&path);
+        file
+    }
 
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_event_actors_event ON event_actors(event_id)",
-            [],
-        )?;
+    /// Move everything written so far into the main database file
+    ///
+    /// Only does work in WAL mode, where it empties the write-ahead log.
+    pub fn checkpoint(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        Ok(())
+    }
 
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_event_signatures_event ON event_signatures(event_id)",
-            [],
+    // The provenance chain, see `ProvenanceStore`
+
+    pub fn upsert_artifact(&self, file_path: &str, sha256_hex: &str) -> Result<i64> {
+        self.chain.upsert_artifact(file_path, sha256_hex)
+    }
+
+    pub fn get_artifact_by_path(&self, file_path: &str) -> Result<Option<(i64, Artifact)>> {
+        self.chain.get_artifact_by_path(file_path)
+    }
+
+    pub fn set_artifact_encryption(
+        &self,
+        file_path: &str,
+        encryption: Option<&Encryption>,
+    ) -> Result<()> {
+        self.chain.set_artifact_encryption(file_path, encryption)
+    }
+
+    pub fn insert_event(&self, args: InsertEventArgs) -> Result<i64> {
+        self.chain.insert_event(args)
+    }
+
+    pub fn import_events(
+        &self,
+        file_path: &str,
+        sha256_hex: &str,
+        events: &[Event],
+    ) -> Result<bool> {
+        self.chain.import_events(file_path, sha256_hex, events)
+    }
+
+    fn get_events(&self, artifact_id: i64) -> Result<Vec<Event>> {
+        self.chain.get_events(artifact_id)
+    }
+
+    pub fn has_event(&self, event_hash_hex: &str) -> Result<bool> {
+        self.chain.has_event(event_hash_hex)
+    }
+
+    pub fn event_hashes(&self) -> Result<Vec<String>> {
+        self.chain.event_hashes()
+    }
+
+    pub fn get_next_event_index(&self, artifact_id: i64) -> Result<u32> {
+        self.chain.get_next_event_index(artifact_id)
+    }
+
+    pub fn update_ots_proof(
+        &self,
+        artifact_id: i64,
+        event_index: u32,
+        ots_proof_b64: &str,
+    ) -> Result<()> {
+        self.chain
+            .update_ots_proof(artifact_id, event_index, ots_proof_b64)
+    }
+
+    pub fn update_verification_result(
+        &self,
+        artifact_id: i64,
+        chain: &str,
+        timestamp: i64,
+        height: u64,
+    ) -> Result<()> {
+        self.chain
+            .update_verification_result(artifact_id, chain, timestamp, height)
+    }
+
+    pub fn update_last_check_at(&self, artifact_id: i64) -> Result<()> {
+        self.chain.update_last_check_at(artifact_id)
+    }
+
+    pub fn unconfirmed_artifacts(
+        &self,
+        checked_before: &str,
+        limit: usize,
+    ) -> Result<Vec<(i64, String)>> {
+        self.chain.unconfirmed_artifacts(checked_before, limit)
+    }
+
+    pub fn update_ots_proof_and_verification(
+        &self,
+        artifact_id: i64,
+        event_index: u32,
+        ots_proof_b64: &str,
+        chain: &str,
+        timestamp: i64,
+        height: u64,
+    ) -> Result<()> {
+        self.chain.update_ots_proof_and_verification(
+            artifact_id,
+            event_index,
+            ots_proof_b64,
+            chain,
+            timestamp,
+            height,
+        )
+    }
+
+    pub fn add_creator_signature(
+        &self,
+        artifact_id: i64,
+        event_index: u32,
+        signature_hex: &str,
+    ) -> Result<()> {
+        self.chain
+            .add_creator_signature(artifact_id, event_index, signature_hex)
+    }
+
+    pub fn add_attestation(&self, artifact_id: i64, attestation: &Attestation) -> Result<()> {
+        self.chain.add_attestation(artifact_id, attestation)
+    }
+
+    /// Update artifact file path (for file and directory moves/renames)
+    /// This is called when a file is moved to update the database.
+    /// Annotations keyed by path move along with the artifact.
+    pub fn update_artifact_path(&self, old_path: &str, new_path: &str) -> Result<bool> {
+        let moved = self.chain.move_artifacts(old_path, new_path)?;
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let move_subtree = |update: &str| move_subtree(&tx, update, old_path, new_path);
+        move_subtree("UPDATE OR REPLACE file_metadata")?;
+        move_subtree("UPDATE file_comments")?;
+        move_subtree("UPDATE OR REPLACE file_tags")?;
+        move_subtree("UPDATE OR REPLACE favorites")?;
+        move_subtree("UPDATE OR REPLACE acl_entries")?;
+        move_subtree("UPDATE OR REPLACE path_visibility")?;
+        move_subtree("UPDATE OR REPLACE file_owners")?;
+        move_subtree("UPDATE OR REPLACE dead_properties")?;
+        move_subtree("UPDATE verification_log")?;
+        move_subtree("UPDATE OR REPLACE integrity_checks")?;
+        tx.commit()?;
+
+        Ok(moved)
+    }
+
+    /// Generate complete manifest for an artifact by file path
+    pub fn get_manifest_by_path(&self, file_path: &str) -> Result<Option<Manifest>> {
+        let (artifact_id, artifact) = match self.get_artifact_by_path(file_path)? {
+            Some(result) => result,
+            None => return Ok(None),
+        };
+
+        let events = self.get_events(artifact_id)?;
+        let attestations = self
+            .chain
+            .get_attestations(artifact_id, &artifact.sha256_hex)?;
+
+        let modified_since_mint = self.is_modified_since_mint(file_path)?;
+
+        Ok(Some(Manifest {
+            manifest_type: "provenance.manifest/v1".to_string(),
+            artifact,
+            events,
+            attestations,
+            modified_since_mint,
+        }))
+    }
+
+    /// Create a new share for a file
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_share(
+        &self,
+        share_id: &str,
+        file_path: &str,
+        file_sha256_hex: &str,
+        created_at: &str,
+        shared_by: Option<&str>,
+        owner_pubkey_hex: &str,
+        share_signature_hex: &str,
+        limits: ShareLimits,
+        password_hash: Option<&str>,
+    ) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+
+        // Get artifact_id if the file exists in provenance system
+        let artifact_id: Option<i64> = conn
+            .query_row(
+                "SELECT id FROM artifacts WHERE file_path = ?1",
+                params![file_path],
+                |row| row.get(0),
+            )
+            .ok();
+
+        let share_db_id: i64 = conn.query_row(
+            "INSERT INTO shares (share_id, file_path, file_sha256_hex, artifact_id, created_at, shared_by, owner_pubkey_hex, share_signature_hex, expires_at, max_downloads, password_hash)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+             RETURNING id",
+            params![
+                share_id,
+                file_path,
+                file_sha256_hex,
+                artifact_id,
+                created_at,
+                shared_by,
+                owner_pubkey_hex,
+                share_signature_hex,
+                limits.expires_at,
+                limits.max_downloads,
+                password_hash
+            ],
+            |row| row.get(0),
         )?;
 
-        // Create shares table for file sharing functionality
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS shares (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                share_id TEXT NOT NULL UNIQUE,
-                file_path TEXT NOT NULL,
-                file_sha256_hex TEXT NOT NULL,
-                artifact_id INTEGER,
-                created_at TEXT NOT NULL,
-                shared_by TEXT,
-                owner_pubkey_hex TEXT NOT NULL,
-                share_signature_hex TEXT NOT NULL,
-                is_active INTEGER NOT NULL DEFAULT 1,
-                FOREIGN KEY (artifact_id) REFERENCES artifacts(id) ON DELETE CASCADE
-            )",
-            [],
+        Ok(share_db_id)
+    }
+
+    /// Get share information by share_id
+    pub fn get_share(&self, share_id: &str) -> Result<Option<ShareInfo>> {
+        let conn = self.reader();
+
+        let mut stmt = conn.prepare(
+            "SELECT file_path, file_sha256_hex, created_at, shared_by, owner_pubkey_hex, share_signature_hex, is_active, expires_at, max_downloads, password_hash
+             FROM shares WHERE share_id = ?1",
         )?;
 
+        let mut rows = stmt.query(params![share_id])?;
+
+        if let Some(row) = rows.next()? {
+            let file_path: String = row.get(0)?;
+            let file_sha256_hex: String = row.get(1)?;
+            let created_at: String = row.get(2)?;
+            let shared_by: Option<String> = row.get(3)?;
+            let owner_pubkey_hex: String = row.get(4)?;
+            let share_signature_hex: String = row.get(5)?;
+            let is_active: i32 = row.get(6)?;
+
+            Ok(Some(ShareInfo {
+                share_id: share_id.to_string(),
+                file_path,
+                file_sha256_hex,
+                created_at,
+                shared_by,
+                owner_pubkey_hex,
+                share_signature_hex,
+                is_active: is_active != 0,
+                limits: ShareLimits {
+                    expires_at: row.get(7)?,
+                    max_downloads: row.get(8)?,
+                },
+                password_hash: row.get(9)?,
+                stamp_status: None, // Will be populated by handler if needed
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Get all shares for a specific file path
+    pub fn get_shares_for_file(&self, file_path: &str) -> Result<Vec<ShareInfo>> {
+        let conn = self.reader();
+
+        let mut stmt = conn.prepare(
+            "SELECT share_id, file_path, file_sha256_hex, created_at, shared_by, owner_pubkey_hex, share_signature_hex, is_active, expires_at, max_downloads, password_hash
+             FROM shares WHERE file_path = ?1 AND is_active = 1
+             ORDER BY created_at DESC",
+        )?;
+
+        let mut rows = stmt.query(params![file_path])?;
+        let mut shares = Vec::new();
+
+        while let Some(row) = rows.next()? {
+            let share_id: String = row.get(0)?;
+            let file_path: String = row.get(1)?;
+            let file_sha256_hex: String = row.get(2)?;
+            let created_at: String = row.get(3)?;
+            let shared_by: Option<String> = row.get(4)?;
+            let owner_pubkey_hex: String = row.get(5)?;
+            let share_signature_hex: String = row.get(6)?;
+            let is_active: i32 = row.get(7)?;
+
+            shares.push(ShareInfo {
+                share_id,
+                file_path,
+                file_sha256_hex,
+                created_at,
+                shared_by,
+                owner_pubkey_hex,
+                share_signature_hex,
+                is_active: is_active != 0,
+                limits: ShareLimits {
+                    expires_at: row.get(8)?,
+                    max_downloads: row.get(9)?,
+                },
+                password_hash: row.get(10)?,
+                stamp_status: None, // Will be populated by handler if needed
+            });
+        }
+
+        Ok(shares)
+    }
+
+    /// Active shares of every file, newest first, with their download count
+    pub fn list_active_shares(&self) -> Result<Vec<(ShareInfo, u64)>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT s.share_id, s.file_path, s.file_sha256_hex, s.created_at, s.shared_by, s.owner_pubkey_hex, s.share_signature_hex,
+                    s.expires_at, s.max_downloads, s.password_hash,
+                    (SELECT COUNT(*) FROM share_downloads d WHERE d.share_id = s.share_id)
+             FROM shares s WHERE s.is_active = 1
+             ORDER BY s.created_at DESC",
+        )?;
+        let shares = stmt
+            .query_map([], |row| {
+                let share = ShareInfo {
+                    share_id: row.get(0)?,
+                    file_path: row.get(1)?,
+                    file_sha256_hex: row.get(2)?,
+                    created_at: row.get(3)?,
+                    shared_by: row.get(4)?,
+                    owner_pubkey_hex: row.get(5)?,
+                    share_signature_hex: row.get(6)?,
+                    is_active: true,
+                    limits: ShareLimits {
+                        expires_at: row.get(7)?,
+                        max_downloads: row.get(8)?,
+                    },
+                    password_hash: row.get(9)?,
+                    stamp_status: None,
+                };
+                Ok((share, row.get::<_, u64>(10)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(shares)
+    }
+
+    /// Record a share download (for tracking distribution chain)
+    ///
+    /// None when the share has no downloads left, checked along with the
+    /// insert so concurrent downloads can't go over `max_downloads`.
+    pub fn record_share_download(
+        &self,
+        share_id: &str,
+        downloaded_by: Option<&str>,
+        redistributor_pubkey_hex: Option<&str>,
+        redistributor_signature_hex: Option<&str>,
+    ) -> Result<Option<i64>> {
+        let conn = self.conn.lock().unwrap();
+        let now = chrono::Utc::now().to_rfc3339();
+
+        let download_id: Option<i64> = conn.query_row(
+            "INSERT INTO share_downloads (share_id, downloaded_at, downloaded_by, redistributor_pubkey_hex, redistributor_signature_hex)
+             SELECT ?1, ?2, ?3, ?4, ?5 FROM shares s
+             WHERE s.share_id = ?1 AND (s.max_downloads IS NULL
+                OR s.max_downloads > (SELECT COUNT(*) FROM share_downloads d WHERE d.share_id = ?1))
+             RETURNING id",
+            params![
+                share_id,
+                now,
+                downloaded_by,
+                redistributor_pubkey_hex,
+                redistributor_signature_hex
+            ],
+            |row| row.get(0),
+        ).optional()?;
+
+        Ok(download_id)
+    }
+
+    /// How many times a share was downloaded
+    pub fn count_share_downloads(&self, share_id: &str) -> Result<u64> {
+        let count = self.reader().query_row(
+            "SELECT COUNT(*) FROM share_downloads WHERE share_id = ?1",
+            params![share_id],
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
+    /// Deactivate a share (soft delete)
+    pub fn deactivate_share(&self, share_id: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+
+        let rows_affected = conn.execute(
+            "UPDATE shares SET is_active = 0 WHERE share_id = ?1",
+            params![share_id],
+        )?;
+
+        Ok(rows_affected > 0)
+    }
+
+    /// Get distribution chain for a share
+    pub fn get_distribution_chain(&self, share_id: &str) -> Result<Vec<DownloadRecord>> {
+        let conn = self.reader();
+
+        let mut stmt = conn.prepare(
+            "SELECT downloaded_at, downloaded_by, redistributor_pubkey_hex, redistributor_signature_hex
+             FROM share_downloads WHERE share_id = ?1
+             ORDER BY downloaded_at ASC",
+        )?;
+
+        let mut rows = stmt.query(params![share_id])?;
+        let mut downloads = Vec::new();
+
+        while let Some(row) = rows.next()? {
+            downloads.push(DownloadRecord {
+                downloaded_at: row.get(0)?,
+                downloaded_by: row.get(1)?,
+                redistributor_pubkey_hex: row.get(2)?,
+                redistributor_signature_hex: row.get(3)?,
+            });
+        }
+
+        Ok(downloads)
+    }
+
+    /// Update file visibility based on active shares
+    /// This is called automatically when shares are created or deleted
+    pub fn update_file_visibility(&self, file_path: &str) -> Result<()> {
+        // Count active shares for this file
+        let active_shares: i64 = self.reader().query_row(
+            "SELECT COUNT(*) FROM shares WHERE file_path = ?1 AND is_active = 1",
+            params![file_path],
+            |row| row.get(0),
+        )?;
+
+        // Determine visibility based on share count
+        let visibility = if active_shares > 0 {
+            "public"
+        } else {
+            "private"
+        };
+
+        self.chain.set_artifact_visibility(file_path, visibility)
+    }
+
+    /// Get all metadata entries of a file
+    pub fn get_file_metadata(&self, file_path: &str) -> Result<BTreeMap<String, String>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare("SELECT key, value FROM file_metadata WHERE file_path = ?1")?;
+        let entries = stmt
+            .query_map(params![file_path], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<BTreeMap<String, String>, _>>()?;
+        Ok(entries)
+    }
+
+    /// Apply a metadata patch to a file: `Some` sets a key, `None` removes it
+    pub fn update_file_metadata(
+        &self,
+        file_path: &str,
+        patch: &BTreeMap<String, Option<String>>,
+        updated_by: Option<&str>,
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let now = chrono::Utc::now().to_rfc3339();
+        let tx = conn.transaction()?;
+        for (key, value) in patch {
+            match value {
+                Some(value) => tx.execute(
+                    "INSERT INTO file_metadata (file_path, key, value, updated_at, updated_by)
+                     VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT(file_path, key) DO UPDATE SET
+                        value = excluded.value,
+                        updated_at = excluded.updated_at,
+                        updated_by = excluded.updated_by",
+                    params![file_path, key, value, now, updated_by],
+                )?,
+                None => tx.execute(
+                    "DELETE FROM file_metadata WHERE file_path = ?1 AND key = ?2",
+                    params![file_path, key],
+                )?,
+            };
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get the comments of a file, oldest first
+    pub fn get_file_comments(&self, file_path: &str) -> Result<Vec<FileComment>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT id, author, body, created_at FROM file_comments
+             WHERE file_path = ?1 ORDER BY id",
+        )?;
+        let comments = stmt
+            .query_map(params![file_path], |row| {
+                Ok(FileComment {
+                    id: row.get(0)?,
+                    author: row.get(1)?,
+                    body: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(comments)
+    }
+
+    /// Count the comments of a file
+    pub fn count_file_comments(&self, file_path: &str) -> Result<u64> {
+        let conn = self.reader();
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM file_comments WHERE file_path = ?1",
+            params![file_path],
+            |row| row.get(0),
+        )?;
+        Ok(count as u64)
+    }
+
+    /// Add a comment to a file
+    pub fn add_file_comment(
+        &self,
+        file_path: &str,
+        author: Option<&str>,
+        body: &str,
+    ) -> Result<FileComment> {
+        let conn = self.conn.lock().unwrap();
+        let now = chrono::Utc::now().to_rfc3339();
         conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_shares_share_id ON shares(share_id)",
+            "INSERT INTO file_comments (file_path, author, body, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![file_path, author, body, now],
+        )?;
+        Ok(FileComment {
+            id: conn.last_insert_rowid(),
+            author: author.map(|v| v.to_string()),
+            body: body.to_string(),
+            created_at: now,
+        })
+    }
+
+    /// Delete a comment of a file, returns false if it doesn't exist
+    pub fn delete_file_comment(&self, file_path: &str, id: i64) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let rows_affected = conn.execute(
+            "DELETE FROM file_comments WHERE file_path = ?1 AND id = ?2",
+            params![file_path, id],
+        )?;
+        Ok(rows_affected > 0)
+    }
+
+    /// Find files whose metadata keys/values or comments contain `needle` (case-insensitive)
+    pub fn search_annotations(&self, needle: &str) -> Result<HashSet<String>> {
+        let conn = self.reader();
+        let pattern = format!(
+            "%{}%",
+            needle
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        let mut stmt = conn.prepare(
+            "SELECT file_path FROM file_metadata
+             WHERE key LIKE ?1 ESCAPE '\\' OR value LIKE ?1 ESCAPE '\\'
+             UNION
+             SELECT file_path FROM file_comments WHERE body LIKE ?1 ESCAPE '\\'",
+        )?;
+        let paths = stmt
+            .query_map(params![pattern], |row| row.get(0))?
+            .collect::<Result<HashSet<String>, _>>()?;
+        Ok(paths)
+    }
+
+    /// Get the tags of a file, sorted
+    pub fn get_file_tags(&self, file_path: &str) -> Result<Vec<String>> {
+        let conn = self.reader();
+        let mut stmt =
+            conn.prepare("SELECT tag FROM file_tags WHERE file_path = ?1 ORDER BY tag")?;
+        let tags = stmt
+            .query_map(params![file_path], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(tags)
+    }
+
+    /// Add and remove tags of a file in one transaction
+    pub fn update_file_tags(
+        &self,
+        file_path: &str,
+        add: &[String],
+        remove: &[String],
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let now = chrono::Utc::now().to_rfc3339();
+        let tx = conn.transaction()?;
+        for tag in remove {
+            tx.execute(
+                "DELETE FROM file_tags WHERE file_path = ?1 AND tag = ?2",
+                params![file_path, tag],
+            )?;
+        }
+        for tag in add {
+            tx.execute(
+                "INSERT OR IGNORE INTO file_tags (file_path, tag, created_at) VALUES (?1, ?2, ?3)",
+                params![file_path, tag, now],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Count how many files carry each tag, limited to paths starting with `path_prefix`
+    pub fn list_tags(&self, path_prefix: &str) -> Result<Vec<(String, u64)>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT tag, COUNT(*) FROM file_tags
+             WHERE substr(file_path, 1, length(?1)) = ?1
+             GROUP BY tag ORDER BY tag",
+        )?;
+        let tags = stmt
+            .query_map(params![path_prefix], |row| {
+                Ok((row.get(0)?, row.get::<_, i64>(1)? as u64))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tags)
+    }
+
+    /// Star a path for a user
+    pub fn add_favorite(&self, user: &str, file_path: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR IGNORE INTO favorites (user, file_path, created_at) VALUES (?1, ?2, ?3)",
+            params![user, file_path, chrono::Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    /// Unstar a path for a user, returns false if it wasn't starred
+    pub fn remove_favorite(&self, user: &str, file_path: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let rows_affected = conn.execute(
+            "DELETE FROM favorites WHERE user = ?1 AND file_path = ?2",
+            params![user, file_path],
+        )?;
+        Ok(rows_affected > 0)
+    }
+
+    /// Get the paths a user starred below `path_prefix`, most recent first
+    pub fn list_favorites(&self, user: &str, path_prefix: &str) -> Result<Vec<String>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT file_path FROM favorites
+             WHERE user = ?1 AND substr(file_path, 1, length(?2)) = ?2
+             ORDER BY created_at DESC",
+        )?;
+        let paths = stmt
+            .query_map(params![user, path_prefix], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(paths)
+    }
+
+    /// Record bytes uploaded to or downloaded from a file
+    pub fn record_transfer(&self, file_path: &str, direction: Direction, bytes: u64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO transfers (file_path, direction, bytes, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                file_path,
+                direction.as_str(),
+                bytes as i64,
+                chrono::Utc::now().timestamp()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Note that a requester fetched or verified the provenance of `file_path`
+    pub fn record_verification(
+        &self,
+        file_path: &str,
+        kind: VerificationKind,
+        requester_ip: Option<&str>,
+        requester_user: Option<&str>,
+        success: bool,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO verification_log (file_path, kind, requester_ip, requester_user, success, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                file_path,
+                kind.as_str(),
+                requester_ip,
+                requester_user,
+                success,
+                chrono::Utc::now().timestamp()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Audit trail of `file_path`, oldest first
+    pub fn get_verification_log(&self, file_path: &str) -> Result<Vec<VerificationRecord>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT kind, requester_ip, requester_user, success, created_at
+             FROM verification_log WHERE file_path = ?1
+             ORDER BY id",
+        )?;
+        let records = stmt
+            .query_map(params![file_path], |row| {
+                Ok(VerificationRecord {
+                    kind: row.get(0)?,
+                    requester_ip: row.get(1)?,
+                    requester_user: row.get(2)?,
+                    success: row.get(3)?,
+                    created_at: row.get(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(records)
+    }
+
+    /// Note a password login of `user` to `path`
+    pub fn record_login(
+        &self,
+        user: &str,
+        ip: Option<&str>,
+        path: &str,
+        outcome: LoginOutcome,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO auth_log (user, ip, path, outcome, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                user,
+                ip,
+                path,
+                outcome.as_str(),
+                chrono::Utc::now().timestamp()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The latest `limit` logins, of `user` only when given, newest first
+    pub fn get_auth_log(&self, user: Option<&str>, limit: usize) -> Result<Vec<LoginRecord>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT user, ip, path, outcome, created_at FROM auth_log
+             WHERE ?1 IS NULL OR user = ?1
+             ORDER BY id DESC LIMIT ?2",
+        )?;
+        let records = stmt
+            .query_map(params![user, limit as i64], |row| {
+                Ok(LoginRecord {
+                    user: row.get(0)?,
+                    ip: row.get(1)?,
+                    path: row.get(2)?,
+                    outcome: row.get(3)?,
+                    created_at: row.get(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(records)
+    }
+
+    /// Last integrity check of `file_path`
+    pub fn get_integrity_check(&self, file_path: &str) -> Result<Option<IntegrityCheck>> {
+        let conn = self.reader();
+        let check = conn
+            .query_row(
+                "SELECT mtime, size, sha256_hex, modified FROM integrity_checks WHERE file_path = ?1",
+                params![file_path],
+                |row| {
+                    Ok(IntegrityCheck {
+                        mtime: row.get(0)?,
+                        size: row.get(1)?,
+                        sha256_hex: row.get(2)?,
+                        modified: row.get(3)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(check)
+    }
+
+    pub fn record_integrity_check(&self, file_path: &str, check: &IntegrityCheck) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO integrity_checks (file_path, mtime, size, sha256_hex, modified, checked_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                file_path,
+                check.mtime,
+                check.size,
+                check.sha256_hex,
+                check.modified,
+                chrono::Utc::now().timestamp()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Whether the last integrity check found other content than the artifact records
+    pub fn is_modified_since_mint(&self, file_path: &str) -> Result<bool> {
+        Ok(self
+            .get_integrity_check(file_path)?
+            .is_some_and(|v| v.modified))
+    }
+
+    /// Record a stamped merkle root over the first `event_count` event hashes
+    pub fn insert_anchor(
+        &self,
+        merkle_root_hex: &str,
+        event_count: usize,
+        ots_proof_b64: &str,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO provenance_anchors (merkle_root_hex, event_count, ots_proof_b64, created_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                merkle_root_hex,
+                event_count as i64,
+                ots_proof_b64,
+                chrono::Utc::now().to_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The most recent anchor, if the database was ever anchored
+    pub fn latest_anchor(&self) -> Result<Option<Anchor>> {
+        let conn = self.reader();
+        let anchor = conn
+            .query_row(
+                "SELECT merkle_root_hex, event_count, ots_proof_b64, created_at
+                 FROM provenance_anchors ORDER BY id DESC LIMIT 1",
+                [],
+                |row| {
+                    Ok(Anchor {
+                        merkle_root_hex: row.get(0)?,
+                        event_count: row.get(1)?,
+                        ots_proof_b64: row.get(2)?,
+                        anchored_at: row.get(3)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(anchor)
+    }
+
+    /// Sum transfers since `since` (unix seconds) into buckets of `bucket_secs`
+    pub fn traffic_buckets(&self, since: i64, bucket_secs: i64) -> Result<Vec<TrafficBucket>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT (created_at / ?2) * ?2 AS start,
+                    SUM(CASE WHEN direction = 'upload' THEN bytes ELSE 0 END),
+                    SUM(CASE WHEN direction = 'download' THEN bytes ELSE 0 END)
+             FROM transfers WHERE created_at >= ?1
+             GROUP BY start ORDER BY start",
+        )?;
+        let buckets = stmt
+            .query_map(params![since, bucket_secs], |row| {
+                Ok(TrafficBucket {
+                    start: row.get(0)?,
+                    uploaded: row.get::<_, i64>(1)? as u64,
+                    downloaded: row.get::<_, i64>(2)? as u64,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(buckets)
+    }
+
+    /// Files below `path_prefix` with the most bytes transferred since `since` (unix seconds)
+    pub fn top_files_by_traffic(
+        &self,
+        path_prefix: &str,
+        since: i64,
+        limit: usize,
+    ) -> Result<Vec<FileTraffic>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT file_path,
+                    SUM(CASE WHEN direction = 'upload' THEN bytes ELSE 0 END),
+                    SUM(CASE WHEN direction = 'download' THEN bytes ELSE 0 END),
+                    SUM(CASE WHEN direction = 'download' THEN 1 ELSE 0 END)
+             FROM transfers
+             WHERE created_at >= ?1 AND substr(file_path, 1, length(?3)) = ?3
+             GROUP BY file_path ORDER BY SUM(bytes) DESC LIMIT ?2",
+        )?;
+        let files = stmt
+            .query_map(params![since, limit as i64, path_prefix], |row| {
+                Ok(FileTraffic {
+                    file_path: row.get(0)?,
+                    uploaded: row.get::<_, i64>(1)? as u64,
+                    downloaded: row.get::<_, i64>(2)? as u64,
+                    downloads: row.get::<_, i64>(3)? as u64,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(files)
+    }
+
+    /// Count shares that haven't been revoked, expired or used up
+    pub fn count_active_shares(&self) -> Result<u64> {
+        let conn = self.reader();
+        let now = chrono::Utc::now().timestamp();
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM shares s
+             WHERE s.is_active = 1 AND (s.expires_at IS NULL OR s.expires_at > ?1)
+                AND (s.max_downloads IS NULL
+                    OR s.max_downloads > (SELECT COUNT(*) FROM share_downloads d WHERE d.share_id = s.share_id))",
+            params![now],
+            |row| row.get(0),
+        )?;
+        Ok(count as u64)
+    }
+
+    /// Get the ACL entries granted on exactly `file_path`
+    pub fn get_acl(&self, file_path: &str) -> Result<Vec<AclEntry>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT user, perm, granted_by, created_at FROM acl_entries
+             WHERE file_path = ?1 ORDER BY user",
+        )?;
+        let entries = stmt
+            .query_map(params![file_path], |row| {
+                Ok(AclEntry {
+                    user: row.get(0)?,
+                    perm: row.get(1)?,
+                    granted_by: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    /// Grant `perm` ('ro' or 'rw') on a path to a user, replacing any previous grant
+    pub fn set_acl_entry(
+        &self,
+        file_path: &str,
+        user: &str,
+        perm: &str,
+        granted_by: Option<&str>,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO acl_entries (file_path, user, perm, granted_by, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                file_path,
+                user,
+                perm,
+                granted_by,
+                chrono::Utc::now().to_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Revoke a user's grant on a path, returns false if there was none
+    pub fn delete_acl_entry(&self, file_path: &str, user: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let rows_affected = conn.execute(
+            "DELETE FROM acl_entries WHERE file_path = ?1 AND user = ?2",
+            params![file_path, user],
+        )?;
+        Ok(rows_affected > 0)
+    }
+
+    /// The grants a user holds on any of `file_paths`
+    pub fn acl_perms(&self, user: &str, file_paths: &[String]) -> Result<Vec<String>> {
+        let conn = self.reader();
+        let mut stmt =
+            conn.prepare("SELECT perm FROM acl_entries WHERE user = ?1 AND file_path = ?2")?;
+        let mut perms = vec![];
+        for file_path in file_paths {
+            if let Some(perm) = stmt
+                .query_row(params![user, file_path], |row| row.get(0))
+                .optional()?
+            {
+                perms.push(perm);
+            }
+        }
+        Ok(perms)
+    }
+
+    /// The keypair stored in this database, generated on first use
+    pub fn signing_keypair(&self) -> Result<ServerKeypair> {
+        let conn = self.conn.lock().unwrap();
+        let stored: Option<String> = conn
+            .query_row(
+                "SELECT value FROM settings WHERE key = 'signing_private_key_hex'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(private_key_hex) = stored {
+            return ServerKeypair::from_private_key_hex(&private_key_hex);
+        }
+        // Another process sharing the database may have stored one in the meantime
+        conn.execute(
+            "INSERT OR IGNORE INTO settings (key, value) VALUES ('signing_private_key_hex', ?1)",
+            params![ServerKeypair::generate().private_key_hex],
+        )?;
+        let private_key_hex: String = conn.query_row(
+            "SELECT value FROM settings WHERE key = 'signing_private_key_hex'",
             [],
+            |row| row.get(0),
+        )?;
+        ServerKeypair::from_private_key_hex(&private_key_hex)
+    }
+
+    /// Replace the keypair stored in this database with a fresh one
+    pub fn rotate_signing_keypair(&self) -> Result<ServerKeypair> {
+        let keypair = ServerKeypair::generate();
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('signing_private_key_hex', ?1)",
+            params![keypair.private_key_hex],
         )?;
+        Ok(keypair)
+    }
+
+    /// Record `public_key_hex` as the active signing key, retiring the previous one
+    pub fn activate_signing_key(&self, public_key_hex: &str) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = chrono::Utc::now().to_rfc3339();
+        let active: Option<String> = tx
+            .query_row(
+                "SELECT public_key_hex FROM signing_keys WHERE retired_at IS NULL",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if active.as_deref() == Some(public_key_hex) {
+            return Ok(());
+        }
+        tx.execute(
+            "UPDATE signing_keys SET retired_at = ?1 WHERE retired_at IS NULL",
+            params![now],
+        )?;
+        tx.execute(
+            "INSERT INTO signing_keys (public_key_hex, activated_at) VALUES (?1, ?2)
+             ON CONFLICT(public_key_hex) DO UPDATE SET
+                activated_at = excluded.activated_at,
+                retired_at = NULL",
+            params![public_key_hex, now],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// All signing keys, the active one first
+    pub fn list_signing_keys(&self) -> Result<Vec<SigningKeyRecord>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT public_key_hex, activated_at, retired_at FROM signing_keys
+             ORDER BY retired_at IS NOT NULL, activated_at DESC",
+        )?;
+        let keys = stmt
+            .query_map([], |row| {
+                Ok(SigningKeyRecord {
+                    public_key_hex: row.get(0)?,
+                    activated_at: row.get(1)?,
+                    retired_at: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(keys)
+    }
+
+    /// The public key `user` registered for minting, if any
+    pub fn get_user_key(&self, user: &str) -> Result<Option<String>> {
+        let conn = self.reader();
+        let key = conn
+            .query_row(
+                "SELECT public_key_hex FROM user_keys WHERE user = ?1",
+                params![user],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(key)
+    }
+
+    /// Register (or replace) the public key `user` mints with
+    pub fn set_user_key(&self, user: &str, public_key_hex: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO user_keys (user, public_key_hex, updated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(user) DO UPDATE SET
+                public_key_hex = excluded.public_key_hex,
+                updated_at = excluded.updated_at",
+            params![user, public_key_hex, chrono::Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    /// Forget the key of `user`, returning whether one was registered
+    pub fn delete_user_key(&self, user: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let deleted = conn.execute("DELETE FROM user_keys WHERE user = ?1", params![user])?;
+        Ok(deleted > 0)
+    }
+
+    /// Get the visibility explicitly set on exactly `file_path`
+    pub fn get_path_visibility(&self, file_path: &str) -> Result<Option<String>> {
+        let conn = self.reader();
+        let visibility = conn
+            .query_row(
+                "SELECT visibility FROM path_visibility WHERE file_path = ?1",
+                params![file_path],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(visibility)
+    }
+
+    /// Set a path 'public' or 'private'
+    pub fn set_path_visibility(
+        &self,
+        file_path: &str,
+        visibility: &str,
+        updated_by: Option<&str>,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO path_visibility (file_path, visibility, updated_at, updated_by)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                file_path,
+                visibility,
+                chrono::Utc::now().to_rfc3339(),
+                updated_by
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Record `user` as the uploader of `file_path`, an anonymous upload clears the owner
+    pub fn set_file_owner(&self, file_path: &str, user: Option<&str>, size: u64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        match user {
+            Some(user) => conn.execute(
+                "INSERT OR REPLACE INTO file_owners (file_path, user, size) VALUES (?1, ?2, ?3)",
+                params![file_path, user, size],
+            )?,
+            None => conn.execute(
+                "DELETE FROM file_owners WHERE file_path = ?1",
+                params![file_path],
+            )?,
+        };
+        Ok(())
+    }
+
+    /// Uploader of `file_path` and the size it was recorded with
+    pub fn get_file_owner(&self, file_path: &str) -> Result<Option<(String, u64)>> {
+        let conn = self.reader();
+        let owner = conn
+            .query_row(
+                "SELECT user, size FROM file_owners WHERE file_path = ?1",
+                params![file_path],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        Ok(owner)
+    }
+
+    /// Total size of the files `user` uploaded
+    pub fn get_owned_size(&self, user: &str) -> Result<u64> {
+        let conn = self.reader();
+        let size: i64 = conn.query_row(
+            "SELECT COALESCE(SUM(size), 0) FROM file_owners WHERE user = ?1",
+            params![user],
+            |row| row.get(0),
+        )?;
+        Ok(size as u64)
+    }
+
+    /// Drop the owners of `path` and everything below it
+    pub fn forget_file_owners(&self, path: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let (lower, upper) = subtree_bounds(path);
+        conn.execute(
+            "DELETE FROM file_owners WHERE file_path = ?1 OR (file_path >= ?2 AND file_path < ?3)",
+            params![path, lower, upper],
+        )?;
+        Ok(())
+    }
+
+    /// Dead properties of `file_path`, by namespace and name
+    pub fn get_dead_properties(&self, file_path: &str) -> Result<Vec<DeadProperty>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT namespace, name, value FROM dead_properties
+             WHERE file_path = ?1 ORDER BY namespace, name",
+        )?;
+        let properties = stmt
+            .query_map(params![file_path], |row| {
+                Ok(DeadProperty {
+                    namespace: row.get(0)?,
+                    name: row.get(1)?,
+                    value: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(properties)
+    }
+
+    /// Set dead properties of `file_path`, or remove those flagged `true`,
+    /// in order and all or nothing
+    pub fn patch_dead_properties(
+        &self,
+        file_path: &str,
+        changes: &[(DeadProperty, bool)],
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for (property, remove) in changes {
+            if *remove {
+                tx.execute(
+                    "DELETE FROM dead_properties
+                     WHERE file_path = ?1 AND namespace = ?2 AND name = ?3",
+                    params![file_path, property.namespace, property.name],
+                )?;
+            } else {
+                tx.execute(
+                    "INSERT OR REPLACE INTO dead_properties (file_path, namespace, name, value)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![file_path, property.namespace, property.name, property.value],
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Give the copy of `from` (and of everything under it) at `to` the same
+    /// dead properties
+    pub fn copy_dead_properties(&self, from: &str, to: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let (lower, upper) = subtree_bounds(from);
+        conn.execute(
+            "INSERT OR REPLACE INTO dead_properties (file_path, namespace, name, value)
+             SELECT ?1 || substr(file_path, ?2), namespace, name, value FROM dead_properties
+             WHERE file_path = ?3 OR (file_path >= ?4 AND file_path < ?5)",
+            params![to, from.chars().count() + 1, from, lower, upper],
+        )?;
+        Ok(())
+    }
+
+    /// Forget the dead properties of `path` and everything under it
+    pub fn forget_dead_properties(&self, path: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let (lower, upper) = subtree_bounds(path);
+        conn.execute(
+            "DELETE FROM dead_properties
+             WHERE file_path = ?1 OR (file_path >= ?2 AND file_path < ?3)",
+            params![path, lower, upper],
+        )?;
+        Ok(())
+    }
+
+    /// Store a new API token with the hash of its secret
+    pub fn create_api_token(&self, token: &ApiToken, secret_sha256_hex: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO api_tokens (id, secret_sha256_hex, user, name, scopes, created_at, expires_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                token.id,
+                secret_sha256_hex,
+                token.user,
+                token.name,
+                token.scopes,
+                token.created_at,
+                token.expires_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// An API token and the hash of its secret
+    pub fn get_api_token(&self, id: &str) -> Result<Option<(ApiToken, String)>> {
+        let conn = self.reader();
+        let token = conn
+            .query_row(
+                "SELECT id, user, name, scopes, created_at, expires_at, secret_sha256_hex
+                 FROM api_tokens WHERE id = ?1",
+                params![id],
+                |row| Ok((ApiToken::from_row(row)?, row.get(6)?)),
+            )
+            .optional()?;
+        Ok(token)
+    }
+
+    /// Tokens of `user`, newest first
+    pub fn list_api_tokens(&self, user: &str) -> Result<Vec<ApiToken>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT id, user, name, scopes, created_at, expires_at
+             FROM api_tokens WHERE user = ?1 ORDER BY created_at DESC",
+        )?;
+        let tokens = stmt
+            .query_map(params![user], ApiToken::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tokens)
+    }
+
+    /// Revoke a token, returning whether it existed
+    pub fn delete_api_token(&self, id: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let deleted = conn.execute("DELETE FROM api_tokens WHERE id = ?1", params![id])?;
+        Ok(deleted > 0)
+    }
+
+    /// Add (or refresh) entries of the search index, as (path, is_dir)
+    pub fn index_entries(&self, entries: &[(String, bool)]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut delete = tx.prepare("DELETE FROM search_entries WHERE path = ?1")?;
+            let mut insert =
+                tx.prepare("INSERT INTO search_entries (path, name, is_dir) VALUES (?1, ?2, ?3)")?;
+            for (path, is_dir) in entries {
+                let name = Path::new(path)
+                    .file_name()
+                    .map(|v| v.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                delete.execute(params![path])?;
+                insert.execute(params![path, name, is_dir])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
 
+    /// Drop `path` and everything below it from the search index
+    pub fn unindex_path(&self, path: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let (lower, upper) = subtree_bounds(path);
         conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_shares_file_path ON shares(file_path)",
-            [],
+            "DELETE FROM search_entries WHERE path = ?1 OR (path >= ?2 AND path < ?3)",
+            params![path, lower, upper],
         )?;
+        Ok(())
+    }
+
+    /// Indexed entries below `dir` whose name contains `needle` (all of them
+    /// for None), as (path, is_dir)
+    pub fn search_index(&self, dir: &str, needle: Option<&str>) -> Result<Vec<(String, bool)>> {
+        let conn = self.reader();
+        let (lower, upper) = subtree_bounds(dir);
+        let rows = match needle {
+            // The trigram index serves plain needles of 3+ chars, others scan the names
+            Some(needle) => {
+                let needle = needle.to_lowercase();
+                let plain = !needle.contains(['\\', '%', '_']);
+                let pattern = format!(
+                    "%{}%",
+                    needle
+                        .replace('\\', "\\\\")
+                        .replace('%', "\\%")
+                        .replace('_', "\\_")
+                );
+                let sql = if plain && needle.chars().count() >= 3 {
+                    "SELECT e.path, e.is_dir FROM search_names s
+                     JOIN search_entries e ON e.id = s.rowid
+                     WHERE s.name LIKE ?3 AND e.path >= ?1 AND e.path < ?2"
+                } else {
+                    "SELECT path, is_dir FROM search_entries
+                     WHERE name LIKE ?3 ESCAPE '\\' AND path >= ?1 AND path < ?2"
+                };
+                let mut stmt = conn.prepare(sql)?;
+                let rows = stmt
+                    .query_map(params![lower, upper, pattern], |row| {
+                        Ok((row.get(0)?, row.get(1)?))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                rows
+            }
+            None => {
+                let mut stmt = conn.prepare(
+                    "SELECT path, is_dir FROM search_entries WHERE path >= ?1 AND path < ?2",
+                )?;
+                let rows = stmt
+                    .query_map(params![lower, upper], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                rows
+            }
+        };
+        Ok(rows)
+    }
+
+    /// Get all files carrying `tag`
+    pub fn paths_with_tag(&self, tag: &str) -> Result<HashSet<String>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare("SELECT file_path FROM file_tags WHERE tag = ?1")?;
+        let paths = stmt
+            .query_map(params![tag], |row| row.get(0))?
+            .collect::<Result<HashSet<String>, _>>()?;
+        Ok(paths)
+    }
 
-        // Create share_downloads table to track distribution chain
+    /// Queue the digest of an event for stamping once the calendars answer
+    pub fn queue_ots_digest(
+        &self,
+        artifact_id: i64,
+        event_index: u32,
+        digest_hex: &str,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
         conn.execute(
-            "CREATE TABLE IF NOT EXISTS share_downloads (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                share_id TEXT NOT NULL,
-                downloaded_at TEXT NOT NULL,
-                downloaded_by TEXT,
-                redistributor_pubkey_hex TEXT,
-                redistributor_signature_hex TEXT,
-                FOREIGN KEY (share_id) REFERENCES shares(share_id) ON DELETE CASCADE
-            )",
-            [],
+            "INSERT OR REPLACE INTO ots_queue (artifact_id, event_index, digest_hex, queued_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                artifact_id,
+                event_index,
+                digest_hex,
+                chrono::Utc::now().to_rfc3339()
+            ],
         )?;
+        Ok(())
+    }
 
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_share_downloads_share_id ON share_downloads(share_id)",
-            [],
+    /// Up to `limit` queued digests, oldest first
+    pub fn queued_ots_digests(&self, limit: usize) -> Result<Vec<QueuedDigest>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT artifact_id, event_index, digest_hex FROM ots_queue
+             ORDER BY queued_at LIMIT ?1",
         )?;
+        let digests = stmt
+            .query_map(params![limit], |row| {
+                Ok(QueuedDigest {
+                    artifact_id: row.get(0)?,
+                    event_index: row.get(1)?,
+                    digest_hex: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(digests)
+    }
 
-        Ok(Self {
-            conn: Arc::new(Mutex::new(conn)),
-            db_path: Arc::new(db_path),
-        })
+    /// Record the proofs of stamped queued digests and take them off the queue
+    pub fn complete_queued_ots(&self, stamped: &[(QueuedDigest, String)]) -> Result<()> {
+        for (queued, ots_proof_b64) in stamped {
+            self.chain
+                .update_ots_proof(queued.artifact_id, queued.event_index, ots_proof_b64)?;
+            let conn = self.conn.lock().unwrap();
+            conn.execute(
+                "DELETE FROM ots_queue WHERE artifact_id = ?1 AND event_index = ?2",
+                params![queued.artifact_id, queued.event_index],
+            )?;
+        }
+        Ok(())
     }
 
-    /// Get the database file path
-    pub fn get_db_path(&self) -> &Path {
-        &self.db_path
+    /// Count, or unless `dry_run` delete, the rows about paths `is_orphan`
+    /// says are gone, then vacuum the database
+    ///
+    /// Artifacts and their events are left alone when the chain lives in
+    /// another database, which other instances may share.
+    pub fn collect_garbage(
+        &self,
+        is_orphan: impl Fn(&str) -> bool,
+        dry_run: bool,
+    ) -> Result<GcReport> {
+        let mut conn = self.conn.lock().unwrap();
+        let page_size: u64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        let page_count = |conn: &Connection| -> rusqlite::Result<u64> {
+            conn.query_row("PRAGMA page_count", [], |row| row.get(0))
+        };
+        let pages = page_count(&conn)?;
+
+        let mut report = GcReport {
+            dry_run,
+            ..Default::default()
+        };
+        // Artifacts go last, so the shares they cascade to are counted the
+        // same with or without `dry_run`
+        let mut tables = PATH_TABLES.to_vec();
+        if !self.external_chain {
+            tables.push(("artifacts", "file_path"));
+        }
+        let tx = conn.transaction()?;
+        for (table, column) in tables {
+            let paths: Vec<String> = tx
+                .prepare(&format!("SELECT DISTINCT {column} FROM {table}"))?
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?;
+            let mut rows = 0;
+            for path in paths.iter().filter(|v| is_orphan(v)) {
+                if table == "artifacts" {
+                    report.events += tx.query_row(
+                        "SELECT COUNT(*) FROM events e JOIN artifacts a ON a.id = e.artifact_id
+                         WHERE a.file_path = ?1",
+                        params![path],
+                        |row| row.get::<_, u64>(0),
+                    )?;
+                }
+                rows += if dry_run {
+                    tx.query_row(
+                        &format!("SELECT COUNT(*) FROM {table} WHERE {column} = ?1"),
+                        params![path],
+                        |row| row.get::<_, u64>(0),
+                    )?
+                } else {
+                    tx.execute(
+                        &format!("DELETE FROM {table} WHERE {column} = ?1"),
+                        params![path],
+                    )? as u64
+                };
+            }
+            if rows > 0 {
+                report.rows.insert(table.to_string(), rows);
+            }
+        }
+        if !self.external_chain && !dry_run {
+            tx.execute(
+                "DELETE FROM ots_queue WHERE artifact_id NOT IN (SELECT id FROM artifacts)",
+                [],
+            )?;
+        }
+        tx.commit()?;
+
+        if !dry_run && !report.rows.is_empty() {
+            conn.execute_batch("VACUUM")?;
+            report.reclaimed_bytes = pages.saturating_sub(page_count(&conn)?) * page_size;
+        }
+        Ok(report)
     }
+}
 
-    /// Insert or update artifact by file path
-    pub fn upsert_artifact(&self, file_path: &str, sha256_hex: &str) -> Result<i64> {
+impl ProvenanceStore for SqliteStore {
+    fn upsert_artifact(&self, file_path: &str, sha256_hex: &str) -> Result<i64> {
         let conn = self.conn.lock().unwrap();
         let now = chrono::Utc::now().to_rfc3339();
 
@@ -271,35 +2000,33 @@ impl ProvenanceDb {
         Ok(artifact_id)
     }
 
-    /// Get artifact by file path
-    pub fn get_artifact_by_path(&self, file_path: &str) -> Result<Option<(i64, Artifact)>> {
-        let conn = self.conn.lock().unwrap();
+    fn get_artifact_by_path(&self, file_path: &str) -> Result<Option<(i64, Artifact)>> {
+        let conn = self.reader();
 
         let mut stmt = conn.prepare(
-            "SELECT id, file_path, sha256_hex, verified_chain, verified_timestamp, verified_height, last_check_at, visibility
-             FROM artifacts WHERE file_path = ?1"
+            "SELECT id, sha256_hex, verified_chain, verified_timestamp, verified_height, visibility,
+                    encryption
+             FROM artifacts WHERE file_path = ?1",
         )?;
 
         let mut rows = stmt.query(params![file_path])?;
 
         if let Some(row) = rows.next()? {
             let id: i64 = row.get(0)?;
-            let file_path_str: String = row.get(1)?;
-            let sha256_hex: String = row.get(2)?;
-            let verified_chain: Option<String> = row.get(3)?;
-            let verified_timestamp: Option<i64> = row.get(4)?;
-            let verified_height: Option<i64> = row.get(5)?;
-            let last_check_at: Option<String> = row.get(6)?;
-            let visibility: String = row.get(7).unwrap_or_else(|_| "private".to_string());
+            let sha256_hex: String = row.get(1)?;
+            let verified_chain: Option<String> = row.get(2)?;
+            let verified_timestamp: Option<i64> = row.get(3)?;
+            let verified_height: Option<i64> = row.get(4)?;
+            let visibility: String = row.get(5).unwrap_or_else(|_| "private".to_string());
+            let encryption: Option<String> = row.get(6)?;
 
             let artifact = Artifact {
-                file_path: PathBuf::from(file_path_str),
                 sha256_hex,
                 verified_chain,
                 verified_timestamp,
                 verified_height: verified_height.map(|h| h as u64),
-                last_check_at,
                 visibility,
+                encryption: encryption.and_then(|v| serde_json::from_str(&v).ok()),
             };
             Ok(Some((id, artifact)))
         } else {
@@ -307,93 +2034,60 @@ impl ProvenanceDb {
         }
     }
 
-    /// Update artifact file path (for file moves/renames)
-    /// This is called when a file is moved to update the database
-    pub fn update_artifact_path(&self, old_path: &str, new_path: &str) -> Result<bool> {
-        let conn = self.conn.lock().unwrap();
-
-        let rows_affected = conn.execute(
-            "UPDATE artifacts SET file_path = ?1 WHERE file_path = ?2",
-            params![new_path, old_path],
-        )?;
-
-        Ok(rows_affected > 0)
+    fn insert_event(&self, args: InsertEventArgs) -> Result<i64> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let event_id = insert_event_tx(&tx, args)?;
+        tx.commit()?;
+        Ok(event_id)
     }
 
-    /// Insert a new provenance event
-    pub fn insert_event(&self, args: InsertEventArgs) -> Result<i64> {
+    fn import_events(&self, file_path: &str, sha256_hex: &str, events: &[Event]) -> Result<bool> {
         let mut conn = self.conn.lock().unwrap();
         let tx = conn.transaction()?;
-
-        let action_str = match args.action {
-            EventAction::Mint => "mint",
-            EventAction::Transfer => "transfer",
-        };
-
-        tx.execute(
-            "INSERT INTO events (artifact_id, index_num, action, artifact_sha256_hex, prev_event_hash_hex, issued_at, event_hash_hex, ots_proof_b64)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            params![
-                args.artifact_id,
-                args.index,
-                action_str,
-                args.artifact_sha256_hex,
-                args.prev_event_hash_hex,
-                args.issued_at,
-                args.event_hash_hex,
-                args.ots_proof_b64
-            ],
+        let now = chrono::Utc::now().to_rfc3339();
+        let artifact_id: i64 = tx.query_row(
+            r#"
+            INSERT INTO artifacts (file_path, sha256_hex, created_at)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT(file_path) DO UPDATE SET
+                sha256_hex = excluded.sha256_hex
+            RETURNING id
+            "#,
+            params![file_path, sha256_hex, now],
+            |row| row.get(0),
         )?;
-
-        let event_id = tx.last_insert_rowid();
-
-        // Insert actors
-        if let Some(ref creator) = args.actors.creator_pubkey_hex {
-            tx.execute(
-                "INSERT INTO event_actors (event_id, role, pubkey_hex) VALUES (?1, 'creator', ?2)",
-                params![event_id, creator],
-            )?;
-        }
-        if let Some(ref prev_owner) = args.actors.prev_owner_pubkey_hex {
-            tx.execute(
-                "INSERT INTO event_actors (event_id, role, pubkey_hex) VALUES (?1, 'prev_owner', ?2)",
-                params![event_id, prev_owner],
-            )?;
-        }
-        if let Some(ref new_owner) = args.actors.new_owner_pubkey_hex {
-            tx.execute(
-                "INSERT INTO event_actors (event_id, role, pubkey_hex) VALUES (?1, 'new_owner', ?2)",
-                params![event_id, new_owner],
-            )?;
-        }
-
-        // Insert signatures
-        if let Some(ref creator_sig) = args.signatures.creator_sig_hex {
-            tx.execute(
-                "INSERT INTO event_signatures (event_id, role, signature_hex) VALUES (?1, 'creator', ?2)",
-                params![event_id, creator_sig],
-            )?;
-        }
-        if let Some(ref prev_owner_sig) = args.signatures.prev_owner_sig_hex {
-            tx.execute(
-                "INSERT INTO event_signatures (event_id, role, signature_hex) VALUES (?1, 'prev_owner', ?2)",
-                params![event_id, prev_owner_sig],
-            )?;
+        let existing: i64 = tx.query_row(
+            "SELECT COUNT(*) FROM events WHERE artifact_id = ?1",
+            params![artifact_id],
+            |row| row.get(0),
+        )?;
+        if existing > 0 {
+            return Ok(false);
         }
-        if let Some(ref new_owner_sig) = args.signatures.new_owner_sig_hex {
-            tx.execute(
-                "INSERT INTO event_signatures (event_id, role, signature_hex) VALUES (?1, 'new_owner', ?2)",
-                params![event_id, new_owner_sig],
+        for event in events {
+            insert_event_tx(
+                &tx,
+                InsertEventArgs {
+                    artifact_id,
+                    index: event.index,
+                    action: &event.action,
+                    artifact_sha256_hex: &event.artifact_sha256_hex,
+                    prev_event_hash_hex: event.prev_event_hash_hex.as_deref(),
+                    issued_at: &event.issued_at,
+                    event_hash_hex: &event.event_hash_hex,
+                    ots_proof_b64: &event.ots_proof_b64,
+                    actors: &event.actors,
+                    signatures: &event.signatures,
+                },
             )?;
         }
-
         tx.commit()?;
-        Ok(event_id)
+        Ok(true)
     }
 
-    /// Get all events for an artifact, ordered by index
     fn get_events(&self, artifact_id: i64) -> Result<Vec<Event>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.reader();
 
         let mut stmt = conn.prepare(
             "SELECT id, index_num, action, artifact_sha256_hex, prev_event_hash_hex, issued_at, event_hash_hex, ots_proof_b64
@@ -460,6 +2154,7 @@ impl ProvenanceDb {
             let action = match action.as_str() {
                 "mint" => EventAction::Mint,
                 "transfer" => EventAction::Transfer,
+                "copy" => EventAction::Copy,
                 _ => continue,
             };
 
@@ -480,25 +2175,29 @@ impl ProvenanceDb {
         Ok(events)
     }
 
-    /// Generate complete manifest for an artifact by file path
-    pub fn get_manifest_by_path(&self, file_path: &str) -> Result<Option<Manifest>> {
-        let (artifact_id, artifact) = match self.get_artifact_by_path(file_path)? {
-            Some(result) => result,
-            None => return Ok(None),
-        };
-
-        let events = self.get_events(artifact_id)?;
+    fn has_event(&self, event_hash_hex: &str) -> Result<bool> {
+        let conn = self.reader();
+        let found = conn
+            .query_row(
+                "SELECT 1 FROM events WHERE event_hash_hex = ?1 LIMIT 1",
+                params![event_hash_hex],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(found.is_some())
+    }
 
-        Ok(Some(Manifest {
-            manifest_type: "provenance.manifest/v1".to_string(),
-            artifact,
-            events,
-        }))
+    fn event_hashes(&self) -> Result<Vec<String>> {
+        let conn = self.reader();
+        let hashes = conn
+            .prepare("SELECT event_hash_hex FROM events ORDER BY id")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(hashes)
     }
 
-    /// Get the next event index for an artifact
-    pub fn get_next_event_index(&self, artifact_id: i64) -> Result<u32> {
-        let conn = self.conn.lock().unwrap();
+    fn get_next_event_index(&self, artifact_id: i64) -> Result<u32> {
+        let conn = self.reader();
 
         let mut stmt = conn.prepare("SELECT MAX(index_num) FROM events WHERE artifact_id = ?1")?;
 
@@ -507,8 +2206,7 @@ impl ProvenanceDb {
         Ok(max_index.map(|i| i + 1).unwrap_or(0))
     }
 
-    /// Update the OTS proof for a specific event
-    pub fn update_ots_proof(
+    fn update_ots_proof(
         &self,
         artifact_id: i64,
         event_index: u32,
@@ -524,8 +2222,7 @@ impl ProvenanceDb {
         Ok(())
     }
 
-    /// Update verification results for an artifact
-    pub fn update_verification_result(
+    fn update_verification_result(
         &self,
         artifact_id: i64,
         chain: &str,
@@ -548,8 +2245,7 @@ impl ProvenanceDb {
         Ok(())
     }
 
-    /// Update last_check_at timestamp for an artifact (to throttle timestamp checking)
-    pub fn update_last_check_at(&self, artifact_id: i64) -> Result<()> {
+    fn update_last_check_at(&self, artifact_id: i64) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         let now = chrono::Utc::now().to_rfc3339();
 
@@ -561,8 +2257,24 @@ impl ProvenanceDb {
         Ok(())
     }
 
-    /// Update both OTS proof and verification results in a single transaction
-    pub fn update_ots_proof_and_verification(
+    fn unconfirmed_artifacts(
+        &self,
+        checked_before: &str,
+        limit: usize,
+    ) -> Result<Vec<(i64, String)>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT id, file_path FROM artifacts
+             WHERE verified_chain IS NULL AND (last_check_at IS NULL OR last_check_at < ?1)
+             ORDER BY id LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![checked_before, limit as i64], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn update_ots_proof_and_verification(
         &self,
         artifact_id: i64,
         event_index: u32,
@@ -598,217 +2310,393 @@ impl ProvenanceDb {
         Ok(())
     }
 
-    /// Create a new share for a file
-    pub fn create_share(
-        &self,
-        share_id: &str,
-        file_path: &str,
-        file_sha256_hex: &str,
-        created_at: &str,
-        shared_by: Option<&str>,
-        owner_pubkey_hex: &str,
-        share_signature_hex: &str,
-    ) -> Result<i64> {
-        let conn = self.conn.lock().unwrap();
-
-        // Get artifact_id if the file exists in provenance system
-        let artifact_id: Option<i64> = conn
-            .query_row(
-                "SELECT id FROM artifacts WHERE file_path = ?1",
-                params![file_path],
-                |row| row.get(0),
-            )
-            .ok();
-
-        let share_db_id: i64 = conn.query_row(
-            "INSERT INTO shares (share_id, file_path, file_sha256_hex, artifact_id, created_at, shared_by, owner_pubkey_hex, share_signature_hex)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
-             RETURNING id",
-            params![
-                share_id,
-                file_path,
-                file_sha256_hex,
-                artifact_id,
-                created_at,
-                shared_by,
-                owner_pubkey_hex,
-                share_signature_hex
-            ],
-            |row| row.get(0),
-        )?;
-
-        Ok(share_db_id)
-    }
-
-    /// Get share information by share_id
-    pub fn get_share(&self, share_id: &str) -> Result<Option<ShareInfo>> {
-        let conn = self.conn.lock().unwrap();
-
-        let mut stmt = conn.prepare(
-            "SELECT file_path, file_sha256_hex, created_at, shared_by, owner_pubkey_hex, share_signature_hex, is_active
-             FROM shares WHERE share_id = ?1",
-        )?;
-
-        let mut rows = stmt.query(params![share_id])?;
-
-        if let Some(row) = rows.next()? {
-            let file_path: String = row.get(0)?;
-            let file_sha256_hex: String = row.get(1)?;
-            let created_at: String = row.get(2)?;
-            let shared_by: Option<String> = row.get(3)?;
-            let owner_pubkey_hex: String = row.get(4)?;
-            let share_signature_hex: String = row.get(5)?;
-            let is_active: i32 = row.get(6)?;
-
-            Ok(Some(ShareInfo {
-                share_id: share_id.to_string(),
-                file_path,
-                file_sha256_hex,
-                created_at,
-                shared_by,
-                owner_pubkey_hex,
-                share_signature_hex,
-                is_active: is_active != 0,
-                stamp_status: None, // Will be populated by handler if needed
-            }))
-        } else {
-            Ok(None)
-        }
-    }
-
-    /// Get all shares for a specific file path
-    pub fn get_shares_for_file(&self, file_path: &str) -> Result<Vec<ShareInfo>> {
-        let conn = self.conn.lock().unwrap();
-
-        let mut stmt = conn.prepare(
-            "SELECT share_id, file_path, file_sha256_hex, created_at, shared_by, owner_pubkey_hex, share_signature_hex, is_active
-             FROM shares WHERE file_path = ?1 AND is_active = 1
-             ORDER BY created_at DESC",
-        )?;
-
-        let mut rows = stmt.query(params![file_path])?;
-        let mut shares = Vec::new();
-
-        while let Some(row) = rows.next()? {
-            let share_id: String = row.get(0)?;
-            let file_path: String = row.get(1)?;
-            let file_sha256_hex: String = row.get(2)?;
-            let created_at: String = row.get(3)?;
-            let shared_by: Option<String> = row.get(4)?;
-            let owner_pubkey_hex: String = row.get(5)?;
-            let share_signature_hex: String = row.get(6)?;
-            let is_active: i32 = row.get(7)?;
-
-            shares.push(ShareInfo {
-                share_id,
-                file_path,
-                file_sha256_hex,
-                created_at,
-                shared_by,
-                owner_pubkey_hex,
-                share_signature_hex,
-                is_active: is_active != 0,
-                stamp_status: None, // Will be populated by handler if needed
-            });
-        }
-
-        Ok(shares)
-    }
-
-    /// Record a share download (for tracking distribution chain)
-    pub fn record_share_download(
+    fn add_creator_signature(
         &self,
-        share_id: &str,
-        downloaded_by: Option<&str>,
-        redistributor_pubkey_hex: Option<&str>,
-        redistributor_signature_hex: Option<&str>,
-    ) -> Result<i64> {
+        artifact_id: i64,
+        event_index: u32,
+        signature_hex: &str,
+    ) -> Result<()> {
         let conn = self.conn.lock().unwrap();
-        let now = chrono::Utc::now().to_rfc3339();
+        conn.execute(
+            "INSERT INTO event_signatures (event_id, role, signature_hex)
+             SELECT id, 'creator', ?3 FROM events WHERE artifact_id = ?1 AND index_num = ?2",
+            params![artifact_id, event_index, signature_hex],
+        )?;
+        Ok(())
+    }
 
-        let download_id: i64 = conn.query_row(
-            "INSERT INTO share_downloads (share_id, downloaded_at, downloaded_by, redistributor_pubkey_hex, redistributor_signature_hex)
-             VALUES (?1, ?2, ?3, ?4, ?5)
-             RETURNING id",
+    fn add_attestation(&self, artifact_id: i64, attestation: &Attestation) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO attestations
+             (artifact_id, artifact_sha256_hex, algorithm, pubkey_hex, signature_hex, signed_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
             params![
-                share_id,
-                now,
-                downloaded_by,
-                redistributor_pubkey_hex,
-                redistributor_signature_hex
+                artifact_id,
+                attestation.artifact_sha256_hex,
+                attestation.algorithm.as_str(),
+                attestation.pubkey_hex,
+                attestation.signature_hex,
+                attestation.signed_at
             ],
-            |row| row.get(0),
         )?;
+        Ok(())
+    }
 
-        Ok(download_id)
+    fn get_attestations(&self, artifact_id: i64, sha256_hex: &str) -> Result<Vec<Attestation>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT algorithm, pubkey_hex, signature_hex, artifact_sha256_hex, signed_at
+             FROM attestations WHERE artifact_id = ?1 AND artifact_sha256_hex = ?2
+             ORDER BY id",
+        )?;
+        let attestations = stmt
+            .query_map(params![artifact_id, sha256_hex], |row| {
+                Ok(Attestation {
+                    // Known algorithms only, see the CHECK constraint
+                    algorithm: SignatureAlgorithm::parse(&row.get::<_, String>(0)?)
+                        .unwrap_or_default(),
+                    pubkey_hex: row.get(1)?,
+                    signature_hex: row.get(2)?,
+                    artifact_sha256_hex: row.get(3)?,
+                    signed_at: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(attestations)
     }
 
-    /// Deactivate a share (soft delete)
-    pub fn deactivate_share(&self, share_id: &str) -> Result<bool> {
+    fn move_artifacts(&self, old_path: &str, new_path: &str) -> Result<bool> {
         let conn = self.conn.lock().unwrap();
+        // Records left behind at the destination keep their artifact
+        let rows_affected = move_subtree(&conn, "UPDATE OR IGNORE artifacts", old_path, new_path)?;
+        Ok(rows_affected > 0)
+    }
 
-        let rows_affected = conn.execute(
-            "UPDATE shares SET is_active = 0 WHERE share_id = ?1",
-            params![share_id],
+    fn set_artifact_visibility(&self, file_path: &str, visibility: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE artifacts SET visibility = ?1 WHERE file_path = ?2",
+            params![visibility, file_path],
         )?;
-
-        Ok(rows_affected > 0)
+        Ok(())
     }
 
-    /// Get distribution chain for a share
-    pub fn get_distribution_chain(&self, share_id: &str) -> Result<Vec<DownloadRecord>> {
+    fn set_artifact_encryption(
+        &self,
+        file_path: &str,
+        encryption: Option<&Encryption>,
+    ) -> Result<()> {
+        let encryption = encryption.map(serde_json::to_string).transpose()?;
         let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE artifacts SET encryption = ?1 WHERE file_path = ?2",
+            params![encryption, file_path],
+        )?;
+        Ok(())
+    }
+}
 
-        let mut stmt = conn.prepare(
-            "SELECT downloaded_at, downloaded_by, redistributor_pubkey_hex, redistributor_signature_hex
-             FROM share_downloads WHERE share_id = ?1
-             ORDER BY downloaded_at ASC",
+/// Insert an event and its actors and signatures within `tx`
+fn insert_event_tx(tx: &rusqlite::Transaction, args: InsertEventArgs) -> Result<i64> {
+    let action_str = match args.action {
+        EventAction::Mint => "mint",
+        EventAction::Transfer => "transfer",
+        EventAction::Copy => "copy",
+    };
+
+    tx.execute(
+        "INSERT INTO events (artifact_id, index_num, action, artifact_sha256_hex, prev_event_hash_hex, issued_at, event_hash_hex, ots_proof_b64)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            args.artifact_id,
+            args.index,
+            action_str,
+            args.artifact_sha256_hex,
+            args.prev_event_hash_hex,
+            args.issued_at,
+            args.event_hash_hex,
+            args.ots_proof_b64
+        ],
+    )?;
+
+    let event_id = tx.last_insert_rowid();
+
+    // Insert actors
+    if let Some(ref creator) = args.actors.creator_pubkey_hex {
+        tx.execute(
+            "INSERT INTO event_actors (event_id, role, pubkey_hex) VALUES (?1, 'creator', ?2)",
+            params![event_id, creator],
+        )?;
+    }
+    if let Some(ref prev_owner) = args.actors.prev_owner_pubkey_hex {
+        tx.execute(
+            "INSERT INTO event_actors (event_id, role, pubkey_hex) VALUES (?1, 'prev_owner', ?2)",
+            params![event_id, prev_owner],
+        )?;
+    }
+    if let Some(ref new_owner) = args.actors.new_owner_pubkey_hex {
+        tx.execute(
+            "INSERT INTO event_actors (event_id, role, pubkey_hex) VALUES (?1, 'new_owner', ?2)",
+            params![event_id, new_owner],
         )?;
+    }
 
-        let mut rows = stmt.query(params![share_id])?;
-        let mut downloads = Vec::new();
+    // Insert signatures
+    if let Some(ref creator_sig) = args.signatures.creator_sig_hex {
+        tx.execute(
+            "INSERT INTO event_signatures (event_id, role, signature_hex) VALUES (?1, 'creator', ?2)",
+            params![event_id, creator_sig],
+        )?;
+    }
+    if let Some(ref prev_owner_sig) = args.signatures.prev_owner_sig_hex {
+        tx.execute(
+            "INSERT INTO event_signatures (event_id, role, signature_hex) VALUES (?1, 'prev_owner', ?2)",
+            params![event_id, prev_owner_sig],
+        )?;
+    }
+    if let Some(ref new_owner_sig) = args.signatures.new_owner_sig_hex {
+        tx.execute(
+            "INSERT INTO event_signatures (event_id, role, signature_hex) VALUES (?1, 'new_owner', ?2)",
+            params![event_id, new_owner_sig],
+        )?;
+    }
 
-        while let Some(row) = rows.next()? {
-            downloads.push(DownloadRecord {
-                downloaded_at: row.get(0)?,
-                downloaded_by: row.get(1)?,
-                redistributor_pubkey_hex: row.get(2)?,
-                redistributor_signature_hex: row.get(3)?,
-            });
-        }
+    Ok(event_id)
+}
 
-        Ok(downloads)
+/// Run `update` (e.g. `UPDATE OR REPLACE file_tags`) to move the records of
+/// `old_path` and, for a moved directory, of everything under it to `new_path`
+fn move_subtree(
+    conn: &Connection,
+    update: &str,
+    old_path: &str,
+    new_path: &str,
+) -> rusqlite::Result<usize> {
+    let (lower, upper) = subtree_bounds(old_path);
+    conn.execute(
+        &format!(
+            "{update} SET file_path = ?1 || substr(file_path, ?2)
+             WHERE file_path = ?3 OR (file_path >= ?4 AND file_path < ?5)"
+        ),
+        params![
+            new_path,
+            old_path.chars().count() + 1,
+            old_path,
+            lower,
+            upper
+        ],
+    )
+}
+
+/// Range of paths strictly below `path`: `>= "<path>/"` and `< "<path>0"`
+pub(crate) fn subtree_bounds(path: &str) -> (String, String) {
+    let path = path.trim_end_matches(std::path::MAIN_SEPARATOR);
+    let sep = std::path::MAIN_SEPARATOR;
+    let after_sep = char::from_u32(sep as u32 + 1).unwrap_or(sep);
+    (format!("{path}{sep}"), format!("{path}{after_sep}"))
+}
+
+/// The digest of an event waiting in the stamping queue
+/// Tables with rows about a path, and the column holding it
+const PATH_TABLES: &[(&str, &str)] = &[
+    ("shares", "file_path"),
+    ("file_metadata", "file_path"),
+    ("file_comments", "file_path"),
+    ("file_tags", "file_path"),
+    ("acl_entries", "file_path"),
+    ("path_visibility", "file_path"),
+    ("favorites", "file_path"),
+    ("file_owners", "file_path"),
+    ("dead_properties", "file_path"),
+    ("search_entries", "path"),
+    ("verification_log", "file_path"),
+    ("integrity_checks", "file_path"),
+];
+
+/// What `collect_garbage` found, or removed unless `dry_run`
+#[derive(Debug, Default, Serialize)]
+pub struct GcReport {
+    pub dry_run: bool,
+    /// Orphaned rows by table
+    pub rows: BTreeMap<String, u64>,
+    /// Events of the orphaned artifacts, which go with them
+    pub events: u64,
+    /// How much the database shrank once vacuumed
+    pub reclaimed_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueuedDigest {
+    pub artifact_id: i64,
+    pub event_index: u32,
+    pub digest_hex: String,
+}
+
+/// A WebDAV property stored for a client, with its text value
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeadProperty {
+    pub namespace: String,
+    pub name: String,
+    pub value: String,
+}
+
+/// An API token, see `POST /__dufs__/tokens`
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub user: String,
+    pub name: Option<String>,
+    /// Paths in the `--auth` syntax, e.g. `/docs:rw,/public`
+    pub scopes: String,
+    pub created_at: String,
+    /// Unix timestamp in seconds, None for tokens that don't expire
+    pub expires_at: Option<u64>,
+}
+
+impl ApiToken {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            user: row.get(1)?,
+            name: row.get(2)?,
+            scopes: row.get(3)?,
+            created_at: row.get(4)?,
+            expires_at: row.get(5)?,
+        })
     }
+}
 
-    /// Update file visibility based on active shares
-    /// This is called automatically when shares are created or deleted
-    pub fn update_file_visibility(&self, file_path: &str) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
+/// A per-path grant stored in the database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AclEntry {
+    pub user: String,
+    pub perm: String,
+    pub granted_by: Option<String>,
+    pub created_at: String,
+}
 
-        // Count active shares for this file
-        let active_shares: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM shares WHERE file_path = ?1 AND is_active = 1",
-            params![file_path],
-            |row| row.get(0),
-        )?;
+/// Which way bytes moved in a recorded transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Upload,
+    Download,
+}
 
-        // Determine visibility based on share count
-        let visibility = if active_shares > 0 {
-            "public"
-        } else {
-            "private"
-        };
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Upload => "upload",
+            Direction::Download => "download",
+        }
+    }
+}
 
-        // Update artifact visibility (or create artifact if it doesn't exist)
-        conn.execute(
-            "UPDATE artifacts SET visibility = ?1 WHERE file_path = ?2",
-            params![visibility, file_path],
-        )?;
+/// Hash of a file as found on disk, compared with its artifact
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntegrityCheck {
+    /// Unix milliseconds
+    pub mtime: i64,
+    pub size: i64,
+    pub sha256_hex: String,
+    pub modified: bool,
+}
 
-        Ok(())
+/// Merkle root over every event hash, stamped with OpenTimestamps
+#[derive(Debug, Clone, Serialize)]
+pub struct Anchor {
+    pub merkle_root_hex: String,
+    pub event_count: u64,
+    pub ots_proof_b64: String,
+    pub anchored_at: String,
+}
+
+/// What a requester did with the provenance of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerificationKind {
+    Manifest,
+    Ots,
+    Verify,
+}
+
+impl VerificationKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            VerificationKind::Manifest => "manifest",
+            VerificationKind::Ots => "ots",
+            VerificationKind::Verify => "verify",
+        }
+    }
+}
+
+/// One entry of a file's verification log
+#[derive(Debug, Clone, Serialize)]
+pub struct VerificationRecord {
+    pub kind: String,
+    pub requester_ip: Option<String>,
+    pub requester_user: Option<String>,
+    pub success: bool,
+    /// Unix seconds
+    pub created_at: i64,
+}
+
+/// How a password login went
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginOutcome {
+    Success,
+    Failure,
+    /// The failure that locked the user out of the address
+    Locked,
+}
+
+impl LoginOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LoginOutcome::Success => "success",
+            LoginOutcome::Failure => "failure",
+            LoginOutcome::Locked => "locked",
+        }
     }
 }
 
+/// One entry of the auth log
+#[derive(Debug, Clone, Serialize)]
+pub struct LoginRecord {
+    pub user: String,
+    pub ip: Option<String>,
+    pub path: String,
+    pub outcome: String,
+    /// Unix seconds
+    pub created_at: i64,
+}
+
+/// Bytes transferred within one time bucket
+#[derive(Debug, Clone, Serialize)]
+pub struct TrafficBucket {
+    /// Unix seconds at which the bucket starts
+    pub start: i64,
+    pub uploaded: u64,
+    pub downloaded: u64,
+}
+
+/// Bytes transferred for one file
+#[derive(Debug, Clone, Serialize)]
+pub struct FileTraffic {
+    pub file_path: String,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub downloads: u64,
+}
+
+/// Free-text comment on a file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileComment {
+    pub id: i64,
+    pub author: Option<String>,
+    pub body: String,
+    pub created_at: String,
+}
+
 /// Share information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ShareInfo {
@@ -820,10 +2708,36 @@ pub struct ShareInfo {
     pub owner_pubkey_hex: String,
     pub share_signature_hex: String,
     pub is_active: bool,
+    #[serde(flatten)]
+    pub limits: ShareLimits,
+    /// Argon2 hash in PHC format, only whether there is one is ever shown
+    #[serde(
+        rename = "password_protected",
+        serialize_with = "serialize_is_some",
+        skip_deserializing
+    )]
+    pub password_hash: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stamp_status: Option<serde_json::Value>,
 }
 
+fn serialize_is_some<S: serde::Serializer>(
+    value: &Option<String>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_bool(value.is_some())
+}
+
+/// When a share stops working, none of them by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ShareLimits {
+    /// Unix time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_downloads: Option<u64>,
+}
+
 /// Download record for tracking distribution chain
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DownloadRecord {
@@ -866,6 +2780,7 @@ pub fn compute_event_hash(
     let action_str = match action {
         EventAction::Mint => "mint",
         EventAction::Transfer => "transfer",
+        EventAction::Copy => "copy",
     };
 
     // Build actors JSON with sorted keys
@@ -1002,6 +2917,30 @@ pub fn verify_event_signature(
     }
 }
 
+/// Verify an ed25519 signature over the bytes of `message_hex`
+pub fn verify_ed25519_signature(
+    message_hex: &str,
+    signature_hex: &str,
+    public_key_hex: &str,
+) -> Result<bool> {
+    use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+
+    let message =
+        hex::decode(message_hex).map_err(|e| anyhow!("Failed to decode message: {}", e))?;
+    let signature_bytes =
+        hex::decode(signature_hex).map_err(|e| anyhow!("Failed to decode signature: {}", e))?;
+    let public_key_bytes: [u8; 32] = hex::decode(public_key_hex)
+        .map_err(|e| anyhow!("Failed to decode public key: {}", e))?
+        .try_into()
+        .map_err(|_| anyhow!("Public key must be 32 bytes"))?;
+
+    let signature =
+        Signature::from_slice(&signature_bytes).map_err(|e| anyhow!("Invalid signature: {}", e))?;
+    let public_key = VerifyingKey::from_bytes(&public_key_bytes)
+        .map_err(|e| anyhow!("Invalid public key: {}", e))?;
+    Ok(public_key.verify(&message, &signature).is_ok())
+}
+
 /// Generate a share signature for a file
 ///
 /// The signature is over: SHA256(file_sha256 + share_id + timestamp)
@@ -1142,16 +3081,114 @@ pub fn verify_event(event: &Event) -> Result<bool> {
 
             Ok(prev_valid && new_valid)
         }
+        EventAction::Copy => {
+            // Copies are signed by the server that made them
+            match (
+                &event.signatures.creator_sig_hex,
+                &event.actors.creator_pubkey_hex,
+            ) {
+                (Some(sig), Some(pubkey)) => {
+                    verify_event_signature(&event.event_hash_hex, sig, pubkey)
+                }
+                _ => Err(anyhow::anyhow!(
+                    "Copy event missing creator signature or public key"
+                )),
+            }
+        }
     }
 }
 
+/// Check that `events` form a complete chain for the artifact `sha256_hex`:
+/// indexes from 0, each event linking to the previous one's hash, starting
+/// with a mint, and every event's hash and signatures verifying
+pub fn verify_chain(sha256_hex: &str, events: &[Event]) -> Result<()> {
+    if !matches!(events.first(), Some(e) if matches!(e.action, EventAction::Mint)) {
+        bail!("The chain must start with a mint event");
+    }
+    let mut prev_event_hash_hex = None;
+    for (i, event) in events.iter().enumerate() {
+        if event.index as usize != i {
+            bail!("Event #{i} has index {}", event.index);
+        }
+        if event.artifact_sha256_hex != sha256_hex {
+            bail!("Event #{i} is for a different file");
+        }
+        if event.prev_event_hash_hex.as_deref() != prev_event_hash_hex {
+            bail!("Event #{i} doesn't link to the previous event");
+        }
+        if i > 0 && matches!(event.action, EventAction::Mint) {
+            bail!("Event #{i} is a second mint");
+        }
+        if !verify_event(event).unwrap_or_default() {
+            bail!("Event #{i} has an invalid hash or signature");
+        }
+        prev_event_hash_hex = Some(event.event_hash_hex.as_str());
+    }
+    Ok(())
+}
+
+/// Outcome of checking one event of a chain
+#[derive(Debug, Clone, Serialize)]
+pub struct EventCheck {
+    pub index: u32,
+    pub action: EventAction,
+    pub event_hash_hex: String,
+    /// The stored hash matches the recomputed canonical hash
+    pub hash_valid: bool,
+    /// The event sits at its index and links to the previous event's hash
+    pub linked: bool,
+    pub signatures_valid: bool,
+    /// All of the above hold
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Check every event of a chain instead of stopping at the first broken one
+/// like `verify_chain`, so a tampered database shows where it was changed
+pub fn check_chain(events: &[Event]) -> Vec<EventCheck> {
+    let mut prev_event_hash_hex = None;
+    events
+        .iter()
+        .enumerate()
+        .map(|(i, event)| {
+            let computed_hash = compute_event_hash(
+                event.index,
+                &event.action,
+                &event.artifact_sha256_hex,
+                event.prev_event_hash_hex.as_deref(),
+                &event.actors,
+                &event.issued_at,
+            );
+            let linked = event.index as usize == i
+                && event.prev_event_hash_hex.as_deref() == prev_event_hash_hex;
+            prev_event_hash_hex = Some(event.event_hash_hex.as_str());
+            let (signatures_valid, error) = match verify_event(event) {
+                Ok(valid) => (valid, None),
+                Err(e) => (false, Some(e.to_string())),
+            };
+            let hash_valid = computed_hash == event.event_hash_hex;
+            EventCheck {
+                index: event.index,
+                action: event.action.clone(),
+                event_hash_hex: event.event_hash_hex.clone(),
+                hash_valid,
+                linked,
+                signatures_valid,
+                passed: hash_valid && linked && signatures_valid,
+                error,
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_db_creation() -> Result<()> {
-        let db = ProvenanceDb::new(":memory:")?;
+        let db = ProvenanceDb::new(":memory:", &DbEncryption::default())?;
 
         // Test artifact insertion
         let artifact_id = db.upsert_artifact("/tmp/test.txt", "abc123")?;
@@ -1161,9 +3198,148 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_unconfirmed_artifacts() -> Result<()> {
+        let db = ProvenanceDb::new(":memory:", &DbEncryption::default())?;
+        let pending = db.upsert_artifact("/tmp/pending.txt", "aa")?;
+        let confirmed = db.upsert_artifact("/tmp/confirmed.txt", "bb")?;
+        let checked = db.upsert_artifact("/tmp/checked.txt", "cc")?;
+        db.update_verification_result(confirmed, "bitcoin", 1, 800000)?;
+        db.update_last_check_at(checked)?;
+
+        let since = (chrono::Utc::now() - chrono::Duration::minutes(5)).to_rfc3339();
+        assert_eq!(
+            db.unconfirmed_artifacts(&since, 10)?,
+            vec![(pending, "/tmp/pending.txt".to_string())]
+        );
+        let later = (chrono::Utc::now() + chrono::Duration::minutes(1)).to_rfc3339();
+        assert_eq!(db.unconfirmed_artifacts(&later, 10)?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_latest_anchor() -> Result<()> {
+        let db = ProvenanceDb::new(":memory:", &DbEncryption::default())?;
+        assert!(db.latest_anchor()?.is_none());
+        db.insert_anchor("aa", 1, "proof1")?;
+        db.insert_anchor("bb", 2, "proof2")?;
+        let anchor = db.latest_anchor()?.unwrap();
+        assert_eq!(anchor.merkle_root_hex, "bb");
+        assert_eq!(anchor.event_count, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reads_during_write() -> Result<()> {
+        let tmpdir = assert_fs::TempDir::new()?;
+        let db = ProvenanceDb::new(
+            tmpdir.path().join("provenance.db"),
+            &DbEncryption::default(),
+        )?;
+        db.upsert_artifact("/tmp/test.txt", "abc123")?;
+        assert_eq!(db.readers.conns.len(), READ_POOL_SIZE);
+
+        // Readers see the last commit while a write transaction is open
+        let mut writer = db.conn.lock().unwrap();
+        let tx = writer.transaction()?;
+        tx.execute("DELETE FROM artifacts", [])?;
+        assert!(db.get_artifact_by_path("/tmp/test.txt")?.is_some());
+        tx.commit()?;
+        drop(writer);
+        assert!(db.get_artifact_by_path("/tmp/test.txt")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_leaves_nothing_behind() -> Result<()> {
+        use std::io::Read;
+
+        let tmpdir = assert_fs::TempDir::new()?;
+        let db = ProvenanceDb::new(
+            tmpdir.path().join("provenance.db"),
+            &DbEncryption::default(),
+        )?;
+        db.upsert_artifact("/tmp/test.txt", "abc123")?;
+        let files_before = std::fs::read_dir(tmpdir.path())?.count();
+
+        let mut head = [0; 16];
+        db.snapshot()?.read_exact(&mut head)?;
+        assert_eq!(&head, b"SQLite format 3\0");
+        assert_eq!(std::fs::read_dir(tmpdir.path())?.count(), files_before);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ots_queue() -> Result<()> {
+        let db = ProvenanceDb::new(":memory:", &DbEncryption::default())?;
+        let artifact_id = db.upsert_artifact("/tmp/test.txt", "abc123")?;
+        db.queue_ots_digest(artifact_id, 0, "abc123")?;
+        db.queue_ots_digest(artifact_id, 0, "abc123")?;
+
+        let queued = db.queued_ots_digests(10)?;
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].digest_hex, "abc123");
+        db.complete_queued_ots(&[(queued[0].clone(), "proof".into())])?;
+        assert!(db.queued_ots_digests(10)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_garbage() -> Result<()> {
+        let db = ProvenanceDb::new(":memory:", &DbEncryption::default())?;
+        let gone_id = db.upsert_artifact("/srv/gone.txt", "abc123")?;
+        db.upsert_artifact("/srv/kept.txt", "def456")?;
+        db.queue_ots_digest(gone_id, 0, "abc123")?;
+        db.update_file_tags("/srv/gone.txt", &["a".into(), "b".into()], &[])?;
+        db.update_file_tags("/srv/kept.txt", &["a".into()], &[])?;
+        let is_orphan = |path: &str| path == "/srv/gone.txt";
+
+        let report = db.collect_garbage(is_orphan, true)?;
+        assert_eq!(report.rows.get("artifacts"), Some(&1));
+        assert_eq!(report.rows.get("file_tags"), Some(&2));
+        assert!(db.get_artifact_by_path("/srv/gone.txt")?.is_some());
+
+        let report = db.collect_garbage(is_orphan, false)?;
+        assert_eq!(report.rows.get("file_tags"), Some(&2));
+        assert!(db.get_artifact_by_path("/srv/gone.txt")?.is_none());
+        assert!(db.get_artifact_by_path("/srv/kept.txt")?.is_some());
+        assert_eq!(db.get_file_tags("/srv/kept.txt")?, vec!["a".to_string()]);
+        assert!(db.queued_ots_digests(10)?.is_empty());
+        assert!(db.collect_garbage(is_orphan, false)?.rows.is_empty());
+        Ok(())
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn test_encrypted_db_rekey() -> Result<()> {
+        let tmpdir = assert_fs::TempDir::new()?;
+        let db_path = tmpdir.path().join("provenance.db");
+        let encryption = |key: &str, rekey: Option<&str>| DbEncryption {
+            key: Some(key.to_string()),
+            rekey: rekey.map(|v| v.to_string()),
+        };
+
+        let db = ProvenanceDb::new(&db_path, &encryption("old-key", None))?;
+        db.upsert_artifact("/tmp/test.txt", "abc123")?;
+        drop(db);
+
+        assert!(ProvenanceDb::new(&db_path, &DbEncryption::default()).is_err());
+        assert!(ProvenanceDb::new(&db_path, &encryption("wrong-key", None)).is_err());
+
+        drop(ProvenanceDb::new(
+            &db_path,
+            &encryption("old-key", Some("new-key")),
+        )?);
+        assert!(ProvenanceDb::new(&db_path, &encryption("old-key", None)).is_err());
+        let db = ProvenanceDb::new(&db_path, &encryption("new-key", None))?;
+        assert!(db.get_artifact_by_path("/tmp/test.txt")?.is_some());
+
+        Ok(())
+    }
+
     #[test]
     fn test_event_insertion() -> Result<()> {
-        let db = ProvenanceDb::new(":memory:")?;
+        let db = ProvenanceDb::new(":memory:", &DbEncryption::default())?;
 
         let artifact_id = db.upsert_artifact("/tmp/test.txt", "abc123")?;
 
@@ -1201,7 +3377,7 @@ mod tests {
 
     #[test]
     fn test_manifest_generation() -> Result<()> {
-        let db = ProvenanceDb::new(":memory:")?;
+        let db = ProvenanceDb::new(":memory:", &DbEncryption::default())?;
 
         let artifact_id = db.upsert_artifact("/tmp/test.txt", "abc123")?;
 
@@ -1504,4 +3680,50 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_verify_chain() -> Result<()> {
+        let keypair = ServerKeypair::generate();
+        let actors = Actors {
+            creator_pubkey_hex: Some(keypair.public_key_hex.clone()),
+            prev_owner_pubkey_hex: None,
+            new_owner_pubkey_hex: None,
+        };
+        let issued_at = "2025-09-25T14:12:34Z";
+        let event_hash =
+            compute_event_hash(0, &EventAction::Mint, "abc123", None, &actors, issued_at);
+        let mint = Event {
+            event_type: "provenance.event/v1".to_string(),
+            index: 0,
+            action: EventAction::Mint,
+            artifact_sha256_hex: "abc123".to_string(),
+            prev_event_hash_hex: None,
+            actors,
+            issued_at: issued_at.to_string(),
+            event_hash_hex: event_hash.clone(),
+            signatures: Signatures {
+                creator_sig_hex: Some(sign_event_hash(&event_hash, &keypair.private_key_hex)?),
+                prev_owner_sig_hex: None,
+                new_owner_sig_hex: None,
+            },
+            ots_proof_b64: String::new(),
+        };
+
+        assert!(verify_chain("abc123", std::slice::from_ref(&mint)).is_ok());
+        assert!(verify_chain("def456", std::slice::from_ref(&mint)).is_err());
+        assert!(verify_chain("abc123", &[]).is_err());
+
+        let mut unlinked = mint.clone();
+        unlinked.prev_event_hash_hex = Some(event_hash);
+        assert!(verify_chain("abc123", &[unlinked]).is_err());
+
+        let checks = check_chain(std::slice::from_ref(&mint));
+        assert!(checks[0].passed);
+        let mut tampered = mint.clone();
+        tampered.issued_at = "2025-09-26T14:12:34Z".to_string();
+        let checks = check_chain(&[tampered, mint]);
+        assert!(!checks[0].hash_valid && checks[0].linked);
+        assert!(checks[1].hash_valid && !checks[1].linked);
+        Ok(())
+    }
 }
@@ -0,0 +1,91 @@
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::time::Duration;
+
+use crate::ots_stamper;
+use crate::provenance::ProvenanceDb;
+
+/// Periodic OpenTimestamps anchor of the merkle root over every event hash,
+/// so the state of the whole chain is timestamped with one proof.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnchorSchedule {
+    pub interval: Duration,
+}
+
+impl AnchorSchedule {
+    /// Run forever, anchoring every `interval` when events were recorded since
+    /// the last anchor.
+    pub fn spawn(self, db: ProvenanceDb) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            loop {
+                ticker.tick().await;
+                match anchor(&db).await {
+                    Ok(Some(root)) => info!("Anchored provenance merkle root {root}"),
+                    Ok(None) => {}
+                    Err(err) => warn!("Failed to anchor provenance database, {err}"),
+                }
+            }
+        });
+    }
+}
+
+/// Stamp the current merkle root, returning it unless there was nothing new to anchor
+async fn anchor(db: &ProvenanceDb) -> Result<Option<String>> {
+    let hashes = db.run(|db| db.event_hashes()).await?;
+    let Some(root) = merkle_root(&hashes)? else {
+        return Ok(None);
+    };
+    let root_hex = hex::encode(&root);
+    let latest = db.run(|db| db.latest_anchor()).await?;
+    if latest.is_some_and(|v| v.merkle_root_hex == root_hex) {
+        return Ok(None);
+    }
+    let proof = ots_stamper::create_timestamps(&[root])
+        .await?
+        .pop()
+        .unwrap_or_default();
+    let proof_b64 = STANDARD.encode(proof);
+    let event_count = hashes.len();
+    let anchored_root = root_hex.clone();
+    db.run(move |db| db.insert_anchor(&anchored_root, event_count, &proof_b64))
+        .await?;
+    Ok(Some(root_hex))
+}
+
+/// Merkle root over the event hashes, None without events
+fn merkle_root(event_hashes: &[String]) -> Result<Option<Vec<u8>>> {
+    if event_hashes.is_empty() {
+        return Ok(None);
+    }
+    let leaves = event_hashes
+        .iter()
+        .map(hex::decode)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Some(ots_stamper::merkle_root(&leaves)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    #[test]
+    fn test_merkle_root() -> Result<()> {
+        assert_eq!(merkle_root(&[])?, None);
+
+        let hashes: Vec<String> = ["a", "b", "c"]
+            .iter()
+            .map(|v| hex::encode(Sha256::digest(v)))
+            .collect();
+        let root = merkle_root(&hashes[..1])?.unwrap();
+        assert_eq!(hex::encode(root), hashes[0]);
+
+        let pair = |left: &[u8], right: &[u8]| Sha256::digest([left, right].concat()).to_vec();
+        let leaves: Vec<Vec<u8>> = hashes.iter().map(|v| hex::decode(v).unwrap()).collect();
+        let expected = pair(&pair(&leaves[0], &leaves[1]), &leaves[2]);
+        assert_eq!(merkle_root(&hashes)?, Some(expected));
+        assert!(merkle_root(&["zz".to_string()]).is_err());
+        Ok(())
+    }
+}
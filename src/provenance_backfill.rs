@@ -0,0 +1,184 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures_util::{stream, StreamExt};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::args::Args;
+use crate::file_utils;
+use crate::ots_aggregator::QUEUED_PROOF;
+use crate::provenance::{
+    compute_event_hash, sign_event_hash, Actors, EventAction, InsertEventArgs, ProvenanceDb,
+    ServerKeypair, Signatures,
+};
+use crate::provenance_store;
+use crate::search_index::{canonical_db_path, is_db_file};
+use crate::signing_keys::SigningKeySource;
+
+/// What `backfill` found and minted
+#[derive(Debug, Default, Serialize)]
+pub struct BackfillReport {
+    /// Files without provenance events
+    pub missing: usize,
+    pub minted: usize,
+    pub failed: usize,
+}
+
+/// Folders whose files get provenance: the serve path and the mounts
+pub fn roots(args: &Args) -> Vec<PathBuf> {
+    std::iter::once(&args.serve_path)
+        .chain(args.mounts.values())
+        .filter(|v| v.is_dir())
+        .cloned()
+        .collect()
+}
+
+/// Number of files hashed at once unless `--jobs` is given
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(4, |v| v.get())
+}
+
+/// Mint the files under `roots` that have no provenance events yet, hashing
+/// up to `jobs` of them at once. Their digests are queued, the server stamps
+/// them in the background.
+pub async fn backfill(
+    db: &ProvenanceDb,
+    keypair: &ServerKeypair,
+    roots: Vec<PathBuf>,
+    skip: Option<PathBuf>,
+    jobs: usize,
+) -> Result<BackfillReport> {
+    let scan_db = db.clone();
+    let missing =
+        tokio::task::spawn_blocking(move || missing_files(&scan_db, &roots, skip)).await??;
+    let mut report = BackfillReport {
+        missing: missing.len(),
+        ..Default::default()
+    };
+    let mut hashed = stream::iter(missing)
+        .map(|path| async move {
+            let sha256_hex = file_utils::sha256_file_hash(&path).await;
+            (path, sha256_hex)
+        })
+        .buffer_unordered(jobs.max(1));
+    while let Some((path, sha256_hex)) = hashed.next().await {
+        let ret = match sha256_hex {
+            Ok(sha256_hex) => {
+                let keypair = keypair.clone();
+                let path = path.clone();
+                db.run(move |db| mint(db, &keypair, &path, &sha256_hex))
+                    .await
+            }
+            Err(err) => Err(err),
+        };
+        match ret {
+            Ok(true) => report.minted += 1,
+            // Uploaded while we were hashing
+            Ok(false) => {}
+            Err(err) => {
+                warn!("Failed to backfill {}, {err}", path.display());
+                report.failed += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Files under `roots`, except the database and `skip`, without events
+fn missing_files(
+    db: &ProvenanceDb,
+    roots: &[PathBuf],
+    skip: Option<PathBuf>,
+) -> Result<Vec<PathBuf>> {
+    let db_path = canonical_db_path(db);
+    let mut files = vec![];
+    for root in roots {
+        let it = WalkDir::new(root)
+            .follow_links(true)
+            .into_iter()
+            .filter_entry(|v| {
+                skip.as_deref()
+                    .is_none_or(|skip| !v.path().starts_with(skip))
+            });
+        for entry in it.flatten() {
+            let path = entry.path();
+            if !entry.file_type().is_file() || is_db_file(path, &db_path) {
+                continue;
+            }
+            let Some(path_str) = path.to_str() else {
+                continue;
+            };
+            let minted = match db.get_artifact_by_path(path_str)? {
+                Some((artifact_id, _)) => db.get_next_event_index(artifact_id)? > 0,
+                None => false,
+            };
+            if !minted {
+                files.push(path.to_path_buf());
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Record a mint signed by the server, with its digest queued for stamping.
+/// False if the file got events meanwhile.
+fn mint(db: &ProvenanceDb, keypair: &ServerKeypair, path: &Path, sha256_hex: &str) -> Result<bool> {
+    let path_str = path
+        .to_str()
+        .ok_or_else(|| anyhow!("Invalid UTF-8 in path"))?;
+    let artifact_id = db.upsert_artifact(path_str, sha256_hex)?;
+    if db.get_next_event_index(artifact_id)? > 0 {
+        return Ok(false);
+    }
+    let actors = Actors {
+        creator_pubkey_hex: Some(keypair.public_key_hex.clone()),
+        prev_owner_pubkey_hex: None,
+        new_owner_pubkey_hex: None,
+    };
+    let issued_at = chrono::Utc::now().to_rfc3339();
+    let event_hash_hex =
+        compute_event_hash(0, &EventAction::Mint, sha256_hex, None, &actors, &issued_at);
+    let signatures = Signatures {
+        creator_sig_hex: Some(sign_event_hash(&event_hash_hex, &keypair.private_key_hex)?),
+        prev_owner_sig_hex: None,
+        new_owner_sig_hex: None,
+    };
+    db.insert_event(InsertEventArgs {
+        artifact_id,
+        index: 0,
+        action: &EventAction::Mint,
+        artifact_sha256_hex: sha256_hex,
+        prev_event_hash_hex: None,
+        issued_at: &issued_at,
+        event_hash_hex: &event_hash_hex,
+        ots_proof_b64: &STANDARD.encode(QUEUED_PROOF),
+        actors: &actors,
+        signatures: &signatures,
+    })?;
+    db.queue_ots_digest(artifact_id, 0, sha256_hex)?;
+    Ok(true)
+}
+
+/// Run `node-drive provenance backfill` against the database the server would use
+pub async fn run(args: &Args, jobs: usize) -> Result<()> {
+    let mut db = ProvenanceDb::new(args.provenance_db_path(), &args.provenance_db_encryption)?;
+    if let Some(url) = args.provenance_db_url() {
+        db = db.with_chain(provenance_store::connect(url)?);
+    }
+    let keypair = SigningKeySource::new(
+        args.signing_key.as_deref(),
+        args.signing_key_file.as_deref(),
+    )
+    .load(false, &db)?;
+    let report = backfill(&db, &keypair, roots(args), args.trash_dir.clone(), jobs).await?;
+
+    println!(
+        "Minted {} of {} files without provenance",
+        report.minted, report.missing
+    );
+    if report.failed > 0 {
+        println!("Failed to mint {} files", report.failed);
+    }
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::provenance::ProvenanceDb;
+
+const BACKUP_FILE_PREFIX: &str = "provenance-";
+const BACKUP_FILE_SUFFIX: &str = ".db";
+
+/// Periodic snapshots of the provenance database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackupSchedule {
+    pub dir: PathBuf,
+    pub interval: Duration,
+    pub keep: usize,
+}
+
+impl BackupSchedule {
+    /// Run forever, taking a snapshot every `interval` and pruning old ones.
+    pub fn spawn(self, db: ProvenanceDb) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            loop {
+                ticker.tick().await;
+                let schedule = self.clone();
+                let db = db.clone();
+                let ret = tokio::task::spawn_blocking(move || schedule.run_once(&db)).await;
+                match ret {
+                    Ok(Ok(path)) => info!("Backed up provenance database to {}", path.display()),
+                    Ok(Err(err)) => error!("Failed to back up provenance database, {err}"),
+                    Err(err) => error!("Failed to back up provenance database, {err}"),
+                }
+            }
+        });
+    }
+
+    fn run_once(&self, db: &ProvenanceDb) -> Result<PathBuf> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create `{}`", self.dir.display()))?;
+        let name = format!(
+            "{BACKUP_FILE_PREFIX}{}{BACKUP_FILE_SUFFIX}",
+            Utc::now().format("%Y%m%dT%H%M%SZ")
+        );
+        let dest = self.dir.join(name);
+        db.backup_to(&dest)?;
+        prune_backups(&self.dir, self.keep)?;
+        Ok(dest)
+    }
+}
+
+/// Remove the oldest snapshots so at most `keep` remain.
+/// Snapshot names embed a sortable timestamp, so name order is age order.
+fn prune_backups(dir: &Path, keep: usize) -> Result<()> {
+    let mut backups: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|v| v.to_str())
+                .map(|v| v.starts_with(BACKUP_FILE_PREFIX) && v.ends_with(BACKUP_FILE_SUFFIX))
+                .unwrap_or_default()
+        })
+        .collect();
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep);
+    for path in backups.into_iter().take(excess) {
+        std::fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove `{}`", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prune_backups() {
+        let tmpdir = assert_fs::TempDir::new().unwrap();
+        for name in [
+            "provenance-20250101T000000Z.db",
+            "provenance-20250102T000000Z.db",
+            "provenance-20250103T000000Z.db",
+            "notes.txt",
+        ] {
+            std::fs::write(tmpdir.path().join(name), "").unwrap();
+        }
+        prune_backups(tmpdir.path(), 2).unwrap();
+        let mut names: Vec<String> = std::fs::read_dir(tmpdir.path())
+            .unwrap()
+            .map(|v| v.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "notes.txt",
+                "provenance-20250102T000000Z.db",
+                "provenance-20250103T000000Z.db"
+            ]
+        );
+    }
+}
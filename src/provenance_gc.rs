@@ -0,0 +1,75 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::args::Args;
+use crate::provenance::{GcReport, ProvenanceDb};
+use crate::provenance_store;
+use crate::trash::Trash;
+
+/// Folders whose files the provenance database may be about: the serve path,
+/// the mounts and the recycle bin. Missing ones are left out, so an unmounted
+/// volume doesn't look like everything on it was deleted.
+pub fn roots(args: &Args) -> Vec<PathBuf> {
+    std::iter::once(&args.serve_path)
+        .chain(args.mounts.values())
+        .chain(args.trash_dir.as_ref())
+        .filter(|v| v.is_dir())
+        .cloned()
+        .collect()
+}
+
+/// Find the rows about files under `roots` that no longer exist, and remove
+/// them unless `dry_run`. Files in the recycle bin still exist at their
+/// original path as far as their annotations go, so a restore brings them back.
+pub async fn collect(
+    db: &ProvenanceDb,
+    roots: Vec<PathBuf>,
+    trash: Option<&Trash>,
+    dry_run: bool,
+) -> Result<GcReport> {
+    let mut trashed = HashSet::new();
+    if let Some(trash) = trash {
+        for item in trash.list().await? {
+            trashed.insert(item.original_path);
+        }
+    }
+    db.run(move |db| {
+        let is_orphan = |path: &str| {
+            let path = Path::new(path);
+            roots.iter().any(|root| path.starts_with(root))
+                && !path.ancestors().any(|v| trashed.contains(v))
+                && std::fs::symlink_metadata(path).is_err()
+        };
+        db.collect_garbage(is_orphan, dry_run)
+    })
+    .await
+}
+
+/// Run `node-drive gc` against the database the server would use
+pub async fn run(args: &Args, dry_run: bool) -> Result<()> {
+    let mut db = ProvenanceDb::new(args.provenance_db_path(), &args.provenance_db_encryption)?;
+    if let Some(url) = args.provenance_db_url() {
+        db = db.with_chain(provenance_store::connect(url)?);
+    }
+    let trash = args.trash_dir.as_deref().map(Trash::new).transpose()?;
+    let report = collect(&db, roots(args), trash.as_ref(), dry_run).await?;
+
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    if report.rows.is_empty() {
+        println!("No orphaned rows found");
+    }
+    for (table, rows) in &report.rows {
+        match table.as_str() {
+            "artifacts" => println!(
+                "{verb} {rows} rows from artifacts, with {} events",
+                report.events
+            ),
+            _ => println!("{verb} {rows} rows from {table}"),
+        }
+    }
+    if !dry_run {
+        println!("Reclaimed {} bytes", report.reclaimed_bytes);
+    }
+    Ok(())
+}
@@ -0,0 +1,256 @@
+use anyhow::{bail, Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+
+/// One step of the provenance database schema
+pub struct Migration {
+    pub name: &'static str,
+    pub up: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+/// Every schema change in order, the version of a database is the number of
+/// migrations applied to it. Released migrations must never change, append a
+/// new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        name: "initial schema",
+        up: |tx| tx.execute_batch(include_str!("migrations/001_initial.sql")),
+    },
+    Migration {
+        name: "artifact columns added before versioning",
+        up: |tx| {
+            add_missing_columns(
+                tx,
+                "artifacts",
+                &[
+                    ("verified_chain", "TEXT"),
+                    ("verified_timestamp", "INTEGER"),
+                    ("verified_height", "INTEGER"),
+                    ("last_check_at", "TEXT"),
+                    (
+                        "visibility",
+                        "TEXT NOT NULL DEFAULT 'private' CHECK(visibility IN ('private', 'public'))",
+                    ),
+                ],
+            )
+        },
+    },
+    Migration {
+        name: "stamping queue",
+        up: |tx| tx.execute_batch(include_str!("migrations/003_ots_queue.sql")),
+    },
+    Migration {
+        name: "attestations",
+        up: |tx| tx.execute_batch(include_str!("migrations/004_attestations.sql")),
+    },
+    Migration {
+        name: "copy events",
+        up: |tx| tx.execute_batch(include_str!("migrations/005_copy_events.sql")),
+    },
+    Migration {
+        name: "share limits",
+        up: |tx| {
+            add_missing_columns(
+                tx,
+                "shares",
+                &[("expires_at", "INTEGER"), ("max_downloads", "INTEGER")],
+            )
+        },
+    },
+    Migration {
+        name: "share passwords",
+        up: |tx| add_missing_columns(tx, "shares", &[("password_hash", "TEXT")]),
+    },
+    Migration {
+        name: "verification log",
+        up: |tx| tx.execute_batch(include_str!("migrations/008_verification_log.sql")),
+    },
+    Migration {
+        name: "provenance anchors",
+        up: |tx| tx.execute_batch(include_str!("migrations/009_provenance_anchors.sql")),
+    },
+    Migration {
+        name: "integrity checks",
+        up: |tx| tx.execute_batch(include_str!("migrations/010_integrity_checks.sql")),
+    },
+    Migration {
+        name: "artifact encryption",
+        up: |tx| add_missing_columns(tx, "artifacts", &[("encryption", "TEXT")]),
+    },
+    Migration {
+        name: "auth log",
+        up: |tx| tx.execute_batch(include_str!("migrations/012_auth_log.sql")),
+    },
+];
+
+/// Bring the schema of `conn` up to date, one transaction per migration
+///
+/// Returns the schema version of the database.
+pub fn migrate(conn: &mut Connection) -> Result<usize> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    let current = schema_version(conn)?;
+    if current > MIGRATIONS.len() {
+        bail!(
+            "Provenance database schema version {current} is newer than this build supports ({}), upgrade node-drive",
+            MIGRATIONS.len()
+        );
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+        let version = i + 1;
+        let tx = conn.transaction()?;
+        (migration.up)(&tx).with_context(|| {
+            format!(
+                "Failed to migrate provenance database to version {version} ({})",
+                migration.name
+            )
+        })?;
+        tx.execute(
+            "INSERT INTO schema_version (version, name, applied_at) VALUES (?1, ?2, ?3)",
+            params![version, migration.name, chrono::Utc::now().to_rfc3339()],
+        )?;
+        tx.commit()?;
+        if current > 0 {
+            info!(
+                "Migrated provenance database to version {version} ({})",
+                migration.name
+            );
+        }
+    }
+    Ok(MIGRATIONS.len())
+}
+
+/// Number of migrations applied to the database, 0 for a new or pre-versioning one
+pub fn schema_version(conn: &Connection) -> Result<usize> {
+    let version: Option<usize> = conn
+        .query_row("SELECT MAX(version) FROM schema_version", [], |row| {
+            row.get(0)
+        })
+        .optional()?
+        .flatten();
+    Ok(version.unwrap_or_default())
+}
+
+/// Add the `columns` that `table` lacks, for tables whose `CREATE TABLE IF NOT
+/// EXISTS` gained columns over time
+fn add_missing_columns(
+    tx: &Transaction,
+    table: &str,
+    columns: &[(&str, &str)],
+) -> rusqlite::Result<()> {
+    let existing = tx
+        .prepare(&format!("SELECT name FROM pragma_table_info('{table}')"))?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (name, definition) in columns {
+        if !existing.iter().any(|v| v == name) {
+            tx.execute(
+                &format!("ALTER TABLE {table} ADD COLUMN {name} {definition}"),
+                [],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_pre_versioning_db() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        conn.execute_batch(
+            "CREATE TABLE artifacts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                file_path TEXT NOT NULL UNIQUE,
+                sha256_hex TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            );
+            INSERT INTO artifacts (file_path, sha256_hex, created_at) VALUES ('/a.txt', 'abc', 'now');",
+        )?;
+
+        assert_eq!(migrate(&mut conn)?, MIGRATIONS.len());
+        assert_eq!(schema_version(&conn)?, MIGRATIONS.len());
+        let visibility: String = conn.query_row(
+            "SELECT visibility FROM artifacts WHERE file_path = '/a.txt'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(visibility, "private");
+
+        // Applying again is a no-op
+        assert_eq!(migrate(&mut conn)?, MIGRATIONS.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_copy_events() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        conn.execute("PRAGMA foreign_keys = ON", [])?;
+        migrate(&mut conn)?;
+        conn.execute("DELETE FROM schema_version WHERE version >= 5", [])?;
+        // Back to the tables as they were before copy events
+        conn.execute_batch(
+            "DROP TABLE event_actors;
+            DROP TABLE event_signatures;
+            DROP TABLE events;",
+        )?;
+        conn.execute_batch(include_str!("migrations/001_initial.sql"))?;
+        conn.execute_batch(
+            "INSERT INTO artifacts (file_path, sha256_hex, created_at) VALUES ('/a.txt', 'abc', 'now');
+            INSERT INTO artifacts (file_path, sha256_hex, created_at) VALUES ('/b.txt', 'abc', 'now');
+            INSERT INTO events (artifact_id, index_num, action, artifact_sha256_hex, issued_at, event_hash_hex, ots_proof_b64)
+            VALUES (1, 0, 'mint', 'abc', 'now', 'def', '');
+            INSERT INTO event_actors (event_id, role, pubkey_hex) VALUES (1, 'creator', '02aa');
+            INSERT INTO event_signatures (event_id, role, signature_hex) VALUES (1, 'creator', '3044');",
+        )?;
+
+        assert_eq!(migrate(&mut conn)?, MIGRATIONS.len());
+        let actors: i64 =
+            conn.query_row("SELECT COUNT(*) FROM event_actors", [], |row| row.get(0))?;
+        let signatures: i64 =
+            conn.query_row("SELECT COUNT(*) FROM event_signatures", [], |row| {
+                row.get(0)
+            })?;
+        assert_eq!((actors, signatures), (1, 1));
+
+        // A copy repeats the events of its source
+        conn.execute_batch(
+            "INSERT INTO events (artifact_id, index_num, action, artifact_sha256_hex, issued_at, event_hash_hex, ots_proof_b64)
+            VALUES (2, 0, 'mint', 'abc', 'now', 'def', '');
+            INSERT INTO events (artifact_id, index_num, action, artifact_sha256_hex, issued_at, event_hash_hex, ots_proof_b64)
+            VALUES (2, 1, 'copy', 'abc', 'now', 'ghi', '');",
+        )?;
+        let result = conn.execute(
+            "INSERT INTO events (artifact_id, index_num, action, artifact_sha256_hex, issued_at, event_hash_hex, ots_proof_b64)
+            VALUES (2, 2, 'delete', 'abc', 'now', 'jkl', '')",
+            [],
+        );
+        assert!(result.is_err());
+
+        // Actors still go with their event
+        conn.execute("DELETE FROM artifacts WHERE id = 1", [])?;
+        let actors: i64 =
+            conn.query_row("SELECT COUNT(*) FROM event_actors", [], |row| row.get(0))?;
+        assert_eq!(actors, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_refuse_newer_db() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        migrate(&mut conn)?;
+        conn.execute(
+            "INSERT INTO schema_version (version, name, applied_at) VALUES (?1, 'future', 'now')",
+            params![MIGRATIONS.len() + 1],
+        )?;
+        assert!(migrate(&mut conn).is_err());
+        Ok(())
+    }
+}
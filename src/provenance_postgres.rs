@@ -0,0 +1,537 @@
+use anyhow::{bail, Result};
+use postgres::{Client, GenericClient, NoTls, Row};
+use std::sync::Mutex;
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+use crate::provenance::{
+    Actors, Artifact, Attestation, Encryption, Event, EventAction, InsertEventArgs,
+    SignatureAlgorithm, Signatures,
+};
+use crate::provenance_store::ProvenanceStore;
+
+/// Schema changes of the Postgres chain, in order, see `provenance_migrations`
+const MIGRATIONS: &[&str] = &[
+    include_str!("migrations/postgres/001_initial.sql"),
+    include_str!("migrations/postgres/002_attestations.sql"),
+    include_str!("migrations/postgres/003_copy_events.sql"),
+    include_str!("migrations/postgres/004_artifact_encryption.sql"),
+];
+
+/// The provenance chain kept in a Postgres database, so instances behind a
+/// load balancer extend the same chain
+pub struct PostgresStore {
+    // Only taken out when dropping
+    client: Mutex<Option<Client>>,
+}
+
+impl PostgresStore {
+    pub fn connect(url: &str) -> Result<Self> {
+        let mut client = blocking(|| Client::connect(url, NoTls))?;
+        blocking(|| migrate(&mut client))?;
+        Ok(Self {
+            client: Mutex::new(Some(client)),
+        })
+    }
+
+    fn with_client<T>(&self, f: impl FnOnce(&mut Client) -> Result<T>) -> Result<T> {
+        blocking(|| {
+            let mut client = self.client.lock().unwrap();
+            f(client
+                .as_mut()
+                .expect("Postgres client is only taken on drop"))
+        })
+    }
+}
+
+impl Drop for PostgresStore {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.get_mut().unwrap().take() {
+            blocking(|| drop(client));
+        }
+    }
+}
+
+/// Run `f`, which blocks on the client's own runtime, without stalling the
+/// worker thread of the server's runtime
+fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+fn migrate(client: &mut Client) -> Result<()> {
+    client.batch_execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            applied_at TEXT NOT NULL
+        )",
+    )?;
+    // Instances starting together take turns
+    let mut tx = client.transaction()?;
+    tx.batch_execute("LOCK TABLE schema_version IN EXCLUSIVE MODE")?;
+    let current: i32 = tx
+        .query_one("SELECT COALESCE(MAX(version), 0) FROM schema_version", &[])?
+        .get(0);
+    let current = current as usize;
+    if current > MIGRATIONS.len() {
+        bail!(
+            "Postgres provenance schema version {current} is newer than this build supports ({}), upgrade node-drive",
+            MIGRATIONS.len()
+        );
+    }
+    for (i, sql) in MIGRATIONS.iter().enumerate().skip(current) {
+        tx.batch_execute(sql)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, applied_at) VALUES ($1, $2)",
+            &[&(i as i32 + 1), &chrono::Utc::now().to_rfc3339()],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+impl ProvenanceStore for PostgresStore {
+    fn upsert_artifact(&self, file_path: &str, sha256_hex: &str) -> Result<i64> {
+        self.with_client(|client| upsert_artifact(client, file_path, sha256_hex))
+    }
+
+    fn get_artifact_by_path(&self, file_path: &str) -> Result<Option<(i64, Artifact)>> {
+        self.with_client(|client| {
+            let row = client.query_opt(
+                "SELECT id, sha256_hex, verified_chain, verified_timestamp, verified_height, visibility,
+                        encryption
+                 FROM artifacts WHERE file_path = $1",
+                &[&file_path],
+            )?;
+            Ok(row.map(|row| {
+                let verified_height: Option<i64> = row.get(4);
+                let encryption: Option<String> = row.get(6);
+                let artifact = Artifact {
+                    sha256_hex: row.get(1),
+                    verified_chain: row.get(2),
+                    verified_timestamp: row.get(3),
+                    verified_height: verified_height.map(|h| h as u64),
+                    visibility: row.get(5),
+                    encryption: encryption.and_then(|v| serde_json::from_str(&v).ok()),
+                };
+                (row.get(0), artifact)
+            }))
+        })
+    }
+
+    fn move_artifacts(&self, old_path: &str, new_path: &str) -> Result<bool> {
+        let (lower, upper) = crate::provenance::subtree_bounds(old_path);
+        let start = old_path.chars().count() as i32 + 1;
+        self.with_client(|client| {
+            // Records left behind at the destination keep their artifact
+            let rows_affected = client.execute(
+                "UPDATE artifacts AS a SET file_path = $1 || substr(a.file_path, $2)
+                 WHERE (a.file_path = $3 OR (a.file_path >= $4 AND a.file_path < $5))
+                 AND NOT EXISTS (
+                     SELECT 1 FROM artifacts AS b WHERE b.file_path = $1 || substr(a.file_path, $2)
+                 )",
+                &[&new_path, &start, &old_path, &lower, &upper],
+            )?;
+            Ok(rows_affected > 0)
+        })
+    }
+
+    fn set_artifact_visibility(&self, file_path: &str, visibility: &str) -> Result<()> {
+        self.with_client(|client| {
+            client.execute(
+                "UPDATE artifacts SET visibility = $1 WHERE file_path = $2",
+                &[&visibility, &file_path],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn set_artifact_encryption(
+        &self,
+        file_path: &str,
+        encryption: Option<&Encryption>,
+    ) -> Result<()> {
+        let encryption = encryption.map(serde_json::to_string).transpose()?;
+        self.with_client(|client| {
+            client.execute(
+                "UPDATE artifacts SET encryption = $1 WHERE file_path = $2",
+                &[&encryption, &file_path],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn insert_event(&self, args: InsertEventArgs) -> Result<i64> {
+        self.with_client(|client| {
+            let mut tx = client.transaction()?;
+            let event_id = insert_event(&mut tx, args)?;
+            tx.commit()?;
+            Ok(event_id)
+        })
+    }
+
+    fn import_events(&self, file_path: &str, sha256_hex: &str, events: &[Event]) -> Result<bool> {
+        self.with_client(|client| {
+            let mut tx = client.transaction()?;
+            let artifact_id = upsert_artifact(&mut tx, file_path, sha256_hex)?;
+            let existing: i64 = tx
+                .query_one(
+                    "SELECT COUNT(*) FROM events WHERE artifact_id = $1",
+                    &[&artifact_id],
+                )?
+                .get(0);
+            if existing > 0 {
+                return Ok(false);
+            }
+            for event in events {
+                insert_event(
+                    &mut tx,
+                    InsertEventArgs {
+                        artifact_id,
+                        index: event.index,
+                        action: &event.action,
+                        artifact_sha256_hex: &event.artifact_sha256_hex,
+                        prev_event_hash_hex: event.prev_event_hash_hex.as_deref(),
+                        issued_at: &event.issued_at,
+                        event_hash_hex: &event.event_hash_hex,
+                        ots_proof_b64: &event.ots_proof_b64,
+                        actors: &event.actors,
+                        signatures: &event.signatures,
+                    },
+                )?;
+            }
+            tx.commit()?;
+            Ok(true)
+        })
+    }
+
+    fn get_events(&self, artifact_id: i64) -> Result<Vec<Event>> {
+        self.with_client(|client| {
+            let rows = client.query(
+                "SELECT id, index_num, action, artifact_sha256_hex, prev_event_hash_hex, issued_at, event_hash_hex, ots_proof_b64
+                 FROM events
+                 WHERE artifact_id = $1
+                 ORDER BY index_num ASC",
+                &[&artifact_id],
+            )?;
+            let mut events = Vec::with_capacity(rows.len());
+            for row in rows {
+                let event_id: i64 = row.get(0);
+                let action = match row.get::<_, &str>(2) {
+                    "mint" => EventAction::Mint,
+                    "transfer" => EventAction::Transfer,
+                    "copy" => EventAction::Copy,
+                    _ => continue,
+                };
+                let mut actors = Actors {
+                    creator_pubkey_hex: None,
+                    prev_owner_pubkey_hex: None,
+                    new_owner_pubkey_hex: None,
+                };
+                for actor in client.query(
+                    "SELECT role, pubkey_hex FROM event_actors WHERE event_id = $1",
+                    &[&event_id],
+                )? {
+                    let pubkey = Some(actor.get(1));
+                    match actor.get::<_, &str>(0) {
+                        "creator" => actors.creator_pubkey_hex = pubkey,
+                        "prev_owner" => actors.prev_owner_pubkey_hex = pubkey,
+                        "new_owner" => actors.new_owner_pubkey_hex = pubkey,
+                        _ => {}
+                    }
+                }
+                let mut signatures = Signatures {
+                    creator_sig_hex: None,
+                    prev_owner_sig_hex: None,
+                    new_owner_sig_hex: None,
+                };
+                for signature in client.query(
+                    "SELECT role, signature_hex FROM event_signatures WHERE event_id = $1",
+                    &[&event_id],
+                )? {
+                    let signature_hex = Some(signature.get(1));
+                    match signature.get::<_, &str>(0) {
+                        "creator" => signatures.creator_sig_hex = signature_hex,
+                        "prev_owner" => signatures.prev_owner_sig_hex = signature_hex,
+                        "new_owner" => signatures.new_owner_sig_hex = signature_hex,
+                        _ => {}
+                    }
+                }
+                events.push(event_from_row(&row, action, actors, signatures));
+            }
+            Ok(events)
+        })
+    }
+
+    fn has_event(&self, event_hash_hex: &str) -> Result<bool> {
+        self.with_client(|client| {
+            let row = client.query_opt(
+                "SELECT 1 FROM events WHERE event_hash_hex = $1 LIMIT 1",
+                &[&event_hash_hex],
+            )?;
+            Ok(row.is_some())
+        })
+    }
+
+    fn event_hashes(&self) -> Result<Vec<String>> {
+        self.with_client(|client| {
+            let rows = client.query("SELECT event_hash_hex FROM events ORDER BY id", &[])?;
+            Ok(rows.iter().map(|row| row.get(0)).collect())
+        })
+    }
+
+    fn get_next_event_index(&self, artifact_id: i64) -> Result<u32> {
+        self.with_client(|client| {
+            let max_index: Option<i32> = client
+                .query_one(
+                    "SELECT MAX(index_num) FROM events WHERE artifact_id = $1",
+                    &[&artifact_id],
+                )?
+                .get(0);
+            Ok(max_index.map(|i| i as u32 + 1).unwrap_or(0))
+        })
+    }
+
+    fn update_ots_proof(
+        &self,
+        artifact_id: i64,
+        event_index: u32,
+        ots_proof_b64: &str,
+    ) -> Result<()> {
+        self.with_client(|client| {
+            client.execute(
+                "UPDATE events SET ots_proof_b64 = $1 WHERE artifact_id = $2 AND index_num = $3",
+                &[&ots_proof_b64, &artifact_id, &(event_index as i32)],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn update_verification_result(
+        &self,
+        artifact_id: i64,
+        chain: &str,
+        timestamp: i64,
+        height: u64,
+    ) -> Result<()> {
+        self.with_client(|client| {
+            update_verification_result(client, artifact_id, chain, timestamp, height)
+        })
+    }
+
+    fn update_last_check_at(&self, artifact_id: i64) -> Result<()> {
+        self.with_client(|client| {
+            client.execute(
+                "UPDATE artifacts SET last_check_at = $1 WHERE id = $2",
+                &[&chrono::Utc::now().to_rfc3339(), &artifact_id],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn unconfirmed_artifacts(
+        &self,
+        checked_before: &str,
+        limit: usize,
+    ) -> Result<Vec<(i64, String)>> {
+        self.with_client(|client| {
+            let rows = client.query(
+                "SELECT id, file_path FROM artifacts
+                 WHERE verified_chain IS NULL AND (last_check_at IS NULL OR last_check_at < $1)
+                 ORDER BY id LIMIT $2",
+                &[&checked_before, &(limit as i64)],
+            )?;
+            Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+        })
+    }
+
+    fn update_ots_proof_and_verification(
+        &self,
+        artifact_id: i64,
+        event_index: u32,
+        ots_proof_b64: &str,
+        chain: &str,
+        timestamp: i64,
+        height: u64,
+    ) -> Result<()> {
+        self.with_client(|client| {
+            let mut tx = client.transaction()?;
+            tx.execute(
+                "UPDATE events SET ots_proof_b64 = $1 WHERE artifact_id = $2 AND index_num = $3",
+                &[&ots_proof_b64, &artifact_id, &(event_index as i32)],
+            )?;
+            update_verification_result(&mut tx, artifact_id, chain, timestamp, height)?;
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    fn add_creator_signature(
+        &self,
+        artifact_id: i64,
+        event_index: u32,
+        signature_hex: &str,
+    ) -> Result<()> {
+        self.with_client(|client| {
+            client.execute(
+                "INSERT INTO event_signatures (event_id, role, signature_hex)
+                 SELECT id, 'creator', $3 FROM events WHERE artifact_id = $1 AND index_num = $2",
+                &[&artifact_id, &(event_index as i32), &signature_hex],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn add_attestation(&self, artifact_id: i64, attestation: &Attestation) -> Result<()> {
+        self.with_client(|client| {
+            client.execute(
+                "INSERT INTO attestations
+                 (artifact_id, artifact_sha256_hex, algorithm, pubkey_hex, signature_hex, signed_at)
+                 VALUES ($1, $2, $3, $4, $5, $6)
+                 ON CONFLICT (artifact_id, artifact_sha256_hex, pubkey_hex) DO UPDATE
+                 SET algorithm = excluded.algorithm, signature_hex = excluded.signature_hex,
+                     signed_at = excluded.signed_at",
+                &[
+                    &artifact_id,
+                    &attestation.artifact_sha256_hex,
+                    &attestation.algorithm.as_str(),
+                    &attestation.pubkey_hex,
+                    &attestation.signature_hex,
+                    &attestation.signed_at,
+                ],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn get_attestations(&self, artifact_id: i64, sha256_hex: &str) -> Result<Vec<Attestation>> {
+        self.with_client(|client| {
+            let rows = client.query(
+                "SELECT algorithm, pubkey_hex, signature_hex, artifact_sha256_hex, signed_at
+                 FROM attestations WHERE artifact_id = $1 AND artifact_sha256_hex = $2
+                 ORDER BY id",
+                &[&artifact_id, &sha256_hex],
+            )?;
+            Ok(rows
+                .iter()
+                .map(|row| Attestation {
+                    algorithm: SignatureAlgorithm::parse(row.get(0)).unwrap_or_default(),
+                    pubkey_hex: row.get(1),
+                    signature_hex: row.get(2),
+                    artifact_sha256_hex: row.get(3),
+                    signed_at: row.get(4),
+                })
+                .collect())
+        })
+    }
+}
+
+fn upsert_artifact(
+    client: &mut impl GenericClient,
+    file_path: &str,
+    sha256_hex: &str,
+) -> Result<i64> {
+    let row = client.query_one(
+        "INSERT INTO artifacts (file_path, sha256_hex, created_at)
+         VALUES ($1, $2, $3)
+         ON CONFLICT (file_path) DO UPDATE SET sha256_hex = excluded.sha256_hex
+         RETURNING id",
+        &[&file_path, &sha256_hex, &chrono::Utc::now().to_rfc3339()],
+    )?;
+    Ok(row.get(0))
+}
+
+fn update_verification_result(
+    client: &mut impl GenericClient,
+    artifact_id: i64,
+    chain: &str,
+    timestamp: i64,
+    height: u64,
+) -> Result<()> {
+    client.execute(
+        "UPDATE artifacts
+         SET verified_chain = $1, verified_timestamp = $2, verified_height = $3, last_check_at = $4
+         WHERE id = $5",
+        &[
+            &chain,
+            &timestamp,
+            &(height as i64),
+            &chrono::Utc::now().to_rfc3339(),
+            &artifact_id,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Insert an event and its actors and signatures, within a transaction
+fn insert_event(client: &mut impl GenericClient, args: InsertEventArgs) -> Result<i64> {
+    let action = match args.action {
+        EventAction::Mint => "mint",
+        EventAction::Transfer => "transfer",
+        EventAction::Copy => "copy",
+    };
+    let event_id: i64 = client
+        .query_one(
+            "INSERT INTO events (artifact_id, index_num, action, artifact_sha256_hex, prev_event_hash_hex, issued_at, event_hash_hex, ots_proof_b64)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             RETURNING id",
+            &[
+                &args.artifact_id,
+                &(args.index as i32),
+                &action,
+                &args.artifact_sha256_hex,
+                &args.prev_event_hash_hex,
+                &args.issued_at,
+                &args.event_hash_hex,
+                &args.ots_proof_b64,
+            ],
+        )?
+        .get(0);
+
+    let actors = [
+        ("creator", &args.actors.creator_pubkey_hex),
+        ("prev_owner", &args.actors.prev_owner_pubkey_hex),
+        ("new_owner", &args.actors.new_owner_pubkey_hex),
+    ];
+    for (role, pubkey_hex) in actors {
+        if let Some(pubkey_hex) = pubkey_hex {
+            client.execute(
+                "INSERT INTO event_actors (event_id, role, pubkey_hex) VALUES ($1, $2, $3)",
+                &[&event_id, &role, pubkey_hex],
+            )?;
+        }
+    }
+    let signatures = [
+        ("creator", &args.signatures.creator_sig_hex),
+        ("prev_owner", &args.signatures.prev_owner_sig_hex),
+        ("new_owner", &args.signatures.new_owner_sig_hex),
+    ];
+    for (role, signature_hex) in signatures {
+        if let Some(signature_hex) = signature_hex {
+            client.execute(
+                "INSERT INTO event_signatures (event_id, role, signature_hex) VALUES ($1, $2, $3)",
+                &[&event_id, &role, signature_hex],
+            )?;
+        }
+    }
+    Ok(event_id)
+}
+
+fn event_from_row(row: &Row, action: EventAction, actors: Actors, signatures: Signatures) -> Event {
+    let index: i32 = row.get(1);
+    Event {
+        event_type: "provenance.event/v1".to_string(),
+        index: index as u32,
+        action,
+        artifact_sha256_hex: row.get(3),
+        prev_event_hash_hex: row.get(4),
+        actors,
+        issued_at: row.get(5),
+        event_hash_hex: row.get(6),
+        signatures,
+        ots_proof_b64: row.get(7),
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::Result;
+use std::sync::Arc;
+
+use crate::provenance::{Artifact, Attestation, Encryption, Event, InsertEventArgs};
+
+/// Where the provenance chain lives: artifacts and their events, actors and
+/// signatures
+///
+/// The chain is kept in the SQLite database next to the rest of the
+/// provenance data, unless `--provenance-db` names a Postgres database that
+/// several instances share.
+pub trait ProvenanceStore: Send + Sync {
+    /// Insert or update artifact by file path
+    fn upsert_artifact(&self, file_path: &str, sha256_hex: &str) -> Result<i64>;
+
+    /// Get artifact by file path
+    fn get_artifact_by_path(&self, file_path: &str) -> Result<Option<(i64, Artifact)>>;
+
+    /// Move the artifacts at and below `old_path` to `new_path`, leaving the
+    /// ones whose destination already has an artifact
+    fn move_artifacts(&self, old_path: &str, new_path: &str) -> Result<bool>;
+
+    /// Set the visibility ("private" or "public") of the artifact at `file_path`
+    fn set_artifact_visibility(&self, file_path: &str, visibility: &str) -> Result<()>;
+
+    /// Record how the uploader encrypted the artifact at `file_path`, None for plaintext
+    fn set_artifact_encryption(
+        &self,
+        file_path: &str,
+        encryption: Option<&Encryption>,
+    ) -> Result<()>;
+
+    /// Insert a new provenance event
+    fn insert_event(&self, args: InsertEventArgs) -> Result<i64>;
+
+    /// Record the events of a bundle from another instance for `file_path`,
+    /// returning false if the path already has provenance events
+    fn import_events(&self, file_path: &str, sha256_hex: &str, events: &[Event]) -> Result<bool>;
+
+    /// Get all events for an artifact, ordered by index
+    fn get_events(&self, artifact_id: i64) -> Result<Vec<Event>>;
+
+    /// Whether an event with this hash is recorded on any artifact
+    fn has_event(&self, event_hash_hex: &str) -> Result<bool>;
+
+    /// Hashes of every recorded event, in the order they were recorded
+    fn event_hashes(&self) -> Result<Vec<String>>;
+
+    /// Get the next event index for an artifact
+    fn get_next_event_index(&self, artifact_id: i64) -> Result<u32>;
+
+    /// Update the OTS proof for a specific event
+    fn update_ots_proof(
+        &self,
+        artifact_id: i64,
+        event_index: u32,
+        ots_proof_b64: &str,
+    ) -> Result<()>;
+
+    /// Update verification results for an artifact
+    fn update_verification_result(
+        &self,
+        artifact_id: i64,
+        chain: &str,
+        timestamp: i64,
+        height: u64,
+    ) -> Result<()>;
+
+    /// Update last_check_at timestamp for an artifact (to throttle timestamp checking)
+    fn update_last_check_at(&self, artifact_id: i64) -> Result<()>;
+
+    /// Artifacts not confirmed on a chain yet and not checked since
+    /// `checked_before` (RFC 3339), as ids and paths
+    fn unconfirmed_artifacts(
+        &self,
+        checked_before: &str,
+        limit: usize,
+    ) -> Result<Vec<(i64, String)>>;
+
+    /// Update both OTS proof and verification results in a single transaction
+    fn update_ots_proof_and_verification(
+        &self,
+        artifact_id: i64,
+        event_index: u32,
+        ots_proof_b64: &str,
+        chain: &str,
+        timestamp: i64,
+        height: u64,
+    ) -> Result<()>;
+
+    /// Attach the creator's signature to an event that was recorded without one
+    fn add_creator_signature(
+        &self,
+        artifact_id: i64,
+        event_index: u32,
+        signature_hex: &str,
+    ) -> Result<()>;
+
+    /// Record a detached signature over an artifact, replacing an earlier one
+    /// by the same key over the same content
+    fn add_attestation(&self, artifact_id: i64, attestation: &Attestation) -> Result<()>;
+
+    /// Get the attestations of an artifact over the content `sha256_hex`
+    fn get_attestations(&self, artifact_id: i64, sha256_hex: &str) -> Result<Vec<Attestation>>;
+}
+
+/// Connect to the Postgres database at `url` and bring its schema up to date
+#[cfg(feature = "postgres")]
+pub fn connect(url: &str) -> Result<Arc<dyn ProvenanceStore>> {
+    Ok(Arc::new(
+        crate::provenance_postgres::PostgresStore::connect(url)?,
+    ))
+}
+
+#[cfg(not(feature = "postgres"))]
+pub fn connect(_url: &str) -> Result<Arc<dyn ProvenanceStore>> {
+    anyhow::bail!("A Postgres provenance database requires building with the `postgres` feature")
+}
@@ -13,7 +13,8 @@ pub async fn get_artifact_by_path(
         .to_str()
         .ok_or_else(|| anyhow::anyhow!("Invalid UTF-8 in path"))?;
 
-    match db.get_artifact_by_path(path_str)? {
+    let path_str = path_str.to_string();
+    match db.run(move |db| db.get_artifact_by_path(&path_str)).await? {
         Some((artifact_id, artifact)) => {
             let sha256_hex = artifact.sha256_hex.clone();
             Ok(Some((artifact_id, artifact, sha256_hex)))
@@ -28,5 +29,6 @@ pub async fn get_manifest_for_file(db: &ProvenanceDb, path: &Path) -> Result<Opt
     let path_str = path
         .to_str()
         .ok_or_else(|| anyhow::anyhow!("Invalid UTF-8 in path"))?;
-    db.get_manifest_by_path(path_str)
+    let path_str = path_str.to_string();
+    db.run(move |db| db.get_manifest_by_path(&path_str)).await
 }
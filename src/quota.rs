@@ -0,0 +1,168 @@
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use std::path::Path;
+use std::str::FromStr;
+use walkdir::WalkDir;
+
+use crate::utils::parse_size;
+
+/// Who a quota limits: the files under a directory, or the files a user uploaded
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuotaScope {
+    /// Directory relative to the serve root, without surrounding slashes
+    Path(String),
+    User(String),
+}
+
+/// A storage limit written as `<path>:<size>` or `@<user>:<size>`,
+/// e.g. `/dir1:10GB` or `@alice:500M`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotaRule {
+    pub scope: QuotaScope,
+    pub limit: u64,
+}
+
+impl FromStr for QuotaRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (target, size) = s.rsplit_once(':').ok_or_else(|| {
+            anyhow!("Invalid quota `{s}`, expected <path>:<size> or @<user>:<size>")
+        })?;
+        let limit = parse_size(size)
+            .ok_or_else(|| anyhow!("Invalid quota size `{size}`, e.g. 500M or 10GB"))?;
+        let scope = match target.strip_prefix('@') {
+            Some("") => bail!("Invalid quota `{s}`, missing user"),
+            Some(user) => QuotaScope::User(user.to_string()),
+            None => QuotaScope::Path(target.trim_matches('/').to_string()),
+        };
+        Ok(Self { scope, limit })
+    }
+}
+
+impl QuotaRule {
+    fn covers(&self, relative_path: &str) -> bool {
+        let QuotaScope::Path(path) = &self.scope else {
+            return false;
+        };
+        path.is_empty()
+            || relative_path == path
+            || relative_path
+                .strip_prefix(path.as_str())
+                .map(|v| v.starts_with('/'))
+                .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QuotaPolicy {
+    rules: Vec<QuotaRule>,
+}
+
+impl QuotaPolicy {
+    pub fn new(rules: &[&str]) -> Result<Self> {
+        let mut parsed: Vec<QuotaRule> = vec![];
+        for rule in rules {
+            let rule: QuotaRule = rule.parse()?;
+            if parsed.iter().any(|v| v.scope == rule.scope) {
+                bail!("Duplicate quota `{rule}`");
+            }
+            parsed.push(rule);
+        }
+        Ok(Self { rules: parsed })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Every path quota covering `relative_path`, parents before children.
+    /// All of them apply: a subdirectory's quota doesn't lift its parent's.
+    pub fn path_rules_for(&self, relative_path: &str) -> Vec<&QuotaRule> {
+        let relative_path = relative_path.trim_matches('/');
+        let mut rules: Vec<_> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.covers(relative_path))
+            .collect();
+        rules.sort_by_key(|rule| match &rule.scope {
+            QuotaScope::Path(path) => path.len(),
+            QuotaScope::User(_) => 0,
+        });
+        rules
+    }
+
+    pub fn user_rule(&self, user: &str) -> Option<&QuotaRule> {
+        self.rules
+            .iter()
+            .find(|rule| matches!(&rule.scope, QuotaScope::User(v) if v == user))
+    }
+}
+
+impl std::fmt::Display for QuotaRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.scope {
+            QuotaScope::Path(path) => write!(f, "/{path}"),
+            QuotaScope::User(user) => write!(f, "@{user}"),
+        }
+    }
+}
+
+/// A quota and how much of it is in use, as shown in directory listings
+#[derive(Debug, Clone, Serialize)]
+pub struct QuotaUsage {
+    /// `/<path>` or `@<user>`
+    pub scope: String,
+    pub limit: u64,
+    pub used: u64,
+}
+
+impl QuotaUsage {
+    pub fn new(rule: &QuotaRule, used: u64) -> Self {
+        Self {
+            scope: rule.to_string(),
+            limit: rule.limit,
+            used,
+        }
+    }
+
+    /// Whether `extra` more bytes would go over the limit
+    pub fn exceeded_by(&self, extra: u64) -> bool {
+        self.used.saturating_add(extra) > self.limit
+    }
+}
+
+/// Total size of the files under `path` (or of the file itself)
+pub fn disk_usage(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|v| v.ok())
+        .filter(|v| v.file_type().is_file())
+        .filter_map(|v| v.metadata().ok())
+        .map(|v| v.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_quotas() {
+        let policy = QuotaPolicy::new(&["/dir1:10GB", "/dir1/sub/:1M", "@alice:500K"]).unwrap();
+        let limits: Vec<_> = policy
+            .path_rules_for("dir1/sub/a.txt")
+            .iter()
+            .map(|v| v.limit)
+            .collect();
+        assert_eq!(limits, [10 << 30, 1 << 20]);
+        assert_eq!(policy.path_rules_for("/dir1/a.txt").len(), 1);
+        assert!(policy.path_rules_for("dir10/a.txt").is_empty());
+        assert_eq!(policy.user_rule("alice").unwrap().limit, 500 << 10);
+        assert!(policy.user_rule("bob").is_none());
+        assert!(QuotaPolicy::new(&["/dir1"]).is_err());
+        assert!(QuotaPolicy::new(&["/dir1:10X"]).is_err());
+        assert!(QuotaPolicy::new(&["@:1G"]).is_err());
+        assert!(QuotaPolicy::new(&["/a:1G", "a/:2G"]).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::Result;
+use clap::ArgMatches;
+use std::sync::{Arc, RwLock};
+
+use crate::args::{check_addrs, Args};
+use crate::server::Server;
+
+/// The server answering requests, replaced by one with the settings of the
+/// re-read `--config` file on reload. Requests in flight finish with the
+/// server they started on, and no connection is dropped.
+pub struct ReloadableServer {
+    matches: ArgMatches,
+    current: RwLock<Arc<Server>>,
+}
+
+impl ReloadableServer {
+    pub fn new(server: Server, matches: ArgMatches) -> Self {
+        Self {
+            matches,
+            current: RwLock::new(Arc::new(server)),
+        }
+    }
+
+    pub fn current(&self) -> Arc<Server> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Re-read the settings, keeping the current ones if they are invalid
+    pub fn reload(&self) -> Result<()> {
+        let mut args = Args::parse(self.matches.clone())?;
+        (args.addrs, _) = check_addrs(&args)?;
+        let server = self.current().reload(args)?;
+        *self.current.write().unwrap() = Arc::new(server);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    pub fn reload_on_sighup(self: Arc<Self>) -> Result<()> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = signal(SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                match self.reload() {
+                    Ok(()) => info!("Reloaded config"),
+                    Err(err) => error!("Failed to reload config, {err}"),
+                }
+            }
+        });
+        Ok(())
+    }
+}
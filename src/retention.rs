@@ -0,0 +1,215 @@
+use anyhow::{anyhow, bail, Result};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+use crate::provenance::ProvenanceDb;
+use crate::trash::{delete_path, Trash};
+
+/// How often the background sweeper looks for expired files.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Auto-delete files under `path` once they are older than `max_age`,
+/// written as `<path>:<age>`, e.g. `/incoming:30d`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionRule {
+    /// Directory relative to the serve root, without surrounding slashes
+    pub path: String,
+    pub max_age: Duration,
+}
+
+impl FromStr for RetentionRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, age) = s
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow!("Invalid retention rule `{s}`, expected <path>:<age>"))?;
+        let max_age = parse_age(age)
+            .ok_or_else(|| anyhow!("Invalid retention age `{age}`, e.g. 12h, 30d or 2w"))?;
+        Ok(Self {
+            path: path.trim_matches('/').to_string(),
+            max_age,
+        })
+    }
+}
+
+impl RetentionRule {
+    fn covers(&self, relative_path: &str) -> bool {
+        self.path.is_empty()
+            || relative_path == self.path
+            || relative_path
+                .strip_prefix(&self.path)
+                .map(|v| v.starts_with('/'))
+                .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionPolicy {
+    rules: Vec<RetentionRule>,
+}
+
+impl RetentionPolicy {
+    pub fn new(rules: &[&str]) -> Result<Self> {
+        let mut parsed: Vec<RetentionRule> = vec![];
+        for rule in rules {
+            let rule: RetentionRule = rule.parse()?;
+            if parsed.iter().any(|v| v.path == rule.path) {
+                bail!("Duplicate retention rule for `/{}`", rule.path);
+            }
+            parsed.push(rule);
+        }
+        Ok(Self { rules: parsed })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The most specific rule covering `relative_path`, so a rule on a
+    /// subdirectory overrides the one on its parent.
+    pub fn rule_for(&self, relative_path: &str) -> Option<&RetentionRule> {
+        let relative_path = relative_path.trim_matches('/');
+        self.rules
+            .iter()
+            .filter(|rule| rule.covers(relative_path))
+            .max_by_key(|rule| rule.path.len())
+    }
+
+    /// Delete expired files every `SWEEP_INTERVAL`, into `trash` when there is one
+    pub fn spawn_sweeper(
+        self,
+        serve_path: PathBuf,
+        trash: Option<Trash>,
+        provenance_db: ProvenanceDb,
+    ) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(SWEEP_INTERVAL);
+            loop {
+                ticker.tick().await;
+                match self
+                    .sweep(&serve_path, trash.as_ref(), &provenance_db)
+                    .await
+                {
+                    Ok(0) => {}
+                    Ok(count) => info!("Retention sweep removed {count} expired file(s)"),
+                    Err(err) => error!("Retention sweep failed, {err}"),
+                }
+            }
+        });
+    }
+
+    async fn sweep(
+        &self,
+        serve_path: &Path,
+        trash: Option<&Trash>,
+        provenance_db: &ProvenanceDb,
+    ) -> Result<usize> {
+        let policy = self.clone();
+        let root = serve_path.to_path_buf();
+        let expired =
+            tokio::task::spawn_blocking(move || policy.expired(&root, SystemTime::now())).await?;
+        let mut removed = 0;
+        for path in expired {
+            match delete_path(&path, false, None, trash, provenance_db).await {
+                Ok(()) => removed += 1,
+                Err(err) => warn!("Failed to remove expired `{}`, {err}", path.display()),
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Every file whose retention period has elapsed at `now`.
+    pub fn expired(&self, serve_path: &Path, now: SystemTime) -> Vec<PathBuf> {
+        let mut expired = vec![];
+        for rule in &self.rules {
+            let dir = serve_path.join(&rule.path);
+            if !dir.is_dir() {
+                continue;
+            }
+            for entry in WalkDir::new(&dir).into_iter().filter_map(|v| v.ok()) {
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let path = entry.path();
+                let Some(relative_path) = path
+                    .strip_prefix(serve_path)
+                    .ok()
+                    .and_then(|v| v.to_str())
+                    .map(|v| v.replace('\\', "/"))
+                else {
+                    continue;
+                };
+                // Nested rules are handled when their own directory is walked
+                if self.rule_for(&relative_path) != Some(rule) {
+                    continue;
+                }
+                let Some(mtime) = entry.metadata().ok().and_then(|v| v.modified().ok()) else {
+                    continue;
+                };
+                if mtime + rule.max_age <= now {
+                    expired.push(path.to_path_buf());
+                }
+            }
+        }
+        expired
+    }
+}
+
+/// Parse an age such as `90s`, `12h`, `30d` or `2w`
+pub fn parse_age(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit())?;
+    let (num, unit) = value.split_at(split);
+    let num: u64 = num.parse().ok()?;
+    let secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        "w" => 60 * 60 * 24 * 7,
+        _ => return None,
+    };
+    Some(Duration::from_secs(num.checked_mul(secs)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rules() {
+        let policy = RetentionPolicy::new(&["/incoming:30d", "/incoming/tmp/:12h"]).unwrap();
+        assert_eq!(
+            policy.rule_for("incoming/a.txt").unwrap().max_age,
+            Duration::from_secs(30 * 24 * 3600)
+        );
+        assert_eq!(
+            policy.rule_for("/incoming/tmp/b/c.txt").unwrap().max_age,
+            Duration::from_secs(12 * 3600)
+        );
+        assert!(policy.rule_for("incoming2/a.txt").is_none());
+        assert!(policy.rule_for("other/a.txt").is_none());
+        assert!(RetentionPolicy::new(&["/incoming"]).is_err());
+        assert!(RetentionPolicy::new(&["/incoming:30x"]).is_err());
+        assert!(RetentionPolicy::new(&["/a:1d", "a/:2d"]).is_err());
+    }
+
+    #[test]
+    fn test_expired() {
+        let tmpdir = assert_fs::TempDir::new().unwrap();
+        let root = tmpdir.path();
+        std::fs::create_dir_all(root.join("incoming/keep")).unwrap();
+        for name in ["incoming/old.txt", "incoming/keep/old.txt", "other.txt"] {
+            std::fs::write(root.join(name), "").unwrap();
+        }
+        let policy = RetentionPolicy::new(&["/incoming:1h", "/incoming/keep:30d"]).unwrap();
+        let now = SystemTime::now() + Duration::from_secs(2 * 3600);
+        assert_eq!(
+            policy.expired(root, now),
+            vec![root.join("incoming/old.txt")]
+        );
+    }
+}
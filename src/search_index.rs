@@ -0,0 +1,199 @@
+use anyhow::Result;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use walkdir::WalkDir;
+
+use crate::provenance::ProvenanceDb;
+
+/// Entries written to the database per transaction while scanning.
+const BATCH_SIZE: usize = 1000;
+
+/// Persistent index of the names under the serve path (`--index`), so `?q=`
+/// searches don't walk the whole tree. It is rebuilt by a scan on startup
+/// and kept current by a filesystem watcher; searches walk the tree until
+/// the first scan is done.
+#[derive(Clone)]
+pub struct SearchIndex {
+    db: ProvenanceDb,
+    ready: Arc<AtomicBool>,
+    _watcher: Arc<Mutex<RecommendedWatcher>>,
+}
+
+impl SearchIndex {
+    pub fn start(db: ProvenanceDb, root: &Path) -> Result<Self> {
+        let ready = Arc::new(AtomicBool::new(false));
+
+        // Watch first, so changes made while scanning are not lost
+        let watcher_db = db.clone();
+        let db_path = canonical_db_path(&db);
+        let mut watcher =
+            notify::recommended_watcher(move |ret: notify::Result<notify::Event>| match ret {
+                Ok(event) => apply_event(&watcher_db, &db_path, event),
+                Err(err) => warn!("Search index watcher error, {err}"),
+            })?;
+        watcher.watch(root, RecursiveMode::Recursive)?;
+
+        let scan_db = db.clone();
+        let scan_ready = ready.clone();
+        let root = root.to_path_buf();
+        std::thread::spawn(move || match rebuild(&scan_db, &root) {
+            Ok(count) => {
+                info!("Search index ready, {count} entries");
+                scan_ready.store(true, Ordering::SeqCst);
+            }
+            Err(err) => error!("Failed to build the search index, {err}"),
+        });
+
+        Ok(Self {
+            db,
+            ready,
+            _watcher: Arc::new(Mutex::new(watcher)),
+        })
+    }
+
+    /// Whether the first scan finished and searches can use the index
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    /// Entries below `dir` whose name contains `needle` (all for None), as (path, is_dir)
+    pub fn search(&self, dir: &Path, needle: Option<&str>) -> Result<Vec<(PathBuf, bool)>> {
+        let Some(dir) = dir.to_str() else {
+            return Ok(vec![]);
+        };
+        let entries = self.db.search_index(dir, needle)?;
+        Ok(entries
+            .into_iter()
+            .map(|(path, is_dir)| (PathBuf::from(path), is_dir))
+            .collect())
+    }
+}
+
+/// Drop what was indexed under `root` and index it again, returning the entry count
+fn rebuild(db: &ProvenanceDb, root: &Path) -> Result<usize> {
+    if let Some(root) = root.to_str() {
+        db.unindex_path(root)?;
+    }
+    index_tree(db, root, &canonical_db_path(db))
+}
+
+fn index_tree(db: &ProvenanceDb, dir: &Path, db_path: &Path) -> Result<usize> {
+    let mut count = 0;
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut it = WalkDir::new(dir).follow_links(true).into_iter();
+    it.next();
+    for entry in it.flatten() {
+        let path = entry.path();
+        if is_db_file(path, db_path) {
+            continue;
+        }
+        let Some(path) = path.to_str() else {
+            continue;
+        };
+        batch.push((path.to_string(), entry.file_type().is_dir()));
+        if batch.len() == BATCH_SIZE {
+            db.index_entries(&batch)?;
+            count += batch.len();
+            batch.clear();
+        }
+    }
+    db.index_entries(&batch)?;
+    Ok(count + batch.len())
+}
+
+fn apply_event(db: &ProvenanceDb, db_path: &Path, event: notify::Event) {
+    // Content changes don't affect names, and reacting to them would loop on
+    // our own writes when the database lives inside the watched tree
+    if !matches!(
+        event.kind,
+        EventKind::Create(_)
+            | EventKind::Remove(_)
+            | EventKind::Modify(notify::event::ModifyKind::Name(_))
+    ) {
+        return;
+    }
+    for path in event.paths {
+        if is_db_file(&path, db_path) {
+            continue;
+        }
+        let Some(path_str) = path.to_str() else {
+            continue;
+        };
+        let ret = match std::fs::metadata(&path) {
+            Ok(meta) if meta.is_dir() => db
+                .index_entries(&[(path_str.to_string(), true)])
+                // A directory moved in comes with its contents
+                .and_then(|_| index_tree(db, &path, db_path).map(|_| ())),
+            Ok(_) => db.index_entries(&[(path_str.to_string(), false)]),
+            Err(_) => db.unindex_path(path_str),
+        };
+        if let Err(err) = ret {
+            warn!(
+                "Failed to update the search index for {}, {err}",
+                path.display()
+            );
+        }
+    }
+}
+
+/// The database and its journal files
+pub(crate) fn is_db_file(path: &Path, db_path: &Path) -> bool {
+    let (Some(name), Some(db_name)) = (path.file_name(), db_path.file_name()) else {
+        return false;
+    };
+    path.parent() == db_path.parent()
+        && name
+            .to_string_lossy()
+            .starts_with(db_name.to_string_lossy().as_ref())
+}
+
+/// Absolute path of the database, comparable with the paths the watcher reports
+pub(crate) fn canonical_db_path(db: &ProvenanceDb) -> PathBuf {
+    let db_path = db.get_db_path();
+    db_path
+        .canonicalize()
+        .unwrap_or_else(|_| db_path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provenance::DbEncryption;
+    use assert_fs::prelude::*;
+    use std::time::{Duration, Instant};
+
+    fn wait_for(mut cond: impl FnMut() -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if cond() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        false
+    }
+
+    #[test]
+    fn test_search_index() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let root = dir.child("root");
+        root.child("docs/Report-2024.pdf").touch().unwrap();
+        root.child("docs/notes.txt").touch().unwrap();
+        let db = ProvenanceDb::new(dir.path().join("p.db"), &DbEncryption::default()).unwrap();
+        let index = SearchIndex::start(db, root.path()).unwrap();
+        assert!(wait_for(|| index.is_ready()));
+
+        let found = index.search(root.path(), Some("report")).unwrap();
+        assert_eq!(found, [(root.path().join("docs/Report-2024.pdf"), false)]);
+        assert_eq!(index.search(root.path(), Some("no")).unwrap().len(), 1);
+        assert_eq!(index.search(root.path(), None).unwrap().len(), 3);
+
+        std::fs::rename(root.path().join("docs"), root.path().join("archive")).unwrap();
+        assert!(wait_for(|| {
+            index.search(root.path(), Some("report")).unwrap()
+                == [(root.path().join("archive/Report-2024.pdf"), false)]
+        }));
+    }
+}
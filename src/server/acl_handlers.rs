@@ -0,0 +1,134 @@
+use anyhow::{anyhow, Result};
+use http_body_util::{BodyExt, Limited};
+use hyper::{Method, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::path::Path;
+
+use crate::auth::{is_readonly_method, AccessControl, AccessPaths, AccessPerm};
+use crate::provenance::ProvenanceDb;
+
+use super::handlers::Server;
+use super::provenance_handlers::Request;
+use super::response_utils::{
+    set_json_response, status_bad_request, status_no_content, status_not_found, Response,
+};
+
+const MAX_BODY_SIZE: usize = 4 * 1024;
+
+#[derive(Debug, Deserialize)]
+struct NewAclEntry {
+    user: String,
+    perm: String,
+}
+
+impl Server {
+    /// Fall back to the ACL rows when the `--auth` rules deny `user` access to
+    /// `relative_path`. A grant on a directory covers everything below it.
+    pub(super) fn acl_access(
+        &self,
+        user: &str,
+        relative_path: &str,
+        method: &Method,
+    ) -> Result<Option<AccessPaths>> {
+        let mut candidates = vec![];
+        let mut rel = relative_path.trim_matches('/');
+        loop {
+            if let Some(path) = self
+                .join_path(rel)
+                .and_then(|v| v.to_str().map(String::from))
+            {
+                candidates.push(path);
+            }
+            match rel.rsplit_once('/') {
+                Some((parent, _)) => rel = parent,
+                None if !rel.is_empty() => rel = "",
+                None => break,
+            }
+        }
+        let perms = self.provenance_db.acl_perms(user, &candidates)?;
+        let perm = if perms.iter().any(|v| v == "rw") {
+            AccessPerm::ReadWrite
+        } else if !perms.is_empty() {
+            AccessPerm::ReadOnly
+        } else {
+            return Ok(None);
+        };
+        if !is_readonly_method(method) && !perm.readwrite() {
+            return Ok(None);
+        }
+        Ok(Some(AccessPaths::new(perm)))
+    }
+}
+
+/// Handle ACL listing (GET /api/<path>?acl)
+pub async fn handle_get_acl(
+    path: &Path,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let entries = provenance_db.get_acl(acl_key(path)?)?;
+    set_json_response(res, json!({ "entries": entries }).to_string());
+    Ok(())
+}
+
+/// Handle ACL grant (POST /api/<path>?acl) with `{"user": "...", "perm": "ro" | "rw"}`
+pub async fn handle_grant_acl(
+    path: &Path,
+    req: Request,
+    granted_by: Option<String>,
+    auth: &AccessControl,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let body = match Limited::new(req.into_body(), MAX_BODY_SIZE).collect().await {
+        Ok(v) => v.to_bytes(),
+        Err(_) => {
+            *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+            return Ok(());
+        }
+    };
+    let entry: NewAclEntry = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(_) => {
+            status_bad_request(res, "Expected a JSON object with `user` and `perm` fields");
+            return Ok(());
+        }
+    };
+    if !matches!(entry.perm.as_str(), "ro" | "rw") {
+        status_bad_request(res, "perm must be `ro` or `rw`");
+        return Ok(());
+    }
+    if !auth.has_user(&entry.user) {
+        status_bad_request(res, &format!("Unknown user `{}`", entry.user));
+        return Ok(());
+    }
+    provenance_db.set_acl_entry(
+        acl_key(path)?,
+        &entry.user,
+        &entry.perm,
+        granted_by.as_deref(),
+    )?;
+    handle_get_acl(path, provenance_db, res).await
+}
+
+/// Handle ACL revocation (DELETE /api/<path>?acl=<user>)
+pub async fn handle_revoke_acl(
+    path: &Path,
+    user: &str,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    if provenance_db.delete_acl_entry(acl_key(path)?, user)? {
+        status_no_content(res);
+    } else {
+        status_not_found(res);
+    }
+    Ok(())
+}
+
+/// ACL rows are keyed by absolute path, like artifacts
+fn acl_key(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| anyhow!("Invalid UTF-8 in file path: {:?}", path))
+}
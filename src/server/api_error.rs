@@ -0,0 +1,127 @@
+use headers::{ContentLength, HeaderMapExt};
+use http_body_util::BodyExt;
+use hyper::body::Body;
+use hyper::header::{HeaderValue, CONTENT_TYPE, RETRY_AFTER};
+use hyper::StatusCode;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::http_utils::body_full;
+
+use super::response_utils::Response;
+use super::router::Route;
+
+/// Largest error body turned into JSON, bigger ones are left as they are
+const MAX_ERROR_BODY: u64 = 64 * 1024;
+
+/// Body of every error response of the API and internal routes
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    /// Stable name of the status, e.g. `not_found`
+    pub code: &'static str,
+    pub message: String,
+    /// More about the error when there is any, e.g. `retry_after`
+    pub details: Value,
+}
+
+impl ApiError {
+    pub fn code(status: StatusCode) -> &'static str {
+        match status {
+            StatusCode::BAD_REQUEST => "bad_request",
+            StatusCode::UNAUTHORIZED => "unauthorized",
+            StatusCode::FORBIDDEN => "forbidden",
+            StatusCode::NOT_FOUND => "not_found",
+            StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+            StatusCode::CONFLICT => "conflict",
+            StatusCode::GONE => "gone",
+            StatusCode::PRECONDITION_FAILED => "precondition_failed",
+            StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+            StatusCode::RANGE_NOT_SATISFIABLE => "range_not_satisfiable",
+            StatusCode::UNPROCESSABLE_ENTITY => "unprocessable",
+            StatusCode::LOCKED => "locked",
+            StatusCode::TOO_MANY_REQUESTS => "too_many_requests",
+            StatusCode::NOT_IMPLEMENTED => "not_implemented",
+            StatusCode::SERVICE_UNAVAILABLE => "unavailable",
+            StatusCode::INSUFFICIENT_STORAGE => "insufficient_storage",
+            status if status.is_client_error() => "client_error",
+            _ => "internal_error",
+        }
+    }
+}
+
+/// Turn the plain-text (or bare JSON) body of an error response to the API
+/// or internal routes into an `ApiError`. Bodies that already are one are
+/// left alone, and other JSON bodies go into `details`.
+pub(super) async fn set_json_error(route: Route, res: &mut Response) {
+    let status = res.status();
+    if !matches!(route, Route::Api(_) | Route::Internal)
+        || (!status.is_client_error() && !status.is_server_error())
+    {
+        return;
+    }
+    let content_type = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let is_json = content_type.starts_with("application/json");
+    if !content_type.is_empty() && !content_type.starts_with("text/plain") && !is_json {
+        return;
+    }
+    if res
+        .body()
+        .size_hint()
+        .upper()
+        .is_none_or(|v| v > MAX_ERROR_BODY)
+    {
+        return;
+    }
+    let body = match std::mem::take(res.body_mut()).collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(err) => {
+            warn!("Failed to read error body, {err}");
+            Default::default()
+        }
+    };
+
+    let reason = status.canonical_reason().unwrap_or("Error").to_string();
+    let (message, mut details) = match is_json {
+        true => match serde_json::from_slice::<Value>(&body) {
+            Ok(value) if value.get("code").is_some() && value.get("message").is_some() => {
+                *res.body_mut() = body_full(body);
+                return;
+            }
+            Ok(value) => (reason, value),
+            Err(_) => (reason, Value::Null),
+        },
+        false => {
+            let text = String::from_utf8_lossy(&body).trim().to_string();
+            match text.is_empty() {
+                true => (reason, Value::Null),
+                false => (text, Value::Null),
+            }
+        }
+    };
+    if let Some(retry_after) = res
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok()?.parse::<u64>().ok())
+    {
+        if details.is_null() {
+            details = json!({ "retry_after": retry_after });
+        }
+    }
+
+    let error = ApiError {
+        code: ApiError::code(status),
+        message,
+        details,
+    };
+    let output = serde_json::to_string(&error).unwrap_or_default();
+    res.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    res.headers_mut()
+        .typed_insert(ContentLength(output.len() as u64));
+    *res.body_mut() = body_full(output);
+}
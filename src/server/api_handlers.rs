@@ -1,14 +1,41 @@
 use anyhow::Result;
+use bytes::Bytes;
+use futures_util::stream;
 use headers::{ContentLength, ContentType, HeaderMapExt};
-use std::collections::HashMap;
-use std::path::Path;
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::Frame;
+use hyper::header::{HeaderName, HeaderValue, ACCEPT_LANGUAGE, CONTENT_TYPE, VARY};
+use hyper::HeaderMap;
+use log::warn;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncReadExt;
 
 use crate::auth::AccessPaths;
 use crate::http_utils::body_full;
-use crate::server::path_item::{DataKind, IndexData, PathItem};
-use crate::server::response_utils::{normalize_path, status_forbid, Response};
+use crate::i18n::Locale;
+use crate::server::path_item::{
+    Brand, ContentCategory, DataKind, IndexData, PathItem, Readme, StampSummary,
+};
+use crate::server::response_utils::{normalize_path, status_bad_request, status_forbid, Response};
 
+use super::content_search::grep_files;
 use super::handlers::{has_query_flag, Server};
+use super::metadata_handlers::{dir_prefix, normalize_tag};
+use super::provenance_handlers;
+use super::visibility_handlers::PublicOnly;
+
+/// Entry count of a paged listing, for the formats without a `total` field
+const TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+
+/// File shown under a listing with `--render-readme`, in any case
+const README_NAME: &str = "README.md";
+
+/// Query parameters that only page or order a listing, not narrow it
+const LISTING_PARAMS: [&str; 6] = ["offset", "limit", "sort", "order", "simple", "ndjson"];
+
+/// Bytes of a README rendered at most
+const README_MAX_SIZE: u64 = 64 * 1024;
 
 impl Server {
     /// Handles API requests for directory listings
@@ -21,13 +48,31 @@ impl Server {
         query_params: &HashMap<String, String>,
         head_only: bool,
         user: Option<String>,
+        locale: Locale,
         access_paths: AccessPaths,
+        public_only: Option<&PublicOnly>,
         res: &mut Response,
     ) -> Result<()> {
+        let filter = match query_params.get("filter") {
+            Some(v) => match ContentCategory::parse(v) {
+                Some(category) => Some(category),
+                None => {
+                    status_bad_request(res, "filter must be image, video, audio or doc");
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
+
         // Get directory listing
         let mut paths = if exist {
             match self
-                .list_dir(path, &self.args.serve_path, access_paths.clone())
+                .list_dir(
+                    path,
+                    &self.args.serve_path,
+                    access_paths.clone(),
+                    public_only,
+                )
                 .await
             {
                 Ok(paths) => paths,
@@ -39,89 +84,203 @@ impl Server {
         } else {
             vec![]
         };
+        if let Some(filter) = filter {
+            paths.retain(|v| v.category == Some(filter));
+        }
 
-        // Sort paths
         self.sort_paths(&mut paths, query_params);
+        self.send_index(
+            path,
+            exist,
+            paths,
+            query_params,
+            head_only,
+            user,
+            locale,
+            &access_paths,
+            res,
+        )
+        .await
+    }
 
-        // Handle simple text format
-        if has_query_flag(query_params, "simple") {
-            let output = paths
-                .into_iter()
-                .map(|v| {
-                    if v.is_dir() {
-                        format!("{}/\n", v.name)
-                    } else {
-                        format!("{}\n", v.name)
-                    }
-                })
-                .collect::<Vec<String>>()
-                .join("");
-            res.headers_mut()
-                .typed_insert(ContentType::from(mime_guess::mime::TEXT_PLAIN_UTF_8));
-            res.headers_mut()
-                .typed_insert(ContentLength(output.len() as u64));
-            if !head_only {
-                *res.body_mut() = body_full(output);
+    /// Handles starred items requests (GET /api/<dir>/?starred)
+    /// Returns JSON data for the paths `owner` starred below the directory
+    #[allow(clippy::too_many_arguments)]
+    pub async fn handle_api_starred(
+        &self,
+        path: &Path,
+        owner: &str,
+        query_params: &HashMap<String, String>,
+        head_only: bool,
+        user: Option<String>,
+        locale: Locale,
+        access_paths: AccessPaths,
+        public_only: Option<&PublicOnly>,
+        res: &mut Response,
+    ) -> Result<()> {
+        let prefix = dir_prefix(path)?;
+        let mut paths: Vec<PathItem> = vec![];
+        for starred in self.provenance_db.list_favorites(owner, &prefix)? {
+            let starred = Path::new(&starred);
+            // Skip stale entries and paths the user can no longer reach
+            let visible = starred
+                .strip_prefix(path)
+                .ok()
+                .and_then(|rel| access_paths.find(&normalize_path(rel)))
+                .is_some()
+                && !public_only.is_some_and(|v| v.is_private(starred));
+            if !visible {
+                continue;
+            }
+            if let Ok(Some(item)) = self.to_pathitem(starred, path).await {
+                paths.push(item);
             }
-            return Ok(());
         }
 
-        // Build JSON response
-        let href = format!(
-            "/{}",
-            normalize_path(path.strip_prefix(&self.args.serve_path)?)
-        );
-        let readwrite = access_paths.perm().readwrite();
-        let data = IndexData {
-            kind: DataKind::Index,
-            href,
-            uri_prefix: self.args.uri_prefix.clone(),
-            allow_upload: self.args.allow_upload && readwrite,
-            allow_delete: self.args.allow_delete && readwrite,
-            allow_search: self.args.allow_search,
-            allow_archive: self.args.allow_archive,
-            dir_exists: exist,
-            auth: self.args.auth.has_users(),
-            user,
+        self.sort_paths(&mut paths, query_params);
+        self.send_index(
+            path,
+            true,
             paths,
+            query_params,
+            head_only,
+            user,
+            locale,
+            &access_paths,
+            res,
+        )
+        .await
+    }
+
+    /// Handles recursive listing requests (GET /api/<dir>/?recursive&glob=<pattern>)
+    /// Returns JSON data for every entry below the directory, flattened
+    ///
+    /// A glob without `/` is matched against entry names, otherwise against
+    /// paths relative to the directory.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn handle_api_recursive(
+        &self,
+        path: &Path,
+        query_params: &HashMap<String, String>,
+        head_only: bool,
+        user: Option<String>,
+        locale: Locale,
+        access_paths: AccessPaths,
+        public_only: Option<&PublicOnly>,
+        res: &mut Response,
+    ) -> Result<()> {
+        use crate::utils::{get_file_name, glob};
+        use std::sync::Arc;
+
+        let pattern = query_params.get("glob").cloned().unwrap_or_default();
+        if !pattern.is_empty() && ::glob::Pattern::new(&pattern).is_err() {
+            status_bad_request(res, "Invalid glob");
+            return Ok(());
+        }
+
+        let base = path.to_path_buf();
+        let public_only = public_only.cloned();
+        let include_entry = move |entry: &walkdir::DirEntry| {
+            let entry_path = entry.path();
+            let matches_glob = pattern.is_empty()
+                || if pattern.contains('/') {
+                    entry_path
+                        .strip_prefix(&base)
+                        .is_ok_and(|v| glob(&pattern, &normalize_path(v)))
+                } else {
+                    glob(&pattern, get_file_name(entry_path))
+                };
+            matches_glob
+                && !public_only
+                    .as_ref()
+                    .is_some_and(|v| v.is_private(entry_path))
         };
+        let entries = tokio::spawn(super::handlers::collect_dir_entries(
+            access_paths.clone(),
+            self.running.clone(),
+            path.to_path_buf(),
+            Arc::new(self.args.hidden.to_vec()),
+            self.args.allow_symlink,
+            self.args.serve_path.clone(),
+            include_entry,
+        ))
+        .await?;
 
-        // Return JSON
-        let output = serde_json::to_string_pretty(&data)?;
-        res.headers_mut()
-            .typed_insert(ContentType::from(mime_guess::mime::APPLICATION_JSON));
-        res.headers_mut()
-            .typed_insert(ContentLength(output.len() as u64));
-        if !head_only {
-            *res.body_mut() = body_full(output);
+        let mut paths: Vec<PathItem> = vec![];
+        for entry in entries {
+            if let Ok(Some(item)) = self.to_pathitem(entry, path.to_path_buf()).await {
+                paths.push(item);
+            }
         }
 
-        Ok(())
+        self.sort_paths(&mut paths, query_params);
+        self.send_index(
+            path,
+            true,
+            paths,
+            query_params,
+            head_only,
+            user,
+            locale,
+            &access_paths,
+            res,
+        )
+        .await
     }
 
     /// Handles API search requests
     /// Returns JSON data for search results
+    #[allow(clippy::too_many_arguments)]
     pub async fn handle_api_search(
         &self,
         path: &Path,
         query_params: &HashMap<String, String>,
         head_only: bool,
         user: Option<String>,
+        locale: Locale,
         access_paths: AccessPaths,
+        public_only: Option<&PublicOnly>,
         res: &mut Response,
     ) -> Result<()> {
         use crate::utils::get_file_name;
-        use anyhow::anyhow;
         use std::sync::Arc;
 
         let search = query_params
             .get("q")
-            .ok_or_else(|| anyhow!("invalid q"))?
-            .to_lowercase();
+            .map(|v| v.to_lowercase())
+            .unwrap_or_default();
+        let tag = match query_params.get("tag").map(|v| normalize_tag(v)) {
+            Some(None) => {
+                status_bad_request(res, "Invalid tag");
+                return Ok(());
+            }
+            Some(tag) => tag,
+            None => None,
+        };
+
+        // `mode=content` greps file contents instead of matching names
+        let content_mode = match query_params.get("mode").map(|v| v.as_str()) {
+            None | Some("name") => false,
+            Some("content") => !search.is_empty(),
+            Some(_) => {
+                status_bad_request(res, "Invalid mode, expected `name` or `content`");
+                return Ok(());
+            }
+        };
 
-        if search.is_empty() {
+        if search.is_empty() && tag.is_none() {
             return self
-                .handle_api_index(path, true, query_params, head_only, user, access_paths, res)
+                .handle_api_index(
+                    path,
+                    true,
+                    query_params,
+                    head_only,
+                    user,
+                    locale,
+                    access_paths,
+                    public_only,
+                    res,
+                )
                 .await;
         }
 
@@ -129,31 +288,193 @@ impl Server {
         let hidden = Arc::new(self.args.hidden.to_vec());
         let search_clone = search.clone();
 
-        let access_paths_clone = access_paths.clone();
-        let search_paths = tokio::spawn(super::handlers::collect_dir_entries(
-            access_paths_clone,
-            self.running.clone(),
-            path_buf.clone(),
-            hidden,
-            self.args.allow_symlink,
-            self.args.serve_path.clone(),
-            move |x| {
-                get_file_name(x.path())
+        // Files whose metadata or comments mention the query match too
+        let annotated = if search.is_empty() || content_mode {
+            HashSet::new()
+        } else {
+            self.provenance_db.search_annotations(&search)?
+        };
+        let tagged = match &tag {
+            Some(tag) => Some(self.provenance_db.paths_with_tag(tag)?),
+            None => None,
+        };
+
+        let public_only_clone = public_only.cloned();
+        let include_entry = move |entry_path: &Path, is_file: bool| {
+            let path_str = entry_path.to_str().unwrap_or_default();
+            // Content candidates are every file, they are grepped below
+            let matches_query = if content_mode {
+                is_file
+            } else {
+                get_file_name(entry_path)
                     .to_lowercase()
                     .contains(&search_clone)
-            },
-        ))
-        .await?;
+                    || annotated.contains(path_str)
+            };
+            let matches_tag = tagged
+                .as_ref()
+                .map(|v| v.contains(path_str))
+                .unwrap_or(true);
+            let visible = !public_only_clone
+                .as_ref()
+                .is_some_and(|v| v.is_private(entry_path));
+            matches_query && matches_tag && visible
+        };
+
+        let search_paths = match self.search_index.as_ref().filter(|v| v.is_ready()) {
+            Some(search_index) => {
+                let needle = (!search.is_empty() && !content_mode).then_some(search.as_str());
+                let mut candidates = search_index.search(path, needle)?;
+                // Annotation matches don't show in the name index
+                if needle.is_some() {
+                    for annotated_path in self.provenance_db.search_annotations(&search)? {
+                        let annotated_path = PathBuf::from(annotated_path);
+                        if let Ok(meta) = tokio::fs::metadata(&annotated_path).await {
+                            candidates.push((annotated_path, meta.is_dir()));
+                        }
+                    }
+                }
+                super::handlers::collect_indexed_entries(
+                    candidates,
+                    &access_paths,
+                    path,
+                    &hidden,
+                    self.args.allow_symlink,
+                    &self.args.serve_path,
+                    include_entry,
+                )
+                .await
+            }
+            None => {
+                tokio::spawn(super::handlers::collect_dir_entries(
+                    access_paths.clone(),
+                    self.running.clone(),
+                    path_buf.clone(),
+                    hidden,
+                    self.args.allow_symlink,
+                    self.args.serve_path.clone(),
+                    move |x| include_entry(x.path(), x.file_type().is_file()),
+                ))
+                .await?
+            }
+        };
+
+        let mut content_matches = match content_mode {
+            true => Some(grep_files(search_paths.clone(), &search).await),
+            false => None,
+        };
 
         let mut paths: Vec<PathItem> = vec![];
         for search_path in search_paths.into_iter() {
-            if let Ok(Some(item)) = self.to_pathitem(search_path, path_buf.clone()).await {
+            let matches = match content_matches.as_mut() {
+                Some(content_matches) => match content_matches.remove(&search_path) {
+                    Some(matches) => Some(matches),
+                    None => continue,
+                },
+                None => None,
+            };
+            if let Ok(Some(mut item)) = self.to_pathitem(search_path, path_buf.clone()).await {
+                item.matches = matches;
                 paths.push(item);
             }
         }
 
         // Sort results
         self.sort_paths(&mut paths, query_params);
+        self.send_index(
+            path,
+            true,
+            paths,
+            query_params,
+            head_only,
+            user,
+            locale,
+            &access_paths,
+            res,
+        )
+        .await
+    }
+
+    /// Stamping progress of every listed file, across pages. Plain listings
+    /// of a directory are cached for a little while, filtered ones counted
+    /// each time.
+    async fn stamp_summary(
+        &self,
+        path: &Path,
+        paths: &[PathItem],
+        query_params: &HashMap<String, String>,
+    ) -> StampSummary {
+        let cacheable = query_params
+            .keys()
+            .all(|v| LISTING_PARAMS.contains(&v.as_str()));
+        if cacheable {
+            if let Some(summary) = self.stamp_summaries.get(path) {
+                return summary;
+            }
+        }
+        let files = paths
+            .iter()
+            .filter(|v| !v.is_dir())
+            .filter_map(|v| {
+                self.args
+                    .serve_path
+                    .join(&v.name)
+                    .to_str()
+                    .map(String::from)
+            })
+            .collect();
+        let summary = provenance_handlers::summarize_stamps(files, &self.provenance_db)
+            .await
+            .inspect_err(|err| warn!("Failed to summarize stamps in {}, {err}", path.display()))
+            .unwrap_or_default();
+        if cacheable {
+            self.stamp_summaries.insert(path, summary);
+        }
+        summary
+    }
+
+    /// Strings of the web UI for a request, see `Locale::select`
+    pub(super) fn locale(&self, headers: &HeaderMap<HeaderValue>) -> Locale {
+        Locale::select(self.args.lang.as_deref(), headers.get(ACCEPT_LANGUAGE))
+    }
+
+    /// Sends one page (`?offset=`, `?limit=`) of sorted `paths`: as JSON with
+    /// the unpaged `total`, or one entry per line with `?simple` (names) and
+    /// `?ndjson` (JSON objects), where the total goes in `x-total-count`
+    #[allow(clippy::too_many_arguments)]
+    pub(super) async fn send_index(
+        &self,
+        path: &Path,
+        exist: bool,
+        paths: Vec<PathItem>,
+        query_params: &HashMap<String, String>,
+        head_only: bool,
+        user: Option<String>,
+        locale: Locale,
+        access_paths: &AccessPaths,
+        res: &mut Response,
+    ) -> Result<()> {
+        let Some(offset) = page_param(query_params, "offset", 0) else {
+            status_bad_request(res, "Invalid offset");
+            return Ok(());
+        };
+        let Some(limit) = page_param(query_params, "limit", usize::MAX) else {
+            status_bad_request(res, "Invalid limit");
+            return Ok(());
+        };
+        // Listed on any page, as long as it is visible here. Names are
+        // relative to the serve path.
+        let dir = path.strip_prefix(&self.args.serve_path).ok();
+        let readme_name = paths
+            .iter()
+            .filter(|_| self.args.render_readme && !query_params.contains_key("q"))
+            .filter(|v| !v.is_dir() && Path::new(&v.name).parent() == dir)
+            .filter_map(|v| Path::new(&v.name).file_name()?.to_str())
+            .find(|v| v.eq_ignore_ascii_case(README_NAME))
+            .map(|v| v.to_string());
+        let total = paths.len();
+        let stamps = self.stamp_summary(path, &paths, query_params).await;
+        let paths: Vec<PathItem> = paths.into_iter().skip(offset).take(limit).collect();
 
         // Handle simple text format
         if has_query_flag(query_params, "simple") {
@@ -172,46 +493,91 @@ impl Server {
                 .typed_insert(ContentType::from(mime_guess::mime::TEXT_PLAIN_UTF_8));
             res.headers_mut()
                 .typed_insert(ContentLength(output.len() as u64));
+            res.headers_mut().insert(TOTAL_COUNT, total.into());
             if !head_only {
                 *res.body_mut() = body_full(output);
             }
             return Ok(());
         }
 
-        // Return as JSON
+        // Stream entries as they serialize rather than building one document
+        if has_query_flag(query_params, "ndjson") {
+            res.headers_mut().insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static("application/x-ndjson"),
+            );
+            res.headers_mut().insert(TOTAL_COUNT, total.into());
+            if !head_only {
+                let lines = stream::iter(paths.into_iter().map(|item| {
+                    let mut line = serde_json::to_vec(&item)?;
+                    line.push(b'\n');
+                    Ok(Frame::data(Bytes::from(line)))
+                }));
+                *res.body_mut() = StreamBody::new(lines).boxed();
+            }
+            return Ok(());
+        }
+
         let href = format!(
             "/{}",
             normalize_path(path.strip_prefix(&self.args.serve_path)?)
         );
         let readwrite = access_paths.perm().readwrite();
+        let limits = access_paths.limits();
+        let quotas = self.quota_usage(path, user.as_deref()).await?;
+        let readme = match readme_name {
+            Some(name) => read_readme(&path.join(&name))
+                .await
+                .inspect_err(|err| warn!("Failed to read {name} in {}, {err}", path.display()))
+                .ok()
+                .map(|(html, truncated)| Readme {
+                    name,
+                    html,
+                    truncated,
+                }),
+            None => None,
+        };
         let data = IndexData {
             kind: DataKind::Index,
             href,
             uri_prefix: self.args.uri_prefix.clone(),
             allow_upload: self.args.allow_upload && readwrite,
-            allow_delete: self.args.allow_delete && readwrite,
+            allow_delete: self.args.allow_delete && readwrite && !limits.no_delete,
             allow_search: self.args.allow_search,
-            allow_archive: self.args.allow_archive,
-            dir_exists: true,
+            allow_archive: self.args.allow_archive && !limits.no_archive,
+            dir_exists: exist,
             auth: self.args.auth.has_users(),
             user,
+            total,
+            stamps,
+            quotas,
+            readme,
+            brand: Brand::from_args(&self.args),
+            locale,
             paths,
         };
 
         let output = serde_json::to_string_pretty(&data)?;
         res.headers_mut()
             .typed_insert(ContentType::from(mime_guess::mime::APPLICATION_JSON));
+        if self.args.lang.is_none() {
+            res.headers_mut()
+                .append(VARY, HeaderValue::from_static("Accept-Language"));
+        }
         res.headers_mut()
             .typed_insert(ContentLength(output.len() as u64));
         if !head_only {
             *res.body_mut() = body_full(output);
         }
-
         Ok(())
     }
 
     /// Helper function to sort paths based on query parameters
-    fn sort_paths(&self, paths: &mut [PathItem], query_params: &HashMap<String, String>) {
+    pub(super) fn sort_paths(
+        &self,
+        paths: &mut [PathItem],
+        query_params: &HashMap<String, String>,
+    ) {
         if let Some(sort) = query_params.get("sort") {
             if sort == "name" {
                 paths.sort_by(|v1, v2| v1.sort_by_name(v2))
@@ -219,6 +585,8 @@ impl Server {
                 paths.sort_by(|v1, v2| v1.sort_by_mtime(v2))
             } else if sort == "size" {
                 paths.sort_by(|v1, v2| v1.sort_by_size(v2))
+            } else if sort == "type" {
+                paths.sort_by(|v1, v2| v1.sort_by_type(v2))
             }
             if query_params
                 .get("order")
@@ -232,3 +600,28 @@ impl Server {
         }
     }
 }
+
+/// The README at `path` rendered to HTML, cut at `README_MAX_SIZE` bytes,
+/// and whether it was cut
+async fn read_readme(path: &Path) -> Result<(String, bool)> {
+    let mut buffer = vec![];
+    crate::chunk_store::open(path)
+        .await?
+        .take(README_MAX_SIZE + 1)
+        .read_to_end(&mut buffer)
+        .await?;
+    let truncated = buffer.len() as u64 > README_MAX_SIZE;
+    buffer.truncate(README_MAX_SIZE as usize);
+    Ok((
+        crate::markdown::render(&String::from_utf8_lossy(&buffer)),
+        truncated,
+    ))
+}
+
+/// `offset`/`limit` from the query, `default` when absent and None when invalid
+fn page_param(query_params: &HashMap<String, String>, name: &str, default: usize) -> Option<usize> {
+    match query_params.get(name) {
+        Some(v) => v.parse().ok(),
+        None => Some(default),
+    }
+}
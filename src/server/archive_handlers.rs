@@ -0,0 +1,154 @@
+use anyhow::{anyhow, Result};
+use futures_util::TryStreamExt;
+use http_body_util::{BodyExt, Limited, StreamBody};
+use hyper::body::Frame;
+use hyper::header::HeaderValue;
+use hyper::StatusCode;
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+use tokio::fs;
+use tokio_util::io::ReaderStream;
+
+use crate::auth::AccessPaths;
+use crate::utils::{get_file_name, try_get_file_name};
+
+use super::handlers::{collect_archive_entries, is_hidden, write_zip, Request, Server};
+use super::response_utils::{set_content_disposition, status_bad_request, Response, BUF_SIZE};
+use super::visibility_handlers::PublicOnly;
+
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+impl Server {
+    /// Handle zipping a selection of a folder (POST /dir/?zip)
+    ///
+    /// The body is a JSON array of paths relative to the folder; selected
+    /// folders are zipped with their content. Entries are filtered like a
+    /// folder download, so hidden, inaccessible and private paths are left
+    /// out rather than reported.
+    pub(super) async fn handle_zip_selection(
+        &self,
+        path: &Path,
+        req: Request,
+        access_paths: AccessPaths,
+        public_only: Option<PublicOnly>,
+        res: &mut Response,
+    ) -> Result<()> {
+        let body = match Limited::new(req.into_body(), MAX_BODY_SIZE).collect().await {
+            Ok(v) => v.to_bytes(),
+            Err(_) => {
+                *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+                return Ok(());
+            }
+        };
+        let selection: Vec<String> = match serde_json::from_slice(&body) {
+            Ok(v) => v,
+            Err(_) => {
+                status_bad_request(res, "Expected a JSON array of relative paths");
+                return Ok(());
+            }
+        };
+        let mut relative_paths = vec![];
+        for item in &selection {
+            match parse_relative_path(item) {
+                Some(v) => relative_paths.push(v),
+                None => {
+                    status_bad_request(res, &format!("Invalid path `{item}`"));
+                    return Ok(());
+                }
+            }
+        }
+
+        let (mut writer, reader) = tokio::io::duplex(BUF_SIZE);
+        let filename = try_get_file_name(path)?;
+        set_content_disposition(res, false, &format!("{filename}.zip"))?;
+        res.headers_mut()
+            .insert("content-type", HeaderValue::from_static("application/zip"));
+
+        let dir = path.to_owned();
+        let hidden = self.args.hidden.clone();
+        let running = self.running.clone();
+        let compression = self.args.compress.to_compression();
+        let follow_symlinks = self.args.allow_symlink;
+        let serve_path = self.args.serve_path.clone();
+        tokio::spawn(async move {
+            let mut paths = BTreeSet::new();
+            for relative_path in relative_paths {
+                let Some(access_paths) = relative_path
+                    .to_str()
+                    .and_then(|v| access_paths.find(&v.replace('\\', "/")))
+                else {
+                    continue;
+                };
+                let full_path = dir.join(&relative_path);
+                let Ok(meta) = fs::metadata(&full_path).await else {
+                    continue;
+                };
+                if is_hidden_path(&hidden, &relative_path, meta.is_dir())
+                    || (!follow_symlinks
+                        && !fs::canonicalize(&full_path)
+                            .await
+                            .is_ok_and(|v| v.starts_with(&serve_path)))
+                    || public_only
+                        .as_ref()
+                        .is_some_and(|v| v.is_private(&full_path))
+                {
+                    continue;
+                }
+                if !meta.is_dir() {
+                    if !access_paths.perm().indexonly() {
+                        paths.insert(full_path);
+                    }
+                    continue;
+                }
+                match collect_archive_entries(
+                    &full_path,
+                    access_paths,
+                    public_only.clone(),
+                    &hidden,
+                    follow_symlinks,
+                    serve_path.clone(),
+                    running.clone(),
+                )
+                .await
+                {
+                    Ok(entries) => paths.extend(entries),
+                    Err(e) => {
+                        error!("Failed to archive {}, {e}", full_path.display());
+                        return;
+                    }
+                }
+            }
+            if let Err(e) =
+                write_zip(&mut writer, &dir, paths.into_iter().collect(), compression).await
+            {
+                error!("Failed to archive {}, {e}", dir.display());
+            }
+        });
+        let reader_stream = ReaderStream::with_capacity(reader, BUF_SIZE);
+        let stream_body = StreamBody::new(
+            reader_stream
+                .map_ok(Frame::data)
+                .map_err(|err| anyhow!("{err}")),
+        );
+        *res.body_mut() = stream_body.boxed();
+        Ok(())
+    }
+}
+
+/// A selected path, as long as it stays inside the folder
+fn parse_relative_path(value: &str) -> Option<PathBuf> {
+    let path = Path::new(value.trim_matches('/'));
+    let valid = path.components().next().is_some()
+        && path.components().all(|v| matches!(v, Component::Normal(_)));
+    valid.then(|| path.to_path_buf())
+}
+
+/// Whether any part of a selected path matches the hidden patterns
+fn is_hidden_path(hidden: &[String], relative_path: &Path, is_dir: bool) -> bool {
+    let mut ancestors: Vec<&Path> = relative_path.ancestors().collect();
+    ancestors.pop();
+    ancestors.iter().enumerate().any(|(i, v)| {
+        // The first ancestor is the path itself; the others are folders
+        is_hidden(hidden, get_file_name(v), i > 0 || is_dir)
+    })
+}
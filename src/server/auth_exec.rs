@@ -0,0 +1,162 @@
+use anyhow::{anyhow, bail, Context, Result};
+use hyper::header::AUTHORIZATION;
+use hyper::Method;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::args::PROVENANCE_DB_KEY_ENV;
+use crate::auth::{get_basic_credentials, AccessPaths};
+
+use super::handlers::{Request, Server};
+
+/// How long the answer of `--auth-exec` for a user and password is reused,
+/// as Basic clients send their password with every request
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// How long `--auth-exec` may take before the login is turned down
+const EXEC_TIMEOUT: Duration = Duration::from_secs(10);
+
+type LoginKey = (String, [u8; 32]);
+type CachedLogin = (Instant, Option<AccessPaths>);
+
+/// A Basic login of a user missing from `--auth`, checked with `--auth-exec`
+#[derive(Debug, Clone)]
+pub(super) struct ExecLogin {
+    user: String,
+    /// What the command let the user access, None when it turned them down
+    paths: Option<AccessPaths>,
+}
+
+/// What `--auth-exec` prints for a user it lets in
+#[derive(Debug, Deserialize)]
+struct ExecOutput {
+    paths: Vec<String>,
+}
+
+/// Recent answers of `--auth-exec`, by user and password hash
+#[derive(Debug, Clone, Default)]
+pub struct ExecLogins {
+    cache: Arc<Mutex<HashMap<LoginKey, CachedLogin>>>,
+}
+
+impl ExecLogins {
+    async fn check(&self, command: &Path, user: &str, pass: &str) -> Option<AccessPaths> {
+        let key = (user.to_string(), Sha256::digest(pass.as_bytes()).into());
+        if let Some((checked, paths)) = self.cache.lock().unwrap().get(&key) {
+            if checked.elapsed() < CACHE_TTL {
+                return paths.clone();
+            }
+        }
+        let paths = match run_auth_exec(command, user, pass).await {
+            Ok(paths) => paths,
+            // Not cached, the next request tries again
+            Err(err) => {
+                warn!("Failed to check login of {user} with --auth-exec, {err}");
+                return None;
+            }
+        };
+        let now = Instant::now();
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, (checked, _)| now.duration_since(*checked) < CACHE_TTL);
+        cache.insert(key, (now, paths.clone()));
+        paths
+    }
+}
+
+/// Run `command` with the user and password on separate lines of its stdin.
+/// Exiting with 0 and printing `{"paths": ["/dir1:rw", "/dir2"]}` lets the
+/// user in to those paths; anything else turns them down.
+async fn run_auth_exec(command: &Path, user: &str, pass: &str) -> Result<Option<AccessPaths>> {
+    let mut child = Command::new(command)
+        .env_remove(PROVENANCE_DB_KEY_ENV)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to run {}", command.display()))?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("No stdin for {}", command.display()))?;
+    let output = tokio::time::timeout(EXEC_TIMEOUT, async move {
+        // Commands may turn users down without reading their password
+        let _ = stdin
+            .write_all(format!("{user}\n{pass}\n").as_bytes())
+            .await;
+        drop(stdin);
+        child.wait_with_output().await
+    })
+    .await
+    .map_err(|_| anyhow!("{} timed out", command.display()))??;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let output: ExecOutput = serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("Invalid output of {}", command.display()))?;
+    let mut paths = AccessPaths::default();
+    for value in &output.paths {
+        if value.trim().is_empty() || paths.merge(value).is_none() {
+            bail!("Invalid paths `{value}` from {}", command.display());
+        }
+    }
+    Ok(Some(paths))
+}
+
+impl Server {
+    /// Check a Basic login of a user missing from `--auth` with `--auth-exec`,
+    /// leaving the outcome on the request for `guard_as`
+    pub(super) async fn check_exec_login(&self, req: &mut Request) {
+        let Some(command) = &self.args.auth_exec else {
+            return;
+        };
+        let Some((user, pass)) = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(get_basic_credentials)
+        else {
+            return;
+        };
+        if self.args.auth.has_user(&user) {
+            return;
+        }
+        let ip = req.extensions().get::<SocketAddr>().map(|v| v.ip());
+        if self.logins.retry_after(ip, &user).is_some() {
+            return;
+        }
+        // The command reads one line each
+        let paths = match user.contains('\n') || pass.contains('\n') {
+            true => None,
+            false => self.exec_logins.check(command, &user, &pass).await,
+        };
+        req.extensions_mut().insert(ExecLogin { user, paths });
+    }
+
+    /// The user and access of a login checked with `--auth-exec`
+    pub(super) fn guard_exec_login(
+        &self,
+        login: &ExecLogin,
+        path: &str,
+        method: &Method,
+        perm_method: &Method,
+    ) -> (Option<String>, Option<AccessPaths>) {
+        let Some(paths) = &login.paths else {
+            return (None, None);
+        };
+        // Mounts serve a top-level folder of the granted paths as their root
+        let paths = match &self.mount_name {
+            Some(name) => paths.find(name).unwrap_or_default(),
+            None => paths.clone(),
+        };
+        self.args
+            .auth
+            .guard_external(&login.user, paths, path, method, perm_method)
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::Result;
+use hyper::header::AUTHORIZATION;
+use hyper::{Method, StatusCode};
+use serde_json::json;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::auth::get_auth_user;
+use crate::provenance::LoginOutcome;
+
+use super::handlers::{Request, Server};
+use super::response_utils::{set_json_response, Response};
+
+pub(super) const AUTH_LOG_PATH: &str = "__dufs__/auth-log";
+
+/// First lockout once a client reaches `--max-login-failures`, doubling
+/// with every failure after that
+const LOCKOUT_BASE: Duration = Duration::from_secs(30);
+const LOCKOUT_MAX: Duration = Duration::from_secs(3600);
+
+/// Successful logins of a user from one address are logged this often,
+/// as Basic and Digest clients send their password with every request
+const SUCCESS_LOG_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// How often clients without failures or a recent login are forgotten
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Entries the auth log returns unless `?limit=` asks for fewer
+const MAX_AUTH_LOG_ENTRIES: usize = 1000;
+
+type LoginKey = (Option<IpAddr>, String);
+
+/// Failed password logins per address and user, locking the pair out for a
+/// while after `--max-login-failures` in a row
+#[derive(Debug, Clone)]
+pub struct LoginThrottle {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    logins: HashMap<LoginKey, Login>,
+    swept: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Login {
+    failures: u32,
+    last_failure: Option<Instant>,
+    locked_until: Option<Instant>,
+    success_logged: Option<Instant>,
+}
+
+impl Default for LoginThrottle {
+    fn default() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                logins: HashMap::new(),
+                swept: Instant::now(),
+            })),
+        }
+    }
+}
+
+impl LoginThrottle {
+    /// Seconds until `user` may log in from `ip` again, None when they may now
+    pub fn retry_after(&self, ip: Option<IpAddr>, user: &str) -> Option<u64> {
+        let state = self.state.lock().unwrap();
+        let locked_until = state.logins.get(&(ip, user.to_string()))?.locked_until?;
+        let left = locked_until.checked_duration_since(Instant::now())?;
+        Some(left.as_secs_f64().ceil().max(1.0) as u64)
+    }
+
+    /// Count a failed login, true when it locks the user out
+    pub fn failed(&self, ip: Option<IpAddr>, user: &str, max_failures: u32) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        if now.duration_since(state.swept) > SWEEP_INTERVAL {
+            state.sweep(now);
+        }
+        let login = state.logins.entry((ip, user.to_string())).or_default();
+        login.failures += 1;
+        login.last_failure = Some(now);
+        if max_failures == 0 || login.failures < max_failures {
+            return false;
+        }
+        let doublings = (login.failures - max_failures).min(16);
+        let lockout = LOCKOUT_BASE.saturating_mul(1 << doublings).min(LOCKOUT_MAX);
+        login.locked_until = Some(now + lockout);
+        true
+    }
+
+    /// Clear the failures of `user` from `ip`, true when the login is due
+    /// for the auth log
+    pub fn succeeded(&self, ip: Option<IpAddr>, user: &str) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let login = state.logins.entry((ip, user.to_string())).or_default();
+        login.failures = 0;
+        login.last_failure = None;
+        login.locked_until = None;
+        let due = login
+            .success_logged
+            .is_none_or(|v| now.duration_since(v) > SUCCESS_LOG_INTERVAL);
+        if due {
+            login.success_logged = Some(now);
+        }
+        due
+    }
+}
+
+impl State {
+    fn sweep(&mut self, now: Instant) {
+        self.logins.retain(|_, login| {
+            let recent = |v: Option<Instant>, within: Duration| {
+                v.is_some_and(|v| now.duration_since(v) < within)
+            };
+            recent(login.last_failure, LOCKOUT_MAX)
+                || recent(login.success_logged, SUCCESS_LOG_INTERVAL)
+        });
+        self.swept = now;
+    }
+}
+
+impl Server {
+    /// The user and address of a password login in `req`, None when it
+    /// carries no Basic or Digest credentials
+    pub(super) fn login_attempt(&self, req: &Request) -> Option<(Option<IpAddr>, String)> {
+        if !self.args.auth.has_users() || req.method() == Method::OPTIONS {
+            return None;
+        }
+        let user = get_auth_user(req.headers().get(AUTHORIZATION)?)?;
+        let ip = req.extensions().get::<SocketAddr>().map(|v| v.ip());
+        Some((ip, user))
+    }
+
+    /// Count a password login and add it to the auth log
+    pub(super) fn audit_login(&self, ip: Option<IpAddr>, user: &str, path: &str, success: bool) {
+        let outcome = match success {
+            true if self.logins.succeeded(ip, user) => LoginOutcome::Success,
+            true => return,
+            false if self.logins.failed(ip, user, self.args.max_login_failures) => {
+                warn!(
+                    "Locked out {user} from {} after too many failed logins",
+                    ip.map(|v| v.to_string()).unwrap_or_default()
+                );
+                LoginOutcome::Locked
+            }
+            false => LoginOutcome::Failure,
+        };
+        let ip = ip.map(|v| v.to_string());
+        let path = format!("/{}", path.trim_start_matches('/'));
+        if let Err(err) = self
+            .provenance_db
+            .record_login(user, ip.as_deref(), &path, outcome)
+        {
+            error!("Failed to record login of {user}, {err}");
+        }
+    }
+
+    /// Handle `GET /__dufs__/auth-log?user=<user>&limit=<n>`: the latest
+    /// password logins, newest first, for admins
+    pub(super) fn handle_auth_log(&self, req: &Request, res: &mut Response) -> Result<()> {
+        if req.method() != Method::GET {
+            *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            return Ok(());
+        }
+        if !self.guard_admin(req, res)? {
+            return Ok(());
+        }
+        let query = req.uri().query().unwrap_or_default();
+        let params: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let limit = params
+            .get("limit")
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(MAX_AUTH_LOG_ENTRIES)
+            .min(MAX_AUTH_LOG_ENTRIES);
+        let entries = self
+            .provenance_db
+            .get_auth_log(params.get("user").map(|v| v.as_str()), limit)?;
+        set_json_response(res, json!({ "entries": entries }).to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_login_throttle() {
+        let throttle = LoginThrottle::default();
+        let ip = Some("10.0.0.1".parse().unwrap());
+        assert!(!throttle.failed(ip, "alice", 3));
+        assert!(!throttle.failed(ip, "alice", 3));
+        assert_eq!(throttle.retry_after(ip, "alice"), None);
+        assert!(throttle.failed(ip, "alice", 3));
+        assert_eq!(throttle.retry_after(ip, "alice"), Some(30));
+        assert!(throttle.failed(ip, "alice", 3));
+        assert_eq!(throttle.retry_after(ip, "alice"), Some(60));
+
+        // Other addresses and users keep their own count
+        assert_eq!(throttle.retry_after(None, "alice"), None);
+        assert_eq!(throttle.retry_after(ip, "bob"), None);
+
+        assert!(throttle.succeeded(ip, "alice"));
+        assert!(!throttle.succeeded(ip, "alice"));
+        assert_eq!(throttle.retry_after(ip, "alice"), None);
+        assert!(!throttle.failed(ip, "alice", 3));
+
+        assert!(!throttle.failed(ip, "bob", 0));
+    }
+}
@@ -0,0 +1,153 @@
+use anyhow::Result;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::auth::AccessControl;
+use crate::Args;
+
+use super::handlers::{Request, Server};
+use super::middleware::{BoxFuture, Middleware};
+use super::response_utils::Response;
+
+/// Settings of a server given in code rather than on the command line, for
+/// serving node-drive from another hyper or tower application
+pub struct ServerBuilder {
+    args: Args,
+    auth: Vec<String>,
+    layers: Vec<Arc<dyn Middleware>>,
+}
+
+impl ServerBuilder {
+    /// A server of `serve_path` with the defaults of the command line, which
+    /// keep provenance in `provenance.db` of the working directory
+    pub fn new(serve_path: impl Into<PathBuf>) -> Self {
+        Self {
+            args: Args {
+                serve_path: serve_path.into(),
+                ..Default::default()
+            },
+            auth: vec![],
+            layers: vec![],
+        }
+    }
+
+    /// Serve `path` under `/<name>/`, like `/<name>:<path>` on the command line
+    pub fn mount(mut self, name: &str, path: impl Into<PathBuf>) -> Self {
+        self.args.mounts.insert(name.to_string(), path.into());
+        self
+    }
+
+    pub fn path_prefix(mut self, prefix: &str) -> Self {
+        self.args.path_prefix = prefix.to_string();
+        self
+    }
+
+    /// An access rule in the format of `--auth`, e.g. `admin:pass@/:rw`
+    pub fn auth(mut self, rule: &str) -> Self {
+        self.auth.push(rule.to_string());
+        self
+    }
+
+    pub fn enable_cors(mut self, enable: bool) -> Self {
+        self.args.enable_cors = enable;
+        self
+    }
+
+    pub fn provenance_db(mut self, path: impl Into<PathBuf>) -> Self {
+        self.args.provenance_db = Some(path.into());
+        self
+    }
+
+    pub fn signing_key_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.args.signing_key_file = Some(path.into());
+        self
+    }
+
+    /// Leave proofs queued instead of sending digests to the calendars
+    pub fn ots_offline(mut self, offline: bool) -> Self {
+        self.args.ots_offline = offline;
+        self
+    }
+
+    /// Add a layer inside the built-in ones, which only sees requests that got
+    /// past the IP filter, rate limits and access checks. Layers run in the
+    /// order added.
+    pub fn layer(mut self, layer: impl Middleware + 'static) -> Self {
+        self.layers.push(Arc::new(layer));
+        self
+    }
+
+    /// Open the provenance database and start the background workers, so it
+    /// must run inside a Tokio runtime
+    pub fn build(self) -> Result<NodeDrive> {
+        let Self {
+            mut args,
+            auth,
+            layers,
+        } = self;
+        if !auth.is_empty() {
+            let rules: Vec<_> = auth.iter().map(|v| v.as_str()).collect();
+            args.auth = AccessControl::new(&rules)?;
+        }
+        args.resolve_paths()?;
+
+        let running = Arc::new(AtomicBool::new(true));
+        let mut server = Server::init(args, running.clone())?;
+        server.layers.extend(layers);
+        Ok(NodeDrive {
+            server: Arc::new(server),
+            running,
+        })
+    }
+}
+
+/// A node-drive server as a `tower` and hyper service, cheap to clone.
+/// The client address comes from a `SocketAddr` in the request extensions.
+#[derive(Clone)]
+pub struct NodeDrive {
+    server: Arc<Server>,
+    running: Arc<AtomicBool>,
+}
+
+impl NodeDrive {
+    /// End event streams and write provenance to disk, when shutting down
+    pub fn shutdown(&self) -> Result<()> {
+        self.server.close_event_streams();
+        self.running.store(false, Ordering::SeqCst);
+        self.server.flush_provenance()
+    }
+
+    fn respond(&self, req: Request) -> BoxFuture<'static, Result<Response, Infallible>> {
+        let server = self.server.clone();
+        let addr = req.extensions().get::<SocketAddr>().copied();
+        Box::pin(server.call(req, addr))
+    }
+}
+
+impl tower::Service<Request> for NodeDrive {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Response, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        self.respond(req)
+    }
+}
+
+impl hyper::service::Service<Request> for NodeDrive {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Response, Infallible>>;
+
+    fn call(&self, req: Request) -> Self::Future {
+        self.respond(req)
+    }
+}
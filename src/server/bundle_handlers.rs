@@ -0,0 +1,312 @@
+use anyhow::{anyhow, Result};
+use async_zip::tokio::read::seek::ZipFileReader;
+use async_zip::{tokio::write::ZipFileWriter, Compression, ZipEntryBuilder};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures_util::TryStreamExt;
+use http_body_util::{BodyExt, StreamBody};
+use hyper::{
+    body::Frame,
+    header::{HeaderValue, CONTENT_TYPE},
+    StatusCode,
+};
+use log::{error, info};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, BufReader};
+use tokio::{fs, io};
+use tokio_util::compat::{FuturesAsyncReadCompatExt, FuturesAsyncWriteCompatExt};
+use tokio_util::io::{InspectReader, ReaderStream, StreamReader};
+
+use crate::http_utils::IncomingStream;
+use crate::provenance::{verify_chain, Manifest, ProvenanceDb};
+use crate::provenance_utils;
+use crate::utils::try_get_file_name;
+
+use super::handlers::{ensure_path_parent, Request};
+use super::response_utils::{
+    set_content_disposition, set_json_response, status_bad_request, status_conflict,
+    status_not_found, status_payload_too_large, Response, BUF_SIZE,
+};
+
+/// Folder of the bundle holding the manifest and the OTS proofs
+const PROVENANCE_DIR: &str = "provenance/";
+const MANIFEST_ENTRY: &str = "provenance/manifest.json";
+/// Largest manifest or proof read from a bundle
+const MAX_METADATA_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Handle `GET /<file>?provenance-bundle`: a zip of the file, its manifest
+/// (`provenance/manifest.json`) and the OTS proof of every event
+/// (`provenance/<index>.ots`), which `?provenance-import` accepts elsewhere
+pub async fn handle_bundle_export(
+    path: &Path,
+    head_only: bool,
+    compression: Compression,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let manifest = match provenance_utils::get_manifest_for_file(provenance_db, path).await? {
+        Some(manifest) if !manifest.events.is_empty() => manifest,
+        _ => {
+            status_not_found(res);
+            return Ok(());
+        }
+    };
+    let filename = try_get_file_name(path)?.to_string();
+    set_content_disposition(res, false, &format!("{filename}.provenance.zip"))?;
+    res.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/zip"));
+    if head_only {
+        return Ok(());
+    }
+
+    let (mut writer, reader) = io::duplex(BUF_SIZE);
+    let path = path.to_owned();
+    tokio::spawn(async move {
+        if let Err(e) = write_bundle(&mut writer, &path, &filename, &manifest, compression).await {
+            error!("Failed to bundle {}, {e}", path.display());
+        }
+    });
+    let stream_body = StreamBody::new(
+        ReaderStream::with_capacity(reader, BUF_SIZE)
+            .map_ok(Frame::data)
+            .map_err(|err| anyhow!("{err}")),
+    );
+    *res.body_mut() = stream_body.boxed();
+    Ok(())
+}
+
+async fn write_bundle<W: tokio::io::AsyncWrite + Unpin>(
+    writer: &mut W,
+    path: &Path,
+    filename: &str,
+    manifest: &Manifest,
+    compression: Compression,
+) -> Result<()> {
+    let mut writer = ZipFileWriter::with_tokio(writer);
+
+    let mut file = crate::chunk_store::open(path).await?;
+    let builder = ZipEntryBuilder::new(filename.to_string().into(), compression);
+    let mut file_writer = writer.write_entry_stream(builder).await?.compat_write();
+    io::copy(&mut file, &mut file_writer).await?;
+    file_writer.into_inner().close().await?;
+
+    let manifest_json = serde_json::to_string_pretty(manifest)?;
+    let builder = ZipEntryBuilder::new(MANIFEST_ENTRY.to_string().into(), compression);
+    writer
+        .write_entry_whole(builder, manifest_json.as_bytes())
+        .await?;
+
+    for event in &manifest.events {
+        let Ok(proof) = STANDARD.decode(&event.ots_proof_b64) else {
+            continue;
+        };
+        let name = format!("{PROVENANCE_DIR}{}.ots", event.index);
+        let builder = ZipEntryBuilder::new(name.into(), Compression::Stored);
+        writer.write_entry_whole(builder, &proof).await?;
+    }
+
+    writer.close().await?;
+    Ok(())
+}
+
+/// Handle `POST /<file>?provenance-import` with a bundle from `?provenance-bundle`
+///
+/// The file is written to `path` and its event chain recorded as is, once the
+/// file matches the manifest and every event's hash, link and signatures
+/// verify. Proofs in `provenance/<index>.ots` take precedence over the ones
+/// embedded in the manifest, so upgraded proofs can be dropped in the bundle.
+pub async fn handle_bundle_import(
+    path: &Path,
+    req: Request,
+    max_size: Option<u64>,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    ensure_path_parent(path).await?;
+    let bundle_path = sibling_path(path, "bundle")?;
+    let file_path = sibling_path(path, "import")?;
+    let ret = import_bundle(
+        path,
+        &bundle_path,
+        &file_path,
+        req,
+        max_size,
+        provenance_db,
+        res,
+    )
+    .await;
+    let _ = fs::remove_file(&bundle_path).await;
+    let _ = fs::remove_file(&file_path).await;
+    ret
+}
+
+async fn import_bundle(
+    path: &Path,
+    bundle_path: &Path,
+    file_path: &Path,
+    req: Request,
+    max_size: Option<u64>,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let path_str = path
+        .to_str()
+        .ok_or_else(|| anyhow!("Invalid UTF-8 in path"))?;
+
+    // The zip index is at the end, so spool the body before reading it
+    let body = IncomingStream::new(req.into_body()).map_err(io::Error::other);
+    let mut bundle_file = fs::File::create(bundle_path).await?;
+    let limit = max_size.map_or(u64::MAX, |v| v.saturating_add(1));
+    let spooled = io::copy(&mut StreamReader::new(body).take(limit), &mut bundle_file).await?;
+    drop(bundle_file);
+    if let Some(max_size) = max_size.filter(|v| spooled > *v) {
+        status_payload_too_large(res, max_size);
+        return Ok(());
+    }
+
+    let bundle_file = BufReader::new(fs::File::open(bundle_path).await?);
+    let Ok(mut zip) = ZipFileReader::with_tokio(bundle_file).await else {
+        status_bad_request(res, "The bundle is not a zip file");
+        return Ok(());
+    };
+
+    let mut manifest_index = None;
+    let mut data_index = None;
+    let mut proof_indexes = HashMap::new();
+    for (i, entry) in zip.file().entries().iter().enumerate() {
+        let Ok(name) = entry.filename().as_str() else {
+            continue;
+        };
+        if name == MANIFEST_ENTRY {
+            manifest_index = Some(i);
+        } else if let Some(proof) = name.strip_prefix(PROVENANCE_DIR) {
+            if let Some(Ok(event_index)) = proof.strip_suffix(".ots").map(|v| v.parse::<u32>()) {
+                proof_indexes.insert(event_index, i);
+            }
+        } else if !name.ends_with('/') {
+            if data_index.is_some() {
+                status_bad_request(res, "The bundle must contain a single file");
+                return Ok(());
+            }
+            data_index = Some(i);
+        }
+    }
+    let (Some(manifest_index), Some(data_index)) = (manifest_index, data_index) else {
+        status_bad_request(res, "The bundle must contain a file and its manifest");
+        return Ok(());
+    };
+
+    let Ok(manifest_json) = read_small_entry(&mut zip, manifest_index).await else {
+        status_bad_request(res, "Unreadable manifest");
+        return Ok(());
+    };
+    let mut manifest: Manifest = match serde_json::from_slice(&manifest_json) {
+        Ok(v) => v,
+        Err(e) => {
+            status_bad_request(res, &format!("Invalid manifest: {e}"));
+            return Ok(());
+        }
+    };
+    for event in manifest.events.iter_mut() {
+        if let Some(&i) = proof_indexes.get(&event.index) {
+            let Ok(proof) = read_small_entry(&mut zip, i).await else {
+                status_bad_request(res, &format!("Unreadable proof for event #{}", event.index));
+                return Ok(());
+            };
+            event.ots_proof_b64 = STANDARD.encode(proof);
+        }
+    }
+
+    // Extract the file, hashing it on the way
+    let mut hasher = Sha256::new();
+    {
+        let entry_reader = zip.reader_without_entry(data_index).await?.compat();
+        let mut entry_reader = InspectReader::new(entry_reader, |chunk| hasher.update(chunk));
+        let mut file = fs::File::create(file_path).await?;
+        if io::copy(&mut entry_reader, &mut file).await.is_err() {
+            status_bad_request(res, "Unreadable file in the bundle");
+            return Ok(());
+        }
+        file.sync_all().await?;
+    }
+    let sha256_hex = format!("{:x}", hasher.finalize());
+    if sha256_hex != manifest.artifact.sha256_hex {
+        status_bad_request(res, "The file doesn't match the manifest");
+        return Ok(());
+    }
+    if let Err(e) = verify_chain(&sha256_hex, &manifest.events) {
+        status_bad_request(res, &e.to_string());
+        return Ok(());
+    }
+
+    if fs::symlink_metadata(path).await.is_ok() {
+        status_conflict(res, "The file already exists");
+        return Ok(());
+    }
+    // Copies share event hashes across artifacts, so the schema no longer
+    // rejects a bundle imported twice
+    let mut recorded = false;
+    for event in &manifest.events {
+        if provenance_db.has_event(&event.event_hash_hex)? {
+            recorded = true;
+            break;
+        }
+    }
+    if recorded {
+        status_conflict(res, "The events are already recorded");
+        return Ok(());
+    }
+    fs::rename(file_path, path).await?;
+    let imported = provenance_db.import_events(path_str, &sha256_hex, &manifest.events);
+    let conflict = match imported {
+        Ok(true) => None,
+        Ok(false) => Some("The path already has a provenance record"),
+        Err(e) if is_constraint_violation(&e) => Some("The events are already recorded"),
+        Err(e) => {
+            let _ = fs::remove_file(path).await;
+            return Err(e);
+        }
+    };
+    if let Some(conflict) = conflict {
+        let _ = fs::remove_file(path).await;
+        status_conflict(res, conflict);
+        return Ok(());
+    }
+
+    info!(
+        "Imported {} with {} provenance events ({})",
+        path.display(),
+        manifest.events.len(),
+        &sha256_hex[..8]
+    );
+    *res.status_mut() = StatusCode::CREATED;
+    let manifest = provenance_db.get_manifest_by_path(path_str)?;
+    set_json_response(res, serde_json::to_string(&manifest)?);
+    Ok(())
+}
+
+async fn read_small_entry<R>(zip: &mut ZipFileReader<R>, index: usize) -> Result<Vec<u8>>
+where
+    R: tokio::io::AsyncBufRead + tokio::io::AsyncSeek + Unpin,
+{
+    let mut reader = zip.reader_with_entry(index).await?;
+    if reader.entry().uncompressed_size() > MAX_METADATA_SIZE {
+        return Err(anyhow!("Entry too large"));
+    }
+    let mut buf = vec![];
+    reader.read_to_end_checked(&mut buf).await?;
+    Ok(buf)
+}
+
+/// Hidden scratch file next to `path` for an import in progress
+fn sibling_path(path: &Path, suffix: &str) -> Result<PathBuf> {
+    let name = try_get_file_name(path)?;
+    Ok(path.with_file_name(format!(".{name}.{suffix}")))
+}
+
+fn is_constraint_violation(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<rusqlite::Error>()
+        .and_then(|v| v.sqlite_error_code())
+        == Some(rusqlite::ErrorCode::ConstraintViolation)
+}
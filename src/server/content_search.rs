@@ -0,0 +1,114 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Files larger than this are skipped by content search.
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+/// How many files are read at the same time.
+const MAX_WORKERS: usize = 8;
+/// Snippets returned per matching file.
+const MAX_SNIPPETS: usize = 5;
+/// Longest snippet returned, in chars.
+const MAX_SNIPPET_LEN: usize = 200;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContentMatch {
+    /// 1-based line number
+    pub line: usize,
+    pub text: String,
+}
+
+/// Search text files in `paths` for lines containing `needle` (lowercase),
+/// returning the files with at least one match.
+pub async fn grep_files(paths: Vec<PathBuf>, needle: &str) -> HashMap<PathBuf, Vec<ContentMatch>> {
+    let workers = Arc::new(Semaphore::new(MAX_WORKERS));
+    let needle: Arc<str> = Arc::from(needle);
+    let mut tasks = JoinSet::new();
+    for path in paths {
+        let Ok(permit) = workers.clone().acquire_owned().await else {
+            break;
+        };
+        let needle = needle.clone();
+        tasks.spawn_blocking(move || {
+            let _permit = permit;
+            let matches = grep_file(&path, &needle).unwrap_or_default();
+            (path, matches)
+        });
+    }
+
+    let mut output = HashMap::new();
+    while let Some(ret) = tasks.join_next().await {
+        match ret {
+            Ok((path, matches)) if !matches.is_empty() => {
+                output.insert(path, matches);
+            }
+            Ok(_) => {}
+            Err(err) => warn!("Content search task failed, {err}"),
+        }
+    }
+    output
+}
+
+fn grep_file(path: &Path, needle: &str) -> std::io::Result<Vec<ContentMatch>> {
+    if std::fs::metadata(path)?.len() > MAX_FILE_SIZE {
+        return Ok(vec![]);
+    }
+    let mut reader = BufReader::new(crate::chunk_store::open_sync(path)?);
+    if !content_inspector::inspect(reader.fill_buf()?).is_text() {
+        return Ok(vec![]);
+    }
+
+    let mut matches = vec![];
+    let mut line = Vec::new();
+    let mut number = 0;
+    let mut reader = reader.take(MAX_FILE_SIZE);
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        number += 1;
+        let text = String::from_utf8_lossy(&line);
+        if text.to_lowercase().contains(needle) {
+            matches.push(ContentMatch {
+                line: number,
+                text: text.trim().chars().take(MAX_SNIPPET_LEN).collect(),
+            });
+            if matches.len() == MAX_SNIPPETS {
+                break;
+            }
+        }
+    }
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[tokio::test]
+    async fn test_grep_files() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        dir.child("a.txt")
+            .write_str("first\nthe Needle\nlast")
+            .unwrap();
+        dir.child("b.txt").write_str("nothing here").unwrap();
+        dir.child("c.bin").write_binary(b"\0\0needle\0").unwrap();
+        let paths = ["a.txt", "b.txt", "c.bin"]
+            .iter()
+            .map(|v| dir.path().join(v))
+            .collect();
+
+        let output = grep_files(paths, "needle").await;
+        assert_eq!(output.len(), 1);
+        let matches = &output[&dir.path().join("a.txt")];
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].line, 2);
+        assert_eq!(matches[0].text, "the Needle");
+    }
+}
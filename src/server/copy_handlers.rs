@@ -0,0 +1,216 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use hyper::StatusCode;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::events::ChangeKind;
+use crate::ots_aggregator::QUEUED_PROOF;
+use crate::provenance::{
+    compute_event_hash, sign_event_hash, Actors, Event, EventAction, InsertEventArgs, QueuedDigest,
+    Signatures,
+};
+use crate::quota;
+use crate::utils::encode_uri;
+
+use super::handlers::{Request, Server};
+use super::response_utils::{set_json_response, status_bad_request, status_conflict, Response};
+use super::webdav;
+
+#[derive(Debug, Serialize)]
+struct CopyResponse {
+    path: String,
+    files: Vec<CopiedFile>,
+}
+
+#[derive(Debug, Serialize)]
+struct CopiedFile {
+    path: String,
+    /// Hash of the copy event closing the cloned lineage, for files with provenance
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event_hash: Option<String>,
+}
+
+impl Server {
+    /// Handle copying a file or folder (POST ?copy=/dest/path)
+    ///
+    /// The JSON counterpart of WebDAV COPY for the web UI. The destination
+    /// needs write access like an upload, and an existing one is only
+    /// replaced with `overwrite`. Each copied file gets the provenance chain
+    /// of its source followed by a `copy` event signed by the server.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) async fn handle_copy_to(
+        &self,
+        path: &Path,
+        dest: &str,
+        is_dir: bool,
+        size: u64,
+        overwrite: bool,
+        user: Option<&str>,
+        req: &Request,
+        res: &mut Response,
+    ) -> Result<()> {
+        let Some(dest_path) = self.resolve_path(&encode_uri(dest)) else {
+            status_bad_request(res, "Invalid copy destination");
+            return Ok(());
+        };
+        let Some((dest, dest_access)) = self.authorize_dest(&dest_path, req, res) else {
+            return Ok(());
+        };
+        if !overwrite && tokio::fs::symlink_metadata(&dest).await.is_ok() {
+            status_conflict(res, "The destination already exists");
+            return Ok(());
+        }
+
+        let headers = req.headers();
+        let copied_size = if is_dir && !self.args.quota.is_empty() {
+            let dir = path.to_path_buf();
+            tokio::task::spawn_blocking(move || quota::disk_usage(&dir)).await?
+        } else {
+            size
+        };
+        if (!is_dir && self.reject_upload_type(&dest, Some(size), res))
+            || webdav::reject_locked(&self.locks, &dest, true, headers, res)
+            || self
+                .reject_over_quota(&dest, user, copied_size, None, res)
+                .await?
+            || !self
+                .prepare_dest(path, &dest, &dest_access, user, headers, res)
+                .await?
+        {
+            return Ok(());
+        }
+
+        let href = encode_uri(&self.relative_path(path));
+        let copied = webdav::handle_copy(path, &dest, true, &href, res).await?;
+        if let (Some(from), Some(to)) = (path.to_str(), dest.to_str()) {
+            self.provenance_db.copy_dead_properties(from, to)?;
+        }
+        let mut files = vec![];
+        for (file, size) in copied {
+            let Some(file_str) = file.to_str() else {
+                continue;
+            };
+            self.provenance_db.set_file_owner(file_str, user, size)?;
+            let source = match file.strip_prefix(&dest) {
+                Ok(relative) if !relative.as_os_str().is_empty() => path.join(relative),
+                _ => path.to_path_buf(),
+            };
+            let event_hash = self.clone_lineage(&source, &file).await?;
+            if event_hash.is_some() {
+                self.publish_provenance(&file, "copy", res);
+            }
+            files.push(CopiedFile {
+                path: self.relative_path(&file),
+                event_hash,
+            });
+        }
+        self.publish_change(ChangeKind::Copy, path, Some(&dest), res);
+
+        // Partial failures keep the multistatus of the WebDAV copy
+        if res.status() == StatusCode::NO_CONTENT {
+            *res.status_mut() = StatusCode::CREATED;
+            let output = CopyResponse {
+                path: self.relative_path(&dest),
+                files,
+            };
+            set_json_response(res, serde_json::to_string(&output)?);
+        }
+        Ok(())
+    }
+
+    /// Give the copy at `dest` the events of `source`, followed by a `copy`
+    /// event linking to their head, and return that event's hash. Files
+    /// without provenance are left without.
+    async fn clone_lineage(&self, source: &Path, dest: &Path) -> Result<Option<String>> {
+        let (Some(source), Some(dest)) = (source.to_str(), dest.to_str()) else {
+            return Ok(None);
+        };
+        let source = source.to_string();
+        let Some(manifest) = self
+            .provenance_db
+            .run(move |db| db.get_manifest_by_path(&source))
+            .await?
+        else {
+            return Ok(None);
+        };
+        let Some(head) = manifest.events.last() else {
+            return Ok(None);
+        };
+
+        let index = head.index + 1;
+        let sha256_hex = manifest.artifact.sha256_hex.clone();
+        let actors = Actors {
+            creator_pubkey_hex: Some(self.keypair.public_key_hex.clone()),
+            prev_owner_pubkey_hex: None,
+            new_owner_pubkey_hex: None,
+        };
+        let issued_at = chrono::Utc::now().to_rfc3339();
+        let event_hash_hex = compute_event_hash(
+            index,
+            &EventAction::Copy,
+            &sha256_hex,
+            Some(&head.event_hash_hex),
+            &actors,
+            &issued_at,
+        );
+        let creator_sig_hex = sign_event_hash(&event_hash_hex, &self.keypair.private_key_hex)
+            .map_err(|e| anyhow!("Failed to sign event: {}", e))?;
+        let event = Event {
+            event_type: "provenance.event/v1".to_string(),
+            index,
+            action: EventAction::Copy,
+            artifact_sha256_hex: sha256_hex.clone(),
+            prev_event_hash_hex: Some(head.event_hash_hex.clone()),
+            actors,
+            issued_at,
+            event_hash_hex: event_hash_hex.clone(),
+            signatures: Signatures {
+                creator_sig_hex: Some(creator_sig_hex),
+                prev_owner_sig_hex: None,
+                new_owner_sig_hex: None,
+            },
+            ots_proof_b64: STANDARD.encode(QUEUED_PROOF),
+        };
+
+        let dest = dest.to_string();
+        let events = manifest.events;
+        let artifact_id = self
+            .provenance_db
+            .run(move |db| {
+                if !db.import_events(&dest, &sha256_hex, &events)? {
+                    return Ok(None);
+                }
+                let Some((artifact_id, _)) = db.get_artifact_by_path(&dest)? else {
+                    return Ok(None);
+                };
+                db.insert_event(InsertEventArgs {
+                    artifact_id,
+                    index,
+                    action: &event.action,
+                    artifact_sha256_hex: &event.artifact_sha256_hex,
+                    prev_event_hash_hex: event.prev_event_hash_hex.as_deref(),
+                    issued_at: &event.issued_at,
+                    event_hash_hex: &event.event_hash_hex,
+                    ots_proof_b64: &event.ots_proof_b64,
+                    actors: &event.actors,
+                    signatures: &event.signatures,
+                })?;
+                db.queue_ots_digest(artifact_id, index, &event.event_hash_hex)?;
+                Ok(Some(artifact_id))
+            })
+            .await?;
+        let Some(artifact_id) = artifact_id else {
+            return Ok(None);
+        };
+        self.ots.spawn_stamp(
+            self.provenance_db.clone(),
+            QueuedDigest {
+                artifact_id,
+                event_index: index,
+                digest_hex: event_hash_hex.clone(),
+            },
+        );
+        Ok(Some(event_hash_hex))
+    }
+}
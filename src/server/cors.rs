@@ -0,0 +1,120 @@
+use headers::{AccessControlAllowCredentials, AccessControlAllowOrigin, HeaderMapExt};
+use hyper::header::{
+    HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE,
+    ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY,
+};
+use hyper::Method;
+
+use crate::Args;
+
+use super::handlers::Request;
+use super::response_utils::Response;
+
+/// How long browsers may cache a preflight answer, in seconds
+const CORS_MAX_AGE: &str = "7200";
+
+/// Headers scripts on an allowed origin may read. Credentialed responses
+/// can't expose `*`, so they are listed.
+const CORS_EXPOSE_HEADERS: &str = "Content-Disposition,Content-Digest,Repr-Digest,ETag,\
+    Last-Modified,Location,Lock-Token,X-Upload-Id,X-Share-Id,X-Total-Count,X-Encrypted,\
+    X-Encrypted-Recipients,X-Preview-Truncated";
+
+/// The CORS headers of a request, kept to answer it once it's handled
+#[derive(Debug)]
+pub struct CorsRequest {
+    origin: Option<HeaderValue>,
+    request_method: Option<HeaderValue>,
+    request_headers: Option<HeaderValue>,
+    preflight: bool,
+}
+
+impl CorsRequest {
+    pub fn new(req: &Request) -> Self {
+        let headers = req.headers();
+        let origin = headers.get(ORIGIN).cloned();
+        let request_method = headers.get(ACCESS_CONTROL_REQUEST_METHOD).cloned();
+        Self {
+            preflight: req.method() == Method::OPTIONS
+                && origin.is_some()
+                && request_method.is_some(),
+            origin,
+            request_method,
+            request_headers: headers.get(ACCESS_CONTROL_REQUEST_HEADERS).cloned(),
+        }
+    }
+
+    /// Whether this is a preflight, answered without going through auth
+    pub fn is_preflight(&self) -> bool {
+        self.preflight
+    }
+}
+
+/// Add CORS headers for `cors` to the response
+///
+/// Origins listed in `--cors-origins` are echoed back and may send
+/// credentials. Any other origin may read responses without credentials
+/// when no origin is listed or `*` is, and gets no CORS headers otherwise.
+pub fn add_cors(res: &mut Response, args: &Args, cors: &CorsRequest) {
+    let headers = res.headers_mut();
+    let listed = args.cors_origins.iter().any(|v| v != "*");
+    let any = args.cors_origins.is_empty() || args.cors_origins.iter().any(|v| v == "*");
+    if listed {
+        headers.append(VARY, HeaderValue::from_static("Origin"));
+    }
+    let origin = cors.origin.as_ref().filter(|v| cors_origin_listed(args, v));
+    let credentials = origin.is_some();
+    match origin {
+        Some(origin) => {
+            headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+            headers.typed_insert(AccessControlAllowCredentials);
+            headers.insert(
+                ACCESS_CONTROL_EXPOSE_HEADERS,
+                HeaderValue::from_static(CORS_EXPOSE_HEADERS),
+            );
+        }
+        None if any => {
+            headers.typed_insert(AccessControlAllowOrigin::ANY);
+            headers.insert(
+                ACCESS_CONTROL_EXPOSE_HEADERS,
+                HeaderValue::from_static("Authorization,*"),
+            );
+        }
+        None => return,
+    }
+
+    // Wildcards are taken literally on credentialed requests, so those
+    // mirror the preflight unless the allowed values are configured
+    let methods = match (args.cors_methods.is_empty(), credentials) {
+        (false, _) => HeaderValue::from_str(&args.cors_methods.join(",")).ok(),
+        (true, true) => cors.request_method.clone(),
+        (true, false) => Some(HeaderValue::from_static("*")),
+    };
+    let allowed_headers = match (args.cors_headers.is_empty(), credentials) {
+        (false, _) => HeaderValue::from_str(&args.cors_headers.join(",")).ok(),
+        (true, true) => cors.request_headers.clone(),
+        (true, false) => Some(HeaderValue::from_static("Authorization,*")),
+    };
+    if let Some(methods) = methods {
+        headers.insert(ACCESS_CONTROL_ALLOW_METHODS, methods);
+    }
+    if let Some(allowed_headers) = allowed_headers {
+        headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, allowed_headers);
+    }
+    if cors.preflight {
+        headers.insert(
+            ACCESS_CONTROL_MAX_AGE,
+            HeaderValue::from_static(CORS_MAX_AGE),
+        );
+    }
+}
+
+/// Whether `origin` is one of `--cors-origins`, which `*` doesn't count as
+fn cors_origin_listed(args: &Args, origin: &HeaderValue) -> bool {
+    let Ok(origin) = origin.to_str() else {
+        return false;
+    };
+    args.cors_origins
+        .iter()
+        .any(|v| v != "*" && v.eq_ignore_ascii_case(origin))
+}
@@ -0,0 +1,204 @@
+use anyhow::Result;
+use futures_util::TryStreamExt;
+use headers::HeaderMapExt;
+use hyper::header::HeaderValue;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::fs;
+use tokio::io::{self, BufReader, BufWriter};
+use tokio_util::io::{InspectWriter, StreamReader};
+use uuid::Uuid;
+
+use crate::chunk_store::{self, ChunkStore};
+use crate::delta::{self, DEFAULT_BLOCK_SIZE, MAX_BLOCK_SIZE, MIN_BLOCK_SIZE};
+use crate::events::ChangeKind;
+use crate::http_utils::{body_full, IncomingStream};
+use crate::provenance::Direction;
+use crate::utils::{get_file_name, sha256_digest_field};
+
+use super::handlers::{declared_digest, Request, Server};
+use super::response_utils::{
+    extract_cache_headers, set_json_response, status_bad_request, status_insufficient_storage,
+    status_payload_too_large, status_unprocessable, Response,
+};
+
+#[derive(Debug, Serialize)]
+struct DeltaResponse {
+    size: u64,
+    sha256: String,
+    copied_bytes: u64,
+    literal_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event_hash: Option<String>,
+}
+
+impl Server {
+    /// Handle `GET /file?signature-blocks[&block-size=N]`: the checksums of
+    /// the file's blocks, with its ETag to send back in `If-Match` along
+    /// with the delta
+    pub(super) async fn handle_signature_blocks(
+        &self,
+        path: &Path,
+        query_params: &HashMap<String, String>,
+        head_only: bool,
+        res: &mut Response,
+    ) -> Result<()> {
+        let block_size = match query_params.get("block-size").map(|v| v.parse::<u32>()) {
+            None => DEFAULT_BLOCK_SIZE,
+            Some(Ok(v)) if (MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&v) => v,
+            Some(_) => {
+                let msg =
+                    format!("block-size must be between {MIN_BLOCK_SIZE} and {MAX_BLOCK_SIZE}");
+                status_bad_request(res, &msg);
+                return Ok(());
+            }
+        };
+        let meta = fs::metadata(path).await?;
+        let mut reader = BufReader::new(chunk_store::open(path).await?);
+        let signature = delta::signature(&mut reader, block_size).await?;
+        set_json_response(res, serde_json::to_string(&signature)?);
+        if let Some((etag, _)) = extract_cache_headers(&meta) {
+            res.headers_mut().typed_insert(etag);
+        }
+        if head_only {
+            *res.body_mut() = body_full("");
+        }
+        Ok(())
+    }
+
+    /// Handle `PATCH /file?delta`: rebuild the file from its current blocks
+    /// and the literals of the delta in the body
+    ///
+    /// The new content is written next to the file and only replaces it once
+    /// the delta applied in full, fit the quota and matched `Repr-Digest`
+    /// when one was declared. It is then minted like an upload.
+    pub(super) async fn handle_delta(
+        &self,
+        path: &Path,
+        user: Option<&str>,
+        size: u64,
+        req: Request,
+        res: &mut Response,
+    ) -> Result<()> {
+        let repr_digest = match declared_digest(req.headers(), "repr-digest") {
+            Ok(v) => v,
+            Err(err) => {
+                status_bad_request(res, &err.to_string());
+                return Ok(());
+            }
+        };
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let upload_limit = self.args.upload_limit(&name);
+        // The new content replaces the whole file
+        let headroom = self.quota_headroom(path, user, size).await?;
+
+        // The base can't change under the delta
+        let _write_lock = self.write_locks.lock(path).await;
+
+        // Blocks are read straight from the content, not the chunk list
+        if let Some(store) = ChunkStore::get() {
+            store.restore(path).await?;
+        }
+        let temp_name = format!(".{}.{}.delta", get_file_name(path), Uuid::new_v4());
+        let temp_path = path.with_file_name(temp_name);
+        let mut hasher = Sha256::new();
+        let applied = {
+            let mut base = fs::File::open(path).await?;
+            let mut output = BufWriter::new(InspectWriter::new(
+                fs::File::create(&temp_path).await?,
+                |chunk| hasher.update(chunk),
+            ));
+            let body = IncomingStream::new(req.into_body()).map_err(io::Error::other);
+            let mut body = StreamReader::new(body);
+            let max_size = headroom
+                .as_ref()
+                .map_or(u64::MAX, |v| v.bytes)
+                .min(upload_limit.unwrap_or(u64::MAX));
+            delta::apply(&mut base, size, &mut body, &mut output, max_size).await
+        };
+        let stats = match applied {
+            Ok(Some(stats)) => stats,
+            Ok(None) => {
+                let _ = fs::remove_file(&temp_path).await;
+                match headroom {
+                    Some(headroom) if upload_limit.is_none_or(|v| headroom.bytes <= v) => {
+                        let msg = format!("Quota exceeded for {}", headroom.scope);
+                        status_insufficient_storage(res, &msg);
+                    }
+                    _ => status_payload_too_large(res, upload_limit.unwrap_or_default()),
+                }
+                return Ok(());
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&temp_path).await;
+                status_bad_request(res, &format!("Invalid delta, {err}"));
+                return Ok(());
+            }
+        };
+        let sha256 = format!("{:x}", hasher.finalize());
+        if repr_digest.is_some_and(|v| v != sha256) {
+            let _ = fs::remove_file(&temp_path).await;
+            status_unprocessable(res, "Repr-Digest doesn't match the rebuilt content");
+            return Ok(());
+        }
+        fs::rename(&temp_path, path).await?;
+
+        if let Some(store) = ChunkStore::get() {
+            let chunk_path = path.to_path_buf();
+            if let Err(err) = tokio::task::spawn_blocking(move || store.store(&chunk_path)).await? {
+                warn!("Failed to split {} into chunks, {err}", path.display());
+            }
+        }
+        if let Some(path_str) = path.to_str() {
+            if let Err(err) =
+                self.provenance_db
+                    .record_transfer(path_str, Direction::Upload, stats.literal_bytes)
+            {
+                warn!("Failed to record upload of {}, {err}", path.display());
+            }
+            if let Err(err) = self
+                .provenance_db
+                .set_file_owner(path_str, user, stats.size)
+            {
+                warn!("Failed to record the owner of {}, {err}", path.display());
+            }
+        }
+        self.publish_change(ChangeKind::Upload, path, None, res);
+
+        let event_hash = match self
+            .create_mint_event(path, sha256.clone(), user, false)
+            .await
+        {
+            Ok(mint) => {
+                self.publish_provenance(path, "mint", res);
+                Some(mint.event_hash)
+            }
+            Err(e) => {
+                error!("Failed to create mint event for {}: {}", path.display(), e);
+                None
+            }
+        };
+        let output = DeltaResponse {
+            size: stats.size,
+            sha256,
+            copied_bytes: stats.copied_bytes,
+            literal_bytes: stats.literal_bytes,
+            event_hash,
+        };
+        set_json_response(res, serde_json::to_string(&output)?);
+        if let Some((etag, _)) = fs::metadata(path)
+            .await
+            .ok()
+            .and_then(|meta| extract_cache_headers(&meta))
+        {
+            res.headers_mut().typed_insert(etag);
+        }
+        if let Some(field) = sha256_digest_field(&output.sha256) {
+            res.headers_mut()
+                .insert("repr-digest", HeaderValue::from_str(&field)?);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use super::path_item::PathType;
+
+use anyhow::Result;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// What the filesystem says about a listed entry
+#[derive(Debug, Clone, Copy)]
+pub struct EntryStat {
+    pub path_type: PathType,
+    pub mtime: u64,
+    /// Bytes for files, visible children for directories
+    pub size: u64,
+}
+
+pub type DirEntries = Arc<Vec<(PathBuf, EntryStat)>>;
+
+/// In-memory directory listings (`--dir-cache`), dropped when a filesystem
+/// event touches the directory, one of its entries or their children. Only
+/// the filesystem part is cached, provenance and annotations are read on
+/// every listing.
+#[derive(Clone)]
+pub struct DirCache {
+    root: PathBuf,
+    listings: Arc<Mutex<HashMap<PathBuf, DirEntries>>>,
+    /// Bumped on every event, so a listing read while one arrived isn't kept
+    generation: Arc<AtomicU64>,
+    _watcher: Arc<Mutex<RecommendedWatcher>>,
+}
+
+impl DirCache {
+    pub fn start(root: &Path) -> Result<Self> {
+        let listings: Arc<Mutex<HashMap<PathBuf, DirEntries>>> = Arc::default();
+        let generation = Arc::new(AtomicU64::new(0));
+        let watcher_listings = listings.clone();
+        let watcher_generation = generation.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |ret: notify::Result<notify::Event>| match ret {
+                Ok(event) => {
+                    if matches!(event.kind, EventKind::Access(_)) {
+                        return;
+                    }
+                    watcher_generation.fetch_add(1, Ordering::SeqCst);
+                    let mut listings = watcher_listings.lock().unwrap();
+                    for path in &event.paths {
+                        for dir in path.ancestors().take(3) {
+                            listings.remove(dir);
+                        }
+                    }
+                }
+                Err(err) => {
+                    warn!("Directory cache watcher error, {err}");
+                    watcher_generation.fetch_add(1, Ordering::SeqCst);
+                    watcher_listings.lock().unwrap().clear();
+                }
+            })?;
+        watcher.watch(root, RecursiveMode::Recursive)?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            listings,
+            generation,
+            _watcher: Arc::new(Mutex::new(watcher)),
+        })
+    }
+
+    pub fn get(&self, dir: &Path) -> Option<DirEntries> {
+        self.listings.lock().unwrap().get(dir).cloned()
+    }
+
+    /// Current generation, to take before reading a directory
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Keep the listing of `dir` read since `generation`, unless events came
+    /// in meanwhile. Directories reached through symlinks aren't watched.
+    pub async fn insert(&self, dir: &Path, entries: DirEntries, generation: u64) {
+        let watched = tokio::fs::canonicalize(dir)
+            .await
+            .is_ok_and(|v| v == dir && v.starts_with(&self.root));
+        let mut listings = self.listings.lock().unwrap();
+        if watched && self.generation() == generation {
+            listings.insert(dir.to_path_buf(), entries);
+        }
+    }
+}
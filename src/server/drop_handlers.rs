@@ -0,0 +1,175 @@
+use anyhow::Result;
+use headers::{ContentLength, HeaderMapExt};
+use hyper::{HeaderMap, Method, StatusCode};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::http_utils::body_full;
+use crate::Args;
+
+use super::handlers::{Request, Server};
+use super::response_utils::{status_conflict, status_forbid, Response};
+
+/// Who uploads and mints dropped files
+pub(super) const DROP_ACTOR: &str = "drop";
+
+/// Bytes each client IP may drop per day without `--anonymous-drop-limit`
+const DEFAULT_DROP_LIMIT: u64 = 100 << 20;
+
+const DROP_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug)]
+struct Usage {
+    since: Instant,
+    bytes: u64,
+}
+
+/// The `--allow-anonymous-drop` directories and the bytes each client IP
+/// dropped into them over the last day
+#[derive(Debug, Clone)]
+pub(super) struct DropBox {
+    dirs: Vec<String>,
+    limit: u64,
+    usage: Arc<Mutex<HashMap<IpAddr, Usage>>>,
+}
+
+impl DropBox {
+    pub fn from_args(args: &Args) -> Option<Self> {
+        if args.allow_anonymous_drop.is_empty() {
+            return None;
+        }
+        Some(Self::new(
+            args.allow_anonymous_drop.clone(),
+            args.anonymous_drop_limit.unwrap_or(DEFAULT_DROP_LIMIT),
+        ))
+    }
+
+    pub fn new(dirs: Vec<String>, limit: u64) -> Self {
+        Self {
+            dirs,
+            limit,
+            usage: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Whether `relative_path` (`incoming/a.txt`) lies inside a drop directory
+    pub fn covers(&self, relative_path: &str) -> bool {
+        self.dirs.iter().any(|dir| {
+            relative_path
+                .strip_prefix(dir.as_str())
+                .is_some_and(|v| v.len() > 1 && v.starts_with('/'))
+        })
+    }
+
+    /// Count `bytes` against the allowance of `ip`, false when they don't fit
+    pub fn reserve(&self, ip: IpAddr, bytes: u64) -> bool {
+        let now = Instant::now();
+        let mut usage = self.usage.lock().unwrap();
+        usage.retain(|_, v| now.duration_since(v.since) < DROP_WINDOW);
+        let entry = usage.entry(ip).or_insert(Usage {
+            since: now,
+            bytes: 0,
+        });
+        if entry.bytes.saturating_add(bytes) > self.limit {
+            return false;
+        }
+        entry.bytes += bytes;
+        true
+    }
+
+    /// Give back bytes of a drop that didn't go through
+    pub fn release(&self, ip: IpAddr, bytes: u64) {
+        if let Some(entry) = self.usage.lock().unwrap().get_mut(&ip) {
+            entry.bytes = entry.bytes.saturating_sub(bytes);
+        }
+    }
+}
+
+impl Server {
+    /// Whether a request denied by the auth rules is an anonymous drop: a
+    /// plain PUT without credentials into a `--allow-anonymous-drop` directory
+    pub(super) fn is_anonymous_drop(
+        &self,
+        relative_path: &str,
+        method: &Method,
+        headers: &HeaderMap,
+        query: &str,
+    ) -> bool {
+        *method == Method::PUT
+            && query.is_empty()
+            && !headers.contains_key(hyper::header::AUTHORIZATION)
+            && self
+                .drop_box
+                .as_ref()
+                .is_some_and(|v| v.covers(relative_path))
+    }
+
+    /// Handle an anonymous drop, uploading a new file as the `drop` actor
+    ///
+    /// Existing files are never replaced, and the body must declare its
+    /// length so it is counted against the client's allowance up front.
+    pub(super) async fn handle_anonymous_drop(
+        &self,
+        relative_path: &str,
+        req: Request,
+        res: &mut Response,
+    ) -> Result<()> {
+        let (Some(drop_box), Some(path)) = (&self.drop_box, self.join_path(relative_path)) else {
+            status_forbid(res);
+            return Ok(());
+        };
+        if tokio::fs::symlink_metadata(&path).await.is_ok() {
+            status_conflict(res, "A file with this name was dropped already");
+            return Ok(());
+        }
+        let Some(ContentLength(size)) = req.headers().typed_get::<ContentLength>() else {
+            *res.status_mut() = StatusCode::LENGTH_REQUIRED;
+            return Ok(());
+        };
+        let ip = req
+            .extensions()
+            .get::<SocketAddr>()
+            .map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |v| v.ip());
+        if !drop_box.reserve(ip, size) {
+            *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+            *res.body_mut() = body_full(format!(
+                "Drops are limited to {} bytes per day",
+                drop_box.limit
+            ));
+            return Ok(());
+        }
+
+        let ret = self
+            .handle_upload(&path, Some(DROP_ACTOR), None, 0, req, res)
+            .await;
+        if ret.is_err() || res.status() != StatusCode::CREATED {
+            drop_box.release(ip, size);
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_box() {
+        let drop_box = DropBox::new(vec!["incoming".into(), "a/b".into()], 10);
+        assert!(drop_box.covers("incoming/x.txt"));
+        assert!(drop_box.covers("a/b/c/x.txt"));
+        assert!(!drop_box.covers("incoming"));
+        assert!(!drop_box.covers("incoming2/x.txt"));
+        assert!(!drop_box.covers("a/x.txt"));
+
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "127.0.0.2".parse().unwrap();
+        assert!(drop_box.reserve(ip, 6));
+        assert!(!drop_box.reserve(ip, 6));
+        assert!(drop_box.reserve(other, 6));
+        drop_box.release(ip, 6);
+        assert!(drop_box.reserve(ip, 10));
+    }
+}
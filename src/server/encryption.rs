@@ -0,0 +1,107 @@
+use anyhow::{anyhow, bail, Result};
+use hyper::header::HeaderValue;
+use hyper::HeaderMap;
+
+use crate::provenance::{Encryption, EncryptionScheme};
+
+/// Most recipients recorded for one file
+const MAX_RECIPIENTS: usize = 32;
+
+/// Bytes of an upload needed to tell whether it is encrypted
+pub const HEAD_SIZE: usize = 64;
+
+/// The encryption an upload declares with `X-Encrypted: age|gpg` and the
+/// comma separated `X-Encrypted-Recipients` fingerprints
+pub fn declared_encryption(headers: &HeaderMap<HeaderValue>) -> Result<Option<Encryption>> {
+    let Some(scheme) = headers.get("x-encrypted") else {
+        return Ok(None);
+    };
+    let scheme = match scheme.to_str().map(|v| v.trim().to_ascii_lowercase()) {
+        Ok(v) if v == "age" => EncryptionScheme::Age,
+        Ok(v) if v == "gpg" || v == "pgp" => EncryptionScheme::Gpg,
+        _ => bail!("Invalid X-Encrypted header, expected age or gpg"),
+    };
+    let recipients = match headers.get("x-encrypted-recipients") {
+        Some(value) => value
+            .to_str()
+            .map_err(|_| anyhow!("Invalid X-Encrypted-Recipients header"))?
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+            .collect(),
+        None => vec![],
+    };
+    let valid = |v: &String| {
+        v.len() <= 128
+            && v.bytes()
+                .all(|c| c.is_ascii_alphanumeric() || b"+/=:_-".contains(&c))
+    };
+    if recipients.len() > MAX_RECIPIENTS || !recipients.iter().all(valid) {
+        bail!("Invalid X-Encrypted-Recipients header");
+    }
+    Ok(Some(Encryption { scheme, recipients }))
+}
+
+/// Whether `head`, the start of a file, is the output of `scheme`, binary
+/// or armored
+pub fn looks_encrypted(scheme: EncryptionScheme, head: &[u8]) -> bool {
+    match scheme {
+        EncryptionScheme::Age => {
+            head.starts_with(b"age-encryption.org/v1\n")
+                || head.starts_with(b"-----BEGIN AGE ENCRYPTED FILE-----")
+        }
+        EncryptionScheme::Gpg => match head.first() {
+            Some(b'-') => head.starts_with(b"-----BEGIN PGP MESSAGE-----"),
+            // A public-key or symmetric-key encrypted session key packet, in
+            // the new or the old packet format
+            Some(&tag) if tag & 0xc0 == 0xc0 => matches!(tag & 0x3f, 1 | 3),
+            Some(&tag) if tag & 0x80 == 0x80 => matches!((tag >> 2) & 0x0f, 1 | 3),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_looks_encrypted() {
+        use EncryptionScheme::*;
+        assert!(looks_encrypted(
+            Age,
+            b"age-encryption.org/v1\n-> X25519 abc"
+        ));
+        assert!(looks_encrypted(
+            Age,
+            b"-----BEGIN AGE ENCRYPTED FILE-----\n"
+        ));
+        assert!(!looks_encrypted(Age, b"hello world"));
+        assert!(looks_encrypted(Gpg, b"-----BEGIN PGP MESSAGE-----\n"));
+        assert!(looks_encrypted(Gpg, &[0x85, 0x01, 0x0c]));
+        assert!(looks_encrypted(Gpg, &[0xc1, 0xc0, 0x4c]));
+        assert!(looks_encrypted(Gpg, &[0x8c, 0x0d]));
+        assert!(!looks_encrypted(Gpg, b"%PDF-1.7"));
+        assert!(!looks_encrypted(Gpg, &[0xa3, 0x01]));
+        assert!(!looks_encrypted(Gpg, b""));
+    }
+
+    #[test]
+    fn test_declared_encryption() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(declared_encryption(&headers).unwrap(), None);
+        headers.insert("x-encrypted", HeaderValue::from_static("AGE"));
+        headers.insert(
+            "x-encrypted-recipients",
+            HeaderValue::from_static("age1abc, age1def"),
+        );
+        let encryption = declared_encryption(&headers).unwrap().unwrap();
+        assert_eq!(encryption.scheme, EncryptionScheme::Age);
+        assert_eq!(encryption.recipients, ["age1abc", "age1def"]);
+        headers.insert("x-encrypted-recipients", HeaderValue::from_static("a b"));
+        assert!(declared_encryption(&headers).is_err());
+        headers.insert("x-encrypted", HeaderValue::from_static("rot13"));
+        assert!(declared_encryption(&headers).is_err());
+    }
+}
@@ -0,0 +1,127 @@
+use anyhow::Result;
+use bytes::Bytes;
+use futures_util::TryStreamExt;
+use headers::{CacheControl, HeaderMapExt};
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::Frame;
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::auth::AccessPaths;
+use crate::events::{ChangeEvent, ChangeKind, ProvenanceAction};
+
+use super::handlers::{Request, Server};
+use super::response_utils::{status_bad_request, status_forbid, Response};
+
+/// Comment line sent when nothing happened for a while, so proxies keep the
+/// connection open and dead clients are noticed.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+impl Server {
+    /// Handle the change stream (GET /__dufs__/events?path=<prefix>)
+    ///
+    /// Streams server-sent events for uploads, deletes, moves and new
+    /// provenance events under the prefix that the client may read.
+    pub(super) fn handle_events(&self, req: &Request, res: &mut Response) -> Result<()> {
+        let query = req.uri().query().unwrap_or_default();
+        let query_params: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let Some(prefix) = self.resolve_path(
+            query_params
+                .get("path")
+                .map(|v| v.as_str())
+                .unwrap_or_default(),
+        ) else {
+            status_bad_request(res, "Invalid Path");
+            return Ok(());
+        };
+        let access_paths =
+            match self.guard_as(&prefix, req.method(), req, query_params.get("token"), false) {
+                (_, Some(access_paths)) => access_paths,
+                (None, None) => return self.auth_reject(res),
+                (Some(_), None) => {
+                    status_forbid(res);
+                    return Ok(());
+                }
+            };
+        let prefix = format!("/{prefix}");
+
+        let events = self.events.clone();
+        let mut rx = events.subscribe();
+        let stream = async_stream::stream! {
+            yield Ok(Bytes::from_static(b": connected\n\n"));
+            loop {
+                let next = tokio::select! {
+                    _ = events.closed() => break,
+                    next = tokio::time::timeout(KEEP_ALIVE_INTERVAL, rx.recv()) => next,
+                };
+                let event = match next {
+                    Ok(Ok(event)) => event,
+                    Ok(Err(RecvError::Lagged(missed))) => {
+                        // Clients should reload the listing, they missed something
+                        yield Ok(Bytes::from(format!("event: lagged\ndata: {missed}\n\n")));
+                        continue;
+                    }
+                    Ok(Err(RecvError::Closed)) => break,
+                    Err(_) => {
+                        yield Ok(Bytes::from_static(b": keep-alive\n\n"));
+                        continue;
+                    }
+                };
+                if !event.is_under(&prefix) || !can_see(&access_paths, &prefix, &event) {
+                    continue;
+                }
+                yield serde_json::to_string(&event)
+                    .map(|data| Bytes::from(format!("event: {}\ndata: {data}\n\n", event.kind.as_str())))
+                    .map_err(anyhow::Error::from);
+            }
+        };
+
+        res.headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+        res.headers_mut()
+            .typed_insert(CacheControl::new().with_no_cache());
+        *res.body_mut() = StreamBody::new(stream.map_ok(Frame::data)).boxed();
+        Ok(())
+    }
+
+    /// Announce a change to `path` to event stream listeners, if the response
+    /// says the change went through
+    pub(super) fn publish_change(
+        &self,
+        kind: ChangeKind,
+        path: &Path,
+        to: Option<&Path>,
+        res: &Response,
+    ) {
+        if !res.status().is_success() {
+            return;
+        }
+        let mut event = ChangeEvent::new(kind, self.relative_path(path));
+        event.to = to.map(|v| self.relative_path(v));
+        self.events.publish(event);
+    }
+
+    /// Announce a new provenance event recorded for `path`, noting it on `res`
+    /// for the access log
+    pub(super) fn publish_provenance(&self, path: &Path, action: &str, res: &mut Response) {
+        res.extensions_mut()
+            .insert(ProvenanceAction(action.to_string()));
+        let mut event = ChangeEvent::new(ChangeKind::Provenance, self.relative_path(path));
+        event.action = Some(action.to_string());
+        self.events.publish(event);
+    }
+}
+
+/// Whether a listener with `access_paths` on `prefix` may read the event's paths
+fn can_see(access_paths: &AccessPaths, prefix: &str, event: &ChangeEvent) -> bool {
+    let readable = |path: &str| {
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| access_paths.find(rest).is_some())
+    };
+    readable(&event.path) || event.to.as_deref().is_some_and(readable)
+}
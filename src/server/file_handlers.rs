@@ -0,0 +1,1363 @@
+use anyhow::{bail, Result};
+use hyper::header::{HeaderValue, AUTHORIZATION, CONNECTION};
+use hyper::{Method, StatusCode};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use tokio::fs;
+
+use crate::auth::{AccessLimits, AccessPaths, AccessPerm};
+use crate::events::ChangeKind;
+use crate::file_utils;
+use crate::http_utils::body_full;
+use crate::i18n::Locale;
+use crate::provenance::VerificationKind;
+use crate::quota;
+
+use super::acl_handlers;
+use super::bundle_handlers;
+use super::handlers::{
+    has_query_flag, parse_upload_offset, partial_upload_path, reject_preconditions, ArchiveFormat,
+    Request, Server,
+};
+use super::metadata_handlers;
+use super::parts_handlers;
+use super::path_item::DataKind;
+use super::preview_handlers;
+use super::provenance_dav::VirtualPath;
+use super::provenance_handlers;
+use super::response_utils::*;
+use super::router::Operation;
+use super::user_key_handlers;
+use super::visibility_handlers::{self, PublicOnly};
+use super::webdav;
+
+/// A request for a file or folder that got past the access checks, with what
+/// the handlers of its operation need to know
+struct FileRequest {
+    path: PathBuf,
+    /// The request path without `api/`, as WebDAV hrefs use it
+    href: String,
+    query_params: HashMap<String, String>,
+    user: Option<String>,
+    access_paths: AccessPaths,
+    /// Limits of the grant, e.g. `/inbox:w` or `/public:ro,noarchive`
+    limits: AccessLimits,
+    public_only: Option<PublicOnly>,
+    locale: Locale,
+    requester_ip: Option<IpAddr>,
+    is_miss: bool,
+    is_dir: bool,
+    is_file: bool,
+    size: u64,
+}
+
+/// What a POST to a path does, picked by the first of its query flags in
+/// this order, so the permission check and the handler agree on it. Zip and
+/// copy only need read access, so they come last and never hide another flag.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PostAction {
+    Acl,
+    Verify,
+    Ots,
+    Sign,
+    Signature,
+    ProvenanceImport,
+    Transfer,
+    Share,
+    Metadata,
+    Zip,
+    Copy,
+    Unknown,
+}
+
+impl PostAction {
+    fn of(query_params: &HashMap<String, String>) -> Self {
+        let flag = |name| has_query_flag(query_params, name);
+        if flag("acl") {
+            Self::Acl
+        } else if flag("verify") {
+            Self::Verify
+        } else if flag("ots") {
+            Self::Ots
+        } else if flag("sign") {
+            Self::Sign
+        } else if flag("signature") {
+            Self::Signature
+        } else if flag("provenance-import") {
+            Self::ProvenanceImport
+        } else if flag("transfer") {
+            Self::Transfer
+        } else if flag("share") {
+            Self::Share
+        } else if flag("meta") || flag("comment") || flag("tags") {
+            Self::Metadata
+        } else if flag("zip") {
+            Self::Zip
+        } else if query_params.contains_key("copy") {
+            Self::Copy
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+/// Who may access the path of a file request, found by the `Auth` layer
+#[derive(Debug, Clone)]
+pub(super) enum FileAccess {
+    Granted {
+        user: Option<String>,
+        access_paths: AccessPaths,
+    },
+    /// An upload to `--drop-box` from a visitor without an account
+    AnonymousDrop,
+}
+
+/// What a file request is for: the request path without `api/`, as WebDAV
+/// hrefs use it, its path relative to the served folder and its query
+struct FileTarget {
+    req_path: String,
+    relative_path: String,
+    query_params: HashMap<String, String>,
+    post_action: Option<PostAction>,
+}
+
+impl FileTarget {
+    /// Proofs under the virtual `.provenance` folder follow the rules of the
+    /// files they describe
+    fn guard_path(&self) -> String {
+        VirtualPath::parse(&self.relative_path)
+            .map(|v| v.target().to_string())
+            .unwrap_or_else(|| self.relative_path.clone())
+    }
+
+    /// Starring, copying or zipping a path only needs read access to it
+    fn perm_method(&self, op: Operation, method: &Method) -> Method {
+        if op == Operation::Star
+            || self.post_action == Some(PostAction::Copy)
+            || self.post_action == Some(PostAction::Zip)
+        {
+            Method::GET
+        } else {
+            method.clone()
+        }
+    }
+}
+
+fn is_microsoft_webdav(req: &Request) -> bool {
+    req.headers()
+        .get("user-agent")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.to_lowercase().starts_with("microsoft-webdav-miniredir/"))
+}
+
+impl Server {
+    /// The path and query of a file request, or `None` for an invalid path
+    fn file_target(&self, op: Operation, req: &Request) -> Option<FileTarget> {
+        // For API requests, strip only the /api part (not the path prefix)
+        // E.g., /dufs/api/index.html becomes /dufs/index.html
+        // This allows resolve_path to strip the path prefix correctly
+        let uri_path = req.uri().path();
+        let uri_prefix = &self.args.uri_prefix;
+        let api_prefix = format!("{uri_prefix}api");
+        let req_path = match uri_path.strip_prefix(&format!("{api_prefix}/")) {
+            Some(rest) => format!("{uri_prefix}{rest}"),
+            None if uri_path == api_prefix => uri_prefix.clone(),
+            None => uri_path.to_string(),
+        };
+        let relative_path = self.resolve_path(&req_path)?;
+
+        let query = req.uri().query().unwrap_or_default();
+        let query_params: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let post_action = (op == Operation::Post).then(|| PostAction::of(&query_params));
+        Some(FileTarget {
+            req_path,
+            relative_path,
+            query_params,
+            post_action,
+        })
+    }
+
+    /// Find who may access the path of a file request, or answer it in `res`
+    /// with the reason nobody may
+    pub(super) fn authorize_file_request(
+        &self,
+        op: Operation,
+        req: &Request,
+        res: &mut Response,
+    ) -> Result<Option<FileAccess>> {
+        let Some(target) = self.file_target(op, req) else {
+            status_bad_request(res, "Invalid Path");
+            return Ok(None);
+        };
+        let is_microsoft_webdav = is_microsoft_webdav(req);
+        if is_microsoft_webdav {
+            res.headers_mut()
+                .insert(CONNECTION, HeaderValue::from_static("close"));
+        }
+        let method = req.method();
+        let perm_method = target.perm_method(op, method);
+        let guard_path = target.guard_path();
+        let guard = self.guard_as(
+            &guard_path,
+            &perm_method,
+            req,
+            target.query_params.get("token"),
+            is_microsoft_webdav,
+        );
+
+        let access = match guard {
+            (None, None) => {
+                let query = req.uri().query().unwrap_or_default();
+                if self.is_anonymous_drop(&guard_path, method, req.headers(), query) {
+                    return Ok(Some(FileAccess::AnonymousDrop));
+                }
+                let locked = self
+                    .login_attempt(req)
+                    .and_then(|(ip, user)| self.logins.retry_after(ip, &user));
+                if let Some(retry_after) = locked {
+                    status_too_many_requests(res, retry_after);
+                } else if !self.oidc_login_redirect(req, res)? {
+                    self.auth_reject(res)?;
+                }
+                return Ok(None);
+            }
+            // ACL grants can't be used to manage ACLs, whatever the value of `acl`
+            (Some(user), None) if !target.query_params.contains_key("acl") => {
+                match self.acl_access(&user, &guard_path, &perm_method)? {
+                    Some(access_paths) => FileAccess::Granted {
+                        user: Some(user),
+                        access_paths,
+                    },
+                    None => {
+                        status_forbid(res);
+                        return Ok(None);
+                    }
+                }
+            }
+            (Some(_), None) => {
+                status_forbid(res);
+                return Ok(None);
+            }
+            (user, Some(access_paths)) => FileAccess::Granted { user, access_paths },
+        };
+        Ok(Some(access))
+    }
+
+    /// Hand a file request that got past the `Auth` layer to the handler of
+    /// its operation
+    pub(super) async fn handle_file_route(
+        &self,
+        op: Operation,
+        mut req: Request,
+        res: &mut Response,
+    ) -> Result<()> {
+        let Some(access) = req.extensions_mut().remove::<FileAccess>() else {
+            bail!("File request reached its handler without authorization");
+        };
+        let Some(target) = self.file_target(op, &req) else {
+            status_bad_request(res, "Invalid Path");
+            return Ok(());
+        };
+        if is_microsoft_webdav(&req) {
+            res.headers_mut()
+                .insert(CONNECTION, HeaderValue::from_static("close"));
+        }
+        let (user, access_paths) = match access {
+            FileAccess::Granted { user, access_paths } => (user, access_paths),
+            FileAccess::AnonymousDrop => {
+                self.handle_anonymous_drop(&target.relative_path, req, res)
+                    .await?;
+                return Ok(());
+            }
+        };
+        let guard_path = target.guard_path();
+        let guard_path = guard_path.as_str();
+        let FileTarget {
+            req_path,
+            relative_path,
+            query_params,
+            post_action,
+        } = target;
+        let virtual_path = VirtualPath::parse(&relative_path);
+        let headers = req.headers();
+        let method = req.method().clone();
+        let requester_ip = req.extensions().get::<SocketAddr>().map(|v| v.ip());
+
+        match op {
+            Operation::CheckAuth => {
+                match user {
+                    Some(user) => {
+                        *res.body_mut() = body_full(user);
+                    }
+                    None => {
+                        if has_query_flag(&query_params, "login")
+                            || !access_paths.perm().readwrite()
+                        {
+                            self.auth_reject(res)?
+                        } else {
+                            *res.body_mut() = body_full("");
+                        }
+                    }
+                }
+                return Ok(());
+            }
+            Operation::Logout => {
+                self.clear_session(headers, res)?;
+                self.auth_reject(res)?;
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        // Limits of the grant, e.g. `/inbox:w` or `/public:ro,noarchive`
+        let limits = access_paths.limits();
+        let archive = ArchiveFormat::from_query(&query_params).is_some()
+            || post_action == Some(PostAction::Zip);
+        if !limits.allow(&method, archive) {
+            status_forbid(res);
+            return Ok(());
+        }
+
+        if has_query_flag(&query_params, "tokengen") {
+            self.handle_tokengen(&relative_path, user, &access_paths, &query_params, res)
+                .await?;
+            return Ok(());
+        }
+
+        let public_only = self.public_only(&user, &access_paths)?;
+        if let Some(public_only) = &public_only {
+            if self
+                .join_path(guard_path)
+                .is_some_and(|v| public_only.is_private(&v))
+            {
+                // Let anonymous visitors log in, like any other denied path
+                if user.is_none() {
+                    self.auth_reject(res)?;
+                } else {
+                    status_not_found(res);
+                }
+                return Ok(());
+            }
+        }
+
+        if !self.mounts.is_empty() {
+            self.handle_mounts_index(
+                &relative_path,
+                &method,
+                headers,
+                &query_params,
+                user,
+                access_paths,
+                res,
+            )
+            .await?;
+            return Ok(());
+        }
+
+        if let Some(virtual_path) = virtual_path.filter(|_| !self.args.path_is_file) {
+            self.handle_provenance_dav(
+                virtual_path,
+                &method,
+                headers,
+                access_paths,
+                public_only.as_ref(),
+                requester_ip,
+                &user,
+                res,
+            )
+            .await?;
+            return Ok(());
+        }
+
+        if self.args.path_is_file {
+            if self
+                .single_file_req_paths
+                .iter()
+                .any(|v| v.as_str() == req_path)
+            {
+                let head_only = method == Method::HEAD;
+                self.handle_send_file(&self.args.serve_path, headers, head_only, res)
+                    .await?;
+            } else {
+                status_not_found(res);
+            }
+            return Ok(());
+        }
+
+        let path = match self.join_path(&relative_path) {
+            Some(v) => v,
+            None => {
+                status_forbid(res);
+                return Ok(());
+            }
+        };
+
+        let file_info = file_utils::get_file_info(&path).await;
+        if !self.args.allow_symlink && file_info.exists && !self.is_root_contained(&path).await {
+            status_not_found(res);
+            return Ok(());
+        }
+
+        let locale = self.locale(headers);
+        let file = FileRequest {
+            path,
+            href: req_path,
+            query_params,
+            user,
+            access_paths,
+            limits,
+            public_only,
+            locale,
+            requester_ip,
+            is_miss: !file_info.exists,
+            is_dir: file_info.is_dir,
+            is_file: file_info.is_file,
+            size: file_info.size,
+        };
+        match op {
+            Operation::Read => self.handle_read_request(req, file, res).await,
+            Operation::Options => {
+                set_webdav_headers(res);
+                Ok(())
+            }
+            Operation::Upload => self.handle_upload_request(req, file, res).await,
+            Operation::Unpack => self.handle_unpack_request(req, file, res).await,
+            Operation::Edit => self.handle_edit_request(req, file, res).await,
+            Operation::Star => self.handle_star_request(req, file, res).await,
+            Operation::Post => {
+                let action = post_action.unwrap_or(PostAction::Unknown);
+                self.handle_post_request(action, req, file, res).await
+            }
+            Operation::Delete => self.handle_delete_request(req, file, res).await,
+            Operation::Propfind => self.handle_propfind_request(req, file, res).await,
+            Operation::Proppatch => self.handle_proppatch_request(req, file, res).await,
+            Operation::Mkcol => self.handle_mkcol_request(req, file, res).await,
+            Operation::Copy => self.handle_copy_request(req, file, res).await,
+            Operation::Move => self.handle_move_request(req, file, res).await,
+            Operation::Lock => self.handle_lock_request(req, file, res).await,
+            Operation::Unlock => self.handle_unlock_request(req, file, res).await,
+            // Answered before the path is looked up
+            Operation::CheckAuth | Operation::Logout => Ok(()),
+            Operation::Unsupported => {
+                *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+                Ok(())
+            }
+        }
+    }
+
+    /// Downloads, folder listings and searches, and the read-only queries of files
+    async fn handle_read_request(
+        &self,
+        req: Request,
+        file: FileRequest,
+        res: &mut Response,
+    ) -> Result<()> {
+        let FileRequest {
+            path,
+            href,
+            query_params,
+            user,
+            access_paths,
+            public_only,
+            locale,
+            requester_ip,
+            is_miss,
+            is_dir,
+            is_file,
+            ..
+        } = file;
+        let path = path.as_path();
+        let headers = req.headers();
+        let head_only = req.method() == Method::HEAD;
+        let allow_upload = self.args.allow_upload;
+        let allow_search = self.args.allow_search;
+        let allow_archive = self.args.allow_archive;
+        if is_dir {
+            // For API requests, always return JSON (never HTML)
+            if let Some(format) = ArchiveFormat::from_query(&query_params) {
+                if !allow_archive {
+                    status_not_found(res);
+                    return Ok(());
+                }
+                self.handle_archive_dir(path, format, head_only, access_paths, public_only, res)
+                    .await?;
+            } else if has_query_flag(&query_params, "acl") {
+                if access_paths.perm().readwrite() {
+                    acl_handlers::handle_get_acl(path, &self.provenance_db, res).await?;
+                } else {
+                    status_forbid(res);
+                }
+            } else if has_query_flag(&query_params, "tags") {
+                metadata_handlers::handle_list_tags(path, &self.provenance_db, res).await?;
+            } else if has_query_flag(&query_params, "starred") {
+                let Some(owner) = self.favorites_owner(&user) else {
+                    self.auth_reject(res)?;
+                    return Ok(());
+                };
+                self.handle_api_starred(
+                    path,
+                    &owner,
+                    &query_params,
+                    head_only,
+                    user,
+                    locale,
+                    access_paths,
+                    public_only.as_ref(),
+                    res,
+                )
+                .await?;
+            } else if has_query_flag(&query_params, "tree") {
+                self.handle_tree(
+                    path,
+                    &query_params,
+                    head_only,
+                    access_paths,
+                    public_only.as_ref(),
+                    res,
+                )
+                .await?;
+            } else if has_query_flag(&query_params, "recursive") {
+                self.handle_api_recursive(
+                    path,
+                    &query_params,
+                    head_only,
+                    user,
+                    locale,
+                    access_paths,
+                    public_only.as_ref(),
+                    res,
+                )
+                .await?;
+            } else if allow_search
+                && (query_params.contains_key("q") || query_params.contains_key("tag"))
+            {
+                self.handle_api_search(
+                    path,
+                    &query_params,
+                    head_only,
+                    user,
+                    locale,
+                    access_paths,
+                    public_only.as_ref(),
+                    res,
+                )
+                .await?;
+            } else {
+                // Directory listing - return JSON
+                self.handle_api_index(
+                    path,
+                    true,
+                    &query_params,
+                    head_only,
+                    user,
+                    locale,
+                    access_paths,
+                    public_only.as_ref(),
+                    res,
+                )
+                .await?;
+            }
+        } else if is_file {
+            if has_query_flag(&query_params, "edit") {
+                self.handle_edit_file(path, DataKind::Edit, head_only, user, locale, res)
+                    .await?;
+            } else if has_query_flag(&query_params, "view") {
+                self.handle_edit_file(path, DataKind::View, head_only, user, locale, res)
+                    .await?;
+            } else if has_query_flag(&query_params, "hash") {
+                provenance_handlers::handle_hash_file(path, head_only, res).await?;
+            } else if has_query_flag(&query_params, "signature-blocks") {
+                self.handle_signature_blocks(path, &query_params, head_only, res)
+                    .await?;
+            } else if query_params.contains_key("parts") {
+                parts_handlers::handle_parts_manifest(path, &query_params, head_only, res).await?;
+            } else if has_query_flag(&query_params, "render") {
+                preview_handlers::handle_render_file(path, head_only, res).await?;
+            } else if query_params.contains_key("preview") {
+                preview_handlers::handle_preview_file(path, &query_params, head_only, res).await?;
+            } else if query_params.get("manifest") == Some(&"json".to_string()) {
+                provenance_handlers::handle_provenance_manifest(
+                    path,
+                    head_only,
+                    &self.provenance_db,
+                    res,
+                )
+                .await?;
+                if !head_only {
+                    let success = res.status().is_success();
+                    self.record_verification(
+                        path,
+                        VerificationKind::Manifest,
+                        requester_ip,
+                        &user,
+                        success,
+                    );
+                }
+            } else if has_query_flag(&query_params, "provenance-bundle") {
+                bundle_handlers::handle_bundle_export(
+                    path,
+                    head_only,
+                    self.args.compress.to_compression(),
+                    &self.provenance_db,
+                    res,
+                )
+                .await?;
+            } else if has_query_flag(&query_params, "ots-info") {
+                provenance_handlers::handle_ots_info(path, head_only, &self.provenance_db, res)
+                    .await?;
+            } else if has_query_flag(&query_params, "ots") {
+                provenance_handlers::handle_ots_download(path, head_only, &self.provenance_db, res)
+                    .await?;
+                if !head_only {
+                    let success = res.status().is_success();
+                    self.record_verification(
+                        path,
+                        VerificationKind::Ots,
+                        requester_ip,
+                        &user,
+                        success,
+                    );
+                }
+            } else if has_query_flag(&query_params, "verify-chain") {
+                provenance_handlers::handle_verify_chain(path, &self.provenance_db, res).await?;
+            } else if has_query_flag(&query_params, "stamp-status") {
+                provenance_handlers::handle_stamp_status(
+                    path,
+                    &self.provenance_db,
+                    &self.stamp_statuses,
+                    res,
+                )
+                .await?;
+            } else if has_query_flag(&query_params, "audit") {
+                if access_paths.perm().readwrite() {
+                    provenance_handlers::handle_verification_log(path, &self.provenance_db, res)
+                        .await?;
+                } else if user.is_none() {
+                    self.auth_reject(res)?;
+                } else {
+                    status_forbid(res);
+                }
+            } else if has_query_flag(&query_params, "share_info") {
+                provenance_handlers::handle_share_info(path, &self.provenance_db, res).await?;
+            } else if has_query_flag(&query_params, "acl") {
+                if access_paths.perm().readwrite() {
+                    acl_handlers::handle_get_acl(path, &self.provenance_db, res).await?;
+                } else {
+                    status_forbid(res);
+                }
+            } else if has_query_flag(&query_params, "tags") {
+                metadata_handlers::handle_get_tags(path, &self.provenance_db, res).await?;
+            } else if has_query_flag(&query_params, "meta") {
+                metadata_handlers::handle_get_annotations(path, &self.provenance_db, res).await?;
+            } else {
+                self.handle_send_file(path, headers, head_only, res).await?;
+                if !head_only {
+                    self.record_download(path, res);
+                }
+            }
+        } else if is_miss && allow_upload && href.ends_with('/') {
+            // Non-existent directory - return empty JSON listing for API
+            self.handle_api_index(
+                path,
+                false,
+                &query_params,
+                head_only,
+                user,
+                locale,
+                access_paths,
+                public_only.as_ref(),
+                res,
+            )
+            .await?;
+        } else {
+            status_not_found(res);
+        }
+        Ok(())
+    }
+
+    /// PUT of a whole file, replacing an existing one only when the grant may delete it
+    async fn handle_upload_request(
+        &self,
+        req: Request,
+        file: FileRequest,
+        res: &mut Response,
+    ) -> Result<()> {
+        let FileRequest {
+            path,
+            user,
+            limits,
+            is_dir,
+            size,
+            ..
+        } = file;
+        let path = path.as_path();
+        let headers = req.headers();
+        let allow_upload = self.args.allow_upload;
+        let allow_delete = self.args.allow_delete;
+        let may_overwrite = allow_delete && !limits.no_delete && !limits.upload_only;
+        if is_dir || !allow_upload || (!may_overwrite && size > 0) {
+            status_forbid(res);
+        } else if !webdav::reject_locked(&self.locks, path, false, headers, res)
+            && !reject_preconditions(path, headers, res).await
+        {
+            self.handle_upload(path, user.as_deref(), None, size, req, res)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// PUT `?unzip` of an archive into a folder
+    async fn handle_unpack_request(
+        &self,
+        req: Request,
+        file: FileRequest,
+        res: &mut Response,
+    ) -> Result<()> {
+        let FileRequest {
+            path,
+            query_params,
+            user,
+            is_miss,
+            is_dir,
+            ..
+        } = file;
+        let path = path.as_path();
+        let headers = req.headers();
+        let allow_upload = self.args.allow_upload;
+        let allow_delete = self.args.allow_delete;
+        if !allow_upload || (!is_miss && !is_dir) {
+            status_forbid(res);
+        } else if !webdav::reject_locked(&self.locks, path, true, headers, res) {
+            let overwrite = allow_delete && has_query_flag(&query_params, "overwrite");
+            self.handle_unpack(path, user.as_deref(), overwrite, req, res)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// PATCH of a file: resumed uploads, writes at an offset and deltas
+    async fn handle_edit_request(
+        &self,
+        req: Request,
+        file: FileRequest,
+        res: &mut Response,
+    ) -> Result<()> {
+        let FileRequest {
+            path,
+            query_params,
+            user,
+            limits,
+            is_miss,
+            size,
+            ..
+        } = file;
+        let path = path.as_path();
+        let headers = req.headers();
+        let allow_upload = self.args.allow_upload;
+        let allow_delete = self.args.allow_delete;
+        // An interrupted PUT is resumed at the end of its `.partial` file
+        let resumed_size = match is_miss {
+            true => fs::metadata(partial_upload_path(path))
+                .await
+                .ok()
+                .map(|v| v.len()),
+            false => None,
+        };
+        let size = resumed_size.unwrap_or(size);
+        if is_miss && resumed_size.is_none() {
+            status_not_found(res);
+        } else if let Some(visibility) = query_params.get("visibility") {
+            visibility_handlers::handle_set_visibility(
+                path,
+                visibility,
+                user,
+                &self.provenance_db,
+                res,
+            )
+            .await?;
+        } else if !allow_upload {
+            status_forbid(res);
+        } else if !webdav::reject_locked(&self.locks, path, false, headers, res)
+            && !reject_preconditions(path, headers, res).await
+        {
+            // Rewriting bytes is an overwrite, held to the rule of PUT, while
+            // `:w` grants may only append to their own interrupted uploads
+            let may_overwrite = allow_delete && !limits.no_delete && !limits.upload_only;
+            let may_append = resumed_size.is_some() || !limits.upload_only;
+            if has_query_flag(&query_params, "delta") {
+                if !may_overwrite {
+                    status_forbid(res);
+                } else {
+                    self.handle_delta(path, user.as_deref(), size, req, res)
+                        .await?;
+                }
+                return Ok(());
+            }
+            let offset = match parse_upload_offset(headers, size) {
+                Ok(v) => v,
+                Err(err) => {
+                    status_bad_request(res, &err.to_string());
+                    return Ok(());
+                }
+            };
+            match offset {
+                Some(offset) => {
+                    if (offset < size && !may_overwrite) || !may_append {
+                        status_forbid(res);
+                        return Ok(());
+                    }
+                    self.handle_upload(path, user.as_deref(), Some(offset), size, req, res)
+                        .await?;
+                }
+                None => {
+                    *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Star (POST) or unstar (DELETE) a path for the user
+    async fn handle_star_request(
+        &self,
+        req: Request,
+        file: FileRequest,
+        res: &mut Response,
+    ) -> Result<()> {
+        let FileRequest {
+            path,
+            user,
+            is_miss,
+            ..
+        } = file;
+        let path = path.as_path();
+        let method = req.method();
+        let Some(owner) = self.favorites_owner(&user) else {
+            self.auth_reject(res)?;
+            return Ok(());
+        };
+        if is_miss && method == Method::POST {
+            status_not_found(res);
+        } else {
+            metadata_handlers::handle_star(
+                path,
+                method == Method::POST,
+                &owner,
+                &self.provenance_db,
+                res,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// POST of a path, with the action picked by its query
+    async fn handle_post_request(
+        &self,
+        action: PostAction,
+        req: Request,
+        file: FileRequest,
+        res: &mut Response,
+    ) -> Result<()> {
+        let FileRequest {
+            path,
+            query_params,
+            user,
+            access_paths,
+            public_only,
+            requester_ip,
+            is_miss,
+            is_dir,
+            size,
+            ..
+        } = file;
+        let path = path.as_path();
+        let allow_upload = self.args.allow_upload;
+        let allow_archive = self.args.allow_archive;
+        match action {
+            PostAction::Acl => {
+                if is_miss {
+                    status_not_found(res);
+                } else {
+                    acl_handlers::handle_grant_acl(
+                        path,
+                        req,
+                        user,
+                        &self.args.auth,
+                        &self.provenance_db,
+                        res,
+                    )
+                    .await?;
+                }
+            }
+            PostAction::Verify => {
+                let verified =
+                    provenance_handlers::handle_ots_verify(req, &self.provenance_db, res).await?;
+                self.record_verification(
+                    path,
+                    VerificationKind::Verify,
+                    requester_ip,
+                    &user,
+                    verified,
+                );
+            }
+            PostAction::Ots => {
+                if is_miss || is_dir {
+                    status_not_found(res);
+                } else {
+                    provenance_handlers::handle_ots_upload(path, req, &self.provenance_db, res)
+                        .await?;
+                }
+            }
+            PostAction::Sign => {
+                if is_miss || is_dir {
+                    status_not_found(res);
+                } else if !allow_upload {
+                    status_forbid(res);
+                } else {
+                    user_key_handlers::handle_sign_mint(path, req, &self.provenance_db, res)
+                        .await?;
+                }
+            }
+            PostAction::Signature => {
+                if is_miss || is_dir {
+                    status_not_found(res);
+                } else if !allow_upload {
+                    status_forbid(res);
+                } else {
+                    user_key_handlers::handle_attestation(path, req, &self.provenance_db, res)
+                        .await?;
+                    if res.status() == StatusCode::CREATED {
+                        self.publish_provenance(path, "attestation", res);
+                    }
+                }
+            }
+            PostAction::ProvenanceImport => {
+                if !allow_upload {
+                    status_forbid(res);
+                } else if !is_miss {
+                    status_conflict(res, "The file already exists");
+                } else if !self.reject_upload_type(path, None, res) {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    bundle_handlers::handle_bundle_import(
+                        path,
+                        req,
+                        self.args.upload_limit(&name),
+                        &self.provenance_db,
+                        res,
+                    )
+                    .await?;
+                    if res.status() == StatusCode::CREATED {
+                        self.publish_change(ChangeKind::Upload, path, None, res);
+                        self.publish_provenance(path, "import", res);
+                    }
+                }
+            }
+            PostAction::Zip => {
+                if !is_dir || !allow_archive {
+                    status_not_found(res);
+                } else {
+                    self.handle_zip_selection(path, req, access_paths, public_only, res)
+                        .await?;
+                }
+            }
+            PostAction::Copy => {
+                let dest = &query_params["copy"];
+                if is_miss {
+                    status_not_found(res);
+                } else if !allow_upload {
+                    status_forbid(res);
+                } else {
+                    self.handle_copy_to(
+                        path,
+                        dest,
+                        is_dir,
+                        size,
+                        has_query_flag(&query_params, "overwrite"),
+                        user.as_deref(),
+                        &req,
+                        res,
+                    )
+                    .await?;
+                }
+            }
+            PostAction::Transfer => {
+                if is_miss || is_dir {
+                    status_not_found(res);
+                } else if !allow_upload {
+                    status_forbid(res);
+                } else {
+                    provenance_handlers::handle_transfer(
+                        path,
+                        req,
+                        &self.provenance_db,
+                        &self.ots,
+                        res,
+                    )
+                    .await?;
+                    if res.status() == StatusCode::CREATED {
+                        self.publish_provenance(path, "transfer", res);
+                    }
+                }
+            }
+            PostAction::Share => {
+                if is_miss || is_dir {
+                    status_not_found(res);
+                } else {
+                    provenance_handlers::handle_create_share(
+                        path,
+                        user,
+                        req,
+                        &self.keypair,
+                        &self.provenance_db,
+                        res,
+                    )
+                    .await?;
+                }
+            }
+            PostAction::Metadata => {
+                if is_miss || is_dir {
+                    status_not_found(res);
+                } else if !allow_upload {
+                    status_forbid(res);
+                } else if has_query_flag(&query_params, "meta") {
+                    metadata_handlers::handle_update_metadata(
+                        path,
+                        req,
+                        user,
+                        &self.provenance_db,
+                        res,
+                    )
+                    .await?;
+                } else if has_query_flag(&query_params, "tags") {
+                    metadata_handlers::handle_update_tags(path, req, &self.provenance_db, res)
+                        .await?;
+                } else {
+                    metadata_handlers::handle_add_comment(
+                        path,
+                        req,
+                        user,
+                        &self.provenance_db,
+                        res,
+                    )
+                    .await?;
+                }
+            }
+            PostAction::Unknown => {
+                *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            }
+        }
+        Ok(())
+    }
+
+    /// DELETE of a path, or of its share, ACL grant or comment
+    async fn handle_delete_request(
+        &self,
+        req: Request,
+        file: FileRequest,
+        res: &mut Response,
+    ) -> Result<()> {
+        let FileRequest {
+            path,
+            query_params,
+            user,
+            is_miss,
+            is_dir,
+            ..
+        } = file;
+        let path = path.as_path();
+        let headers = req.headers();
+        let allow_upload = self.args.allow_upload;
+        let allow_delete = self.args.allow_delete;
+        // Check if this is a share deletion
+        if let Some(share_id) = query_params.get("share") {
+            provenance_handlers::handle_delete_share(share_id, user, &self.provenance_db, res)
+                .await?;
+        } else if let Some(acl_user) = query_params.get("acl") {
+            acl_handlers::handle_revoke_acl(path, acl_user, &self.provenance_db, res).await?;
+        } else if let Some(comment_id) = query_params.get("comment") {
+            if !allow_upload {
+                status_forbid(res);
+            } else {
+                metadata_handlers::handle_delete_comment(
+                    path,
+                    comment_id,
+                    &self.provenance_db,
+                    res,
+                )
+                .await?;
+            }
+        } else if !allow_delete {
+            status_forbid(res);
+        } else if !is_miss {
+            if webdav::reject_locked(&self.locks, path, is_dir, headers, res)
+                || reject_preconditions(path, headers, res).await
+            {
+                return Ok(());
+            }
+            self.handle_delete(path, is_dir, user.as_deref(), res)
+                .await?;
+            if res.status().is_success() {
+                self.locks.forget(path);
+            }
+            self.publish_change(ChangeKind::Delete, path, None, res);
+        } else {
+            status_not_found(res);
+        }
+        Ok(())
+    }
+
+    async fn handle_propfind_request(
+        &self,
+        req: Request,
+        file: FileRequest,
+        res: &mut Response,
+    ) -> Result<()> {
+        let FileRequest {
+            path,
+            access_paths,
+            public_only,
+            is_dir,
+            is_file,
+            ..
+        } = file;
+        let path = path.as_path();
+        let headers = req.headers();
+        let authorization = headers.get(AUTHORIZATION);
+        if is_dir {
+            let access_paths = if access_paths.perm().indexonly() && authorization.is_none() {
+                AccessPaths::new(AccessPerm::ReadOnly)
+            } else {
+                access_paths
+            };
+            self.handle_propfind_dir(path, headers, access_paths, public_only.as_ref(), res)
+                .await?;
+        } else if is_file {
+            self.handle_propfind_file(path, res).await?;
+        } else {
+            status_not_found(res);
+        }
+        Ok(())
+    }
+
+    async fn handle_proppatch_request(
+        &self,
+        req: Request,
+        file: FileRequest,
+        res: &mut Response,
+    ) -> Result<()> {
+        let FileRequest {
+            path,
+            href,
+            is_miss,
+            ..
+        } = file;
+        let path = path.as_path();
+        let headers = req.headers();
+        let allow_upload = self.args.allow_upload;
+        if is_miss {
+            status_not_found(res);
+        } else if !allow_upload {
+            status_forbid(res);
+        } else if !webdav::reject_locked(&self.locks, path, false, headers, res) {
+            webdav::handle_proppatch(path, &href, req, &self.provenance_db, res).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_mkcol_request(
+        &self,
+        req: Request,
+        file: FileRequest,
+        res: &mut Response,
+    ) -> Result<()> {
+        let FileRequest {
+            path,
+            user,
+            is_miss,
+            ..
+        } = file;
+        let path = path.as_path();
+        let headers = req.headers();
+        let allow_upload = self.args.allow_upload;
+        if !allow_upload {
+            status_forbid(res);
+        } else if !is_miss {
+            *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            *res.body_mut() = body_full("Already exists");
+        } else if !webdav::reject_locked(&self.locks, path, false, headers, res)
+            && !self
+                .reject_over_quota(path, user.as_deref(), 0, None, res)
+                .await?
+        {
+            webdav::handle_mkcol(path, res).await?;
+            self.publish_change(ChangeKind::Mkdir, path, None, res);
+        }
+        Ok(())
+    }
+
+    /// WebDAV COPY to the `Destination` header
+    async fn handle_copy_request(
+        &self,
+        req: Request,
+        file: FileRequest,
+        res: &mut Response,
+    ) -> Result<()> {
+        let FileRequest {
+            path,
+            href,
+            user,
+            is_miss,
+            is_dir,
+            size,
+            ..
+        } = file;
+        let path = path.as_path();
+        let headers = req.headers();
+        let allow_upload = self.args.allow_upload;
+        if !allow_upload {
+            status_forbid(res);
+        } else if is_miss {
+            status_not_found(res);
+        } else {
+            let Some((dest, dest_access)) = self.extract_dest(&req, res) else {
+                return Ok(());
+            };
+            let deep = headers
+                .get("depth")
+                .is_none_or(|v| !v.as_bytes().eq_ignore_ascii_case(b"0"));
+            let copied_size = if is_dir && deep && !self.args.quota.is_empty() {
+                let dir = path.to_path_buf();
+                tokio::task::spawn_blocking(move || quota::disk_usage(&dir)).await?
+            } else {
+                size
+            };
+            if (!is_dir && self.reject_upload_type(&dest, Some(size), res))
+                || webdav::reject_locked(&self.locks, &dest, true, headers, res)
+                || self
+                    .reject_over_quota(&dest, user.as_deref(), copied_size, None, res)
+                    .await?
+                || !self
+                    .prepare_dest(path, &dest, &dest_access, user.as_deref(), headers, res)
+                    .await?
+            {
+                return Ok(());
+            }
+            let copied = webdav::handle_copy(path, &dest, deep, &href, res).await?;
+            if let (Some(from), Some(to)) = (path.to_str(), dest.to_str()) {
+                self.provenance_db.copy_dead_properties(from, to)?;
+            }
+            for (file, size) in copied {
+                if let Some(file) = file.to_str() {
+                    self.provenance_db
+                        .set_file_owner(file, user.as_deref(), size)?;
+                }
+            }
+            self.publish_change(ChangeKind::Copy, path, Some(&dest), res);
+        }
+        Ok(())
+    }
+
+    /// WebDAV MOVE to the `Destination` header
+    async fn handle_move_request(
+        &self,
+        req: Request,
+        file: FileRequest,
+        res: &mut Response,
+    ) -> Result<()> {
+        let FileRequest {
+            path,
+            user,
+            is_miss,
+            is_dir,
+            size,
+            ..
+        } = file;
+        let path = path.as_path();
+        let headers = req.headers();
+        let allow_upload = self.args.allow_upload;
+        let allow_delete = self.args.allow_delete;
+        if !allow_upload || !allow_delete {
+            status_forbid(res);
+        } else if is_miss {
+            status_not_found(res);
+        } else {
+            let Some((dest, dest_access)) = self.extract_dest(&req, res) else {
+                return Ok(());
+            };
+            let depth = headers.get("depth").map(|v| v.as_bytes());
+            if is_dir && depth.is_some_and(|v| !v.eq_ignore_ascii_case(b"infinity")) {
+                status_bad_request(res, "Moving a folder requires Depth: infinity");
+                return Ok(());
+            }
+            // Renaming a file into a refused type counts as uploading it
+            if (!is_dir && self.reject_upload_type(&dest, Some(size), res))
+                || webdav::reject_locked(&self.locks, path, is_dir, headers, res)
+                || webdav::reject_locked(&self.locks, &dest, true, headers, res)
+            {
+                return Ok(());
+            }
+            let moved = if is_dir && !self.args.quota.is_empty() {
+                let dir = path.to_path_buf();
+                tokio::task::spawn_blocking(move || quota::disk_usage(&dir)).await?
+            } else {
+                size
+            };
+            if self
+                .reject_over_quota(&dest, None, moved, Some(path), res)
+                .await?
+                || !self
+                    .prepare_dest(path, &dest, &dest_access, user.as_deref(), headers, res)
+                    .await?
+            {
+                return Ok(());
+            }
+            webdav::handle_move(path, &dest, res, Some(&self.provenance_db)).await?;
+            self.locks.forget(path);
+            self.publish_change(ChangeKind::Move, path, Some(&dest), res);
+        }
+        Ok(())
+    }
+
+    async fn handle_lock_request(
+        &self,
+        req: Request,
+        file: FileRequest,
+        res: &mut Response,
+    ) -> Result<()> {
+        let FileRequest {
+            path,
+            href,
+            is_miss,
+            ..
+        } = file;
+        let path = path.as_path();
+        let allow_upload = self.args.allow_upload;
+        if is_miss && !allow_upload {
+            status_forbid(res);
+        } else {
+            webdav::handle_lock(&self.locks, path, &href, is_miss, req, res).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_unlock_request(
+        &self,
+        req: Request,
+        file: FileRequest,
+        res: &mut Response,
+    ) -> Result<()> {
+        let FileRequest { path, is_miss, .. } = file;
+        let path = path.as_path();
+        let headers = req.headers();
+        if is_miss {
+            status_not_found(res);
+        } else {
+            webdav::handle_unlock(&self.locks, path, headers, res);
+        }
+        Ok(())
+    }
+}
@@ -1,67 +1,166 @@
 use anyhow::{anyhow, Result};
 use base64::{engine::general_purpose::STANDARD, Engine as _};
-use futures_util::{pin_mut, TryStreamExt};
+use futures_util::{future, pin_mut, stream, Stream, TryStreamExt};
 use headers::{
     AcceptRanges, CacheControl, ContentLength, ContentType, HeaderMap, HeaderMapExt, IfMatch,
     IfModifiedSince, IfNoneMatch, IfRange, IfUnmodifiedSince, Range,
 };
-use http_body_util::{BodyExt, StreamBody};
-use hyper::body::Frame;
+use http_body_util::{combinators::BoxBody, BodyExt, StreamBody};
+use hyper::body::{Bytes, Frame};
 use hyper::{
     body::Incoming,
     header::{
-        HeaderValue, AUTHORIZATION, CONNECTION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE,
+        HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE, VARY,
     },
     Method, StatusCode,
 };
+use sha2::{Digest, Sha256};
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::Infallible;
 use std::io::SeekFrom;
-use std::net::SocketAddr;
-use std::path::Path;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::pin::Pin;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::fs::{self};
-use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::OwnedSemaphorePermit;
 use tokio::{self, io};
-use tokio_util::io::{ReaderStream, StreamReader};
+use tokio_util::io::{InspectReader, ReaderStream, StreamReader};
 use uuid::Uuid;
 
-use crate::auth::{AccessPaths, AccessPerm};
+use crate::auth::{parse_grant, AccessPaths};
+use crate::chunk_store::{self, ChunkStore};
+use crate::events::{ChangeKind, EventBus};
 use crate::file_utils;
-use crate::http_utils::{body_full, IncomingStream, LengthLimitedStream};
-use crate::provenance::ProvenanceDb;
-use crate::utils::{encode_uri, get_file_name, parse_range, try_get_file_name};
+use crate::http_utils::{body_full, IncomingStream, LengthLimitedStream, ThrottledStream};
+use crate::i18n::Locale;
+use crate::integrity::{self, IntegrityScanner};
+use crate::ip_filter::client_addr;
+use crate::oidc::Oidc;
+use crate::ots_aggregator::{self, OtsAggregator, QUEUED_PROOF};
+use crate::ots_upgrader;
+use crate::provenance::{
+    DeadProperty, Direction, ProvenanceDb, QueuedDigest, ServerKeypair, VerificationKind,
+};
+use crate::provenance_anchor::AnchorSchedule;
+use crate::provenance_backfill;
+use crate::provenance_backup::BackupSchedule;
+use crate::provenance_gc;
+use crate::provenance_store;
+use crate::provenance_utils;
+use crate::retention::parse_age;
+use crate::search_index::SearchIndex;
+use crate::sessions::Sessions;
+use crate::signing_keys::SigningKeySource;
+use crate::trash::{self, Trash};
+use crate::utils::{
+    encode_uri, get_file_name, parse_range, parse_sha256_digest, sha256_digest_field,
+    try_get_file_name,
+};
 use crate::Args;
 
-use super::path_item::{DataKind, EditData, PathItem, PathType};
+use super::auth_exec::ExecLogins;
+use super::auth_log::{LoginThrottle, AUTH_LOG_PATH};
+use super::dir_cache::{DirCache, DirEntries, EntryStat};
+use super::drop_handlers::DropBox;
+use super::encryption::{self, declared_encryption};
+use super::locks::LockManager;
+use super::metrics::RequestMetrics;
+use super::middleware::{builtin_layers, Middleware, Next};
+use super::openapi::{self, OPENAPI_PATH};
+use super::path_item::{
+    category_of, is_partial_upload, Brand, DataKind, EditData, PathItem, PathType, RetentionStatus,
+    StampStatus, StampSummary, PARTIAL_UPLOAD_SUFFIX,
+};
 use super::provenance_handlers;
+use super::range_streams::RangeStreams;
+use super::rate_limit::RateLimiter;
 use super::response_utils::{
-    add_cors, extract_cache_headers, get_content_type, normalize_path, set_content_disposition,
-    set_webdav_headers, status_bad_request, status_forbid, status_no_content, status_not_found,
-    to_timestamp, Response, BUF_SIZE, EDITABLE_TEXT_MAX_SIZE, INDEX_NAME, MAX_SUBPATHS_COUNT,
-    RESUMABLE_UPLOAD_MIN_SIZE,
+    extract_cache_headers, get_content_type, normalize_path, pick_variant, precompressed_variants,
+    send_buf_size, set_content_disposition, set_json_response, status_bad_request, status_forbid,
+    status_insufficient_storage, status_no_content, status_not_found, status_payload_too_large,
+    status_unprocessable, to_timestamp, ErrorPages, Response, BUF_SIZE, EDITABLE_TEXT_MAX_SIZE,
+    INDEX_NAME, MAX_SUBPATHS_COUNT, RESUMABLE_UPLOAD_MIN_SIZE,
+};
+use super::router::{Route, ASSETS_PREFIX};
+use super::session_handlers::{LOGIN_PATH, LOGOUT_PATH};
+use super::share_handlers::SHARES_PATH;
+use super::stamp_cache::StampCache;
+use super::stats_handlers;
+use super::tenants::Tenants;
+use super::token_handlers::TOKENS_PATH;
+use super::upload_handlers::{
+    PartialUploads, UploadTracker, PARTIAL_UPLOAD_RETENTION, UPLOADS_PATH, UPLOAD_ID_HEADER,
 };
+use super::user_key_handlers;
+use super::visibility_handlers::PublicOnly;
 use super::webdav;
+use super::write_locks::WriteLocks;
 
 pub type Request = hyper::Request<Incoming>;
 
+/// Most ranges served in one multipart response, more are refused with 416
+const MAX_RANGES: usize = 16;
+
+/// Chunks of a file, or of one part of a multipart response
+type FileStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send + Sync>>;
+
 const INDEX_HTML: &str = include_str!("../../assets/index.html");
 pub(super) const HEALTH_CHECK_PATH: &str = "__dufs__/health";
 pub(super) const PROVENANCE_DB_PATH: &str = "__dufs__/provenance-db";
+pub(super) const STATS_PATH: &str = "__dufs__/stats";
+pub(super) const METRICS_PATH: &str = "__dufs__/metrics";
+pub(super) const USER_KEY_PATH: &str = "__dufs__/user-key";
+pub(super) const TRASH_PATH: &str = "__dufs__/trash";
+pub(super) const EVENTS_PATH: &str = "__dufs__/events";
+pub(super) const GC_PATH: &str = "__dufs__/gc";
+pub(super) const BACKFILL_PATH: &str = "__dufs__/backfill";
+pub(super) const PROVENANCE_ANCHOR_PATH: &str = "__dufs__/provenance-anchor";
 
 pub struct Server {
-    pub(super) args: Args,
-    pub(super) assets_prefix: String,
+    pub(super) args: Arc<Args>,
     pub(super) html: Cow<'static, str>,
     pub(super) single_file_req_paths: Vec<String>,
     pub(super) running: Arc<AtomicBool>,
     pub(super) provenance_db: ProvenanceDb,
+    pub(super) keypair: ServerKeypair,
+    pub(super) trash: Option<Trash>,
+    pub(super) events: EventBus,
+    pub(super) ots: OtsAggregator,
+    pub(super) range_streams: Option<RangeStreams>,
+    pub(super) locks: LockManager,
+    pub(super) write_locks: WriteLocks,
+    pub(super) uploads: UploadTracker,
+    pub(super) partial_uploads: PartialUploads,
+    pub(super) logins: LoginThrottle,
+    pub(super) exec_logins: ExecLogins,
+    pub(super) stamp_summaries: StampCache<StampSummary>,
+    pub(super) stamp_statuses: StampCache<StampStatus>,
+    pub(super) search_index: Option<SearchIndex>,
+    pub(super) dir_cache: Option<DirCache>,
+    pub(super) rate_limiter: Option<RateLimiter>,
+    pub(super) request_metrics: RequestMetrics,
+    pub(super) drop_box: Option<DropBox>,
+    pub(super) oidc: Option<Oidc>,
+    pub(super) sessions: Sessions,
+    pub(super) tenants: Option<Arc<Tenants>>,
+    pub(super) error_pages: ErrorPages,
+    /// Top-level directory served by this tenant server
+    pub(super) tenant_root: Option<PathBuf>,
+    /// Servers of the `/<name>:<path>` mounts, by name
+    pub(super) mounts: BTreeMap<String, Arc<Server>>,
+    /// Name of the mount served by this mount server
+    pub(super) mount_name: Option<String>,
+    /// Layers around `handle`, outermost first
+    pub(super) layers: Vec<Arc<dyn Middleware>>,
 }
 
 impl Server {
     pub fn init(args: Args, running: Arc<AtomicBool>) -> Result<Self> {
-        let assets_prefix = format!("__dufs_v{}__/", env!("CARGO_PKG_VERSION"));
         let single_file_req_paths = if args.path_is_file {
             vec![
                 args.uri_prefix.to_string(),
@@ -78,669 +177,700 @@ impl Server {
         let html = Cow::Borrowed(INDEX_HTML);
 
         // Initialize provenance database
-        let db_path = args
-            .provenance_db
-            .as_ref()
-            .map(|p| p.to_owned())
-            .unwrap_or_else(|| "provenance.db".into());
-        let provenance_db = ProvenanceDb::new(&db_path)?;
+        let mut provenance_db =
+            ProvenanceDb::new(args.provenance_db_path(), &args.provenance_db_encryption)?;
+        if let Some(url) = args.provenance_db_url() {
+            provenance_db = provenance_db.with_chain(provenance_store::connect(url)?);
+        }
 
-        Ok(Self {
-            args,
+        if let Some(dir) = &args.provenance_backup_dir {
+            BackupSchedule {
+                dir: dir.clone(),
+                interval: Duration::from_secs(args.provenance_backup_interval * 60),
+                keep: args.provenance_backup_keep,
+            }
+            .spawn(provenance_db.clone());
+        }
+
+        if let Some(interval) = args.integrity_scan_interval {
+            IntegrityScanner {
+                roots: provenance_backfill::roots(&args),
+                interval: Duration::from_secs(interval * 60),
+            }
+            .spawn(provenance_db.clone());
+        }
+
+        if let Some(interval) = args.provenance_anchor_interval {
+            AnchorSchedule {
+                interval: Duration::from_secs(interval * 60),
+            }
+            .spawn(provenance_db.clone());
+        }
+
+        let keypair = SigningKeySource::new(
+            args.signing_key.as_deref(),
+            args.signing_key_file.as_deref(),
+        )
+        .load(args.rotate_signing_key, &provenance_db)?;
+
+        let trash = args.trash_dir.as_deref().map(Trash::new).transpose()?;
+        if let (Some(trash), Some(retention)) = (&trash, args.trash_retention) {
+            trash.clone().spawn_sweeper(retention);
+        }
+        if !args.retention.is_empty() && args.mounts.is_empty() {
+            args.retention.clone().spawn_sweeper(
+                args.serve_path.clone(),
+                trash.clone(),
+                provenance_db.clone(),
+            );
+        }
+
+        let ots = OtsAggregator::spawn(
+            Duration::from_secs(args.ots_batch_interval),
+            args.ots_offline,
+        );
+        ots_aggregator::spawn_queue_worker(provenance_db.clone());
+        ots_upgrader::spawn_upgrade_worker(provenance_db.clone());
+
+        if let Some(dir) = &args.dedup_dir {
+            ChunkStore::install(dir)?;
+        }
+
+        let range_streams = args.max_range_streams.map(RangeStreams::new);
+
+        let search_index = match args.index && !args.path_is_file && args.mounts.is_empty() {
+            true => Some(SearchIndex::start(provenance_db.clone(), &args.serve_path)?),
+            false => None,
+        };
+
+        let dir_cache = match args.dir_cache && !args.path_is_file && args.mounts.is_empty() {
+            true => Some(DirCache::start(&args.serve_path)?),
+            false => None,
+        };
+
+        let rate_limiter = RateLimiter::from_args(&args);
+        let drop_box = DropBox::from_args(&args);
+
+        let oidc = args.oidc_config().map(Oidc::new).transpose()?;
+
+        let tenants = match &args.tenant_db_dir {
+            Some(dir) => Some(Arc::new(Tenants::new(dir)?)),
+            None => None,
+        };
+
+        let error_pages = match &args.error_pages {
+            Some(dir) => ErrorPages::load(dir)?,
+            None => ErrorPages::default(),
+        };
+
+        let mut server = Self {
+            args: Arc::new(args),
             running,
             single_file_req_paths,
-            assets_prefix,
             html,
             provenance_db,
+            keypair,
+            trash,
+            events: EventBus::default(),
+            ots,
+            range_streams,
+            locks: LockManager::default(),
+            write_locks: WriteLocks::default(),
+            uploads: UploadTracker::default(),
+            partial_uploads: PartialUploads::default(),
+            logins: LoginThrottle::default(),
+            exec_logins: ExecLogins::default(),
+            stamp_summaries: StampCache::default(),
+            stamp_statuses: StampCache::default(),
+            search_index,
+            dir_cache,
+            rate_limiter,
+            request_metrics: RequestMetrics::default(),
+            drop_box,
+            oidc,
+            sessions: Sessions::default(),
+            tenants,
+            error_pages,
+            tenant_root: None,
+            mounts: BTreeMap::new(),
+            mount_name: None,
+            layers: builtin_layers(),
+        };
+        if server.args.allow_upload {
+            server.partial_uploads.clone().spawn_sweeper(
+                server.write_locks.clone(),
+                server
+                    .args
+                    .partial_upload_retention
+                    .unwrap_or(PARTIAL_UPLOAD_RETENTION),
+            );
+        }
+        for (name, path) in server.args.mounts.clone() {
+            let mount = server.for_mount(&name, &path)?;
+            server.mounts.insert(name, Arc::new(mount));
+        }
+        Ok(server)
+    }
+
+    /// A server for one tenant, sharing everything but the provenance data
+    pub(super) fn for_tenant(&self, root: PathBuf, provenance_db: ProvenanceDb) -> Result<Self> {
+        ots_aggregator::spawn_queue_worker(provenance_db.clone());
+        ots_upgrader::spawn_upgrade_worker(provenance_db.clone());
+        Ok(Self {
+            args: self.args.clone(),
+            html: self.html.clone(),
+            single_file_req_paths: self.single_file_req_paths.clone(),
+            running: self.running.clone(),
+            keypair: SigningKeySource::Database
+                .load(self.args.rotate_signing_key, &provenance_db)?,
+            provenance_db,
+            trash: self.trash.clone(),
+            events: self.events.clone(),
+            ots: self.ots.clone(),
+            range_streams: self.range_streams.clone(),
+            locks: self.locks.clone(),
+            write_locks: self.write_locks.clone(),
+            uploads: UploadTracker::default(),
+            partial_uploads: self.partial_uploads.clone(),
+            logins: LoginThrottle::default(),
+            exec_logins: ExecLogins::default(),
+            stamp_summaries: StampCache::default(),
+            stamp_statuses: StampCache::default(),
+            search_index: self.search_index.clone(),
+            dir_cache: self.dir_cache.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            request_metrics: self.request_metrics.clone(),
+            drop_box: self.drop_box.clone(),
+            oidc: self.oidc.clone(),
+            sessions: self.sessions.clone(),
+            tenants: None,
+            error_pages: self.error_pages.clone(),
+            tenant_root: Some(root),
+            mounts: BTreeMap::new(),
+            mount_name: None,
+            layers: self.layers.clone(),
         })
     }
 
     pub async fn call(
         self: Arc<Self>,
-        req: Request,
+        mut req: Request,
         addr: Option<SocketAddr>,
-    ) -> Result<Response, hyper::Error> {
-        let uri = req.uri().clone();
-        let api_prefix = format!("{}api", self.args.uri_prefix);
-        let is_api_request = uri.path().starts_with(&api_prefix);
-        let enable_cors = self.args.enable_cors;
-        let mut http_log_data = self.args.http_logger.data(&req);
+    ) -> Result<Response, Infallible> {
+        let addr = addr.map(|peer| client_addr(&self.args.trusted_proxies, peer, req.headers()));
         if let Some(addr) = addr {
-            http_log_data.insert("remote_addr".to_string(), addr.ip().to_string());
+            req.extensions_mut().insert(addr);
         }
+        let route = Route::of(&req, &self.args.uri_prefix);
+        Ok(Next::new(&self, route).run(req).await)
+    }
 
-        let mut res = match self.clone().handle(req).await {
-            Ok(res) => {
-                http_log_data.insert("status".to_string(), res.status().as_u16().to_string());
-                // Only log API requests (the application logic). Public asset
-                // requests are served from the SPA and are noisy, so avoid
-                // logging them here.
-                if is_api_request {
-                    self.args.http_logger.log(&http_log_data, None);
-                }
-                res
-            }
-            Err(err) => {
-                let mut res = Response::default();
-                let status = StatusCode::INTERNAL_SERVER_ERROR;
-                *res.status_mut() = status;
-                http_log_data.insert("status".to_string(), status.as_u16().to_string());
-                self.args
-                    .http_logger
-                    .log(&http_log_data, Some(err.to_string()));
-                res
-            }
-        };
-
-        if enable_cors {
-            add_cors(&mut res);
+    /// Answer a request that got through the middleware layers with the
+    /// handler of its route
+    pub(super) async fn handle(&self, route: Route, req: Request) -> Result<Response> {
+        let mut res = Response::default();
+        match route {
+            Route::Internal => self.handle_internal_route(req, &mut res).await?,
+            Route::Share => self.handle_share_route(&req, &mut res).await?,
+            Route::SigningKey => provenance_handlers::handle_signing_key(
+                &self.keypair,
+                &self.provenance_db,
+                req.method() == Method::HEAD,
+                &mut res,
+            )?,
+            Route::Asset => self.handle_asset_route(&req, &mut res).await?,
+            Route::Api(op) | Route::WebDav(op) => self.handle_file_route(op, req, &mut res).await?,
         }
         Ok(res)
     }
 
-    pub async fn handle(self: Arc<Self>, req: Request) -> Result<Response> {
-        let mut res = Response::default();
+    /// Public routes of shared files: /share/<id>/download, /share/<id>/info,
+    /// /share/<id>/manifest, /share/<id>/ots-info, /share/<id>/chain and
+    /// /share/<id>?info. The web UI shows the share viewer at /share/<id>.
+    async fn handle_share_route(&self, req: &Request, res: &mut Response) -> Result<()> {
         let uri_path = req.uri().path();
         let headers = req.headers();
-        let method = req.method().clone();
         let query = req.uri().query().unwrap_or_default();
-
-        // Check for share routes first (public access to shared files)
-        // Routes like /share/<id>, /share/<id>/download, /share/<id>/info, /share/<id>/chain
-        if uri_path.starts_with("/share/") {
-            let share_path = uri_path.trim_start_matches("/share/");
-
-            if method == Method::GET || method == Method::HEAD {
-                if let Some(share_id) = share_path.split('/').next() {
-                    let head_only = method == Method::HEAD;
-
-                    if share_path.ends_with("/chain") {
-                        // GET /share/<id>/chain - distribution chain
-                        provenance_handlers::handle_distribution_chain(
-                            share_id,
-                            &self.provenance_db,
-                            &mut res,
-                        )
-                        .await?;
-                        return Ok(res);
-                    } else if share_path.ends_with("/download") {
-                        // GET /share/<id>/download - download shared file
-                        provenance_handlers::handle_shared_file_download(
-                            share_id,
-                            head_only,
-                            &self.provenance_db,
-                            &mut res,
-                        )
-                        .await?;
-                        return Ok(res);
-                    } else if share_path.ends_with("/info") {
-                        // GET /share/<id>/info - get share metadata
-                        provenance_handlers::handle_share_metadata(
-                            share_id,
-                            &self.provenance_db,
-                            &mut res,
-                        )
-                        .await?;
-                        return Ok(res);
-                    } else if share_path.ends_with("/manifest") {
-                        // GET /share/<id>/manifest - get provenance manifest
-                        provenance_handlers::handle_share_manifest(
-                            share_id,
-                            &self.provenance_db,
-                            &mut res,
-                        )
-                        .await?;
-                        return Ok(res);
-                    } else if share_path.ends_with("/ots-info") {
-                        // GET /share/<id>/ots-info - get OTS info
-                        provenance_handlers::handle_share_ots_info(
-                            share_id,
-                            &self.provenance_db,
-                            &mut res,
-                        )
-                        .await?;
-                        return Ok(res);
-                    } else if share_id == share_path {
-                        // GET /share/<id> - serve SPA page for share viewer
-                        // This will fall through to normal index.html serving
-                        // The React app will handle the /share/:id route
-                        // We intentionally do NOT return here, let it continue to SPA serving
-                    } else {
-                        // Unknown share sub-route
-                        status_not_found(&mut res);
-                        return Ok(res);
-                    }
+        let head_only = req.method() == Method::HEAD;
+
+        let share_path = uri_path.trim_start_matches("/share/");
+        let share_id = share_path.split('/').next().unwrap_or_default();
+        let password = provenance_handlers::share_password(query, headers);
+        let password = password.as_deref();
+        let share_landing = form_urlencoded::parse(query.as_bytes())
+            .find(|(k, _)| k == "info")
+            .map(|(_, v)| v.into_owned());
+
+        if share_path.ends_with("/chain") {
+            // GET /share/<id>/chain - distribution chain
+            provenance_handlers::handle_distribution_chain(share_id, &self.provenance_db, res)
+                .await?;
+        } else if share_path.ends_with("/download") {
+            // GET /share/<id>/download - download shared file
+            if let Some(path) = provenance_handlers::prepare_shared_file_download(
+                share_id,
+                password,
+                &self.provenance_db,
+                res,
+            )? {
+                self.handle_send_file(&path, headers, head_only, res)
+                    .await?;
+                if !head_only {
+                    provenance_handlers::record_shared_file_download(
+                        share_id,
+                        &self.provenance_db,
+                        res,
+                    )?;
                 }
             }
-
-            // If we haven't returned yet and path is /share/<id>,
-            // it means we want to serve the SPA (continue processing)
-        }
-
-        // Check for internal routes (these should not require path prefix)
-        // Health check and other __dufs__ routes are always accessible
-        if uri_path.contains("__dufs__") {
-            // Strip the uri_prefix to get the actual internal path
-            // E.g., /xyz/__dufs__/health -> __dufs__/health
-            // or /__dufs__/health -> __dufs__/health
-            let req_path = uri_path
-                .strip_prefix(&self.args.uri_prefix)
-                .unwrap_or(uri_path)
-                .trim_start_matches('/');
-
-            if method == Method::GET && self.handle_internal(req_path, headers, &mut res).await? {
-                return Ok(res);
-            }
-        }
-
-        // Determine the expected API prefix based on path-prefix setting
-        // If --path-prefix is set to "dufs", API routes are at /dufs/api/*
-        // Otherwise, API routes are at /api/*
-        let api_prefix = format!("{}api", self.args.uri_prefix);
-        let api_prefix_str = api_prefix.as_str();
-
-        // Check if this request has special query parameters that require server processing
-        // These should not be served as static assets even though they're not API routes
-        let has_simple = query.contains("simple");
-        let has_search = query.contains("q=");
-        let requires_server_processing = has_simple
-            || query.contains("edit")
-            || query.contains("view")
-            || query.contains("hash")
-            || query.contains("zip")
-            || query.contains("ots")
-            || query.contains("manifest=")
-            || query.contains("verify")
-            || query.contains("download")
-            || query.contains("share")
-            || query.contains("share_info")
-            || (has_search && has_simple); // search with simple returns plain text
-
-        // If the request is not for the API and doesn't have special query params,
-        // serve SPA assets and handle client-side routing for the SPA.
-        // Only do this for GET/HEAD requests - other methods (WebDAV, etc.) should
-        // continue to normal request handling.
-        //
-        // Serve SPA for:
-        // - Root path "/"
-        // - Share routes "/share/:id"
-        // - Actual asset files "/assets/*", "/chunks/*", etc.
-        //
-        // DO NOT serve SPA for file paths that should be served from the filesystem
-        // (these have extensions and are not in the assets/chunks directories).
-        let is_spa_route = uri_path == "/"
-            || uri_path == ""
-            || uri_path.starts_with("/share/")
-            || uri_path.starts_with("/assets/")
-            || uri_path.starts_with("/chunks/")
-            || uri_path.ends_with(".js")
-            || uri_path.ends_with(".css")
-            || uri_path.ends_with(".map")
-            || (!uri_path.contains('.') && !uri_path.starts_with(api_prefix_str));
-
-        if !uri_path.starts_with(api_prefix_str)
-            && !requires_server_processing
-            && (method == Method::GET || method == Method::HEAD)
-            && is_spa_route
-        {
-            // handle_public will always return Ok(true) after writing a response
-            // (either the asset or a 404). If it returns Ok(true), we return the
-            // response immediately.
-            if self.handle_public(uri_path, headers, &mut res).await? {
-                return Ok(res);
-            }
-            return Ok(res);
+        } else if share_path.ends_with("/info") {
+            // GET /share/<id>/info - get share metadata
+            provenance_handlers::handle_share_metadata(
+                share_id,
+                password,
+                &self.provenance_db,
+                &self.stamp_statuses,
+                res,
+            )
+            .await?;
+        } else if share_path.ends_with("/manifest") {
+            // GET /share/<id>/manifest - get provenance manifest
+            provenance_handlers::handle_share_manifest(
+                share_id,
+                password,
+                &self.provenance_db,
+                res,
+            )
+            .await?;
+        } else if share_path.ends_with("/ots-info") {
+            // GET /share/<id>/ots-info - get OTS info
+            provenance_handlers::handle_share_ots_info(
+                share_id,
+                password,
+                &self.provenance_db,
+                res,
+            )
+            .await?;
+        } else if share_id == share_path && share_landing.is_some() {
+            // GET /share/<id>?info - provenance summary before downloading
+            provenance_handlers::handle_share_landing(
+                share_id,
+                password,
+                share_landing.as_deref() == Some("json"),
+                head_only,
+                &self.provenance_db,
+                &self.stamp_statuses,
+                res,
+            )
+            .await?;
+        } else if share_id == share_path {
+            // GET /share/<id> - the React app serves the share viewer
+            self.handle_public(uri_path, headers, res).await?;
+        } else {
+            // Unknown share sub-route
+            status_not_found(res);
         }
+        Ok(())
+    }
 
-        // For API requests, strip only the /api part (not the path prefix)
-        // E.g., /dufs/api/index.html becomes /dufs/index.html
-        // This allows resolve_path to strip the path prefix correctly
-        let req_path =
-            if uri_path == api_prefix_str || uri_path == api_prefix_str.trim_end_matches('/') {
-                self.args.uri_prefix.as_str()
-            } else {
-                // Replace "api/" or "api" with empty string, keeping the prefix
-                &uri_path.replace(
-                    &format!("{}api/", self.args.uri_prefix),
-                    self.args.uri_prefix.as_str(),
-                )
-            };
+    /// The health check and the other `__dufs__` routes under the path prefix
+    async fn handle_internal_route(&self, req: Request, res: &mut Response) -> Result<()> {
+        let uri_path = req.uri().path();
+        let method = req.method().clone();
 
-        let relative_path = match self.resolve_path(req_path) {
-            Some(v) => v,
-            None => {
-                status_bad_request(&mut res, "Invalid Path");
-                return Ok(res);
-            }
-        };
+        // E.g., /xyz/__dufs__/health -> __dufs__/health
+        let req_path = uri_path
+            .strip_prefix(&self.args.uri_prefix)
+            .unwrap_or(uri_path)
+            .to_string();
+        let req_path = req_path.as_str();
 
-        if method == Method::GET
-            && self
-                .handle_internal(&relative_path, headers, &mut res)
-                .await?
-        {
-            return Ok(res);
+        if method == Method::GET && self.handle_internal(req_path, res).await? {
+            return Ok(());
         }
 
-        let user_agent = headers
-            .get("user-agent")
-            .and_then(|v| v.to_str().ok())
-            .map(|v| v.to_lowercase())
-            .unwrap_or_default();
+        if self.handle_oidc(req_path, &req, res).await? {
+            return Ok(());
+        }
 
-        let is_microsoft_webdav = user_agent.starts_with("microsoft-webdav-miniredir/");
+        if self.handle_admin(req_path, &req, res).await? {
+            return Ok(());
+        }
 
-        if is_microsoft_webdav {
-            res.headers_mut()
-                .insert(CONNECTION, HeaderValue::from_static("close"));
+        if req_path == EVENTS_PATH && method == Method::GET {
+            self.handle_events(&req, res)?;
+            return Ok(());
         }
 
-        let authorization = headers.get(AUTHORIZATION);
+        if req_path == TOKENS_PATH || req_path.starts_with(&format!("{TOKENS_PATH}/")) {
+            self.handle_api_tokens(req_path, req, res).await?;
+            return Ok(());
+        }
 
-        let query = req.uri().query().unwrap_or_default();
-        let query_params: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
-            .map(|(k, v)| (k.to_string(), v.to_string()))
-            .collect();
-
-        let guard = self.args.auth.guard(
-            &relative_path,
-            &method,
-            authorization,
-            query_params.get("token"),
-            is_microsoft_webdav,
-        );
+        if req_path == UPLOADS_PATH || req_path.starts_with(&format!("{UPLOADS_PATH}/")) {
+            self.handle_uploads(req_path, &req, res)?;
+            return Ok(());
+        }
 
-        let (user, access_paths) = match guard {
-            (None, None) => {
-                self.auth_reject(&mut res)?;
-                return Ok(res);
-            }
-            (Some(_), None) => {
-                status_forbid(&mut res);
-                return Ok(res);
-            }
-            (x, Some(y)) => (x, y),
-        };
+        if req_path == LOGIN_PATH || req_path == LOGOUT_PATH {
+            self.handle_session(req_path, req, res).await?;
+            return Ok(());
+        }
 
-        if method.as_str() == "CHECKAUTH" {
-            match user.clone() {
-                Some(user) => {
-                    *res.body_mut() = body_full(user);
-                }
-                None => {
-                    if has_query_flag(&query_params, "login") || !access_paths.perm().readwrite() {
-                        self.auth_reject(&mut res)?
-                    } else {
-                        *res.body_mut() = body_full("");
-                    }
-                }
-            }
-            return Ok(res);
-        } else if method.as_str() == "LOGOUT" {
-            self.auth_reject(&mut res)?;
-            return Ok(res);
+        if req_path == AUTH_LOG_PATH {
+            self.handle_auth_log(&req, res)?;
+            return Ok(());
         }
 
-        if has_query_flag(&query_params, "tokengen") {
-            self.handle_tokengen(&relative_path, user, &mut res).await?;
-            return Ok(res);
+        if req_path == USER_KEY_PATH {
+            if let Some(user) = self.guard_user(&req, res)? {
+                user_key_handlers::handle_user_key(req, &user, &self.provenance_db, res).await?;
+            }
+            return Ok(());
         }
+        status_not_found(res);
+        Ok(())
+    }
 
-        let head_only = method == Method::HEAD;
+    /// The web UI: versioned assets whatever the path prefix, and the bundle
+    /// with the entry page for client-side routes
+    async fn handle_asset_route(&self, req: &Request, res: &mut Response) -> Result<()> {
+        let uri_path = req.uri().path();
+        let relative = uri_path
+            .strip_prefix(&self.args.uri_prefix)
+            .unwrap_or_else(|| uri_path.trim_start_matches('/'));
+        match relative.strip_prefix(ASSETS_PREFIX) {
+            Some(name) => self.handle_versioned_asset(name, req.headers(), res).await,
+            None => self.handle_public(uri_path, req.headers(), res).await,
+        }
+    }
 
-        if self.args.path_is_file {
-            if self
-                .single_file_req_paths
-                .iter()
-                .any(|v| v.as_str() == req_path)
-            {
-                self.handle_send_file(&self.args.serve_path, headers, head_only, &mut res)
-                    .await?;
-            } else {
-                status_not_found(&mut res);
+    pub async fn handle_upload(
+        &self,
+        path: &Path,
+        user: Option<&str>,
+        upload_offset: Option<u64>,
+        size: u64,
+        req: Request,
+        res: &mut Response,
+    ) -> Result<()> {
+        // Mint events are stamped in the background unless the client waits for the proof
+        let wait_stamp = form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+            .any(|(k, v)| k == "wait" && v == "stamp");
+        let declared = declared_digest(req.headers(), "content-digest")
+            .and_then(|content| Ok((content, declared_digest(req.headers(), "repr-digest")?)));
+        let (content_digest, repr_digest) = match declared {
+            Ok(v) => v,
+            Err(err) => {
+                status_bad_request(res, &err.to_string());
+                return Ok(());
+            }
+        };
+        let encryption = match declared_encryption(req.headers()) {
+            Ok(v) => v,
+            Err(err) => {
+                status_bad_request(res, &err.to_string());
+                return Ok(());
             }
-            return Ok(res);
+        };
+
+        if self.reject_upload_type(path, None, res) {
+            return Ok(());
         }
 
-        let path = match self.join_path(&relative_path) {
-            Some(v) => v,
-            None => {
-                status_forbid(&mut res);
-                return Ok(res);
+        let content_length = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        // Resumed uploads may only bring the file up to the upload limit
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let upload_limit = self.args.upload_limit(&name);
+        let max_body_size =
+            upload_limit.map(|v| v.saturating_sub(upload_offset.unwrap_or_default()));
+        if let (Some(max_body_size), Some(content_length)) = (max_body_size, content_length) {
+            if content_length > max_body_size {
+                status_payload_too_large(res, upload_limit.unwrap_or_default());
+                return Ok(());
             }
-        };
+        }
 
-        let path = path.as_path();
+        // Bytes past the offset overwrite the existing file rather than add to it
+        let replaced = size.saturating_sub(upload_offset.unwrap_or_default());
+        let headroom = self.quota_headroom(path, user, replaced).await?;
+        if let Some(headroom) = &headroom {
+            if content_length.is_some_and(|v| v > headroom.bytes) {
+                let msg = format!("Quota exceeded for {}", headroom.scope);
+                status_insufficient_storage(res, &msg);
+                return Ok(());
+            }
+        }
 
-        let file_info = file_utils::get_file_info(path).await;
-        let (is_miss, is_dir, is_file, size) = (
-            !file_info.exists,
-            file_info.is_dir,
-            file_info.is_file,
-            file_info.size,
-        );
+        ensure_path_parent(path).await?;
 
-        let allow_upload = self.args.allow_upload;
-        let allow_delete = self.args.allow_delete;
-        let allow_search = self.args.allow_search;
-        let allow_archive = self.args.allow_archive;
+        let client = req.extensions().get::<SocketAddr>().map(|v| v.ip());
+        let upload = self
+            .uploads
+            .start(req.headers(), self.relative_path(path), user, client);
+        res.headers_mut()
+            .insert(UPLOAD_ID_HEADER, HeaderValue::from_str(upload.id())?);
+
+        // Uploads to the same file wait for each other
+        let _write_lock = self.write_locks.lock(path).await;
+
+        // Whole files are written to `.<name>.partial` and renamed over the old
+        // content once complete, so readers never see a partial upload. Writes
+        // past the start of a missing file resume an interrupted one.
+        let partial_path = partial_upload_path(path);
+        let resuming = upload_offset.is_some()
+            && !fs::try_exists(path).await?
+            && fs::try_exists(&partial_path).await?;
+        let completes = upload_offset.is_none() || resuming;
+        let write_path = match completes {
+            true => partial_path,
+            false => path.to_path_buf(),
+        };
 
-        if !self.args.allow_symlink && !is_miss && !self.is_root_contained(path).await {
-            status_not_found(&mut res);
-            return Ok(res);
+        // Writes past the start go into the content, not the chunk list
+        if let (Some(store), Some(_), false) = (ChunkStore::get(), upload_offset, resuming) {
+            store.restore(path).await?;
         }
 
-        match method {
-            Method::GET | Method::HEAD => {
-                if is_dir {
-                    // For API requests, always return JSON (never HTML)
-                    if has_query_flag(&query_params, "zip") {
-                        if !allow_archive {
-                            status_not_found(&mut res);
-                            return Ok(res);
-                        }
-                        self.handle_zip_dir(path, head_only, access_paths, &mut res)
-                            .await?;
-                    } else if allow_search && query_params.contains_key("q") {
-                        self.handle_api_search(
-                            path,
-                            &query_params,
-                            head_only,
-                            user,
-                            access_paths,
-                            &mut res,
-                        )
-                        .await?;
-                    } else {
-                        // Directory listing - return JSON
-                        self.handle_api_index(
-                            path,
-                            true,
-                            &query_params,
-                            head_only,
-                            user,
-                            access_paths,
-                            &mut res,
-                        )
-                        .await?;
-                    }
-                } else if is_file {
-                    if has_query_flag(&query_params, "edit") {
-                        self.handle_edit_file(path, DataKind::Edit, head_only, user, &mut res)
-                            .await?;
-                    } else if has_query_flag(&query_params, "view") {
-                        self.handle_edit_file(path, DataKind::View, head_only, user, &mut res)
-                            .await?;
-                    } else if has_query_flag(&query_params, "hash") {
-                        provenance_handlers::handle_hash_file(path, head_only, &mut res).await?;
-                    } else if query_params.get("manifest") == Some(&"json".to_string()) {
-                        provenance_handlers::handle_provenance_manifest(
-                            path,
-                            head_only,
-                            &self.provenance_db,
-                            &mut res,
-                        )
-                        .await?;
-                    } else if has_query_flag(&query_params, "ots-info") {
-                        provenance_handlers::handle_ots_info(
-                            path,
-                            head_only,
-                            &self.provenance_db,
-                            &mut res,
-                        )
-                        .await?;
-                    } else if has_query_flag(&query_params, "ots") {
-                        provenance_handlers::handle_ots_download(
-                            path,
-                            head_only,
-                            &self.provenance_db,
-                            &mut res,
-                        )
-                        .await?;
-                    } else if has_query_flag(&query_params, "share_info") {
-                        provenance_handlers::handle_share_info(path, &self.provenance_db, &mut res)
-                            .await?;
-                    } else {
-                        self.handle_send_file(path, headers, head_only, &mut res)
-                            .await?;
-                    }
-                } else if is_miss && allow_upload && req_path.ends_with('/') {
-                    // Non-existent directory - return empty JSON listing for API
-                    self.handle_api_index(
-                        path,
-                        false,
-                        &query_params,
-                        head_only,
-                        user,
-                        access_paths,
-                        &mut res,
-                    )
-                    .await?;
-                } else {
-                    status_not_found(&mut res);
-                }
+        let status = match completes {
+            true => StatusCode::CREATED,
+            false => StatusCode::NO_CONTENT,
+        };
+        let mut file = match upload_offset {
+            None => fs::File::create(&write_path).await?,
+            Some(offset) if offset == size => {
+                fs::OpenOptions::new()
+                    .append(true)
+                    .open(&write_path)
+                    .await?
             }
-            Method::OPTIONS => {
-                set_webdav_headers(&mut res);
+            Some(offset) => {
+                let mut file = fs::OpenOptions::new().write(true).open(&write_path).await?;
+                file.seek(SeekFrom::Start(offset)).await?;
+                file
             }
-            Method::PUT => {
-                if is_dir || !allow_upload || (!allow_delete && size > 0) {
-                    status_forbid(&mut res);
-                } else {
-                    self.handle_upload(path, None, size, req, &mut res).await?;
+        };
+        let stream = IncomingStream::new(req.into_body());
+
+        let body_with_io_error = stream.map_err(io::Error::other);
+
+        // Hash whole-file uploads as they stream in, so minting doesn't read the file again
+        let mut hasher = upload_offset.is_none().then(Sha256::new);
+        // Partial writes are hashed on their own when the client declared their digest
+        let mut body_hasher =
+            (upload_offset.is_some() && content_digest.is_some()).then(Sha256::new);
+        let ret = {
+            let body_reader = InspectReader::new(StreamReader::new(body_with_io_error), |chunk| {
+                upload.received(chunk.len());
+                if let Some(hasher) = hasher.as_mut() {
+                    hasher.update(chunk);
                 }
-            }
-            Method::POST => {
-                if has_query_flag(&query_params, "verify") {
-                    provenance_handlers::handle_ots_verify(req, &self.provenance_db, &mut res)
-                        .await?;
-                } else if has_query_flag(&query_params, "ots") {
-                    if is_miss || is_dir {
-                        status_not_found(&mut res);
-                    } else {
-                        provenance_handlers::handle_ots_upload(
-                            path,
-                            req,
-                            &self.provenance_db,
-                            &mut res,
-                        )
-                        .await?;
-                    }
-                } else if has_query_flag(&query_params, "share") {
-                    if is_miss || is_dir {
-                        status_not_found(&mut res);
-                    } else {
-                        provenance_handlers::handle_create_share(
-                            path,
-                            user,
-                            &self.provenance_db,
-                            &mut res,
-                        )
-                        .await?;
-                    }
-                } else {
-                    *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+                if let Some(hasher) = body_hasher.as_mut() {
+                    hasher.update(chunk);
                 }
+            });
+            // Bodies of unknown length are cut off once they go over the
+            // quota or the upload limit
+            let limit = headroom
+                .as_ref()
+                .map_or(u64::MAX, |v| v.bytes)
+                .min(max_body_size.unwrap_or(u64::MAX))
+                .saturating_add(1);
+            let body_reader = body_reader.take(limit);
+            pin_mut!(body_reader);
+            tokio::select! {
+                ret = io::copy(&mut body_reader, &mut file) => Some(ret),
+                _ = upload.cancelled() => None,
             }
-            Method::PATCH => {
-                if is_miss {
-                    status_not_found(&mut res);
-                } else if !allow_upload {
-                    status_forbid(&mut res);
-                } else {
-                    let offset = match parse_upload_offset(headers, size) {
-                        Ok(v) => v,
-                        Err(err) => {
-                            status_bad_request(&mut res, &err.to_string());
-                            return Ok(res);
-                        }
-                    };
-                    match offset {
-                        Some(offset) => {
-                            if offset < size && !allow_delete {
-                                status_forbid(&mut res);
-                            }
-                            self.handle_upload(path, Some(offset), size, req, &mut res)
-                                .await?;
-                        }
-                        None => {
-                            *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
-                        }
-                    }
-                }
+        };
+        let Some(ret) = ret else {
+            match upload_offset {
+                None => fs::remove_file(&write_path).await?,
+                Some(_) => file.set_len(size).await?,
             }
-            Method::DELETE => {
-                // Check if this is a share deletion
-                if let Some(share_id) = query_params.get("share") {
-                    provenance_handlers::handle_delete_share(
-                        share_id,
-                        user,
-                        &self.provenance_db,
-                        &mut res,
-                    )
-                    .await?;
-                } else if !allow_delete {
-                    status_forbid(&mut res);
-                } else if !is_miss {
-                    self.handle_delete(path, is_dir, &mut res).await?
-                } else {
-                    status_not_found(&mut res);
+            *res.status_mut() = StatusCode::CONFLICT;
+            *res.body_mut() = body_full("The upload was cancelled");
+            return Ok(());
+        };
+        if let (Ok(bytes), Some(max_body_size)) = (&ret, max_body_size) {
+            if *bytes > max_body_size {
+                match upload_offset {
+                    None => fs::remove_file(&write_path).await?,
+                    Some(_) => file.set_len(size).await?,
                 }
+                status_payload_too_large(res, upload_limit.unwrap_or_default());
+                return Ok(());
             }
-            method => match method.as_str() {
-                "PROPFIND" => {
-                    if is_dir {
-                        let access_paths =
-                            if access_paths.perm().indexonly() && authorization.is_none() {
-                                AccessPaths::new(AccessPerm::ReadOnly)
-                            } else {
-                                access_paths
-                            };
-                        self.handle_propfind_dir(path, headers, access_paths, &mut res)
-                            .await?;
-                    } else if is_file {
-                        self.handle_propfind_file(path, &mut res).await?;
-                    } else {
-                        status_not_found(&mut res);
-                    }
-                }
-                "PROPPATCH" => {
-                    if is_file {
-                        webdav::handle_proppatch(req_path, &mut res).await?;
-                    } else {
-                        status_not_found(&mut res);
-                    }
-                }
-                "MKCOL" => {
-                    if !allow_upload {
-                        status_forbid(&mut res);
-                    } else if !is_miss {
-                        *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
-                        *res.body_mut() = body_full("Already exists");
-                    } else {
-                        webdav::handle_mkcol(path, &mut res).await?;
-                    }
+        }
+        if let (Ok(bytes), Some(headroom)) = (&ret, &headroom) {
+            if *bytes > headroom.bytes {
+                match upload_offset {
+                    None => fs::remove_file(&write_path).await?,
+                    Some(_) => file.set_len(size).await?,
                 }
-                "COPY" => {
-                    if !allow_upload {
-                        status_forbid(&mut res);
-                    } else if is_miss {
-                        status_not_found(&mut res);
-                    } else {
-                        let dest = match self.extract_dest(&req, &mut res) {
-                            Some(dest) => dest,
-                            None => return Ok(res),
-                        };
-                        webdav::handle_copy(path, &dest, &mut res).await?
-                    }
+                let msg = format!("Quota exceeded for {}", headroom.scope);
+                status_insufficient_storage(res, &msg);
+                return Ok(());
+            }
+        }
+        // Writes not matching their declared digest are undone
+        let mut repr_sha256 = None;
+        if ret.is_ok() {
+            let body_sha256 = match (&hasher, body_hasher) {
+                (Some(hasher), _) => Some(format!("{:x}", hasher.clone().finalize())),
+                (None, Some(hasher)) => Some(format!("{:x}", hasher.finalize())),
+                (None, None) => None,
+            };
+            repr_sha256 = match upload_offset {
+                None => body_sha256.clone(),
+                Some(_) if repr_digest.is_some() => {
+                    file.flush().await?;
+                    Some(file_utils::sha256_file_hash(&write_path).await?)
                 }
-                "MOVE" => {
-                    if !allow_upload || !allow_delete {
-                        status_forbid(&mut res);
-                    } else if is_miss {
-                        status_not_found(&mut res);
-                    } else {
-                        let dest = match self.extract_dest(&req, &mut res) {
-                            Some(dest) => dest,
-                            None => return Ok(res),
-                        };
-                        webdav::handle_move(path, &dest, &mut res, Some(&self.provenance_db))
-                            .await?
-                    }
+                Some(_) => None,
+            };
+            let mismatch = if content_digest.is_some() && content_digest != body_sha256 {
+                Some("Content-Digest")
+            } else if repr_digest.is_some() && repr_digest != repr_sha256 {
+                Some("Repr-Digest")
+            } else {
+                None
+            };
+            if let Some(header) = mismatch {
+                match upload_offset {
+                    None => fs::remove_file(&write_path).await?,
+                    Some(_) => file.set_len(size).await?,
                 }
-                "LOCK" => {
-                    if is_file {
-                        let has_auth = authorization.is_some();
-                        webdav::handle_lock(req_path, has_auth, &mut res).await?;
+                status_unprocessable(res, &format!("{header} doesn't match the uploaded content"));
+                return Ok(());
+            }
+        }
+        let bytes = match ret {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                // Large interrupted uploads of new files stay in their
+                // `.partial` file for the client to resume, while overwrites
+                // can't be resumed as appends go to the existing file
+                if upload_offset.is_none() {
+                    let written = fs::metadata(&write_path)
+                        .await
+                        .map(|v| v.len())
+                        .unwrap_or_default();
+                    let overwrite = fs::try_exists(path).await.unwrap_or_default();
+                    if overwrite || written < RESUMABLE_UPLOAD_MIN_SIZE {
+                        drop(file);
+                        let _ = fs::remove_file(&write_path).await;
                     } else {
-                        status_not_found(&mut res);
-                    }
-                }
-                "UNLOCK" => {
-                    if is_miss {
-                        status_not_found(&mut res);
+                        self.partial_uploads.keep(path);
                     }
                 }
-                _ => {
-                    *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+                return Err(err.into());
+            }
+        };
+        file.flush().await?;
+        // Files declared encrypted must start like the output of their scheme
+        if let (Some(encryption), true) = (&encryption, completes) {
+            let mut head = Vec::with_capacity(encryption::HEAD_SIZE);
+            fs::File::open(&write_path)
+                .await?
+                .take(encryption::HEAD_SIZE as u64)
+                .read_to_end(&mut head)
+                .await?;
+            if !encryption::looks_encrypted(encryption.scheme, &head) {
+                match upload_offset {
+                    None => fs::remove_file(&write_path).await?,
+                    Some(_) => file.set_len(size).await?,
                 }
-            },
-        }
-        Ok(res)
-    }
-
-    pub async fn handle_upload(
-        &self,
-        path: &Path,
-        upload_offset: Option<u64>,
-        size: u64,
-        req: Request,
-        res: &mut Response,
-    ) -> Result<()> {
-        ensure_path_parent(path).await?;
-
-        let (mut file, status) = match upload_offset {
-            None => (fs::File::create(path).await?, StatusCode::CREATED),
-            Some(offset) if offset == size => (
-                fs::OpenOptions::new().append(true).open(path).await?,
-                StatusCode::NO_CONTENT,
-            ),
-            Some(offset) => {
-                let mut file = fs::OpenOptions::new().write(true).open(path).await?;
-                file.seek(SeekFrom::Start(offset)).await?;
-                (file, StatusCode::NO_CONTENT)
+                status_unprocessable(res, "X-Encrypted doesn't match the uploaded content");
+                return Ok(());
             }
+        }
+        if self.args.durable_writes {
+            file.sync_all().await?;
+        }
+        drop(file);
+        // Resumed uploads are only hashed whole once the last bytes are in
+        let mint_sha256 = match (hasher, resuming) {
+            (Some(hasher), _) => Some(format!("{:x}", hasher.finalize())),
+            (None, true) => Some(file_utils::sha256_file_hash(&write_path).await?),
+            (None, false) => None,
         };
-        let stream = IncomingStream::new(req.into_body());
-
-        let body_with_io_error = stream.map_err(io::Error::other);
-        let body_reader = StreamReader::new(body_with_io_error);
-
-        pin_mut!(body_reader);
-
-        let ret = io::copy(&mut body_reader, &mut file).await;
+        if completes {
+            fs::rename(&write_path, path).await?;
+            self.partial_uploads.forget(path);
+            if self.args.durable_writes {
+                if let Some(parent) = path.parent() {
+                    sync_dir(parent).await?;
+                }
+            }
+        }
         let size = fs::metadata(path)
             .await
             .map(|v| v.len())
             .unwrap_or_default();
-        if ret.is_err() {
-            if upload_offset.is_none() && size < RESUMABLE_UPLOAD_MIN_SIZE {
-                let _ = tokio::fs::remove_file(&path).await;
+
+        if let (Some(store), true) = (ChunkStore::get(), completes) {
+            let chunk_path = path.to_path_buf();
+            if let Err(err) = tokio::task::spawn_blocking(move || store.store(&chunk_path)).await? {
+                warn!("Failed to split {} into chunks, {err}", path.display());
             }
-            ret?;
         }
 
         *res.status_mut() = status;
+        // The ETag to send back in `If-Match` for the next write
+        if let Some((etag, _)) = fs::metadata(path)
+            .await
+            .ok()
+            .and_then(|meta| extract_cache_headers(&meta))
+        {
+            res.headers_mut().typed_insert(etag);
+        }
+        if let Some(field) = repr_sha256.as_deref().and_then(sha256_digest_field) {
+            res.headers_mut()
+                .insert("repr-digest", HeaderValue::from_str(&field)?);
+        }
+        self.publish_change(ChangeKind::Upload, path, None, res);
+
+        if let Some(path_str) = path.to_str() {
+            if let Err(err) = self
+                .provenance_db
+                .record_transfer(path_str, Direction::Upload, bytes)
+            {
+                warn!("Failed to record upload of {}, {err}", path.display());
+            }
+            if let Err(err) = self.provenance_db.set_file_owner(path_str, user, size) {
+                warn!("Failed to record the owner of {}, {err}", path.display());
+            }
+        }
 
         // Create provenance mint event if this is a new file
-        if status == StatusCode::CREATED {
+        if let Some(sha256_hex) = mint_sha256 {
             info!(
                 "File uploaded successfully: {} ({} bytes)",
                 path.display(),
                 size
             );
-            match self.create_mint_event(path).await {
+            match self
+                .create_mint_event(path, sha256_hex, user, wait_stamp)
+                .await
+            {
                 Ok(mint_response) => {
+                    if let Some(path_str) = path.to_str() {
+                        if let Err(err) = self
+                            .provenance_db
+                            .set_artifact_encryption(path_str, encryption.as_ref())
+                        {
+                            warn!(
+                                "Failed to record the encryption of {}, {err}",
+                                path.display()
+                            );
+                        }
+                    }
+                    self.publish_provenance(path, "mint", res);
                     info!(
                         "Mint event created for: {} (hash: {})",
                         mint_response.filename,
@@ -764,28 +894,79 @@ impl Server {
         Ok(())
     }
 
-    pub async fn handle_delete(&self, path: &Path, is_dir: bool, res: &mut Response) -> Result<()> {
-        match is_dir {
-            true => fs::remove_dir_all(path).await?,
-            false => fs::remove_file(path).await?,
+    /// Count the bytes a successful file response is about to send
+    pub(super) fn record_download(&self, path: &Path, res: &Response) {
+        if !res.status().is_success() {
+            return;
+        }
+        let bytes = res
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if let (Some(bytes), Some(path_str)) = (bytes, path.to_str()) {
+            if let Err(err) =
+                self.provenance_db
+                    .record_transfer(path_str, Direction::Download, bytes)
+            {
+                warn!("Failed to record download of {}, {err}", path.display());
+            }
+        }
+    }
+
+    pub(super) fn record_verification(
+        &self,
+        path: &Path,
+        kind: VerificationKind,
+        requester_ip: Option<IpAddr>,
+        user: &Option<String>,
+        success: bool,
+    ) {
+        let Some(path_str) = path.to_str() else {
+            return;
+        };
+        let requester_ip = requester_ip.map(|v| v.to_string());
+        if let Err(err) = self.provenance_db.record_verification(
+            path_str,
+            kind,
+            requester_ip.as_deref(),
+            user.as_deref(),
+            success,
+        ) {
+            warn!("Failed to record verification of {}, {err}", path.display());
         }
+    }
 
+    /// Delete `path` through `trash::delete_path`, which every deletion of a
+    /// served file goes through so the trash sees it
+    pub async fn handle_delete(
+        &self,
+        path: &Path,
+        is_dir: bool,
+        user: Option<&str>,
+        res: &mut Response,
+    ) -> Result<()> {
+        trash::delete_path(path, is_dir, user, self.trash.as_ref(), &self.provenance_db).await?;
         status_no_content(res);
         Ok(())
     }
 
-    pub async fn handle_zip_dir(
+    pub async fn handle_archive_dir(
         &self,
         path: &Path,
+        format: ArchiveFormat,
         head_only: bool,
         access_paths: AccessPaths,
+        public_only: Option<PublicOnly>,
         res: &mut Response,
     ) -> Result<()> {
         let (mut writer, reader) = tokio::io::duplex(BUF_SIZE);
         let filename = try_get_file_name(path)?;
-        set_content_disposition(res, false, &format!("{filename}.zip"))?;
-        res.headers_mut()
-            .insert("content-type", HeaderValue::from_static("application/zip"));
+        set_content_disposition(res, false, &format!("{filename}.{}", format.extension()))?;
+        res.headers_mut().insert(
+            "content-type",
+            HeaderValue::from_static(format.content_type()),
+        );
         if head_only {
             return Ok(());
         }
@@ -796,19 +977,38 @@ impl Server {
         let follow_symlinks = self.args.allow_symlink;
         let serve_path = self.args.serve_path.clone();
         tokio::spawn(async move {
-            if let Err(e) = super::zip_dir(
-                &mut writer,
-                &path,
-                access_paths,
-                &hidden,
-                compression,
-                follow_symlinks,
-                serve_path,
-                running,
-            )
-            .await
-            {
-                error!("Failed to zip {}, {e}", path.display());
+            let ret = match format {
+                ArchiveFormat::Zip => {
+                    super::zip_dir(
+                        &mut writer,
+                        &path,
+                        access_paths,
+                        public_only,
+                        &hidden,
+                        compression,
+                        follow_symlinks,
+                        serve_path,
+                        running,
+                    )
+                    .await
+                }
+                ArchiveFormat::Tar | ArchiveFormat::TarGz => {
+                    tar_dir(
+                        writer,
+                        &path,
+                        format == ArchiveFormat::TarGz,
+                        access_paths,
+                        public_only,
+                        &hidden,
+                        follow_symlinks,
+                        serve_path,
+                        running,
+                    )
+                    .await
+                }
+            };
+            if let Err(e) = ret {
+                error!("Failed to archive {}, {e}", path.display());
             }
         });
         let reader_stream = ReaderStream::with_capacity(reader, BUF_SIZE);
@@ -861,111 +1061,223 @@ impl Server {
         Ok(())
     }
 
-    pub async fn handle_internal(
+    /// Versioned web UI assets, which have content hashes in their names and
+    /// are cached for good. Unknown ones get the entry page.
+    async fn handle_versioned_asset(
         &self,
-        req_path: &str,
-        _headers: &HeaderMap<HeaderValue>,
+        name: &str,
+        headers: &HeaderMap<HeaderValue>,
         res: &mut Response,
-    ) -> Result<bool> {
-        if let Some(_name) = req_path.strip_prefix(&self.assets_prefix) {
-            // Serve embedded assets from dist folder
-            let asset_file = format!("assets/dist/{}", _name);
-
-            #[cfg(debug_assertions)]
-            let path = {
-                use std::path::PathBuf;
-                PathBuf::from(&asset_file)
-            };
+    ) -> Result<()> {
+        // Serve embedded assets from dist folder
+        let asset_file = format!("assets/dist/{}", name);
 
-            #[cfg(not(debug_assertions))]
-            let path = {
-                use std::path::PathBuf;
-                std::env::current_exe()
-                    .ok()
-                    .and_then(|exe| exe.parent().map(|p| p.join(&asset_file)))
-                    .unwrap_or_else(|| PathBuf::from(&asset_file))
-            };
+        #[cfg(debug_assertions)]
+        let path = {
+            use std::path::PathBuf;
+            PathBuf::from(&asset_file)
+        };
 
-            if path.exists() && path.is_file() {
-                self.handle_send_file(&path, _headers, false, res).await?;
+        #[cfg(not(debug_assertions))]
+        let path = {
+            use std::path::PathBuf;
+            std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(|p| p.join(&asset_file)))
+                .unwrap_or_else(|| PathBuf::from(&asset_file))
+        };
 
-                // Add aggressive caching for versioned assets (1 year, immutable)
-                // These assets have content hashes in filenames, so they're safe to cache forever
-                res.headers_mut().insert(
-                    hyper::header::CACHE_CONTROL,
-                    HeaderValue::from_static("public, max-age=31536000, immutable"),
-                );
+        if path.exists() && path.is_file() {
+            self.handle_send_file(&path, headers, false, res).await?;
 
-                return Ok(true);
-            } else {
-                let asset_file = "assets/dist/index.html";
-                #[cfg(debug_assertions)]
-                let root_index = {
-                    use std::path::PathBuf;
-                    PathBuf::from(asset_file)
-                };
-                #[cfg(not(debug_assertions))]
-                let root_index = {
-                    use std::path::PathBuf;
-                    std::env::current_exe()
-                        .ok()
-                        .and_then(|exe| exe.parent().map(|p| p.join(asset_file)))
-                        .unwrap_or_else(|| PathBuf::from(asset_file))
-                };
-                if root_index.exists() && root_index.is_file() {
-                    self.handle_send_file(&root_index, _headers, false, res)
-                        .await?;
+            // Add aggressive caching for versioned assets (1 year, immutable)
+            // These assets have content hashes in filenames, so they're safe to cache forever
+            res.headers_mut().insert(
+                hyper::header::CACHE_CONTROL,
+                HeaderValue::from_static("public, max-age=31536000, immutable"),
+            );
+            return Ok(());
+        }
 
-                    // No caching for index.html - always revalidate to get latest version
-                    res.headers_mut().insert(
-                        hyper::header::CACHE_CONTROL,
-                        HeaderValue::from_static("no-cache, must-revalidate"),
-                    );
+        let asset_file = "assets/dist/index.html";
+        #[cfg(debug_assertions)]
+        let root_index = {
+            use std::path::PathBuf;
+            PathBuf::from(asset_file)
+        };
+        #[cfg(not(debug_assertions))]
+        let root_index = {
+            use std::path::PathBuf;
+            std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(|p| p.join(asset_file)))
+                .unwrap_or_else(|| PathBuf::from(asset_file))
+        };
+        if root_index.exists() && root_index.is_file() {
+            self.handle_send_file(&root_index, headers, false, res)
+                .await?;
 
-                    return Ok(true);
-                } else {
-                    status_not_found(res);
-                    // We wrote a 404 response for this internal asset request --
-                    // indicate the request was handled so the caller doesn't continue
-                    // processing the path.
-                    return Ok(true);
-                }
-            }
-        } else if req_path == HEALTH_CHECK_PATH {
+            // No caching for index.html - always revalidate to get latest version
+            res.headers_mut().insert(
+                hyper::header::CACHE_CONTROL,
+                HeaderValue::from_static("no-cache, must-revalidate"),
+            );
+        } else {
+            status_not_found(res);
+        }
+        Ok(())
+    }
+
+    /// Internal routes anyone may read: the health check, this API's
+    /// specification and the latest provenance anchor
+    async fn handle_internal(&self, req_path: &str, res: &mut Response) -> Result<bool> {
+        if req_path == HEALTH_CHECK_PATH {
             res.headers_mut()
                 .typed_insert(ContentType::from(mime_guess::mime::APPLICATION_JSON));
 
             *res.body_mut() = body_full(r#"{"status":"OK"}"#);
             return Ok(true);
-        } else if req_path == PROVENANCE_DB_PATH {
-            // Handle provenance database download
-            let db_path = self.provenance_db.get_db_path();
-
-            if !db_path.exists() {
-                status_not_found(res);
-                return Ok(true);
-            }
+        } else if req_path == OPENAPI_PATH {
+            set_json_response(res, openapi::spec(&self.args).to_string());
+            return Ok(true);
+        } else if req_path == PROVENANCE_ANCHOR_PATH {
+            provenance_handlers::handle_provenance_anchor(&self.provenance_db, res).await?;
+            return Ok(true);
+        }
 
-            // Set headers for file download
-            res.headers_mut().insert(
-                CONTENT_TYPE,
-                HeaderValue::from_static("application/vnd.sqlite3"),
-            );
+        Ok(false)
+    }
 
-            let filename = db_path
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("provenance.db");
-            set_content_disposition(res, false, filename)?;
+    /// Internal routes exposing server-wide data. These are only served to
+    /// users with read-write access to the whole tree.
+    async fn handle_admin(
+        &self,
+        req_path: &str,
+        req: &Request,
+        res: &mut Response,
+    ) -> Result<bool> {
+        let method = req.method();
+        let head_only = method == Method::HEAD;
+        match req_path {
+            PROVENANCE_DB_PATH if method == Method::GET || head_only => {
+                if self.guard_admin(req, res)? {
+                    provenance_handlers::handle_provenance_db_download(
+                        &self.provenance_db,
+                        head_only,
+                        res,
+                    )
+                    .await?;
+                }
+            }
+            STATS_PATH if method == Method::GET || head_only => {
+                if self.guard_admin(req, res)? {
+                    let query = req.uri().query().unwrap_or_default();
+                    let query_params: HashMap<String, String> =
+                        form_urlencoded::parse(query.as_bytes())
+                            .map(|(k, v)| (k.to_string(), v.to_string()))
+                            .collect();
+                    stats_handlers::handle_stats(
+                        &self.args.serve_path,
+                        &query_params,
+                        head_only,
+                        &self.provenance_db,
+                        res,
+                    )
+                    .await?;
+                }
+            }
+            METRICS_PATH if method == Method::GET || head_only => {
+                if self.guard_admin(req, res)? {
+                    res.headers_mut().insert(
+                        CONTENT_TYPE,
+                        HeaderValue::from_static("text/plain; version=0.0.4"),
+                    );
+                    if !head_only {
+                        *res.body_mut() = body_full(self.request_metrics.render());
+                    }
+                }
+            }
+            TRASH_PATH => {
+                if self.guard_admin(req, res)? {
+                    self.handle_trash(req, res).await?;
+                }
+            }
+            path if path == SHARES_PATH || path.starts_with(&format!("{SHARES_PATH}/")) => {
+                if self.guard_admin(req, res)? {
+                    self.handle_shares(req_path, req, res).await?;
+                }
+            }
+            GC_PATH if method == Method::POST => {
+                if self.guard_admin(req, res)? {
+                    let query = req.uri().query().unwrap_or_default();
+                    let dry_run =
+                        form_urlencoded::parse(query.as_bytes()).any(|(k, _)| k == "dry-run");
+                    let report = provenance_gc::collect(
+                        &self.provenance_db,
+                        provenance_gc::roots(&self.args),
+                        self.trash.as_ref(),
+                        dry_run,
+                    )
+                    .await?;
+                    set_json_response(res, serde_json::to_string(&report)?);
+                }
+            }
+            BACKFILL_PATH if method == Method::POST => {
+                if self.guard_admin(req, res)? {
+                    let report = provenance_backfill::backfill(
+                        &self.provenance_db,
+                        &self.keypair,
+                        provenance_backfill::roots(&self.args),
+                        self.args.trash_dir.clone(),
+                        provenance_backfill::default_jobs(),
+                    )
+                    .await?;
+                    set_json_response(res, serde_json::to_string(&report)?);
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
 
-            // Send the database file
-            self.handle_send_file(db_path, _headers, false, res).await?;
-            return Ok(true);
+    /// Favorites are kept per user; without configured users everyone shares one list.
+    pub(super) fn favorites_owner(&self, user: &Option<String>) -> Option<String> {
+        if self.args.auth.has_users() {
+            user.clone()
+        } else {
+            Some(String::new())
         }
+    }
 
+    /// Check that the request carries read-write access to the serve root.
+    /// Writes a 401/403 response and returns false otherwise.
+    pub(super) fn guard_admin(&self, req: &Request, res: &mut Response) -> Result<bool> {
+        let query = req.uri().query().unwrap_or_default();
+        let token = form_urlencoded::parse(query.as_bytes())
+            .find(|(k, _)| k == "token")
+            .map(|(_, v)| v.to_string());
+        let guard = self.guard_as("/", req.method(), req, token.as_ref(), false);
+        match guard {
+            (_, Some(access_paths))
+                if access_paths.perm().readwrite() && !access_paths.limits().upload_only =>
+            {
+                return Ok(true)
+            }
+            (None, _) => self.auth_reject(res)?,
+            (Some(_), _) => status_forbid(res),
+        }
         Ok(false)
     }
 
+    /// The signed-in user, writing a 401 response when there is none
+    fn guard_user(&self, req: &Request, res: &mut Response) -> Result<Option<String>> {
+        let (user, _) = self.guard_as("/", req.method(), req, None, false);
+        if user.is_none() {
+            self.auth_reject(res)?;
+        }
+        Ok(user)
+    }
+
     /// Serve public SPA assets from `assets/dist/*` for non-/api requests.
     /// Returns Ok(true) when the request has been handled (including 404).
     pub async fn handle_public(
@@ -973,7 +1285,7 @@ impl Server {
         uri_path: &str,
         headers: &HeaderMap<HeaderValue>,
         res: &mut Response,
-    ) -> Result<bool> {
+    ) -> Result<()> {
         // Normalize path: strip leading '/'
         let rel = uri_path.strip_prefix('/').unwrap_or(uri_path);
         let asset_file = format!("assets/dist/{}", rel.trim_start_matches('/'));
@@ -995,7 +1307,14 @@ impl Server {
 
         if path.exists() && path.is_file() {
             self.handle_send_file(&path, headers, false, res).await?;
-            return Ok(true);
+            // Bundler output under these folders has a content hash in its name
+            if rel.starts_with("assets/") || rel.starts_with("chunks/") {
+                res.headers_mut().insert(
+                    hyper::header::CACHE_CONTROL,
+                    HeaderValue::from_static("public, max-age=31536000, immutable"),
+                );
+            }
+            return Ok(());
         }
 
         // Fallback to bundled index.html (for SPA client-side routing)
@@ -1014,13 +1333,22 @@ impl Server {
                 .unwrap_or_else(|| PathBuf::from(asset_file))
         };
         if root_index.exists() && root_index.is_file() {
-            self.handle_send_file(&root_index, headers, false, res)
-                .await?;
-            return Ok(true);
+            let output =
+                Brand::from_args(&self.args).apply(&fs::read_to_string(&root_index).await?);
+            res.headers_mut().insert(
+                CONTENT_TYPE,
+                HeaderValue::from_str(&get_content_type(&root_index).await?)?,
+            );
+            res.headers_mut()
+                .typed_insert(ContentLength(output.len() as u64));
+            res.headers_mut()
+                .typed_insert(CacheControl::new().with_no_cache());
+            *res.body_mut() = body_full(output);
+            return Ok(());
         }
 
         status_not_found(res);
-        Ok(true)
+        Ok(())
     }
 
     pub async fn handle_send_file(
@@ -1030,8 +1358,29 @@ impl Server {
         head_only: bool,
         res: &mut Response,
     ) -> Result<()> {
-        let (mut file, meta) = file_utils::open_file_with_metadata(path).await?;
+        if !head_only {
+            // Notice files changed behind our back, without delaying the download
+            integrity::spawn_check(self.provenance_db.clone(), path.to_path_buf());
+        }
+        let variants = precompressed_variants(path).await;
+        let variant = pick_variant(&variants, headers);
+        let file_path = variant.map(|(v, _)| v.as_path()).unwrap_or(path);
+        let (mut file, meta) = file_utils::open_file_with_metadata(file_path).await?;
         let size = meta.len();
+        let chunks = match ChunkStore::get() {
+            Some(store) => chunk_store::read_list_async(&mut file, size)
+                .await?
+                .map(|list| (store, list)),
+            None => None,
+        };
+        if !variants.is_empty() {
+            res.headers_mut()
+                .insert(VARY, HeaderValue::from_static("Accept-Encoding"));
+        }
+        if let Some((_, encoding)) = variant {
+            res.headers_mut()
+                .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding));
+        }
         let mut use_range = true;
         if let Some((etag, last_modified)) = extract_cache_headers(&meta) {
             if let Some(if_unmodified_since) = headers.typed_get::<IfUnmodifiedSince>() {
@@ -1099,7 +1448,9 @@ impl Server {
             if let Some(ranges) = ranges {
                 if ranges.len() == 1 {
                     let (start, end) = ranges[0];
-                    file.seek(SeekFrom::Start(start)).await?;
+                    if chunks.is_none() {
+                        file.seek(SeekFrom::Start(start)).await?;
+                    }
                     let range_size = end - start + 1;
                     *res.status_mut() = StatusCode::PARTIAL_CONTENT;
                     let content_range = format!("bytes {start}-{end}/{size}");
@@ -1111,42 +1462,80 @@ impl Server {
                         return Ok(());
                     }
 
-                    let stream_body = StreamBody::new(
-                        LengthLimitedStream::new(file, range_size as usize)
-                            .map_ok(Frame::data)
-                            .map_err(|err| anyhow!("{err}")),
-                    );
-                    let boxed_body = stream_body.boxed();
-                    *res.body_mut() = boxed_body;
+                    let permit = match &self.range_streams {
+                        Some(range_streams) => Some(range_streams.acquire(path).await),
+                        None => None,
+                    };
+                    *res.body_mut() = match &chunks {
+                        Some((store, list)) => {
+                            self.file_body(store.stream(list, start, range_size), permit)
+                        }
+                        None => self.file_body(
+                            LengthLimitedStream::with_capacity(
+                                file,
+                                range_size as usize,
+                                send_buf_size(range_size),
+                            ),
+                            permit,
+                        ),
+                    };
+                } else if ranges.len() > MAX_RANGES {
+                    *res.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+                    res.headers_mut()
+                        .insert(CONTENT_RANGE, format!("bytes */{size}").parse()?);
                 } else {
+                    // Parts are streamed one after the other, each from its own reader
                     *res.status_mut() = StatusCode::PARTIAL_CONTENT;
                     let boundary = Uuid::new_v4();
-                    let mut body = Vec::new();
                     let content_type = get_content_type(path).await?;
+                    let mut parts: Vec<FileStream> = vec![];
+                    let mut body_size = 0;
                     for (start, end) in ranges {
-                        file.seek(SeekFrom::Start(start)).await?;
                         let range_size = end - start + 1;
                         let content_range = format!("bytes {start}-{end}/{size}");
                         let part_header = format!(
                             "--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: {content_range}\r\n\r\n",
                         );
-                        body.extend_from_slice(part_header.as_bytes());
-                        let mut buffer = vec![0; range_size as usize];
-                        file.read_exact(&mut buffer).await?;
-                        body.extend_from_slice(&buffer);
-                        body.extend_from_slice(b"\r\n");
+                        body_size += part_header.len() as u64 + range_size + 2;
+                        if head_only {
+                            continue;
+                        }
+                        let content: FileStream = match &chunks {
+                            Some((store, list)) => Box::pin(store.stream(list, start, range_size)),
+                            None => {
+                                let mut file = fs::File::open(path).await?;
+                                file.seek(SeekFrom::Start(start)).await?;
+                                Box::pin(LengthLimitedStream::with_capacity(
+                                    file,
+                                    range_size as usize,
+                                    send_buf_size(range_size),
+                                ))
+                            }
+                        };
+                        parts.push(Box::pin(stream::once(future::ok(Bytes::from(part_header)))));
+                        parts.push(content);
+                        parts.push(Box::pin(stream::once(future::ok(Bytes::from_static(
+                            b"\r\n",
+                        )))));
                     }
-                    body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+                    let closing = format!("--{boundary}--\r\n");
+                    body_size += closing.len() as u64;
                     res.headers_mut().insert(
                         CONTENT_TYPE,
                         format!("multipart/byteranges; boundary={boundary}").parse()?,
                     );
                     res.headers_mut()
-                        .insert(CONTENT_LENGTH, format!("{}", body.len()).parse()?);
+                        .insert(CONTENT_LENGTH, format!("{body_size}").parse()?);
                     if head_only {
                         return Ok(());
                     }
-                    *res.body_mut() = body_full(body);
+                    parts.push(Box::pin(stream::once(future::ok(Bytes::from(closing)))));
+                    let permit = match &self.range_streams {
+                        Some(range_streams) => Some(range_streams.acquire(path).await),
+                        None => None,
+                    };
+                    let body = stream::iter(parts.into_iter().map(io::Result::Ok)).try_flatten();
+                    *res.body_mut() = self.file_body(body, permit);
                 }
             } else {
                 *res.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
@@ -1160,27 +1549,53 @@ impl Server {
                 return Ok(());
             }
 
-            let reader_stream = ReaderStream::with_capacity(file, BUF_SIZE);
-            let stream_body = StreamBody::new(
-                reader_stream
-                    .map_ok(Frame::data)
-                    .map_err(|err| anyhow!("{err}")),
-            );
-            let boxed_body = stream_body.boxed();
-            *res.body_mut() = boxed_body;
+            *res.body_mut() = match &chunks {
+                Some((store, list)) => self.file_body(store.stream(list, 0, size), None),
+                None => {
+                    self.file_body(ReaderStream::with_capacity(file, send_buf_size(size)), None)
+                }
+            };
         }
         Ok(())
     }
 
+    /// Body streaming file chunks, paced to `--max-bandwidth` and holding on
+    /// to the range slot `permit` until done
+    fn file_body<S>(
+        &self,
+        stream: S,
+        permit: Option<OwnedSemaphorePermit>,
+    ) -> BoxBody<Bytes, anyhow::Error>
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + Sync + 'static,
+    {
+        let stream: Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send + Sync>> =
+            match self.args.max_bandwidth {
+                Some(rate) => Box::pin(ThrottledStream::new(stream, rate)),
+                None => Box::pin(stream),
+            };
+        StreamBody::new(
+            stream
+                .map_ok(move |chunk| {
+                    let _permit = &permit;
+                    Frame::data(chunk)
+                })
+                .map_err(|err| anyhow!("{err}")),
+        )
+        .boxed()
+    }
+
     pub async fn handle_edit_file(
         &self,
         path: &Path,
         kind: DataKind,
         head_only: bool,
         user: Option<String>,
+        locale: Locale,
         res: &mut Response,
     ) -> Result<()> {
-        let (file, meta) = file_utils::open_file_with_metadata(path).await?;
+        let meta = fs::metadata(path).await?;
+        let file = chunk_store::open(path).await?;
         let href = format!(
             "/{}",
             normalize_path(path.strip_prefix(&self.args.serve_path)?)
@@ -1198,15 +1613,18 @@ impl Server {
             auth: self.args.auth.has_users(),
             user,
             editable,
+            brand: Brand::from_args(&self.args),
+            locale,
         };
         res.headers_mut()
             .typed_insert(ContentType::from(mime_guess::mime::TEXT_HTML_UTF_8));
         let index_data = STANDARD.encode(serde_json::to_string(&data)?);
-        let output = self
-            .html
+        let output = data
+            .brand
+            .apply(&self.html)
             .replace(
                 "__ASSETS_PREFIX__",
-                &format!("{}{}", self.args.uri_prefix, self.assets_prefix),
+                &format!("{}{ASSETS_PREFIX}", self.args.uri_prefix),
             )
             .replace("__INDEX_DATA__", &index_data);
         res.headers_mut()
@@ -1220,16 +1638,45 @@ impl Server {
         Ok(())
     }
 
+    /// Handle `?tokengen`, optionally with `perm=ro|rw|w` (read-only by
+    /// default) and `expires_in=` such as `2h`, making a `?token=` link to the
+    /// path and everything under it
     pub async fn handle_tokengen(
         &self,
         relative_path: &str,
         user: Option<String>,
+        access_paths: &AccessPaths,
+        query_params: &HashMap<String, String>,
         res: &mut Response,
     ) -> Result<()> {
-        let output = self
-            .args
-            .auth
-            .generate_token(relative_path, &user.unwrap_or_default())?;
+        // Links can't make links, which would outlive them
+        if query_params.contains_key("token") {
+            status_forbid(res);
+            return Ok(());
+        }
+        let grant = query_params.get("perm").map_or("ro", |v| v.as_str());
+        let Some((perm, _)) = parse_grant(grant) else {
+            status_bad_request(res, "Invalid perm, expected ro, rw or w");
+            return Ok(());
+        };
+        if perm > access_paths.perm() {
+            status_forbid(res);
+            return Ok(());
+        }
+        let expires_in = match query_params.get("expires_in").map(|v| parse_age(v)) {
+            None => None,
+            Some(Some(age)) => Some(age),
+            Some(None) => {
+                status_bad_request(res, "Invalid expires_in, e.g. 30d or 12h");
+                return Ok(());
+            }
+        };
+        let output = self.args.auth.generate_token(
+            relative_path,
+            &user.unwrap_or_default(),
+            grant,
+            expires_in,
+        )?;
         res.headers_mut()
             .typed_insert(ContentType::from(mime_guess::mime::TEXT_PLAIN_UTF_8));
         res.headers_mut()
@@ -1243,21 +1690,13 @@ impl Server {
         entry_path: &Path,
         base_path: &Path,
         access_paths: AccessPaths,
+        public_only: Option<&PublicOnly>,
     ) -> Result<Vec<PathItem>> {
         let mut paths: Vec<PathItem> = vec![];
 
         // Add ".." parent directory link if we're in a subdirectory
         if entry_path != self.args.serve_path && entry_path != base_path {
-            use crate::server::path_item::PathType;
-
-            let parent_item = PathItem {
-                path_type: PathType::Dir,
-                name: "..".to_string(),
-                mtime: 0,
-                size: 0,
-                stamp_status: None,
-                visibility: None,
-            };
+            let parent_item = PathItem::new(PathType::Dir, "..".to_string(), 0, 0);
             paths.push(parent_item);
         }
 
@@ -1266,6 +1705,19 @@ impl Server {
                 let child_path = entry_path.join(name);
                 self.add_pathitem(&mut paths, base_path, &child_path).await;
             }
+        } else if let Some(dir_cache) = &self.dir_cache {
+            for (child_path, stat) in self.cached_dir_entries(dir_cache, entry_path).await?.iter() {
+                if is_hidden(
+                    &self.args.hidden,
+                    get_file_name(child_path),
+                    stat.path_type.is_dir(),
+                ) {
+                    continue;
+                }
+                if let Ok(item) = self.annotate_entry(child_path, base_path, *stat) {
+                    paths.push(item);
+                }
+            }
         } else {
             let mut rd = fs::read_dir(entry_path).await?;
             while let Ok(Some(entry)) = rd.next_entry().await {
@@ -1273,6 +1725,9 @@ impl Server {
                 self.add_pathitem(&mut paths, base_path, &child_path).await;
             }
         }
+        if let Some(public_only) = public_only {
+            paths.retain(|v| v.name == ".." || !public_only.is_private(&base_path.join(&v.name)));
+        }
         Ok(paths)
     }
 
@@ -1286,12 +1741,42 @@ impl Server {
         }
     }
 
+    /// Entries of `dir` from the directory cache, read and cached on a miss
+    async fn cached_dir_entries(&self, dir_cache: &DirCache, dir: &Path) -> Result<DirEntries> {
+        if let Some(entries) = dir_cache.get(dir) {
+            return Ok(entries);
+        }
+        let generation = dir_cache.generation();
+        let mut entries = vec![];
+        let mut rd = fs::read_dir(dir).await?;
+        while let Ok(Some(entry)) = rd.next_entry().await {
+            let child_path = entry.path();
+            if let Ok(Some(stat)) = self.stat_entry(&child_path).await {
+                entries.push((child_path, stat));
+            }
+        }
+        let entries = Arc::new(entries);
+        dir_cache.insert(dir, entries.clone(), generation).await;
+        Ok(entries)
+    }
+
     pub async fn to_pathitem<P: AsRef<Path>>(
         &self,
         path: P,
         base_path: P,
     ) -> Result<Option<PathItem>> {
         let path = path.as_ref();
+        match self.stat_entry(path).await? {
+            Some(stat) => self
+                .annotate_entry(path, base_path.as_ref(), stat)
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Type, mtime and size of an entry, None for symlinks leaving the root
+    /// without `--allow-symlink`
+    async fn stat_entry(&self, path: &Path) -> Result<Option<EntryStat>> {
         let (meta, meta2) = tokio::join!(fs::metadata(path), fs::symlink_metadata(path));
         let (meta, meta2) = (meta?, meta2?);
         let is_symlink = meta2.is_symlink();
@@ -1333,39 +1818,94 @@ impl Server {
             }
             PathType::File | PathType::SymlinkFile => meta.len(),
         };
+        Ok(Some(EntryStat {
+            path_type,
+            mtime,
+            size,
+        }))
+    }
+
+    /// A listed entry with its provenance and annotations
+    fn annotate_entry(&self, path: &Path, base_path: &Path, stat: EntryStat) -> Result<PathItem> {
+        let EntryStat {
+            path_type,
+            mtime,
+            size,
+        } = stat;
         let rel_path = path.strip_prefix(base_path)?;
         let name = normalize_path(rel_path);
 
-        // Compute stamp status for files (not directories)
-        let stamp_status = if matches!(path_type, PathType::File | PathType::SymlinkFile) {
-            provenance_handlers::compute_stamp_status(path, &self.provenance_db).await
+        let artifact = match (path_type, path.to_str()) {
+            (PathType::File | PathType::SymlinkFile, Some(path_str)) => self
+                .provenance_db
+                .get_artifact_by_path(path_str)
+                .ok()
+                .flatten()
+                .map(|(_, artifact)| artifact),
+            _ => None,
+        };
+
+        // Explicit visibility wins, files otherwise follow their shares
+        let visibility = path.to_str().and_then(|path_str| {
+            match self
+                .provenance_db
+                .get_path_visibility(path_str)
+                .ok()
+                .flatten()
+            {
+                Some(v) => Some(v),
+                None => artifact.as_ref().map(|v| v.visibility.clone()),
+            }
+        });
+
+        let retention = if matches!(path_type, PathType::File | PathType::SymlinkFile) {
+            path.strip_prefix(&self.args.serve_path)
+                .ok()
+                .map(normalize_path)
+                .and_then(|rel| {
+                    let rule = self.args.retention.rule_for(&rel)?;
+                    Some(RetentionStatus {
+                        rule: format!("/{}", rule.path),
+                        expires_at: mtime + rule.max_age.as_millis() as u64,
+                    })
+                })
         } else {
             None
         };
 
-        // Get visibility status from provenance DB (only for files)
-        let visibility = if matches!(path_type, PathType::File | PathType::SymlinkFile) {
-            if let Some(path_str) = path.to_str() {
+        let (metadata, comment_count, tags) = match path.to_str() {
+            Some(path_str) if matches!(path_type, PathType::File | PathType::SymlinkFile) => (
                 self.provenance_db
-                    .get_artifact_by_path(path_str)
+                    .get_file_metadata(path_str)
                     .ok()
-                    .flatten()
-                    .map(|(_, artifact)| artifact.visibility)
-            } else {
-                None
-            }
-        } else {
-            None
+                    .filter(|v| !v.is_empty()),
+                self.provenance_db
+                    .count_file_comments(path_str)
+                    .ok()
+                    .filter(|v| *v > 0),
+                self.provenance_db
+                    .get_file_tags(path_str)
+                    .ok()
+                    .filter(|v| !v.is_empty()),
+            ),
+            _ => (None, None, None),
         };
 
-        Ok(Some(PathItem {
+        Ok(PathItem {
+            category: category_of(path_type, &name),
+            partial: is_partial_upload(path_type, &name),
+            encryption: artifact.and_then(|v| v.encryption),
             path_type,
             name,
             mtime,
             size,
-            stamp_status,
             visibility,
-        }))
+            retention,
+            metadata,
+            comment_count,
+            tags,
+            matches: None,
+        })
     }
 
     // Helper methods from mod.rs
@@ -1413,6 +1953,15 @@ impl Server {
         Some(self.args.serve_path.join(path))
     }
 
+    /// The inverse of `join_path`, as shown to clients (`/dir/file`)
+    pub(super) fn relative_path(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.args.serve_path).unwrap_or(path);
+        format!(
+            "/{}",
+            relative.to_string_lossy().replace(MAIN_SEPARATOR, "/")
+        )
+    }
+
     pub(super) fn auth_reject(&self, res: &mut Response) -> Result<()> {
         use super::response_utils::set_webdav_headers;
         use crate::auth::www_authenticate;
@@ -1431,17 +1980,17 @@ impl Server {
             .unwrap_or_default()
     }
 
+    /// The destination of a WebDAV COPY or MOVE, with the access the user
+    /// has there, which must allow the method too
     pub(super) fn extract_dest(
         &self,
         req: &Request,
         res: &mut Response,
-    ) -> Option<std::path::PathBuf> {
-        use super::response_utils::{status_bad_request, status_forbid};
-        use hyper::header::AUTHORIZATION;
+    ) -> Option<(std::path::PathBuf, AccessPaths)> {
+        use super::response_utils::status_bad_request;
 
-        let headers = req.headers();
         let dest_path = match self
-            .extract_destination_header(headers)
+            .extract_destination_header(req.headers())
             .and_then(|dest| self.resolve_path(&dest))
         {
             Some(dest) => dest,
@@ -1450,22 +1999,35 @@ impl Server {
                 return None;
             }
         };
+        let (dest, access_paths) = self.authorize_dest(&dest_path, req, res)?;
+        if !access_paths.limits().allow(req.method(), false) {
+            status_forbid(res);
+            return None;
+        }
+        Some((dest, access_paths))
+    }
+
+    /// Check that the user of `req` may write to `dest_path`, relative to
+    /// the served directory, and resolve it
+    pub(super) fn authorize_dest(
+        &self,
+        dest_path: &str,
+        req: &Request,
+        res: &mut Response,
+    ) -> Option<(std::path::PathBuf, AccessPaths)> {
+        use super::response_utils::status_forbid;
 
-        let authorization = headers.get(AUTHORIZATION);
-        let guard = self
-            .args
-            .auth
-            .guard(&dest_path, req.method(), authorization, None, false);
+        let guard = self.guard_as(dest_path, req.method(), req, None, false);
 
-        match guard {
-            (_, Some(_)) => {}
+        let access_paths = match guard {
+            (_, Some(access_paths)) => access_paths,
             _ => {
                 status_forbid(res);
                 return None;
             }
         };
 
-        let dest = match self.join_path(&dest_path) {
+        let dest = match self.join_path(dest_path) {
             Some(dest) => dest,
             None => {
                 *res.status_mut() = StatusCode::BAD_REQUEST;
@@ -1473,7 +2035,52 @@ impl Server {
             }
         };
 
-        Some(dest)
+        // Provenance records can't follow a file into another tenant's database
+        if !self.is_same_tenant(&dest) {
+            status_forbid(res);
+            return None;
+        }
+
+        Some((dest, access_paths))
+    }
+
+    /// Make way for COPY or MOVE of `path` to `dest`: an existing destination
+    /// is deleted first, unless the request has `Overwrite: F` or the grant
+    /// on `dest` doesn't allow deleting it. Writes the response and returns
+    /// false when the request can't go on.
+    pub(super) async fn prepare_dest(
+        &self,
+        path: &Path,
+        dest: &Path,
+        dest_access: &AccessPaths,
+        user: Option<&str>,
+        headers: &HeaderMap<HeaderValue>,
+        res: &mut Response,
+    ) -> Result<bool> {
+        if dest.starts_with(path) {
+            status_forbid(res);
+            return Ok(false);
+        }
+        let Ok(meta) = fs::symlink_metadata(dest).await else {
+            return Ok(true);
+        };
+        let overwrite = headers
+            .get("overwrite")
+            .is_none_or(|v| !v.as_bytes().eq_ignore_ascii_case(b"F"));
+        if !overwrite {
+            *res.status_mut() = StatusCode::PRECONDITION_FAILED;
+            return Ok(false);
+        }
+        // Replacing the destination deletes it, like a PUT over a file
+        let limits = dest_access.limits();
+        let may_overwrite = self.args.allow_delete && !limits.no_delete && !limits.upload_only;
+        if !may_overwrite {
+            status_forbid(res);
+            return Ok(false);
+        }
+        self.handle_delete(dest, meta.is_dir(), user, res).await?;
+        self.locks.forget(dest);
+        Ok(true)
     }
 
     fn extract_destination_header(&self, headers: &HeaderMap<HeaderValue>) -> Option<String> {
@@ -1489,28 +2096,27 @@ impl Server {
         path: &Path,
         headers: &HeaderMap<HeaderValue>,
         access_paths: AccessPaths,
+        public_only: Option<&PublicOnly>,
         res: &mut Response,
     ) -> Result<()> {
         use super::response_utils::{res_multistatus, status_bad_request, status_forbid};
 
-        let depth: u32 = match headers.get("depth") {
-            Some(v) => match v.to_str().ok().and_then(|v| v.parse().ok()) {
-                Some(0) => 0,
-                Some(1) => 1,
-                _ => {
-                    status_bad_request(res, "Invalid depth: only 0 and 1 are allowed.");
-                    return Ok(());
-                }
-            },
-            None => 1,
+        let Some(depth) = propfind_depth(headers) else {
+            status_bad_request(res, "Invalid depth: only 0, 1 and infinity are allowed.");
+            return Ok(());
         };
+        if depth == DEPTH_INFINITY {
+            return self
+                .handle_propfind_infinity(path, access_paths, public_only, res)
+                .await;
+        }
         let mut paths = match self.to_pathitem(path, &self.args.serve_path).await? {
             Some(v) => vec![v],
             None => vec![],
         };
         if depth == 1 {
             match self
-                .list_dir(path, &self.args.serve_path, access_paths)
+                .list_dir(path, &self.args.serve_path, access_paths, public_only)
                 .await
             {
                 Ok(child) => {
@@ -1523,61 +2129,158 @@ impl Server {
                 }
             }
         }
-        let output = paths
-            .iter()
-            .map(|v| v.to_dav_xml(self.args.uri_prefix.as_str()))
-            .fold(String::new(), |mut acc, v| {
-                acc.push_str(&v);
-                acc
-            });
+        let mut output = String::new();
+        for item in &paths {
+            let dead_properties = self.dead_properties(&self.args.serve_path.join(&item.name))?;
+            output.push_str(&item.to_dav_xml(self.args.uri_prefix.as_str(), &dead_properties));
+        }
         res_multistatus(res, &output);
         Ok(())
     }
 
+    /// PROPFIND with `Depth: infinity`, answered for folders of up to
+    /// `--propfind-max-entries` entries and refused with
+    /// `propfind-finite-depth` otherwise, so clients fall back to Depth 1
+    async fn handle_propfind_infinity(
+        &self,
+        path: &Path,
+        access_paths: AccessPaths,
+        public_only: Option<&PublicOnly>,
+        res: &mut Response,
+    ) -> Result<()> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let Some(max_entries) = self.args.propfind_max_entries else {
+            webdav::status_propfind_finite_depth(res);
+            return Ok(());
+        };
+        let Some(root) = self.to_pathitem(path, &self.args.serve_path).await? else {
+            status_not_found(res);
+            return Ok(());
+        };
+
+        // Stop walking as soon as the folder turns out to be too large
+        let walking = Arc::new(AtomicBool::new(true));
+        let count = AtomicUsize::new(0);
+        let public_only = public_only.cloned();
+        let paths = tokio::task::spawn(collect_dir_entries(
+            access_paths,
+            walking.clone(),
+            path.to_path_buf(),
+            Arc::new(self.args.hidden.to_vec()),
+            self.args.allow_symlink,
+            self.args.serve_path.clone(),
+            move |x| {
+                if public_only.as_ref().is_some_and(|v| v.is_private(x.path())) {
+                    return false;
+                }
+                if count.fetch_add(1, Ordering::Relaxed) >= max_entries {
+                    walking.store(false, Ordering::SeqCst);
+                }
+                true
+            },
+        ))
+        .await?;
+        if paths.len() > max_entries {
+            webdav::status_propfind_finite_depth(res);
+            return Ok(());
+        }
+
+        let mut items = vec![root];
+        for path in paths {
+            if !self.running.load(std::sync::atomic::Ordering::SeqCst) {
+                break;
+            }
+            if let Some(item) = self
+                .to_pathitem(path.as_path(), &self.args.serve_path)
+                .await?
+            {
+                items.push(item);
+            }
+        }
+        let provenance_db = self.provenance_db.clone();
+        let serve_path = self.args.serve_path.clone();
+        let uri_prefix = self.args.uri_prefix.clone();
+        webdav::res_multistatus_stream(
+            res,
+            items.into_iter().map(move |item| {
+                let dead_properties = match serve_path.join(&item.name).to_str() {
+                    Some(path) => provenance_db.get_dead_properties(path)?,
+                    None => vec![],
+                };
+                Ok(item.to_dav_xml(&uri_prefix, &dead_properties))
+            }),
+        );
+        Ok(())
+    }
+
     pub(super) async fn handle_propfind_file(&self, path: &Path, res: &mut Response) -> Result<()> {
         use super::response_utils::{res_multistatus, status_not_found};
 
         if let Some(pathitem) = self.to_pathitem(path, &self.args.serve_path).await? {
-            res_multistatus(res, &pathitem.to_dav_xml(self.args.uri_prefix.as_str()));
+            let dead_properties = self.dead_properties(path)?;
+            res_multistatus(
+                res,
+                &pathitem.to_dav_xml(self.args.uri_prefix.as_str(), &dead_properties),
+            );
         } else {
             status_not_found(res);
         }
         Ok(())
     }
 
+    fn dead_properties(&self, path: &Path) -> Result<Vec<DeadProperty>> {
+        match path.to_str() {
+            Some(path) => self.provenance_db.get_dead_properties(path),
+            None => Ok(vec![]),
+        }
+    }
+
     pub(super) async fn create_mint_event(
         &self,
         path: &Path,
+        sha256_hex: String,
+        user: Option<&str>,
+        wait_stamp: bool,
     ) -> Result<super::path_item::MintEventResponse> {
         use crate::provenance::{
             compute_event_hash, sign_event_hash, verify_event, Actors, Event, EventAction,
-            Signatures, SERVER_PRIVATE_KEY_HEX, SERVER_PUBLIC_KEY_HEX,
+            Signatures,
         };
         use base64::{engine::general_purpose::STANDARD, Engine as _};
 
-        // Compute file hash and upsert artifact
-        let sha256_hex = file_utils::sha256_file_hash(path).await?;
+        // Upsert artifact with the hash computed while uploading
         let path_str = path
             .to_str()
-            .ok_or_else(|| anyhow!("Invalid UTF-8 in path"))?;
-        let artifact_id = self.provenance_db.upsert_artifact(path_str, &sha256_hex)?;
+            .ok_or_else(|| anyhow!("Invalid UTF-8 in path"))?
+            .to_string();
+        let hash = sha256_hex.clone();
+        let (artifact_id, next_index) = self
+            .provenance_db
+            .run(move |db| {
+                let artifact_id = db.upsert_artifact(&path_str, &hash)?;
+                Ok((artifact_id, db.get_next_event_index(artifact_id)?))
+            })
+            .await?;
 
         let file_name = file_utils::extract_filename(path)?.to_string();
 
         // Check if mint event already exists
-        let next_index = self.provenance_db.get_next_event_index(artifact_id)?;
         if next_index > 0 {
             // Artifact already has events, return existing mint event
-            let manifest = self
-                .provenance_db
-                .get_manifest_by_path(path_str)?
+            let manifest = provenance_utils::get_manifest_for_file(&self.provenance_db, path)
+                .await?
                 .ok_or_else(|| anyhow!("Manifest not found after checking event index"))?;
 
             let first_event = &manifest.events[0];
 
             // Compute stamp status for existing event
-            let stamp_status =
-                provenance_handlers::compute_stamp_status(path, &self.provenance_db).await;
+            let stamp_status = provenance_handlers::compute_stamp_status(
+                path,
+                &self.provenance_db,
+                &self.stamp_statuses,
+            )
+            .await;
 
             return Ok(super::path_item::MintEventResponse {
                 filename: file_name,
@@ -1585,13 +2288,27 @@ impl Server {
                 ots_base64: first_event.ots_proof_b64.clone(),
                 event_hash: first_event.event_hash_hex.clone(),
                 issued_at: first_event.issued_at.clone(),
+                creator_pubkey_hex: first_event.actors.creator_pubkey_hex.clone(),
                 stamp_status,
             });
         }
 
-        // Use server's static keypair for signing
+        // Uploaders with a registered key are the creator, otherwise the server is
+        let user_key = match user {
+            Some(user) => {
+                let user = user.to_string();
+                self.provenance_db
+                    .run(move |db| db.get_user_key(&user))
+                    .await?
+            }
+            None => None,
+        };
         let actors = Actors {
-            creator_pubkey_hex: Some(SERVER_PUBLIC_KEY_HEX.to_string()),
+            creator_pubkey_hex: Some(
+                user_key
+                    .clone()
+                    .unwrap_or_else(|| self.keypair.public_key_hex.clone()),
+            ),
             prev_owner_pubkey_hex: None,
             new_owner_pubkey_hex: None,
         };
@@ -1608,12 +2325,17 @@ impl Server {
             &issued_at,
         );
 
-        // Sign the event hash with server's private key
-        let creator_signature = sign_event_hash(&event_hash_hex, SERVER_PRIVATE_KEY_HEX)
-            .map_err(|e| anyhow!("Failed to sign event: {}", e))?;
+        // Sign the event hash with server's private key, users sign their own later (?sign)
+        let creator_signature = match user_key {
+            Some(_) => None,
+            None => Some(
+                sign_event_hash(&event_hash_hex, &self.keypair.private_key_hex)
+                    .map_err(|e| anyhow!("Failed to sign event: {}", e))?,
+            ),
+        };
 
         let signatures = Signatures {
-            creator_sig_hex: Some(creator_signature),
+            creator_sig_hex: creator_signature,
             prev_owner_sig_hex: None,
             new_owner_sig_hex: None,
         };
@@ -1622,33 +2344,12 @@ impl Server {
         let digest =
             hex::decode(&sha256_hex).map_err(|e| anyhow!("Failed to decode SHA256 hex: {}", e))?;
 
-        let ots_bytes = match crate::ots_stamper::create_timestamp(&digest).await {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                warn!("Failed to create OTS proof for mint event: {}", e);
-                // Fall back to placeholder if OTS stamping fails
-                Vec::from(b"PLACEHOLDER_OTS_PROOF" as &[u8])
-            }
+        let ots_bytes = match wait_stamp {
+            true => self.ots.try_stamp(digest).await,
+            false => None,
         };
+        let ots_proof_b64 = STANDARD.encode(ots_bytes.as_deref().unwrap_or(QUEUED_PROOF));
 
-        let ots_proof_b64 = STANDARD.encode(&ots_bytes);
-
-        // Insert mint event
-        self.provenance_db
-            .insert_event(crate::provenance::InsertEventArgs {
-                artifact_id,
-                index: 0,
-                action: &EventAction::Mint,
-                artifact_sha256_hex: &sha256_hex,
-                prev_event_hash_hex: None,
-                issued_at: &issued_at,
-                event_hash_hex: &event_hash_hex,
-                ots_proof_b64: &ots_proof_b64,
-                actors: &actors,
-                signatures: &signatures,
-            })?;
-
-        // Verify the event we just created
         let created_event = Event {
             event_type: "provenance.event/v1".to_string(),
             index: 0,
@@ -1662,7 +2363,49 @@ impl Server {
             ots_proof_b64: ots_proof_b64.clone(),
         };
 
+        // Insert mint event
+        let event = created_event.clone();
+        self.provenance_db
+            .run(move |db| {
+                db.insert_event(crate::provenance::InsertEventArgs {
+                    artifact_id,
+                    index: 0,
+                    action: &event.action,
+                    artifact_sha256_hex: &event.artifact_sha256_hex,
+                    prev_event_hash_hex: None,
+                    issued_at: &event.issued_at,
+                    event_hash_hex: &event.event_hash_hex,
+                    ots_proof_b64: &event.ots_proof_b64,
+                    actors: &event.actors,
+                    signatures: &event.signatures,
+                })?;
+                if ots_bytes.is_none() {
+                    db.queue_ots_digest(artifact_id, 0, &event.artifact_sha256_hex)?;
+                }
+                Ok(())
+            })
+            .await?;
+        self.stamp_statuses.remove(path);
+        if !wait_stamp {
+            self.ots.spawn_stamp(
+                self.provenance_db.clone(),
+                QueuedDigest {
+                    artifact_id,
+                    event_index: 0,
+                    digest_hex: sha256_hex.clone(),
+                },
+            );
+        }
+
+        // Verify the event we just created
         match verify_event(&created_event) {
+            _ if created_event.signatures.creator_sig_hex.is_none() => {
+                info!(
+                    "Created mint event for {} ({}), awaiting the creator's signature",
+                    file_name,
+                    &sha256_hex[..8]
+                );
+            }
             Ok(true) => {
                 info!(
                     "Created and verified mint event for {} ({})",
@@ -1688,17 +2431,24 @@ impl Server {
             ots_base64: ots_proof_b64,
             event_hash: event_hash_hex,
             issued_at,
+            creator_pubkey_hex: created_event.actors.creator_pubkey_hex,
             stamp_status: Some(super::path_item::StampStatus {
                 success: false,
                 results: None,
                 error: None, // No error, just pending Bitcoin confirmation
                 sha256_hex: Some(sha256_hex),
+                modified_since_mint: false,
             }),
         })
     }
 }
 
-async fn ensure_path_parent(path: &Path) -> Result<()> {
+/// Requests writing a file, limited by `--max-concurrent-uploads`
+pub(super) fn is_upload(req: &Request) -> bool {
+    matches!(*req.method(), Method::PUT | Method::PATCH)
+}
+
+pub(super) async fn ensure_path_parent(path: &Path) -> Result<()> {
     if let Some(parent) = path.parent() {
         if fs::symlink_metadata(parent).await.is_err() {
             fs::create_dir_all(&parent).await?;
@@ -1714,6 +2464,53 @@ pub(super) fn has_query_flag(query_params: &HashMap<String, String>, name: &str)
         .unwrap_or_default()
 }
 
+/// Answer 412 when the request's `If-Match` or `If-None-Match` don't hold for
+/// the current state of `path`, so a write can't clobber a change made since
+/// the client read it. ETags are the ones GET hands out.
+pub(super) async fn reject_preconditions(
+    path: &Path,
+    headers: &HeaderMap<HeaderValue>,
+    res: &mut Response,
+) -> bool {
+    let if_match = headers.typed_get::<IfMatch>();
+    let if_none_match = headers.typed_get::<IfNoneMatch>();
+    if if_match.is_none() && if_none_match.is_none() {
+        return false;
+    }
+    let etag = fs::metadata(path)
+        .await
+        .ok()
+        .and_then(|meta| extract_cache_headers(&meta))
+        .map(|(etag, _)| etag);
+    let passes = match &etag {
+        Some(etag) => {
+            if_match.is_none_or(|v| v.precondition_passes(etag))
+                && if_none_match.is_none_or(|v| v.precondition_passes(etag))
+        }
+        // `If-Match` needs a current file, even for `*`
+        None => if_match.is_none(),
+    };
+    if !passes {
+        *res.status_mut() = StatusCode::PRECONDITION_FAILED;
+    }
+    !passes
+}
+
+/// The sha-256 digest declared in a `Content-Digest` or `Repr-Digest` header
+pub(super) fn declared_digest(
+    headers: &HeaderMap<HeaderValue>,
+    name: &str,
+) -> Result<Option<String>> {
+    match headers.get(name) {
+        Some(value) => parse_sha256_digest(
+            value
+                .to_str()
+                .map_err(|_| anyhow!("Invalid {name} header"))?,
+        ),
+        None => Ok(None),
+    }
+}
+
 pub(crate) fn parse_upload_offset(
     headers: &HeaderMap<HeaderValue>,
     size: u64,
@@ -1732,7 +2529,36 @@ pub(crate) fn parse_upload_offset(
     Ok(Some(*start))
 }
 
-fn is_hidden(hidden: &[String], file_name: &str, is_dir: bool) -> bool {
+/// Flush a change to the entries of a directory, like a rename, to disk
+async fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    fs::File::open(dir).await?.sync_all().await?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// The `.<name>.partial` file next to `path` that a PUT is written to
+pub(super) fn partial_upload_path(path: &Path) -> PathBuf {
+    path.with_file_name(format!(".{}{PARTIAL_UPLOAD_SUFFIX}", get_file_name(path)))
+}
+
+/// `Depth: infinity`, as returned by `propfind_depth`
+pub(super) const DEPTH_INFINITY: u32 = u32::MAX;
+
+/// PROPFIND depth, defaulting to 1; None for anything but 0, 1 or infinity
+pub(super) fn propfind_depth(headers: &HeaderMap<HeaderValue>) -> Option<u32> {
+    match headers.get("depth") {
+        Some(v) if v.as_bytes().eq_ignore_ascii_case(b"infinity") => Some(DEPTH_INFINITY),
+        Some(v) => match v.to_str().ok().and_then(|v| v.parse().ok()) {
+            Some(depth @ (0 | 1)) => Some(depth),
+            _ => None,
+        },
+        None => Some(1),
+    }
+}
+
+pub(super) fn is_hidden(hidden: &[String], file_name: &str, is_dir: bool) -> bool {
     use crate::utils::glob;
     hidden.iter().any(|v| {
         if is_dir {
@@ -1800,38 +2626,161 @@ where
     paths
 }
 
+/// Like `collect_dir_entries`, but for candidates looked up in the search index
+pub(crate) async fn collect_indexed_entries<F>(
+    candidates: Vec<(PathBuf, bool)>,
+    access_paths: &AccessPaths,
+    path: &Path,
+    hidden: &[String],
+    follow_symlinks: bool,
+    serve_path: &Path,
+    include_entry: F,
+) -> Vec<PathBuf>
+where
+    F: Fn(&Path, bool) -> bool,
+{
+    let entry_paths = access_paths.entry_paths(path);
+    let mut seen = HashSet::new();
+    let mut paths = vec![];
+    for (entry_path, is_dir) in candidates {
+        let Some(relative) = entry_paths
+            .iter()
+            .find_map(|v| entry_path.strip_prefix(v).ok())
+            .filter(|v| !v.as_os_str().is_empty())
+        else {
+            continue;
+        };
+        // Hidden directories hide everything below them
+        let names: Vec<_> = relative.iter().map(|v| v.to_string_lossy()).collect();
+        let last = names.len() - 1;
+        if names
+            .iter()
+            .enumerate()
+            .any(|(i, name)| is_hidden(hidden, name, i < last || is_dir))
+        {
+            continue;
+        }
+        if !follow_symlinks
+            && !fs::canonicalize(&entry_path)
+                .await
+                .ok()
+                .map(|v| v.starts_with(serve_path))
+                .unwrap_or_default()
+        {
+            continue;
+        }
+        if include_entry(&entry_path, !is_dir) && seen.insert(entry_path.clone()) {
+            paths.push(entry_path);
+        }
+    }
+    paths
+}
+
+/// Archive formats a directory can be downloaded as
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub(super) fn from_query(query_params: &HashMap<String, String>) -> Option<Self> {
+        if has_query_flag(query_params, "zip") {
+            Some(Self::Zip)
+        } else if has_query_flag(query_params, "tar") {
+            Some(Self::Tar)
+        } else if has_query_flag(query_params, "tar.gz") {
+            Some(Self::TarGz)
+        } else {
+            None
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::Tar => "tar",
+            Self::TarGz => "tar.gz",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Self::Zip => "application/zip",
+            Self::Tar => "application/x-tar",
+            Self::TarGz => "application/gzip",
+        }
+    }
+}
+
+/// Files of `dir` that go into an archive, honoring hidden, symlink and access rules
+pub(super) async fn collect_archive_entries(
+    dir: &Path,
+    access_paths: AccessPaths,
+    public_only: Option<PublicOnly>,
+    hidden: &[String],
+    follow_symlinks: bool,
+    serve_path: std::path::PathBuf,
+    running: Arc<std::sync::atomic::AtomicBool>,
+) -> Result<Vec<std::path::PathBuf>> {
+    let paths = tokio::task::spawn(collect_dir_entries(
+        access_paths,
+        running,
+        dir.to_path_buf(),
+        Arc::new(hidden.to_vec()),
+        follow_symlinks,
+        serve_path,
+        move |x| {
+            x.path().symlink_metadata().is_ok()
+                && x.file_type().is_file()
+                && !public_only.as_ref().is_some_and(|v| v.is_private(x.path()))
+        },
+    ))
+    .await?;
+    Ok(paths)
+}
+
 #[allow(clippy::too_many_arguments)]
 pub(crate) async fn zip_dir<W: tokio::io::AsyncWrite + Unpin>(
     writer: &mut W,
     dir: &Path,
     access_paths: AccessPaths,
+    public_only: Option<PublicOnly>,
     hidden: &[String],
     compression: async_zip::Compression,
     follow_symlinks: bool,
     serve_path: std::path::PathBuf,
     running: Arc<std::sync::atomic::AtomicBool>,
 ) -> Result<()> {
-    use crate::utils::get_file_mtime_and_mode;
-    use async_zip::{tokio::write::ZipFileWriter, ZipDateTime, ZipEntryBuilder};
-    use std::path::MAIN_SEPARATOR;
-    use tokio::fs::File;
-    use tokio_util::compat::FuturesAsyncWriteCompatExt;
-
-    let mut writer = ZipFileWriter::with_tokio(writer);
-    let hidden = Arc::new(hidden.to_vec());
-    let zip_paths = tokio::task::spawn(collect_dir_entries(
+    let zip_paths = collect_archive_entries(
+        dir,
         access_paths,
-        running,
-        dir.to_path_buf(),
+        public_only,
         hidden,
         follow_symlinks,
         serve_path,
-        move |x| x.path().symlink_metadata().is_ok() && x.file_type().is_file(),
-    ))
+        running,
+    )
     .await?;
-    for zip_path in zip_paths.into_iter() {
+    write_zip(writer, dir, zip_paths, compression).await
+}
+
+/// Write `paths` as a zip archive, naming entries relative to `base`
+pub(super) async fn write_zip<W: tokio::io::AsyncWrite + Unpin>(
+    writer: &mut W,
+    base: &Path,
+    paths: Vec<std::path::PathBuf>,
+    compression: async_zip::Compression,
+) -> Result<()> {
+    use crate::utils::get_file_mtime_and_mode;
+    use async_zip::{tokio::write::ZipFileWriter, ZipDateTime, ZipEntryBuilder};
+    use tokio_util::compat::FuturesAsyncWriteCompatExt;
+
+    let mut writer = ZipFileWriter::with_tokio(writer);
+    for zip_path in paths.into_iter() {
         let filename = match zip_path
-            .strip_prefix(dir)
+            .strip_prefix(base)
             .ok()
             .and_then(|v| v.to_str())
             .map(|v| v.replace(MAIN_SEPARATOR, "/"))
@@ -1843,7 +2792,7 @@ pub(crate) async fn zip_dir<W: tokio::io::AsyncWrite + Unpin>(
         let builder = ZipEntryBuilder::new(filename.into(), compression)
             .unix_permissions(mode)
             .last_modification_date(ZipDateTime::from_chrono(&datetime));
-        let mut file = File::open(&zip_path).await?;
+        let mut file = chunk_store::open(&zip_path).await?;
         let mut file_writer = writer.write_entry_stream(builder).await?.compat_write();
         io::copy(&mut file, &mut file_writer).await?;
         file_writer.into_inner().close().await?;
@@ -1851,3 +2800,56 @@ pub(crate) async fn zip_dir<W: tokio::io::AsyncWrite + Unpin>(
     writer.close().await?;
     Ok(())
 }
+
+/// Stream `dir` as a tar archive (gzipped when `gzip`), keeping file modes and mtimes
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn tar_dir<W: tokio::io::AsyncWrite + Unpin + Send + 'static>(
+    writer: W,
+    dir: &Path,
+    gzip: bool,
+    access_paths: AccessPaths,
+    public_only: Option<PublicOnly>,
+    hidden: &[String],
+    follow_symlinks: bool,
+    serve_path: std::path::PathBuf,
+    running: Arc<std::sync::atomic::AtomicBool>,
+) -> Result<()> {
+    use async_compression::tokio::write::GzipEncoder;
+    use tokio::io::AsyncWriteExt;
+    use tokio_tar::Builder;
+
+    let tar_paths = collect_archive_entries(
+        dir,
+        access_paths,
+        public_only,
+        hidden,
+        follow_symlinks,
+        serve_path,
+        running,
+    )
+    .await?;
+    let writer: Box<dyn tokio::io::AsyncWrite + Unpin + Send> = match gzip {
+        true => Box::new(GzipEncoder::new(writer)),
+        false => Box::new(writer),
+    };
+    let mut builder = Builder::new(writer);
+    builder.follow_symlinks(follow_symlinks);
+    for tar_path in tar_paths.into_iter() {
+        let Ok(name) = tar_path.strip_prefix(dir) else {
+            continue;
+        };
+        // Chunked files go in with their content rather than their chunk list
+        let meta = fs::metadata(&tar_path).await?;
+        if meta.is_file() && chunk_store::chunk_list(&tar_path).await?.is_some() {
+            let mut header = tokio_tar::Header::new_gnu();
+            header.set_metadata(&meta);
+            let file = chunk_store::open(&tar_path).await?;
+            builder.append_data(&mut header, name, file).await?;
+            continue;
+        }
+        builder.append_path_with_name(&tar_path, name).await?;
+    }
+    let mut writer = builder.into_inner().await?;
+    writer.shutdown().await?;
+    Ok(())
+}
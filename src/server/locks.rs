@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Timeout of locks that don't ask for one
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+/// Longest timeout granted, also for `Timeout: Infinite`
+pub const MAX_LOCK_TIMEOUT: Duration = Duration::from_secs(60 * 60 * 24);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockScope {
+    Exclusive,
+    Shared,
+}
+
+impl LockScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LockScope::Exclusive => "exclusive",
+            LockScope::Shared => "shared",
+        }
+    }
+}
+
+/// A WebDAV write lock
+#[derive(Debug, Clone)]
+pub struct ActiveLock {
+    pub token: String,
+    pub path: PathBuf,
+    /// URL the lock was taken on, reported as its lock root
+    pub href: String,
+    pub scope: LockScope,
+    /// Depth infinity, covering everything under `path`
+    pub deep: bool,
+    /// The client's `<D:owner>` element, as sent
+    pub owner: Option<String>,
+    pub timeout: Duration,
+    expires: Instant,
+}
+
+impl ActiveLock {
+    fn covers(&self, path: &Path) -> bool {
+        self.path == path || (self.deep && path.starts_with(&self.path))
+    }
+
+    /// Whether the lock applies to a change of `path`, or of anything under
+    /// it when `deep`
+    fn applies_to(&self, path: &Path, deep: bool) -> bool {
+        self.covers(path) || (deep && self.path.starts_with(path))
+    }
+}
+
+/// In-memory table of the WebDAV locks, which end when the server restarts
+#[derive(Debug, Clone, Default)]
+pub struct LockManager {
+    locks: Arc<Mutex<HashMap<String, ActiveLock>>>,
+}
+
+impl LockManager {
+    /// Lock `path`, None when it conflicts with an existing lock
+    pub fn lock(
+        &self,
+        path: &Path,
+        href: &str,
+        scope: LockScope,
+        deep: bool,
+        owner: Option<String>,
+        timeout: Duration,
+    ) -> Option<ActiveLock> {
+        let mut locks = self.active_locks();
+        let conflict = locks.values().any(|lock| {
+            lock.applies_to(path, deep)
+                && (scope == LockScope::Exclusive || lock.scope == LockScope::Exclusive)
+        });
+        if conflict {
+            return None;
+        }
+        let timeout = timeout.min(MAX_LOCK_TIMEOUT);
+        let lock = ActiveLock {
+            token: format!("opaquelocktoken:{}", Uuid::new_v4()),
+            path: path.to_path_buf(),
+            href: href.to_string(),
+            scope,
+            deep,
+            owner,
+            timeout,
+            expires: Instant::now() + timeout,
+        };
+        locks.insert(lock.token.clone(), lock.clone());
+        Some(lock)
+    }
+
+    /// Extend the first of `tokens` that locks `path`
+    pub fn refresh(&self, path: &Path, tokens: &[String], timeout: Duration) -> Option<ActiveLock> {
+        let mut locks = self.active_locks();
+        let token = tokens
+            .iter()
+            .find(|token| locks.get(*token).is_some_and(|lock| lock.covers(path)))?;
+        let lock = locks.get_mut(token)?;
+        lock.timeout = timeout.min(MAX_LOCK_TIMEOUT);
+        lock.expires = Instant::now() + lock.timeout;
+        Some(lock.clone())
+    }
+
+    /// Remove the lock `token` on `path`, returning whether there was one
+    pub fn unlock(&self, path: &Path, token: &str) -> bool {
+        let mut locks = self.active_locks();
+        match locks.get(token) {
+            Some(lock) if lock.covers(path) => locks.remove(token).is_some(),
+            _ => false,
+        }
+    }
+
+    /// Whether `tokens` unlock a change of `path` (of everything under it
+    /// when `deep`): the token of every exclusive lock on it, and of one of
+    /// its shared locks, must be among them
+    pub fn can_write(&self, path: &Path, deep: bool, tokens: &[String]) -> bool {
+        let locks = self.active_locks();
+        let (shared, exclusive): (Vec<_>, Vec<_>) = locks
+            .values()
+            .filter(|lock| lock.applies_to(path, deep))
+            .partition(|lock| lock.scope == LockScope::Shared);
+        exclusive.iter().all(|lock| tokens.contains(&lock.token))
+            && (shared.is_empty() || shared.iter().any(|lock| tokens.contains(&lock.token)))
+    }
+
+    /// Drop the locks on `path` and under it, once it is gone
+    pub fn forget(&self, path: &Path) {
+        self.active_locks()
+            .retain(|_, lock| !lock.path.starts_with(path));
+    }
+
+    fn active_locks(&self) -> std::sync::MutexGuard<'_, HashMap<String, ActiveLock>> {
+        let mut locks = self.locks.lock().unwrap();
+        let now = Instant::now();
+        locks.retain(|_, lock| lock.expires > now);
+        locks
+    }
+}
+
+/// Lock tokens submitted in an `If:` header, e.g.
+/// `(<opaquelocktoken:...>)` or `</file> (<opaquelocktoken:...> ["etag"])`
+pub fn parse_if_tokens(value: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut in_list = false;
+    let mut rest = value;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '(' => in_list = true,
+            ')' => in_list = false,
+            '<' => {
+                let end = rest.find('>').unwrap_or(rest.len());
+                if in_list {
+                    tokens.push(rest[1..end].to_string());
+                }
+                rest = &rest[end..];
+                continue;
+            }
+            '[' => {
+                rest = &rest[rest.find(']').unwrap_or(rest.len())..];
+                continue;
+            }
+            _ => {}
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    tokens
+}
+
+/// Parse a `Timeout:` header such as `Second-3600` or `Infinite, Second-60`
+pub fn parse_timeout(value: &str) -> Duration {
+    value
+        .split(',')
+        .find_map(|v| match v.trim() {
+            "Infinite" => Some(MAX_LOCK_TIMEOUT),
+            v => v
+                .strip_prefix("Second-")
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs),
+        })
+        .unwrap_or(DEFAULT_LOCK_TIMEOUT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_manager() {
+        let locks = LockManager::default();
+        let dir = Path::new("/srv/dir");
+        let file = Path::new("/srv/dir/file");
+        let timeout = Duration::from_secs(60);
+
+        let shared = locks
+            .lock(file, "/dir/file", LockScope::Shared, false, None, timeout)
+            .unwrap();
+        assert!(locks
+            .lock(file, "/dir/file", LockScope::Shared, false, None, timeout)
+            .is_some());
+        assert!(locks
+            .lock(dir, "/dir/", LockScope::Exclusive, true, None, timeout)
+            .is_none());
+        assert!(locks
+            .lock(dir, "/dir/", LockScope::Exclusive, false, None, timeout)
+            .is_some());
+
+        assert!(locks.can_write(file, false, std::slice::from_ref(&shared.token)));
+        assert!(!locks.can_write(file, false, &[]));
+        assert!(locks.can_write(Path::new("/srv/other"), true, &[]));
+        assert!(!locks.can_write(Path::new("/srv"), true, &[]));
+
+        assert!(!locks.unlock(dir, &shared.token));
+        assert!(locks.unlock(file, &shared.token));
+        locks.forget(dir);
+        assert!(locks.can_write(Path::new("/srv"), true, &[]));
+    }
+
+    #[test]
+    fn test_parse_if_header() {
+        assert_eq!(
+            parse_if_tokens(r#"</dir/file> (<opaquelocktoken:a> ["etag"]) (Not <DAV:no-lock>)"#),
+            ["opaquelocktoken:a", "DAV:no-lock"]
+        );
+        assert_eq!(parse_timeout("Second-60"), Duration::from_secs(60));
+        assert_eq!(parse_timeout("Infinite, Second-60"), MAX_LOCK_TIMEOUT);
+        assert_eq!(parse_timeout("Second-x"), DEFAULT_LOCK_TIMEOUT);
+    }
+}
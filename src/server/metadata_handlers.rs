@@ -0,0 +1,283 @@
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Limited};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::provenance::{FileComment, ProvenanceDb};
+
+use super::provenance_handlers::Request;
+use super::response_utils::{
+    set_json_response, status_bad_request, status_no_content, status_not_found, Response,
+};
+
+const MAX_METADATA_KEY_LEN: usize = 128;
+const MAX_METADATA_VALUE_LEN: usize = 4096;
+const MAX_COMMENT_LEN: usize = 16 * 1024;
+const MAX_TAG_LEN: usize = 64;
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Serialize)]
+struct Annotations {
+    metadata: BTreeMap<String, String>,
+    comments: Vec<FileComment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NewComment {
+    text: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct TagsPatch {
+    add: Vec<String>,
+    remove: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct TagCount {
+    name: String,
+    count: u64,
+}
+
+/// Handle annotations request (GET /api/<file>?meta)
+pub async fn handle_get_annotations(
+    path: &Path,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let file_path = annotation_key(path)?;
+    write_annotations(file_path, provenance_db, res)
+}
+
+/// Handle metadata update (POST /api/<file>?meta)
+///
+/// The body is a JSON object merged into the existing metadata,
+/// a `null` value removes the key.
+pub async fn handle_update_metadata(
+    path: &Path,
+    req: Request,
+    user: Option<String>,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let file_path = annotation_key(path)?;
+    let Some(body) = read_json_body(req, res).await else {
+        return Ok(());
+    };
+    let patch: BTreeMap<String, Option<String>> = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(_) => {
+            status_bad_request(res, "Expected a JSON object with string or null values");
+            return Ok(());
+        }
+    };
+    for (key, value) in &patch {
+        if key.is_empty() || key.len() > MAX_METADATA_KEY_LEN {
+            status_bad_request(res, &format!("Invalid metadata key `{key}`"));
+            return Ok(());
+        }
+        if value.as_ref().map(|v| v.len()).unwrap_or_default() > MAX_METADATA_VALUE_LEN {
+            status_bad_request(res, &format!("Metadata value of `{key}` is too long"));
+            return Ok(());
+        }
+    }
+
+    provenance_db.update_file_metadata(file_path, &patch, user.as_deref())?;
+    write_annotations(file_path, provenance_db, res)
+}
+
+/// Handle comment creation (POST /api/<file>?comment)
+pub async fn handle_add_comment(
+    path: &Path,
+    req: Request,
+    user: Option<String>,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let file_path = annotation_key(path)?;
+    let Some(body) = read_json_body(req, res).await else {
+        return Ok(());
+    };
+    let text = match serde_json::from_slice::<NewComment>(&body) {
+        Ok(v) => v.text,
+        Err(_) => {
+            status_bad_request(res, "Expected a JSON object with a `text` field");
+            return Ok(());
+        }
+    };
+    let text = text.trim();
+    if text.is_empty() || text.len() > MAX_COMMENT_LEN {
+        status_bad_request(
+            res,
+            &format!("Comment must be between 1 and {MAX_COMMENT_LEN} bytes"),
+        );
+        return Ok(());
+    }
+
+    let comment = provenance_db.add_file_comment(file_path, user.as_deref(), text)?;
+    set_json_response(res, serde_json::to_string(&comment)?);
+    *res.status_mut() = StatusCode::CREATED;
+    Ok(())
+}
+
+/// Handle comment deletion (DELETE /api/<file>?comment=<id>)
+pub async fn handle_delete_comment(
+    path: &Path,
+    comment_id: &str,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let file_path = annotation_key(path)?;
+    let Ok(comment_id) = comment_id.parse::<i64>() else {
+        status_bad_request(res, "Invalid comment id");
+        return Ok(());
+    };
+    if provenance_db.delete_file_comment(file_path, comment_id)? {
+        status_no_content(res);
+    } else {
+        status_not_found(res);
+    }
+    Ok(())
+}
+
+/// Handle file tags request (GET /api/<file>?tags)
+pub async fn handle_get_tags(
+    path: &Path,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let file_path = annotation_key(path)?;
+    write_tags(file_path, provenance_db, res)
+}
+
+/// Handle tags update (POST /api/<file>?tags)
+///
+/// The body is `{"add": [...], "remove": [...]}`, tags are trimmed and lowercased.
+pub async fn handle_update_tags(
+    path: &Path,
+    req: Request,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let file_path = annotation_key(path)?;
+    let Some(body) = read_json_body(req, res).await else {
+        return Ok(());
+    };
+    let patch: TagsPatch = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(_) => {
+            status_bad_request(res, "Expected a JSON object with `add` and `remove` arrays");
+            return Ok(());
+        }
+    };
+    let normalize = |tags: &[String]| -> Option<Vec<String>> {
+        tags.iter().map(|v| normalize_tag(v)).collect()
+    };
+    let (Some(add), Some(remove)) = (normalize(&patch.add), normalize(&patch.remove)) else {
+        status_bad_request(
+            res,
+            &format!("Tags must be non-empty words of at most {MAX_TAG_LEN} characters"),
+        );
+        return Ok(());
+    };
+
+    provenance_db.update_file_tags(file_path, &add, &remove)?;
+    write_tags(file_path, provenance_db, res)
+}
+
+/// Handle tag index request (GET /api/<dir>/?tags), counting tagged files below the directory
+pub async fn handle_list_tags(
+    path: &Path,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let prefix = dir_prefix(path)?;
+    let tags: Vec<TagCount> = provenance_db
+        .list_tags(&prefix)?
+        .into_iter()
+        .map(|(name, count)| TagCount { name, count })
+        .collect();
+    set_json_response(res, serde_json::to_string_pretty(&json!({ "tags": tags }))?);
+    Ok(())
+}
+
+/// Handle star/unstar (POST or DELETE /api/<path>?star)
+pub async fn handle_star(
+    path: &Path,
+    starred: bool,
+    owner: &str,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let file_path = annotation_key(path)?;
+    if starred {
+        provenance_db.add_favorite(owner, file_path)?;
+    } else if !provenance_db.remove_favorite(owner, file_path)? {
+        status_not_found(res);
+        return Ok(());
+    }
+    set_json_response(res, json!({ "starred": starred }).to_string());
+    Ok(())
+}
+
+/// Prefix matching every annotated path below the directory `path`
+pub fn dir_prefix(path: &Path) -> Result<String> {
+    let dir_path = annotation_key(path)?;
+    Ok(format!(
+        "{}{}",
+        dir_path.trim_end_matches(std::path::MAIN_SEPARATOR),
+        std::path::MAIN_SEPARATOR
+    ))
+}
+
+/// Tags are case-insensitive single words, e.g. `legal` or `q3-report`
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_lowercase();
+    let valid = !tag.is_empty()
+        && tag.len() <= MAX_TAG_LEN
+        && !tag
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == ',');
+    valid.then_some(tag)
+}
+
+fn write_tags(file_path: &str, provenance_db: &ProvenanceDb, res: &mut Response) -> Result<()> {
+    let tags = provenance_db.get_file_tags(file_path)?;
+    set_json_response(res, serde_json::to_string_pretty(&json!({ "tags": tags }))?);
+    Ok(())
+}
+
+fn write_annotations(
+    file_path: &str,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let annotations = Annotations {
+        metadata: provenance_db.get_file_metadata(file_path)?,
+        comments: provenance_db.get_file_comments(file_path)?,
+    };
+    set_json_response(res, serde_json::to_string_pretty(&annotations)?);
+    Ok(())
+}
+
+/// Annotations are keyed by absolute path, like artifacts
+fn annotation_key(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| anyhow!("Invalid UTF-8 in file path: {:?}", path))
+}
+
+/// Read a small JSON body, responding with 413 when it exceeds `MAX_BODY_SIZE`
+async fn read_json_body(req: Request, res: &mut Response) -> Option<Bytes> {
+    match Limited::new(req.into_body(), MAX_BODY_SIZE).collect().await {
+        Ok(v) => Some(v.to_bytes()),
+        Err(_) => {
+            *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+            None
+        }
+    }
+}
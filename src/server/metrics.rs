@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::router::Route;
+
+/// Requests answered since the server started, by route, operation and
+/// status, shared by the mounts, tenants and reloads of a server
+#[derive(Debug, Clone, Default)]
+pub(super) struct RequestMetrics(Arc<Mutex<BTreeMap<Labels, Totals>>>);
+
+type Labels = (&'static str, &'static str, u16);
+
+/// Name, help text and value of each series in the output
+type Series = (&'static str, &'static str, fn(&Totals) -> String);
+
+const SERIES: [Series; 2] = [
+    (
+        "node_drive_requests_total",
+        "Requests answered, by route, operation and status",
+        |v| v.requests.to_string(),
+    ),
+    (
+        "node_drive_request_duration_seconds_sum",
+        "Seconds spent answering requests until their headers were sent",
+        |v| v.seconds.to_string(),
+    ),
+];
+
+#[derive(Debug, Default)]
+struct Totals {
+    requests: u64,
+    seconds: f64,
+}
+
+impl RequestMetrics {
+    pub fn record(&self, route: Route, status: u16, latency: Duration) {
+        let operation = route.operation().map(|v| v.as_str()).unwrap_or_default();
+        let mut totals = self.0.lock().unwrap();
+        let totals = totals
+            .entry((route.as_str(), operation, status))
+            .or_default();
+        totals.requests += 1;
+        totals.seconds += latency.as_secs_f64();
+    }
+
+    /// The totals in the Prometheus text format
+    pub fn render(&self) -> String {
+        let totals = self.0.lock().unwrap();
+        let mut output = String::new();
+        for (name, help, value) in SERIES {
+            let _ = writeln!(output, "# HELP {name} {help}\n# TYPE {name} counter");
+            for ((route, operation, status), v) in totals.iter() {
+                let _ = writeln!(
+                    output,
+                    r#"{name}{{route="{route}",operation="{operation}",status="{status}"}} {}"#,
+                    value(v)
+                );
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::router::Operation;
+
+    #[test]
+    fn test_render_request_metrics() {
+        let metrics = RequestMetrics::default();
+        let upload = Route::Api(Operation::Upload);
+        metrics.record(upload, 201, Duration::from_millis(500));
+        metrics.record(upload, 201, Duration::from_millis(250));
+        metrics.record(Route::Internal, 404, Duration::ZERO);
+        let output = metrics.render();
+        assert!(output.contains(
+            r#"node_drive_requests_total{route="api",operation="upload",status="201"} 2"#
+        ));
+        assert!(output.contains(
+            r#"node_drive_request_duration_seconds_sum{route="api",operation="upload",status="201"} 0.75"#
+        ));
+        assert!(output.contains(
+            r#"node_drive_requests_total{route="internal",operation="",status="404"} 1"#
+        ));
+    }
+}
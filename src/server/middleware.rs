@@ -0,0 +1,342 @@
+use headers::{ContentLength, HeaderMapExt};
+use hyper::StatusCode;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::error_reporter::ErrorContext;
+use crate::events::ProvenanceAction;
+use crate::utils::decode_uri;
+
+use super::api_error::set_json_error;
+use super::cors::{add_cors, CorsRequest};
+use super::file_handlers::FileAccess;
+use super::handlers::{is_upload, Request, Server};
+use super::rate_limit::Client;
+use super::response_utils::{
+    accepts_html, status_forbid, status_no_content, status_too_many_requests, Response,
+};
+use super::router::Route;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A layer around the route dispatch, which answers the request itself or
+/// passes it on with `next.run(req)` and works on the response it gets back
+pub trait Middleware: Send + Sync {
+    fn call<'a>(&'a self, req: Request, next: Next<'a>) -> BoxFuture<'a, Response>;
+}
+
+/// The layers after the current one, ending in `Server::handle`
+#[derive(Clone, Copy)]
+pub struct Next<'a> {
+    server: &'a Arc<Server>,
+    route: Route,
+    layers: &'a [Arc<dyn Middleware>],
+}
+
+impl<'a> Next<'a> {
+    pub(super) fn new(server: &'a Arc<Server>, route: Route) -> Self {
+        Self {
+            server,
+            route,
+            layers: &server.layers,
+        }
+    }
+
+    pub fn server(&self) -> &'a Server {
+        self.server
+    }
+
+    pub fn route(&self) -> Route {
+        self.route
+    }
+
+    pub async fn run(self, req: Request) -> Response {
+        let Some((layer, layers)) = self.layers.split_first() else {
+            return match self.server.handle(self.route, req).await {
+                Ok(res) => res,
+                Err(err) => HandlerError::response(err),
+            };
+        };
+        layer.call(req, Self { layers, ..self }).await
+    }
+}
+
+/// The error a handler failed with, on the 500 response answering it
+#[derive(Debug, Clone)]
+pub struct HandlerError(pub Arc<anyhow::Error>);
+
+impl HandlerError {
+    fn response(err: anyhow::Error) -> Response {
+        let mut res = Response::default();
+        *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        res.extensions_mut().insert(HandlerError(Arc::new(err)));
+        res
+    }
+}
+
+/// The layers of every server, outermost first
+pub(super) fn builtin_layers() -> Vec<Arc<dyn Middleware>> {
+    vec![
+        Arc::new(AccessLog),
+        Arc::new(Metrics),
+        Arc::new(IpFilter),
+        Arc::new(RateLimit::PerIp),
+        Arc::new(Cors),
+        Arc::new(ErrorBodies),
+        Arc::new(ExecLogin),
+        Arc::new(Mounts),
+        Arc::new(Auth),
+        Arc::new(RateLimit::PerUser),
+    ]
+}
+
+/// `--log-format` entries and `--error-reporter` reports
+struct AccessLog;
+
+impl Middleware for AccessLog {
+    fn call<'a>(&'a self, req: Request, next: Next<'a>) -> BoxFuture<'a, Response> {
+        Box::pin(async move {
+            let args = &next.server().args;
+            let route = next.route();
+            let started = Instant::now();
+            let addr = req.extensions().get::<SocketAddr>().copied();
+            let mut http_log_data = args.http_logger.data(&req);
+            let error_ctx = args
+                .error_reporter
+                .as_ref()
+                .map(|_| ErrorContext::new(req.method(), req.uri(), req.headers(), addr));
+            if let Some(addr) = addr {
+                http_log_data.insert("remote_addr".to_string(), addr.ip().to_string());
+            }
+            http_log_data.insert("route".to_string(), route.as_str().to_string());
+            if let Some(op) = route.operation() {
+                http_log_data.insert("operation".to_string(), op.as_str().to_string());
+            }
+            if let Some(size) = req.headers().typed_get::<ContentLength>() {
+                http_log_data.insert("bytes_received".to_string(), size.0.to_string());
+            }
+
+            let res = next.run(req).await;
+            http_log_data.insert("status".to_string(), res.status().as_u16().to_string());
+            http_log_data.insert(
+                "latency_ms".to_string(),
+                started.elapsed().as_millis().to_string(),
+            );
+            if let Some(HandlerError(err)) = res.extensions().get::<HandlerError>() {
+                args.http_logger.log(&http_log_data, Some(err.to_string()));
+                if let (Some(reporter), Some(ctx)) = (&args.error_reporter, error_ctx) {
+                    reporter.report(err, ctx);
+                }
+                return res;
+            }
+            if let Some(size) = res.headers().typed_get::<ContentLength>() {
+                http_log_data.insert("bytes_sent".to_string(), size.0.to_string());
+            }
+            if let Some(action) = res.extensions().get::<ProvenanceAction>() {
+                http_log_data.insert("provenance_action".to_string(), action.0.clone());
+            }
+            // Public asset requests are served from the SPA and are
+            // noisy, so avoid logging them here.
+            if args.http_logger.logs(route) {
+                args.http_logger.log(&http_log_data, None);
+            }
+            res
+        })
+    }
+}
+
+/// Counts of the requests answered, served at `__dufs__/metrics`
+struct Metrics;
+
+impl Middleware for Metrics {
+    fn call<'a>(&'a self, req: Request, next: Next<'a>) -> BoxFuture<'a, Response> {
+        Box::pin(async move {
+            let started = Instant::now();
+            let res = next.run(req).await;
+            next.server().request_metrics.record(
+                next.route(),
+                res.status().as_u16(),
+                started.elapsed(),
+            );
+            res
+        })
+    }
+}
+
+/// `--allow-ip` and `--deny-ip`
+struct IpFilter;
+
+impl Middleware for IpFilter {
+    fn call<'a>(&'a self, req: Request, next: Next<'a>) -> BoxFuture<'a, Response> {
+        Box::pin(async move {
+            let addr = req.extensions().get::<SocketAddr>();
+            if addr.is_some_and(|v| !next.server().args.ip_allowed(v.ip())) {
+                let mut res = Response::default();
+                status_forbid(&mut res);
+                set_json_error(next.route(), &mut res).await;
+                return res;
+            }
+            next.run(req).await
+        })
+    }
+}
+
+/// `--rate-limit`, with the upload slot held until the upload is written.
+/// Every request counts for its address, and file requests of signed-in
+/// users for the user as well.
+enum RateLimit {
+    PerIp,
+    PerUser,
+}
+
+impl RateLimit {
+    fn client<'a>(&self, req: &'a Request) -> Option<Client<'a>> {
+        match self {
+            RateLimit::PerIp => req
+                .extensions()
+                .get::<SocketAddr>()
+                .map(|v| Client::Ip(v.ip())),
+            RateLimit::PerUser => match req.extensions().get::<FileAccess>() {
+                Some(FileAccess::Granted {
+                    user: Some(user), ..
+                }) => Some(Client::User(user)),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl Middleware for RateLimit {
+    fn call<'a>(&'a self, req: Request, next: Next<'a>) -> BoxFuture<'a, Response> {
+        Box::pin(async move {
+            let (Some(limiter), Some(client)) = (&next.server().rate_limiter, self.client(&req))
+            else {
+                return next.run(req).await;
+            };
+            let upload_permit = match limiter.admit(client, is_upload(&req)) {
+                Ok(permit) => permit,
+                Err(limited) => {
+                    let mut res = Response::default();
+                    status_too_many_requests(&mut res, limited.retry_after);
+                    set_json_error(next.route(), &mut res).await;
+                    return res;
+                }
+            };
+            let res = next.run(req).await;
+            drop(upload_permit);
+            res
+        })
+    }
+}
+
+/// `--enable-cors`
+struct Cors;
+
+impl Middleware for Cors {
+    fn call<'a>(&'a self, req: Request, next: Next<'a>) -> BoxFuture<'a, Response> {
+        Box::pin(async move {
+            let args = &next.server().args;
+            if !args.enable_cors {
+                return next.run(req).await;
+            }
+            let cors = CorsRequest::new(&req);
+            // Preflights carry no credentials, so they are answered before auth
+            let mut res = match cors.is_preflight() {
+                true => {
+                    let mut res = Response::default();
+                    status_no_content(&mut res);
+                    res
+                }
+                false => next.run(req).await,
+            };
+            add_cors(&mut res, args, &cors);
+            res
+        })
+    }
+}
+
+/// `ApiError` bodies for the API and `--error-pages` for browsers
+struct ErrorBodies;
+
+impl Middleware for ErrorBodies {
+    fn call<'a>(&'a self, req: Request, next: Next<'a>) -> BoxFuture<'a, Response> {
+        Box::pin(async move {
+            let uri = req.uri().clone();
+            let accepts_html = accepts_html(req.headers());
+            let mut res = next.run(req).await;
+            set_json_error(next.route(), &mut res).await;
+            if accepts_html {
+                let path = decode_uri(uri.path()).unwrap_or_else(|| uri.path().into());
+                next.server().error_pages.render(&mut res, &path);
+            }
+            res
+        })
+    }
+}
+
+/// `--auth-exec` logins
+struct ExecLogin;
+
+impl Middleware for ExecLogin {
+    fn call<'a>(&'a self, mut req: Request, next: Next<'a>) -> BoxFuture<'a, Response> {
+        Box::pin(async move {
+            next.server().check_exec_login(&mut req).await;
+            next.run(req).await
+        })
+    }
+}
+
+/// Mounts and `--multi-tenant` folders, answered by a server of their own
+/// from here on
+struct Mounts;
+
+impl Middleware for Mounts {
+    fn call<'a>(&'a self, mut req: Request, next: Next<'a>) -> BoxFuture<'a, Response> {
+        Box::pin(async move {
+            let server = next.server();
+            let target = match server.route_mount(&mut req) {
+                Ok(None) => server.route_tenant(&req),
+                target => target,
+            };
+            match target {
+                Ok(Some(target)) => {
+                    let route = Route::of(&req, &target.args.uri_prefix);
+                    let next = Next {
+                        server: &target,
+                        route,
+                        layers: next.layers,
+                    };
+                    next.run(req).await
+                }
+                Ok(None) => next.run(req).await,
+                Err(err) => HandlerError::response(err),
+            }
+        })
+    }
+}
+
+/// Who may access the path of a file request, checked before any handler
+/// sees it and handed on as a `FileAccess`
+struct Auth;
+
+impl Middleware for Auth {
+    fn call<'a>(&'a self, mut req: Request, next: Next<'a>) -> BoxFuture<'a, Response> {
+        Box::pin(async move {
+            let Some(op) = next.route().operation() else {
+                return next.run(req).await;
+            };
+            let mut res = Response::default();
+            match next.server().authorize_file_request(op, &req, &mut res) {
+                Ok(Some(access)) => {
+                    req.extensions_mut().insert(access);
+                    next.run(req).await
+                }
+                Ok(None) => res,
+                Err(err) => HandlerError::response(err),
+            }
+        })
+    }
+}
@@ -1,13 +1,61 @@
+mod acl_handlers;
+mod api_error;
 mod api_handlers;
+mod archive_handlers;
+mod auth_exec;
+mod auth_log;
+mod builder;
+mod bundle_handlers;
+mod content_search;
+mod copy_handlers;
+mod cors;
+mod delta_handlers;
+mod dir_cache;
+mod drop_handlers;
+mod encryption;
+mod event_handlers;
+mod file_handlers;
 mod handlers;
+mod locks;
+mod metadata_handlers;
+mod metrics;
+mod middleware;
+mod mounts;
+mod oidc_handlers;
+mod openapi;
+mod parts_handlers;
 mod path_item;
+mod preview_handlers;
+mod provenance_dav;
 mod provenance_handlers;
+mod quota_handlers;
+mod range_streams;
+mod rate_limit;
+mod reload;
 mod response_utils;
+mod router;
+mod session_handlers;
+mod share_handlers;
+mod shutdown;
+mod stamp_cache;
+mod stats_handlers;
+mod tenants;
+mod token_handlers;
+mod trash_handlers;
+mod tree_handlers;
+mod unpack_handlers;
+mod upload_handlers;
+mod user_key_handlers;
+mod visibility_handlers;
 mod webdav;
+mod write_locks;
 
 // Re-export public types and functions
+pub use builder::{NodeDrive, ServerBuilder};
 pub use handlers::{Request, Server};
+pub use middleware::{BoxFuture, Middleware, Next};
 pub use response_utils::*;
+pub use router::Route;
 
 // Re-export helper functions for internal use
 pub(crate) use handlers::zip_dir;
@@ -0,0 +1,177 @@
+use anyhow::Result;
+use hyper::{
+    header::{HeaderMap, HeaderValue},
+    Method, Uri,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::auth::AccessPaths;
+use crate::search_index::SearchIndex;
+use crate::utils::decode_uri;
+
+use super::dir_cache::DirCache;
+use super::handlers::{propfind_depth, Request, Server, DEPTH_INFINITY};
+use super::path_item::{PathItem, PathType};
+use super::response_utils::{
+    res_multistatus, set_webdav_headers, status_bad_request, status_forbid, status_not_found,
+    Response,
+};
+use super::webdav;
+
+impl Server {
+    /// A server for the mount `name`, serving `root` under `/<name>/` and
+    /// sharing the provenance database with the other mounts, whose records
+    /// are kept apart by their paths
+    pub(super) fn for_mount(&self, name: &str, root: &Path) -> Result<Self> {
+        let args = self.args.for_mount(name, root);
+        if !args.retention.is_empty() {
+            args.retention.clone().spawn_sweeper(
+                root.to_path_buf(),
+                self.trash.clone(),
+                self.provenance_db.clone(),
+            );
+        }
+        let search_index = match args.index {
+            true => Some(SearchIndex::start(self.provenance_db.clone(), root)?),
+            false => None,
+        };
+        let dir_cache = match args.dir_cache {
+            true => Some(DirCache::start(root)?),
+            false => None,
+        };
+        Ok(Self {
+            args: Arc::new(args),
+            html: self.html.clone(),
+            single_file_req_paths: vec![],
+            running: self.running.clone(),
+            provenance_db: self.provenance_db.clone(),
+            keypair: self.keypair.clone(),
+            trash: self.trash.clone(),
+            events: self.events.clone(),
+            ots: self.ots.clone(),
+            range_streams: self.range_streams.clone(),
+            locks: self.locks.clone(),
+            write_locks: self.write_locks.clone(),
+            uploads: self.uploads.clone(),
+            partial_uploads: self.partial_uploads.clone(),
+            logins: self.logins.clone(),
+            exec_logins: self.exec_logins.clone(),
+            stamp_summaries: self.stamp_summaries.clone(),
+            stamp_statuses: self.stamp_statuses.clone(),
+            search_index,
+            dir_cache,
+            rate_limiter: self.rate_limiter.clone(),
+            request_metrics: self.request_metrics.clone(),
+            drop_box: self.drop_box.clone(),
+            oidc: self.oidc.clone(),
+            sessions: self.sessions.clone(),
+            tenants: None,
+            error_pages: self.error_pages.clone(),
+            tenant_root: None,
+            mounts: BTreeMap::new(),
+            mount_name: Some(name.to_string()),
+            layers: self.layers.clone(),
+        })
+    }
+
+    /// The mount server that should handle `req`, None for the root server.
+    /// API requests (`/api/<name>/...`) are rewritten to the mount's own
+    /// prefix (`/<name>/api/...`).
+    pub(super) fn route_mount(&self, req: &mut Request) -> Result<Option<Arc<Server>>> {
+        if self.mounts.is_empty() {
+            return Ok(None);
+        }
+        let uri_path = req.uri().path();
+        let Some(rest) = uri_path.strip_prefix(self.args.uri_prefix.as_str()) else {
+            return Ok(None);
+        };
+        let (is_api, rest) = match rest.strip_prefix("api/") {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let (name, rest) = rest.split_once('/').unwrap_or((rest, ""));
+        let Some(server) = decode_uri(name).and_then(|v| self.mounts.get(v.as_ref())) else {
+            return Ok(None);
+        };
+        if is_api {
+            let query = req
+                .uri()
+                .query()
+                .map(|v| format!("?{v}"))
+                .unwrap_or_default();
+            let uri: Uri = format!("{}api/{rest}{query}", server.args.uri_prefix).parse()?;
+            *req.uri_mut() = uri;
+        }
+        Ok(Some(server.clone()))
+    }
+
+    /// The root of a server with mounts, listing the mounts the user can
+    /// enter; there is nothing else to serve outside of them
+    #[allow(clippy::too_many_arguments)]
+    pub(super) async fn handle_mounts_index(
+        &self,
+        relative_path: &str,
+        method: &Method,
+        headers: &HeaderMap<HeaderValue>,
+        query_params: &HashMap<String, String>,
+        user: Option<String>,
+        access_paths: AccessPaths,
+        res: &mut Response,
+    ) -> Result<()> {
+        if !relative_path.is_empty() {
+            status_not_found(res);
+            return Ok(());
+        }
+        let mut paths = vec![];
+        for (name, server) in &self.mounts {
+            if access_paths.find(name).is_none() {
+                continue;
+            }
+            let root = server.args.serve_path.as_path();
+            if let Some(mut item) = server.to_pathitem(root, root).await? {
+                item.name.clone_from(name);
+                paths.push(item);
+            }
+        }
+
+        match method.as_str() {
+            "GET" | "HEAD" => {
+                self.sort_paths(&mut paths, query_params);
+                self.send_index(
+                    &self.args.serve_path,
+                    true,
+                    paths,
+                    query_params,
+                    method == Method::HEAD,
+                    user,
+                    self.locale(headers),
+                    &access_paths,
+                    res,
+                )
+                .await?;
+            }
+            "PROPFIND" => match propfind_depth(headers) {
+                None => {
+                    status_bad_request(res, "Invalid depth: only 0, 1 and infinity are allowed.")
+                }
+                Some(DEPTH_INFINITY) => webdav::status_propfind_finite_depth(res),
+                Some(depth) => {
+                    let root = PathItem::new(PathType::Dir, String::new(), 0, paths.len() as u64);
+                    let uri_prefix = self.args.uri_prefix.as_str();
+                    let mut output = root.to_dav_xml(uri_prefix, &[]);
+                    if depth == 1 {
+                        for item in &paths {
+                            output.push_str(&item.to_dav_xml(uri_prefix, &[]));
+                        }
+                    }
+                    res_multistatus(res, &output);
+                }
+            },
+            "OPTIONS" => set_webdav_headers(res),
+            _ => status_forbid(res),
+        }
+        Ok(())
+    }
+}
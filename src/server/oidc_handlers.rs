@@ -0,0 +1,260 @@
+use anyhow::Result;
+use hyper::{
+    header::{HeaderValue, AUTHORIZATION, COOKIE, HOST, LOCATION, SET_COOKIE},
+    HeaderMap, Method, StatusCode, Uri,
+};
+use std::collections::HashMap;
+
+use crate::auth::AccessPaths;
+use crate::oidc::LOGIN_TIMEOUT;
+use crate::sessions::SESSION_EXPIRATION;
+
+use super::auth_exec::ExecLogin;
+use super::handlers::{Request, Server};
+use super::response_utils::{status_bad_request, status_forbid, Response};
+use super::token_handlers::api_token_credential;
+
+pub(super) const OIDC_LOGIN_PATH: &str = "__dufs__/oidc/login";
+pub(super) const OIDC_CALLBACK_PATH: &str = "__dufs__/oidc/callback";
+pub(super) const OIDC_LOGOUT_PATH: &str = "__dufs__/oidc/logout";
+
+/// Binds a login to the browser that started it, see `Oidc::complete_login`
+const OIDC_STATE_COOKIE: &str = "dufs_oidc_state";
+
+impl Server {
+    /// Like `AccessControl::guard_as`, also accepting API tokens and a
+    /// session cookie when the request has no `Authorization` header.
+    /// Password logins go to the auth log, and are refused without being
+    /// checked while their user is locked out of the client address.
+    pub(super) fn guard_as(
+        &self,
+        path: &str,
+        perm_method: &Method,
+        req: &Request,
+        token: Option<&String>,
+        guard_options: bool,
+    ) -> (Option<String>, Option<AccessPaths>) {
+        let method = req.method();
+        let headers = req.headers();
+        let authorization = headers.get(AUTHORIZATION);
+        if let Some(token) = api_token_credential(authorization, method, token) {
+            return self.guard_api_token(token, path, perm_method);
+        }
+        if authorization.is_none() {
+            if let Some(user) = self.session_user(headers) {
+                return self.args.auth.guard_session(&user, path, perm_method);
+            }
+        }
+        let attempt = self.login_attempt(req);
+        if let Some((ip, user)) = &attempt {
+            if self.logins.retry_after(*ip, user).is_some() {
+                return (None, None);
+            }
+        }
+        let guard = match req.extensions().get::<ExecLogin>() {
+            Some(login) => self.guard_exec_login(login, path, method, perm_method),
+            None => self.args.auth.guard_as(
+                path,
+                method,
+                perm_method,
+                authorization,
+                token,
+                guard_options,
+            ),
+        };
+        if let Some((ip, user)) = attempt {
+            self.audit_login(ip, &user, path, guard.0.is_some());
+        }
+        guard
+    }
+
+    /// Handle the OIDC routes: `login?redirect=<path>` sends the browser to
+    /// the provider, which returns to `callback` to start a session, and
+    /// `logout` ends it
+    pub(super) async fn handle_oidc(
+        &self,
+        req_path: &str,
+        req: &Request,
+        res: &mut Response,
+    ) -> Result<bool> {
+        let Some(oidc) = &self.oidc else {
+            return Ok(false);
+        };
+        if !matches!(
+            req_path,
+            OIDC_LOGIN_PATH | OIDC_CALLBACK_PATH | OIDC_LOGOUT_PATH
+        ) {
+            return Ok(false);
+        }
+        if req.method() != Method::GET {
+            *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            return Ok(true);
+        }
+        let query = req.uri().query().unwrap_or_default();
+        let query_params: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        match req_path {
+            OIDC_LOGIN_PATH => {
+                // Only local paths, so the login can't be used to redirect elsewhere
+                let return_to = query_params
+                    .get("redirect")
+                    .filter(|v| is_local_path(v))
+                    .cloned()
+                    .unwrap_or_else(|| self.args.uri_prefix.clone());
+                let Some(redirect_uri) = self.oidc_redirect_uri(req.headers()) else {
+                    status_bad_request(res, "Missing Host header");
+                    return Ok(true);
+                };
+                let (url, binding) = oidc.login_url(&redirect_uri, &return_to).await?;
+                let cookie =
+                    self.oidc_state_cookie(req.headers(), &binding, LOGIN_TIMEOUT.as_secs());
+                res.headers_mut().insert(SET_COOKIE, cookie.parse()?);
+                redirect(res, &url)?;
+            }
+            OIDC_CALLBACK_PATH => {
+                let cookie = self.oidc_state_cookie(req.headers(), "", 0);
+                res.headers_mut().insert(SET_COOKIE, cookie.parse()?);
+                let (Some(code), Some(state)) =
+                    (query_params.get("code"), query_params.get("state"))
+                else {
+                    let error = query_params.get("error").map_or("Invalid login", |v| v);
+                    status_bad_request(res, error);
+                    return Ok(true);
+                };
+                let binding = oidc_state_binding(req.headers());
+                let login = oidc.complete_login(code, state, binding.as_deref()).await;
+                let (claims, return_to) = match login {
+                    Ok(v) => v,
+                    Err(err) => {
+                        warn!("OIDC login failed, {err}");
+                        status_bad_request(res, &format!("Login failed, {err}"));
+                        return Ok(true);
+                    }
+                };
+                let Some(user) = oidc.user_from_claims(&claims, &self.args.auth) else {
+                    info!(
+                        "OIDC login of `{}` has no matching --auth user",
+                        claims["sub"].as_str().unwrap_or_default()
+                    );
+                    status_forbid(res);
+                    return Ok(true);
+                };
+                info!("OIDC login of {user}");
+                let cookie = self.session_cookie(
+                    req.headers(),
+                    &self.sessions.value(&user),
+                    SESSION_EXPIRATION,
+                );
+                res.headers_mut().append(SET_COOKIE, cookie.parse()?);
+                redirect(res, &return_to)?;
+            }
+            _ => {
+                self.clear_session(req.headers(), res)?;
+                redirect(res, &self.args.uri_prefix)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Send browsers that aren't signed in to the OIDC login, returning
+    /// whether the request was redirected
+    pub(super) fn oidc_login_redirect(&self, req: &Request, res: &mut Response) -> Result<bool> {
+        let accepts_html = req
+            .headers()
+            .get(hyper::header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("text/html"));
+        if self.oidc.is_none() || req.method() != Method::GET || !accepts_html {
+            return Ok(false);
+        }
+        let return_to = req.uri().path_and_query().map_or("/", |v| v.as_str());
+        let query: String = form_urlencoded::Serializer::new(String::new())
+            .append_pair("redirect", return_to)
+            .finish();
+        redirect(
+            res,
+            &format!("{}{OIDC_LOGIN_PATH}?{query}", self.args.uri_prefix),
+        )?;
+        Ok(true)
+    }
+
+    /// The cookie keeping the state binding of a login until its callback
+    fn oidc_state_cookie(
+        &self,
+        headers: &HeaderMap<HeaderValue>,
+        value: &str,
+        max_age: u64,
+    ) -> String {
+        let secure = if self.is_https(headers) {
+            "; Secure"
+        } else {
+            ""
+        };
+        format!(
+            "{OIDC_STATE_COOKIE}={value}; Path={}{OIDC_CALLBACK_PATH}; Max-Age={max_age}; HttpOnly; SameSite=Lax{secure}",
+            self.args.uri_prefix
+        )
+    }
+
+    /// Where the provider sends the browser back to, as seen by the browser
+    fn oidc_redirect_uri(&self, headers: &HeaderMap<HeaderValue>) -> Option<String> {
+        let host = headers.get(HOST)?.to_str().ok()?;
+        let scheme = if self.is_https(headers) {
+            "https"
+        } else {
+            "http"
+        };
+        Some(format!(
+            "{scheme}://{host}{}{OIDC_CALLBACK_PATH}",
+            self.args.uri_prefix
+        ))
+    }
+}
+
+fn oidc_state_binding(headers: &HeaderMap<HeaderValue>) -> Option<String> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|v| v.trim().split_once('='))
+        .find(|(name, _)| *name == OIDC_STATE_COOKIE)
+        .map(|(_, value)| value.to_string())
+}
+
+/// Whether `value` is a path on this server and nothing else: no scheme or
+/// host, and no `//` or backslash that browsers would read as one
+fn is_local_path(value: &str) -> bool {
+    value.starts_with('/')
+        && !value.starts_with("//")
+        && !value.contains('\\')
+        && !value.chars().any(|c| c.is_control())
+        && value
+            .parse::<Uri>()
+            .is_ok_and(|v| v.scheme().is_none() && v.authority().is_none())
+}
+
+fn redirect(res: &mut Response, location: &str) -> Result<()> {
+    *res.status_mut() = StatusCode::FOUND;
+    res.headers_mut().insert(LOCATION, location.parse()?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_local_path() {
+        assert!(is_local_path("/"));
+        assert!(is_local_path("/dir1/?q=a%20b"));
+        assert!(!is_local_path("//evil.example.com"));
+        assert!(!is_local_path("/\\evil.example.com"));
+        assert!(!is_local_path("/\\/evil.example.com"));
+        assert!(!is_local_path("/%0a\nLocation: x"));
+        assert!(!is_local_path("https://evil.example.com/"));
+        assert!(!is_local_path("dir1/"));
+    }
+}
@@ -0,0 +1,235 @@
+use serde_json::{json, Map, Value};
+
+use crate::Args;
+
+use super::auth_log::AUTH_LOG_PATH;
+use super::handlers::{
+    BACKFILL_PATH, EVENTS_PATH, GC_PATH, HEALTH_CHECK_PATH, METRICS_PATH, PROVENANCE_ANCHOR_PATH,
+    PROVENANCE_DB_PATH, STATS_PATH, TRASH_PATH, USER_KEY_PATH,
+};
+use super::path_item::Brand;
+use super::router::{Operation, Route};
+use super::session_handlers::{LOGIN_PATH, LOGOUT_PATH};
+use super::share_handlers::SHARES_PATH;
+use super::token_handlers::TOKENS_PATH;
+use super::upload_handlers::UPLOADS_PATH;
+
+pub(super) const OPENAPI_PATH: &str = "__dufs__/openapi.json";
+
+/// Methods of OpenAPI operations, the others (WebDAV) go in `x-methods`
+const OPENAPI_METHODS: [&str; 7] = ["get", "head", "put", "patch", "post", "delete", "options"];
+
+/// An endpoint of a route, with the path relative to `--path-prefix`
+struct Endpoint {
+    method: &'static str,
+    path: String,
+    summary: &'static str,
+}
+
+fn endpoint(method: &'static str, path: &str, summary: &'static str) -> Endpoint {
+    Endpoint {
+        method,
+        path: path.to_string(),
+        summary,
+    }
+}
+
+/// The endpoints of each operation on files, matched exhaustively so new
+/// operations show up in the specification
+fn file_endpoints(op: Operation) -> Vec<(&'static str, &'static str)> {
+    match op {
+        Operation::Read => vec![
+            ("get", "Download a file, or list a directory as JSON"),
+            ("head", "Headers of a file or directory"),
+        ],
+        Operation::Upload => vec![("put", "Upload a file, or unpack an archive with ?unzip")],
+        Operation::Edit => vec![("patch", "Resume an upload or edit a file in place")],
+        Operation::Post => vec![(
+            "post",
+            "Archives, copies, signatures and other actions by query",
+        )],
+        Operation::Delete => vec![("delete", "Delete a file or directory")],
+        Operation::Propfind => vec![("PROPFIND", "WebDAV")],
+        Operation::Proppatch => vec![("PROPPATCH", "WebDAV")],
+        Operation::Mkcol => vec![("MKCOL", "WebDAV")],
+        Operation::Copy => vec![("COPY", "WebDAV")],
+        Operation::Move => vec![("MOVE", "WebDAV")],
+        Operation::Lock => vec![("LOCK", "WebDAV")],
+        Operation::Unlock => vec![("UNLOCK", "WebDAV")],
+        // Queries of the methods above, or not part of the API
+        Operation::Unpack
+        | Operation::Star
+        | Operation::Options
+        | Operation::CheckAuth
+        | Operation::Logout
+        | Operation::Unsupported => vec![],
+    }
+}
+
+/// The endpoints of each route: file operations in OpenAPI methods under
+/// `api/`, WebDAV ones at the paths themselves
+fn operations(route: Route) -> Vec<Endpoint> {
+    let item = |base: &str| format!("{base}/{{id}}");
+    let file_ops = |op, api| {
+        file_endpoints(op)
+            .into_iter()
+            .filter(move |(method, _)| OPENAPI_METHODS.contains(method) == api)
+            .map(move |(method, summary)| {
+                endpoint(method, if api { "api/{path}" } else { "{path}" }, summary)
+            })
+            .collect()
+    };
+    match route {
+        Route::Api(op) => file_ops(op, true),
+        Route::WebDav(op) => file_ops(op, false),
+        Route::Internal => vec![
+            endpoint("get", HEALTH_CHECK_PATH, "Health check"),
+            endpoint("get", OPENAPI_PATH, "This specification"),
+            endpoint("get", EVENTS_PATH, "Server-sent events of changes"),
+            endpoint(
+                "post",
+                LOGIN_PATH,
+                "Start a session with a user and password",
+            ),
+            endpoint("post", LOGOUT_PATH, "End the session"),
+            endpoint("get", TOKENS_PATH, "API tokens of the user"),
+            endpoint("post", TOKENS_PATH, "Create an API token"),
+            endpoint("delete", &item(TOKENS_PATH), "Revoke an API token"),
+            endpoint("get", USER_KEY_PATH, "Signing key of the user"),
+            endpoint("put", USER_KEY_PATH, "Register a signing key"),
+            endpoint("delete", USER_KEY_PATH, "Remove the signing key"),
+            endpoint("get", UPLOADS_PATH, "Uploads in flight"),
+            endpoint("get", &item(UPLOADS_PATH), "Progress of an upload"),
+            endpoint("delete", &item(UPLOADS_PATH), "Cancel an upload"),
+            endpoint("get", SHARES_PATH, "Active share links"),
+            endpoint("delete", &item(SHARES_PATH), "Revoke a share link"),
+            endpoint("get", TRASH_PATH, "Items in the recycle bin"),
+            endpoint("post", TRASH_PATH, "Restore an item with ?restore="),
+            endpoint("delete", TRASH_PATH, "Purge items from the recycle bin"),
+            endpoint("get", AUTH_LOG_PATH, "Latest password logins"),
+            endpoint("get", STATS_PATH, "Storage and provenance statistics"),
+            endpoint(
+                "get",
+                METRICS_PATH,
+                "Request counts in the Prometheus format",
+            ),
+            endpoint(
+                "get",
+                PROVENANCE_DB_PATH,
+                "Snapshot of the provenance database",
+            ),
+            endpoint(
+                "get",
+                PROVENANCE_ANCHOR_PATH,
+                "Latest anchor of the provenance log",
+            ),
+            endpoint("post", GC_PATH, "Collect provenance of deleted files"),
+            endpoint("post", BACKFILL_PATH, "Mint provenance for existing files"),
+        ],
+        Route::Share => vec![
+            endpoint("get", "share/{id}", "Share page, or a summary with ?info"),
+            endpoint("get", "share/{id}/download", "Download a shared file"),
+            endpoint("get", "share/{id}/info", "Metadata of a share"),
+            endpoint(
+                "get",
+                "share/{id}/manifest",
+                "Provenance manifest of a shared file",
+            ),
+            endpoint(
+                "get",
+                "share/{id}/chain",
+                "Distribution chain of a shared file",
+            ),
+        ],
+        // The web UI, and the signing key outside the path prefix
+        Route::Asset | Route::SigningKey => vec![],
+    }
+}
+
+/// OpenAPI 3.1 description of the routes, with the `ApiError` body every
+/// failing API and internal request answers with
+pub(super) fn spec(args: &Args) -> Value {
+    let mut paths = Map::new();
+    let mut tags = vec![];
+    for route in Route::all() {
+        let endpoints = operations(route);
+        if endpoints.is_empty() {
+            continue;
+        }
+        let tag = json!({ "name": route.as_str() });
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+        for op in endpoints {
+            let item = paths
+                .entry(format!("/{}", op.path))
+                .or_insert_with(|| json!({}));
+            if !OPENAPI_METHODS.contains(&op.method) {
+                match item.get_mut("x-methods").and_then(Value::as_array_mut) {
+                    Some(methods) => methods.push(op.method.into()),
+                    None => item["x-methods"] = json!([op.method]),
+                }
+                continue;
+            }
+            let parameters: Vec<Value> = ["path", "id"]
+                .into_iter()
+                .filter(|name| op.path.contains(&format!("{{{name}}}")))
+                .map(|name| {
+                    json!({
+                        "name": name,
+                        "in": "path",
+                        "required": true,
+                        "schema": { "type": "string" },
+                    })
+                })
+                .collect();
+            item[op.method] = json!({
+                "tags": [route.as_str()],
+                "summary": op.summary,
+                "parameters": parameters,
+                "responses": {
+                    "2XX": { "description": "Success" },
+                    "default": { "$ref": "#/components/responses/Error" },
+                },
+            });
+        }
+    }
+
+    let server_url = match args.uri_prefix.trim_end_matches('/') {
+        "" => "/",
+        prefix => prefix,
+    };
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": args.brand_name.as_deref().unwrap_or(Brand::DEFAULT_NAME),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [{ "url": server_url }],
+        "tags": tags,
+        "paths": paths,
+        "components": {
+            "schemas": {
+                "Error": {
+                    "type": "object",
+                    "required": ["code", "message", "details"],
+                    "properties": {
+                        "code": { "type": "string", "examples": ["not_found"] },
+                        "message": { "type": "string" },
+                        "details": {},
+                    },
+                },
+            },
+            "responses": {
+                "Error": {
+                    "description": "Error",
+                    "content": {
+                        "application/json": {
+                            "schema": { "$ref": "#/components/schemas/Error" },
+                        },
+                    },
+                },
+            },
+        },
+    })
+}
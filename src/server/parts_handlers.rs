@@ -0,0 +1,106 @@
+use anyhow::Result;
+use headers::HeaderMapExt;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+
+use crate::http_utils::body_full;
+use crate::utils::{get_file_name, parse_size};
+
+use super::response_utils::{
+    extract_cache_headers, set_json_response, status_bad_request, Response,
+};
+
+/// Smallest part a file is split into
+const MIN_PART_SIZE: u64 = 1 << 20;
+
+/// Most parts a manifest lists
+const MAX_PARTS: u64 = 10_000;
+
+#[derive(Debug, Serialize)]
+struct PartsManifest {
+    name: String,
+    size: u64,
+    part_size: u64,
+    sha256: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Serialize)]
+struct Part {
+    index: u64,
+    /// First byte of the part
+    start: u64,
+    /// Last byte of the part, inclusive like in a `Range` header
+    end: u64,
+    sha256: String,
+}
+
+/// Handle `GET /file?parts=<size>`: the byte ranges a file splits into and
+/// their checksums, with the ETag to send in `If-Range` when fetching them
+pub async fn handle_parts_manifest(
+    path: &Path,
+    query_params: &HashMap<String, String>,
+    head_only: bool,
+    res: &mut Response,
+) -> Result<()> {
+    let meta = fs::metadata(path).await?;
+    let size = meta.len();
+    let part_size = match query_params.get("parts").and_then(|v| parse_size(v)) {
+        Some(v) if v >= MIN_PART_SIZE && size.div_ceil(v) <= MAX_PARTS => v,
+        _ => {
+            let msg = format!(
+                "parts must be a size of at least 1M that splits the file into at most {MAX_PARTS} parts"
+            );
+            status_bad_request(res, &msg);
+            return Ok(());
+        }
+    };
+
+    let mut file = crate::chunk_store::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut parts = vec![];
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut start = 0;
+    while start < size {
+        let len = part_size.min(size - start);
+        let mut part_hasher = Sha256::new();
+        let mut remaining = len;
+        while remaining > 0 {
+            let want = remaining.min(buffer.len() as u64) as usize;
+            let read = file.read(&mut buffer[..want]).await?;
+            if read == 0 {
+                anyhow::bail!("`{}` changed while splitting it", path.display());
+            }
+            part_hasher.update(&buffer[..read]);
+            hasher.update(&buffer[..read]);
+            remaining -= read as u64;
+        }
+        parts.push(Part {
+            index: parts.len() as u64,
+            start,
+            end: start + len - 1,
+            sha256: format!("{:x}", part_hasher.finalize()),
+        });
+        start += len;
+    }
+
+    let manifest = PartsManifest {
+        name: get_file_name(path).to_string(),
+        size,
+        part_size,
+        sha256: format!("{:x}", hasher.finalize()),
+        parts,
+    };
+    set_json_response(res, serde_json::to_string(&manifest)?);
+    if let Some((etag, _)) = extract_cache_headers(&meta) {
+        res.headers_mut().typed_insert(etag);
+    }
+    if head_only {
+        *res.body_mut() = body_full("");
+    }
+    Ok(())
+}
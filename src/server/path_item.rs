@@ -1,9 +1,16 @@
 use chrono::{LocalResult, TimeZone, Utc};
 use serde::Serialize;
 use std::cmp::Ordering;
-use xml::escape::escape_str_pcdata;
+use std::collections::BTreeMap;
+use xml::escape::{escape_str_attribute, escape_str_pcdata};
 
-use crate::utils::encode_uri;
+use crate::i18n::Locale;
+use crate::provenance::{DeadProperty, Encryption};
+use crate::quota::QuotaUsage;
+use crate::utils::{encode_uri, escape_html};
+use crate::Args;
+
+use super::content_search::ContentMatch;
 
 #[derive(Debug, Serialize, Clone, Copy, Eq, PartialEq)]
 pub enum PathType {
@@ -38,6 +45,54 @@ impl PartialOrd for PathType {
     }
 }
 
+/// Coarse kind of a file's content, from the type its extension maps to
+#[derive(Debug, Serialize, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentCategory {
+    Image,
+    Video,
+    Audio,
+    Doc,
+    Other,
+}
+
+impl ContentCategory {
+    pub fn from_name(name: &str) -> Self {
+        let Some(mime) = mime_guess::from_path(name).first() else {
+            return Self::Other;
+        };
+        match (mime.type_().as_str(), mime.subtype().as_str()) {
+            ("image", _) => Self::Image,
+            ("video", _) => Self::Video,
+            ("audio", _) => Self::Audio,
+            ("text", _) => Self::Doc,
+            ("application", subtype)
+                if subtype == "pdf"
+                    || subtype == "rtf"
+                    || subtype == "msword"
+                    || subtype == "epub+zip"
+                    || subtype.starts_with("vnd.ms-")
+                    || subtype.starts_with("vnd.openxmlformats-officedocument")
+                    || subtype.starts_with("vnd.oasis.opendocument") =>
+            {
+                Self::Doc
+            }
+            _ => Self::Other,
+        }
+    }
+
+    /// The `?filter=` value, None when it names no category
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "image" => Some(Self::Image),
+            "video" => Some(Self::Video),
+            "audio" => Some(Self::Audio),
+            "doc" => Some(Self::Doc),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub struct StampStatus {
     pub success: bool,
@@ -47,6 +102,26 @@ pub struct StampStatus {
     pub error: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sha256_hex: Option<String>,
+    /// The file no longer has the content that was minted
+    pub modified_since_mint: bool,
+}
+
+/// Stamping progress of the files of a listing, from the database alone
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct StampSummary {
+    /// Files whose timestamp is confirmed on a chain
+    pub n_confirmed: usize,
+    /// Files minted but not confirmed yet
+    pub n_pending: usize,
+}
+
+/// When a file falls under a retention rule
+#[derive(Debug, Serialize, Clone)]
+pub struct RetentionStatus {
+    /// Directory the rule applies to, relative to the serve root
+    pub rule: String,
+    /// Milliseconds since the epoch at which the file will be removed
+    pub expires_at: u64,
 }
 
 #[derive(Debug, Serialize)]
@@ -55,18 +130,70 @@ pub struct PathItem {
     pub name: String,
     pub mtime: u64,
     pub size: u64,
+    /// Kind of content, for files
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub stamp_status: Option<StampStatus>,
+    pub category: Option<ContentCategory>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub visibility: Option<String>, // "private" or "public"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retention: Option<RetentionStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Matching lines, for content searches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matches: Option<Vec<ContentMatch>>,
+    /// An upload still in progress, or interrupted and waiting to be resumed
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+    /// How the uploader encrypted the file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<Encryption>,
 }
 
 impl PathItem {
+    /// An item without any provenance or annotation details
+    pub fn new(path_type: PathType, name: String, mtime: u64, size: u64) -> Self {
+        Self {
+            category: category_of(path_type, &name),
+            partial: is_partial_upload(path_type, &name),
+            path_type,
+            name,
+            mtime,
+            size,
+            visibility: None,
+            retention: None,
+            metadata: None,
+            comment_count: None,
+            tags: None,
+            matches: None,
+            encryption: None,
+        }
+    }
+
     pub fn is_dir(&self) -> bool {
-        self.path_type == PathType::Dir || self.path_type == PathType::SymlinkDir
+        self.path_type.is_dir()
     }
 
-    pub fn to_dav_xml(&self, prefix: &str) -> String {
+    pub fn to_dav_xml(&self, prefix: &str, dead_properties: &[DeadProperty]) -> String {
+        let dead_properties: String = dead_properties
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let value = escape_str_pcdata(&v.value);
+                match v.namespace.as_str() {
+                    "" => format!("\n<{0} xmlns=\"\">{value}</{0}>", v.name),
+                    namespace => format!(
+                        "\n<P{i}:{0} xmlns:P{i}=\"{1}\">{value}</P{i}:{0}>",
+                        v.name,
+                        escape_str_attribute(namespace),
+                    ),
+                }
+            })
+            .collect();
         let mtime = match Utc.timestamp_millis_opt(self.mtime as i64) {
             LocalResult::Single(v) => format!("{}", v.format("%a, %d %b %Y %H:%M:%S GMT")),
             _ => String::new(),
@@ -84,7 +211,7 @@ impl PathItem {
 <D:prop>
 <D:displayname>{displayname}</D:displayname>
 <D:getlastmodified>{mtime}</D:getlastmodified>
-<D:resourcetype><D:collection/></D:resourcetype>
+<D:resourcetype><D:collection/></D:resourcetype>{dead_properties}
 </D:prop>
 <D:status>HTTP/1.1 200 OK</D:status>
 </D:propstat>
@@ -98,7 +225,7 @@ impl PathItem {
 <D:displayname>{displayname}</D:displayname>
 <D:getcontentlength>{}</D:getcontentlength>
 <D:getlastmodified>{mtime}</D:getlastmodified>
-<D:resourcetype></D:resourcetype>
+<D:resourcetype></D:resourcetype>{dead_properties}
 </D:prop>
 <D:status>HTTP/1.1 200 OK</D:status>
 </D:propstat>
@@ -134,6 +261,30 @@ impl PathItem {
             v => v,
         }
     }
+
+    pub fn sort_by_type(&self, other: &Self) -> Ordering {
+        match self.category.cmp(&other.category) {
+            Ordering::Equal => self.sort_by_name(other),
+            v => v,
+        }
+    }
+}
+
+/// The content category of a file named `name`, None for directories
+pub fn category_of(path_type: PathType, name: &str) -> Option<ContentCategory> {
+    (!path_type.is_dir()).then(|| ContentCategory::from_name(name))
+}
+
+/// Suffix of the `.<name>.partial` files uploads are written to
+pub const PARTIAL_UPLOAD_SUFFIX: &str = ".partial";
+
+/// Whether a listed entry is the file an upload is being written to
+pub fn is_partial_upload(path_type: PathType, name: &str) -> bool {
+    let name = name.rsplit('/').next().unwrap_or(name);
+    path_type == PathType::File
+        && name.starts_with('.')
+        && name.len() > PARTIAL_UPLOAD_SUFFIX.len() + 1
+        && name.ends_with(PARTIAL_UPLOAD_SUFFIX)
 }
 
 #[derive(Debug, Serialize, PartialEq)]
@@ -155,9 +306,31 @@ pub struct IndexData {
     pub dir_exists: bool,
     pub auth: bool,
     pub user: Option<String>,
+    /// Entries before `?offset=`/`?limit=` paging
+    pub total: usize,
+    /// Stamping progress of the listed files, see `?stamp-status` for a file
+    pub stamps: StampSummary,
+    /// Quotas limiting uploads here, with their usage
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub quotas: Vec<QuotaUsage>,
+    /// The directory's README.md with `--render-readme`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub readme: Option<Readme>,
+    pub brand: Brand,
+    /// Strings of the web UI, in the language of `--lang` or the browser
+    pub locale: Locale,
     pub paths: Vec<PathItem>,
 }
 
+/// A README rendered to sanitized HTML
+#[derive(Debug, Serialize)]
+pub struct Readme {
+    pub name: String,
+    pub html: String,
+    /// Only the start of a large README was rendered
+    pub truncated: bool,
+}
+
 #[derive(Debug, Serialize)]
 pub struct EditData {
     pub href: String,
@@ -168,6 +341,48 @@ pub struct EditData {
     pub auth: bool,
     pub user: Option<String>,
     pub editable: bool,
+    pub brand: Brand,
+    pub locale: Locale,
+}
+
+/// White-labeling of the web UI from `--brand-name`, `--brand-logo` and
+/// `--theme-color`
+#[derive(Debug, Clone, Serialize)]
+pub struct Brand {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logo: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme_color: Option<String>,
+}
+
+impl Brand {
+    pub const DEFAULT_NAME: &'static str = "Node Drive";
+
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            name: args
+                .brand_name
+                .clone()
+                .unwrap_or_else(|| Self::DEFAULT_NAME.to_string()),
+            logo: args.brand_logo.clone(),
+            theme_color: args.theme_color.clone(),
+        }
+    }
+
+    /// Fill in the `__BRAND_NAME__`, `__BRAND_LOGO__` and `__THEME_COLOR__`
+    /// placeholders of an HTML page
+    pub fn apply(&self, html: &str) -> String {
+        html.replace("__BRAND_NAME__", &escape_html(&self.name))
+            .replace(
+                "__BRAND_LOGO__",
+                &escape_html(self.logo.as_deref().unwrap_or_default()),
+            )
+            .replace(
+                "__THEME_COLOR__",
+                &escape_html(self.theme_color.as_deref().unwrap_or_default()),
+            )
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -178,5 +393,30 @@ pub struct MintEventResponse {
     pub event_hash: String,
     pub issued_at: String,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub creator_pubkey_hex: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub stamp_status: Option<StampStatus>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_category() {
+        for (name, category) in [
+            ("a/photo.JPG", ContentCategory::Image),
+            ("clip.mp4", ContentCategory::Video),
+            ("song.flac", ContentCategory::Audio),
+            ("report.pdf", ContentCategory::Doc),
+            ("notes.md", ContentCategory::Doc),
+            ("sheet.xlsx", ContentCategory::Doc),
+            ("archive.zip", ContentCategory::Other),
+            ("Makefile", ContentCategory::Other),
+        ] {
+            assert_eq!(ContentCategory::from_name(name), category, "{name}");
+        }
+        assert_eq!(category_of(PathType::Dir, "photos.jpg"), None);
+        assert_eq!(ContentCategory::parse("other"), None);
+    }
+}
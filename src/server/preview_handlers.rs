@@ -0,0 +1,145 @@
+use anyhow::Result;
+use headers::{ContentLength, HeaderMapExt};
+use hyper::{
+    header::{HeaderValue, CONTENT_TYPE},
+    StatusCode,
+};
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::fs;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+use crate::http_utils::body_full;
+use crate::utils::escape_html;
+
+use super::response_utils::{get_content_type, status_bad_request, Response};
+
+const DEFAULT_PREVIEW_LINES: usize = 50;
+const MAX_PREVIEW_LINES: usize = 1000;
+const MAX_PREVIEW_BYTES: u64 = 1024 * 1024;
+const MAX_RENDER_BYTES: u64 = 512 * 1024;
+
+/// Handle text snippet request (GET /api/<file>?preview=head&lines=<n>)
+///
+/// Returns the first lines of a text file with the same content type (and
+/// charset) as a full download, `x-preview-truncated: true` is set when the
+/// file continues past the snippet.
+pub async fn handle_preview_file(
+    path: &Path,
+    query_params: &HashMap<String, String>,
+    head_only: bool,
+    res: &mut Response,
+) -> Result<()> {
+    if query_params.get("preview").map(|v| v.as_str()) != Some("head") {
+        status_bad_request(res, "preview must be `head`");
+        return Ok(());
+    }
+    let lines = match query_params.get("lines").map(|v| v.parse::<usize>()) {
+        None => DEFAULT_PREVIEW_LINES,
+        Some(Ok(v)) if (1..=MAX_PREVIEW_LINES).contains(&v) => v,
+        Some(_) => {
+            status_bad_request(
+                res,
+                &format!("lines must be between 1 and {MAX_PREVIEW_LINES}"),
+            );
+            return Ok(());
+        }
+    };
+
+    let mut reader = BufReader::new(
+        crate::chunk_store::open(path)
+            .await?
+            .take(MAX_PREVIEW_BYTES),
+    );
+    let mut output: Vec<u8> = vec![];
+    for _ in 0..lines {
+        if reader.read_until(b'\n', &mut output).await? == 0 {
+            break;
+        }
+    }
+    if !content_inspector::inspect(&output[..output.len().min(1024)]).is_text() {
+        *res.status_mut() = StatusCode::UNSUPPORTED_MEDIA_TYPE;
+        *res.body_mut() = body_full("Only text files can be previewed");
+        return Ok(());
+    }
+    let truncated = (output.len() as u64) < fs::metadata(path).await?.len();
+
+    res.headers_mut()
+        .insert(CONTENT_TYPE, get_content_type(path).await?.parse()?);
+    res.headers_mut()
+        .typed_insert(ContentLength(output.len() as u64));
+    if truncated {
+        res.headers_mut()
+            .insert("x-preview-truncated", HeaderValue::from_static("true"));
+    }
+    if !head_only {
+        *res.body_mut() = body_full(output);
+    }
+    Ok(())
+}
+
+/// Handle rendered preview request (GET /<file>?render)
+///
+/// Markdown becomes sanitized HTML and recognized source files are syntax
+/// highlighted, other text files are shown as is. Only the first
+/// `MAX_RENDER_BYTES` are rendered, `x-preview-truncated: true` is set when
+/// the file is longer.
+pub async fn handle_render_file(path: &Path, head_only: bool, res: &mut Response) -> Result<()> {
+    let mut output: Vec<u8> = vec![];
+    crate::chunk_store::open(path)
+        .await?
+        .take(MAX_RENDER_BYTES)
+        .read_to_end(&mut output)
+        .await?;
+    if !content_inspector::inspect(&output[..output.len().min(1024)]).is_text() {
+        *res.status_mut() = StatusCode::UNSUPPORTED_MEDIA_TYPE;
+        *res.body_mut() = body_full("Only text files can be rendered");
+        return Ok(());
+    }
+    let truncated = (output.len() as u64) < fs::metadata(path).await?.len();
+
+    let name = path
+        .file_name()
+        .map(|v| v.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|v| v.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let text = String::from_utf8_lossy(&output).to_string();
+    let content = tokio::task::spawn_blocking(move || match extension.as_str() {
+        "md" | "markdown" => crate::markdown::render(&text),
+        _ => crate::markdown::highlight(&text, &extension)
+            .unwrap_or_else(|| format!("<pre>{}</pre>", escape_html(&text))),
+    })
+    .await?;
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{}</title>
+</head>
+<body>
+{content}</body>
+</html>
+"#,
+        escape_html(&name)
+    );
+
+    res.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    res.headers_mut()
+        .typed_insert(ContentLength(html.len() as u64));
+    if truncated {
+        res.headers_mut()
+            .insert("x-preview-truncated", HeaderValue::from_static("true"));
+    }
+    if !head_only {
+        *res.body_mut() = body_full(html);
+    }
+    Ok(())
+}
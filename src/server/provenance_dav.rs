@@ -0,0 +1,300 @@
+use anyhow::Result;
+use headers::{ContentLength, ContentType, HeaderMapExt};
+use hyper::{
+    header::{HeaderValue, ALLOW},
+    HeaderMap, Method, StatusCode,
+};
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::auth::AccessPaths;
+use crate::http_utils::body_full;
+use crate::provenance::VerificationKind;
+
+use super::handlers::{propfind_depth, Server, DEPTH_INFINITY};
+use super::path_item::{PathItem, PathType};
+use super::provenance_handlers;
+use super::response_utils::{
+    normalize_path, res_multistatus, status_bad_request, status_not_found, to_timestamp, Response,
+};
+use super::visibility_handlers::PublicOnly;
+
+/// Name of the read-only folder synthesized at the serve root.
+pub const PROVENANCE_DIR: &str = ".provenance";
+
+const MANIFEST_SUFFIX: &str = ".manifest.json";
+const OTS_SUFFIX: &str = ".ots";
+
+/// A path inside the virtual `.provenance` folder, mirroring the real tree:
+/// `.provenance/<dir>/` lists `<file>.manifest.json` and `<file>.ots` for
+/// every file with provenance records in `<dir>`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VirtualPath {
+    Dir(String),
+    Manifest(String),
+    Ots(String),
+}
+
+impl VirtualPath {
+    /// Parse a relative request path, returns None outside the virtual folder
+    pub fn parse(relative_path: &str) -> Option<Self> {
+        let rest = match relative_path.strip_prefix(PROVENANCE_DIR)? {
+            "" => "",
+            v => v.strip_prefix('/')?,
+        };
+        if let Some(file) = rest.strip_suffix(MANIFEST_SUFFIX) {
+            Some(Self::Manifest(file.to_string()))
+        } else if let Some(file) = rest.strip_suffix(OTS_SUFFIX) {
+            Some(Self::Ots(file.to_string()))
+        } else {
+            Some(Self::Dir(rest.to_string()))
+        }
+    }
+
+    /// The real path, relative to the serve root, whose access rules apply
+    pub fn target(&self) -> &str {
+        match self {
+            Self::Dir(v) | Self::Manifest(v) | Self::Ots(v) => v,
+        }
+    }
+}
+
+impl Server {
+    #[allow(clippy::too_many_arguments)]
+    pub(super) async fn handle_provenance_dav(
+        &self,
+        virtual_path: VirtualPath,
+        method: &Method,
+        headers: &HeaderMap<HeaderValue>,
+        access_paths: AccessPaths,
+        public_only: Option<&PublicOnly>,
+        requester_ip: Option<IpAddr>,
+        user: &Option<String>,
+        res: &mut Response,
+    ) -> Result<()> {
+        let Some(path) = self.join_path(virtual_path.target()) else {
+            status_not_found(res);
+            return Ok(());
+        };
+        let head_only = method == Method::HEAD;
+        let is_file = path.is_file();
+        match (method.as_str(), &virtual_path) {
+            ("GET" | "HEAD", VirtualPath::Manifest(_)) if is_file => {
+                provenance_handlers::handle_provenance_manifest(
+                    &path,
+                    head_only,
+                    &self.provenance_db,
+                    res,
+                )
+                .await?;
+                if !head_only {
+                    let success = res.status().is_success();
+                    self.record_verification(
+                        &path,
+                        VerificationKind::Manifest,
+                        requester_ip,
+                        user,
+                        success,
+                    );
+                }
+            }
+            ("GET" | "HEAD", VirtualPath::Ots(_)) if is_file => {
+                provenance_handlers::handle_ots_download(
+                    &path,
+                    head_only,
+                    &self.provenance_db,
+                    res,
+                )
+                .await?;
+                if !head_only {
+                    let success = res.status().is_success();
+                    self.record_verification(
+                        &path,
+                        VerificationKind::Ots,
+                        requester_ip,
+                        user,
+                        success,
+                    );
+                }
+            }
+            ("GET" | "HEAD", VirtualPath::Dir(_)) if path.is_dir() => {
+                let output = self
+                    .list_provenance_dir(&path, 1, access_paths, public_only)
+                    .await?
+                    .into_iter()
+                    .skip(1)
+                    .map(|v| match v.is_dir() {
+                        true => format!("{}/\n", v.base_name()),
+                        false => format!("{}\n", v.base_name()),
+                    })
+                    .collect::<String>();
+                res.headers_mut()
+                    .typed_insert(ContentType::from(mime_guess::mime::TEXT_PLAIN_UTF_8));
+                res.headers_mut()
+                    .typed_insert(ContentLength(output.len() as u64));
+                if !head_only {
+                    *res.body_mut() = body_full(output);
+                }
+            }
+            ("PROPFIND", VirtualPath::Dir(_)) if path.is_dir() => {
+                let Some(depth) = propfind_depth(headers).filter(|v| *v != DEPTH_INFINITY) else {
+                    status_bad_request(res, "Invalid depth: only 0 and 1 are allowed.");
+                    return Ok(());
+                };
+                let output = self
+                    .list_provenance_dir(&path, depth, access_paths, public_only)
+                    .await?
+                    .iter()
+                    .map(|v| v.to_dav_xml(self.args.uri_prefix.as_str(), &[]))
+                    .collect::<String>();
+                res_multistatus(res, &output);
+            }
+            ("PROPFIND", _) if is_file => {
+                match self.provenance_file_item(&path, &virtual_path).await? {
+                    Some(item) => {
+                        res_multistatus(res, &item.to_dav_xml(self.args.uri_prefix.as_str(), &[]))
+                    }
+                    None => status_not_found(res),
+                }
+            }
+            ("OPTIONS", _) => {
+                res.headers_mut()
+                    .insert(ALLOW, HeaderValue::from_static("GET,HEAD,PROPFIND,OPTIONS"));
+            }
+            ("GET" | "HEAD" | "PROPFIND", _) => status_not_found(res),
+            _ => {
+                *res.status_mut() = StatusCode::FORBIDDEN;
+                *res.body_mut() = body_full("The provenance folder is read-only");
+            }
+        }
+        Ok(())
+    }
+
+    /// The virtual directory itself followed, at depth 1, by its entries
+    async fn list_provenance_dir(
+        &self,
+        dir: &Path,
+        depth: u32,
+        access_paths: AccessPaths,
+        public_only: Option<&PublicOnly>,
+    ) -> Result<Vec<PathItem>> {
+        let rel = dir.strip_prefix(&self.args.serve_path)?;
+        let mut items = vec![PathItem::new(
+            PathType::Dir,
+            virtual_name(&normalize_path(rel)),
+            0,
+            0,
+        )];
+        if depth == 0 {
+            return Ok(items);
+        }
+        for item in self
+            .list_dir(dir, &self.args.serve_path, access_paths, public_only)
+            .await?
+        {
+            if item.name == ".." {
+                continue;
+            }
+            if item.is_dir() {
+                items.push(PathItem::new(
+                    PathType::Dir,
+                    virtual_name(&item.name),
+                    item.mtime,
+                    0,
+                ));
+                continue;
+            }
+            let path = self.args.serve_path.join(&item.name);
+            for virtual_path in [
+                VirtualPath::Manifest(item.name.clone()),
+                VirtualPath::Ots(item.name.clone()),
+            ] {
+                if let Some(item) = self.provenance_file_item(&path, &virtual_path).await? {
+                    items.push(item);
+                }
+            }
+        }
+        Ok(items)
+    }
+
+    /// Describe a virtual proof file, None if the real file has no such record
+    async fn provenance_file_item(
+        &self,
+        path: &Path,
+        virtual_path: &VirtualPath,
+    ) -> Result<Option<PathItem>> {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+        let Some(manifest) =
+            crate::provenance_utils::get_manifest_for_file(&self.provenance_db, path).await?
+        else {
+            return Ok(None);
+        };
+        let mtime = tokio::fs::metadata(path)
+            .await
+            .ok()
+            .and_then(|v| v.modified().ok())
+            .map(|v| to_timestamp(&v))
+            .unwrap_or_default();
+        let (name, size) = match virtual_path {
+            VirtualPath::Manifest(file) => (
+                format!("{file}{MANIFEST_SUFFIX}"),
+                serde_json::to_string_pretty(&manifest)?.len(),
+            ),
+            VirtualPath::Ots(file) => {
+                let Some(size) = manifest
+                    .events
+                    .last()
+                    .filter(|v| !v.ots_proof_b64.is_empty())
+                    .and_then(|v| STANDARD.decode(&v.ots_proof_b64).ok())
+                    .map(|v| v.len())
+                else {
+                    return Ok(None);
+                };
+                (format!("{file}{OTS_SUFFIX}"), size)
+            }
+            VirtualPath::Dir(_) => return Ok(None),
+        };
+        Ok(Some(PathItem::new(
+            PathType::File,
+            virtual_name(&name),
+            mtime,
+            size as u64,
+        )))
+    }
+}
+
+fn virtual_name(rel: &str) -> String {
+    if rel.is_empty() {
+        PROVENANCE_DIR.to_string()
+    } else {
+        format!("{PROVENANCE_DIR}/{rel}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_virtual_path() {
+        assert_eq!(
+            VirtualPath::parse(".provenance"),
+            Some(VirtualPath::Dir(String::new()))
+        );
+        assert_eq!(
+            VirtualPath::parse(".provenance/dir1"),
+            Some(VirtualPath::Dir("dir1".into()))
+        );
+        assert_eq!(
+            VirtualPath::parse(".provenance/dir1/a.txt.manifest.json"),
+            Some(VirtualPath::Manifest("dir1/a.txt".into()))
+        );
+        assert_eq!(
+            VirtualPath::parse(".provenance/a.txt.ots"),
+            Some(VirtualPath::Ots("a.txt".into()))
+        );
+        assert_eq!(VirtualPath::parse(".provenances/a.txt"), None);
+        assert_eq!(VirtualPath::parse("dir1/.provenance"), None);
+    }
+}
@@ -1,29 +1,44 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
 use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures_util::TryStreamExt;
 use headers::{ContentLength, ContentType, HeaderMapExt};
-use http_body_util::BodyExt;
+use http_body_util::{BodyExt, Limited, StreamBody};
 use hyper::{
-    header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE},
-    StatusCode,
+    body::Frame,
+    header::{
+        HeaderValue, AUTHORIZATION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, WWW_AUTHENTICATE,
+    },
+    HeaderMap, StatusCode,
 };
 use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use tokio_util::io::ReaderStream;
 use uuid::Uuid;
 
 use crate::file_utils;
 use crate::http_utils::body_full;
+use crate::ots_aggregator::{OtsAggregator, QUEUED_PROOF};
 use crate::provenance::{
-    generate_share_signature, verify_share_signature, ProvenanceDb, SERVER_PRIVATE_KEY_HEX,
-    SERVER_PUBLIC_KEY_HEX,
+    check_chain, compute_event_hash, generate_share_signature, verify_event,
+    verify_share_signature, Actors, Event, EventAction, InsertEventArgs, ProvenanceDb,
+    ServerKeypair, ShareInfo, ShareLimits, Signatures,
 };
 use crate::provenance_utils;
+use crate::retention::parse_age;
+use crate::utils::{encode_uri, escape_html, unix_now};
 
-use super::path_item::StampStatus;
+use super::path_item::{StampStatus, StampSummary};
 use super::response_utils::{
-    set_content_disposition, set_json_response, status_bad_request, status_not_found, Response,
+    send_buf_size, set_content_disposition, set_json_response, status_bad_request, status_conflict,
+    status_forbid, status_gone, status_not_found, Response,
 };
+use super::stamp_cache::StampCache;
 
 pub type Request = hyper::Request<hyper::body::Incoming>;
 
@@ -60,12 +75,16 @@ pub async fn handle_ots_upload(
     res: &mut Response,
 ) -> Result<()> {
     // Read the OTS bytes from request body
-    let body_bytes = req
-        .into_body()
+    let body_bytes = match Limited::new(req.into_body(), MAX_OTS_PROOF_SIZE)
         .collect()
         .await
-        .map_err(|e| anyhow!("Failed to read request body: {}", e))?
-        .to_bytes();
+    {
+        Ok(v) => v.to_bytes(),
+        Err(_) => {
+            *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+            return Ok(());
+        }
+    };
 
     // Get artifact from database using unified utility
     let (artifact_id, _, _) =
@@ -95,6 +114,275 @@ pub async fn handle_ots_upload(
     Ok(())
 }
 
+const MAX_TRANSFER_BODY_SIZE: usize = 16 * 1024;
+
+/// Proofs are a few KiB, even once upgraded with a Bitcoin attestation
+const MAX_OTS_PROOF_SIZE: usize = 64 * 1024;
+
+/// A base64 proof and the digest it stamps
+const MAX_VERIFY_BODY_SIZE: usize = 128 * 1024;
+
+const MAX_SHARE_BODY_SIZE: usize = 4 * 1024;
+
+/// Optional request body of a share
+#[derive(Debug, Default, Deserialize)]
+struct ShareRequest {
+    /// Unix time the share stops working at
+    expires_at: Option<u64>,
+    /// Lifetime such as `7d` or `12h`, instead of `expires_at`
+    expires_in: Option<String>,
+    max_downloads: Option<u64>,
+    /// Asked for on every access to the share
+    password: Option<String>,
+}
+
+impl ShareRequest {
+    fn limits(&self) -> Result<ShareLimits> {
+        let now = unix_now().as_secs();
+        let expires_at = match (self.expires_at, self.expires_in.as_deref()) {
+            (Some(_), Some(_)) => bail!("Give either expires_at or expires_in"),
+            (Some(v), None) if v <= now => bail!("expires_at must be in the future"),
+            (Some(v), None) => Some(v),
+            (None, Some(v)) => match parse_age(v) {
+                Some(age) => Some(now + age.as_secs()),
+                None => bail!("Invalid expires_in, e.g. 7d or 12h"),
+            },
+            (None, None) => None,
+        };
+        if self.max_downloads == Some(0) {
+            bail!("max_downloads must be at least 1");
+        }
+        Ok(ShareLimits {
+            expires_at,
+            max_downloads: self.max_downloads,
+        })
+    }
+
+    /// Argon2 hash of the password, if any
+    fn password_hash(&self) -> Result<Option<String>> {
+        let Some(password) = self.password.as_deref() else {
+            return Ok(None);
+        };
+        if password.is_empty() {
+            bail!("password must not be empty");
+        }
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|e| anyhow!("Failed to hash share password, {e}"))?;
+        Ok(Some(hash.to_string()))
+    }
+}
+
+/// The password given for a share, from `?password=` or from the
+/// Authorization header, either Basic with any user name or Bearer
+pub fn share_password(query: &str, headers: &HeaderMap) -> Option<String> {
+    if let Some((_, v)) = form_urlencoded::parse(query.as_bytes()).find(|(k, _)| k == "password") {
+        return Some(v.into_owned());
+    }
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, credentials) = value.split_once(' ')?;
+    if scheme.eq_ignore_ascii_case("bearer") {
+        return Some(credentials.trim().to_string());
+    }
+    if scheme.eq_ignore_ascii_case("basic") {
+        let decoded = STANDARD.decode(credentials.trim()).ok()?;
+        let decoded = String::from_utf8(decoded).ok()?;
+        let (_, password) = decoded.split_once(':')?;
+        return Some(password.to_string());
+    }
+    None
+}
+
+/// Request body of a transfer, signed by both owners over the event hash
+#[derive(Debug, Deserialize)]
+struct TransferRequest {
+    prev_owner_pubkey_hex: String,
+    new_owner_pubkey_hex: String,
+    issued_at: String,
+    prev_event_hash_hex: String,
+    prev_owner_sig_hex: String,
+    new_owner_sig_hex: String,
+}
+
+/// Handle ownership transfer (POST /api/<file>?transfer)
+///
+/// The client computes the canonical hash of the next event (index, chain head,
+/// both pubkeys and `issued_at`), has both owners sign it and posts the result.
+/// The event is appended only if it extends the current head of the chain and
+/// is signed by the current owner.
+pub async fn handle_transfer(
+    path: &Path,
+    req: Request,
+    provenance_db: &ProvenanceDb,
+    ots: &OtsAggregator,
+    res: &mut Response,
+) -> Result<()> {
+    let body = match Limited::new(req.into_body(), MAX_TRANSFER_BODY_SIZE)
+        .collect()
+        .await
+    {
+        Ok(v) => v.to_bytes(),
+        Err(_) => {
+            *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+            return Ok(());
+        }
+    };
+    let transfer: TransferRequest = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => {
+            status_bad_request(res, &format!("Invalid transfer request: {e}"));
+            return Ok(());
+        }
+    };
+
+    let Some((artifact_id, _, sha256_hex)) =
+        provenance_utils::get_artifact_by_path(provenance_db, path).await?
+    else {
+        status_not_found(res);
+        return Ok(());
+    };
+    let Some(manifest) = provenance_utils::get_manifest_for_file(provenance_db, path).await? else {
+        status_not_found(res);
+        return Ok(());
+    };
+    let Some(head) = manifest.events.last() else {
+        status_not_found(res);
+        return Ok(());
+    };
+
+    if transfer.prev_event_hash_hex != head.event_hash_hex {
+        status_conflict(res, "prev_event_hash_hex is not the head of the chain");
+        return Ok(());
+    }
+    if file_utils::sha256_file_hash(path).await? != sha256_hex {
+        status_conflict(res, "File content no longer matches the provenance record");
+        return Ok(());
+    }
+    if current_owner(&manifest.events) != Some(transfer.prev_owner_pubkey_hex.as_str()) {
+        status_forbid(res);
+        return Ok(());
+    }
+    let issued_after_head = match (
+        chrono::DateTime::parse_from_rfc3339(&transfer.issued_at),
+        chrono::DateTime::parse_from_rfc3339(&head.issued_at),
+    ) {
+        (Ok(issued_at), Ok(head_issued_at)) => issued_at >= head_issued_at,
+        (Ok(_), Err(_)) => true,
+        (Err(_), _) => {
+            status_bad_request(res, "issued_at must be an RFC 3339 timestamp");
+            return Ok(());
+        }
+    };
+    if !issued_after_head {
+        status_bad_request(res, "issued_at is earlier than the previous event");
+        return Ok(());
+    }
+
+    let index = head.index + 1;
+    let actors = Actors {
+        creator_pubkey_hex: None,
+        prev_owner_pubkey_hex: Some(transfer.prev_owner_pubkey_hex),
+        new_owner_pubkey_hex: Some(transfer.new_owner_pubkey_hex),
+    };
+    let event_hash_hex = compute_event_hash(
+        index,
+        &EventAction::Transfer,
+        &sha256_hex,
+        Some(&head.event_hash_hex),
+        &actors,
+        &transfer.issued_at,
+    );
+    let mut event = Event {
+        event_type: "provenance.event/v1".to_string(),
+        index,
+        action: EventAction::Transfer,
+        artifact_sha256_hex: sha256_hex,
+        prev_event_hash_hex: Some(head.event_hash_hex.clone()),
+        actors,
+        issued_at: transfer.issued_at,
+        event_hash_hex,
+        signatures: Signatures {
+            creator_sig_hex: None,
+            prev_owner_sig_hex: Some(transfer.prev_owner_sig_hex),
+            new_owner_sig_hex: Some(transfer.new_owner_sig_hex),
+        },
+        ots_proof_b64: String::new(),
+    };
+    if !verify_event(&event).unwrap_or_default() {
+        status_bad_request(res, "Signatures don't match the transfer event");
+        return Ok(());
+    }
+
+    let digest = hex::decode(&event.event_hash_hex)?;
+    let ots_bytes = ots.try_stamp(digest).await;
+    event.ots_proof_b64 = STANDARD.encode(ots_bytes.as_deref().unwrap_or(QUEUED_PROOF));
+
+    provenance_db.insert_event(InsertEventArgs {
+        artifact_id,
+        index,
+        action: &event.action,
+        artifact_sha256_hex: &event.artifact_sha256_hex,
+        prev_event_hash_hex: event.prev_event_hash_hex.as_deref(),
+        issued_at: &event.issued_at,
+        event_hash_hex: &event.event_hash_hex,
+        ots_proof_b64: &event.ots_proof_b64,
+        actors: &event.actors,
+        signatures: &event.signatures,
+    })?;
+    if ots_bytes.is_none() {
+        provenance_db.queue_ots_digest(artifact_id, index, &event.event_hash_hex)?;
+    }
+    info!(
+        "Recorded transfer #{} of {} ({})",
+        index,
+        path.display(),
+        &event.artifact_sha256_hex[..8]
+    );
+
+    *res.status_mut() = StatusCode::CREATED;
+    set_json_response(res, serde_json::to_string(&event)?);
+    Ok(())
+}
+
+/// The owner after the last event: the new owner of the latest transfer, or the creator
+fn current_owner(events: &[Event]) -> Option<&str> {
+    events.iter().rev().find_map(|event| match event.action {
+        EventAction::Transfer => event.actors.new_owner_pubkey_hex.as_deref(),
+        EventAction::Mint => event.actors.creator_pubkey_hex.as_deref(),
+        // Copies keep the owner
+        EventAction::Copy => None,
+    })
+}
+
+/// Handle signing key discovery (GET /.well-known/node-drive/signing-key)
+///
+/// Lists the active public key first, followed by retired keys that may still
+/// appear in older events.
+pub fn handle_signing_key(
+    keypair: &ServerKeypair,
+    provenance_db: &ProvenanceDb,
+    head_only: bool,
+    res: &mut Response,
+) -> Result<()> {
+    let keys = provenance_db.list_signing_keys()?;
+    let output = serde_json::json!({
+        "algorithm": "secp256k1",
+        "public_key_hex": keypair.public_key_hex,
+        "keys": keys,
+    })
+    .to_string();
+    if head_only {
+        res.headers_mut()
+            .typed_insert(ContentType::from(mime_guess::mime::APPLICATION_JSON));
+        res.headers_mut()
+            .typed_insert(ContentLength(output.len() as u64));
+    } else {
+        set_json_response(res, output);
+    }
+    Ok(())
+}
+
 pub async fn handle_ots_download(
     path: &Path,
     head_only: bool,
@@ -260,11 +548,12 @@ pub async fn handle_ots_info(
     Ok(())
 }
 
+/// Returns whether the proof verified
 pub async fn handle_ots_verify(
     req: Request,
     _provenance_db: &ProvenanceDb,
     res: &mut Response,
-) -> Result<()> {
+) -> Result<bool> {
     use crate::ots_stamper;
 
     // Parse JSON request body
@@ -289,12 +578,16 @@ pub async fn handle_ots_verify(
         error: Option<String>,
     }
 
-    let body_bytes = req
-        .into_body()
+    let body_bytes = match Limited::new(req.into_body(), MAX_VERIFY_BODY_SIZE)
         .collect()
         .await
-        .map_err(|e| anyhow!("Failed to read request body: {}", e))?
-        .to_bytes();
+    {
+        Ok(v) => v.to_bytes(),
+        Err(_) => {
+            *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+            return Ok(false);
+        }
+    };
 
     let verify_req: VerifyRequest = serde_json::from_slice(&body_bytes)
         .map_err(|e| anyhow!("Failed to parse JSON request: {}", e))?;
@@ -348,6 +641,53 @@ pub async fn handle_ots_verify(
     let json = serde_json::to_string(&response)?;
     set_json_response(res, json);
 
+    Ok(response.success)
+}
+
+/// Handle the integrity check of a file's event chain (GET /api/<path>?verify-chain)
+pub async fn handle_verify_chain(
+    path: &Path,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let Some(manifest) = provenance_utils::get_manifest_for_file(provenance_db, path).await? else {
+        status_not_found(res);
+        return Ok(());
+    };
+    let events = check_chain(&manifest.events);
+    let valid = !events.is_empty() && events.iter().all(|v| v.passed);
+    let output = serde_json::json!({
+        "valid": valid,
+        "artifact_sha256_hex": manifest.artifact.sha256_hex,
+        "events": events,
+    });
+    set_json_response(res, output.to_string());
+    Ok(())
+}
+
+/// Handle the stamping status of a file (GET /api/<path>?stamp-status)
+pub async fn handle_stamp_status(
+    path: &Path,
+    provenance_db: &ProvenanceDb,
+    stamp_statuses: &StampCache<StampStatus>,
+    res: &mut Response,
+) -> Result<()> {
+    match compute_stamp_status(path, provenance_db, stamp_statuses).await {
+        Some(status) => set_json_response(res, serde_json::to_string(&status)?),
+        None => status_not_found(res),
+    }
+    Ok(())
+}
+
+/// Handle the audit trail of a file (GET /api/<path>?audit)
+pub async fn handle_verification_log(
+    path: &Path,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let path_str = path.to_str().ok_or_else(|| anyhow!("Invalid file path"))?;
+    let entries = provenance_db.get_verification_log(path_str)?;
+    set_json_response(res, serde_json::json!({ "entries": entries }).to_string());
     Ok(())
 }
 
@@ -364,168 +704,119 @@ pub async fn handle_hash_file(path: &Path, head_only: bool, res: &mut Response)
     Ok(())
 }
 
+/// Count the minted `files` by stamping state, from what the database
+/// already knows. Files without provenance aren't counted.
+pub async fn summarize_stamps(
+    files: Vec<String>,
+    provenance_db: &ProvenanceDb,
+) -> Result<StampSummary> {
+    provenance_db
+        .run(move |db| {
+            let mut summary = StampSummary::default();
+            for file in &files {
+                match db.get_artifact_by_path(file)? {
+                    Some((_, artifact)) if artifact.verified_chain.is_some() => {
+                        summary.n_confirmed += 1
+                    }
+                    Some(_) => summary.n_pending += 1,
+                    None => {}
+                }
+            }
+            Ok(summary)
+        })
+        .await
+}
+
+/// Stamp status of a file from what the database records, None for files
+/// without provenance. Calendars are never asked here, pending proofs are
+/// upgraded by the background worker, and statuses are cached for a little
+/// while.
 pub async fn compute_stamp_status(
     path: &Path,
     provenance_db: &ProvenanceDb,
+    stamp_statuses: &StampCache<StampStatus>,
 ) -> Option<StampStatus> {
-    use crate::ots_stamper;
-    use chrono::{DateTime, Duration, Utc};
-
-    // Throttle interval: only check calendar servers once every 5 minutes per file
-    const CHECK_THROTTLE_MINUTES: i64 = 5;
-
-    // Get artifact from database by file path
-    let (artifact_id, artifact) = match provenance_utils::get_artifact_by_path(provenance_db, path)
+    if let Some(status) = stamp_statuses.get(path) {
+        return Some(status);
+    }
+    let (_, artifact, sha256_hex) = provenance_utils::get_artifact_by_path(provenance_db, path)
         .await
-        .ok()?
-    {
-        Some((id, artifact, _hash)) => (id, artifact),
-        None => {
-            // File not in provenance system yet
-            return None;
-        }
-    };
-
-    let sha256_hex = artifact.sha256_hex.clone();
-
-    // OPTIMIZATION 1: Check if we have cached verification results in artifacts table
-    if let (Some(chain), Some(timestamp), Some(height)) = (
-        &artifact.verified_chain,
+        .ok()??;
+    let results = match (
+        artifact.verified_chain,
         artifact.verified_timestamp,
         artifact.verified_height,
     ) {
-        // Return cached verification results without any database joins or network calls
-        let mut results_map = serde_json::Map::new();
-        let chain_result = serde_json::json!({
-            "timestamp": timestamp as u64,
-            "height": height,
-        });
-        results_map.insert(chain.clone(), chain_result);
-
-        return Some(StampStatus {
-            success: true,
-            results: Some(serde_json::Value::Object(results_map)),
-            error: None,
-            sha256_hex: Some(sha256_hex),
-        });
-    }
-
-    // OPTIMIZATION 2: Check last_check_at to throttle network calls
-    if let Some(ref last_check_str) = artifact.last_check_at {
-        if let Ok(last_check) = DateTime::parse_from_rfc3339(last_check_str) {
-            let now = Utc::now();
-            let elapsed = now.signed_duration_since(last_check);
-
-            if elapsed < Duration::minutes(CHECK_THROTTLE_MINUTES) {
-                // Too soon since last check, return pending status without network calls
-                return Some(StampStatus {
-                    success: false,
-                    results: None,
-                    error: None, // No error means it's just pending
-                    sha256_hex: Some(sha256_hex),
-                });
-            }
-        }
-    }
-
-    // Get manifest to access the latest OTS proof
-    let path_str = path.to_str()?;
-    let manifest = match provenance_db
-        .get_manifest_by_path(path_str)
-        .inspect_err(|e| {
-            warn!("Failed to get manifest for {}: {}", sha256_hex, e);
-        })
-        .ok()?
-    {
-        Some(m) => m,
-        None => {
-            return None;
-        }
-    };
-
-    // Get the latest event
-    if manifest.events.is_empty() {
-        return None;
-    }
-
-    let latest_event = &manifest.events[manifest.events.len() - 1];
-    let event_index = manifest.events.len().saturating_sub(1) as u32;
-
-    // Update last_check_at to prevent repeated checks
-    let _ = provenance_db.update_last_check_at(artifact_id);
-
-    // No cached results and throttle expired, need to verify the OTS proof (network calls)
-    match ots_stamper::verify_timestamp(
-        &latest_event.ots_proof_b64,
-        &latest_event.artifact_sha256_hex,
-    )
-    .await
-    {
-        Ok(verification_response) => {
-            // If timestamp was upgraded AND we have verification results, save both
-            if let Some(ref upgraded_ots_b64) = verification_response.upgraded_ots_b64 {
-                if let Some(first_result) = verification_response.results.first() {
-                    // Save upgraded OTS and verification results together
-                    let _ = provenance_db.update_ots_proof_and_verification(
-                        artifact_id,
-                        event_index,
-                        upgraded_ots_b64,
-                        &first_result.chain,
-                        first_result.timestamp as i64,
-                        first_result.height,
-                    );
-                } else {
-                    // Just save upgraded OTS without verification results
-                    let _ =
-                        provenance_db.update_ots_proof(artifact_id, event_index, upgraded_ots_b64);
-                }
-            } else if let Some(first_result) = verification_response.results.first() {
-                // No upgrade, but we have verification results to cache
-                let _ = provenance_db.update_verification_result(
-                    artifact_id,
-                    &first_result.chain,
-                    first_result.timestamp as i64,
-                    first_result.height,
-                );
-            }
-
-            // Build results map matching the verify endpoint format
+        (Some(chain), Some(timestamp), Some(height)) => {
             let mut results_map = serde_json::Map::new();
-
-            for vr in verification_response.results {
-                let chain_result = serde_json::json!({
-                    "timestamp": vr.timestamp,
-                    "height": vr.height,
-                });
-                results_map.insert(vr.chain, chain_result);
-            }
-
-            Some(StampStatus {
-                success: true,
-                results: Some(serde_json::Value::Object(results_map)),
-                error: None,
-                sha256_hex: Some(sha256_hex),
-            })
+            results_map.insert(
+                chain,
+                serde_json::json!({
+                    "timestamp": timestamp as u64,
+                    "height": height,
+                }),
+            );
+            Some(serde_json::Value::Object(results_map))
         }
-        Err(_) => {
-            // Verification failed or pending
-            Some(StampStatus {
-                success: false,
-                results: None,
-                error: None, // No error means it's just pending
-                sha256_hex: Some(sha256_hex),
-            })
-        }
-    }
+        _ => None,
+    };
+    let path_str = path.to_str()?.to_string();
+    let modified_since_mint = provenance_db
+        .run(move |db| db.is_modified_since_mint(&path_str))
+        .await
+        .unwrap_or_default();
+    let status = StampStatus {
+        success: results.is_some(),
+        results,
+        error: None,
+        sha256_hex: Some(sha256_hex),
+        modified_since_mint,
+    };
+    stamp_statuses.insert(path, status.clone());
+    Some(status)
 }
 
 /// Handle share creation request (POST /api/<file>?share)
 pub async fn handle_create_share(
     path: &Path,
     user: Option<String>,
+    req: Request,
+    keypair: &ServerKeypair,
     provenance_db: &ProvenanceDb,
     res: &mut Response,
 ) -> Result<()> {
+    let body = match Limited::new(req.into_body(), MAX_SHARE_BODY_SIZE)
+        .collect()
+        .await
+    {
+        Ok(v) => v.to_bytes(),
+        Err(_) => {
+            *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+            return Ok(());
+        }
+    };
+    let request: ShareRequest = if body.is_empty() {
+        ShareRequest::default()
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(v) => v,
+            Err(e) => {
+                status_bad_request(res, &format!("Invalid share request, {e}"));
+                return Ok(());
+            }
+        }
+    };
+    let (limits, password_hash) = match request.limits().and_then(|limits| {
+        let password_hash = request.password_hash()?;
+        Ok((limits, password_hash))
+    }) {
+        Ok(v) => v,
+        Err(e) => {
+            status_bad_request(res, &e.to_string());
+            return Ok(());
+        }
+    };
+
     // Get file hash - file must exist
     let file_sha256_hex = match file_utils::sha256_file_hash(path).await {
         Ok(hash) => hash,
@@ -546,7 +837,7 @@ pub async fn handle_create_share(
         &file_sha256_hex,
         &share_id,
         &timestamp,
-        SERVER_PRIVATE_KEY_HEX,
+        &keypair.private_key_hex,
     ) {
         Ok(sig) => sig,
         Err(e) => {
@@ -574,8 +865,10 @@ pub async fn handle_create_share(
         &file_sha256_hex,
         &timestamp,
         user.as_deref(),
-        SERVER_PUBLIC_KEY_HEX,
+        &keypair.public_key_hex,
         &share_signature,
+        limits,
+        password_hash.as_deref(),
     ) {
         Ok(_) => {}
         Err(e) => {
@@ -602,6 +895,9 @@ pub async fn handle_create_share(
         owner_pubkey: String,
         signature: String,
         file_sha256: String,
+        #[serde(flatten)]
+        limits: ShareLimits,
+        password_protected: bool,
     }
 
     let response = ShareResponse {
@@ -609,9 +905,11 @@ pub async fn handle_create_share(
         share_id: share_id.clone(),
         share_url: format!("/share/{}", share_id),
         created_at: timestamp,
-        owner_pubkey: SERVER_PUBLIC_KEY_HEX.to_string(),
+        owner_pubkey: keypair.public_key_hex.clone(),
         signature: share_signature,
         file_sha256: file_sha256_hex,
+        limits,
+        password_protected: password_hash.is_some(),
     };
 
     let json = serde_json::to_string(&response)?;
@@ -620,35 +918,72 @@ pub async fn handle_create_share(
     Ok(())
 }
 
-/// Handle shared file download (GET /share/<id>/download)
-pub async fn handle_shared_file_download(
+/// The share `share_id` while it can be used: 404 without such an active
+/// share, 401 without its password, 410 once it expired or ran out of
+/// downloads
+fn usable_share(
     share_id: &str,
-    head_only: bool,
+    password: Option<&str>,
     provenance_db: &ProvenanceDb,
     res: &mut Response,
-) -> Result<()> {
-    // Get share info from database
+) -> Result<Option<ShareInfo>> {
     let share_info = match provenance_db.get_share(share_id)? {
-        Some(info) => info,
-        None => {
+        Some(info) if info.is_active => info,
+        _ => {
             status_not_found(res);
-            return Ok(());
+            return Ok(None);
         }
     };
-
-    // Check if share is active
-    if !share_info.is_active {
-        status_not_found(res);
-        return Ok(());
+    if let Some(hash) = share_info.password_hash.as_deref() {
+        let hash =
+            PasswordHash::new(hash).map_err(|e| anyhow!("Invalid share password hash, {e}"))?;
+        let valid = password.is_some_and(|v| {
+            Argon2::default()
+                .verify_password(v.as_bytes(), &hash)
+                .is_ok()
+        });
+        if !valid {
+            *res.status_mut() = StatusCode::UNAUTHORIZED;
+            res.headers_mut().insert(
+                WWW_AUTHENTICATE,
+                HeaderValue::from_static("Basic realm=\"Shared file\""),
+            );
+            return Ok(None);
+        }
+    }
+    let limits = share_info.limits;
+    if limits.expires_at.is_some_and(|v| v <= unix_now().as_secs()) {
+        status_gone(res, "This share has expired");
+        return Ok(None);
+    }
+    if let Some(max_downloads) = limits.max_downloads {
+        if provenance_db.count_share_downloads(share_id)? >= max_downloads {
+            status_gone(res, "This share has no downloads left");
+            return Ok(None);
+        }
     }
+    Ok(Some(share_info))
+}
+
+/// Check a shared file download (GET /share/<id>/download) and attach the
+/// share headers, the file itself is sent by the caller
+pub fn prepare_shared_file_download(
+    share_id: &str,
+    password: Option<&str>,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<Option<PathBuf>> {
+    let Some(share_info) = usable_share(share_id, password, provenance_db, res)? else {
+        return Ok(None);
+    };
 
     // Get the file path
-    let file_path = Path::new(&share_info.file_path);
+    let file_path = PathBuf::from(&share_info.file_path);
 
     // Check if file exists
     if !file_path.exists() {
         status_not_found(res);
-        return Ok(());
+        return Ok(None);
     }
 
     // Verify the share signature using the stored hash
@@ -663,12 +998,9 @@ pub async fn handle_shared_file_download(
 
     if !is_valid {
         status_bad_request(res, "Invalid share signature");
-        return Ok(());
+        return Ok(None);
     }
 
-    // Record the download
-    let _ = provenance_db.record_share_download(share_id, None, None, None);
-
     // Serve the file with share metadata in headers
     res.headers_mut()
         .insert("X-Share-Id", HeaderValue::from_str(share_id)?);
@@ -685,50 +1017,55 @@ pub async fn handle_shared_file_download(
         HeaderValue::from_str(&share_info.file_sha256_hex)?,
     );
 
-    // Read and return the file
-    let file_data = tokio::fs::read(file_path).await?;
-    let filename = file_utils::extract_filename(file_path)?;
-
-    res.headers_mut().insert(
-        CONTENT_TYPE,
-        HeaderValue::from_static("application/octet-stream"),
-    );
-    set_content_disposition(res, true, &filename)?;
-    res.headers_mut()
-        .insert(CONTENT_LENGTH, format!("{}", file_data.len()).parse()?);
+    Ok(Some(file_path))
+}
 
-    if head_only {
-        return Ok(());
+/// Record a download of the share once the response carries the first byte
+/// of the file, so resumed ranges and revalidations don't count
+///
+/// Turns the response into a 410 if the last download was taken since the
+/// share was checked.
+pub fn record_shared_file_download(
+    share_id: &str,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let first_byte = match res.status() {
+        StatusCode::OK => true,
+        StatusCode::PARTIAL_CONTENT => res
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("bytes 0-")),
+        _ => false,
+    };
+    if first_byte
+        && provenance_db
+            .record_share_download(share_id, None, None, None)?
+            .is_none()
+    {
+        *res = Response::default();
+        status_gone(res, "This share has no downloads left");
     }
-
-    *res.body_mut() = body_full(file_data);
     Ok(())
 }
 
 /// Handle share metadata request (GET /share/<id>/info)
 pub async fn handle_share_metadata(
     share_id: &str,
+    password: Option<&str>,
     provenance_db: &ProvenanceDb,
+    stamp_statuses: &StampCache<StampStatus>,
     res: &mut Response,
 ) -> Result<()> {
-    // Get share info from database
-    let mut share_info = match provenance_db.get_share(share_id)? {
-        Some(info) => info,
-        None => {
-            status_not_found(res);
-            return Ok(());
-        }
-    };
-
-    // Check if share is active
-    if !share_info.is_active {
-        status_not_found(res);
+    let Some(mut share_info) = usable_share(share_id, password, provenance_db, res)? else {
         return Ok(());
-    }
+    };
 
     // Compute stamp status for the shared file
     let file_path = Path::new(&share_info.file_path);
-    if let Some(stamp_status) = compute_stamp_status(file_path, provenance_db).await {
+    if let Some(stamp_status) = compute_stamp_status(file_path, provenance_db, stamp_statuses).await
+    {
         // Serialize stamp_status to JSON value
         share_info.stamp_status = Some(serde_json::to_value(stamp_status)?);
     }
@@ -745,26 +1082,189 @@ pub async fn handle_share_metadata(
     Ok(())
 }
 
-/// Handle share manifest request (GET /share/<id>/manifest)
-pub async fn handle_share_manifest(
+/// What a recipient sees of a share before downloading it
+#[derive(Debug, Serialize)]
+struct ShareLanding {
+    share_id: String,
+    name: String,
+    size: u64,
+    created_at: String,
+    shared_by: Option<String>,
+    owner_pubkey_hex: String,
+    file_sha256_hex: String,
+    /// The server signed this share for this very file hash
+    signature_valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stamp_status: Option<StampStatus>,
+    /// A valid signature over a hash confirmed in the Bitcoin blockchain
+    verified: bool,
+    download_url: String,
+    #[serde(flatten)]
+    limits: ShareLimits,
+    password_protected: bool,
+}
+
+impl ShareLanding {
+    /// Human description of the OpenTimestamps status
+    fn stamp_text(&self) -> String {
+        let Some(status) = &self.stamp_status else {
+            return "Not timestamped".to_string();
+        };
+        if !status.success {
+            return match &status.error {
+                Some(_) => "Timestamp could not be checked".to_string(),
+                None => "Waiting for Bitcoin confirmation".to_string(),
+            };
+        }
+        let confirmed = status
+            .results
+            .as_ref()
+            .and_then(|v| v.as_object())
+            .and_then(|v| v.values().next())
+            .and_then(|v| Some((v["height"].as_u64()?, v["timestamp"].as_i64()?)));
+        match confirmed {
+            Some((height, timestamp)) => {
+                let time = chrono::DateTime::from_timestamp(timestamp, 0)
+                    .map(|v| v.format("%Y-%m-%d %H:%M UTC").to_string())
+                    .unwrap_or_default();
+                format!("Confirmed in Bitcoin block {height}, {time}")
+            }
+            None => "Confirmed in the Bitcoin blockchain".to_string(),
+        }
+    }
+
+    fn to_html(&self, password: Option<&str>) -> String {
+        let (badge_class, badge) = if self.verified {
+            ("verified", "Verified")
+        } else if self.signature_valid {
+            ("pending", "Signed, not yet confirmed")
+        } else {
+            ("unverified", "Unverified")
+        };
+        let mut download_url = format!("{}/download", encode_uri(&self.share_id));
+        if let Some(password) = password {
+            let query: String = form_urlencoded::Serializer::new(String::new())
+                .append_pair("password", password)
+                .finish();
+            download_url = format!("{download_url}?{query}");
+        }
+        let shared_by = self.shared_by.as_deref().unwrap_or("anonymous");
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{name}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; max-width: 40rem; margin: 2rem auto; padding: 0 1rem; color: #222; }}
+dt {{ font-weight: 600; margin-top: .75rem; }}
+dd {{ margin: .25rem 0 0; overflow-wrap: anywhere; }}
+.badge {{ display: inline-block; padding: .2rem .6rem; border-radius: 1rem; font-size: .9rem; }}
+.verified {{ background: #d3f5dc; color: #14532d; }}
+.pending {{ background: #fdf0c8; color: #713f12; }}
+.unverified {{ background: #fddede; color: #7f1d1d; }}
+.button {{ display: inline-block; margin-top: 1.5rem; padding: .6rem 1.2rem; border-radius: .4rem; background: #1d4ed8; color: #fff; text-decoration: none; }}
+</style>
+</head>
+<body>
+<h1>{name}</h1>
+<span class="badge {badge_class}">{badge}</span>
+<dl>
+<dt>Size</dt><dd>{size} bytes</dd>
+<dt>Shared by</dt><dd>{shared_by}, {created_at}</dd>
+<dt>Owner key</dt><dd><code>{owner}</code></dd>
+<dt>SHA-256</dt><dd><code>{sha256}</code></dd>
+<dt>Timestamp</dt><dd>{stamp}</dd>
+</dl>
+<a class="button" href="{download_url}" download="{name}">Download</a>
+</body>
+</html>
+"#,
+            name = escape_html(&self.name),
+            size = self.size,
+            shared_by = escape_html(shared_by),
+            created_at = escape_html(&self.created_at),
+            owner = escape_html(&self.owner_pubkey_hex),
+            sha256 = escape_html(&self.file_sha256_hex),
+            stamp = escape_html(&self.stamp_text()),
+            download_url = escape_html(&download_url),
+        )
+    }
+}
+
+/// Handle the share landing page (GET /share/<id>?info), HTML for people or
+/// JSON with `?info=json`, so recipients can inspect the provenance of a
+/// file before downloading it
+pub async fn handle_share_landing(
     share_id: &str,
+    password: Option<&str>,
+    json: bool,
+    head_only: bool,
     provenance_db: &ProvenanceDb,
+    stamp_statuses: &StampCache<StampStatus>,
     res: &mut Response,
 ) -> Result<()> {
-    // Get share info from database
-    let share_info = match provenance_db.get_share(share_id)? {
-        Some(info) => info,
-        None => {
-            status_not_found(res);
-            return Ok(());
-        }
+    let Some(share_info) = usable_share(share_id, password, provenance_db, res)? else {
+        return Ok(());
     };
-
-    // Check if share is active
-    if !share_info.is_active {
+    let file_path = Path::new(&share_info.file_path);
+    let Ok(meta) = tokio::fs::metadata(file_path).await else {
         status_not_found(res);
         return Ok(());
+    };
+    let signature_valid = verify_share_signature(
+        &share_info.file_sha256_hex,
+        share_id,
+        &share_info.created_at,
+        &share_info.share_signature_hex,
+        &share_info.owner_pubkey_hex,
+    )?;
+    let stamp_status = compute_stamp_status(file_path, provenance_db, stamp_statuses).await;
+    let verified = signature_valid && stamp_status.as_ref().is_some_and(|v| v.success);
+    let landing = ShareLanding {
+        share_id: share_id.to_string(),
+        name: file_utils::extract_filename(file_path)?.to_string(),
+        size: meta.len(),
+        created_at: share_info.created_at.clone(),
+        shared_by: share_info.shared_by.clone(),
+        owner_pubkey_hex: share_info.owner_pubkey_hex.clone(),
+        file_sha256_hex: share_info.file_sha256_hex.clone(),
+        signature_valid,
+        stamp_status,
+        verified,
+        download_url: format!("/share/{share_id}/download"),
+        limits: share_info.limits,
+        password_protected: share_info.password_hash.is_some(),
+    };
+
+    let body = if json {
+        res.headers_mut()
+            .typed_insert(ContentType::from(mime_guess::mime::APPLICATION_JSON));
+        serde_json::to_string_pretty(&landing)?
+    } else {
+        res.headers_mut()
+            .typed_insert(ContentType::from(mime_guess::mime::TEXT_HTML_UTF_8));
+        landing.to_html(password)
+    };
+    res.headers_mut()
+        .typed_insert(ContentLength(body.len() as u64));
+    if !head_only {
+        *res.body_mut() = body_full(body);
     }
+    Ok(())
+}
+
+/// Handle share manifest request (GET /share/<id>/manifest)
+pub async fn handle_share_manifest(
+    share_id: &str,
+    password: Option<&str>,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let Some(share_info) = usable_share(share_id, password, provenance_db, res)? else {
+        return Ok(());
+    };
 
     // Get the file path
     let file_path = Path::new(&share_info.file_path);
@@ -790,23 +1290,13 @@ pub async fn handle_share_manifest(
 /// Handle share OTS info request (GET /share/<id>/ots-info)
 pub async fn handle_share_ots_info(
     share_id: &str,
+    password: Option<&str>,
     provenance_db: &ProvenanceDb,
     res: &mut Response,
 ) -> Result<()> {
-    // Get share info from database
-    let share_info = match provenance_db.get_share(share_id)? {
-        Some(info) => info,
-        None => {
-            status_not_found(res);
-            return Ok(());
-        }
-    };
-
-    // Check if share is active
-    if !share_info.is_active {
-        status_not_found(res);
+    let Some(share_info) = usable_share(share_id, password, provenance_db, res)? else {
         return Ok(());
-    }
+    };
 
     // Get the file path
     let file_path = Path::new(&share_info.file_path);
@@ -855,6 +1345,8 @@ pub async fn handle_share_info(
         shared_by: Option<String>,
         owner_pubkey: String,
         downloads: usize,
+        #[serde(flatten)]
+        limits: ShareLimits,
     }
 
     let mut share_items = Vec::new();
@@ -869,6 +1361,7 @@ pub async fn handle_share_info(
             shared_by: share.shared_by,
             owner_pubkey: share.owner_pubkey_hex,
             downloads,
+            limits: share.limits,
         });
     }
 
@@ -968,3 +1461,54 @@ pub async fn handle_distribution_chain(
 
     Ok(())
 }
+
+/// Handle the latest anchor of the whole database (GET /__dufs__/provenance-anchor)
+pub async fn handle_provenance_anchor(
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    match provenance_db.run(|db| db.latest_anchor()).await? {
+        Some(anchor) => set_json_response(res, serde_json::to_string(&anchor)?),
+        None => status_not_found(res),
+    }
+    Ok(())
+}
+
+/// Handle provenance database download (GET /__dufs__/provenance-db)
+///
+/// The live database may be mid-write, so a snapshot is taken with the
+/// SQLite backup API and streamed instead of the file on disk.
+pub async fn handle_provenance_db_download(
+    provenance_db: &ProvenanceDb,
+    head_only: bool,
+    res: &mut Response,
+) -> Result<()> {
+    let file = provenance_db.run(|db| db.snapshot()).await?;
+    let file = tokio::fs::File::from_std(file);
+    let size = file.metadata().await?.len();
+
+    let filename = provenance_db
+        .get_db_path()
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("provenance.db");
+    res.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/vnd.sqlite3"),
+    );
+    set_content_disposition(res, true, filename)?;
+    res.headers_mut().typed_insert(ContentLength(size));
+
+    if head_only {
+        return Ok(());
+    }
+
+    let reader_stream = ReaderStream::with_capacity(file, send_buf_size(size));
+    let stream_body = StreamBody::new(
+        reader_stream
+            .map_ok(Frame::data)
+            .map_err(|err| anyhow!("{err}")),
+    );
+    *res.body_mut() = stream_body.boxed();
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use anyhow::Result;
+use std::path::Path;
+
+use crate::quota::{disk_usage, QuotaScope, QuotaUsage};
+
+use super::handlers::Server;
+use super::response_utils::{status_insufficient_storage, Response};
+
+/// The quota closest to its limit for a write, and the bytes left under it
+pub(super) struct Headroom {
+    pub scope: String,
+    pub bytes: u64,
+}
+
+impl Server {
+    /// Quotas limiting writes to `path` by `user`, with their current usage
+    pub(super) async fn quota_usage(
+        &self,
+        path: &Path,
+        user: Option<&str>,
+    ) -> Result<Vec<QuotaUsage>> {
+        let policy = &self.args.quota;
+        if policy.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut usages = vec![];
+        for rule in policy.path_rules_for(&self.relative_path(path)) {
+            let QuotaScope::Path(dir) = &rule.scope else {
+                continue;
+            };
+            let dir = self.args.serve_path.join(dir);
+            let used = tokio::task::spawn_blocking(move || disk_usage(&dir)).await?;
+            usages.push(QuotaUsage::new(rule, used));
+        }
+        if let Some((user, rule)) = user.and_then(|v| Some((v, policy.user_rule(v)?))) {
+            usages.push(QuotaUsage::new(
+                rule,
+                self.provenance_db.get_owned_size(user)?,
+            ));
+        }
+        Ok(usages)
+    }
+
+    /// Bytes `user` may still write to `path`, None when no quota applies
+    ///
+    /// `replaced` bytes of the existing file are overwritten by the write and
+    /// count as free, for a user's quota only when the file is theirs.
+    pub(super) async fn quota_headroom(
+        &self,
+        path: &Path,
+        user: Option<&str>,
+        replaced: u64,
+    ) -> Result<Option<Headroom>> {
+        let usages = self.quota_usage(path, user).await?;
+        if usages.is_empty() {
+            return Ok(None);
+        }
+        let owned = match (user, path.to_str()) {
+            (Some(user), Some(path_str)) if replaced > 0 => self
+                .provenance_db
+                .get_file_owner(path_str)?
+                .filter(|(owner, _)| owner == user)
+                .map(|(_, size)| size.min(replaced))
+                .unwrap_or_default(),
+            _ => 0,
+        };
+        let headroom = usages
+            .into_iter()
+            .map(|usage| {
+                let freed = if usage.scope.starts_with('@') {
+                    owned
+                } else {
+                    replaced
+                };
+                Headroom {
+                    bytes: usage.limit.saturating_sub(usage.used).saturating_add(freed),
+                    scope: usage.scope,
+                }
+            })
+            .min_by_key(|v| v.bytes);
+        Ok(headroom)
+    }
+
+    /// Reject a write of `bytes` more to `path` by `user` that goes over a
+    /// quota, returning whether it was rejected
+    ///
+    /// With `moved_from`, quotas covering the source too are left out, since
+    /// moving within them doesn't change their usage.
+    pub(super) async fn reject_over_quota(
+        &self,
+        path: &Path,
+        user: Option<&str>,
+        bytes: u64,
+        moved_from: Option<&Path>,
+        res: &mut Response,
+    ) -> Result<bool> {
+        let mut usages = self.quota_usage(path, user).await?;
+        if let Some(from) = moved_from {
+            let from = self.relative_path(from);
+            let covering: Vec<_> = self
+                .args
+                .quota
+                .path_rules_for(&from)
+                .iter()
+                .map(|rule| rule.to_string())
+                .collect();
+            usages
+                .retain(|usage| !usage.scope.starts_with('@') && !covering.contains(&usage.scope));
+        }
+        // Directories take no quota, but can't be created once it is used up
+        let over = usages
+            .iter()
+            .find(|usage| usage.exceeded_by(bytes) || (bytes == 0 && usage.used >= usage.limit));
+        match over {
+            Some(usage) => {
+                status_insufficient_storage(res, &format!("Quota exceeded for {}", usage.scope));
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
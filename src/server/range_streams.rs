@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Caps how many Range responses of the same file stream at once. Media
+/// players and download accelerators open many connections for one file;
+/// beyond the cap, requests wait for an earlier range to finish instead of
+/// all reading from disk in parallel.
+#[derive(Debug, Clone)]
+pub struct RangeStreams {
+    per_file: usize,
+    files: Arc<Mutex<HashMap<PathBuf, Arc<Semaphore>>>>,
+}
+
+impl RangeStreams {
+    pub fn new(per_file: usize) -> Self {
+        Self {
+            per_file,
+            files: Default::default(),
+        }
+    }
+
+    /// Wait for a slot to stream a range of `path`, held until the permit drops
+    pub async fn acquire(&self, path: &Path) -> OwnedSemaphorePermit {
+        let semaphore = {
+            let mut files = self.files.lock().unwrap();
+            // Forget files nobody is streaming anymore
+            files.retain(|_, v| Arc::strong_count(v) > 1);
+            files
+                .entry(path.to_path_buf())
+                .or_insert_with(|| Arc::new(Semaphore::new(self.per_file)))
+                .clone()
+        };
+        semaphore
+            .acquire_owned()
+            .await
+            .expect("range semaphore is never closed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_range_streams_queue() {
+        let streams = RangeStreams::new(2);
+        let path = Path::new("/srv/movie.mp4");
+        let first = streams.acquire(path).await;
+        let _second = streams.acquire(path).await;
+        let _other = streams.acquire(Path::new("/srv/other.mp4")).await;
+
+        let third = tokio::time::timeout(Duration::from_millis(50), streams.acquire(path)).await;
+        assert!(third.is_err());
+
+        drop(first);
+        let third = tokio::time::timeout(Duration::from_millis(50), streams.acquire(path)).await;
+        assert!(third.is_ok());
+    }
+}
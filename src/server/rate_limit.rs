@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::Args;
+
+/// How often idle clients are forgotten
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Who a limit is counted for
+#[derive(Debug, Clone, Copy)]
+pub enum Client<'a> {
+    Ip(IpAddr),
+    User(&'a str),
+}
+
+impl Client<'_> {
+    fn key(&self) -> String {
+        match self {
+            Client::Ip(ip) => format!("ip:{ip}"),
+            Client::User(user) => format!("user:{user}"),
+        }
+    }
+}
+
+/// A request turned away, to be retried after this many seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limited {
+    pub retry_after: u64,
+}
+
+/// Token bucket holding up to one second of requests
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+struct State {
+    buckets: HashMap<String, Bucket>,
+    uploads: HashMap<String, Arc<Semaphore>>,
+    swept: Instant,
+}
+
+/// Per-IP and per-user request rates (`--rate-limit`, `--user-rate-limit`)
+/// and uploads in progress (`--max-concurrent-uploads`)
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    ip_rate: Option<u32>,
+    user_rate: Option<u32>,
+    max_uploads: Option<usize>,
+    state: Arc<Mutex<State>>,
+}
+
+impl RateLimiter {
+    /// The limiter of `--rate-limit`, `--user-rate-limit` and
+    /// `--max-concurrent-uploads`, None without any of them
+    pub fn from_args(args: &Args) -> Option<Self> {
+        match (
+            args.rate_limit,
+            args.user_rate_limit,
+            args.max_concurrent_uploads,
+        ) {
+            (None, None, None) => None,
+            (ip_rate, user_rate, max_uploads) => Some(Self::new(ip_rate, user_rate, max_uploads)),
+        }
+    }
+
+    pub fn new(ip_rate: Option<u32>, user_rate: Option<u32>, max_uploads: Option<usize>) -> Self {
+        Self {
+            ip_rate,
+            user_rate,
+            max_uploads,
+            state: Arc::new(Mutex::new(State {
+                buckets: HashMap::new(),
+                uploads: HashMap::new(),
+                swept: Instant::now(),
+            })),
+        }
+    }
+
+    /// Count a request of `client`, with a permit held for the duration of
+    /// an upload when uploads are limited
+    pub fn admit(
+        &self,
+        client: Client,
+        upload: bool,
+    ) -> Result<Option<OwnedSemaphorePermit>, Limited> {
+        let rate = match client {
+            Client::Ip(_) => self.ip_rate,
+            Client::User(_) => self.user_rate,
+        };
+        let key = client.key();
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        if now.duration_since(state.swept) > SWEEP_INTERVAL {
+            state.sweep(now, self.ip_rate, self.user_rate);
+        }
+
+        if let Some(rate) = rate {
+            let rate = rate as f64;
+            let bucket = state.buckets.entry(key.clone()).or_insert(Bucket {
+                tokens: rate,
+                updated: now,
+            });
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+            bucket.updated = now;
+            if bucket.tokens < 1.0 {
+                let retry_after = ((1.0 - bucket.tokens) / rate).ceil() as u64;
+                return Err(Limited {
+                    retry_after: retry_after.max(1),
+                });
+            }
+            bucket.tokens -= 1.0;
+        }
+
+        match (self.max_uploads, upload) {
+            (Some(max_uploads), true) => {
+                let uploads = state
+                    .uploads
+                    .entry(key)
+                    .or_insert_with(|| Arc::new(Semaphore::new(max_uploads)))
+                    .clone();
+                uploads
+                    .try_acquire_owned()
+                    .map(Some)
+                    .map_err(|_| Limited { retry_after: 1 })
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl State {
+    /// Drop full buckets and uploads nobody holds, they behave like new ones
+    fn sweep(&mut self, now: Instant, ip_rate: Option<u32>, user_rate: Option<u32>) {
+        self.buckets.retain(|key, bucket| {
+            let rate = match key.starts_with("ip:") {
+                true => ip_rate,
+                false => user_rate,
+            };
+            let rate = rate.unwrap_or(1) as f64;
+            bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate < rate
+        });
+        self.uploads.retain(|_, v| Arc::strong_count(v) > 1);
+        self.swept = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(Some(2), None, Some(1));
+        let ip = Client::Ip("10.0.0.1".parse().unwrap());
+        assert!(limiter.admit(ip, false).is_ok());
+        let upload = limiter.admit(ip, true).unwrap();
+        assert!(upload.is_some());
+        assert_eq!(
+            limiter.admit(ip, false).unwrap_err(),
+            Limited { retry_after: 1 }
+        );
+
+        // Other clients and users without a rate have their own budget
+        let other = Client::Ip("10.0.0.2".parse().unwrap());
+        assert!(limiter.admit(other, false).is_ok());
+        let user = Client::User("alice");
+        assert!(limiter.admit(user, false).is_ok());
+        let user_upload = limiter.admit(user, true).unwrap();
+        assert!(limiter.admit(user, true).is_err());
+        drop(user_upload);
+        assert!(limiter.admit(user, true).unwrap().is_some());
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::oidc::Oidc;
+use crate::Args;
+
+use super::auth_exec::ExecLogins;
+use super::drop_handlers::DropBox;
+use super::handlers::Server;
+use super::range_streams::RangeStreams;
+use super::rate_limit::RateLimiter;
+use super::response_utils::ErrorPages;
+use super::tenants::Tenants;
+
+/// Settings read once at startup, which a reload leaves as they were
+macro_rules! keep_startup_settings {
+    ($old:expr, $new:expr, $($field:ident),+ $(,)?) => {
+        $(
+            if $new.$field != $old.$field {
+                warn!(
+                    "Changing `{}` requires a restart",
+                    stringify!($field).replace('_', "-")
+                );
+                $new.$field = $old.$field.clone();
+            }
+        )+
+    };
+}
+
+impl Server {
+    /// A server with reloaded `args`, keeping the provenance database, locks,
+    /// event subscribers and other state of this one
+    pub fn reload(&self, mut args: Args) -> Result<Self> {
+        let old = &self.args;
+        keep_startup_settings!(
+            old,
+            args,
+            serve_path,
+            mounts,
+            addrs,
+            port,
+            path_prefix,
+            uri_prefix,
+            path_is_file,
+            tls_cert,
+            tls_key,
+            log_file,
+            log_rotate_size,
+            log_rotate_interval,
+            log_keep,
+            retention,
+            trash_dir,
+            trash_retention,
+            partial_upload_retention,
+            index,
+            dir_cache,
+            provenance_db,
+            provenance_local_db,
+            provenance_db_key_file,
+            provenance_db_rekey_file,
+            provenance_db_encryption,
+            provenance_backup_dir,
+            provenance_backup_interval,
+            provenance_backup_keep,
+            provenance_anchor_interval,
+            integrity_scan_interval,
+            ots_batch_interval,
+            ots_offline,
+            dedup_dir,
+            tenant_db_dir,
+            shutdown_timeout,
+            signing_key_file,
+            signing_key,
+            rotate_signing_key,
+        );
+
+        // Limits start over when they change, pending OIDC logins when the provider does
+        let rate_limiter = match (
+            args.rate_limit,
+            args.user_rate_limit,
+            args.max_concurrent_uploads,
+        ) == (
+            old.rate_limit,
+            old.user_rate_limit,
+            old.max_concurrent_uploads,
+        ) {
+            true => self.rate_limiter.clone(),
+            false => RateLimiter::from_args(&args),
+        };
+        let drop_box = match (&args.allow_anonymous_drop, args.anonymous_drop_limit)
+            == (&old.allow_anonymous_drop, old.anonymous_drop_limit)
+        {
+            true => self.drop_box.clone(),
+            false => DropBox::from_args(&args),
+        };
+        let range_streams = match args.max_range_streams == old.max_range_streams {
+            true => self.range_streams.clone(),
+            false => args.max_range_streams.map(RangeStreams::new),
+        };
+        let oidc = match args.oidc_config() == old.oidc_config() {
+            true => self.oidc.clone(),
+            false => args.oidc_config().map(Oidc::new).transpose()?,
+        };
+        let tenants = match &args.tenant_db_dir {
+            Some(dir) => Some(Arc::new(Tenants::new(dir)?)),
+            None => None,
+        };
+
+        let error_pages = match &args.error_pages {
+            Some(dir) => ErrorPages::load(dir)?,
+            None => ErrorPages::default(),
+        };
+
+        let mut server = self.with_args(args);
+        server.rate_limiter = rate_limiter;
+        server.drop_box = drop_box;
+        server.range_streams = range_streams;
+        server.oidc = oidc;
+        server.tenants = tenants;
+        server.error_pages = error_pages;
+        // The command may have changed
+        server.exec_logins = ExecLogins::default();
+        server.mounts = self
+            .mounts
+            .iter()
+            .map(|(name, mount)| {
+                let mut reloaded =
+                    server.with_args(server.args.for_mount(name, &mount.args.serve_path));
+                reloaded.search_index = mount.search_index.clone();
+                reloaded.mounts = BTreeMap::new();
+                reloaded.mount_name = Some(name.clone());
+                (name.clone(), Arc::new(reloaded))
+            })
+            .collect();
+        Ok(server)
+    }
+
+    fn with_args(&self, args: Args) -> Self {
+        Self {
+            args: Arc::new(args),
+            html: self.html.clone(),
+            single_file_req_paths: self.single_file_req_paths.clone(),
+            running: self.running.clone(),
+            provenance_db: self.provenance_db.clone(),
+            keypair: self.keypair.clone(),
+            trash: self.trash.clone(),
+            events: self.events.clone(),
+            ots: self.ots.clone(),
+            range_streams: self.range_streams.clone(),
+            locks: self.locks.clone(),
+            write_locks: self.write_locks.clone(),
+            uploads: self.uploads.clone(),
+            partial_uploads: self.partial_uploads.clone(),
+            logins: self.logins.clone(),
+            exec_logins: self.exec_logins.clone(),
+            stamp_summaries: self.stamp_summaries.clone(),
+            stamp_statuses: self.stamp_statuses.clone(),
+            search_index: self.search_index.clone(),
+            dir_cache: self.dir_cache.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            request_metrics: self.request_metrics.clone(),
+            drop_box: self.drop_box.clone(),
+            oidc: self.oidc.clone(),
+            sessions: self.sessions.clone(),
+            tenants: self.tenants.clone(),
+            error_pages: self.error_pages.clone(),
+            tenant_root: self.tenant_root.clone(),
+            mounts: self.mounts.clone(),
+            mount_name: self.mount_name.clone(),
+            layers: self.layers.clone(),
+        }
+    }
+}
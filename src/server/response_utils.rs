@@ -1,48 +1,41 @@
-use anyhow::Result;
-use headers::{
-    AccessControlAllowCredentials, AccessControlAllowOrigin, CacheControl, ContentLength,
-    ContentType, ETag, HeaderMapExt, LastModified,
-};
+use anyhow::{Context, Result};
+use headers::{ContentLength, ContentType, ETag, HeaderMapExt, LastModified};
 use http_body_util::combinators::BoxBody;
 use hyper::{
     body::Bytes,
-    header::{HeaderValue, CONTENT_DISPOSITION},
+    header::{
+        HeaderMap, HeaderValue, ACCEPT, ACCEPT_ENCODING, CONTENT_DISPOSITION, CONTENT_ENCODING,
+        RETRY_AFTER,
+    },
     StatusCode,
 };
+use std::collections::HashMap;
 use std::fs::Metadata;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 use tokio::fs;
 use tokio::io::AsyncReadExt;
 
 use crate::http_utils::body_full;
-use crate::utils::encode_uri;
+use crate::utils::{encode_uri, escape_html};
 
 pub type Response = hyper::Response<BoxBody<Bytes, anyhow::Error>>;
 
 pub const BUF_SIZE: usize = 65536;
+/// Largest read buffer when sending a file
+pub const MAX_SEND_BUF_SIZE: usize = 1048576; // 1M
 pub const EDITABLE_TEXT_MAX_SIZE: u64 = 4194304; // 4M
 pub const RESUMABLE_UPLOAD_MIN_SIZE: u64 = 20971520; // 20M
 pub const INDEX_NAME: &str = "index.html";
 pub const MAX_SUBPATHS_COUNT: u64 = 1000;
 
-pub fn add_cors(res: &mut Response) {
-    res.headers_mut()
-        .typed_insert(AccessControlAllowOrigin::ANY);
-    res.headers_mut()
-        .typed_insert(AccessControlAllowCredentials);
-    res.headers_mut().insert(
-        "Access-Control-Allow-Methods",
-        HeaderValue::from_static("*"),
-    );
-    res.headers_mut().insert(
-        "Access-Control-Allow-Headers",
-        HeaderValue::from_static("Authorization,*"),
-    );
-    res.headers_mut().insert(
-        "Access-Control-Expose-Headers",
-        HeaderValue::from_static("Authorization,*"),
-    );
+/// Read buffer for sending `size` bytes of a file: `BUF_SIZE` for small
+/// files, growing with the size so large downloads take fewer reads and
+/// frames
+pub fn send_buf_size(size: u64) -> usize {
+    (size / 64)
+        .clamp(BUF_SIZE as u64, MAX_SEND_BUF_SIZE as u64)
+        .next_power_of_two() as usize
 }
 
 pub fn res_multistatus(res: &mut Response, content: &str) {
@@ -73,6 +66,42 @@ pub fn status_no_content(res: &mut Response) {
     *res.status_mut() = StatusCode::NO_CONTENT;
 }
 
+pub fn status_conflict(res: &mut Response, body: &str) {
+    *res.status_mut() = StatusCode::CONFLICT;
+    *res.body_mut() = body_full(body.to_string());
+}
+
+pub fn status_insufficient_storage(res: &mut Response, body: &str) {
+    *res.status_mut() = StatusCode::INSUFFICIENT_STORAGE;
+    *res.body_mut() = body_full(body.to_string());
+}
+
+pub fn status_payload_too_large(res: &mut Response, limit: u64) {
+    *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+    *res.body_mut() = body_full(format!("Uploads are limited to {limit} bytes"));
+}
+
+pub fn status_unsupported_media_type(res: &mut Response, body: &str) {
+    *res.status_mut() = StatusCode::UNSUPPORTED_MEDIA_TYPE;
+    *res.body_mut() = body_full(body.to_string());
+}
+
+pub fn status_gone(res: &mut Response, body: &str) {
+    *res.status_mut() = StatusCode::GONE;
+    *res.body_mut() = body_full(body.to_string());
+}
+
+pub fn status_unprocessable(res: &mut Response, body: &str) {
+    *res.status_mut() = StatusCode::UNPROCESSABLE_ENTITY;
+    *res.body_mut() = body_full(body.to_string());
+}
+
+pub fn status_too_many_requests(res: &mut Response, retry_after: u64) {
+    *res.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+    res.headers_mut().insert(RETRY_AFTER, retry_after.into());
+    *res.body_mut() = body_full("Too Many Requests");
+}
+
 pub fn status_bad_request(res: &mut Response, body: &str) {
     *res.status_mut() = StatusCode::BAD_REQUEST;
     if !body.is_empty() {
@@ -80,6 +109,65 @@ pub fn status_bad_request(res: &mut Response, body: &str) {
     }
 }
 
+/// Operator-supplied HTML pages for error responses, `<dir>/<status>.html`
+/// such as `404.html`
+#[derive(Debug, Clone, Default)]
+pub struct ErrorPages(HashMap<u16, String>);
+
+impl ErrorPages {
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut pages = HashMap::new();
+        let entries = std::fs::read_dir(dir)
+            .with_context(|| format!("Failed to read error pages at {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|v| v.to_str()) != Some("html") {
+                continue;
+            }
+            let Some(status) = path
+                .file_stem()
+                .and_then(|v| v.to_str())
+                .and_then(|v| v.parse::<u16>().ok())
+                .filter(|v| (400..600).contains(v))
+            else {
+                continue;
+            };
+            let page = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read error page {}", path.display()))?;
+            pages.insert(status, page);
+        }
+        Ok(Self(pages))
+    }
+
+    /// Replace the body of an error response with its page, filling in
+    /// `{{status}}`, `{{reason}}` and `{{path}}`
+    pub fn render(&self, res: &mut Response, path: &str) {
+        let status = res.status();
+        let Some(page) = self.0.get(&status.as_u16()) else {
+            return;
+        };
+        let output = page
+            .replace("{{status}}", status.as_str())
+            .replace("{{reason}}", status.canonical_reason().unwrap_or_default())
+            .replace("{{path}}", &escape_html(path));
+        res.headers_mut().remove(CONTENT_ENCODING);
+        res.headers_mut()
+            .typed_insert(ContentType::from(mime_guess::mime::TEXT_HTML_UTF_8));
+        res.headers_mut()
+            .typed_insert(ContentLength(output.len() as u64));
+        *res.body_mut() = body_full(output);
+    }
+}
+
+/// Whether the request comes from a browser, which gets error pages rather
+/// than plain text
+pub fn accepts_html(headers: &HeaderMap<HeaderValue>) -> bool {
+    headers
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/html"))
+}
+
 pub fn set_content_disposition(res: &mut Response, inline: bool, filename: &str) -> Result<()> {
     let kind = if inline { "inline" } else { "attachment" };
     let filename: String = filename
@@ -124,22 +212,6 @@ pub fn set_json_response(res: &mut Response, content: String) {
     *res.body_mut() = body_full(content);
 }
 
-pub fn set_html_response(res: &mut Response, content: String, no_cache: bool) {
-    res.headers_mut()
-        .typed_insert(ContentType::from(mime_guess::mime::TEXT_HTML_UTF_8));
-    res.headers_mut()
-        .typed_insert(ContentLength(content.len() as u64));
-    if no_cache {
-        res.headers_mut()
-            .typed_insert(CacheControl::new().with_no_cache());
-    }
-    res.headers_mut().insert(
-        "x-content-type-options",
-        HeaderValue::from_static("nosniff"),
-    );
-    *res.body_mut() = body_full(content);
-}
-
 pub fn to_timestamp(time: &SystemTime) -> u64 {
     time.duration_since(SystemTime::UNIX_EPOCH)
         .unwrap_or_default()
@@ -164,9 +236,62 @@ pub fn extract_cache_headers(meta: &Metadata) -> Option<(ETag, LastModified)> {
     Some((etag, last_modified))
 }
 
+/// Fresh precompressed siblings of `path` (`<file>.br`, `<file>.gz`) with
+/// their content encoding, siblings older than the file being stale
+pub async fn precompressed_variants(path: &Path) -> Vec<(PathBuf, &'static str)> {
+    let Some(mtime) = fs::metadata(path)
+        .await
+        .ok()
+        .and_then(|v| v.modified().ok())
+    else {
+        return vec![];
+    };
+    let mut variants = vec![];
+    for (encoding, ext) in [("br", "br"), ("gzip", "gz")] {
+        let mut sibling = path.as_os_str().to_owned();
+        sibling.push(format!(".{ext}"));
+        let sibling = PathBuf::from(sibling);
+        let fresh = fs::metadata(&sibling)
+            .await
+            .ok()
+            .filter(|v| v.is_file())
+            .and_then(|v| v.modified().ok())
+            .is_some_and(|v| v >= mtime);
+        if fresh {
+            variants.push((sibling, encoding));
+        }
+    }
+    variants
+}
+
+/// The first of `variants` the request's `Accept-Encoding` allows
+pub fn pick_variant<'a>(
+    variants: &'a [(PathBuf, &'static str)],
+    headers: &HeaderMap<HeaderValue>,
+) -> Option<&'a (PathBuf, &'static str)> {
+    let accept_encoding = headers.get(ACCEPT_ENCODING)?.to_str().ok()?;
+    variants
+        .iter()
+        .find(|(_, encoding)| accepts_encoding(accept_encoding, encoding))
+}
+
+/// Whether an `Accept-Encoding` value allows `encoding` (`q=0` refuses it)
+fn accepts_encoding(accept_encoding: &str, encoding: &str) -> bool {
+    accept_encoding.split(',').any(|item| {
+        let mut parts = item.split(';').map(|v| v.trim());
+        let name = parts.next().unwrap_or_default();
+        let refused = parts.any(|v| {
+            v.strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .is_some_and(|q| q == 0.0)
+        });
+        (name.eq_ignore_ascii_case(encoding) || name == "*") && !refused
+    })
+}
+
 pub async fn get_content_type(path: &Path) -> Result<String> {
     let mut buffer: Vec<u8> = vec![];
-    fs::File::open(path)
+    crate::chunk_store::open(path)
         .await?
         .take(1024)
         .read_to_end(&mut buffer)
@@ -194,3 +319,25 @@ pub async fn get_content_type(path: &Path) -> Result<String> {
     };
     Ok(content_type)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accepts_encoding() {
+        assert!(accepts_encoding("gzip, deflate, br", "br"));
+        assert!(accepts_encoding("gzip;q=0.5", "gzip"));
+        assert!(accepts_encoding("*", "br"));
+        assert!(!accepts_encoding("gzip;q=0, deflate", "gzip"));
+        assert!(!accepts_encoding("deflate", "br"));
+    }
+
+    #[test]
+    fn test_send_buf_size() {
+        assert_eq!(send_buf_size(0), BUF_SIZE);
+        assert_eq!(send_buf_size(4 * 1024 * 1024), BUF_SIZE);
+        assert_eq!(send_buf_size(10 * 1024 * 1024), 262144);
+        assert_eq!(send_buf_size(8 * 1024 * 1024 * 1024), MAX_SEND_BUF_SIZE);
+    }
+}
@@ -0,0 +1,308 @@
+use hyper::Method;
+use std::collections::HashMap;
+
+use super::handlers::{has_query_flag, Request};
+
+/// Versioned web UI assets, served whatever the path prefix
+pub(super) const ASSETS_PREFIX: &str = concat!("__dufs_v", env!("CARGO_PKG_VERSION"), "__/");
+
+/// The public half of the signing key, published for anyone verifying events
+pub(super) const SIGNING_KEY_PATH: &str = "/.well-known/node-drive/signing-key";
+
+/// Query flags of a GET that the file handlers answer rather than the web UI
+const SERVER_QUERIES: [&str; 26] = [
+    "simple",
+    "edit",
+    "view",
+    "hash",
+    "zip",
+    "tar",
+    "ots",
+    "manifest=",
+    "verify",
+    "audit",
+    "stamp-status",
+    "download",
+    "share",
+    "meta",
+    "comment",
+    "tag",
+    "star",
+    "acl",
+    "preview",
+    "render",
+    "parts",
+    "transfer",
+    "sign",
+    "provenance-",
+    "ndjson",
+    "tree",
+];
+
+/// Which handler a request goes to, decided from its method, path and query
+/// in `Server::call`, and again by the `Mounts` layer for a mount or tenant
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Route {
+    /// `__dufs__` endpoints under the path prefix
+    Internal,
+    /// Public pages of share links under `/share/`
+    Share,
+    SigningKey,
+    /// The web UI and its assets
+    Asset,
+    /// An operation on a file or folder under `api/`
+    Api(Operation),
+    /// An operation on a file or folder at its own path, as WebDAV clients send
+    WebDav(Operation),
+}
+
+impl Route {
+    pub fn of(req: &Request, uri_prefix: &str) -> Self {
+        let uri = req.uri();
+        Self::from_parts(
+            req.method(),
+            uri.path(),
+            uri.query().unwrap_or_default(),
+            uri_prefix,
+        )
+    }
+
+    pub fn from_parts(method: &Method, path: &str, query: &str, uri_prefix: &str) -> Self {
+        let query_params: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let operation = Operation::of(method, &query_params);
+        let is_read = operation == Operation::Read;
+        let under = |base: &str| {
+            let base = format!("{uri_prefix}{base}");
+            path == base || path.starts_with(&format!("{base}/"))
+        };
+        let relative = path
+            .strip_prefix(uri_prefix)
+            .unwrap_or_else(|| path.trim_start_matches('/'));
+
+        if under("__dufs__") {
+            Route::Internal
+        } else if *method == Method::GET && relative.starts_with(ASSETS_PREFIX) {
+            Route::Asset
+        } else if is_read && path.starts_with("/share/") {
+            Route::Share
+        } else if path == SIGNING_KEY_PATH && *method != Method::OPTIONS {
+            Route::SigningKey
+        } else if under("api") {
+            Route::Api(operation)
+        } else if is_read && is_web_ui(path, query) {
+            Route::Asset
+        } else {
+            Route::WebDav(operation)
+        }
+    }
+
+    /// Every route, with each operation on files under the API and WebDAV
+    pub fn all() -> impl Iterator<Item = Route> {
+        let file_routes = |route: fn(Operation) -> Route| Operation::ALL.into_iter().map(route);
+        file_routes(Route::Api)
+            .chain([Route::Internal, Route::Share])
+            .chain(file_routes(Route::WebDav))
+            .chain([Route::SigningKey, Route::Asset])
+    }
+
+    pub fn operation(self) -> Option<Operation> {
+        match self {
+            Route::Api(op) | Route::WebDav(op) => Some(op),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Route::Internal => "internal",
+            Route::Share => "share",
+            Route::SigningKey => "signing-key",
+            Route::Asset => "asset",
+            Route::Api(_) => "api",
+            Route::WebDav(_) => "webdav",
+        }
+    }
+}
+
+/// Paths outside the API that the web UI answers, unless the query asks the
+/// server for something: its entry page, bundles, share viewer and its own
+/// client-side routes, which have no extension
+fn is_web_ui(path: &str, query: &str) -> bool {
+    if SERVER_QUERIES.iter().any(|v| query.contains(v)) {
+        return false;
+    }
+    path == "/"
+        || path.starts_with("/share/")
+        || path.starts_with("/assets/")
+        || path.starts_with("/chunks/")
+        || path.ends_with(".js")
+        || path.ends_with(".css")
+        || path.ends_with(".map")
+        || !path.contains('.')
+}
+
+/// What a request does to a file or folder, each with its own handler
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    /// GET or HEAD: downloads, listings, searches and the read-only queries
+    Read,
+    Options,
+    Upload,
+    /// PUT with `?unzip`
+    Unpack,
+    /// PATCH: resumed uploads, ranges and deltas
+    Edit,
+    /// POST or DELETE with `?star`
+    Star,
+    /// POST, with the action picked by its query
+    Post,
+    Delete,
+    Propfind,
+    Proppatch,
+    Mkcol,
+    Copy,
+    Move,
+    Lock,
+    Unlock,
+    CheckAuth,
+    Logout,
+    /// Any other method, answered with 405
+    Unsupported,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 18] = [
+        Operation::Read,
+        Operation::Options,
+        Operation::Upload,
+        Operation::Unpack,
+        Operation::Edit,
+        Operation::Star,
+        Operation::Post,
+        Operation::Delete,
+        Operation::Propfind,
+        Operation::Proppatch,
+        Operation::Mkcol,
+        Operation::Copy,
+        Operation::Move,
+        Operation::Lock,
+        Operation::Unlock,
+        Operation::CheckAuth,
+        Operation::Logout,
+        Operation::Unsupported,
+    ];
+
+    pub fn of(method: &Method, query_params: &HashMap<String, String>) -> Self {
+        let flag = |name| has_query_flag(query_params, name);
+        match method.as_str() {
+            "GET" | "HEAD" => Operation::Read,
+            "OPTIONS" => Operation::Options,
+            "PUT" if flag("unzip") => Operation::Unpack,
+            "PUT" => Operation::Upload,
+            "PATCH" => Operation::Edit,
+            "POST" | "DELETE" if flag("star") => Operation::Star,
+            "POST" => Operation::Post,
+            "DELETE" => Operation::Delete,
+            "PROPFIND" => Operation::Propfind,
+            "PROPPATCH" => Operation::Proppatch,
+            "MKCOL" => Operation::Mkcol,
+            "COPY" => Operation::Copy,
+            "MOVE" => Operation::Move,
+            "LOCK" => Operation::Lock,
+            "UNLOCK" => Operation::Unlock,
+            "CHECKAUTH" => Operation::CheckAuth,
+            "LOGOUT" => Operation::Logout,
+            _ => Operation::Unsupported,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Operation::Read => "read",
+            Operation::Options => "options",
+            Operation::Upload => "upload",
+            Operation::Unpack => "unpack",
+            Operation::Edit => "edit",
+            Operation::Star => "star",
+            Operation::Post => "post",
+            Operation::Delete => "delete",
+            Operation::Propfind => "propfind",
+            Operation::Proppatch => "proppatch",
+            Operation::Mkcol => "mkcol",
+            Operation::Copy => "copy",
+            Operation::Move => "move",
+            Operation::Lock => "lock",
+            Operation::Unlock => "unlock",
+            Operation::CheckAuth => "checkauth",
+            Operation::Logout => "logout",
+            Operation::Unsupported => "unsupported",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_from_parts() {
+        let route = |method: &[u8], path: &str, query: &str| {
+            Route::from_parts(&Method::from_bytes(method).unwrap(), path, query, "/")
+        };
+        assert_eq!(route(b"GET", "/__dufs__/health", ""), Route::Internal);
+        assert_eq!(route(b"POST", "/__dufs__/login", ""), Route::Internal);
+        assert_eq!(route(b"GET", "/share/abc/download", ""), Route::Share);
+        assert_eq!(route(b"GET", SIGNING_KEY_PATH, ""), Route::SigningKey);
+        assert_eq!(
+            route(b"PUT", "/api/dir/file.txt", ""),
+            Route::Api(Operation::Upload)
+        );
+        assert_eq!(
+            route(b"PUT", "/api/dir", "unzip"),
+            Route::Api(Operation::Unpack)
+        );
+        assert_eq!(
+            route(b"DELETE", "/api/file.txt", "star"),
+            Route::Api(Operation::Star)
+        );
+        assert_eq!(route(b"GET", "/", ""), Route::Asset);
+        assert_eq!(route(b"HEAD", "/assets/index.js", ""), Route::Asset);
+        assert_eq!(
+            route(b"GET", "/dir/file.txt", ""),
+            Route::WebDav(Operation::Read)
+        );
+        assert_eq!(route(b"GET", "/dir", "zip"), Route::WebDav(Operation::Read));
+        assert_eq!(
+            route(b"PUT", "/file.txt", ""),
+            Route::WebDav(Operation::Upload)
+        );
+        assert_eq!(
+            route(b"PROPFIND", "/dir", ""),
+            Route::WebDav(Operation::Propfind)
+        );
+
+        // Only the prefix itself is internal or the API, not names containing it
+        assert_eq!(
+            route(b"GET", "/api/__dufs__/health", ""),
+            Route::Api(Operation::Read)
+        );
+        assert_eq!(
+            route(b"PUT", "/my__dufs__notes.txt", ""),
+            Route::WebDav(Operation::Upload)
+        );
+        assert_eq!(
+            route(b"PUT", "/apiary.txt", ""),
+            Route::WebDav(Operation::Upload)
+        );
+
+        let prefixed = |path: &str| Route::from_parts(&Method::GET, path, "", "/xyz/");
+        assert_eq!(prefixed("/xyz/api/file.txt"), Route::Api(Operation::Read));
+        assert_eq!(prefixed("/api/file.txt"), Route::WebDav(Operation::Read));
+        assert_eq!(prefixed("/xyz/__dufs__/health"), Route::Internal);
+        assert_eq!(prefixed("/__dufs__/health"), Route::Asset);
+        let asset = format!("/{ASSETS_PREFIX}index.js");
+        assert_eq!(prefixed(&asset), Route::Asset);
+    }
+}
@@ -0,0 +1,153 @@
+use anyhow::Result;
+use hyper::header::{HeaderValue, CONTENT_TYPE, COOKIE, SET_COOKIE};
+use hyper::{HeaderMap, Method, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::net::SocketAddr;
+
+use crate::sessions::{SESSION_COOKIE, SESSION_EXPIRATION};
+use crate::utils::unix_now;
+
+use super::handlers::{Request, Server};
+use super::response_utils::{
+    set_json_response, status_bad_request, status_no_content, status_not_found,
+    status_too_many_requests, Response,
+};
+use super::user_key_handlers::read_body;
+
+pub(super) const LOGIN_PATH: &str = "__dufs__/login";
+pub(super) const LOGOUT_PATH: &str = "__dufs__/logout";
+
+#[derive(Debug, Deserialize)]
+struct LoginRequest {
+    user: String,
+    password: String,
+}
+
+impl Server {
+    /// Handle `POST /__dufs__/login`, which checks the user and password of
+    /// a JSON body or form and starts a session, and `POST /__dufs__/logout`
+    pub(super) async fn handle_session(
+        &self,
+        req_path: &str,
+        req: Request,
+        res: &mut Response,
+    ) -> Result<()> {
+        if req.method() != Method::POST {
+            *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            return Ok(());
+        }
+        if !self.args.auth.has_users() {
+            status_not_found(res);
+            return Ok(());
+        }
+        let headers = req.headers().clone();
+        if req_path == LOGOUT_PATH {
+            self.clear_session(&headers, res)?;
+            status_no_content(res);
+            return Ok(());
+        }
+
+        let ip = req.extensions().get::<SocketAddr>().map(|v| v.ip());
+        let is_form = headers.get(CONTENT_TYPE).is_some_and(|v| {
+            v.as_bytes()
+                .starts_with(b"application/x-www-form-urlencoded")
+        });
+        let Some(body) = read_body(req, res).await else {
+            return Ok(());
+        };
+        let login = match is_form {
+            true => {
+                let field = |name: &str| {
+                    form_urlencoded::parse(&body)
+                        .find(|(k, _)| k == name)
+                        .map(|(_, v)| v.into_owned())
+                };
+                field("user")
+                    .zip(field("password"))
+                    .map(|(user, password)| LoginRequest { user, password })
+            }
+            false => serde_json::from_slice::<LoginRequest>(&body).ok(),
+        };
+        let Some(LoginRequest { user, password }) = login else {
+            status_bad_request(res, "Invalid login, expected user and password");
+            return Ok(());
+        };
+
+        if let Some(retry_after) = self.logins.retry_after(ip, &user) {
+            status_too_many_requests(res, retry_after);
+            return Ok(());
+        }
+        let success = self.args.auth.check_password(&user, &password);
+        self.audit_login(ip, &user, LOGIN_PATH, success);
+        if !success {
+            // No WWW-Authenticate, so browsers don't show their own prompt
+            match self.logins.retry_after(ip, &user) {
+                Some(retry_after) => status_too_many_requests(res, retry_after),
+                None => *res.status_mut() = StatusCode::UNAUTHORIZED,
+            }
+            return Ok(());
+        }
+
+        let cookie = self.session_cookie(&headers, &self.sessions.value(&user), SESSION_EXPIRATION);
+        res.headers_mut().insert(SET_COOKIE, cookie.parse()?);
+        set_json_response(
+            res,
+            json!({
+                "user": user,
+                "expires_at": unix_now().as_secs() + SESSION_EXPIRATION,
+            })
+            .to_string(),
+        );
+        Ok(())
+    }
+
+    /// User of a valid session cookie
+    pub(super) fn session_user(&self, headers: &HeaderMap<HeaderValue>) -> Option<String> {
+        headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(';'))
+            .filter_map(|v| v.trim().split_once('='))
+            .filter(|(name, _)| *name == SESSION_COOKIE)
+            .find_map(|(_, value)| self.sessions.user(value))
+    }
+
+    /// Expire the session cookie, if there are users to log in as
+    pub(super) fn clear_session(
+        &self,
+        headers: &HeaderMap<HeaderValue>,
+        res: &mut Response,
+    ) -> Result<()> {
+        if self.args.auth.has_users() {
+            let cookie = self.session_cookie(headers, "", 0);
+            res.headers_mut().insert(SET_COOKIE, cookie.parse()?);
+        }
+        Ok(())
+    }
+
+    pub(super) fn session_cookie(
+        &self,
+        headers: &HeaderMap<HeaderValue>,
+        value: &str,
+        max_age: u64,
+    ) -> String {
+        let secure = if self.is_https(headers) {
+            "; Secure"
+        } else {
+            ""
+        };
+        format!(
+            "{SESSION_COOKIE}={value}; Path={}; Max-Age={max_age}; HttpOnly; SameSite=Lax{secure}",
+            self.args.uri_prefix
+        )
+    }
+
+    pub(super) fn is_https(&self, headers: &HeaderMap<HeaderValue>) -> bool {
+        self.args.tls_cert.is_some()
+            || headers
+                .get("x-forwarded-proto")
+                .is_some_and(|v| v.as_bytes() == b"https")
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::Result;
+use hyper::{Method, StatusCode};
+use serde::Serialize;
+use serde_json::json;
+use std::path::Path;
+
+use crate::provenance::ShareInfo;
+
+use super::handlers::{Request, Server};
+use super::response_utils::{set_json_response, status_no_content, status_not_found, Response};
+
+pub(super) const SHARES_PATH: &str = "__dufs__/shares";
+
+#[derive(Debug, Serialize)]
+struct ShareEntry {
+    #[serde(flatten)]
+    share: ShareInfo,
+    /// The shared file, relative to the serve path
+    path: String,
+    downloads: u64,
+}
+
+impl Server {
+    /// Handle share management (/__dufs__/shares), for admins
+    ///
+    /// GET lists the active shares of every file with their owner and
+    /// download count, `DELETE /__dufs__/shares/<id>` revokes a share and
+    /// `GET /__dufs__/shares/<id>/downloads` lists who downloaded it.
+    pub(super) async fn handle_shares(
+        &self,
+        req_path: &str,
+        req: &Request,
+        res: &mut Response,
+    ) -> Result<()> {
+        let sub_path = req_path
+            .strip_prefix(SHARES_PATH)
+            .unwrap_or_default()
+            .trim_matches('/');
+        let (id, action) = sub_path.split_once('/').unwrap_or((sub_path, ""));
+
+        match (req.method().clone(), id, action) {
+            (Method::GET, "", "") => {
+                let shares: Vec<_> = self
+                    .provenance_db
+                    .list_active_shares()?
+                    .into_iter()
+                    .map(|(share, downloads)| ShareEntry {
+                        path: self.relative_path(Path::new(&share.file_path)),
+                        share,
+                        downloads,
+                    })
+                    .collect();
+                set_json_response(res, json!({ "shares": shares }).to_string());
+            }
+            (Method::DELETE, id, "") if !id.is_empty() => {
+                match self.provenance_db.get_share(id)? {
+                    Some(share) if share.is_active => {
+                        self.provenance_db.deactivate_share(id)?;
+                        // The file goes back to private once its last share is gone
+                        let _ = self.provenance_db.update_file_visibility(&share.file_path);
+                        status_no_content(res);
+                    }
+                    _ => status_not_found(res),
+                }
+            }
+            (Method::GET, id, "downloads") if !id.is_empty() => {
+                if self.provenance_db.get_share(id)?.is_none() {
+                    status_not_found(res);
+                    return Ok(());
+                }
+                let downloads = self.provenance_db.get_distribution_chain(id)?;
+                set_json_response(
+                    res,
+                    json!({ "share_id": id, "downloads": downloads }).to_string(),
+                );
+            }
+            (_, _, "") | (_, _, "downloads") => *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED,
+            _ => status_not_found(res),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+use anyhow::Result;
+
+use super::handlers::Server;
+
+impl Server {
+    /// End the change streams, they would otherwise keep their connections
+    /// open for as long as the client listens
+    pub fn close_event_streams(&self) {
+        self.events.close();
+    }
+
+    /// Flush provenance writes of this server and every opened tenant to disk
+    ///
+    /// Mount servers share the database of the root server.
+    pub fn flush_provenance(&self) -> Result<()> {
+        self.provenance_db.checkpoint()?;
+        if let Some(tenants) = &self.tenants {
+            for server in tenants.opened() {
+                server.provenance_db.checkpoint()?;
+            }
+        }
+        Ok(())
+    }
+}
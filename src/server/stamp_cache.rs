@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long an entry is served before it is read from the database again
+const STAMP_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// In-memory cache of stamping state by path, the stamp status of files or
+/// the summary of listed directories
+#[derive(Debug, Clone)]
+pub struct StampCache<V> {
+    entries: Arc<Mutex<HashMap<PathBuf, (Instant, V)>>>,
+}
+
+impl<V> Default for StampCache<V> {
+    fn default() -> Self {
+        Self {
+            entries: Arc::default(),
+        }
+    }
+}
+
+impl<V: Clone> StampCache<V> {
+    pub fn get(&self, path: &Path) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+        let (cached_at, value) = entries.get(path)?;
+        (cached_at.elapsed() < STAMP_CACHE_TTL).then(|| value.clone())
+    }
+
+    pub fn insert(&self, path: &Path, value: V) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (cached_at, _)| cached_at.elapsed() < STAMP_CACHE_TTL);
+        entries.insert(path.to_path_buf(), (Instant::now(), value));
+    }
+
+    pub fn remove(&self, path: &Path) {
+        self.entries.lock().unwrap().remove(path);
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::Result;
+use headers::{ContentLength, ContentType, HeaderMapExt};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::chunk_store::{ChunkStore, DedupUsage};
+use crate::http_utils::body_full;
+use crate::provenance::{ProvenanceDb, TrafficBucket};
+
+use super::metadata_handlers::dir_prefix;
+use super::response_utils::{normalize_path, status_bad_request, Response};
+
+const DEFAULT_WINDOW_DAYS: i64 = 30;
+const MAX_WINDOW_DAYS: i64 = 366;
+const DEFAULT_TOP_FILES: usize = 10;
+const MAX_TOP_FILES: usize = 100;
+
+#[derive(Debug, Serialize)]
+struct Stats {
+    storage: Vec<DirUsage>,
+    traffic: Traffic,
+    top_files: Vec<TopFile>,
+    active_shares: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dedup: Option<DedupUsage>,
+}
+
+/// Bytes stored under one top-level directory, `/` holds files at the root
+#[derive(Debug, Default, Serialize)]
+struct DirUsage {
+    path: String,
+    bytes: u64,
+    files: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct Traffic {
+    bucket: &'static str,
+    since: i64,
+    buckets: Vec<TrafficBucket>,
+}
+
+#[derive(Debug, Serialize)]
+struct TopFile {
+    path: String,
+    uploaded: u64,
+    downloaded: u64,
+    downloads: u64,
+}
+
+/// Handle usage statistics (GET /__dufs__/stats)
+///
+/// Accepts `bucket=hour|day|week`, `days=<window>` and `top=<count>`.
+pub async fn handle_stats(
+    serve_path: &Path,
+    query_params: &HashMap<String, String>,
+    head_only: bool,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let (bucket, bucket_secs) = match query_params.get("bucket").map(|v| v.as_str()) {
+        Some("hour") => ("hour", 3600),
+        None | Some("day") => ("day", 86400),
+        Some("week") => ("week", 7 * 86400),
+        Some(_) => {
+            status_bad_request(res, "bucket must be one of hour, day or week");
+            return Ok(());
+        }
+    };
+    let days = match query_params.get("days").map(|v| v.parse::<i64>()) {
+        None => DEFAULT_WINDOW_DAYS,
+        Some(Ok(v)) if (1..=MAX_WINDOW_DAYS).contains(&v) => v,
+        Some(_) => {
+            status_bad_request(
+                res,
+                &format!("days must be between 1 and {MAX_WINDOW_DAYS}"),
+            );
+            return Ok(());
+        }
+    };
+    let top = match query_params.get("top").map(|v| v.parse::<usize>()) {
+        None => DEFAULT_TOP_FILES,
+        Some(Ok(v)) if v <= MAX_TOP_FILES => v,
+        Some(_) => {
+            status_bad_request(res, &format!("top must be at most {MAX_TOP_FILES}"));
+            return Ok(());
+        }
+    };
+
+    let since = chrono::Utc::now().timestamp() - days * 86400;
+    let walk_path = serve_path.to_path_buf();
+    let storage = tokio::task::spawn_blocking(move || storage_by_dir(&walk_path)).await?;
+    let dedup = match ChunkStore::get() {
+        Some(store) => Some(tokio::task::spawn_blocking(|| store.usage()).await??),
+        None => None,
+    };
+    let top_files = provenance_db
+        .top_files_by_traffic(&dir_prefix(serve_path)?, since, top)?
+        .into_iter()
+        .filter_map(|v| {
+            let rel = Path::new(&v.file_path).strip_prefix(serve_path).ok()?;
+            Some(TopFile {
+                path: format!("/{}", normalize_path(rel)),
+                uploaded: v.uploaded,
+                downloaded: v.downloaded,
+                downloads: v.downloads,
+            })
+        })
+        .collect();
+
+    let stats = Stats {
+        storage,
+        traffic: Traffic {
+            bucket,
+            since,
+            buckets: provenance_db.traffic_buckets(since, bucket_secs)?,
+        },
+        top_files,
+        active_shares: provenance_db.count_active_shares()?,
+        dedup,
+    };
+    let json = serde_json::to_string_pretty(&stats)?;
+    res.headers_mut()
+        .typed_insert(ContentType::from(mime_guess::mime::APPLICATION_JSON));
+    res.headers_mut()
+        .typed_insert(ContentLength(json.len() as u64));
+    if !head_only {
+        *res.body_mut() = body_full(json);
+    }
+    Ok(())
+}
+
+fn storage_by_dir(serve_path: &Path) -> Vec<DirUsage> {
+    let mut usage: BTreeMap<String, DirUsage> = BTreeMap::new();
+    for entry in WalkDir::new(serve_path).into_iter().filter_map(|v| v.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(rel) = entry.path().strip_prefix(serve_path) else {
+            continue;
+        };
+        let mut components = rel.components();
+        let top_level = match (components.next(), components.next()) {
+            (Some(dir), Some(_)) => format!("/{}", dir.as_os_str().to_string_lossy()),
+            _ => "/".to_string(),
+        };
+        let item = usage.entry(top_level.clone()).or_insert_with(|| DirUsage {
+            path: top_level,
+            ..Default::default()
+        });
+        item.bytes += entry.metadata().map(|v| v.len()).unwrap_or_default();
+        item.files += 1;
+    }
+    usage.into_values().collect()
+}
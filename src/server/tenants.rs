@@ -0,0 +1,131 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::provenance::ProvenanceDb;
+use crate::utils::decode_uri;
+
+use super::handlers::{Request, Server};
+use super::provenance_dav::PROVENANCE_DIR;
+
+/// Every top-level directory of the serve path is a tenant with its own
+/// provenance database (`<db_dir>/<name>.db`), and with it its own shares,
+/// annotations and signing keypair. Tenant servers are opened on first use.
+pub struct Tenants {
+    db_dir: PathBuf,
+    servers: Mutex<HashMap<String, Arc<Server>>>,
+}
+
+impl Tenants {
+    pub fn new(db_dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(db_dir)?;
+        Ok(Self {
+            db_dir: db_dir.to_path_buf(),
+            servers: Default::default(),
+        })
+    }
+
+    fn get(&self, root: &Server, name: &str) -> Result<Arc<Server>> {
+        let mut servers = self.servers.lock().unwrap();
+        if let Some(server) = servers.get(name) {
+            return Ok(server.clone());
+        }
+        let db = ProvenanceDb::new(
+            self.db_dir.join(format!("{name}.db")),
+            &root.args.provenance_db_encryption,
+        )?;
+        let server = Arc::new(root.for_tenant(root.args.serve_path.join(name), db)?);
+        servers.insert(name.to_string(), server.clone());
+        Ok(server)
+    }
+
+    /// Tenant servers opened so far
+    pub(super) fn opened(&self) -> Vec<Arc<Server>> {
+        self.servers.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Share links don't name their tenant, ask each one for the share
+    fn find_share(&self, root: &Server, share_id: &str) -> Result<Option<Arc<Server>>> {
+        for name in tenant_names(&root.args.serve_path) {
+            let server = self.get(root, &name)?;
+            if server.provenance_db.get_share(share_id)?.is_some() {
+                return Ok(Some(server));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Server {
+    /// The tenant server that should handle `req`, None for the root server
+    pub(super) fn route_tenant(&self, req: &Request) -> Result<Option<Arc<Server>>> {
+        let Some(tenants) = &self.tenants else {
+            return Ok(None);
+        };
+        let uri_path = req.uri().path();
+        if let Some(share_path) = uri_path.strip_prefix("/share/") {
+            let share_id = share_path.split('/').next().unwrap_or_default();
+            return tenants.find_share(self, share_id);
+        }
+        let Some(rest) = uri_path.strip_prefix(self.args.uri_prefix.as_str()) else {
+            return Ok(None);
+        };
+        let rest = rest.strip_prefix("api/").unwrap_or(rest);
+        let rest = rest
+            .strip_prefix(PROVENANCE_DIR)
+            .and_then(|v| v.strip_prefix('/'))
+            .unwrap_or(rest);
+        let Some(name) = rest.split('/').next().and_then(decode_uri) else {
+            return Ok(None);
+        };
+        if name.is_empty()
+            || name.starts_with("__dufs")
+            || name.contains(['/', '\\'])
+            || matches!(name.as_ref(), "." | "..")
+            || !self.args.serve_path.join(name.as_ref()).is_dir()
+        {
+            return Ok(None);
+        }
+        tenants.get(self, &name).map(Some)
+    }
+
+    /// The database with the records of `path`, its tenant's in multi-tenant mode
+    pub(super) fn provenance_db_for(&self, path: &Path) -> Result<ProvenanceDb> {
+        let Some(tenants) = &self.tenants else {
+            return Ok(self.provenance_db.clone());
+        };
+        let mut components = path
+            .strip_prefix(&self.args.serve_path)
+            .map(|v| v.components())
+            .into_iter()
+            .flatten();
+        match (components.next(), components.next()) {
+            (Some(name), Some(_)) => match name.as_os_str().to_str() {
+                Some(name) => Ok(tenants.get(self, name)?.provenance_db.clone()),
+                None => Ok(self.provenance_db.clone()),
+            },
+            _ => Ok(self.provenance_db.clone()),
+        }
+    }
+
+    /// Whether moving or copying to `dest` stays inside the current tenant
+    pub(super) fn is_same_tenant(&self, dest: &Path) -> bool {
+        match (&self.tenant_root, &self.tenants) {
+            (Some(root), _) => dest.starts_with(root),
+            (None, Some(_)) => dest.parent() == Some(self.args.serve_path.as_path()),
+            (None, None) => true,
+        }
+    }
+}
+
+fn tenant_names(serve_path: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(serve_path) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|v| v.ok())
+        .filter(|v| v.file_type().map(|v| v.is_dir()).unwrap_or_default())
+        .filter_map(|v| v.file_name().into_string().ok())
+        .collect()
+}
@@ -0,0 +1,189 @@
+use anyhow::Result;
+use hyper::{
+    header::{HeaderValue, AUTHORIZATION},
+    Method, StatusCode,
+};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::auth::AccessPaths;
+use crate::provenance::ApiToken;
+use crate::retention::parse_age;
+use crate::utils::unix_now;
+
+use super::handlers::{Request, Server};
+use super::response_utils::{
+    set_json_response, status_bad_request, status_forbid, status_no_content, status_not_found,
+    Response,
+};
+use super::user_key_handlers::read_body;
+
+pub(super) const TOKENS_PATH: &str = "__dufs__/tokens";
+
+#[derive(Debug, Deserialize)]
+struct TokenRequest {
+    name: Option<String>,
+    /// Paths in the `--auth` syntax, read-only access to everything by default
+    #[serde(default = "default_scopes")]
+    scopes: Vec<String>,
+    /// Lifetime such as `30d` or `12h`, tokens don't expire without it
+    expires_in: Option<String>,
+}
+
+fn default_scopes() -> Vec<String> {
+    vec!["/".into()]
+}
+
+/// API token sent with a request, as `Authorization: Bearer <token>` or as
+/// `?token=<token>` on GET requests (where `?tokengen` links also go)
+pub(super) fn api_token_credential<'a>(
+    authorization: Option<&'a HeaderValue>,
+    method: &Method,
+    token: Option<&'a String>,
+) -> Option<&'a str> {
+    if let Some(authorization) = authorization {
+        return authorization.to_str().ok()?.strip_prefix("Bearer ");
+    }
+    token
+        .filter(|v| method == Method::GET && v.contains('.'))
+        .map(|v| v.as_str())
+}
+
+impl Server {
+    /// Access granted by an API token: the scopes of the token, within the
+    /// rules of the user who created it
+    pub(super) fn guard_api_token(
+        &self,
+        token: &str,
+        path: &str,
+        perm_method: &Method,
+    ) -> (Option<String>, Option<AccessPaths>) {
+        match self.verify_api_token(token) {
+            Ok(Some((user, scopes))) => {
+                self.args
+                    .auth
+                    .guard_scoped(&user, &scopes, path, perm_method)
+            }
+            Ok(None) => (None, None),
+            Err(err) => {
+                warn!("Failed to check API token, {err}");
+                (None, None)
+            }
+        }
+    }
+
+    fn verify_api_token(&self, token: &str) -> Result<Option<(String, AccessPaths)>> {
+        let Some((id, secret)) = token.split_once('.') else {
+            return Ok(None);
+        };
+        let Some((token, secret_sha256_hex)) = self.provenance_db.get_api_token(id)? else {
+            return Ok(None);
+        };
+        if hex::encode(Sha256::digest(secret.as_bytes())) != secret_sha256_hex {
+            return Ok(None);
+        }
+        if token.expires_at.is_some_and(|v| v <= unix_now().as_secs()) {
+            return Ok(None);
+        }
+        let mut scopes = AccessPaths::default();
+        if scopes.merge(&token.scopes).is_none() {
+            return Ok(None);
+        }
+        // Scopes name paths from the top, mount servers check what is below their mount
+        let scopes = match &self.mount_name {
+            Some(name) => scopes.find(name).unwrap_or_default(),
+            None => scopes,
+        };
+        Ok(Some((token.user, scopes)))
+    }
+
+    /// Handle API tokens (/__dufs__/tokens)
+    ///
+    /// GET lists the user's tokens, POST creates one and returns its secret
+    /// (only this once), and `DELETE /__dufs__/tokens/<id>` revokes a token
+    /// of the user, or any token for users with read-write access to `/`.
+    /// Tokens can't be used to manage tokens.
+    pub(super) async fn handle_api_tokens(
+        &self,
+        req_path: &str,
+        req: Request,
+        res: &mut Response,
+    ) -> Result<()> {
+        let method = req.method().clone();
+        let headers = req.headers();
+        if api_token_credential(headers.get(AUTHORIZATION), &method, None).is_some() {
+            status_forbid(res);
+            return Ok(());
+        }
+        let (user, access_paths) = self.guard_as("/", &method, &req, None, false);
+        let Some(user) = user else {
+            self.auth_reject(res)?;
+            return Ok(());
+        };
+        let id = req_path
+            .strip_prefix(TOKENS_PATH)
+            .unwrap_or_default()
+            .trim_matches('/');
+
+        match (method, id) {
+            (Method::GET, "") => {
+                let tokens = self.provenance_db.list_api_tokens(&user)?;
+                set_json_response(res, json!({ "tokens": tokens }).to_string());
+            }
+            (Method::POST, "") => {
+                let Some(body) = read_body(req, res).await else {
+                    return Ok(());
+                };
+                let request = match serde_json::from_slice::<TokenRequest>(&body) {
+                    Ok(v) => v,
+                    Err(err) => {
+                        status_bad_request(res, &format!("Invalid token request, {err}"));
+                        return Ok(());
+                    }
+                };
+                let scopes = request.scopes.join(",");
+                if request.scopes.is_empty() || AccessPaths::default().merge(&scopes).is_none() {
+                    status_bad_request(res, "Invalid scopes, e.g. [\"/docs:rw\", \"/public\"]");
+                    return Ok(());
+                }
+                let expires_at = match request.expires_in.as_deref().map(parse_age) {
+                    None => None,
+                    Some(Some(age)) => Some(unix_now().as_secs() + age.as_secs()),
+                    Some(None) => {
+                        status_bad_request(res, "Invalid expires_in, e.g. 30d or 12h");
+                        return Ok(());
+                    }
+                };
+
+                let secret = hex::encode(rand::random::<[u8; 24]>());
+                let token = ApiToken {
+                    id: hex::encode(rand::random::<[u8; 8]>()),
+                    user,
+                    name: request.name,
+                    scopes,
+                    created_at: chrono::Utc::now().to_rfc3339(),
+                    expires_at,
+                };
+                self.provenance_db
+                    .create_api_token(&token, &hex::encode(Sha256::digest(secret.as_bytes())))?;
+                let mut output = serde_json::to_value(&token)?;
+                output["token"] = format!("{}.{secret}", token.id).into();
+                set_json_response(res, output.to_string());
+                *res.status_mut() = StatusCode::CREATED;
+            }
+            (Method::DELETE, id) if !id.is_empty() => {
+                let is_admin = access_paths.is_some_and(|v| v.perm().readwrite());
+                match self.provenance_db.get_api_token(id)? {
+                    Some((token, _)) if token.user == user || is_admin => {
+                        self.provenance_db.delete_api_token(id)?;
+                        status_no_content(res);
+                    }
+                    _ => status_not_found(res),
+                }
+            }
+            _ => *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED,
+        }
+        Ok(())
+    }
+}
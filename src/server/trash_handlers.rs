@@ -0,0 +1,101 @@
+use anyhow::Result;
+use hyper::{Method, StatusCode};
+use serde_json::json;
+use std::collections::HashMap;
+
+use crate::retention::parse_age;
+use crate::trash::TrashItem;
+
+use super::handlers::{Request, Server};
+use super::response_utils::{
+    set_json_response, status_bad_request, status_conflict, status_not_found, Response,
+};
+
+impl Server {
+    /// Handle the recycle bin (/__dufs__/trash)
+    ///
+    /// GET lists items, `POST ?restore=<id>` moves one back to where it was
+    /// deleted from, and DELETE purges `?id=<id>`, items deleted more than
+    /// `?older_than=<age>` ago, or everything.
+    pub(super) async fn handle_trash(&self, req: &Request, res: &mut Response) -> Result<()> {
+        let Some(trash) = &self.trash else {
+            status_not_found(res);
+            return Ok(());
+        };
+        let query = req.uri().query().unwrap_or_default();
+        let query_params: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        match *req.method() {
+            Method::GET => {
+                let items: Vec<_> = trash
+                    .list()
+                    .await?
+                    .iter()
+                    .map(|item| self.trash_item_json(item))
+                    .collect();
+                set_json_response(res, json!({ "items": items }).to_string());
+            }
+            Method::POST => {
+                let Some(id) = query_params.get("restore") else {
+                    status_bad_request(res, "Missing `restore=<id>`");
+                    return Ok(());
+                };
+                let Some(item) = trash.get(id).await? else {
+                    status_not_found(res);
+                    return Ok(());
+                };
+                if tokio::fs::symlink_metadata(&item.original_path)
+                    .await
+                    .is_ok()
+                {
+                    status_conflict(res, "A file already exists at the original path");
+                    return Ok(());
+                }
+                trash.restore(&item).await?;
+                let trashed_path = trash.item_path(&item.id);
+                if let (Some(old_path), Some(new_path)) =
+                    (trashed_path.to_str(), item.original_path.to_str())
+                {
+                    self.provenance_db_for(&item.original_path)?
+                        .update_artifact_path(old_path, new_path)?;
+                }
+                set_json_response(res, self.trash_item_json(&item).to_string());
+            }
+            Method::DELETE => {
+                let purged = if let Some(id) = query_params.get("id") {
+                    let Some(item) = trash.get(id).await? else {
+                        status_not_found(res);
+                        return Ok(());
+                    };
+                    trash.remove(&item).await?;
+                    1
+                } else if let Some(age) = query_params.get("older_than") {
+                    let Some(age) = parse_age(age) else {
+                        status_bad_request(res, "Invalid `older_than`, e.g. 12h, 30d or 2w");
+                        return Ok(());
+                    };
+                    trash.purge(Some(age)).await?
+                } else {
+                    trash.purge(None).await?
+                };
+                set_json_response(res, json!({ "purged": purged }).to_string());
+            }
+            _ => *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED,
+        }
+        Ok(())
+    }
+
+    /// An item as listed to clients, with its path relative to the serve root
+    fn trash_item_json(&self, item: &TrashItem) -> serde_json::Value {
+        json!({
+            "id": item.id,
+            "path": self.relative_path(&item.original_path),
+            "is_dir": item.is_dir,
+            "size": item.size,
+            "deleted_at": item.deleted_at,
+            "deleted_by": item.deleted_by,
+        })
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use crate::auth::AccessPaths;
+use crate::http_utils::body_full;
+use crate::utils::get_file_name;
+
+use super::handlers::{is_hidden, Server};
+use super::response_utils::{set_json_response, status_bad_request, Response};
+use super::visibility_handlers::PublicOnly;
+
+/// Deepest tree served in one request
+const MAX_TREE_DEPTH: usize = 8;
+
+/// A folder of `?tree`, with the folders below it while the depth lasts
+#[derive(Debug, Serialize)]
+struct TreeNode {
+    name: String,
+    path: String,
+    /// Visible subfolders and files, counted even past the requested depth
+    dirs: usize,
+    files: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    children: Option<Vec<TreeNode>>,
+}
+
+impl Server {
+    /// Handle `GET /<dir>/?tree&depth=N`: the folders below `dir`, N levels
+    /// deep (1 by default), with the count of their entries but no listing,
+    /// so a tree view can expand lazily
+    pub(super) async fn handle_tree(
+        &self,
+        path: &Path,
+        query_params: &HashMap<String, String>,
+        head_only: bool,
+        access_paths: AccessPaths,
+        public_only: Option<&PublicOnly>,
+        res: &mut Response,
+    ) -> Result<()> {
+        let depth = match query_params.get("depth").map(|v| v.parse::<usize>()) {
+            None => 1,
+            Some(Ok(depth)) if depth <= MAX_TREE_DEPTH => depth,
+            Some(_) => {
+                let msg = format!("depth must be a number up to {MAX_TREE_DEPTH}");
+                status_bad_request(res, &msg);
+                return Ok(());
+            }
+        };
+        let tree = self
+            .tree_node(path.to_path_buf(), access_paths, public_only, depth)
+            .await?;
+        set_json_response(res, serde_json::to_string(&tree)?);
+        if head_only {
+            *res.body_mut() = body_full("");
+        }
+        Ok(())
+    }
+
+    fn tree_node<'a>(
+        &'a self,
+        path: PathBuf,
+        access_paths: AccessPaths,
+        public_only: Option<&'a PublicOnly>,
+        depth: usize,
+    ) -> BoxFuture<'a, Result<TreeNode>> {
+        Box::pin(async move {
+            let entries = self.tree_entries(&path, &access_paths, public_only).await?;
+            let dirs = entries.iter().filter(|(_, is_dir, _)| *is_dir).count();
+            let mut node = TreeNode {
+                name: get_file_name(&path).to_string(),
+                path: self.relative_path(&path),
+                dirs,
+                files: entries.len() - dirs,
+                children: None,
+            };
+            if depth > 0 {
+                let mut children = vec![];
+                for (child, is_dir, child_access) in entries {
+                    if is_dir {
+                        children.push(
+                            self.tree_node(child, child_access, public_only, depth - 1)
+                                .await?,
+                        );
+                    }
+                }
+                node.children = Some(children);
+            }
+            Ok(node)
+        })
+    }
+
+    /// Entries of `dir` a listing would show, sorted by name, with whether
+    /// they are folders and the access to them
+    async fn tree_entries(
+        &self,
+        dir: &Path,
+        access_paths: &AccessPaths,
+        public_only: Option<&PublicOnly>,
+    ) -> Result<Vec<(PathBuf, bool, AccessPaths)>> {
+        let mut candidates = vec![];
+        if access_paths.perm().indexonly() {
+            for name in access_paths.child_names() {
+                candidates.push(dir.join(name));
+            }
+        } else {
+            let mut rd = fs::read_dir(dir).await?;
+            while let Some(entry) = rd.next_entry().await? {
+                candidates.push(entry.path());
+            }
+        }
+
+        let mut entries = vec![];
+        for path in candidates {
+            let name = get_file_name(&path);
+            let (Ok(meta), Ok(link_meta)) =
+                tokio::join!(fs::metadata(&path), fs::symlink_metadata(&path))
+            else {
+                continue;
+            };
+            if is_hidden(&self.args.hidden, name, meta.is_dir())
+                || (!self.args.allow_symlink
+                    && link_meta.is_symlink()
+                    && !self.is_root_contained(&path).await)
+                || public_only.is_some_and(|v| v.is_private(&path))
+            {
+                continue;
+            }
+            let Some(child_access) = access_paths.find(name) else {
+                continue;
+            };
+            entries.push((path, meta.is_dir(), child_access));
+        }
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(entries)
+    }
+}
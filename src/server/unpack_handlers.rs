@@ -0,0 +1,419 @@
+use anyhow::Result;
+use async_compression::tokio::bufread::GzipDecoder;
+use async_zip::tokio::read::seek::ZipFileReader;
+use futures_util::{StreamExt, TryStreamExt};
+use hyper::StatusCode;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::{Component, Path, PathBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader};
+use tokio::{fs, io};
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+use tokio_util::io::{InspectReader, StreamReader};
+use uuid::Uuid;
+
+use crate::chunk_store::ChunkStore;
+use crate::events::ChangeKind;
+use crate::http_utils::IncomingStream;
+use crate::utils::get_file_name;
+
+use super::handlers::{ensure_path_parent, Request, Server};
+use super::response_utils::{
+    set_json_response, status_bad_request, status_payload_too_large, Response,
+};
+
+/// Unix file type bits of a zip entry's permissions, and the value for links
+const S_IFMT: u16 = 0o170000;
+const S_IFLNK: u16 = 0o120000;
+
+#[derive(Debug, Clone, Copy)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+#[derive(Debug)]
+enum EntryKind {
+    File,
+    Dir,
+    /// Links and special files, which are never extracted
+    Other,
+}
+
+#[derive(Debug)]
+struct ArchiveEntry {
+    name: String,
+    kind: EntryKind,
+    size: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct UnpackResponse {
+    path: String,
+    files: Vec<UnpackedFile>,
+    /// Entries left out: links, special files and files that already exist
+    skipped: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct UnpackedFile {
+    path: String,
+    size: u64,
+    sha256: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event_hash: Option<String>,
+}
+
+impl Server {
+    /// Handle `PUT /<dir>/?unzip`: expand a zip, tar or tar.gz body into `dir`
+    ///
+    /// The body is spooled to a temporary file and checked before anything is
+    /// written: every entry must stay inside the folder, be a type of file
+    /// that may be uploaded and the content must fit the quota. Links and
+    /// special files are skipped, and so are files that would replace
+    /// existing ones unless `overwrite`. Each extracted file is written like
+    /// an upload, under its write lock and renamed into place once complete,
+    /// then minted.
+    pub(super) async fn handle_unpack(
+        &self,
+        dir: &Path,
+        user: Option<&str>,
+        overwrite: bool,
+        req: Request,
+        res: &mut Response,
+    ) -> Result<()> {
+        let spool_path = std::env::temp_dir().join(format!("node-drive-unpack-{}", Uuid::new_v4()));
+        let ret = self
+            .unpack(dir, &spool_path, user, overwrite, req, res)
+            .await;
+        let _ = fs::remove_file(&spool_path).await;
+        ret
+    }
+
+    async fn unpack(
+        &self,
+        dir: &Path,
+        spool_path: &Path,
+        user: Option<&str>,
+        overwrite: bool,
+        req: Request,
+        res: &mut Response,
+    ) -> Result<()> {
+        let body = IncomingStream::new(req.into_body()).map_err(io::Error::other);
+        let mut spool = fs::File::create(spool_path).await?;
+        let max_size = self.args.max_upload_size;
+        let limit = max_size.map_or(u64::MAX, |v| v.saturating_add(1));
+        let spooled = io::copy(&mut StreamReader::new(body).take(limit), &mut spool).await?;
+        drop(spool);
+        if let Some(max_size) = max_size.filter(|v| spooled > *v) {
+            status_payload_too_large(res, max_size);
+            return Ok(());
+        }
+
+        let Some(kind) = detect_kind(spool_path).await? else {
+            status_bad_request(res, "The body is not a zip, tar or tar.gz archive");
+            return Ok(());
+        };
+        let entries = match list_entries(spool_path, kind).await {
+            Ok(v) => v,
+            Err(_) => {
+                status_bad_request(res, "Unreadable archive");
+                return Ok(());
+            }
+        };
+        let mut total_size = 0;
+        for entry in &entries {
+            if entry_path(&entry.name).is_none() {
+                status_bad_request(res, &format!("Unsafe path `{}` in the archive", entry.name));
+                return Ok(());
+            }
+            if matches!(entry.kind, EntryKind::File) {
+                if self.reject_upload_type(Path::new(&entry.name), Some(entry.size), res) {
+                    return Ok(());
+                }
+                total_size += entry.size;
+            }
+        }
+        if self
+            .reject_over_quota(dir, user, total_size, None, res)
+            .await?
+        {
+            return Ok(());
+        }
+
+        fs::create_dir_all(dir).await?;
+        let root = fs::canonicalize(dir).await?;
+        let mut output = UnpackResponse {
+            path: self.relative_path(dir),
+            files: vec![],
+            skipped: vec![],
+        };
+        *res.status_mut() = StatusCode::CREATED;
+        match kind {
+            ArchiveKind::Zip => {
+                let mut zip =
+                    ZipFileReader::with_tokio(BufReader::new(fs::File::open(spool_path).await?))
+                        .await?;
+                for (index, entry) in entries.iter().enumerate() {
+                    let reader = zip.reader_without_entry(index).await?.compat();
+                    self.extract_entry(
+                        dir,
+                        &root,
+                        entry,
+                        reader,
+                        user,
+                        overwrite,
+                        &mut output,
+                        res,
+                    )
+                    .await?;
+                }
+            }
+            ArchiveKind::Tar | ArchiveKind::TarGz => {
+                let mut archive = tokio_tar::Archive::new(open_tar(spool_path, kind).await?);
+                let mut tar_entries = archive.entries()?;
+                for entry in &entries {
+                    let Some(reader) = tar_entries.next().await.transpose()? else {
+                        break;
+                    };
+                    self.extract_entry(
+                        dir,
+                        &root,
+                        entry,
+                        reader,
+                        user,
+                        overwrite,
+                        &mut output,
+                        res,
+                    )
+                    .await?;
+                }
+            }
+        }
+
+        set_json_response(res, serde_json::to_string(&output)?);
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn extract_entry<R: AsyncRead + Unpin>(
+        &self,
+        dir: &Path,
+        root: &Path,
+        entry: &ArchiveEntry,
+        reader: R,
+        user: Option<&str>,
+        overwrite: bool,
+        output: &mut UnpackResponse,
+        res: &mut Response,
+    ) -> Result<()> {
+        let Some(relative_path) = entry_path(&entry.name) else {
+            return Ok(());
+        };
+        if relative_path.as_os_str().is_empty() {
+            return Ok(());
+        }
+        let path = dir.join(&relative_path);
+        // Uploads to the same file wait for each other, and so do entries
+        let _write_lock = match entry.kind {
+            EntryKind::File => Some(self.write_locks.lock(&path).await),
+            _ => None,
+        };
+        let existing = fs::symlink_metadata(&path).await.ok();
+        // Links already in the folder must not lead the entry outside of it
+        let skip = match entry.kind {
+            EntryKind::Other => true,
+            EntryKind::Dir => existing.as_ref().is_some_and(|v| !v.is_dir()),
+            EntryKind::File => existing.as_ref().is_some_and(|v| v.is_dir() || !overwrite),
+        } || !is_contained(root, &path).await;
+        if skip {
+            output.skipped.push(self.relative_path(&path));
+            return Ok(());
+        }
+        if matches!(entry.kind, EntryKind::Dir) {
+            fs::create_dir_all(&path).await?;
+            return Ok(());
+        }
+
+        // Readers never see a file half extracted
+        ensure_path_parent(&path).await?;
+        let temp_name = format!(".{}.{}.unpack", get_file_name(&path), Uuid::new_v4());
+        let temp_path = path.with_file_name(temp_name);
+        let mut hasher = Sha256::new();
+        let reader = InspectReader::new(reader.take(entry.size), |chunk| hasher.update(chunk));
+        let written = write_entry(reader, &temp_path, self.args.durable_writes).await;
+        let size = match written {
+            Ok(size) => size,
+            Err(err) => {
+                let _ = fs::remove_file(&temp_path).await;
+                return Err(err);
+            }
+        };
+        if let Err(err) = fs::rename(&temp_path, &path).await {
+            let _ = fs::remove_file(&temp_path).await;
+            return Err(err.into());
+        }
+        if let Some(store) = ChunkStore::get() {
+            let chunk_path = path.clone();
+            if let Err(err) = tokio::task::spawn_blocking(move || store.store(&chunk_path)).await? {
+                warn!("Failed to split {} into chunks, {err}", path.display());
+            }
+        }
+        if let Some(path_str) = path.to_str() {
+            self.provenance_db.set_file_owner(path_str, user, size)?;
+        }
+        self.publish_change(ChangeKind::Upload, &path, None, res);
+
+        let sha256 = format!("{:x}", hasher.finalize());
+        let event_hash = match self
+            .create_mint_event(&path, sha256.clone(), user, false)
+            .await
+        {
+            Ok(mint) => {
+                self.publish_provenance(&path, "mint", res);
+                Some(mint.event_hash)
+            }
+            Err(e) => {
+                error!("Failed to create mint event for {}: {}", path.display(), e);
+                None
+            }
+        };
+        output.files.push(UnpackedFile {
+            path: self.relative_path(&path),
+            size,
+            sha256,
+            event_hash,
+        });
+        Ok(())
+    }
+}
+
+/// Write the content of an entry to `path`, synced to disk when `durable`
+async fn write_entry<R: AsyncRead>(reader: R, path: &Path, durable: bool) -> Result<u64> {
+    tokio::pin!(reader);
+    let mut file = fs::File::create(path).await?;
+    let size = io::copy(&mut reader, &mut file).await?;
+    if durable {
+        file.sync_all().await?;
+    }
+    Ok(size)
+}
+
+async fn detect_kind(path: &Path) -> Result<Option<ArchiveKind>> {
+    let mut header = vec![];
+    fs::File::open(path)
+        .await?
+        .take(512)
+        .read_to_end(&mut header)
+        .await?;
+    let kind = if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+        Some(ArchiveKind::Zip)
+    } else if header.starts_with(&[0x1f, 0x8b]) {
+        Some(ArchiveKind::TarGz)
+    } else if header.get(257..262) == Some(b"ustar") {
+        Some(ArchiveKind::Tar)
+    } else {
+        None
+    };
+    Ok(kind)
+}
+
+async fn open_tar(path: &Path, kind: ArchiveKind) -> Result<Box<dyn AsyncRead + Unpin + Send>> {
+    let file = BufReader::new(fs::File::open(path).await?);
+    Ok(match kind {
+        ArchiveKind::TarGz => Box::new(GzipDecoder::new(file)),
+        _ => Box::new(file),
+    })
+}
+
+/// Entries of the archive in their stored order
+async fn list_entries(path: &Path, kind: ArchiveKind) -> Result<Vec<ArchiveEntry>> {
+    let mut entries = vec![];
+    match kind {
+        ArchiveKind::Zip => {
+            let zip =
+                ZipFileReader::with_tokio(BufReader::new(fs::File::open(path).await?)).await?;
+            for entry in zip.file().entries() {
+                let is_link = entry
+                    .unix_permissions()
+                    .is_some_and(|v| v & S_IFMT == S_IFLNK);
+                let kind = if is_link {
+                    EntryKind::Other
+                } else if entry.dir()? {
+                    EntryKind::Dir
+                } else {
+                    EntryKind::File
+                };
+                entries.push(ArchiveEntry {
+                    name: entry.filename().as_str()?.to_string(),
+                    kind,
+                    size: entry.uncompressed_size(),
+                });
+            }
+        }
+        ArchiveKind::Tar | ArchiveKind::TarGz => {
+            let mut archive = tokio_tar::Archive::new(open_tar(path, kind).await?);
+            let mut tar_entries = archive.entries()?;
+            while let Some(entry) = tar_entries.next().await {
+                let entry = entry?;
+                let header = entry.header();
+                let entry_type = header.entry_type();
+                let kind = if entry_type.is_file() {
+                    EntryKind::File
+                } else if entry_type.is_dir() {
+                    EntryKind::Dir
+                } else {
+                    EntryKind::Other
+                };
+                entries.push(ArchiveEntry {
+                    name: String::from_utf8_lossy(&entry.path_bytes()).to_string(),
+                    kind,
+                    size: header.size()?,
+                });
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Path of an entry inside the folder, None when it would escape it
+fn entry_path(name: &str) -> Option<PathBuf> {
+    let name = name.replace('\\', "/");
+    let mut path = PathBuf::new();
+    for component in Path::new(&name).components() {
+        match component {
+            Component::Normal(v) => path.push(v),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+/// Whether `path`, or its nearest existing ancestor, resolves inside `root`
+async fn is_contained(root: &Path, path: &Path) -> bool {
+    for ancestor in path.ancestors() {
+        if fs::symlink_metadata(ancestor).await.is_ok() {
+            return fs::canonicalize(ancestor)
+                .await
+                .is_ok_and(|v| v.starts_with(root));
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_path() {
+        assert_eq!(entry_path("./a/b.txt"), Some(PathBuf::from("a/b.txt")));
+        assert_eq!(entry_path("a\\b.txt"), Some(PathBuf::from("a/b.txt")));
+        assert_eq!(entry_path("./"), Some(PathBuf::new()));
+        assert_eq!(entry_path("../evil.txt"), None);
+        assert_eq!(entry_path("a/../../evil.txt"), None);
+        assert_eq!(entry_path("/etc/passwd"), None);
+    }
+}
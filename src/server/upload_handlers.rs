@@ -0,0 +1,313 @@
+use anyhow::Result;
+use chrono::Utc;
+use hyper::header::HeaderValue;
+use hyper::{HeaderMap, Method, StatusCode};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::fs;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use super::handlers::{partial_upload_path, Request, Server};
+use super::response_utils::{
+    set_json_response, status_no_content, status_not_found, status_payload_too_large,
+    status_unsupported_media_type, Response,
+};
+use super::write_locks::WriteLocks;
+
+pub(super) const UPLOADS_PATH: &str = "__dufs__/uploads";
+
+/// Request and response header carrying the ID of an upload
+pub(super) const UPLOAD_ID_HEADER: &str = "x-upload-id";
+
+/// How long an interrupted upload waits to be resumed, unless configured
+pub(super) const PARTIAL_UPLOAD_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How often the sweeper looks for interrupted uploads to remove, at most
+const PARTIAL_SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Uploads being received, for progress reports and to cancel stuck ones
+#[derive(Debug, Clone, Default)]
+pub struct UploadTracker {
+    uploads: Arc<Mutex<HashMap<String, Arc<ActiveUpload>>>>,
+}
+
+#[derive(Debug)]
+struct ActiveUpload {
+    /// The file, relative to the serve path
+    path: String,
+    user: Option<String>,
+    client: Option<IpAddr>,
+    started_at: String,
+    started: Instant,
+    received: AtomicU64,
+    cancelled: CancellationToken,
+}
+
+#[derive(Debug, Serialize)]
+struct UploadEntry<'a> {
+    id: &'a str,
+    path: &'a str,
+    user: Option<&'a str>,
+    client: Option<IpAddr>,
+    started_at: &'a str,
+    bytes_received: u64,
+    /// Average bytes per second since the upload started
+    rate: u64,
+}
+
+impl UploadTracker {
+    /// Track an upload until the returned guard drops. Clients may pick the
+    /// ID with `X-Upload-Id` to follow the upload before it's answered.
+    pub fn start(
+        &self,
+        headers: &HeaderMap<HeaderValue>,
+        path: String,
+        user: Option<&str>,
+        client: Option<IpAddr>,
+    ) -> UploadGuard {
+        let upload = Arc::new(ActiveUpload {
+            path,
+            user: user.map(str::to_string),
+            client,
+            started_at: Utc::now().to_rfc3339(),
+            started: Instant::now(),
+            received: AtomicU64::new(0),
+            cancelled: CancellationToken::new(),
+        });
+        let requested = headers
+            .get(UPLOAD_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| valid_upload_id(v));
+        let mut uploads = self.uploads.lock().unwrap();
+        let id = match requested {
+            Some(id) if !uploads.contains_key(id) => id.to_string(),
+            _ => Uuid::new_v4().to_string(),
+        };
+        uploads.insert(id.clone(), upload.clone());
+        UploadGuard {
+            id,
+            upload,
+            tracker: self.clone(),
+        }
+    }
+
+    /// All uploads in flight as JSON, oldest first
+    fn list_json(&self) -> String {
+        let uploads = self.uploads.lock().unwrap();
+        let mut entries: Vec<_> = uploads
+            .iter()
+            .map(|(id, upload)| upload.entry(id))
+            .collect();
+        entries.sort_by(|a, b| a.started_at.cmp(b.started_at));
+        json!({ "uploads": entries }).to_string()
+    }
+
+    /// Cancel an upload, false when there is no such upload
+    fn cancel(&self, id: &str) -> bool {
+        match self.uploads.lock().unwrap().get(id) {
+            Some(upload) => {
+                upload.cancelled.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl ActiveUpload {
+    fn entry<'a>(&'a self, id: &'a str) -> UploadEntry<'a> {
+        let bytes_received = self.received.load(Ordering::Relaxed);
+        let elapsed = self.started.elapsed().as_secs_f64();
+        UploadEntry {
+            id,
+            path: &self.path,
+            user: self.user.as_deref(),
+            client: self.client,
+            started_at: &self.started_at,
+            bytes_received,
+            rate: match elapsed > 0.0 {
+                true => (bytes_received as f64 / elapsed) as u64,
+                false => 0,
+            },
+        }
+    }
+}
+
+/// An upload in the tracker, removed from it on drop
+pub struct UploadGuard {
+    id: String,
+    upload: Arc<ActiveUpload>,
+    tracker: UploadTracker,
+}
+
+impl UploadGuard {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn received(&self, bytes: usize) {
+        self.upload
+            .received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Resolves once an admin cancels the upload
+    pub async fn cancelled(&self) {
+        self.upload.cancelled.cancelled().await
+    }
+}
+
+impl Drop for UploadGuard {
+    fn drop(&mut self) {
+        self.tracker.uploads.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Files whose interrupted upload this server kept in `.partial` for a
+/// resume. Only these `.partial` files are ever removed by the sweeper, never
+/// ones it didn't write itself.
+#[derive(Debug, Clone, Default)]
+pub struct PartialUploads {
+    targets: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl PartialUploads {
+    pub fn keep(&self, path: &Path) {
+        self.targets.lock().unwrap().insert(path.to_path_buf());
+    }
+
+    pub fn forget(&self, path: &Path) {
+        self.targets.lock().unwrap().remove(path);
+    }
+
+    /// Remove kept uploads not resumed within `max_age`
+    pub fn spawn_sweeper(self, write_locks: WriteLocks, max_age: Duration) {
+        let interval = max_age.clamp(Duration::from_secs(1), PARTIAL_SWEEP_INTERVAL);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match self.sweep(&write_locks, max_age).await {
+                    0 => {}
+                    count => info!("Partial upload sweep removed {count} interrupted upload(s)"),
+                }
+            }
+        });
+    }
+
+    async fn sweep(&self, write_locks: &WriteLocks, max_age: Duration) -> usize {
+        let targets: Vec<_> = self.targets.lock().unwrap().iter().cloned().collect();
+        let mut removed = 0;
+        for path in targets {
+            // Resumes write under the same lock
+            let _write_lock = write_locks.lock(&path).await;
+            let partial_path = partial_upload_path(&path);
+            let Ok(mtime) = fs::metadata(&partial_path).await.and_then(|v| v.modified()) else {
+                // Resumed or removed in the meantime
+                self.forget(&path);
+                continue;
+            };
+            if mtime + max_age > SystemTime::now() {
+                continue;
+            }
+            match fs::remove_file(&partial_path).await {
+                Ok(()) => {
+                    self.forget(&path);
+                    removed += 1;
+                }
+                Err(err) => warn!("Failed to remove `{}`, {err}", partial_path.display()),
+            }
+        }
+        removed
+    }
+}
+
+/// IDs picked by clients are hard to guess, as they give access to progress
+fn valid_upload_id(id: &str) -> bool {
+    (16..=64).contains(&id.len())
+        && id
+            .bytes()
+            .all(|v| v.is_ascii_alphanumeric() || v == b'-' || v == b'_')
+}
+
+impl Server {
+    /// Refuse to create `path` when its file type may not be uploaded, or
+    /// when `size` goes over the limit for the type. True when refused.
+    pub(super) fn reject_upload_type(
+        &self,
+        path: &Path,
+        size: Option<u64>,
+        res: &mut Response,
+    ) -> bool {
+        let name = path
+            .file_name()
+            .map(|v| v.to_string_lossy())
+            .unwrap_or_default();
+        if !self.args.upload_type_allowed(&name) {
+            let msg = format!("Files like `{name}` may not be uploaded");
+            status_unsupported_media_type(res, &msg);
+            return true;
+        }
+        match (self.args.upload_limit(&name), size) {
+            (Some(limit), Some(size)) if size > limit => {
+                status_payload_too_large(res, limit);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Handle upload progress (/__dufs__/uploads)
+    ///
+    /// GET lists the uploads in flight and `DELETE /__dufs__/uploads/<id>`
+    /// cancels one, for admins. `GET /__dufs__/uploads/<id>` reports the
+    /// progress of a single upload to whoever knows its ID.
+    pub(super) fn handle_uploads(
+        &self,
+        req_path: &str,
+        req: &Request,
+        res: &mut Response,
+    ) -> Result<()> {
+        let id = req_path
+            .strip_prefix(UPLOADS_PATH)
+            .unwrap_or_default()
+            .trim_matches('/');
+        match (req.method().clone(), id) {
+            (Method::GET, "") => {
+                if self.guard_admin(req, res)? {
+                    set_json_response(res, self.uploads.list_json());
+                }
+            }
+            (Method::GET, id) => {
+                let body = self
+                    .uploads
+                    .uploads
+                    .lock()
+                    .unwrap()
+                    .get(id)
+                    .map(|v| serde_json::to_string(&v.entry(id)));
+                match body {
+                    Some(body) => set_json_response(res, body?),
+                    None => status_not_found(res),
+                }
+            }
+            (Method::DELETE, id) if !id.is_empty() => {
+                if self.guard_admin(req, res)? {
+                    match self.uploads.cancel(id) {
+                        true => status_no_content(res),
+                        false => status_not_found(res),
+                    }
+                }
+            }
+            _ => *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED,
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+use anyhow::Result;
+use http_body_util::{BodyExt, Limited};
+use hyper::{Method, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::path::Path;
+
+use crate::provenance::{
+    verify_event_signature, Attestation, EventAction, ProvenanceDb, SignatureAlgorithm,
+};
+use crate::provenance_utils;
+
+use super::provenance_handlers::Request;
+use super::response_utils::{
+    set_json_response, status_bad_request, status_conflict, status_no_content, status_not_found,
+    Response,
+};
+
+const MAX_BODY_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Deserialize)]
+struct UserKeyRequest {
+    public_key_hex: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SignRequest {
+    creator_sig_hex: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AttestationRequest {
+    #[serde(default)]
+    algorithm: SignatureAlgorithm,
+    pubkey_hex: String,
+    signature_hex: String,
+}
+
+/// Handle the signed-in user's key (GET/PUT/DELETE /__dufs__/user-key)
+///
+/// Files uploaded by a user with a registered key are minted with that key as
+/// creator; the user then attaches their signature with `?sign`.
+pub async fn handle_user_key(
+    req: Request,
+    user: &str,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    match *req.method() {
+        Method::GET => match provenance_db.get_user_key(user)? {
+            Some(public_key_hex) => set_json_response(
+                res,
+                json!({ "user": user, "public_key_hex": public_key_hex }).to_string(),
+            ),
+            None => status_not_found(res),
+        },
+        Method::PUT => {
+            let Some(body) = read_body(req, res).await else {
+                return Ok(());
+            };
+            let public_key_hex = match serde_json::from_slice::<UserKeyRequest>(&body) {
+                Ok(v) => v.public_key_hex.to_lowercase(),
+                Err(_) => {
+                    status_bad_request(res, "Expected {\"public_key_hex\": \"<hex>\"}");
+                    return Ok(());
+                }
+            };
+            let valid = hex::decode(&public_key_hex)
+                .ok()
+                .and_then(|v| secp256k1::PublicKey::from_slice(&v).ok())
+                .is_some_and(|v| hex::encode(v.serialize()) == public_key_hex);
+            if !valid {
+                status_bad_request(res, "public_key_hex must be a compressed secp256k1 key");
+                return Ok(());
+            }
+            provenance_db.set_user_key(user, &public_key_hex)?;
+            set_json_response(
+                res,
+                json!({ "user": user, "public_key_hex": public_key_hex }).to_string(),
+            );
+        }
+        Method::DELETE => {
+            if provenance_db.delete_user_key(user)? {
+                status_no_content(res);
+            } else {
+                status_not_found(res);
+            }
+        }
+        _ => *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED,
+    }
+    Ok(())
+}
+
+/// Handle creator signature upload (POST /api/<file>?sign)
+///
+/// Completes a mint event recorded with a user's key, the signature must be
+/// over its `event_hash_hex`.
+pub async fn handle_sign_mint(
+    path: &Path,
+    req: Request,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let Some(body) = read_body(req, res).await else {
+        return Ok(());
+    };
+    let signature_hex = match serde_json::from_slice::<SignRequest>(&body) {
+        Ok(v) => v.creator_sig_hex,
+        Err(_) => {
+            status_bad_request(res, "Expected {\"creator_sig_hex\": \"<hex>\"}");
+            return Ok(());
+        }
+    };
+    let (Some((artifact_id, _, _)), Some(manifest)) = (
+        provenance_utils::get_artifact_by_path(provenance_db, path).await?,
+        provenance_utils::get_manifest_for_file(provenance_db, path).await?,
+    ) else {
+        status_not_found(res);
+        return Ok(());
+    };
+    let Some(mint) = manifest
+        .events
+        .first()
+        .filter(|v| matches!(v.action, EventAction::Mint))
+    else {
+        status_not_found(res);
+        return Ok(());
+    };
+    if mint.signatures.creator_sig_hex.is_some() {
+        status_conflict(res, "The mint event is already signed");
+        return Ok(());
+    }
+    let creator_pubkey_hex = mint
+        .actors
+        .creator_pubkey_hex
+        .as_deref()
+        .unwrap_or_default();
+    if !verify_event_signature(&mint.event_hash_hex, &signature_hex, creator_pubkey_hex)
+        .unwrap_or_default()
+    {
+        status_bad_request(res, "Signature doesn't match the creator key");
+        return Ok(());
+    }
+    provenance_db.add_creator_signature(artifact_id, mint.index, &signature_hex)?;
+
+    let mut mint = mint.clone();
+    mint.signatures.creator_sig_hex = Some(signature_hex);
+    set_json_response(res, serde_json::to_string(&mint)?);
+    Ok(())
+}
+
+/// Handle detached signature upload (POST /api/<file>?signature)
+///
+/// Anyone holding a secp256k1 or ed25519 key can vouch for the file, the
+/// signature must be over its `sha256_hex`. Manifests list them as attestations.
+pub async fn handle_attestation(
+    path: &Path,
+    req: Request,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let Some(body) = read_body(req, res).await else {
+        return Ok(());
+    };
+    let request = match serde_json::from_slice::<AttestationRequest>(&body) {
+        Ok(v) => v,
+        Err(_) => {
+            status_bad_request(
+                res,
+                "Expected {\"pubkey_hex\": \"<hex>\", \"signature_hex\": \"<hex>\", \"algorithm\": \"secp256k1\" | \"ed25519\"}",
+            );
+            return Ok(());
+        }
+    };
+    let Some((artifact_id, artifact, _)) =
+        provenance_utils::get_artifact_by_path(provenance_db, path).await?
+    else {
+        status_not_found(res);
+        return Ok(());
+    };
+    let attestation = Attestation {
+        algorithm: request.algorithm,
+        pubkey_hex: request.pubkey_hex.to_lowercase(),
+        signature_hex: request.signature_hex.to_lowercase(),
+        artifact_sha256_hex: artifact.sha256_hex,
+        signed_at: chrono::Utc::now().to_rfc3339(),
+    };
+    if !attestation.verify().unwrap_or_default() {
+        status_bad_request(res, "Signature doesn't match the file and key");
+        return Ok(());
+    }
+    provenance_db.add_attestation(artifact_id, &attestation)?;
+
+    *res.status_mut() = StatusCode::CREATED;
+    set_json_response(res, serde_json::to_string(&attestation)?);
+    Ok(())
+}
+
+pub(super) async fn read_body(req: Request, res: &mut Response) -> Option<bytes::Bytes> {
+    match Limited::new(req.into_body(), MAX_BODY_SIZE).collect().await {
+        Ok(v) => Some(v.to_bytes()),
+        Err(_) => {
+            *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+            None
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::{anyhow, Result};
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::auth::AccessPaths;
+use crate::provenance::ProvenanceDb;
+
+use super::handlers::Server;
+use super::response_utils::{set_json_response, status_bad_request, Response};
+
+/// Explicit visibility settings, consulted for principals that may only see
+/// public items. A path without a setting inherits the nearest ancestor's,
+/// and is visible when no ancestor has one either. Settings are looked up
+/// per path as they are needed and remembered for the rest of the request.
+#[derive(Clone)]
+pub struct PublicOnly {
+    provenance_db: ProvenanceDb,
+    /// The setting on exactly each path looked up so far, `true` meaning public
+    settings: Arc<Mutex<HashMap<PathBuf, Option<bool>>>>,
+}
+
+impl PublicOnly {
+    pub fn new(provenance_db: ProvenanceDb) -> Self {
+        Self {
+            provenance_db,
+            settings: Default::default(),
+        }
+    }
+
+    pub fn is_private(&self, path: &Path) -> bool {
+        for ancestor in path.ancestors() {
+            match self.setting(ancestor) {
+                Ok(Some(public)) => return !public,
+                Ok(None) => {}
+                Err(err) => {
+                    warn!(
+                        "Failed to look up visibility of {}, {err}",
+                        ancestor.display()
+                    );
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn setting(&self, path: &Path) -> Result<Option<bool>> {
+        if let Some(setting) = self.settings.lock().unwrap().get(path) {
+            return Ok(*setting);
+        }
+        let setting = match path.to_str() {
+            Some(path) => self
+                .provenance_db
+                .get_path_visibility(path)?
+                .map(|v| v == "public"),
+            None => None,
+        };
+        self.settings
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), setting);
+        Ok(setting)
+    }
+}
+
+impl std::fmt::Debug for PublicOnly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PublicOnly").finish_non_exhaustive()
+    }
+}
+
+impl Server {
+    /// Anonymous visitors (when users are configured) and index-only principals
+    /// only see public items; None for everyone else.
+    pub(super) fn public_only(
+        &self,
+        user: &Option<String>,
+        access_paths: &AccessPaths,
+    ) -> Result<Option<PublicOnly>> {
+        let restricted =
+            (user.is_none() && self.args.auth.has_users()) || access_paths.perm().indexonly();
+        if !restricted {
+            return Ok(None);
+        }
+        Ok(Some(PublicOnly::new(self.provenance_db.clone())))
+    }
+}
+
+/// Handle visibility toggle (PATCH /api/<path>?visibility=public|private)
+pub async fn handle_set_visibility(
+    path: &Path,
+    visibility: &str,
+    user: Option<String>,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    if !matches!(visibility, "public" | "private") {
+        status_bad_request(res, "visibility must be `public` or `private`");
+        return Ok(());
+    }
+    let file_path = path
+        .to_str()
+        .ok_or_else(|| anyhow!("Invalid UTF-8 in file path: {:?}", path))?;
+    provenance_db.set_path_visibility(file_path, visibility, user.as_deref())?;
+    set_json_response(res, json!({ "visibility": visibility }).to_string());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_private_inherits() {
+        let db = ProvenanceDb::new(":memory:", &Default::default()).unwrap();
+        db.set_path_visibility("/srv/dir1", "private", None)
+            .unwrap();
+        db.set_path_visibility("/srv/dir1/shared", "public", None)
+            .unwrap();
+        let rules = PublicOnly::new(db.clone());
+        assert!(!rules.is_private(Path::new("/srv/dir2/a.txt")));
+        assert!(rules.is_private(Path::new("/srv/dir1")));
+        assert!(rules.is_private(Path::new("/srv/dir1/a.txt")));
+        assert!(!rules.is_private(Path::new("/srv/dir1/shared/a.txt")));
+
+        // A request keeps the settings it has seen
+        db.set_path_visibility("/srv/dir2", "private", None)
+            .unwrap();
+        assert!(!rules.is_private(Path::new("/srv/dir2/a.txt")));
+        assert!(PublicOnly::new(db).is_private(Path::new("/srv/dir2/a.txt")));
+    }
+}
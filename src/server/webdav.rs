@@ -1,12 +1,30 @@
-use anyhow::Result;
-use hyper::{header::HeaderValue, StatusCode};
-use std::path::Path;
+use anyhow::{anyhow, bail, Result};
+use futures_util::stream;
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::{Bytes, Frame};
+use hyper::{
+    header::{HeaderMap, HeaderValue},
+    StatusCode,
+};
+use std::io;
+use std::path::{Path, PathBuf};
 use tokio::fs;
-use uuid::Uuid;
+use walkdir::WalkDir;
+use xml::escape::{escape_str_attribute, escape_str_pcdata};
+use xml::reader::{EventReader, XmlEvent};
 
 use crate::http_utils::body_full;
+use crate::provenance::{DeadProperty, ProvenanceDb};
+use crate::utils::encode_uri;
 
-use super::response_utils::{res_multistatus, status_forbid, status_no_content, Response};
+use super::handlers::Request;
+use super::locks::{
+    parse_if_tokens, parse_timeout, ActiveLock, LockManager, LockScope, DEFAULT_LOCK_TIMEOUT,
+};
+use super::response_utils::{
+    res_multistatus, status_bad_request, status_conflict, status_no_content, Response,
+};
+use super::user_key_handlers::read_body;
 
 pub async fn handle_mkcol(path: &Path, res: &mut Response) -> Result<()> {
     fs::create_dir_all(path).await?;
@@ -14,17 +32,84 @@ pub async fn handle_mkcol(path: &Path, res: &mut Response) -> Result<()> {
     Ok(())
 }
 
-pub async fn handle_copy(path: &Path, dest: &Path, res: &mut Response) -> Result<()> {
-    let meta = fs::symlink_metadata(path).await?;
-    if meta.is_dir() {
-        status_forbid(res);
-        return Ok(());
+/// Copy a file, or a directory with everything under it unless `deep` is
+/// false, returning the copied files and their sizes. Files that can't be
+/// copied are reported in a 207 response, under the source's `href`.
+pub async fn handle_copy(
+    path: &Path,
+    dest: &Path,
+    deep: bool,
+    href: &str,
+    res: &mut Response,
+) -> Result<Vec<(PathBuf, u64)>> {
+    ensure_path_parent(dest).await?;
+    if !fs::metadata(path).await?.is_dir() {
+        let size = fs::copy(path, dest).await?;
+        status_no_content(res);
+        return Ok(vec![(dest.to_path_buf(), size)]);
     }
 
-    ensure_path_parent(dest).await?;
-    fs::copy(path, dest).await?;
-    status_no_content(res);
-    Ok(())
+    fs::create_dir_all(dest).await?;
+    let entries = match deep {
+        true => {
+            let path = path.to_path_buf();
+            tokio::task::spawn_blocking(move || {
+                WalkDir::new(path)
+                    .min_depth(1)
+                    .into_iter()
+                    .collect::<Vec<_>>()
+            })
+            .await?
+        }
+        false => vec![],
+    };
+    let mut copied = vec![];
+    let mut failed = vec![];
+    for entry in entries {
+        let entry = match entry {
+            Ok(v) => v,
+            Err(err) => {
+                if let Some(relative) = err.path().and_then(|v| v.strip_prefix(path).ok()) {
+                    failed.push((relative.to_path_buf(), StatusCode::INTERNAL_SERVER_ERROR));
+                }
+                continue;
+            }
+        };
+        let relative = entry.path().strip_prefix(path)?;
+        let target = dest.join(relative);
+        let result = match entry.file_type().is_dir() {
+            true => fs::create_dir_all(&target).await.map(|_| None),
+            false => fs::copy(entry.path(), &target).await.map(Some),
+        };
+        match result {
+            Ok(Some(size)) => copied.push((target, size)),
+            Ok(None) => {}
+            Err(err) => {
+                let status = match err.kind() {
+                    io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+                failed.push((relative.to_path_buf(), status));
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        status_no_content(res);
+    } else {
+        let href = href.trim_end_matches('/');
+        let output: Vec<String> = failed
+            .iter()
+            .map(|(relative, status)| {
+                format!(
+                    "<D:response>\n<D:href>{href}/{}</D:href>\n<D:status>HTTP/1.1 {status}</D:status>\n</D:response>",
+                    encode_uri(&relative.to_string_lossy())
+                )
+            })
+            .collect();
+        res_multistatus(res, &output.join("\n"));
+    }
+    Ok(copied)
 }
 
 pub async fn handle_move(
@@ -56,45 +141,312 @@ pub async fn handle_move(
     Ok(())
 }
 
-pub async fn handle_lock(req_path: &str, auth: bool, res: &mut Response) -> Result<()> {
-    let token = if auth {
-        format!("opaquelocktoken:{}", Uuid::new_v4())
-    } else {
-        chrono::Utc::now().timestamp().to_string()
+/// Handle LOCK: a body asks for a new lock, an empty body refreshes the lock
+/// named in the `If:` header. Locking a missing file creates it empty.
+pub async fn handle_lock(
+    locks: &LockManager,
+    path: &Path,
+    href: &str,
+    is_miss: bool,
+    req: Request,
+    res: &mut Response,
+) -> Result<()> {
+    let headers = req.headers();
+    let timeout = headers
+        .get("timeout")
+        .and_then(|v| v.to_str().ok())
+        .map(parse_timeout)
+        .unwrap_or(DEFAULT_LOCK_TIMEOUT);
+    let deep = headers
+        .get("depth")
+        .is_none_or(|v| !v.as_bytes().eq_ignore_ascii_case(b"0"));
+    let tokens = if_tokens(headers);
+    let Some(body) = read_body(req, res).await else {
+        return Ok(());
     };
 
+    if body.iter().all(|v| v.is_ascii_whitespace()) {
+        match locks.refresh(path, &tokens, timeout) {
+            Some(lock) => set_lock_response(&lock, res),
+            None => *res.status_mut() = StatusCode::PRECONDITION_FAILED,
+        }
+        return Ok(());
+    }
+    let Ok((scope, owner)) = parse_lockinfo(&body) else {
+        status_bad_request(res, "Invalid lockinfo");
+        return Ok(());
+    };
+    if is_miss && fs::metadata(path.parent().unwrap_or(path)).await.is_err() {
+        *res.status_mut() = StatusCode::CONFLICT;
+        return Ok(());
+    }
+    let deep = deep && !is_miss && fs::metadata(path).await?.is_dir();
+    let Some(lock) = locks.lock(path, href, scope, deep, owner, timeout) else {
+        status_locked(res);
+        return Ok(());
+    };
+    if is_miss {
+        fs::File::create(path).await?;
+        *res.status_mut() = StatusCode::CREATED;
+    }
+    res.headers_mut()
+        .insert("lock-token", format!("<{}>", lock.token).parse()?);
+    set_lock_response(&lock, res);
+    Ok(())
+}
+
+/// Handle UNLOCK of the lock named in the `Lock-Token` header
+pub fn handle_unlock(
+    locks: &LockManager,
+    path: &Path,
+    headers: &HeaderMap<HeaderValue>,
+    res: &mut Response,
+) {
+    let token = headers
+        .get("lock-token")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().trim_start_matches('<').trim_end_matches('>'));
+    match token {
+        Some(token) if locks.unlock(path, token) => status_no_content(res),
+        Some(_) => status_conflict(res, "No matching lock"),
+        None => status_bad_request(res, "Missing Lock-Token"),
+    }
+}
+
+/// Reject a change of `path` (and of everything under it when `deep`) that
+/// doesn't submit the tokens of its locks, returning whether it was rejected
+pub fn reject_locked(
+    locks: &LockManager,
+    path: &Path,
+    deep: bool,
+    headers: &HeaderMap<HeaderValue>,
+    res: &mut Response,
+) -> bool {
+    if locks.can_write(path, deep, &if_tokens(headers)) {
+        return false;
+    }
+    status_locked(res);
+    true
+}
+
+fn if_tokens(headers: &HeaderMap<HeaderValue>) -> Vec<String> {
+    headers
+        .get("if")
+        .and_then(|v| v.to_str().ok())
+        .map(parse_if_tokens)
+        .unwrap_or_default()
+}
+
+/// Refuse a `Depth: infinity` PROPFIND (RFC 4918, section 9.1)
+pub fn status_propfind_finite_depth(res: &mut Response) {
+    *res.status_mut() = StatusCode::FORBIDDEN;
     res.headers_mut().insert(
         "content-type",
         HeaderValue::from_static("application/xml; charset=utf-8"),
     );
-    res.headers_mut()
-        .insert("lock-token", format!("<{token}>").parse()?);
+    *res.body_mut() = body_full(
+        r#"<?xml version="1.0" encoding="utf-8" ?>
+<D:error xmlns:D="DAV:"><D:propfind-finite-depth/></D:error>"#,
+    );
+}
 
+/// Like `res_multistatus`, but sending the responses as they are rendered
+pub fn res_multistatus_stream<I>(res: &mut Response, responses: I)
+where
+    I: Iterator<Item = Result<String>> + Send + Sync + 'static,
+{
+    *res.status_mut() = StatusCode::MULTI_STATUS;
+    res.headers_mut().insert(
+        "content-type",
+        HeaderValue::from_static("application/xml; charset=utf-8"),
+    );
+    let head = std::iter::once(Ok(
+        "<?xml version=\"1.0\" encoding=\"utf-8\" ?>\n<D:multistatus xmlns:D=\"DAV:\">".to_string(),
+    ));
+    let tail = std::iter::once(Ok("\n</D:multistatus>".to_string()));
+    let frames = head
+        .chain(responses.map(|v| v.map(|v| format!("\n{v}"))))
+        .chain(tail)
+        .map(|v| v.map(|v| Frame::data(Bytes::from(v))));
+    *res.body_mut() = StreamBody::new(stream::iter(frames)).boxed();
+}
+
+fn status_locked(res: &mut Response) {
+    *res.status_mut() = StatusCode::LOCKED;
+    *res.body_mut() = body_full("Locked");
+}
+
+fn set_lock_response(lock: &ActiveLock, res: &mut Response) {
+    res.headers_mut().insert(
+        "content-type",
+        HeaderValue::from_static("application/xml; charset=utf-8"),
+    );
+    let depth = if lock.deep { "infinity" } else { "0" };
+    let owner = lock.owner.as_deref().unwrap_or_default();
     *res.body_mut() = body_full(format!(
         r#"<?xml version="1.0" encoding="utf-8"?>
 <D:prop xmlns:D="DAV:"><D:lockdiscovery><D:activelock>
-<D:locktoken><D:href>{token}</D:href></D:locktoken>
-<D:lockroot><D:href>{req_path}</D:href></D:lockroot>
-</D:activelock></D:lockdiscovery></D:prop>"#
+<D:locktype><D:write/></D:locktype>
+<D:lockscope><D:{}/></D:lockscope>
+<D:depth>{depth}</D:depth>{owner}
+<D:timeout>Second-{}</D:timeout>
+<D:locktoken><D:href>{}</D:href></D:locktoken>
+<D:lockroot><D:href>{}</D:href></D:lockroot>
+</D:activelock></D:lockdiscovery></D:prop>"#,
+        lock.scope.as_str(),
+        lock.timeout.as_secs(),
+        lock.token,
+        escape_str_pcdata(&lock.href),
     ));
-    Ok(())
 }
 
-pub async fn handle_proppatch(req_path: &str, res: &mut Response) -> Result<()> {
+/// Scope and owner (as XML to report back) of a `<D:lockinfo>` body
+fn parse_lockinfo(body: &[u8]) -> Result<(LockScope, Option<String>)> {
+    let mut scope = LockScope::Exclusive;
+    let mut owner: Option<(String, bool)> = None;
+    let mut in_owner = false;
+    for event in EventReader::new(body) {
+        match event? {
+            XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
+                "shared" => scope = LockScope::Shared,
+                "exclusive" => scope = LockScope::Exclusive,
+                "owner" => {
+                    in_owner = true;
+                    owner = Some((String::new(), false));
+                }
+                "href" if in_owner => {
+                    if let Some((_, is_href)) = owner.as_mut() {
+                        *is_href = true;
+                    }
+                }
+                _ => {}
+            },
+            XmlEvent::EndElement { name } if name.local_name == "owner" => in_owner = false,
+            XmlEvent::Characters(text) if in_owner => {
+                if let Some((value, _)) = owner.as_mut() {
+                    value.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+    let owner = owner.map(|(value, is_href)| {
+        let value = escape_str_pcdata(value.trim());
+        match is_href {
+            true => format!("\n<D:owner><D:href>{value}</D:href></D:owner>"),
+            false => format!("\n<D:owner>{value}</D:owner>"),
+        }
+    });
+    Ok((scope, owner))
+}
+
+/// Handle PROPPATCH: store the dead properties set (and drop those removed)
+/// by a `<D:propertyupdate>`, all or nothing. Properties in the `DAV:`
+/// namespace are computed by the server and can't be changed.
+pub async fn handle_proppatch(
+    path: &Path,
+    href: &str,
+    req: Request,
+    provenance_db: &ProvenanceDb,
+    res: &mut Response,
+) -> Result<()> {
+    let Some(body) = read_body(req, res).await else {
+        return Ok(());
+    };
+    let Ok(changes) = parse_propertyupdate(&body) else {
+        status_bad_request(res, "Invalid propertyupdate");
+        return Ok(());
+    };
+    let protected = changes.iter().any(|(v, _)| v.namespace == "DAV:");
+    if !protected {
+        let path = path
+            .to_str()
+            .ok_or_else(|| anyhow!("Invalid UTF-8 in path"))?;
+        provenance_db.patch_dead_properties(path, &changes)?;
+    }
+
+    let propstats: String = changes
+        .iter()
+        .map(|(property, _)| {
+            let status = match (protected, property.namespace.as_str()) {
+                (false, _) => "200 OK",
+                (true, "DAV:") => "403 Forbidden",
+                (true, _) => "424 Failed Dependency",
+            };
+            let prop = match property.namespace.as_str() {
+                "" => format!("<{} xmlns=\"\"/>", property.name),
+                namespace => format!(
+                    "<P:{} xmlns:P=\"{}\"/>",
+                    property.name,
+                    escape_str_attribute(namespace)
+                ),
+            };
+            format!(
+                "\n<D:propstat>\n<D:prop>{prop}</D:prop>\n<D:status>HTTP/1.1 {status}</D:status>\n</D:propstat>"
+            )
+        })
+        .collect();
     let output = format!(
-        r#"<D:response>
-<D:href>{req_path}</D:href>
-<D:propstat>
-<D:prop>
-</D:prop>
-<D:status>HTTP/1.1 403 Forbidden</D:status>
-</D:propstat>
-</D:response>"#
+        "<D:response>\n<D:href>{}</D:href>{propstats}\n</D:response>",
+        escape_str_pcdata(href)
     );
     res_multistatus(res, &output);
     Ok(())
 }
 
+/// Properties of a `<D:propertyupdate>` in order, flagged `true` when removed.
+/// Values are kept as text.
+fn parse_propertyupdate(body: &[u8]) -> Result<Vec<(DeadProperty, bool)>> {
+    let mut changes: Vec<(DeadProperty, bool)> = vec![];
+    let mut remove = false;
+    // Depth of the `<D:prop>` being read, and of the property in it
+    let mut prop_depth = None;
+    let mut property_depth = None;
+    let mut depth = 0;
+    for event in EventReader::new(body) {
+        match event? {
+            XmlEvent::StartElement { name, .. } => {
+                depth += 1;
+                let is_dav = name.namespace.as_deref() == Some("DAV:");
+                match (prop_depth, name.local_name.as_str()) {
+                    (None, "set") if is_dav => remove = false,
+                    (None, "remove") if is_dav => remove = true,
+                    (None, "prop") if is_dav => prop_depth = Some(depth),
+                    (Some(prop), _) if depth == prop + 1 => {
+                        property_depth = Some(depth);
+                        let property = DeadProperty {
+                            namespace: name.namespace.unwrap_or_default(),
+                            name: name.local_name,
+                            value: String::new(),
+                        };
+                        changes.push((property, remove));
+                    }
+                    _ => {}
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                if prop_depth == Some(depth) {
+                    prop_depth = None;
+                }
+                if property_depth == Some(depth) {
+                    property_depth = None;
+                }
+                depth -= 1;
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) if property_depth.is_some() => {
+                if let Some((property, _)) = changes.last_mut() {
+                    property.value.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+    if changes.is_empty() {
+        bail!("No properties to update");
+    }
+    Ok(changes)
+}
+
 async fn ensure_path_parent(path: &Path) -> Result<()> {
     if let Some(parent) = path.parent() {
         if fs::symlink_metadata(parent).await.is_err() {
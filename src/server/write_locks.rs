@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// One writer per file at a time. Concurrent uploads to the same path run
+/// one after the other, so each replaces the file as a whole and its mint
+/// hash matches what ends up on disk.
+#[derive(Debug, Clone, Default)]
+pub struct WriteLocks {
+    files: Arc<Mutex<HashMap<PathBuf, Arc<AsyncMutex<()>>>>>,
+}
+
+impl WriteLocks {
+    /// Wait for the other writers of `path`, held until the guard drops
+    pub async fn lock(&self, path: &Path) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut files = self.files.lock().unwrap();
+            // Forget files nobody is writing anymore
+            files.retain(|_, v| Arc::strong_count(v) > 1);
+            files.entry(path.to_path_buf()).or_default().clone()
+        };
+        lock.lock_owned().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_write_locks_serialize() {
+        let locks = WriteLocks::default();
+        let path = Path::new("/srv/report.pdf");
+        let first = locks.lock(path).await;
+        let _other = locks.lock(Path::new("/srv/other.pdf")).await;
+
+        let second = tokio::time::timeout(Duration::from_millis(50), locks.lock(path)).await;
+        assert!(second.is_err());
+
+        drop(first);
+        let second = tokio::time::timeout(Duration::from_millis(50), locks.lock(path)).await;
+        assert!(second.is_ok());
+    }
+}
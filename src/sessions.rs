@@ -0,0 +1,76 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier};
+
+use crate::utils::unix_now;
+
+/// Cookie holding the signed-in user after a login form or OIDC login
+pub const SESSION_COOKIE: &str = "dufs_session";
+/// How long a session lasts, in seconds
+pub const SESSION_EXPIRATION: u64 = 60 * 60 * 24;
+
+/// Signed session cookies naming a user of the `--auth` rules. The key is
+/// made at startup, so sessions end when the server restarts.
+#[derive(Clone)]
+pub struct Sessions {
+    key: SigningKey,
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Self {
+            key: SigningKey::from_bytes(&rand::random()),
+        }
+    }
+}
+
+impl Sessions {
+    /// Cookie value for a session of `user`
+    pub fn value(&self, user: &str) -> String {
+        let exp = unix_now().as_secs() + SESSION_EXPIRATION;
+        let message = format!("{exp}:{user}");
+        let sig = self.key.sign(message.as_bytes()).to_bytes();
+
+        let mut raw = Vec::with_capacity(64 + 8 + user.len());
+        raw.extend_from_slice(&sig);
+        raw.extend_from_slice(&exp.to_be_bytes());
+        raw.extend_from_slice(user.as_bytes());
+        hex::encode(raw)
+    }
+
+    /// User of a session cookie value, if it is valid and not expired
+    pub fn user(&self, value: &str) -> Option<String> {
+        let raw = hex::decode(value).ok()?;
+        if raw.len() < 72 {
+            return None;
+        }
+        let sig = Signature::from_bytes(&<[u8; 64]>::try_from(&raw[..64]).ok()?);
+        let exp = u64::from_be_bytes(raw[64..72].try_into().ok()?);
+        let user = std::str::from_utf8(&raw[72..]).ok()?;
+        if unix_now().as_secs() > exp {
+            return None;
+        }
+        let message = format!("{exp}:{user}");
+        self.key
+            .verifying_key()
+            .verify(message.as_bytes(), &sig)
+            .ok()?;
+        Some(user.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_value() {
+        let sessions = Sessions::default();
+        let value = sessions.value("alice");
+        assert_eq!(sessions.user(&value).as_deref(), Some("alice"));
+
+        let mut forged = hex::decode(&value).unwrap();
+        *forged.last_mut().unwrap() = b'x';
+        assert_eq!(sessions.user(&hex::encode(forged)), None);
+        // Sessions are bound to the server that issued them
+        assert_eq!(Sessions::default().user(&value), None);
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::path::{Path, PathBuf};
+
+use crate::provenance::{ProvenanceDb, ServerKeypair};
+
+/// Where the key that signs mint events and shares comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SigningKeySource<'a> {
+    /// A hex private key handed over directly (`DUFS_SIGNING_KEY`)
+    Hex(&'a str),
+    /// A key file, generated on first run (`--signing-key`)
+    File(&'a Path),
+    /// Generated on first run and stored in the provenance database
+    Database,
+}
+
+impl<'a> SigningKeySource<'a> {
+    pub fn new(key: Option<&'a str>, file: Option<&'a Path>) -> Self {
+        match (key, file) {
+            (Some(key), _) => Self::Hex(key),
+            (None, Some(file)) => Self::File(file),
+            (None, None) => Self::Database,
+        }
+    }
+
+    /// Load (or create) the keypair, replacing it with a fresh one when `rotate`
+    /// is set, and record its public key as the active one in `db`.
+    pub fn load(&self, rotate: bool, db: &ProvenanceDb) -> Result<ServerKeypair> {
+        let keypair = match self {
+            Self::Hex(key) => ServerKeypair::from_private_key_hex(key)?,
+            Self::File(path) => load_key_file(path, rotate)?,
+            Self::Database if rotate => db.rotate_signing_keypair()?,
+            Self::Database => db.signing_keypair()?,
+        };
+        db.activate_signing_key(&keypair.public_key_hex)?;
+        Ok(keypair)
+    }
+}
+
+fn load_key_file(path: &Path, rotate: bool) -> Result<ServerKeypair> {
+    if path.exists() && !rotate {
+        let key = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read signing key `{}`", path.display()))?;
+        return ServerKeypair::from_private_key_hex(&key)
+            .with_context(|| format!("Invalid signing key `{}`", path.display()));
+    }
+    if path.exists() {
+        // Keep the retired key around rather than destroying it
+        let mut retired = path.as_os_str().to_owned();
+        retired.push(format!(".retired-{}", Utc::now().timestamp()));
+        let retired = PathBuf::from(retired);
+        std::fs::rename(path, &retired).with_context(|| {
+            format!(
+                "Failed to move `{}` to `{}`",
+                path.display(),
+                retired.display()
+            )
+        })?;
+        info!("Retired signing key moved to {}", retired.display());
+    }
+    let keypair = ServerKeypair::generate();
+    write_private_file(path, &keypair.private_key_hex)
+        .with_context(|| format!("Failed to write signing key `{}`", path.display()))?;
+    info!("Generated signing key {}", path.display());
+    Ok(keypair)
+}
+
+#[cfg(unix)]
+fn write_private_file(path: &Path, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    writeln!(file, "{contents}")
+}
+
+#[cfg(not(unix))]
+fn write_private_file(path: &Path, contents: &str) -> std::io::Result<()> {
+    std::fs::write(path, format!("{contents}\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provenance::DbEncryption;
+
+    #[test]
+    fn test_key_file_rotation() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let db = ProvenanceDb::new(dir.path().join("p.db"), &DbEncryption::default()).unwrap();
+        let key_file = dir.path().join("signing.key");
+        let source = SigningKeySource::new(None, Some(&key_file));
+
+        let first = source.load(false, &db).unwrap();
+        assert_eq!(source.load(false, &db).unwrap(), first);
+
+        let second = source.load(true, &db).unwrap();
+        assert_ne!(second, first);
+        assert_eq!(source.load(false, &db).unwrap(), second);
+
+        let keys = db.list_signing_keys().unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].public_key_hex, second.public_key_hex);
+        assert!(keys[0].retired_at.is_none());
+        assert_eq!(keys[1].public_key_hex, first.public_key_hex);
+        assert!(keys[1].retired_at.is_some());
+    }
+}
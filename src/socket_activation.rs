@@ -0,0 +1,54 @@
+//! systemd socket activation: listening sockets handed over through `LISTEN_FDS`
+
+use anyhow::{Context, Result};
+use socket2::{Domain, SockAddr, Socket};
+use std::mem::ManuallyDrop;
+use std::os::fd::{FromRawFd, RawFd};
+
+/// First descriptor passed by systemd (`SD_LISTEN_FDS_START`)
+const LISTEN_FDS_START: RawFd = 3;
+
+pub enum ActivatedListener {
+    Tcp(tokio::net::TcpListener),
+    Unix(tokio::net::UnixListener),
+}
+
+/// Descriptors systemd passed to this process, empty when it wasn't
+/// socket-activated. The variables are cleared so they don't leak into
+/// processes we spawn.
+pub fn listen_fds() -> Result<Vec<RawFd>> {
+    let (Ok(pid), Ok(count)) = (std::env::var("LISTEN_PID"), std::env::var("LISTEN_FDS")) else {
+        return Ok(vec![]);
+    };
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+    if pid.parse::<u32>().ok() != Some(std::process::id()) {
+        return Ok(vec![]);
+    }
+    let count: RawFd = count
+        .parse()
+        .with_context(|| format!("Invalid LISTEN_FDS `{count}`"))?;
+    Ok((LISTEN_FDS_START..LISTEN_FDS_START + count).collect())
+}
+
+/// Address an inherited socket listens on, without taking it over
+pub fn local_addr(fd: RawFd) -> Option<SockAddr> {
+    let socket = ManuallyDrop::new(unsafe { Socket::from_raw_fd(fd) });
+    socket.local_addr().ok()
+}
+
+/// Take over an inherited listening socket
+pub fn take_listener(fd: RawFd) -> Result<ActivatedListener> {
+    let addr = local_addr(fd).with_context(|| format!("Inherited fd {fd} is not a socket"))?;
+    let socket = unsafe { Socket::from_raw_fd(fd) };
+    socket.set_nonblocking(true)?;
+    let listener = if addr.domain() == Domain::UNIX {
+        let listener: std::os::unix::net::UnixListener = socket.into();
+        ActivatedListener::Unix(tokio::net::UnixListener::from_std(listener)?)
+    } else {
+        let listener: std::net::TcpListener = socket.into();
+        ActivatedListener::Tcp(tokio::net::TcpListener::from_std(listener)?)
+    };
+    Ok(listener)
+}
@@ -0,0 +1,101 @@
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tokio_rustls::rustls::{
+    crypto::ring::default_provider,
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    ServerConfig,
+};
+
+use crate::utils::{load_certs, load_private_key};
+
+/// The certificate served over HTTPS, swapped in place when the files are
+/// reloaded so renewed certificates don't need a restart.
+#[derive(Debug)]
+pub struct ReloadableCert {
+    cert_file: PathBuf,
+    key_file: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ReloadableCert {
+    pub fn load(cert_file: &Path, key_file: &Path) -> Result<Self> {
+        Ok(Self {
+            cert_file: cert_file.to_path_buf(),
+            key_file: key_file.to_path_buf(),
+            current: RwLock::new(Arc::new(read_certified_key(cert_file, key_file)?)),
+        })
+    }
+
+    /// Re-read the certificate and key, keeping the old pair if either is invalid
+    pub fn reload(&self) -> Result<()> {
+        let certified_key = read_certified_key(&self.cert_file, &self.key_file)?;
+        *self.current.write().unwrap() = Arc::new(certified_key);
+        Ok(())
+    }
+
+    pub fn server_config(self: &Arc<Self>) -> Arc<ServerConfig> {
+        let mut config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_cert_resolver(self.clone());
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Arc::new(config)
+    }
+
+    #[cfg(unix)]
+    pub fn reload_on_sighup(self: Arc<Self>) -> Result<()> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = signal(SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                match self.reload() {
+                    Ok(()) => info!("Reloaded TLS certificate {}", self.cert_file.display()),
+                    Err(err) => error!("Failed to reload TLS certificate, {err}"),
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for ReloadableCert {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+fn read_certified_key(cert_file: &Path, key_file: &Path) -> Result<CertifiedKey> {
+    let certs = load_certs(cert_file)?;
+    let key = load_private_key(key_file)?;
+    CertifiedKey::from_der(certs, key, &default_provider())
+        .map_err(|err| anyhow!("Invalid TLS certificate or key, {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn test_reload_cert() {
+        let data = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+        let dir = assert_fs::TempDir::new().unwrap();
+        let (cert_file, key_file) = (dir.child("cert.pem"), dir.child("key.pem"));
+        cert_file.write_file(&data.join("cert.pem")).unwrap();
+        key_file.write_file(&data.join("key_pkcs8.pem")).unwrap();
+
+        let cert = ReloadableCert::load(cert_file.path(), key_file.path()).unwrap();
+        let before = cert.current.read().unwrap().cert.clone();
+
+        // A half-written renewal keeps the old pair in place
+        cert_file.write_file(&data.join("cert_ecdsa.pem")).unwrap();
+        assert!(cert.reload().is_err());
+        assert_eq!(cert.current.read().unwrap().cert, before);
+
+        key_file.write_file(&data.join("key_ecdsa.pem")).unwrap();
+        cert.reload().unwrap();
+        assert_ne!(cert.current.read().unwrap().cert, before);
+    }
+}
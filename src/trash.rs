@@ -0,0 +1,232 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::provenance::ProvenanceDb;
+
+/// How often the background sweeper purges expired trash items.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// A deleted file or directory, kept as `<trash_dir>/<id>` next to a
+/// `<trash_dir>/<id>.json` sidecar holding this record.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashItem {
+    pub id: String,
+    pub original_path: PathBuf,
+    pub is_dir: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    pub deleted_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_by: Option<String>,
+}
+
+/// Server-managed recycle bin. It must be on the same filesystem as the
+/// served directory, since items are moved in and out with a rename.
+#[derive(Debug, Clone)]
+pub struct Trash {
+    dir: PathBuf,
+}
+
+impl Trash {
+    pub fn new(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create trash dir `{}`", dir.display()))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Where the content of item `id` is kept
+    pub fn item_path(&self, id: &str) -> PathBuf {
+        self.dir.join(id)
+    }
+
+    fn sidecar_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+
+    /// Move `path` into the trash
+    pub async fn put(&self, path: &Path, is_dir: bool, user: Option<&str>) -> Result<TrashItem> {
+        let id = Uuid::new_v4().to_string();
+        let size = match is_dir {
+            true => None,
+            false => Some(fs::metadata(path).await?.len()),
+        };
+        let item = TrashItem {
+            id: id.clone(),
+            original_path: path.to_path_buf(),
+            is_dir,
+            size,
+            deleted_at: Utc::now().to_rfc3339(),
+            deleted_by: user.map(|v| v.to_string()),
+        };
+        fs::write(self.sidecar_path(&id), serde_json::to_vec(&item)?).await?;
+        if let Err(err) = fs::rename(path, self.item_path(&id)).await {
+            let _ = fs::remove_file(self.sidecar_path(&id)).await;
+            return Err(err)
+                .with_context(|| format!("Failed to move `{}` to trash", path.display()));
+        }
+        Ok(item)
+    }
+
+    /// The item with `id`, None for unknown or malformed ids
+    pub async fn get(&self, id: &str) -> Result<Option<TrashItem>> {
+        if Uuid::parse_str(id).is_err() {
+            return Ok(None);
+        }
+        match fs::read(self.sidecar_path(id)).await {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// All items, most recently deleted first
+    pub async fn list(&self) -> Result<Vec<TrashItem>> {
+        let mut items = vec![];
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let Some(id) = name.to_str().and_then(|v| v.strip_suffix(".json")) else {
+                continue;
+            };
+            match self.get(id).await {
+                Ok(Some(item)) => items.push(item),
+                Ok(None) => {}
+                Err(err) => warn!("Ignoring unreadable trash item `{id}`, {err}"),
+            }
+        }
+        items.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+        Ok(items)
+    }
+
+    /// Move `item` back to its original path, which must not exist
+    pub async fn restore(&self, item: &TrashItem) -> Result<()> {
+        if fs::symlink_metadata(&item.original_path).await.is_ok() {
+            bail!("`{}` already exists", item.original_path.display());
+        }
+        if let Some(parent) = item.original_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::rename(self.item_path(&item.id), &item.original_path).await?;
+        fs::remove_file(self.sidecar_path(&item.id)).await?;
+        Ok(())
+    }
+
+    /// Delete `item` for good
+    pub async fn remove(&self, item: &TrashItem) -> Result<()> {
+        let path = self.item_path(&item.id);
+        match item.is_dir {
+            true => fs::remove_dir_all(&path).await,
+            false => fs::remove_file(&path).await,
+        }
+        .or_else(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => Ok(()),
+            _ => Err(anyhow!("Failed to purge `{}`, {err}", path.display())),
+        })?;
+        fs::remove_file(self.sidecar_path(&item.id)).await?;
+        Ok(())
+    }
+
+    /// Delete every item deleted more than `older_than` ago (all of them for None)
+    pub async fn purge(&self, older_than: Option<Duration>) -> Result<usize> {
+        let cutoff = match older_than {
+            Some(age) => Utc::now() - chrono::Duration::from_std(age)?,
+            None => DateTime::<Utc>::MAX_UTC,
+        };
+        let mut purged = 0;
+        for item in self.list().await? {
+            let deleted_at = DateTime::parse_from_rfc3339(&item.deleted_at)?;
+            if deleted_at <= cutoff {
+                self.remove(&item).await?;
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+
+    pub fn spawn_sweeper(self, retention: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(SWEEP_INTERVAL);
+            loop {
+                ticker.tick().await;
+                match self.purge(Some(retention)).await {
+                    Ok(0) => {}
+                    Ok(count) => info!("Trash sweep purged {count} expired item(s)"),
+                    Err(err) => error!("Trash sweep failed, {err}"),
+                }
+            }
+        });
+    }
+}
+
+/// Delete `path` for good, or move it into `trash` when there is one.
+/// Provenance follows the file into the trash so a restore brings it back
+/// intact.
+pub async fn delete_path(
+    path: &Path,
+    is_dir: bool,
+    user: Option<&str>,
+    trash: Option<&Trash>,
+    provenance_db: &ProvenanceDb,
+) -> Result<()> {
+    if let Some(path_str) = path.to_str() {
+        provenance_db.forget_file_owners(path_str)?;
+        // The trash keeps them, for a restore
+        if trash.is_none() {
+            provenance_db.forget_dead_properties(path_str)?;
+        }
+    }
+    match (trash, is_dir) {
+        (Some(trash), _) => {
+            let item = trash.put(path, is_dir, user).await?;
+            let trashed_path = trash.item_path(&item.id);
+            if let (Some(old_path), Some(new_path)) = (path.to_str(), trashed_path.to_str()) {
+                provenance_db.update_artifact_path(old_path, new_path)?;
+            }
+        }
+        (None, true) => fs::remove_dir_all(path).await?,
+        (None, false) => fs::remove_file(path).await?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[tokio::test]
+    async fn test_trash_roundtrip() {
+        let root = assert_fs::TempDir::new().unwrap();
+        let file = root.child("docs/a.txt");
+        file.write_str("abc").unwrap();
+        let trash = Trash::new(&root.path().join(".trash")).unwrap();
+
+        let item = trash.put(file.path(), false, Some("user")).await.unwrap();
+        assert!(!file.path().exists());
+        assert_eq!(item.size, Some(3));
+        assert_eq!(trash.list().await.unwrap().len(), 1);
+        assert!(trash.get("../docs").await.unwrap().is_none());
+
+        file.write_str("new").unwrap();
+        assert!(trash.restore(&item).await.is_err());
+        std::fs::remove_file(file.path()).unwrap();
+        trash.restore(&item).await.unwrap();
+        assert_eq!(std::fs::read_to_string(file.path()).unwrap(), "abc");
+        assert!(trash.list().await.unwrap().is_empty());
+
+        trash.put(file.path(), false, None).await.unwrap();
+        assert_eq!(
+            trash.purge(Some(Duration::from_secs(3600))).await.unwrap(),
+            0
+        );
+        assert_eq!(trash.purge(None).await.unwrap(), 1);
+        assert!(trash.list().await.unwrap().is_empty());
+    }
+}
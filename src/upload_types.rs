@@ -0,0 +1,126 @@
+use anyhow::{anyhow, bail, Result};
+use std::str::FromStr;
+
+use crate::utils::parse_size;
+
+/// File extensions like `exe` or `.tar.gz`, matched against the end of file
+/// names regardless of case
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExtensionList {
+    extensions: Vec<String>,
+}
+
+impl ExtensionList {
+    pub fn new(extensions: &[&str]) -> Result<Self> {
+        let extensions = extensions
+            .iter()
+            .map(|v| parse_extension(v))
+            .collect::<Result<_>>()?;
+        Ok(Self { extensions })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.extensions.is_empty()
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.extensions.iter().any(|v| has_extension(name, v))
+    }
+}
+
+/// A size limit for one type of file written as `<extension>:<size>`,
+/// e.g. `mp4:2G`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeLimit {
+    pub extension: String,
+    pub limit: u64,
+}
+
+impl FromStr for TypeLimit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (extension, size) = s
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow!("Invalid upload type limit `{s}`, expected <ext>:<size>"))?;
+        let limit = parse_size(size)
+            .ok_or_else(|| anyhow!("Invalid upload type limit `{size}`, e.g. 500M or 2G"))?;
+        Ok(Self {
+            extension: parse_extension(extension)?,
+            limit,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TypeLimits {
+    rules: Vec<TypeLimit>,
+}
+
+impl TypeLimits {
+    pub fn new(rules: &[&str]) -> Result<Self> {
+        let mut parsed: Vec<TypeLimit> = vec![];
+        for rule in rules {
+            let rule: TypeLimit = rule.parse()?;
+            if parsed.iter().any(|v| v.extension == rule.extension) {
+                bail!("Duplicate upload type limit `{}`", rule.extension);
+            }
+            parsed.push(rule);
+        }
+        Ok(Self { rules: parsed })
+    }
+
+    /// The limit for files named `name`. The longest matching extension
+    /// wins, so `tar.gz` takes over from `gz`.
+    pub fn limit_for(&self, name: &str) -> Option<u64> {
+        self.rules
+            .iter()
+            .filter(|rule| has_extension(name, &rule.extension))
+            .max_by_key(|rule| rule.extension.len())
+            .map(|rule| rule.limit)
+    }
+}
+
+fn parse_extension(value: &str) -> Result<String> {
+    let extension = value.trim().trim_start_matches('.').to_ascii_lowercase();
+    if extension.is_empty() || extension.contains(['/', '\\']) {
+        bail!("Invalid file extension `{value}`, e.g. exe or tar.gz");
+    }
+    Ok(extension)
+}
+
+fn has_extension(name: &str, extension: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name.strip_suffix(extension)
+        .is_some_and(|v| v.len() > 1 && v.ends_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extension_list() {
+        let list = ExtensionList::new(&["exe", ".Tar.GZ"]).unwrap();
+        assert!(list.matches("setup.EXE"));
+        assert!(list.matches("backup.tar.gz"));
+        assert!(!list.matches("backup.gz"));
+        assert!(!list.matches("exe"));
+        assert!(!list.matches(".exe"));
+        assert!(!list.matches("notes.exe.txt"));
+        assert!(ExtensionList::new(&["."]).is_err());
+        assert!(ExtensionList::new(&["a/b"]).is_err());
+    }
+
+    #[test]
+    fn test_type_limits() {
+        let limits = TypeLimits::new(&["gz:1M", "tar.gz:1G", "mp4:0"]).unwrap();
+        assert_eq!(limits.limit_for("a.gz"), Some(1 << 20));
+        assert_eq!(limits.limit_for("a.tar.gz"), Some(1 << 30));
+        assert_eq!(limits.limit_for("clip.MP4"), Some(0));
+        assert_eq!(limits.limit_for("a.txt"), None);
+        assert!(TypeLimits::new(&["mp4"]).is_err());
+        assert!(TypeLimits::new(&["mp4:1X"]).is_err());
+        assert!(TypeLimits::new(&["mp4:1G", ".MP4:2G"]).is_err());
+    }
+}
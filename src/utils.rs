@@ -1,4 +1,7 @@
-use anyhow::{anyhow, Context, Result};
+#[cfg(feature = "tls")]
+use anyhow::Context;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
 use chrono::{DateTime, Utc};
 #[cfg(feature = "tls")]
 use rustls_pki_types::{CertificateDer, PrivateKeyDer};
@@ -138,6 +141,68 @@ pub fn parse_range(range: &str, size: u64) -> Option<Vec<(u64, u64)>> {
     Some(result)
 }
 
+/// Parse a byte size such as `512`, `64K`, `10M` or `2G` (powers of 1024)
+pub fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value, ""),
+    };
+    let multiplier: u64 = match unit.to_ascii_uppercase().trim_end_matches(['B', 'I']) {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// The sha-256 entry of an RFC 9530 `Content-Digest` or `Repr-Digest` field
+/// as hex, None when the field only lists other algorithms
+pub fn parse_sha256_digest(value: &str) -> Result<Option<String>> {
+    for item in value.split(',') {
+        let (algorithm, digest) = item
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid digest `{}`", item.trim()))?;
+        if !algorithm.trim().eq_ignore_ascii_case("sha-256") {
+            continue;
+        }
+        let digest = digest
+            .trim()
+            .strip_prefix(':')
+            .and_then(|v| v.strip_suffix(':'))
+            .and_then(|v| STANDARD.decode(v).ok())
+            .filter(|v| v.len() == 32)
+            .ok_or_else(|| anyhow!("Invalid sha-256 digest `{}`", digest.trim()))?;
+        return Ok(Some(hex::encode(digest)));
+    }
+    Ok(None)
+}
+
+/// A `Content-Digest` or `Repr-Digest` field for a hex sha-256 digest
+pub fn sha256_digest_field(sha256_hex: &str) -> Option<String> {
+    let digest = hex::decode(sha256_hex).ok()?;
+    Some(format!("sha-256=:{}:", STANDARD.encode(digest)))
+}
+
+/// Escape text for use in HTML content and quoted attributes
+pub fn escape_html(v: &str) -> String {
+    let mut output = String::with_capacity(v.len());
+    for c in v.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -183,4 +248,42 @@ mod tests {
         assert_eq!(parse_range("bytes=0-199,", 500), None);
         assert_eq!(parse_range("bytes=0-199, 500-", 500), None);
     }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("64K"), Some(65536));
+        assert_eq!(parse_size("10M"), Some(10 * 1024 * 1024));
+        assert_eq!(parse_size("2gb"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("1KiB"), Some(1024));
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("1.5M"), None);
+        assert_eq!(parse_size("10X"), None);
+    }
+
+    #[test]
+    fn test_parse_sha256_digest() {
+        let hex = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        let field = sha256_digest_field(hex).unwrap();
+        assert_eq!(
+            field,
+            "sha-256=:LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=:"
+        );
+        assert_eq!(parse_sha256_digest(&field).unwrap().as_deref(), Some(hex));
+        let fields = format!("sha-512=:AAAA:, {field}");
+        assert_eq!(parse_sha256_digest(&fields).unwrap().as_deref(), Some(hex));
+        assert_eq!(parse_sha256_digest("sha-512=:AAAA:").unwrap(), None);
+        assert!(parse_sha256_digest("sha-256=:AAAA:").is_err());
+        assert!(parse_sha256_digest("sha-256").is_err());
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(escape_html("a.txt"), "a.txt");
+        assert_eq!(
+            escape_html(r#"<b class="x">'&'</b>"#),
+            "&lt;b class=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/b&gt;"
+        );
+    }
 }
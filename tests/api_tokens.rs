@@ -0,0 +1,110 @@
+mod digest_auth_util;
+mod fixtures;
+mod utils;
+
+use digest_auth_util::send_with_digest_auth;
+use fixtures::{server, Error};
+use serde_json::{json, Value};
+
+#[test]
+fn api_tokens() -> Result<(), Error> {
+    let db_dir = assert_fs::TempDir::new()?;
+    let db = db_dir.path().join("provenance.db");
+    let server = server([
+        "-a",
+        "admin:pass@/:rw",
+        "-a",
+        "user:pass@/dir1:rw,/dir2:rw",
+        "--provenance-db",
+        db.to_str().unwrap(),
+    ]);
+    let tokens_url = format!("{}__dufs__/tokens", server.url());
+
+    let resp = send_with_digest_auth(
+        fetch!(b"POST", &tokens_url).body(json!({ "scopes": ["/dir1:rw/"] }).to_string()),
+        "user",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 400);
+    let resp = send_with_digest_auth(
+        fetch!(b"POST", &tokens_url).body(
+            json!({ "name": "backup", "scopes": ["/dir1", "/dir3:rw"], "expires_in": "1d" })
+                .to_string(),
+        ),
+        "user",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 201);
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    let token = json["token"].as_str().unwrap().to_string();
+    let id = json["id"].as_str().unwrap().to_string();
+    assert_eq!(json["scopes"], "/dir1,/dir3:rw");
+    assert!(json["expires_at"].as_u64().is_some());
+
+    // Read-only within the scopes, and never beyond the user's own rules
+    let bearer = format!("Bearer {token}");
+    let resp = fetch!(b"GET", format!("{}dir1/", server.api_url()))
+        .header("Authorization", &bearer)
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    let resp = fetch!(b"GET", format!("{}dir1/?token={token}", server.api_url())).send()?;
+    assert_eq!(resp.status(), 200);
+    let resp = fetch!(b"PUT", format!("{}dir1/file", server.api_url()))
+        .header("Authorization", &bearer)
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    let resp = fetch!(b"GET", format!("{}dir2/", server.api_url()))
+        .header("Authorization", &bearer)
+        .send()?;
+    assert_eq!(resp.status(), 403);
+
+    // The Destination of COPY and MOVE is checked against the token too
+    let resp = fetch!(b"COPY", format!("{}dir1/test.txt", server.url()))
+        .header("Authorization", &bearer)
+        .header("Destination", format!("{}dir2/copy.txt", server.url()))
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    let resp = send_with_digest_auth(
+        fetch!(b"POST", &tokens_url).body(json!({ "scopes": ["/dir2:rw"] }).to_string()),
+        "user",
+        "pass",
+    )?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    let resp = fetch!(b"COPY", format!("{}dir2/test.txt", server.url()))
+        .header(
+            "Authorization",
+            format!("Bearer {}", json["token"].as_str().unwrap()),
+        )
+        .header("Destination", format!("{}dir2/copy.txt", server.url()))
+        .send()?;
+    assert_eq!(resp.status(), 204);
+
+    // Tokens can't create other tokens
+    let resp = fetch!(b"POST", &tokens_url)
+        .header("Authorization", &bearer)
+        .body("{}")
+        .send()?;
+    assert_eq!(resp.status(), 403);
+
+    let resp = send_with_digest_auth(fetch!(b"GET", &tokens_url), "user", "pass")?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["tokens"][1]["id"], id.as_str());
+    assert_eq!(json["tokens"][1]["name"], "backup");
+    assert!(json["tokens"][1].get("token").is_none());
+    let resp = send_with_digest_auth(fetch!(b"GET", &tokens_url), "admin", "pass")?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["tokens"], json!([]));
+
+    let resp = send_with_digest_auth(
+        fetch!(b"DELETE", format!("{tokens_url}/{id}")),
+        "user",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 204);
+    let resp = fetch!(b"GET", format!("{}dir1/", server.api_url()))
+        .header("Authorization", &bearer)
+        .send()?;
+    assert_eq!(resp.status(), 401);
+    Ok(())
+}
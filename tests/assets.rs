@@ -56,3 +56,47 @@ fn asset_js_with_prefix(
     );
     Ok(())
 }
+
+#[rstest]
+fn versioned_asset_is_immutable(server: TestServer) -> Result<(), Error> {
+    let url = format!(
+        "{}__dufs_v{}__/index.js",
+        server.url(),
+        env!("CARGO_PKG_VERSION")
+    );
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/javascript; charset=UTF-8"
+    );
+    assert_eq!(
+        resp.headers().get("cache-control").unwrap(),
+        "public, max-age=31536000, immutable"
+    );
+    let etag = resp.headers().get("etag").unwrap().clone();
+
+    let resp = reqwest::blocking::Client::new()
+        .get(&url)
+        .header("if-none-match", etag)
+        .send()?;
+    assert_eq!(resp.status(), 304);
+    Ok(())
+}
+
+#[rstest]
+fn versioned_asset_brotli(server: TestServer) -> Result<(), Error> {
+    let url = format!(
+        "{}__dufs_v{}__/index.js",
+        server.url(),
+        env!("CARGO_PKG_VERSION")
+    );
+    let resp = reqwest::blocking::Client::new()
+        .get(url)
+        .header("accept-encoding", "br")
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers().get("content-encoding").unwrap(), "br");
+    assert_eq!(resp.headers().get("vary").unwrap(), "Accept-Encoding");
+    Ok(())
+}
@@ -451,3 +451,462 @@ fn token_auth(#[with(&["-a", "user:pass@/"])] server: TestServer) -> Result<(),
     assert_eq!(resp.status(), 200);
     Ok(())
 }
+
+#[rstest]
+fn token_auth_scope(
+    #[with(&["-a", "user:pass@/:rw", "-a", "guest:pass@/dir2", "--allow-upload"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let tokengen = |path: &str, query: &str, user: &str| -> Result<_, Error> {
+        let url = format!("{}{path}?tokengen{query}", server.api_url());
+        Ok(fetch!(b"GET", &url).basic_auth(user, Some("pass")).send()?)
+    };
+    let token = tokengen("dir1/", "&perm=rw", "user")?.text()?;
+
+    let url = format!("{}dir1/file1?token={token}", server.api_url());
+    let resp = fetch!(b"PUT", &url).body(b"abc".to_vec()).send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = fetch!(b"GET", &url).send()?;
+    assert_eq!(resp.status(), 200);
+    // Nothing outside the folder of the link
+    let url = format!("{}dir2/file1?token={token}", server.api_url());
+    let resp = fetch!(b"PUT", &url).body(b"abc".to_vec()).send()?;
+    assert_eq!(resp.status(), 401);
+    let url = format!("{}index.html?token={token}", server.api_url());
+    let resp = fetch!(b"GET", &url).send()?;
+    assert_eq!(resp.status(), 401);
+
+    // Read-only by default
+    let token = tokengen("dir1/", "", "user")?.text()?;
+    let url = format!("{}dir1/file2?token={token}", server.api_url());
+    let resp = fetch!(b"PUT", &url).body(b"abc".to_vec()).send()?;
+    assert_eq!(resp.status(), 403);
+
+    // No more than the user has, for no longer than asked
+    assert_eq!(tokengen("dir2/", "&perm=rw", "guest")?.status(), 403);
+    assert_eq!(tokengen("dir1/", "&perm=all", "user")?.status(), 400);
+    let token = tokengen("dir1/", "&expires_in=1s", "user")?.text()?;
+    std::thread::sleep(std::time::Duration::from_millis(1500));
+    let url = format!("{}dir1/file1?token={token}", server.api_url());
+    let resp = fetch!(b"GET", &url).send()?;
+    assert_eq!(resp.status(), 401);
+    Ok(())
+}
+
+#[rstest]
+fn acl_grants(
+    #[with(&["--auth", "admin:pass@/:rw", "--auth", "alice:pass@/dir1"])] server: TestServer,
+) -> Result<(), Error> {
+    let file_url = format!("{}dir2/test.txt", server.api_url());
+    let acl_url = format!("{}dir2/?acl", server.api_url());
+    let resp = send_with_digest_auth(fetch!(b"GET", &file_url), "alice", "pass")?;
+    assert_eq!(resp.status(), 403);
+
+    let grant = |url: &str, body: &'static str| {
+        send_with_digest_auth(fetch!(b"POST", url).body(body), "admin", "pass")
+    };
+    let resp = grant(&acl_url, r#"{"user":"bob","perm":"ro"}"#)?;
+    assert_eq!(resp.status(), 400);
+    let resp = grant(&acl_url, r#"{"user":"alice","perm":"ro"}"#)?;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["entries"][0]["user"], "alice");
+    assert_eq!(json["entries"][0]["granted_by"], "admin");
+
+    let resp = send_with_digest_auth(fetch!(b"GET", &file_url), "alice", "pass")?;
+    assert_eq!(resp.status(), 200);
+    let resp = send_with_digest_auth(fetch!(b"PUT", &file_url).body("new"), "alice", "pass")?;
+    assert_eq!(resp.status(), 403);
+    let resp = send_with_digest_auth(fetch!(b"GET", &acl_url), "alice", "pass")?;
+    assert_eq!(resp.status(), 403);
+
+    let resp = grant(
+        &format!("{file_url}?acl"),
+        r#"{"user":"alice","perm":"rw"}"#,
+    )?;
+    assert_eq!(resp.status(), 200);
+    let resp = send_with_digest_auth(fetch!(b"PUT", &file_url).body("new"), "alice", "pass")?;
+    assert_eq!(resp.status(), 201);
+
+    let resp = send_with_digest_auth(
+        fetch!(b"DELETE", format!("{}dir2/?acl=alice", server.api_url())),
+        "admin",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 204);
+    let resp = send_with_digest_auth(
+        fetch!(b"GET", format!("{}dir2/index.html", server.api_url())),
+        "alice",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 403);
+    Ok(())
+}
+
+#[rstest]
+fn acl_grants_cant_manage_acls(
+    #[with(&["--auth", "admin:pass@/:rw", "--auth", "alice:pass@/dir1", "--auth", "bob:pass@/dir1"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let acl_url = format!("{}dir2/?acl", server.api_url());
+    for body in [
+        r#"{"user":"alice","perm":"rw"}"#,
+        r#"{"user":"bob","perm":"ro"}"#,
+    ] {
+        let req = fetch!(b"POST", &acl_url).body(body);
+        assert_eq!(send_with_digest_auth(req, "admin", "pass")?.status(), 200);
+    }
+
+    let revoke_bob = format!("{acl_url}=bob");
+    let resp = send_with_digest_auth(fetch!(b"DELETE", &revoke_bob), "alice", "pass")?;
+    assert_eq!(resp.status(), 403);
+    let resp = send_with_digest_auth(fetch!(b"GET", &revoke_bob), "alice", "pass")?;
+    assert_eq!(resp.status(), 403);
+    let resp = send_with_digest_auth(
+        fetch!(b"GET", format!("{}dir2/test.txt", server.api_url())),
+        "bob",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 200);
+    Ok(())
+}
+
+#[rstest]
+fn visibility_toggle(
+    #[with(&["--auth", "user:pass@/:rw", "--auth", "@/:ro"])] server: TestServer,
+) -> Result<(), Error> {
+    let set_visibility = |path: &str, visibility: &str| {
+        let url = format!("{}{path}?visibility={visibility}", server.api_url());
+        send_with_digest_auth(fetch!(b"PATCH", url), "user", "pass")
+    };
+    let resp = fetch!(
+        b"PATCH",
+        format!("{}dir1?visibility=private", server.api_url())
+    )
+    .send()?;
+    assert_eq!(resp.status(), 401);
+    let resp = set_visibility("dir1", "hidden")?;
+    assert_eq!(resp.status(), 400);
+    let resp = set_visibility("dir1", "private")?;
+    assert_eq!(resp.status(), 200);
+
+    let resp = reqwest::blocking::get(server.api_url())?;
+    let paths = utils::retrieve_index_paths(&resp.text()?);
+    assert!(!paths.contains("dir1/"));
+    assert!(paths.contains("dir2/"));
+    let resp = reqwest::blocking::get(format!("{}dir1/test.txt", server.api_url()))?;
+    assert_eq!(resp.status(), 401);
+    let resp = reqwest::blocking::get(format!("{}?q=test.txt&simple", server.api_url()))?;
+    assert!(!resp.text()?.contains("dir1/"));
+
+    let url = format!("{}dir1/test.txt", server.api_url());
+    let resp = send_with_digest_auth(fetch!(b"GET", &url), "user", "pass")?;
+    assert_eq!(resp.status(), 200);
+    let resp = fetch!(b"GET", server.api_url())
+        .basic_auth("user", Some("pass"))
+        .send()?;
+    let json: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    let dir1 = json["paths"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|v| v["name"] == "dir1")
+        .unwrap();
+    assert_eq!(dir1["visibility"], "private");
+
+    let resp = set_visibility("dir1/test.txt", "public")?;
+    assert_eq!(resp.status(), 200);
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.status(), 200);
+    Ok(())
+}
+
+#[rstest]
+fn user_signing_key(
+    #[with(&["--auth", "user:pass@/:rw"])] server: TestServer,
+) -> Result<(), Error> {
+    use secp256k1::{Message, SecretKey, SECP256K1};
+
+    let secret_key = SecretKey::from_slice(&[5u8; 32])?;
+    let public_key_hex = hex::encode(secret_key.public_key(SECP256K1).serialize());
+    let key_url = format!("{}__dufs__/user-key", server.url());
+
+    let resp = fetch!(b"GET", &key_url).send()?;
+    assert_eq!(resp.status(), 401);
+    let resp = fetch!(b"PUT", &key_url)
+        .basic_auth("user", Some("pass"))
+        .body(r#"{"public_key_hex":"00"}"#)
+        .send()?;
+    assert_eq!(resp.status(), 400);
+    let resp = fetch!(b"PUT", &key_url)
+        .basic_auth("user", Some("pass"))
+        .body(serde_json::json!({ "public_key_hex": public_key_hex }).to_string())
+        .send()?;
+    assert_eq!(resp.status(), 200);
+
+    let url = format!("{}signed.txt", server.api_url());
+    let resp = fetch!(b"PUT", &url)
+        .basic_auth("user", Some("pass"))
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let mint: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(mint["creator_pubkey_hex"], public_key_hex);
+
+    let sign = |key: &SecretKey| {
+        let hash = hex::decode(mint["event_hash"].as_str().unwrap()).unwrap();
+        let message = Message::from_digest_slice(&hash).unwrap();
+        let signature = SECP256K1.sign_ecdsa(&message, key).serialize_der();
+        serde_json::json!({ "creator_sig_hex": hex::encode(signature) }).to_string()
+    };
+    let resp = fetch!(b"POST", format!("{url}?sign"))
+        .basic_auth("user", Some("pass"))
+        .body(sign(&SecretKey::from_slice(&[6u8; 32])?))
+        .send()?;
+    assert_eq!(resp.status(), 400);
+    let resp = fetch!(b"POST", format!("{url}?sign"))
+        .basic_auth("user", Some("pass"))
+        .body(sign(&secret_key))
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    let resp = fetch!(b"POST", format!("{url}?sign"))
+        .basic_auth("user", Some("pass"))
+        .body(sign(&secret_key))
+        .send()?;
+    assert_eq!(resp.status(), 409);
+
+    let resp = fetch!(b"DELETE", &key_url)
+        .basic_auth("user", Some("pass"))
+        .send()?;
+    assert_eq!(resp.status(), 204);
+    Ok(())
+}
+
+#[rstest]
+fn auth_zip_selection(
+    #[with(&["--auth", "user:pass@/dir1:ro", "--allow-archive"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"POST", format!("{}api/?zip", server.url()))
+        .body(r#"["dir1", "dir2", "test.txt"]"#)
+        .basic_auth("user", Some("pass"))
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    let body = resp.bytes()?.to_vec();
+    let names = tokio::runtime::Runtime::new()?.block_on(async move {
+        let zip = async_zip::base::read::mem::ZipFileReader::new(body)
+            .await
+            .unwrap();
+        zip.file()
+            .entries()
+            .iter()
+            .map(|v| v.filename().as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    });
+    assert!(names.contains(&"dir1/test.txt".to_string()));
+    assert!(names.iter().all(|v| v.starts_with("dir1/")));
+    Ok(())
+}
+
+#[rstest]
+fn auth_read_flags_only_authorize_themselves(
+    #[with(&["--auth", "user:pass@/dir1:ro", "--allow-archive"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}dir1/?acl&zip", server.api_url());
+    let resp = fetch!(b"POST", &url)
+        .body(r#"{"user":"user","perm":"rw"}"#)
+        .basic_auth("user", Some("pass"))
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    let url = format!("{}dir1/test.txt?transfer&zip", server.api_url());
+    let resp = fetch!(b"POST", &url)
+        .body(r#"{"to":"user"}"#)
+        .basic_auth("user", Some("pass"))
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    let url = format!("{}dir1/test.txt?star", server.api_url());
+    let resp = fetch!(b"PUT", &url)
+        .body(b"mine".to_vec())
+        .basic_auth("user", Some("pass"))
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    Ok(())
+}
+
+#[rstest]
+fn auth_partial_tree(
+    #[with(&["--auth", "user:pass@/dir1:rw,/dir2:rw"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"GET", format!("{}api/?tree", server.url()))
+        .basic_auth("user", Some("pass"))
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = resp.json()?;
+    assert_eq!(json["dirs"], 2);
+    assert_eq!(json["files"], 0);
+    assert_eq!(json["children"][0]["name"], "dir1");
+    assert_eq!(json["children"][1]["name"], "dir2");
+    Ok(())
+}
+
+#[rstest]
+fn auth_lockout(
+    #[with(&["--auth", "admin:admin@/:rw", "--auth", "mallory:pass@/:rw", "--max-login-failures", "2"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}api/", server.url());
+    let resp = fetch!(b"GET", &url)
+        .basic_auth("mallory", Some("nope"))
+        .send()?;
+    assert_eq!(resp.status(), 401);
+    let resp = fetch!(b"GET", &url)
+        .basic_auth("mallory", Some("nope"))
+        .send()?;
+    assert_eq!(resp.status(), 429);
+    // The right password doesn't help until the lockout ends
+    let resp = fetch!(b"GET", &url)
+        .basic_auth("mallory", Some("pass"))
+        .send()?;
+    assert_eq!(resp.status(), 429);
+    assert_eq!(resp.headers().get("retry-after").unwrap(), "30");
+    let resp = fetch!(b"GET", &url)
+        .basic_auth("admin", Some("admin"))
+        .send()?;
+    assert_eq!(resp.status(), 200);
+
+    let resp = fetch!(
+        b"GET",
+        format!("{}__dufs__/auth-log?user=mallory&limit=2", server.url())
+    )
+    .basic_auth("admin", Some("admin"))
+    .send()?;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = resp.json()?;
+    assert_eq!(json["entries"][0]["outcome"], "locked");
+    assert_eq!(json["entries"][0]["path"], "/");
+    assert_eq!(json["entries"][0]["ip"], "127.0.0.1");
+    assert_eq!(json["entries"][1]["outcome"], "failure");
+    Ok(())
+}
+
+#[rstest]
+fn session_login(
+    #[with(&["--auth", "alice:pass@/dir1:rw", "--allow-upload"])] server: TestServer,
+) -> Result<(), Error> {
+    let login_url = format!("{}__dufs__/login", server.url());
+    let resp = fetch!(b"POST", &login_url)
+        .body(r#"{"user": "alice", "password": "wrong"}"#)
+        .send()?;
+    assert_eq!(resp.status(), 401);
+    assert!(resp.headers().get("www-authenticate").is_none());
+    assert!(resp.headers().get("set-cookie").is_none());
+
+    let resp = fetch!(b"POST", &login_url)
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body("user=alice&password=pass")
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = resp.json()?;
+    assert_eq!(json["user"], "alice");
+    let resp = fetch!(b"POST", &login_url)
+        .body(r#"{"user": "alice", "password": "pass"}"#)
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    let cookie = resp.headers()["set-cookie"].to_str()?;
+    assert!(cookie.contains("HttpOnly"));
+    assert!(cookie.contains("Max-Age=86400"));
+    let session = cookie.split(';').next().unwrap().to_string();
+
+    let resp = fetch!(b"PUT", format!("{}dir1/from-session.txt", server.api_url()))
+        .header("cookie", &session)
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = fetch!(b"PUT", format!("{}dir1/from-session.txt", server.api_url()))
+        .header("cookie", "dufs_session=forged")
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 401);
+
+    let resp = fetch!(b"POST", format!("{}__dufs__/logout", server.url()))
+        .header("cookie", &session)
+        .send()?;
+    assert_eq!(resp.status(), 204);
+    let cookie = resp.headers()["set-cookie"].to_str()?;
+    assert!(cookie.starts_with("dufs_session=;"));
+    assert!(cookie.contains("Max-Age=0"));
+    Ok(())
+}
+
+#[rstest]
+fn auth_limits(
+    #[with(&["--auth", "user:pass@/dir1:w,/dir2:rw,nodelete,noarchive", "--allow-upload", "--allow-delete", "--allow-archive"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}dir1/new.txt", server.api_url());
+    let resp = send_with_digest_auth(fetch!(b"PUT", &url).body(b"abc".to_vec()), "user", "pass")?;
+    assert_eq!(resp.status(), 201);
+    // Upload-only: no reading, overwriting or deleting
+    let resp = send_with_digest_auth(fetch!(b"GET", &url), "user", "pass")?;
+    assert_eq!(resp.status(), 403);
+    let resp = send_with_digest_auth(fetch!(b"PUT", &url).body(b"def".to_vec()), "user", "pass")?;
+    assert_eq!(resp.status(), 403);
+    let resp = send_with_digest_auth(fetch!(b"DELETE", &url), "user", "pass")?;
+    assert_eq!(resp.status(), 403);
+    for range in ["bytes=0-", "append"] {
+        let req = fetch!(b"PATCH", &url)
+            .header("X-Update-Range", range)
+            .body(b"def".to_vec());
+        assert_eq!(send_with_digest_auth(req, "user", "pass")?.status(), 403);
+    }
+
+    let url = format!("{}dir2/", server.api_url());
+    let resp = send_with_digest_auth(fetch!(b"GET", &url), "user", "pass")?;
+    assert_eq!(resp.status(), 200);
+    let resp = send_with_digest_auth(fetch!(b"GET", format!("{url}?zip")), "user", "pass")?;
+    assert_eq!(resp.status(), 403);
+    let resp = send_with_digest_auth(fetch!(b"DELETE", format!("{url}test.html")), "user", "pass")?;
+    assert_eq!(resp.status(), 403);
+    // `nodelete` keeps bytes from being rewritten, though not appended to
+    let req = fetch!(b"PATCH", format!("{url}test.html"))
+        .header("X-Update-Range", "bytes=0-")
+        .body(b"abc".to_vec());
+    assert_eq!(send_with_digest_auth(req, "user", "pass")?.status(), 403);
+    let req = fetch!(b"PATCH", format!("{url}test.html?delta")).body(b"NDD1".to_vec());
+    assert_eq!(send_with_digest_auth(req, "user", "pass")?.status(), 403);
+    let req = fetch!(b"PATCH", format!("{url}test.html"))
+        .header("X-Update-Range", "append")
+        .body(b"abc".to_vec());
+    assert_eq!(send_with_digest_auth(req, "user", "pass")?.status(), 204);
+    let resp = send_with_digest_auth(
+        fetch!(b"PUT", format!("{url}new.txt")).body(b"abc".to_vec()),
+        "user",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 201);
+    Ok(())
+}
+
+#[rstest]
+fn auth_limits_copy_move_dest(
+    #[with(&["--auth", "user:pass@/dir1:w,/dir2:rw,nodelete,/dir3:rw", "--allow-upload", "--allow-delete"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let source = format!("{}dir3/test.txt", server.url());
+    // Existing files under upload-only and `nodelete` grants stay as they are
+    for dest in ["dir1/test.txt", "dir2/test.txt"] {
+        let destination = format!("{}{dest}", server.url());
+        let copy = fetch!(b"COPY", &source).header("Destination", &destination);
+        let moved = fetch!(b"MOVE", &source).header("Destination", &destination);
+        for req in [copy, moved] {
+            let resp = send_with_digest_auth(req.header("Overwrite", "T"), "user", "pass")?;
+            assert_eq!(resp.status(), 403);
+            let content = std::fs::read_to_string(server.path().join(dest))?;
+            assert_eq!(content, format!("This is {dest}"));
+        }
+    }
+    assert!(server.path().join("dir3/test.txt").exists());
+    let req =
+        fetch!(b"COPY", &source).header("Destination", format!("{}dir2/new.txt", server.url()));
+    assert_eq!(send_with_digest_auth(req, "user", "pass")?.status(), 204);
+    Ok(())
+}
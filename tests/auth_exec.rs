@@ -0,0 +1,90 @@
+mod fixtures;
+mod utils;
+
+use assert_fs::{prelude::*, TempDir};
+use fixtures::{server, Error};
+use std::os::unix::fs::PermissionsExt;
+
+const CHECK_USER: &str = r#"#!/bin/sh
+read -r user
+read -r pass
+if [ "$user" = alice ] && [ "$pass" = secret ]; then
+  echo '{"paths": ["/dir1:rw", "/dir2"]}'
+  exit 0
+fi
+exit 1
+"#;
+
+fn check_user_script(dir: &TempDir) -> Result<String, Error> {
+    let script = dir.child("check-user");
+    script.write_str(CHECK_USER)?;
+    std::fs::set_permissions(script.path(), std::fs::Permissions::from_mode(0o755))?;
+    Ok(script.path().to_str().unwrap().to_string())
+}
+
+#[test]
+fn auth_exec() -> Result<(), Error> {
+    let dir = TempDir::new()?;
+    let script = check_user_script(&dir)?;
+    let server = server(["--auth-exec", &script, "--allow-upload"]);
+
+    // No rules but the command, so anonymous users are asked to log in
+    let resp = fetch!(b"GET", server.api_url()).send()?;
+    assert_eq!(resp.status(), 401);
+    let values: Vec<_> = resp.headers().get_all("www-authenticate").iter().collect();
+    assert_eq!(values.len(), 1);
+    assert!(values[0].to_str()?.starts_with("Basic"));
+
+    let url = format!("{}dir1/file1", server.api_url());
+    let resp = fetch!(b"PUT", &url)
+        .basic_auth("alice", Some("secret"))
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = fetch!(b"GET", format!("{}dir2/", server.api_url()))
+        .basic_auth("alice", Some("secret"))
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    let resp = fetch!(b"PUT", format!("{}dir2/file1", server.api_url()))
+        .basic_auth("alice", Some("secret"))
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 403);
+
+    let resp = fetch!(b"GET", &url)
+        .basic_auth("alice", Some("wrong"))
+        .send()?;
+    assert_eq!(resp.status(), 401);
+    let resp = fetch!(b"GET", &url)
+        .basic_auth("bob", Some("secret"))
+        .send()?;
+    assert_eq!(resp.status(), 401);
+    Ok(())
+}
+
+#[test]
+fn auth_exec_with_rules() -> Result<(), Error> {
+    let dir = TempDir::new()?;
+    let script = check_user_script(&dir)?;
+    let server = server(["--auth", "admin:admin@/:rw|@/dir3", "--auth-exec", &script]);
+
+    // Users in --auth don't go through the command
+    let resp = fetch!(b"GET", server.api_url())
+        .basic_auth("admin", Some("admin"))
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    let resp = fetch!(b"GET", format!("{}test.html", server.api_url()))
+        .basic_auth("alice", Some("secret"))
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    // Along with what anonymous users may access
+    let resp = fetch!(b"GET", format!("{}dir3/", server.api_url()))
+        .basic_auth("alice", Some("secret"))
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    let resp = fetch!(b"GET", format!("{}dir1/", server.api_url()))
+        .basic_auth("alice", Some("secret"))
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    Ok(())
+}
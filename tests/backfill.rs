@@ -0,0 +1,67 @@
+mod fixtures;
+mod utils;
+
+use assert_cmd::prelude::*;
+use fixtures::{server, tmpdir, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+use std::process::Command;
+
+const BACKFILL_PATH: &str = "__dufs__/backfill";
+
+#[rstest]
+fn backfill_mints_existing_files(server: TestServer) -> Result<(), Error> {
+    let manifest_url = format!("{}dir1/test.txt?manifest=json", server.api_url());
+    let resp = reqwest::blocking::get(&manifest_url)?;
+    assert_eq!(resp.status(), 404);
+
+    let resp = fetch!(b"POST", format!("{}{BACKFILL_PATH}", server.url())).send()?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = resp.json()?;
+    assert!(json["missing"].as_u64().unwrap() > 0);
+    assert_eq!(json["minted"], json["missing"]);
+    assert_eq!(json["failed"], 0);
+
+    let resp = reqwest::blocking::get(&manifest_url)?;
+    assert_eq!(resp.status(), 200);
+    let manifest: Value = resp.json()?;
+    assert_eq!(manifest["events"][0]["action"], "mint");
+
+    let resp = fetch!(b"POST", format!("{}{BACKFILL_PATH}", server.url())).send()?;
+    let json: Value = resp.json()?;
+    assert_eq!(json["missing"], 0);
+    Ok(())
+}
+
+#[rstest]
+fn backfill_requires_admin(
+    #[with(&["--auth", "admin:pass@/:rw", "--auth", "user:pass@/dir1:rw"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"POST", format!("{}{BACKFILL_PATH}", server.url()))
+        .basic_auth("user", Some("pass"))
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    Ok(())
+}
+
+#[test]
+fn backfill_command() -> Result<(), Error> {
+    let dir = tmpdir();
+    let db_dir = assert_fs::TempDir::new()?;
+    let db = db_dir.path().join("backfill.db");
+    let backfill = || {
+        Command::cargo_bin("node-drive")
+            .unwrap()
+            .arg("--provenance-db")
+            .arg(&db)
+            .arg(dir.path())
+            .args(["provenance", "backfill", "--jobs", "2"])
+            .assert()
+            .success()
+    };
+    let output = String::from_utf8(backfill().get_output().stdout.clone())?;
+    assert!(output.starts_with("Minted "), "{output}");
+    assert!(!output.contains("Minted 0 of"));
+    backfill().stdout("Minted 0 of 0 files without provenance\n");
+    Ok(())
+}
@@ -82,3 +82,41 @@ fn validate_printed_urls(tmpdir: TempDir, port: u16, #[case] args: &[&str]) -> R
 
     Ok(())
 }
+
+#[cfg(unix)]
+#[rstest]
+fn bind_unix_socket(tmpdir: TempDir, port: u16) -> Result<(), Error> {
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+
+    let socket_path = tmpdir.path().join("node-drive.sock");
+    let mut child = Command::cargo_bin("node-drive")?
+        .arg(tmpdir.path())
+        .arg("-p")
+        .arg(port.to_string())
+        .arg("-b")
+        .arg(format!("unix:{}", socket_path.display()))
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let start_wait = std::time::Instant::now();
+    let mut stream = loop {
+        match UnixStream::connect(&socket_path) {
+            Ok(stream) => break stream,
+            Err(_) if start_wait.elapsed().as_secs() < 2 => {
+                std::thread::sleep(std::time::Duration::from_millis(250))
+            }
+            Err(e) => panic!("timeout waiting for {}: {e}", socket_path.display()),
+        }
+    };
+    stream.write_all(
+        b"GET /__dufs__/health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    assert!(response.starts_with("HTTP/1.1 200"));
+
+    child.kill()?;
+
+    Ok(())
+}
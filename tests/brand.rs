@@ -0,0 +1,41 @@
+mod fixtures;
+
+use assert_fs::TempDir;
+use fixtures::{server, tmpdir, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+
+#[rstest]
+fn brand_in_listing(
+    #[with(&["--brand-name", "Acme Files", "--brand-logo", "/logo.svg", "--theme-color", "#0b5fff"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(format!("{}dir1/", server.api_url()))?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = resp.json()?;
+    assert_eq!(json["brand"]["name"], "Acme Files");
+    assert_eq!(json["brand"]["logo"], "/logo.svg");
+    assert_eq!(json["brand"]["theme_color"], "#0b5fff");
+    Ok(())
+}
+
+#[rstest]
+fn brand_defaults(server: TestServer) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(server.api_url())?;
+    let json: Value = resp.json()?;
+    assert_eq!(json["brand"]["name"], "Node Drive");
+    assert!(json["brand"].get("logo").is_none());
+    assert!(json["brand"].get("theme_color").is_none());
+    Ok(())
+}
+
+#[rstest]
+fn invalid_theme_color(tmpdir: TempDir) -> Result<(), Error> {
+    assert_cmd::Command::cargo_bin("node-drive")?
+        .arg(tmpdir.path())
+        .args(["--theme-color", "red\"><script>"])
+        .assert()
+        .stderr(predicates::str::contains("Invalid theme color"))
+        .failure();
+    Ok(())
+}
@@ -7,13 +7,14 @@ use assert_fs::TempDir;
 use digest_auth_util::send_with_digest_auth;
 use fixtures::{port, tmpdir, wait_for_port, Error};
 use rstest::rstest;
-use serde_json;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
 
 #[rstest]
-fn use_config_file(tmpdir: TempDir, port: u16) -> Result<(), Error> {
-    let config_path = get_config_path().display().to_string();
+#[case("config.yaml")]
+#[case("config.toml")]
+fn use_config_file(tmpdir: TempDir, port: u16, #[case] name: &str) -> Result<(), Error> {
+    let config_path = get_config_path(name).display().to_string();
     let mut child = Command::cargo_bin("node-drive")?
         .arg(tmpdir.path())
         .arg("-p")
@@ -63,10 +64,51 @@ fn use_config_file(tmpdir: TempDir, port: u16) -> Result<(), Error> {
     Ok(())
 }
 
-fn get_config_path() -> PathBuf {
+#[cfg(unix)]
+#[rstest]
+fn reload_config_on_sighup(tmpdir: TempDir, port: u16) -> Result<(), Error> {
+    use assert_fs::fixture::FileWriteStr;
+
+    let config = assert_fs::NamedTempFile::new("node-drive.toml")?;
+    config.write_str("auth = [\"user:pass@/:ro\"]\nallow-upload = true\n")?;
+    let mut child = Command::cargo_bin("node-drive")?
+        .arg(tmpdir.path())
+        .arg("-p")
+        .arg(port.to_string())
+        .args(["--config", config.path().to_str().unwrap()])
+        .stdout(Stdio::null())
+        .spawn()?;
+    wait_for_port(port);
+
+    let url = format!("http://localhost:{port}/api/dir1/upload.txt");
+    let resp = send_with_digest_auth(fetch!(b"PUT", &url).body("Hello"), "user", "pass")?;
+    assert_eq!(resp.status(), 403);
+
+    config.write_str("auth = [\"user:pass@/:rw\"]\nallow-upload = true\n")?;
+    Command::new("kill")
+        .args(["-HUP", &child.id().to_string()])
+        .status()?;
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    let resp = send_with_digest_auth(fetch!(b"PUT", &url).body("Hello"), "user", "pass")?;
+    assert_eq!(resp.status(), 201);
+
+    // An invalid config leaves the server as it was
+    config.write_str("auth = 1")?;
+    Command::new("kill")
+        .args(["-HUP", &child.id().to_string()])
+        .status()?;
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    let resp = send_with_digest_auth(fetch!(b"PUT", &url).body("Hello"), "user", "pass")?;
+    assert_eq!(resp.status(), 201);
+
+    child.kill()?;
+    Ok(())
+}
+
+fn get_config_path(name: &str) -> PathBuf {
     let mut path = std::env::current_dir().expect("Failed to get current directory");
     path.push("tests");
     path.push("data");
-    path.push("config.yaml");
+    path.push(name);
     path
 }
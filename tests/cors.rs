@@ -11,23 +11,152 @@ fn cors(#[with(&["--enable-cors"])] server: TestServer) -> Result<(), Error> {
         resp.headers().get("access-control-allow-origin").unwrap(),
         "*"
     );
+    // Browsers refuse credentials on responses open to any origin
+    assert!(resp
+        .headers()
+        .get("access-control-allow-credentials")
+        .is_none());
+    assert_eq!(
+        resp.headers().get("access-control-allow-methods").unwrap(),
+        "*"
+    );
+    assert_eq!(
+        resp.headers().get("access-control-allow-headers").unwrap(),
+        "Authorization,*"
+    );
+    assert_eq!(
+        resp.headers().get("access-control-expose-headers").unwrap(),
+        "Authorization,*"
+    );
+    Ok(())
+}
+
+#[rstest]
+fn cors_reflects_allowed_origin(
+    #[with(&["--cors-origins", "https://app.example.com,http://localhost:3000/"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"GET", server.url())
+        .header("Origin", "http://localhost:3000")
+        .send()?;
+    assert_eq!(
+        resp.headers().get("access-control-allow-origin").unwrap(),
+        "http://localhost:3000"
+    );
     assert_eq!(
         resp.headers()
             .get("access-control-allow-credentials")
             .unwrap(),
         "true"
     );
+    assert_eq!(resp.headers().get("vary").unwrap(), "Origin");
+
+    let resp = fetch!(b"GET", server.url())
+        .header("Origin", "https://evil.example.com")
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    assert!(resp.headers().get("access-control-allow-origin").is_none());
+    assert!(resp
+        .headers()
+        .get("access-control-allow-credentials")
+        .is_none());
+    Ok(())
+}
+
+#[rstest]
+fn cors_preflight(
+    #[with(&["--auth", "user:pass@/:rw", "--cors-origins", "https://app.example.com"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"OPTIONS", format!("{}file1", server.url()))
+        .header("Origin", "https://app.example.com")
+        .header("Access-Control-Request-Method", "PUT")
+        .header(
+            "Access-Control-Request-Headers",
+            "authorization,content-type",
+        )
+        .send()?;
+    assert_eq!(resp.status(), 204);
+    let headers = resp.headers();
+    assert_eq!(
+        headers.get("access-control-allow-origin").unwrap(),
+        "https://app.example.com"
+    );
+    assert_eq!(headers.get("access-control-allow-methods").unwrap(), "PUT");
+    assert_eq!(
+        headers.get("access-control-allow-headers").unwrap(),
+        "authorization,content-type"
+    );
+    assert_eq!(headers.get("access-control-max-age").unwrap(), "7200");
+
+    // The actual request still needs credentials
+    let resp = fetch!(b"PUT", format!("{}file1", server.url()))
+        .header("Origin", "https://app.example.com")
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 401);
+    assert_eq!(
+        resp.headers().get("access-control-allow-origin").unwrap(),
+        "https://app.example.com"
+    );
+    Ok(())
+}
+
+#[rstest]
+fn cors_configured_methods_and_headers(
+    #[with(&["--cors-methods", "get,put", "--cors-headers", "Authorization,X-Upload-Id"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"OPTIONS", server.url())
+        .header("Origin", "https://app.example.com")
+        .header("Access-Control-Request-Method", "DELETE")
+        .send()?;
+    assert_eq!(resp.status(), 204);
+    let headers = resp.headers();
+    assert_eq!(headers.get("access-control-allow-origin").unwrap(), "*");
+    assert_eq!(
+        headers.get("access-control-allow-methods").unwrap(),
+        "GET,PUT"
+    );
+    assert_eq!(
+        headers.get("access-control-allow-headers").unwrap(),
+        "Authorization,X-Upload-Id"
+    );
+    Ok(())
+}
+
+#[rstest]
+fn cors_wildcard_origin(
+    #[with(&["--cors-origins", "*,https://app.example.com"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"GET", server.url())
+        .header("Origin", "https://evil.example.com")
+        .send()?;
+    assert_eq!(
+        resp.headers().get("access-control-allow-origin").unwrap(),
+        "*"
+    );
+    assert!(resp
+        .headers()
+        .get("access-control-allow-credentials")
+        .is_none());
     assert_eq!(
         resp.headers().get("access-control-allow-methods").unwrap(),
         "*"
     );
+
+    let resp = fetch!(b"GET", server.url())
+        .header("Origin", "https://app.example.com")
+        .send()?;
     assert_eq!(
-        resp.headers().get("access-control-allow-headers").unwrap(),
-        "Authorization,*"
+        resp.headers().get("access-control-allow-origin").unwrap(),
+        "https://app.example.com"
     );
     assert_eq!(
-        resp.headers().get("access-control-expose-headers").unwrap(),
-        "Authorization,*"
+        resp.headers()
+            .get("access-control-allow-credentials")
+            .unwrap(),
+        "true"
     );
     Ok(())
 }
@@ -0,0 +1,67 @@
+mod fixtures;
+mod utils;
+
+use fixtures::{server, Error};
+use rstest::rstest;
+use serde_json::Value;
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+#[rstest]
+fn dedup_identical_uploads() -> Result<(), Error> {
+    let dedup_dir = assert_fs::TempDir::new()?;
+    let server = server(["--dedup-dir", dedup_dir.path().to_str().unwrap()]);
+    let content: Vec<u8> = (0..CHUNK_SIZE * 2 + 100).map(|v| (v % 251) as u8).collect();
+
+    for name in ["a.bin", "b.bin"] {
+        let resp = fetch!(b"PUT", format!("{}{name}", server.url()))
+            .body(content.clone())
+            .send()?;
+        assert_eq!(resp.status(), 201);
+    }
+
+    let url = format!("{}b.bin", server.api_url());
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-length"], content.len().to_string());
+    assert_eq!(resp.bytes()?.to_vec(), content);
+
+    // A range across two chunks
+    let start = CHUNK_SIZE - 10;
+    let resp = fetch!(b"GET", &url)
+        .header("range", format!("bytes={start}-{}", start + 19))
+        .send()?;
+    assert_eq!(resp.status(), 206);
+    assert_eq!(resp.bytes()?.to_vec(), &content[start..start + 20]);
+
+    let resp = reqwest::blocking::get(format!("{}__dufs__/stats", server.url()))?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    let dedup = &json["dedup"];
+    assert_eq!(dedup["files"], 2);
+    assert_eq!(dedup["bytes"], content.len() * 2);
+    assert_eq!(dedup["deduplicated_bytes"], content.len());
+    assert_eq!(dedup["chunks"], 3);
+    assert_eq!(dedup["stored_bytes"], content.len());
+    Ok(())
+}
+
+#[rstest]
+fn dedup_resumes_chunked_upload() -> Result<(), Error> {
+    let dedup_dir = assert_fs::TempDir::new()?;
+    let server = server(["--dedup-dir", dedup_dir.path().to_str().unwrap()]);
+    let url = format!("{}big.bin", server.url());
+    let mut content = vec![7u8; CHUNK_SIZE + 1];
+    let resp = fetch!(b"PUT", &url).body(content.clone()).send()?;
+    assert_eq!(resp.status(), 201);
+
+    // Appending writes into the content, not the chunk list
+    let resp = fetch!(b"PATCH", &url)
+        .header("X-Update-Range", "append")
+        .body(b"tail".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 204);
+    content.extend_from_slice(b"tail");
+    let resp = reqwest::blocking::get(format!("{}big.bin", server.api_url()))?;
+    assert_eq!(resp.bytes()?.to_vec(), content);
+    Ok(())
+}
@@ -0,0 +1,105 @@
+mod fixtures;
+mod utils;
+
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+fn copy_op(index: u64, count: u32) -> Vec<u8> {
+    let mut op = vec![1];
+    op.extend_from_slice(&index.to_be_bytes());
+    op.extend_from_slice(&count.to_be_bytes());
+    op
+}
+
+fn literal_op(data: &[u8]) -> Vec<u8> {
+    let mut op = vec![2];
+    op.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    op.extend_from_slice(data);
+    op
+}
+
+fn delta_of(block_size: u32, ops: &[Vec<u8>]) -> Vec<u8> {
+    let mut delta = b"NDD1".to_vec();
+    delta.extend_from_slice(&block_size.to_be_bytes());
+    for op in ops {
+        delta.extend_from_slice(op);
+    }
+    delta
+}
+
+#[rstest]
+fn delta_sync(server: TestServer) -> Result<(), Error> {
+    let data: Vec<u8> = (0..4096u32).map(|v| (v % 251) as u8).collect();
+    let url = format!("{}big.bin", server.url());
+    let resp = fetch!(b"PUT", &url).body(data.clone()).send()?;
+    assert_eq!(resp.status(), 201);
+
+    let resp = reqwest::blocking::get(format!("{url}?signature-blocks&block-size=1024"))?;
+    assert_eq!(resp.status(), 200);
+    let etag = resp.headers().get("etag").unwrap().clone();
+    let json: Value = resp.json()?;
+    assert_eq!(json["size"], 4096);
+    let blocks = json["blocks"].as_array().unwrap();
+    assert_eq!(blocks.len(), 4);
+    assert_eq!(
+        blocks[1]["strong"],
+        format!("{:x}", Sha256::digest(&data[1024..2048]))
+    );
+
+    let mut expected = data[..2048].to_vec();
+    expected.extend_from_slice(b"changed");
+    expected.extend_from_slice(&data[3072..]);
+    let delta = delta_of(
+        1024,
+        &[copy_op(0, 2), literal_op(b"changed"), copy_op(3, 1)],
+    );
+    let resp = fetch!(b"PATCH", format!("{url}?delta"))
+        .header("if-match", etag.clone())
+        .body(delta.clone())
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = resp.json()?;
+    assert_eq!(json["size"], expected.len());
+    assert_eq!(json["copied_bytes"], 3072);
+    assert_eq!(json["literal_bytes"], 7);
+    assert_eq!(json["sha256"], format!("{:x}", Sha256::digest(&expected)));
+    assert!(json["event_hash"].is_string());
+
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.bytes()?.to_vec(), expected);
+
+    // The file changed since the signature was taken
+    let resp = fetch!(b"PATCH", format!("{url}?delta"))
+        .header("if-match", etag)
+        .body(delta)
+        .send()?;
+    assert_eq!(resp.status(), 412);
+    Ok(())
+}
+
+#[rstest]
+fn delta_rejects_bad_input(server: TestServer) -> Result<(), Error> {
+    let url = format!("{}index.html", server.url());
+    let resp = reqwest::blocking::get(format!("{url}?signature-blocks&block-size=1"))?;
+    assert_eq!(resp.status(), 400);
+
+    let original = reqwest::blocking::get(&url)?.bytes()?;
+    let resp = fetch!(b"PATCH", format!("{url}?delta"))
+        .body(delta_of(1024, &[copy_op(100, 1)]))
+        .send()?;
+    assert_eq!(resp.status(), 400);
+
+    let resp = fetch!(b"PATCH", format!("{url}?delta"))
+        .header(
+            "repr-digest",
+            "sha-256=:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=:",
+        )
+        .body(delta_of(1024, &[literal_op(b"other")]))
+        .send()?;
+    assert_eq!(resp.status(), 422);
+
+    assert_eq!(reqwest::blocking::get(&url)?.bytes()?, original);
+    Ok(())
+}
@@ -0,0 +1,65 @@
+mod fixtures;
+mod utils;
+
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+
+#[rstest]
+fn anonymous_drop(
+    #[with(&[
+        "--auth",
+        "admin:pass@/:rw",
+        "--allow-anonymous-drop",
+        "/dir1",
+        "--anonymous-drop-limit",
+        "20",
+    ])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}dir1/drop.txt", server.url());
+    let resp = fetch!(b"PUT", &url).body(b"hello".to_vec()).send()?;
+    assert_eq!(resp.status(), 201);
+    let json: Value = resp.json()?;
+    assert_eq!(json["filename"], "drop.txt");
+
+    // Dropping doesn't let anyone look inside
+    assert_eq!(reqwest::blocking::get(&url)?.status(), 401);
+    let resp = reqwest::blocking::get(format!("{}dir1/", server.api_url()))?;
+    assert_eq!(resp.status(), 401);
+    let resp = fetch!(b"DELETE", &url).send()?;
+    assert_eq!(resp.status(), 401);
+
+    // Nor replace what is there
+    let resp = fetch!(b"PUT", &url).body(b"other".to_vec()).send()?;
+    assert_eq!(resp.status(), 409);
+    let resp = fetch!(b"PUT", format!("{}dir1/test.txt", server.url()))
+        .body(b"other".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 409);
+
+    let resp = fetch!(b"PUT", format!("{}dir2/drop.txt", server.url()))
+        .body(b"hello".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 401);
+
+    let resp = fetch!(b"PUT", format!("{}dir1/big.txt", server.url()))
+        .body(vec![b'a'; 16])
+        .send()?;
+    assert_eq!(resp.status(), 413);
+
+    let resp = fetch!(b"GET", &url)
+        .basic_auth("admin", Some("pass"))
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.text()?, "hello");
+    let resp = fetch!(
+        b"GET",
+        format!("{}dir1/drop.txt?manifest=json", server.api_url())
+    )
+    .basic_auth("admin", Some("pass"))
+    .send()?;
+    let json: Value = resp.json()?;
+    assert_eq!(json["events"][0]["action"], "mint");
+    Ok(())
+}
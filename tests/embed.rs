@@ -0,0 +1,110 @@
+mod fixtures;
+
+use assert_fs::TempDir;
+use fixtures::{tmpdir, Error};
+use hyper::header::HeaderValue;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use node_drive::{BoxFuture, Middleware, Next, NodeDrive, Request, Response, ServerBuilder};
+use rstest::rstest;
+use serde_json::Value;
+use std::net::TcpListener;
+use tokio::runtime::Runtime;
+
+/// Marks the responses it sees
+struct Tag;
+
+impl Middleware for Tag {
+    fn call<'a>(&'a self, req: Request, next: Next<'a>) -> BoxFuture<'a, Response> {
+        Box::pin(async move {
+            let mut res = next.run(req).await;
+            res.headers_mut()
+                .insert("x-tag", HeaderValue::from_static(next.route().as_str()));
+            res
+        })
+    }
+}
+
+/// Serve `drive` on a local port like an application embedding it would
+fn serve(rt: &Runtime, drive: NodeDrive) -> Result<String, Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    listener.set_nonblocking(true)?;
+    rt.spawn(async move {
+        let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+        while let Ok((stream, _)) = listener.accept().await {
+            let drive = drive.clone();
+            tokio::spawn(async move {
+                let builder = Builder::new(TokioExecutor::new());
+                let _ = builder.serve_connection(TokioIo::new(stream), drive).await;
+            });
+        }
+    });
+    Ok(url)
+}
+
+#[rstest]
+fn embed_server_builder(tmpdir: TempDir) -> Result<(), Error> {
+    let rt = Runtime::new()?;
+    let db_dir = TempDir::new()?;
+    let drive = {
+        let _guard = rt.enter();
+        ServerBuilder::new(tmpdir.path())
+            .path_prefix("/drive/")
+            .auth("user:pass@/:rw")
+            .provenance_db(db_dir.path().join("provenance.db"))
+            .ots_offline(true)
+            .layer(Tag)
+            .build()?
+    };
+    let url = format!("{}drive/api/", serve(&rt, drive.clone())?);
+
+    let client = reqwest::blocking::Client::new();
+    let resp = client.get(&url).send()?;
+    // Layers only see requests that got past the access checks
+    assert_eq!(resp.status(), 401);
+    assert!(resp.headers().get("x-tag").is_none());
+
+    let resp = client
+        .put(format!("{url}embedded.txt"))
+        .basic_auth("user", Some("pass"))
+        .body("hello")
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    assert_eq!(resp.headers()["x-tag"], "api");
+
+    let json: Value = client
+        .get(&url)
+        .basic_auth("user", Some("pass"))
+        .send()?
+        .json()?;
+    let names: Vec<_> = json["paths"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|v| v["name"].as_str())
+        .collect();
+    assert!(names.contains(&"embedded.txt"));
+    assert!(names.contains(&"test.txt"));
+
+    drive.shutdown()?;
+    Ok(())
+}
+
+#[rstest]
+fn embed_invalid_settings(tmpdir: TempDir) -> Result<(), Error> {
+    let rt = Runtime::new()?;
+    let _guard = rt.enter();
+    let err = ServerBuilder::new(tmpdir.path().join("missing"))
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("doesn't exist"));
+    let err = ServerBuilder::new(tmpdir.path())
+        .auth("user:pass@/:xx")
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().starts_with("Invalid auth value"));
+    Ok(())
+}
@@ -0,0 +1,74 @@
+mod fixtures;
+
+use assert_fs::fixture::{FileWriteStr, PathChild};
+use fixtures::{server, Error};
+use rstest::rstest;
+
+#[rstest]
+fn error_page_for_browsers() -> Result<(), Error> {
+    let pages = assert_fs::TempDir::new()?;
+    pages
+        .child("404.html")
+        .write_str("<h1>{{status}} {{reason}}</h1><p>{{path}}</p>")?;
+    pages.child("notes.txt").write_str("ignored")?;
+    let server = server(["--error-pages", pages.path().to_str().unwrap()]);
+
+    let url = format!("{}api/missing%3Cb%3E.txt", server.url());
+    let resp = reqwest::blocking::Client::new()
+        .get(&url)
+        .header("accept", "text/html,application/xhtml+xml")
+        .send()?;
+    assert_eq!(resp.status(), 404);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/html; charset=utf-8"
+    );
+    assert_eq!(
+        resp.text()?,
+        "<h1>404 Not Found</h1><p>/api/missing&lt;b&gt;.txt</p>"
+    );
+
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.status(), 404);
+    let json: serde_json::Value = resp.json()?;
+    assert_eq!(json["code"], "not_found");
+    assert_eq!(json["message"], "Not Found");
+    Ok(())
+}
+
+#[rstest]
+fn error_page_missing_status_keeps_body() -> Result<(), Error> {
+    let pages = assert_fs::TempDir::new()?;
+    pages.child("500.html").write_str("<h1>{{status}}</h1>")?;
+    let server = server([
+        "--error-pages",
+        pages.path().to_str().unwrap(),
+        "-a",
+        "user:pass@/:rw",
+        "-a",
+        "@/dir1",
+    ]);
+
+    let resp = reqwest::blocking::Client::new()
+        .put(format!("{}api/dir1/file.txt", server.url()))
+        .header("accept", "text/html")
+        .body("abc")
+        .send()?;
+    assert_eq!(resp.status(), 401);
+    assert!(!resp.text()?.contains("<h1>"));
+    Ok(())
+}
+
+#[rstest]
+fn error_pages_dir_must_exist() -> Result<(), Error> {
+    let pages = assert_fs::TempDir::new()?;
+    let missing = pages.path().join("missing");
+    assert_cmd::Command::cargo_bin("node-drive")?
+        .arg(pages.path())
+        .arg("--error-pages")
+        .arg(&missing)
+        .assert()
+        .stderr(predicates::str::contains("Failed to read error pages"))
+        .failure();
+    Ok(())
+}
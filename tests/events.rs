@@ -0,0 +1,79 @@
+mod digest_auth_util;
+mod fixtures;
+mod utils;
+
+use digest_auth_util::send_with_digest_auth;
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+use std::io::{BufRead, BufReader};
+use std::time::Duration;
+
+/// Read server-sent events until `count` of them arrived, skipping comments
+fn read_events(resp: reqwest::blocking::Response, count: usize) -> Vec<(String, Value)> {
+    let mut events = vec![];
+    let mut name = String::new();
+    for line in BufReader::new(resp).lines() {
+        let line = line.unwrap();
+        if let Some(v) = line.strip_prefix("event: ") {
+            name = v.to_string();
+        } else if let Some(data) = line.strip_prefix("data: ") {
+            events.push((name.clone(), serde_json::from_str(data).unwrap()));
+            if events.len() == count {
+                break;
+            }
+        }
+    }
+    events
+}
+
+#[rstest]
+fn events_stream_changes(server: TestServer) -> Result<(), Error> {
+    let resp = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?
+        .get(format!("{}__dufs__/events?path=/dir1", server.url()))
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/event-stream"
+    );
+
+    // Outside of the watched prefix
+    let resp_put = fetch!(b"PUT", format!("{}api/dir2/b.txt", server.url()))
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp_put.status(), 201);
+
+    let url = format!("{}dir1/a.txt", server.url());
+    let new_url = format!("{}dir1/c.txt", server.url());
+    fetch!(b"PUT", &url).body(b"abc".to_vec()).send()?;
+    let resp_move = fetch!(b"MOVE", &url)
+        .header("Destination", &new_url)
+        .send()?;
+    assert_eq!(resp_move.status(), 204);
+    let resp_delete = fetch!(b"DELETE", &new_url).send()?;
+    assert_eq!(resp_delete.status(), 204);
+
+    let events = read_events(resp, 4);
+    let names: Vec<_> = events.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["upload", "provenance", "move", "delete"]);
+    assert_eq!(events[0].1["path"], "/dir1/a.txt");
+    assert_eq!(events[1].1["action"], "mint");
+    assert_eq!(events[2].1["to"], "/dir1/c.txt");
+    assert_eq!(events[3].1["kind"], "delete");
+    Ok(())
+}
+
+#[rstest]
+fn events_require_read_access(
+    #[with(&["--auth", "user:pass@/:rw", "--auth", "guest:pass@/dir1:ro"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}__dufs__/events?path=/dir2", server.url());
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.status(), 401);
+    let resp = send_with_digest_auth(fetch!(b"GET", &url), "guest", "pass")?;
+    assert_eq!(resp.status(), 403);
+    Ok(())
+}
@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+
 use assert_cmd::prelude::*;
 use assert_fs::fixture::TempDir;
 use assert_fs::prelude::*;
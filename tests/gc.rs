@@ -0,0 +1,73 @@
+mod fixtures;
+mod utils;
+
+use assert_cmd::prelude::*;
+use fixtures::{server, tmpdir, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+use std::process::Command;
+
+const GC_PATH: &str = "__dufs__/gc";
+
+#[rstest]
+fn gc_removes_orphaned_rows(server: TestServer) -> Result<(), Error> {
+    for file in ["gone.txt", "kept.txt"] {
+        let resp = fetch!(b"PUT", format!("{}dir1/{file}", server.url()))
+            .body(b"content".to_vec())
+            .send()?;
+        assert_eq!(resp.status(), 201);
+        let resp = fetch!(b"POST", format!("{}dir1/{file}?tags", server.api_url()))
+            .body(r#"{"add":["draft"]}"#)
+            .send()?;
+        assert_eq!(resp.status(), 200);
+    }
+    std::fs::remove_file(server.path().join("dir1/gone.txt"))?;
+
+    let resp = fetch!(b"POST", format!("{}{GC_PATH}?dry-run", server.url())).send()?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = resp.json()?;
+    assert_eq!(json["dry_run"], true);
+    assert_eq!(json["rows"]["artifacts"], 1);
+    assert_eq!(json["rows"]["file_tags"], 1);
+    assert_eq!(json["events"], 1);
+
+    let resp = fetch!(b"POST", format!("{}{GC_PATH}", server.url())).send()?;
+    let json: Value = resp.json()?;
+    assert_eq!(json["rows"]["artifacts"], 1);
+
+    let resp = fetch!(b"POST", format!("{}{GC_PATH}", server.url())).send()?;
+    let json: Value = resp.json()?;
+    assert_eq!(json["rows"], serde_json::json!({}));
+    let resp = reqwest::blocking::get(format!("{}dir1/kept.txt?manifest=json", server.api_url()))?;
+    assert_eq!(resp.status(), 200);
+    Ok(())
+}
+
+#[rstest]
+fn gc_requires_admin(
+    #[with(&["--auth", "admin:pass@/:rw", "--auth", "user:pass@/dir1:rw"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"POST", format!("{}{GC_PATH}", server.url())).send()?;
+    assert_eq!(resp.status(), 401);
+    let resp = fetch!(b"POST", format!("{}{GC_PATH}", server.url()))
+        .basic_auth("user", Some("pass"))
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    Ok(())
+}
+
+#[test]
+fn gc_command() -> Result<(), Error> {
+    let dir = tmpdir();
+    let db = dir.path().join("gc.db");
+    Command::cargo_bin("node-drive")?
+        .arg("--provenance-db")
+        .arg(&db)
+        .arg(dir.path())
+        .arg("gc")
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout("No orphaned rows found\n");
+    Ok(())
+}
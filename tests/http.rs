@@ -56,6 +56,78 @@ fn get_dir_zip(#[case] server: TestServer) -> Result<(), Error> {
     Ok(())
 }
 
+#[rstest]
+#[case("tar", "application/x-tar")]
+#[case("tar.gz", "application/gzip")]
+fn get_dir_tar(
+    #[case] flag: &str,
+    #[case] content_type: &str,
+    #[with(&["--allow-archive", "--hidden", ".git"])] server: TestServer,
+) -> Result<(), Error> {
+    use futures_util::StreamExt;
+
+    let resp = reqwest::blocking::get(format!("{}?{flag}", server.api_url()))?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers().get("content-type").unwrap(), content_type);
+    assert!(resp.headers()["content-disposition"]
+        .to_str()?
+        .contains(&format!(".{flag}")));
+    let body = resp.bytes()?.to_vec();
+
+    let names = tokio::runtime::Runtime::new()?.block_on(async move {
+        let reader: Box<dyn tokio::io::AsyncRead + Unpin + Send> = match flag {
+            "tar.gz" => Box::new(async_compression::tokio::bufread::GzipDecoder::new(
+                &body[..],
+            )),
+            _ => Box::new(&body[..]),
+        };
+        let mut archive = tokio_tar::Archive::new(reader);
+        let mut entries = archive.entries().unwrap();
+        let mut names = vec![];
+        while let Some(entry) = entries.next().await {
+            names.push(entry.unwrap().path().unwrap().display().to_string());
+        }
+        names
+    });
+    assert!(names.contains(&"dir1/test.txt".to_string()));
+    assert!(!names.iter().any(|v| v.starts_with(".git")));
+    Ok(())
+}
+
+#[rstest]
+fn post_dir_zip_selection(
+    #[with(&["--allow-archive", "--hidden", ".git"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}?zip", server.api_url());
+    let resp = fetch!(b"POST", &url)
+        .body(r#"["test.txt", "dir1", ".git", "dir2/test.html", "missing"]"#)
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "application/zip");
+    let body = resp.bytes()?.to_vec();
+
+    let names = tokio::runtime::Runtime::new()?.block_on(async move {
+        let zip = async_zip::base::read::mem::ZipFileReader::new(body)
+            .await
+            .unwrap();
+        zip.file()
+            .entries()
+            .iter()
+            .map(|v| v.filename().as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    });
+    assert!(names.contains(&"test.txt".to_string()));
+    assert!(names.contains(&"dir1/test.txt".to_string()));
+    assert!(names.contains(&"dir2/test.html".to_string()));
+    assert!(!names.iter().any(|v| v.starts_with("dir2/test.txt")));
+    assert!(!names.iter().any(|v| v.starts_with(".git")));
+    assert!(!names.iter().any(|v| v.starts_with("dir3")));
+
+    let resp = fetch!(b"POST", &url).body(r#"["../etc"]"#).send()?;
+    assert_eq!(resp.status(), 400);
+    Ok(())
+}
+
 #[rstest]
 fn get_dir_json(
     #[with(&["--allow-upload", "--allow-delete", "--allow-search", "--allow-archive", "--allow-symlink"])]
@@ -72,6 +144,134 @@ fn get_dir_json(
     Ok(())
 }
 
+#[rstest]
+fn get_dir_paged(server: TestServer) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(format!("{}dir1/?sort=name", server.api_url()))?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    let all = json["paths"].as_array().unwrap();
+    let total = all.len();
+    assert_eq!(json["total"], total);
+
+    let resp = reqwest::blocking::get(format!(
+        "{}dir1/?sort=name&offset=2&limit=3",
+        server.api_url()
+    ))?;
+    let page: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(page["total"], total);
+    assert_eq!(page["paths"].as_array().unwrap()[..], all[2..5]);
+
+    let resp = reqwest::blocking::get(format!(
+        "{}dir1/?sort=name&offset=2&limit=3&ndjson",
+        server.api_url()
+    ))?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/x-ndjson"
+    );
+    assert_eq!(
+        resp.headers().get("x-total-count").unwrap(),
+        &total.to_string()
+    );
+    let lines: Vec<Value> = resp
+        .text()?
+        .lines()
+        .map(|v| serde_json::from_str(v).unwrap())
+        .collect();
+    assert_eq!(lines[..], all[2..5]);
+
+    let resp = reqwest::blocking::get(format!("{}dir1/?limit=-1", server.api_url()))?;
+    assert_eq!(resp.status(), 400);
+    Ok(())
+}
+
+#[rstest]
+fn get_dir_recursive(#[with(&["--hidden", "secret"])] server: TestServer) -> Result<(), Error> {
+    for name in [
+        "dir1/sub/deep.pdf",
+        "dir2/a.PDF",
+        "dir2/b.txt",
+        "secret/c.pdf",
+    ] {
+        let resp = fetch!(b"PUT", format!("{}{name}", server.url()))
+            .body(b"data".to_vec())
+            .send()?;
+        assert_eq!(resp.status(), 201);
+    }
+    let names = |url: String| -> Result<Vec<String>, Error> {
+        let json: Value = reqwest::blocking::get(url)?.json()?;
+        Ok(json["paths"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v["name"].as_str().unwrap().to_string())
+            .collect())
+    };
+
+    let all = names(format!("{}?recursive", server.api_url()))?;
+    assert!(all.contains(&"dir1/sub".to_string()));
+    assert!(all.contains(&"dir2/b.txt".to_string()));
+    assert!(all.iter().all(|v| !v.starts_with("secret")));
+
+    let pdfs = names(format!("{}?recursive&glob=*.pdf", server.api_url()))?;
+    assert_eq!(pdfs, ["dir1/sub/deep.pdf"]);
+    let pdfs = names(format!(
+        "{}?recursive&glob=*.%5BpP%5D%5BdD%5D%5BfF%5D",
+        server.api_url()
+    ))?;
+    assert_eq!(pdfs, ["dir1/sub/deep.pdf", "dir2/a.PDF"]);
+    let nested = names(format!("{}dir1/?recursive&glob=sub/*", server.api_url()))?;
+    assert_eq!(nested, ["sub/deep.pdf"]);
+
+    let resp = reqwest::blocking::get(format!("{}?recursive&glob=%5B", server.api_url()))?;
+    assert_eq!(resp.status(), 400);
+    Ok(())
+}
+
+#[rstest]
+fn get_dir_filtered(server: TestServer) -> Result<(), Error> {
+    for name in ["photo.png", "clip.mp4", "b.jpg"] {
+        let resp = fetch!(b"PUT", format!("{}dir1/{name}", server.url()))
+            .body(b"data".to_vec())
+            .send()?;
+        assert_eq!(resp.status(), 201);
+    }
+    let names = |json: &Value| -> Vec<String> {
+        json["paths"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v["name"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let resp = reqwest::blocking::get(format!("{}dir1/?filter=image", server.api_url()))?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = resp.json()?;
+    assert_eq!(names(&json), ["dir1/b.jpg", "dir1/photo.png"]);
+    assert_eq!(json["total"], 2);
+    assert_eq!(json["paths"][0]["category"], "image");
+
+    let resp = reqwest::blocking::get(format!("{}dir1/?sort=type", server.api_url()))?;
+    let json: Value = resp.json()?;
+    // Folders come first, without a category
+    let paths = json["paths"].as_array().unwrap();
+    let files: Vec<_> = paths
+        .iter()
+        .skip_while(|v| v["category"].is_null())
+        .collect();
+    assert!(files.iter().all(|v| !v["category"].is_null()));
+    let categories: Vec<_> = files.iter().map(|v| v["category"].clone()).collect();
+    assert_eq!(categories[..3], ["image", "image", "video"]);
+    assert_eq!(files[2]["name"], "dir1/clip.mp4");
+    assert!(categories.contains(&Value::from("doc")));
+    assert_eq!(categories.last().unwrap(), "other");
+
+    let resp = reqwest::blocking::get(format!("{}dir1/?filter=other", server.api_url()))?;
+    assert_eq!(resp.status(), 400);
+    Ok(())
+}
+
 #[rstest]
 fn get_dir_simple(
     #[with(&["--allow-upload", "--allow-delete", "--allow-search", "--allow-archive", "--allow-symlink"])]
@@ -158,6 +358,89 @@ fn get_dir_search4(
     Ok(())
 }
 
+#[rstest]
+fn get_dir_search_content(server: TestServer) -> Result<(), Error> {
+    let url = format!("{}dir1/notes.md", server.api_url());
+    let resp = fetch!(b"PUT", &url)
+        .body(b"# Notes\nsee the Quarterly report\n".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+
+    let resp = reqwest::blocking::get(format!("{}?q=quarterly&mode=content", server.api_url()))?;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    let paths = json["paths"].as_array().unwrap();
+    assert_eq!(paths.len(), 1);
+    assert_eq!(paths[0]["name"], "dir1/notes.md");
+    assert_eq!(paths[0]["matches"][0]["line"], 2);
+    assert_eq!(paths[0]["matches"][0]["text"], "see the Quarterly report");
+
+    // Names alone don't match in content mode
+    let resp = reqwest::blocking::get(format!("{}?q=notes.md&mode=content", server.api_url()))?;
+    let json: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert!(json["paths"].as_array().unwrap().is_empty());
+
+    let resp = reqwest::blocking::get(format!("{}?q=notes&mode=regex", server.api_url()))?;
+    assert_eq!(resp.status(), 400);
+    Ok(())
+}
+
+#[rstest]
+fn get_dir_search_index(
+    #[with(&["--index", "--hidden", ".*"])] server: TestServer,
+) -> Result<(), Error> {
+    let search = |q: &str| -> Result<Vec<String>, Error> {
+        let resp = reqwest::blocking::get(format!("{}?q={q}&simple", server.api_url()))?;
+        Ok(resp.text()?.lines().map(|v| v.to_string()).collect())
+    };
+    // Searches walk the tree until the first scan is done, results are the same
+    assert!(search("test.html")?.contains(&"dir1/test.html".to_string()));
+
+    let resp = fetch!(b"PUT", format!("{}dir1/zebra.txt", server.api_url()))
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    fetch!(b"PUT", format!("{}.secret/zebra2.txt", server.api_url()))
+        .body(b"abc".to_vec())
+        .send()?;
+    let mut found = vec![];
+    for _ in 0..50 {
+        found = search("zebra")?;
+        if !found.is_empty() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert_eq!(found, ["dir1/zebra.txt"]);
+    Ok(())
+}
+
+#[rstest]
+fn get_dir_cached(#[with(&["--dir-cache"])] server: TestServer) -> Result<(), Error> {
+    let list = || -> Result<Vec<String>, Error> {
+        let resp = reqwest::blocking::get(format!("{}dir1/?simple", server.api_url()))?;
+        Ok(resp.text()?.lines().map(|v| v.to_string()).collect())
+    };
+    let before = list()?;
+    assert!(!before.contains(&"dir1/zebra.txt".to_string()));
+    assert_eq!(list()?, before);
+
+    let wait_for = |present: bool| -> Result<bool, Error> {
+        for _ in 0..50 {
+            if list()?.contains(&"dir1/zebra.txt".to_string()) == present {
+                return Ok(true);
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        Ok(false)
+    };
+    std::fs::write(server.path().join("dir1/zebra.txt"), "abc")?;
+    assert!(wait_for(true)?);
+    std::fs::remove_file(server.path().join("dir1/zebra.txt"))?;
+    assert!(wait_for(false)?);
+    Ok(())
+}
+
 #[rstest]
 fn head_dir_search(
     #[with(&["--allow-upload", "--allow-delete", "--allow-search", "--allow-archive", "--allow-symlink"])]
@@ -231,6 +514,62 @@ fn hash_file(server: TestServer) -> Result<(), Error> {
     Ok(())
 }
 
+#[rstest]
+fn preview_file(server: TestServer) -> Result<(), Error> {
+    let url = format!("{}data.csv", server.api_url());
+    let resp = fetch!(b"PUT", &url).body("a,b\n1,2\n3,4\n").send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = reqwest::blocking::get(format!("{url}?preview=head&lines=2"))?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/csv; charset=UTF-8"
+    );
+    assert_eq!(resp.headers().get("x-preview-truncated").unwrap(), "true");
+    assert_eq!(resp.text()?, "a,b\n1,2\n");
+
+    let resp = reqwest::blocking::get(format!("{url}?preview=head"))?;
+    assert!(!resp.headers().contains_key("x-preview-truncated"));
+    assert_eq!(resp.text()?, "a,b\n1,2\n3,4\n");
+
+    let resp = reqwest::blocking::get(format!("{url}?preview=head&lines=0"))?;
+    assert_eq!(resp.status(), 400);
+    let resp = reqwest::blocking::get(format!("{}{BIN_FILE}?preview=head", server.api_url()))?;
+    assert_eq!(resp.status(), 415);
+    Ok(())
+}
+
+#[rstest]
+fn get_file_precompressed(server: TestServer) -> Result<(), Error> {
+    let url = format!("{}dir1/data.txt", server.api_url());
+    let resp = fetch!(b"PUT", &url).body(b"plain".to_vec()).send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = fetch!(b"PUT", format!("{url}.gz"))
+        .body(b"gzipped".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+
+    let resp = fetch!(b"GET", &url)
+        .header("accept-encoding", "br;q=1, gzip")
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers().get("content-encoding").unwrap(), "gzip");
+    assert_eq!(resp.headers().get("vary").unwrap(), "Accept-Encoding");
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/plain; charset=UTF-8"
+    );
+    assert_eq!(resp.text()?, "gzipped");
+
+    let resp = fetch!(b"GET", &url)
+        .header("accept-encoding", "gzip;q=0")
+        .send()?;
+    assert!(!resp.headers().contains_key("content-encoding"));
+    assert_eq!(resp.headers().get("vary").unwrap(), "Accept-Encoding");
+    assert_eq!(resp.text()?, "plain");
+    Ok(())
+}
+
 #[rstest]
 fn get_file_404(server: TestServer) -> Result<(), Error> {
     let resp = reqwest::blocking::get(format!("{}404", server.api_url()))?;
@@ -324,6 +663,196 @@ fn put_file_create_dir(
     Ok(())
 }
 
+#[rstest]
+fn put_file_too_large(
+    #[with(&["--allow-upload", "--max-upload-size", "4"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}file1", server.api_url());
+    let resp = fetch!(b"PUT", &url).body(b"abcdef".to_vec()).send()?;
+    assert_eq!(resp.status(), 413);
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.status(), 404);
+    let resp = fetch!(b"PUT", &url).body(b"abcd".to_vec()).send()?;
+    assert_eq!(resp.status(), 201);
+    Ok(())
+}
+
+#[rstest]
+fn put_file_upload_types(
+    #[with(&["--allow-upload", "--allow-delete", "--deny-upload-types", "exe,.BAT", "--upload-type-limits", "mp4:4,tar.gz:8"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"PUT", format!("{}setup.EXE", server.api_url()))
+        .body(b"MZ".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 415);
+    assert!(!server.path().join("setup.EXE").exists());
+    let resp = fetch!(b"PUT", format!("{}clip.mp4", server.api_url()))
+        .body(b"abcdef".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 413);
+    let resp = fetch!(b"PUT", format!("{}clip.mp4", server.api_url()))
+        .body(b"abcd".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = fetch!(b"PUT", format!("{}backup.tar.gz", server.api_url()))
+        .body(b"abcdef".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    // Renaming counts as uploading under the new name
+    let resp = fetch!(b"MOVE", format!("{}test.txt", server.url()))
+        .header("Destination", format!("{}run.bat", server.url()))
+        .send()?;
+    assert_eq!(resp.status(), 415);
+    assert!(server.path().join("test.txt").exists());
+    Ok(())
+}
+
+#[rstest]
+fn put_file_allowed_types(
+    #[with(&["--allow-upload", "--allow-upload-types", "jpg,pdf"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"PUT", format!("{}scan.PDF", server.api_url()))
+        .body(b"%PDF".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = fetch!(b"PUT", format!("{}notes.txt", server.api_url()))
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 415);
+    Ok(())
+}
+
+#[rstest]
+fn put_file_interrupted_resumes(
+    #[with(&["--allow-upload", "--durable-writes"])] server: TestServer,
+) -> Result<(), Error> {
+    use std::io::Write;
+
+    // Hang up 20 MiB into a PUT announcing a few more bytes
+    let sent = 20 * 1024 * 1024;
+    let mut stream = std::net::TcpStream::connect(format!("localhost:{}", server.port()))?;
+    stream.write_all(
+        format!(
+            "PUT /api/big.bin HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n",
+            sent + 5
+        )
+        .as_bytes(),
+    )?;
+    stream.write_all(&vec![b'a'; sent])?;
+
+    // Hanging up before the server read everything could drop unread bytes
+    let partial = server.path().join(".big.bin.partial");
+    for _ in 0..50 {
+        if std::fs::metadata(&partial).is_ok_and(|v| v.len() == sent as u64) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    drop(stream);
+    let url = format!("{}big.bin", server.api_url());
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.status(), 404);
+    let resp = reqwest::blocking::get(format!("{}?json", server.api_url()))?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    let entry = json["paths"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|v| v["name"] == ".big.bin.partial")
+        .unwrap();
+    assert_eq!(entry["partial"], true);
+
+    let resp = fetch!(b"PATCH", &url)
+        .header("X-Update-Range", "append")
+        .body(b"bbbbb".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let body = reqwest::blocking::get(&url)?.bytes()?;
+    assert_eq!(body.len(), sent + 5);
+    assert!(body.ends_with(b"abbbbb"));
+    assert!(!partial.exists());
+    Ok(())
+}
+
+#[rstest]
+fn put_file_interrupted_overwrite(
+    #[with(&["--allow-upload", "--allow-delete"])] server: TestServer,
+) -> Result<(), Error> {
+    use std::io::Write;
+
+    // Overwrites can't be resumed, so hanging up leaves nothing behind
+    let sent = 20 * 1024 * 1024;
+    let mut stream = std::net::TcpStream::connect(format!("localhost:{}", server.port()))?;
+    stream.write_all(
+        format!(
+            "PUT /api/test.txt HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n",
+            sent + 5
+        )
+        .as_bytes(),
+    )?;
+    stream.write_all(&vec![b'a'; sent])?;
+
+    let partial = server.path().join(".test.txt.partial");
+    for _ in 0..50 {
+        if std::fs::metadata(&partial).is_ok_and(|v| v.len() == sent as u64) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    drop(stream);
+    for _ in 0..50 {
+        if !partial.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert!(!partial.exists());
+    let resp = reqwest::blocking::get(format!("{}test.txt", server.api_url()))?;
+    assert_eq!(resp.text()?, "This is test.txt");
+    Ok(())
+}
+
+#[rstest]
+fn put_file_interrupted_expires(
+    #[with(&["--allow-upload", "--partial-upload-retention", "1s"])] server: TestServer,
+) -> Result<(), Error> {
+    use std::io::Write;
+
+    // Files that look like partial uploads but weren't written by the server stay
+    let notes = server.path().join(".notes.partial");
+    std::fs::write(&notes, "my notes")?;
+
+    let sent = 20 * 1024 * 1024;
+    let mut stream = std::net::TcpStream::connect(format!("localhost:{}", server.port()))?;
+    stream.write_all(
+        format!(
+            "PUT /api/big.bin HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n",
+            sent + 5
+        )
+        .as_bytes(),
+    )?;
+    stream.write_all(&vec![b'a'; sent])?;
+
+    let partial = server.path().join(".big.bin.partial");
+    for _ in 0..50 {
+        if std::fs::metadata(&partial).is_ok_and(|v| v.len() == sent as u64) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    drop(stream);
+    for _ in 0..50 {
+        if !partial.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert!(!partial.exists());
+    assert_eq!(std::fs::read_to_string(&notes)?, "my notes");
+    Ok(())
+}
+
 #[rstest]
 fn put_file_conflict_dir(
     #[with(&["--allow-upload", "--allow-delete", "--allow-search", "--allow-archive", "--allow-symlink"])]
@@ -403,3 +932,134 @@ fn resumable_upload(#[with(&["--allow-upload"])] server: TestServer) -> Result<(
     assert_eq!(resp.text().unwrap(), "abc123");
     Ok(())
 }
+
+#[rstest]
+fn upload_content_digest(#[with(&["--allow-upload"])] server: TestServer) -> Result<(), Error> {
+    let abc_digest = "sha-256=:ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=:";
+    let url = format!("{}file1", server.api_url());
+    let resp = fetch!(b"PUT", &url)
+        .header("Content-Digest", abc_digest)
+        .body(b"abd".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 422);
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.status(), 404);
+
+    let resp = fetch!(b"PUT", &url)
+        .header("Content-Digest", abc_digest)
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    assert_eq!(resp.headers()["repr-digest"], abc_digest);
+
+    // A partial write is undone when the whole file doesn't match
+    let resp = fetch!(b"PATCH", &url)
+        .header("X-Update-Range", "append")
+        .header(
+            "Content-Digest",
+            "sha-256=:pmWkWSBCL51Bfkhn79xPuKBKHz//H6B+mY6G9/eieuM=:",
+        )
+        .header("Repr-Digest", abc_digest)
+        .body(b"123".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 422);
+    let resp = fetch!(b"PATCH", &url)
+        .header("X-Update-Range", "append")
+        .header(
+            "Repr-Digest",
+            "sha-256=:bKE9UspwyIPg8LsQHkJaiehiTeUdstI5JZOvaoQRgJA=:",
+        )
+        .body(b"123".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 204);
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.text()?, "abc123");
+
+    let resp = fetch!(b"PUT", &url)
+        .header("Content-Digest", "sha-256=:abc:")
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 400);
+    Ok(())
+}
+
+#[rstest]
+fn conditional_writes(
+    #[with(&["--allow-upload", "--allow-delete"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}file1", server.api_url());
+    let resp = fetch!(b"PUT", &url)
+        .header("If-Match", "*")
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 412);
+    let resp = fetch!(b"PUT", &url)
+        .header("If-None-Match", "*")
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let etag = resp.headers()["etag"].to_str()?.to_string();
+    let resp = fetch!(b"PUT", &url)
+        .header("If-None-Match", "*")
+        .body(b"other".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 412);
+
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.headers()["etag"], etag.as_str());
+    let resp = fetch!(b"PATCH", &url)
+        .header("X-Update-Range", "append")
+        .header("If-Match", etag.as_str())
+        .body(b"123".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 204);
+
+    // The append changed the ETag
+    let resp = fetch!(b"DELETE", &url)
+        .header("If-Match", etag.as_str())
+        .send()?;
+    assert_eq!(resp.status(), 412);
+    let etag = reqwest::blocking::get(&url)?.headers()["etag"].clone();
+    let resp = fetch!(b"DELETE", &url).header("If-Match", etag).send()?;
+    assert_eq!(resp.status(), 204);
+    Ok(())
+}
+
+#[rstest]
+fn get_dir_tree(#[with(&["--hidden", ".git"])] server: TestServer) -> Result<(), Error> {
+    std::fs::create_dir_all(server.path().join("dir1/sub/deeper"))?;
+    let resp = reqwest::blocking::get(format!("{}?tree&depth=2", server.api_url()))?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = resp.json()?;
+    let children = json["children"].as_array().unwrap();
+    let names: Vec<_> = children
+        .iter()
+        .map(|v| v["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "content-types",
+            "dir-assets",
+            "dir-no-index",
+            "dir1",
+            "dir2",
+            "dir3",
+            "dir4"
+        ]
+    );
+    assert_eq!(json["dirs"], names.len());
+    let dir1 = &children[3];
+    assert_eq!(dir1["path"], "/dir1");
+    assert_eq!(dir1["dirs"], 1);
+    assert!(dir1["files"].as_u64().unwrap() > 0);
+    let sub = &dir1["children"][0];
+    assert_eq!(sub["name"], "sub");
+    assert_eq!(sub["dirs"], 1);
+    // Past the depth, folders are only counted
+    assert!(sub.get("children").is_none());
+
+    let resp = reqwest::blocking::get(format!("{}?tree&depth=100", server.api_url()))?;
+    assert_eq!(resp.status(), 400);
+    Ok(())
+}
@@ -36,3 +36,36 @@ fn no_log(tmpdir: TempDir, port: u16, #[case] args: &[&str]) -> Result<(), Error
     child.kill()?;
     Ok(())
 }
+
+#[rstest]
+fn json_log_file() -> Result<(), Error> {
+    let log_dir = TempDir::new()?;
+    let log_file = log_dir.path().join("access.log");
+    let server = fixtures::server([
+        "--log-format",
+        "json",
+        "--log-file",
+        log_file.to_str().unwrap(),
+    ]);
+
+    let url = format!("{}dir1/log.txt", server.api_url());
+    let resp = fetch!(b"PUT", &url).body(b"abc".to_vec()).send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.status(), 200);
+
+    let entries: Vec<serde_json::Value> = std::fs::read_to_string(&log_file)?
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .filter(|entry: &serde_json::Value| entry["route"] == "api")
+        .collect();
+    let upload = entries.iter().find(|v| v["method"] == "PUT").unwrap();
+    assert_eq!(upload["status"], 201);
+    assert_eq!(upload["bytes_received"], 3);
+    assert_eq!(upload["provenance_action"], "mint");
+    assert!(upload["latency_ms"].is_u64());
+    let download = entries.iter().find(|v| v["method"] == "GET").unwrap();
+    assert_eq!(download["status"], 200);
+    assert_eq!(download["bytes_sent"], 3);
+    Ok(())
+}
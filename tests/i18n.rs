@@ -0,0 +1,60 @@
+mod fixtures;
+mod utils;
+
+use assert_fs::TempDir;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use fixtures::{server, tmpdir, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+
+#[rstest]
+fn locale_from_accept_language(server: TestServer) -> Result<(), Error> {
+    let resp = fetch!(b"GET", server.api_url())
+        .header("accept-language", "ja, de-CH;q=0.9, en;q=0.8")
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["vary"], "Accept-Language");
+    let json: Value = resp.json()?;
+    assert_eq!(json["locale"]["lang"], "de");
+    assert_eq!(json["locale"]["strings"]["delete"], "Löschen");
+
+    let json: Value = reqwest::blocking::get(server.api_url())?.json()?;
+    assert_eq!(json["locale"]["lang"], "en");
+    assert_eq!(json["locale"]["strings"]["delete"], "Delete");
+    Ok(())
+}
+
+#[rstest]
+fn locale_from_lang(#[with(&["--lang", "fr"])] server: TestServer) -> Result<(), Error> {
+    let json: Value = fetch!(b"GET", server.api_url())
+        .header("accept-language", "de")
+        .send()?
+        .json()?;
+    assert_eq!(json["locale"]["lang"], "fr");
+    assert_eq!(json["locale"]["strings"]["delete"], "Supprimer");
+
+    // Pages for viewing and editing files carry them too
+    let html = fetch!(b"GET", format!("{}index.html?view", server.api_url()))
+        .header("accept-language", "de")
+        .send()?
+        .text()?;
+    let data = html
+        .split_once(r#"<template id="index-data">"#)
+        .and_then(|(_, v)| v.split_once("</template>"))
+        .map(|(v, _)| v)
+        .unwrap();
+    let json: Value = serde_json::from_slice(&STANDARD.decode(data)?)?;
+    assert_eq!(json["locale"]["lang"], "fr");
+    Ok(())
+}
+
+#[rstest]
+fn invalid_lang(tmpdir: TempDir) -> Result<(), Error> {
+    assert_cmd::Command::cargo_bin("node-drive")?
+        .arg(tmpdir.path())
+        .args(["--lang", "xx"])
+        .assert()
+        .stderr(predicates::str::contains("Unsupported language `xx`"))
+        .failure();
+    Ok(())
+}
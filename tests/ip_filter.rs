@@ -0,0 +1,48 @@
+mod fixtures;
+mod utils;
+
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+
+#[rstest]
+fn deny_ip(#[with(&["--deny-ip", "127.0.0.0/8,::1"])] server: TestServer) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(server.url())?;
+    assert_eq!(resp.status(), 403);
+    Ok(())
+}
+
+#[rstest]
+fn allow_ip(#[with(&["--allow-ip", "10.0.0.0/8"])] server: TestServer) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(server.url())?;
+    assert_eq!(resp.status(), 403);
+    Ok(())
+}
+
+#[rstest]
+fn trusted_proxy_sets_client(
+    #[with(&["--trusted-proxies", "127.0.0.1,::1", "--allow-ip", "203.0.113.0/24"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"GET", server.url())
+        .header("X-Forwarded-For", "203.0.113.9")
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    let resp = fetch!(b"GET", server.url())
+        .header("Forwarded", "for=198.51.100.1")
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    let resp = reqwest::blocking::get(server.url())?;
+    assert_eq!(resp.status(), 403);
+    Ok(())
+}
+
+#[rstest]
+fn untrusted_peer_forwarded_ignored(
+    #[with(&["--allow-ip", "203.0.113.0/24"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"GET", server.url())
+        .header("X-Forwarded-For", "203.0.113.9")
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    Ok(())
+}
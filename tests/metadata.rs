@@ -0,0 +1,192 @@
+mod digest_auth_util;
+mod fixtures;
+mod utils;
+
+use digest_auth_util::send_with_digest_auth;
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+
+#[rstest]
+fn annotate_file(server: TestServer) -> Result<(), Error> {
+    let url = format!("{}dir1/test.txt", server.api_url());
+    let resp = fetch!(b"POST", format!("{url}?meta"))
+        .body(r#"{"approval":"approved by legal","ticket":"LEG-42"}"#)
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    let resp = fetch!(b"POST", format!("{url}?meta"))
+        .body(r#"{"ticket":null}"#)
+        .send()?;
+    assert_eq!(resp.status(), 200);
+
+    let resp = fetch!(b"POST", format!("{url}?comment"))
+        .body(r#"{"text":"Reviewed, ship it"}"#)
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let comment: Value = serde_json::from_str(&resp.text()?)?;
+    let comment_id = comment["id"].as_i64().unwrap();
+
+    let resp = reqwest::blocking::get(format!("{url}?meta"))?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(
+        json["metadata"],
+        serde_json::json!({ "approval": "approved by legal" })
+    );
+    assert_eq!(json["comments"][0]["body"], "Reviewed, ship it");
+
+    let resp = reqwest::blocking::get(format!("{}dir1/", server.api_url()))?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    let item = json["paths"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|v| v["name"] == "dir1/test.txt")
+        .unwrap();
+    assert_eq!(item["metadata"]["approval"], "approved by legal");
+    assert_eq!(item["comment_count"], 1);
+
+    let resp = fetch!(b"DELETE", format!("{url}?comment={comment_id}")).send()?;
+    assert_eq!(resp.status(), 204);
+    let resp = fetch!(b"DELETE", format!("{url}?comment={comment_id}")).send()?;
+    assert_eq!(resp.status(), 404);
+    Ok(())
+}
+
+#[rstest]
+fn annotate_file_rejects_invalid(server: TestServer) -> Result<(), Error> {
+    let url = format!("{}dir1/test.txt", server.api_url());
+    let resp = fetch!(b"POST", format!("{url}?meta"))
+        .body(r#"["not", "an", "object"]"#)
+        .send()?;
+    assert_eq!(resp.status(), 400);
+    let resp = fetch!(b"POST", format!("{url}?comment"))
+        .body(r#"{"text":"   "}"#)
+        .send()?;
+    assert_eq!(resp.status(), 400);
+    let resp = fetch!(b"POST", format!("{}missing.txt?meta", server.api_url()))
+        .body("{}")
+        .send()?;
+    assert_eq!(resp.status(), 404);
+    Ok(())
+}
+
+#[rstest]
+fn annotate_file_requires_write(
+    #[with(&["--auth", "user:pass@/:rw", "--auth", "guest:pass@/:ro"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}dir1/test.txt?meta", server.api_url());
+    let body = r#"{"k":"v"}"#;
+    let resp = send_with_digest_auth(fetch!(b"POST", &url).body(body), "guest", "pass")?;
+    assert_eq!(resp.status(), 403);
+    let resp = send_with_digest_auth(fetch!(b"POST", &url).body(body), "user", "pass")?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["metadata"]["k"], "v");
+    Ok(())
+}
+
+#[rstest]
+fn search_matches_annotations(server: TestServer) -> Result<(), Error> {
+    let resp = fetch!(b"POST", format!("{}dir1/test.txt?meta", server.api_url()))
+        .body(r#"{"status":"Approved by Legal"}"#)
+        .send()?;
+    assert_eq!(resp.status(), 200);
+
+    let resp = reqwest::blocking::get(format!("{}?q=legal", server.api_url()))?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    let names: Vec<&str> = json["paths"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["dir1/test.txt"]);
+    Ok(())
+}
+
+#[rstest]
+fn tag_files(server: TestServer) -> Result<(), Error> {
+    for (file, tags) in [
+        ("dir1/test.txt", r#"{"add":["Legal","q3"]}"#),
+        ("dir2/test.html", r#"{"add":["legal"]}"#),
+        ("test.txt", r#"{"add":["draft"]}"#),
+    ] {
+        let resp = fetch!(b"POST", format!("{}{file}?tags", server.api_url()))
+            .body(tags)
+            .send()?;
+        assert_eq!(resp.status(), 200);
+    }
+    let resp = fetch!(b"POST", format!("{}dir1/test.txt?tags", server.api_url()))
+        .body(r#"{"remove":["q3"]}"#)
+        .send()?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["tags"], serde_json::json!(["legal"]));
+
+    let resp = reqwest::blocking::get(format!("{}?tags", server.api_url()))?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(
+        json["tags"],
+        serde_json::json!([{ "name": "draft", "count": 1 }, { "name": "legal", "count": 2 }])
+    );
+
+    let resp = reqwest::blocking::get(format!("{}?tag=LEGAL", server.api_url()))?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    let mut names: Vec<&str> = json["paths"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v["name"].as_str().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["dir1/test.txt", "dir2/test.html"]);
+    assert_eq!(json["paths"][0]["tags"], serde_json::json!(["legal"]));
+
+    let resp = reqwest::blocking::get(format!("{}?q=test.txt&tag=legal", server.api_url()))?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["paths"].as_array().unwrap().len(), 1);
+
+    let resp = fetch!(b"POST", format!("{}test.txt?tags", server.api_url()))
+        .body(r#"{"add":["two words"]}"#)
+        .send()?;
+    assert_eq!(resp.status(), 400);
+    Ok(())
+}
+
+#[rstest]
+fn star_files_per_user(
+    #[with(&["--auth", "user:pass@/:rw", "--auth", "guest:pass@/dir1:ro"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}dir1/test.txt?star", server.api_url());
+    let resp = send_with_digest_auth(fetch!(b"POST", &url), "guest", "pass")?;
+    assert_eq!(resp.status(), 200);
+    let resp = send_with_digest_auth(
+        fetch!(b"POST", format!("{}dir2/test.txt?star", server.api_url())),
+        "guest",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 403);
+
+    let starred_names = |user: &str| -> Result<Vec<String>, Error> {
+        let url = format!("{}dir1/?starred", server.api_url());
+        let resp = send_with_digest_auth(fetch!(b"GET", &url), user, "pass")?;
+        assert_eq!(resp.status(), 200);
+        let json: Value = serde_json::from_str(&resp.text()?)?;
+        Ok(json["paths"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v["name"].as_str().unwrap().to_string())
+            .collect())
+    };
+    assert_eq!(starred_names("guest")?, ["test.txt"]);
+    assert!(starred_names("user")?.is_empty());
+
+    let resp = send_with_digest_auth(fetch!(b"DELETE", &url), "guest", "pass")?;
+    assert_eq!(resp.status(), 200);
+    assert!(starred_names("guest")?.is_empty());
+    let resp = send_with_digest_auth(fetch!(b"DELETE", &url), "guest", "pass")?;
+    assert_eq!(resp.status(), 404);
+    Ok(())
+}
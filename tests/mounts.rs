@@ -0,0 +1,127 @@
+mod digest_auth_util;
+mod fixtures;
+mod utils;
+
+use assert_cmd::prelude::*;
+use digest_auth_util::send_with_digest_auth;
+use fixtures::{port, tmpdir, wait_for_port, Error, TestServer};
+use serde_json::Value;
+use std::process::{Command, Stdio};
+
+fn mounts_server(db: &std::path::Path) -> Result<TestServer, Error> {
+    let tmpdir = tmpdir();
+    let port = port();
+    let child = Command::cargo_bin("node-drive")?
+        .arg(format!("/docs:{}", tmpdir.path().join("dir1").display()))
+        .arg(format!("/media:{}", tmpdir.path().join("dir2").display()))
+        .args(["-p", &port.to_string(), "--allow-upload", "--allow-delete"])
+        .args(["-a", "admin:pass@/:rw", "-a", "user:pass@/docs:rw"])
+        .args(["--provenance-db", db.to_str().unwrap()])
+        .stdout(Stdio::null())
+        .spawn()?;
+    wait_for_port(port);
+    Ok(TestServer::new(port, tmpdir, child, false))
+}
+
+#[test]
+fn mounts_index() -> Result<(), Error> {
+    let db_dir = assert_fs::TempDir::new()?;
+    let server = mounts_server(&db_dir.path().join("provenance.db"))?;
+
+    let resp = fetch!(b"GET", server.api_url()).send()?;
+    assert_eq!(resp.status(), 401);
+    let resp = send_with_digest_auth(fetch!(b"GET", server.api_url()), "admin", "pass")?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    let names: Vec<_> = json["paths"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["docs", "media"]);
+
+    // Only the mounts the user has access to
+    let resp = send_with_digest_auth(fetch!(b"GET", server.api_url()), "user", "pass")?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["paths"].as_array().unwrap().len(), 1);
+    assert_eq!(json["paths"][0]["name"], "docs");
+
+    let resp = send_with_digest_auth(
+        fetch!(b"PROPFIND", server.url()).header("Depth", "1"),
+        "admin",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 207);
+    let body = resp.text()?;
+    assert!(body.contains("<D:href>/docs/</D:href>"));
+    assert!(body.contains("<D:href>/media/</D:href>"));
+
+    // Nothing is served outside of the mounts
+    let resp = send_with_digest_auth(
+        fetch!(b"GET", format!("{}dir1/test.txt", server.api_url())),
+        "admin",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 404);
+    Ok(())
+}
+
+#[test]
+fn mounts_serve_files() -> Result<(), Error> {
+    let db_dir = assert_fs::TempDir::new()?;
+    let server = mounts_server(&db_dir.path().join("provenance.db"))?;
+
+    let resp = send_with_digest_auth(
+        fetch!(b"GET", format!("{}docs/test.txt", server.api_url())),
+        "user",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.text()?, "This is dir1/test.txt");
+
+    // Rules name the mount, and apply to what is below it
+    let resp = send_with_digest_auth(
+        fetch!(b"PUT", format!("{}docs/new.txt", server.api_url())).body(b"abc".to_vec()),
+        "user",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 201);
+    assert!(server.path().join("dir1/new.txt").exists());
+    let resp = send_with_digest_auth(
+        fetch!(b"PUT", format!("{}media/new.txt", server.api_url())).body(b"abc".to_vec()),
+        "user",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 403);
+
+    let resp = send_with_digest_auth(
+        fetch!(
+            b"GET",
+            format!("{}docs/new.txt?manifest=json", server.api_url())
+        ),
+        "user",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["events"][0]["action"], "mint");
+
+    let resp = send_with_digest_auth(
+        fetch!(b"PROPFIND", format!("{}docs/", server.url())).header("Depth", "1"),
+        "user",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 207);
+    assert!(resp.text()?.contains("<D:href>/docs/new.txt</D:href>"));
+
+    let resp = send_with_digest_auth(
+        fetch!(b"MOVE", format!("{}docs/new.txt", server.url()))
+            .header("Destination", format!("{}docs/moved.txt", server.url())),
+        "admin",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 204);
+    assert!(server.path().join("dir1/moved.txt").exists());
+    Ok(())
+}
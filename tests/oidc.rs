@@ -0,0 +1,204 @@
+mod fixtures;
+mod utils;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use fixtures::{server, Error};
+use reqwest::{blocking::Client, redirect::Policy};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// A provider answering discovery and token requests, issuing ID tokens for
+/// `alice` with the nonce of the login in progress
+fn mock_provider(nonce: Arc<Mutex<String>>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    let base = issuer.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let output = if request_line.contains("/.well-known/openid-configuration") {
+                serde_json::json!({
+                    "issuer": base,
+                    "authorization_endpoint": format!("{base}/authorize"),
+                    "token_endpoint": format!("{base}/token"),
+                })
+            } else {
+                let exp = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+                    + 300;
+                let claims = serde_json::json!({
+                    "iss": base,
+                    "aud": "drive",
+                    "sub": "1234",
+                    "exp": exp,
+                    "nonce": *nonce.lock().unwrap(),
+                    "preferred_username": "alice",
+                });
+                let id_token = format!("e30.{}.sig", URL_SAFE_NO_PAD.encode(claims.to_string()));
+                serde_json::json!({ "id_token": id_token, "token_type": "Bearer" })
+            }
+            .to_string();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{output}",
+                output.len()
+            );
+        }
+    });
+    issuer
+}
+
+#[test]
+fn oidc_login() -> Result<(), Error> {
+    let nonce = Arc::new(Mutex::new(String::new()));
+    let issuer = mock_provider(nonce.clone());
+    let server = server([
+        "-a",
+        "alice:pass@/:rw",
+        "--oidc-issuer",
+        &issuer,
+        "--oidc-client-id",
+        "drive",
+    ]);
+    let client = Client::builder().redirect(Policy::none()).build()?;
+
+    // Browsers are sent to the login, other clients get the usual 401
+    let resp = client
+        .get(format!("{}dir1/", server.api_url()))
+        .header("accept", "text/html")
+        .send()?;
+    assert_eq!(resp.status(), 302);
+    assert_eq!(
+        resp.headers()["location"],
+        "/__dufs__/oidc/login?redirect=%2Fapi%2Fdir1%2F"
+    );
+    let resp = client.get(format!("{}dir1/", server.api_url())).send()?;
+    assert_eq!(resp.status(), 401);
+
+    let resp = client
+        .get(format!(
+            "{}__dufs__/oidc/login?redirect=/dir1/",
+            server.url()
+        ))
+        .send()?;
+    assert_eq!(resp.status(), 302);
+    let location = Url::parse(resp.headers()["location"].to_str()?)?;
+    assert_eq!(
+        location.as_str().split('?').next(),
+        Some(format!("{issuer}/authorize").as_str())
+    );
+    let params: HashMap<_, _> = location.query_pairs().into_owned().collect();
+    assert_eq!(params["client_id"], "drive");
+    assert_eq!(params["code_challenge_method"], "S256");
+    assert_eq!(
+        params["redirect_uri"],
+        format!("{}__dufs__/oidc/callback", server.url())
+    );
+    *nonce.lock().unwrap() = params["nonce"].clone();
+    let state_cookie = resp.headers()["set-cookie"].to_str()?;
+    assert!(state_cookie.contains("Path=/__dufs__/oidc/callback"));
+    let state_cookie = state_cookie.split(';').next().unwrap().to_string();
+    let callback_url = format!(
+        "{}__dufs__/oidc/callback?code=abc&state={}",
+        server.url(),
+        params["state"]
+    );
+
+    // Only the browser that started the login can complete it
+    let resp = client.get(&callback_url).send()?;
+    assert_eq!(resp.status(), 400);
+    let resp = client
+        .get(&callback_url)
+        .header("cookie", "dufs_oidc_state=forged")
+        .send()?;
+    assert_eq!(resp.status(), 400);
+
+    let resp = client
+        .get(&callback_url)
+        .header("cookie", &state_cookie)
+        .send()?;
+    assert_eq!(resp.status(), 302);
+    assert_eq!(resp.headers()["location"], "/dir1/");
+    let cookies: Vec<_> = resp
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .map(|v| v.to_str().unwrap())
+        .collect();
+    assert!(cookies
+        .iter()
+        .any(|v| v.starts_with("dufs_oidc_state=;") && v.contains("Max-Age=0")));
+    let cookie = cookies
+        .iter()
+        .find(|v| v.starts_with("dufs_session="))
+        .unwrap();
+    assert!(cookie.contains("HttpOnly"));
+    let session = cookie.split(';').next().unwrap().to_string();
+
+    let resp = client
+        .request(reqwest::Method::from_bytes(b"CHECKAUTH")?, server.url())
+        .header("cookie", &session)
+        .send()?;
+    assert_eq!(resp.text()?, "alice");
+    let resp = client
+        .put(format!("{}dir1/from-oidc.txt", server.api_url()))
+        .header("cookie", &session)
+        .body("hello")
+        .send()?;
+    assert_eq!(resp.status(), 201);
+
+    // A login can't be completed twice
+    let resp = client
+        .get(&callback_url)
+        .header("cookie", &state_cookie)
+        .send()?;
+    assert_eq!(resp.status(), 400);
+
+    // Nor sent anywhere but to a path of this server
+    let resp = client
+        .get(format!(
+            "{}__dufs__/oidc/login?redirect=/%5Cevil.example.com",
+            server.url()
+        ))
+        .send()?;
+    let location = Url::parse(resp.headers()["location"].to_str()?)?;
+    let params: HashMap<_, _> = location.query_pairs().into_owned().collect();
+    *nonce.lock().unwrap() = params["nonce"].clone();
+    let state_cookie = resp.headers()["set-cookie"].to_str()?;
+    let state_cookie = state_cookie.split(';').next().unwrap().to_string();
+    let resp = client
+        .get(format!(
+            "{}__dufs__/oidc/callback?code=abc&state={}",
+            server.url(),
+            params["state"]
+        ))
+        .header("cookie", &state_cookie)
+        .send()?;
+    assert_eq!(resp.status(), 302);
+    assert_eq!(resp.headers()["location"], "/");
+    Ok(())
+}
@@ -0,0 +1,48 @@
+mod fixtures;
+mod utils;
+
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+
+#[rstest]
+fn openapi_spec(#[with(&["--auth", "user:pass@/:rw"])] server: TestServer) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(format!("{}__dufs__/openapi.json", server.url()))?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = resp.json()?;
+    assert_eq!(json["openapi"], "3.1.0");
+    let api = &json["paths"]["/api/{path}"];
+    assert_eq!(api["put"]["tags"][0], "api");
+    assert_eq!(api["get"]["parameters"][0]["name"], "path");
+    assert_eq!(
+        api["delete"]["responses"]["default"]["$ref"],
+        "#/components/responses/Error"
+    );
+    assert!(json["paths"]["/__dufs__/tokens/{id}"]["delete"].is_object());
+    assert_eq!(json["paths"]["/{path}"]["x-methods"][0], "PROPFIND");
+    assert!(json["components"]["schemas"]["Error"]["properties"]["code"].is_object());
+    Ok(())
+}
+
+#[rstest]
+fn api_errors_are_json(server: TestServer) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(format!("{}missing.txt", server.api_url()))?;
+    assert_eq!(resp.status(), 404);
+    assert_eq!(resp.headers()["content-type"], "application/json");
+    let json: Value = resp.json()?;
+    assert_eq!(json["code"], "not_found");
+    assert_eq!(json["message"], "Not Found");
+    assert!(json["details"].is_null());
+
+    let resp = reqwest::blocking::get(format!("{}?offset=x", server.api_url()))?;
+    assert_eq!(resp.status(), 400);
+    let json: Value = resp.json()?;
+    assert_eq!(json["code"], "bad_request");
+    assert_eq!(json["message"], "Invalid offset");
+
+    // WebDAV clients keep plain-text bodies
+    let resp = fetch!(b"PROPFIND", format!("{}missing.txt", server.url())).send()?;
+    assert_eq!(resp.status(), 404);
+    assert_eq!(resp.text()?, "Not Found");
+    Ok(())
+}
@@ -0,0 +1,63 @@
+mod fixtures;
+mod utils;
+
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+#[rstest]
+fn parts_manifest(server: TestServer) -> Result<(), Error> {
+    let data: Vec<u8> = (0..(5u32 << 19)).map(|v| (v % 251) as u8).collect();
+    let url = format!("{}big.bin", server.url());
+    let resp = fetch!(b"PUT", &url).body(data.clone()).send()?;
+    assert_eq!(resp.status(), 201);
+
+    let resp = reqwest::blocking::get(format!("{url}?parts=1MB"))?;
+    assert_eq!(resp.status(), 200);
+    let etag = resp.headers()["etag"].clone();
+    let json: Value = resp.json()?;
+    assert_eq!(json["name"], "big.bin");
+    assert_eq!(json["size"], data.len());
+    assert_eq!(json["part_size"], 1 << 20);
+    assert_eq!(json["sha256"], format!("{:x}", Sha256::digest(&data)));
+    let parts = json["parts"].as_array().unwrap();
+    assert_eq!(parts.len(), 3);
+    assert_eq!(parts[2]["start"], 2 << 20);
+    assert_eq!(parts[2]["end"], data.len() - 1);
+
+    // Each part fetched by range matches its checksum
+    for part in parts {
+        let (start, end) = (
+            part["start"].as_u64().unwrap(),
+            part["end"].as_u64().unwrap(),
+        );
+        let resp = fetch!(b"GET", &url)
+            .header("range", format!("bytes={start}-{end}"))
+            .header("if-range", etag.clone())
+            .send()?;
+        assert_eq!(resp.status(), 206);
+        let body = resp.bytes()?;
+        assert_eq!(&body[..], &data[start as usize..=end as usize]);
+        assert_eq!(part["sha256"], format!("{:x}", Sha256::digest(&body)));
+    }
+
+    for parts in ["10", "1K", "lots"] {
+        let resp = reqwest::blocking::get(format!("{url}?parts={parts}"))?;
+        assert_eq!(resp.status(), 400, "{parts}");
+    }
+    Ok(())
+}
+
+#[rstest]
+fn parts_manifest_empty_file(server: TestServer) -> Result<(), Error> {
+    let url = format!("{}empty.bin", server.url());
+    let resp = fetch!(b"PUT", &url).body(vec![]).send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = reqwest::blocking::get(format!("{url}?parts=1M"))?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = resp.json()?;
+    assert_eq!(json["size"], 0);
+    assert!(json["parts"].as_array().unwrap().is_empty());
+    Ok(())
+}
@@ -0,0 +1,61 @@
+//! Runs against the database in `NODE_DRIVE_TEST_POSTGRES_URL`, e.g.
+//! `postgres://postgres@localhost/postgres`, and is skipped without it.
+#![cfg(feature = "postgres")]
+
+mod fixtures;
+mod utils;
+
+use assert_cmd::prelude::*;
+use assert_fs::TempDir;
+use fixtures::{port, tmpdir, wait_for_port, Error};
+use rstest::rstest;
+use std::process::{Child, Command, Stdio};
+
+#[rstest]
+fn instances_share_provenance_chain(tmpdir: TempDir) -> Result<(), Error> {
+    let Ok(url) = std::env::var("NODE_DRIVE_TEST_POSTGRES_URL") else {
+        eprintln!("NODE_DRIVE_TEST_POSTGRES_URL is not set, skipping");
+        return Ok(());
+    };
+    let (port1, mut child1) = spawn_instance(&tmpdir, &url, "local1.db")?;
+    let (port2, mut child2) = spawn_instance(&tmpdir, &url, "local2.db")?;
+
+    let url1 = format!("http://localhost:{port1}/api/");
+    let url2 = format!("http://localhost:{port2}/api/");
+    let resp = fetch!(b"PUT", format!("{url1}shared.txt"))
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = reqwest::blocking::get(format!("{url2}shared.txt?manifest=json"))?;
+    assert_eq!(resp.status(), 200);
+    let manifest: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(manifest["events"].as_array().unwrap().len(), 1);
+
+    // A move on one instance is seen by the other
+    let resp = fetch!(b"MOVE", format!("http://localhost:{port2}/shared.txt"))
+        .header("Destination", format!("http://localhost:{port2}/moved.txt"))
+        .send()?;
+    assert_eq!(resp.status(), 204);
+    let resp = reqwest::blocking::get(format!("{url1}moved.txt?manifest=json"))?;
+    assert_eq!(resp.status(), 200);
+
+    child1.kill()?;
+    child2.kill()?;
+    Ok(())
+}
+
+fn spawn_instance(tmpdir: &TempDir, url: &str, local_db: &str) -> Result<(u16, Child), Error> {
+    let port = port();
+    let child = Command::cargo_bin("node-drive")?
+        .arg(tmpdir.path())
+        .arg("-p")
+        .arg(port.to_string())
+        .args(["--allow-upload", "--allow-delete"])
+        .args(["--provenance-db", url])
+        .arg("--provenance-local-db")
+        .arg(tmpdir.path().join(local_db))
+        .stdout(Stdio::null())
+        .spawn()?;
+    wait_for_port(port);
+    Ok((port, child))
+}
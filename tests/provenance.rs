@@ -0,0 +1,665 @@
+mod digest_auth_util;
+mod fixtures;
+mod utils;
+
+use digest_auth_util::send_with_digest_auth;
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+
+const PROVENANCE_DB_PATH: &str = "__dufs__/provenance-db";
+
+#[rstest]
+fn download_provenance_db(server: TestServer) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(format!("{}{PROVENANCE_DB_PATH}", server.url()))?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/vnd.sqlite3"
+    );
+    let body = resp.bytes()?;
+    assert!(body.starts_with(b"SQLite format 3\0"));
+    Ok(())
+}
+
+#[rstest]
+fn download_provenance_db_requires_admin(
+    #[with(&["--auth", "user:pass@/:rw", "--auth", "guest:pass@/dir1:rw"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}{PROVENANCE_DB_PATH}", server.url());
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.status(), 401);
+    let resp = send_with_digest_auth(fetch!(b"GET", &url), "guest", "pass")?;
+    assert_eq!(resp.status(), 403);
+    let resp = send_with_digest_auth(fetch!(b"GET", &url), "user", "pass")?;
+    assert_eq!(resp.status(), 200);
+    assert!(resp.bytes()?.starts_with(b"SQLite format 3\0"));
+    Ok(())
+}
+
+#[rstest]
+fn provenance_anchor_missing(server: TestServer) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(format!("{}__dufs__/provenance-anchor", server.url()))?;
+    assert_eq!(resp.status(), 404);
+    Ok(())
+}
+
+#[rstest]
+fn tenant_databases() -> Result<(), Error> {
+    let db_dir = assert_fs::TempDir::new()?;
+    let server = server(["--tenant-db-dir", db_dir.path().to_str().unwrap()]);
+    let mut creators = vec![];
+    for file in ["dir1/a.txt", "dir2/a.txt"] {
+        let url = format!("{}{file}", server.api_url());
+        let resp = fetch!(b"PUT", &url).body(b"abc".to_vec()).send()?;
+        assert_eq!(resp.status(), 201);
+        let resp = reqwest::blocking::get(format!("{url}?manifest=json"))?;
+        let json: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+        creators.push(json["events"][0]["actors"]["creator_pubkey_hex"].clone());
+    }
+    assert!(db_dir.path().join("dir1.db").exists());
+    assert!(db_dir.path().join("dir2.db").exists());
+    assert_ne!(creators[0], creators[1]);
+
+    let resp = fetch!(b"POST", format!("{}dir1/a.txt?share", server.api_url())).send()?;
+    let json: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["owner_pubkey"], creators[0]);
+    let share_url = format!(
+        "{}share/{}/info",
+        server.url(),
+        json["share_id"].as_str().unwrap()
+    );
+    let resp = reqwest::blocking::get(share_url)?;
+    assert_eq!(resp.status(), 200);
+
+    let resp = fetch!(b"MOVE", format!("{}dir1/a.txt", server.url()))
+        .header("Destination", format!("{}dir2/b.txt", server.url()))
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    Ok(())
+}
+
+#[rstest]
+fn transfer_ownership() -> Result<(), Error> {
+    use secp256k1::{Message, Secp256k1, SecretKey};
+    use sha2::{Digest, Sha256};
+
+    // The server mints with this key, so it is the first owner
+    let key_dir = assert_fs::TempDir::new()?;
+    let key_file = key_dir.path().join("signing.key");
+    std::fs::write(&key_file, hex::encode([3u8; 32]))?;
+    let server = server(["--signing-key", key_file.to_str().unwrap()]);
+    let prev_owner = SecretKey::from_slice(&[3u8; 32])?;
+    let new_owner = SecretKey::from_slice(&[7u8; 32])?;
+    let secp = Secp256k1::new();
+    let pubkey_hex = |key: &SecretKey| hex::encode(key.public_key(&secp).serialize());
+    let sign = |key: &SecretKey, hash: &str| {
+        let message = Message::from_digest_slice(&hex::decode(hash).unwrap()).unwrap();
+        hex::encode(secp.sign_ecdsa(&message, key).serialize_der())
+    };
+
+    let url = format!("{}transfer.txt", server.api_url());
+    let resp = fetch!(b"PUT", &url).body(b"abc".to_vec()).send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = reqwest::blocking::get(format!("{url}?manifest=json"))?;
+    let manifest: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    let mint = &manifest["events"][0];
+    let head = mint["event_hash_hex"].as_str().unwrap();
+
+    let issued_at = "2099-01-01T00:00:00+00:00";
+    let canonical = format!(
+        r#"{{"type":"provenance.event/v1","index":1,"action":"transfer","artifact_sha256_hex":"{}","prev_event_hash_hex":"{head}","actors":{{"new_owner_pubkey_hex":"{}","prev_owner_pubkey_hex":"{}"}},"issued_at":"{issued_at}"}}"#,
+        mint["artifact_sha256_hex"].as_str().unwrap(),
+        pubkey_hex(&new_owner),
+        pubkey_hex(&prev_owner),
+    );
+    let event_hash = hex::encode(Sha256::digest(canonical.as_bytes()));
+    let body = serde_json::json!({
+        "prev_owner_pubkey_hex": pubkey_hex(&prev_owner),
+        "new_owner_pubkey_hex": pubkey_hex(&new_owner),
+        "issued_at": issued_at,
+        "prev_event_hash_hex": head,
+        "prev_owner_sig_hex": sign(&prev_owner, &event_hash),
+        "new_owner_sig_hex": sign(&new_owner, &event_hash),
+    });
+
+    // Signed by the wrong key
+    let mut forged = body.clone();
+    forged["prev_owner_sig_hex"] = sign(&new_owner, &event_hash).into();
+    let resp = fetch!(b"POST", format!("{url}?transfer"))
+        .body(forged.to_string())
+        .send()?;
+    assert_eq!(resp.status(), 400);
+
+    let resp = fetch!(b"POST", format!("{url}?transfer"))
+        .body(body.to_string())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let event: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(event["index"], 1);
+    assert_eq!(event["event_hash_hex"], event_hash);
+
+    // The chain moved on, replaying the same transfer is stale
+    let resp = fetch!(b"POST", format!("{url}?transfer"))
+        .body(body.to_string())
+        .send()?;
+    assert_eq!(resp.status(), 409);
+
+    let resp = reqwest::blocking::get(format!("{url}?manifest=json"))?;
+    let manifest: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(manifest["events"].as_array().unwrap().len(), 2);
+    Ok(())
+}
+
+#[rstest]
+fn attest_file(#[with(&["--allow-upload"])] server: TestServer) -> Result<(), Error> {
+    use ed25519_dalek::{Signer, SigningKey};
+    use secp256k1::{Message, Secp256k1, SecretKey};
+
+    let url = format!("{}attested.txt", server.api_url());
+    let resp = fetch!(b"PUT", &url).body(b"abc".to_vec()).send()?;
+    assert_eq!(resp.status(), 201);
+    let mint: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    let sha256 = hex::decode(mint["sha256"].as_str().unwrap())?;
+
+    let secp = Secp256k1::new();
+    let key = SecretKey::from_slice(&[5u8; 32])?;
+    let message = Message::from_digest_slice(&sha256)?;
+    let body = serde_json::json!({
+        "pubkey_hex": hex::encode(key.public_key(&secp).serialize()),
+        "signature_hex": hex::encode(secp.sign_ecdsa(&message, &key).serialize_der()),
+    });
+    let resp = fetch!(b"POST", format!("{url}?signature"))
+        .body(body.to_string())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+
+    let key = SigningKey::from_bytes(&[9u8; 32]);
+    let body = serde_json::json!({
+        "algorithm": "ed25519",
+        "pubkey_hex": hex::encode(key.verifying_key().as_bytes()),
+        "signature_hex": hex::encode(key.sign(&sha256).to_bytes()),
+    });
+    let resp = fetch!(b"POST", format!("{url}?signature"))
+        .body(body.to_string())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+
+    // Signatures over anything else are refused
+    let body = serde_json::json!({
+        "algorithm": "ed25519",
+        "pubkey_hex": hex::encode(key.verifying_key().as_bytes()),
+        "signature_hex": hex::encode(key.sign(b"other").to_bytes()),
+    });
+    let resp = fetch!(b"POST", format!("{url}?signature"))
+        .body(body.to_string())
+        .send()?;
+    assert_eq!(resp.status(), 400);
+
+    let resp = reqwest::blocking::get(format!("{url}?manifest=json"))?;
+    let manifest: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    let attestations = manifest["attestations"].as_array().unwrap();
+    assert_eq!(attestations.len(), 2);
+    assert_eq!(attestations[0]["algorithm"], "secp256k1");
+    assert_eq!(attestations[1]["algorithm"], "ed25519");
+    Ok(())
+}
+
+#[rstest]
+fn signing_key_rotation() -> Result<(), Error> {
+    let key_dir = assert_fs::TempDir::new()?;
+    let db = key_dir.path().join("provenance.db");
+    let args = ["--provenance-db", db.to_str().unwrap()];
+    let url_of =
+        |server: &TestServer| format!("{}.well-known/node-drive/signing-key", server.url());
+
+    let server1 = server(args);
+    let resp = reqwest::blocking::get(url_of(&server1))?;
+    assert_eq!(resp.status(), 200);
+    let first: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(first["algorithm"], "secp256k1");
+    assert_eq!(first["keys"][0]["public_key_hex"], first["public_key_hex"]);
+    drop(server1);
+
+    // Restarts keep the generated key
+    let server2 = server(args);
+    let second: serde_json::Value =
+        serde_json::from_str(&reqwest::blocking::get(url_of(&server2))?.text()?)?;
+    assert_eq!(second["public_key_hex"], first["public_key_hex"]);
+    drop(server2);
+
+    let server3 = server([&args[..], &["--rotate-signing-key"]].concat());
+    let rotated: serde_json::Value =
+        serde_json::from_str(&reqwest::blocking::get(url_of(&server3))?.text()?)?;
+    assert_ne!(rotated["public_key_hex"], first["public_key_hex"]);
+    assert_eq!(
+        rotated["keys"][1]["public_key_hex"],
+        first["public_key_hex"]
+    );
+    assert!(rotated["keys"][1]["retired_at"].is_string());
+    Ok(())
+}
+
+#[rstest]
+fn mint_hashes_uploaded_body(server: TestServer) -> Result<(), Error> {
+    use sha2::{Digest, Sha256};
+
+    let body: Vec<u8> = (0..200_000u32).map(|v| (v % 251) as u8).collect();
+    let url = format!("{}hashed.bin", server.api_url());
+    let resp = fetch!(b"PUT", &url).body(body.clone()).send()?;
+    assert_eq!(resp.status(), 201);
+    let mint: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(mint["sha256"], hex::encode(Sha256::digest(&body)));
+
+    let resp = reqwest::blocking::get(format!("{url}?manifest=json"))?;
+    let manifest: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(manifest["artifact"]["sha256_hex"], mint["sha256"]);
+    Ok(())
+}
+
+#[rstest]
+fn concurrent_uploads_stay_whole(server: TestServer) -> Result<(), Error> {
+    use sha2::{Digest, Sha256};
+
+    let url = format!("{}racy.bin", server.api_url());
+    let bodies: Vec<Vec<u8>> = (0..2u8).map(|v| vec![v; 4 * 1024 * 1024]).collect();
+    let uploads: Vec<_> = bodies
+        .iter()
+        .map(|body| {
+            let (url, body) = (url.clone(), body.clone());
+            std::thread::spawn(move || {
+                let client = reqwest::blocking::Client::new();
+                client.put(&url).body(body).send().map(|v| v.status())
+            })
+        })
+        .collect();
+    for upload in uploads {
+        assert_eq!(upload.join().unwrap()?, 201);
+    }
+
+    let content = reqwest::blocking::get(&url)?.bytes()?;
+    assert!(bodies.iter().any(|v| v[..] == content[..]));
+    let resp = reqwest::blocking::get(format!("{url}?manifest=json"))?;
+    let manifest: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(
+        manifest["artifact"]["sha256_hex"],
+        hex::encode(Sha256::digest(&content))
+    );
+    let leftovers = std::fs::read_dir(server.path())?
+        .flatten()
+        .filter(|v| v.file_name().to_string_lossy().ends_with(".upload"))
+        .count();
+    assert_eq!(leftovers, 0);
+    Ok(())
+}
+
+#[rstest]
+fn encrypted_upload_records_scheme(server: TestServer) -> Result<(), Error> {
+    let url = format!("{}secret.age", server.api_url());
+    let resp = fetch!(b"PUT", &url)
+        .header("X-Encrypted", "age")
+        .header(
+            "X-Encrypted-Recipients",
+            "age1qyqszqgpqyqszqgp, age1zvkyg2lqzra",
+        )
+        .body(b"age-encryption.org/v1\n-> X25519 c2VjcmV0\n".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = reqwest::blocking::get(format!("{url}?manifest=json"))?;
+    let manifest: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(manifest["artifact"]["encryption"]["scheme"], "age");
+    assert_eq!(
+        manifest["artifact"]["encryption"]["recipients"],
+        serde_json::json!(["age1qyqszqgpqyqszqgp", "age1zvkyg2lqzra"])
+    );
+    let resp = reqwest::blocking::get(format!("{}?json", server.api_url()))?;
+    let listing: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    let entry = listing["paths"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|v| v["name"] == "secret.age")
+        .unwrap();
+    assert_eq!(entry["encryption"]["scheme"], "age");
+
+    // Plaintext replacing it is no longer marked encrypted
+    let resp = fetch!(b"PUT", &url).body(b"plain".to_vec()).send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = reqwest::blocking::get(format!("{url}?manifest=json"))?;
+    let manifest: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert!(manifest["artifact"].get("encryption").is_none());
+    Ok(())
+}
+
+#[rstest]
+fn encrypted_upload_rejects_plaintext(server: TestServer) -> Result<(), Error> {
+    let url = format!("{}secret.gpg", server.api_url());
+    let resp = fetch!(b"PUT", &url)
+        .header("X-Encrypted", "gpg")
+        .body(b"not encrypted at all".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 422);
+    assert_eq!(reqwest::blocking::get(&url)?.status(), 404);
+    let resp = fetch!(b"PUT", &url)
+        .header("X-Encrypted", "rot13")
+        .body(b"-----BEGIN PGP MESSAGE-----\n".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 400);
+    Ok(())
+}
+
+#[rstest]
+fn verify_chain_detects_tampering() -> Result<(), Error> {
+    let db_dir = assert_fs::TempDir::new()?;
+    let db = db_dir.path().join("provenance.db");
+    let server = server(["--provenance-db", db.to_str().unwrap()]);
+    let url = format!("{}checked.txt", server.api_url());
+    let resp = fetch!(b"PUT", &url).body(b"abc".to_vec()).send()?;
+    assert_eq!(resp.status(), 201);
+
+    let resp = reqwest::blocking::get(format!("{url}?verify-chain"))?;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["valid"], true);
+    assert_eq!(json["events"][0]["action"], "mint");
+    assert_eq!(json["events"][0]["passed"], true);
+
+    let conn = rusqlite::Connection::open(&db)?;
+    conn.execute("UPDATE events SET issued_at = '2000-01-01T00:00:00Z'", [])?;
+    let resp = reqwest::blocking::get(format!("{url}?verify-chain"))?;
+    let json: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["valid"], false);
+    assert_eq!(json["events"][0]["hash_valid"], false);
+    assert_eq!(json["events"][0]["linked"], true);
+    assert_eq!(json["events"][0]["passed"], false);
+
+    let resp = reqwest::blocking::get(format!("{}missing.txt?verify-chain", server.api_url()))?;
+    assert_eq!(resp.status(), 404);
+    Ok(())
+}
+
+#[rstest]
+fn verify_rejects_large_body(server: TestServer) -> Result<(), Error> {
+    let url = format!("{}index.html?verify", server.api_url());
+    let resp = fetch!(b"POST", &url).body(vec![b' '; 256 * 1024]).send()?;
+    assert_eq!(resp.status(), 413);
+    Ok(())
+}
+
+#[rstest]
+fn download_flags_modified_file(server: TestServer) -> Result<(), Error> {
+    let url = format!("{}edited.txt", server.api_url());
+    let resp = fetch!(b"PUT", &url).body(b"abc".to_vec()).send()?;
+    assert_eq!(resp.status(), 201);
+    std::fs::write(server.path().join("edited.txt"), "changed behind our back")?;
+
+    let resp = reqwest::blocking::get(format!("{}edited.txt", server.url()))?;
+    assert_eq!(resp.status(), 200);
+    let mut manifest = serde_json::Value::Null;
+    for _ in 0..50 {
+        let resp = reqwest::blocking::get(format!("{url}?manifest=json"))?;
+        manifest = serde_json::from_str(&resp.text()?)?;
+        if manifest["modified_since_mint"] == true {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    assert_eq!(manifest["modified_since_mint"], true);
+    Ok(())
+}
+
+#[rstest]
+fn audit_records_verifications(server: TestServer) -> Result<(), Error> {
+    let url = format!("{}audited.txt", server.api_url());
+    let resp = fetch!(b"PUT", &url).body(b"abc".to_vec()).send()?;
+    assert_eq!(resp.status(), 201);
+
+    let resp = reqwest::blocking::get(format!("{url}?manifest=json"))?;
+    assert_eq!(resp.status(), 200);
+    reqwest::blocking::get(format!("{url}?ots"))?;
+    let resp = fetch!(b"HEAD", format!("{url}?manifest=json")).send()?;
+    assert_eq!(resp.status(), 200);
+
+    let resp = reqwest::blocking::get(format!("{url}?audit"))?;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    let entries = json["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["kind"], "manifest");
+    assert_eq!(entries[0]["requester_ip"], "127.0.0.1");
+    assert_eq!(entries[0]["success"], true);
+    assert_eq!(entries[1]["kind"], "ots");
+    Ok(())
+}
+
+#[rstest]
+fn audit_requires_write(
+    #[with(&["--auth", "user:pass@/:rw", "--auth", "@/"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}audited.txt", server.api_url());
+    let resp = send_with_digest_auth(fetch!(b"PUT", &url).body(b"abc".to_vec()), "user", "pass")?;
+    assert_eq!(resp.status(), 201);
+    let resp = reqwest::blocking::get(format!("{url}?manifest=json"))?;
+    assert_eq!(resp.status(), 200);
+
+    let resp = reqwest::blocking::get(format!("{url}?audit"))?;
+    assert_eq!(resp.status(), 401);
+    let resp = send_with_digest_auth(fetch!(b"GET", format!("{url}?audit")), "user", "pass")?;
+    let json: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["entries"][0]["kind"], "manifest");
+    assert_eq!(
+        json["entries"][0]["requester_user"],
+        serde_json::Value::Null
+    );
+    Ok(())
+}
+
+#[rstest]
+fn mint_stamps_in_background(#[with(&["--allow-upload"])] server: TestServer) -> Result<(), Error> {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    let resp = fetch!(b"PUT", format!("{}async.txt", server.api_url()))
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let mint: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(mint["ots_base64"], STANDARD.encode("PLACEHOLDER_OTS_PROOF"));
+    assert_eq!(mint["stamp_status"]["success"], false);
+
+    let resp = fetch!(b"PUT", format!("{}sync.txt?wait=stamp", server.api_url()))
+        .body(b"def".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let mint: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(mint["sha256"].as_str().unwrap().len(), 64);
+    Ok(())
+}
+
+#[rstest]
+fn listing_summarizes_stamps(
+    #[with(&["--allow-upload", "--ots-offline"])] server: TestServer,
+) -> Result<(), Error> {
+    for name in ["a.txt", "b.txt"] {
+        let resp = fetch!(b"PUT", format!("{}stamped/{name}", server.api_url()))
+            .body(b"abc".to_vec())
+            .send()?;
+        assert_eq!(resp.status(), 201);
+    }
+    std::fs::write(server.path().join("stamped/c.txt"), "not minted")?;
+
+    let resp = reqwest::blocking::get(format!("{}stamped/?limit=1", server.api_url()))?;
+    let json: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["stamps"]["n_confirmed"], 0);
+    assert_eq!(json["stamps"]["n_pending"], 2);
+    assert!(json["paths"][0].get("stamp_status").is_none());
+
+    let resp = reqwest::blocking::get(format!("{}stamped/a.txt?stamp-status", server.api_url()))?;
+    assert_eq!(resp.status(), 200);
+    let status: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(status["success"], false);
+    assert_eq!(status["sha256_hex"].as_str().unwrap().len(), 64);
+
+    let resp = reqwest::blocking::get(format!("{}stamped/c.txt?stamp-status", server.api_url()))?;
+    assert_eq!(resp.status(), 404);
+    Ok(())
+}
+
+#[rstest]
+fn ots_offline_queues_digests(
+    #[with(&["--allow-upload", "--ots-offline"])] server: TestServer,
+) -> Result<(), Error> {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    let url = format!("{}offline.txt", server.api_url());
+    let resp = fetch!(b"PUT", &url).body(b"abc".to_vec()).send()?;
+    assert_eq!(resp.status(), 201);
+    let mint: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(mint["ots_base64"], STANDARD.encode("PLACEHOLDER_OTS_PROOF"));
+    assert_eq!(mint["stamp_status"]["success"], false);
+    assert!(mint["stamp_status"]["error"].is_null());
+    Ok(())
+}
+
+#[rstest]
+fn copy_clones_lineage(
+    #[with(&["--allow-upload", "--allow-delete"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"PUT", format!("{}orig.txt", server.api_url()))
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+
+    let resp = fetch!(
+        b"POST",
+        format!("{}orig.txt?copy=/copies/dup.txt", server.url())
+    )
+    .send()?;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["path"], "/copies/dup.txt");
+    let event_hash = json["files"][0]["event_hash"].as_str().unwrap().to_string();
+
+    let manifest_url = format!("{}copies/dup.txt?manifest=json", server.api_url());
+    let manifest: serde_json::Value =
+        serde_json::from_str(&reqwest::blocking::get(&manifest_url)?.text()?)?;
+    let events = manifest["events"].as_array().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["action"], "mint");
+    assert_eq!(events[1]["action"], "copy");
+    assert_eq!(
+        events[1]["prev_event_hash_hex"],
+        events[0]["event_hash_hex"]
+    );
+    assert_eq!(events[1]["event_hash_hex"], event_hash);
+    let resp = reqwest::blocking::get(format!("{}copies/dup.txt", server.url()))?;
+    assert_eq!(resp.text()?, "abc");
+
+    // The source keeps its own chain
+    let manifest_url = format!("{}orig.txt?manifest=json", server.api_url());
+    let manifest: serde_json::Value =
+        serde_json::from_str(&reqwest::blocking::get(&manifest_url)?.text()?)?;
+    assert_eq!(manifest["events"].as_array().unwrap().len(), 1);
+
+    let url = format!("{}orig.txt?copy=/copies/dup.txt", server.url());
+    assert_eq!(fetch!(b"POST", &url).send()?.status(), 409);
+    let resp = fetch!(b"POST", format!("{url}&overwrite")).send()?;
+    assert_eq!(resp.status(), 201);
+
+    // Folders are copied with their files
+    let resp = fetch!(b"POST", format!("{}dir1?copy=/dir1-copy", server.url())).send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = reqwest::blocking::get(format!("{}dir1-copy/index.html", server.url()))?;
+    assert_eq!(resp.status(), 200);
+    Ok(())
+}
+
+#[rstest]
+fn copy_requires_write_on_destination(
+    #[with(&["--auth", "user:pass@/dir1:ro,/dir2:rw", "--allow-upload"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}dir1/test.html?copy=/dir3/test.html", server.url());
+    let resp = send_with_digest_auth(fetch!(b"POST", &url), "user", "pass")?;
+    assert_eq!(resp.status(), 403);
+
+    let url = format!("{}dir1/test.html?copy=/dir2/copied.html", server.url());
+    let resp = send_with_digest_auth(fetch!(b"POST", &url), "user", "pass")?;
+    assert_eq!(resp.status(), 201);
+    Ok(())
+}
+
+#[rstest]
+fn copy_flag_only_authorizes_copy(
+    #[with(&["--auth", "user:pass@/dir1:ro,/dir2:rw,nodelete", "--allow-upload"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    // Read access to a path must not reach its other POST actions
+    let url = format!("{}dir1/?acl&copy=/dir2/x", server.api_url());
+    let req = fetch!(b"POST", &url).body(r#"{"user":"user","perm":"rw"}"#);
+    let resp = send_with_digest_auth(req, "user", "pass")?;
+    assert_eq!(resp.status(), 403);
+    let url = format!("{}dir1/test.html?transfer&copy=/dir2/x", server.api_url());
+    let req = fetch!(b"POST", &url).body(r#"{"to":"user"}"#);
+    let resp = send_with_digest_auth(req, "user", "pass")?;
+    assert_eq!(resp.status(), 403);
+    let url = format!("{}dir1/test.html", server.api_url());
+    let req = fetch!(b"PUT", &url).body(b"mine".to_vec());
+    assert_eq!(send_with_digest_auth(req, "user", "pass")?.status(), 403);
+
+    // Replacing the destination deletes it, which `nodelete` takes away
+    let url = format!("{}dir1/test.html?copy=/dir2/copied.html", server.url());
+    let resp = send_with_digest_auth(fetch!(b"POST", &url), "user", "pass")?;
+    assert_eq!(resp.status(), 201);
+    let url = format!("{url}&overwrite");
+    let resp = send_with_digest_auth(fetch!(b"POST", &url), "user", "pass")?;
+    assert_eq!(resp.status(), 403);
+    Ok(())
+}
+
+#[rstest]
+fn provenance_bundle_import() -> Result<(), Error> {
+    let db_dir = assert_fs::TempDir::new()?;
+    let db_path = |name: &str| db_dir.path().join(name).to_str().unwrap().to_string();
+    let origin = server(["--provenance-db", &db_path("origin.db")]);
+    let replica = server(["--provenance-db", &db_path("replica.db")]);
+
+    let url = format!("{}dir1/report.txt", origin.api_url());
+    let resp = fetch!(b"PUT", &url).body(b"quarterly".to_vec()).send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = reqwest::blocking::get(format!("{url}?manifest=json"))?;
+    let manifest: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+
+    let resp = reqwest::blocking::get(format!("{url}?provenance-bundle"))?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/zip"
+    );
+    let bundle = resp.bytes()?.to_vec();
+
+    let import_url = format!("{}imported/report.txt", replica.api_url());
+    let resp = fetch!(b"POST", format!("{import_url}?provenance-import"))
+        .body(b"not a zip".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 400);
+
+    let resp = fetch!(b"POST", format!("{import_url}?provenance-import"))
+        .body(bundle.clone())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let imported: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(imported["artifact"], manifest["artifact"]);
+    assert_eq!(imported["events"], manifest["events"]);
+    assert_eq!(reqwest::blocking::get(&import_url)?.text()?, "quarterly");
+
+    // The file exists now, and the events can't be recorded a second time
+    let resp = fetch!(b"POST", format!("{import_url}?provenance-import"))
+        .body(bundle.clone())
+        .send()?;
+    assert_eq!(resp.status(), 409);
+    let other_url = format!("{}imported/copy.txt", replica.api_url());
+    let resp = fetch!(b"POST", format!("{other_url}?provenance-import"))
+        .body(bundle)
+        .send()?;
+    assert_eq!(resp.status(), 409);
+    assert_eq!(reqwest::blocking::get(&other_url)?.status(), 404);
+    Ok(())
+}
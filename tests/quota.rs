@@ -0,0 +1,92 @@
+mod digest_auth_util;
+mod fixtures;
+mod utils;
+
+use digest_auth_util::send_with_digest_auth;
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+
+#[rstest]
+fn path_quota(#[with(&["--quota", "/dir1:1K"])] server: TestServer) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(format!("{}dir1/", server.api_url()))?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["quotas"][0]["scope"], "/dir1");
+    assert_eq!(json["quotas"][0]["limit"], 1024);
+    let used = json["quotas"][0]["used"].as_u64().unwrap();
+    assert!(used > 0 && used < 1024);
+
+    let url = format!("{}dir1/big", server.api_url());
+    let resp = fetch!(b"PUT", &url).body(vec![b'a'; 1024]).send()?;
+    assert_eq!(resp.status(), 507);
+    let json: Value = resp.json()?;
+    assert_eq!(json["code"], "insufficient_storage");
+    assert_eq!(json["message"], "Quota exceeded for /dir1");
+    let resp = fetch!(b"HEAD", &url).send()?;
+    assert_eq!(resp.status(), 404);
+
+    let resp = fetch!(b"PUT", format!("{}dir1/small", server.api_url()))
+        .body(vec![b'a'; 1024 - used as usize])
+        .send()?;
+    assert_eq!(resp.status(), 201);
+
+    let resp = fetch!(b"MKCOL", format!("{}dir1/sub", server.url())).send()?;
+    assert_eq!(resp.status(), 507);
+    let resp = fetch!(b"COPY", format!("{}test.txt", server.url()))
+        .header("Destination", format!("{}dir1/copy.txt", server.url()))
+        .send()?;
+    assert_eq!(resp.status(), 507);
+    let resp = fetch!(b"MOVE", format!("{}dir1/small", server.url()))
+        .header("Destination", format!("{}dir1/renamed", server.url()))
+        .send()?;
+    assert_eq!(resp.status(), 204);
+
+    // Other directories are not limited
+    let resp = fetch!(b"PUT", format!("{}dir2/big", server.api_url()))
+        .body(vec![b'a'; 1024])
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = reqwest::blocking::get(format!("{}dir2/", server.api_url()))?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    assert!(json.get("quotas").is_none());
+    Ok(())
+}
+
+#[test]
+fn user_quota() -> Result<(), Error> {
+    // Owners from other tests' uploads must not count, so use a database of its own
+    let db_dir = assert_fs::TempDir::new()?;
+    let db = db_dir.path().join("provenance.db");
+    let server = server([
+        "-a",
+        "user:pass@/:rw",
+        "--quota",
+        "@user:10",
+        "--provenance-db",
+        db.to_str().unwrap(),
+    ]);
+    let url = format!("{}file1", server.api_url());
+    let resp = send_with_digest_auth(fetch!(b"PUT", &url).body(b"12345".to_vec()), "user", "pass")?;
+    assert_eq!(resp.status(), 201);
+    // Replacing their own file only counts the difference
+    let resp = send_with_digest_auth(
+        fetch!(b"PUT", &url).body(b"1234567".to_vec()),
+        "user",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 201);
+    let url2 = format!("{}file2", server.api_url());
+    let resp = send_with_digest_auth(fetch!(b"PUT", &url2).body(b"1234".to_vec()), "user", "pass")?;
+    assert_eq!(resp.status(), 507);
+
+    let resp = send_with_digest_auth(fetch!(b"GET", server.api_url()), "user", "pass")?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["quotas"][0]["scope"], "@user");
+    assert_eq!(json["quotas"][0]["used"], 7);
+
+    let resp = send_with_digest_auth(fetch!(b"DELETE", &url), "user", "pass")?;
+    assert_eq!(resp.status(), 204);
+    let resp = send_with_digest_auth(fetch!(b"PUT", &url2).body(b"1234".to_vec()), "user", "pass")?;
+    assert_eq!(resp.status(), 201);
+    Ok(())
+}
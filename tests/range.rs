@@ -104,3 +104,42 @@ fn get_file_multipart_range_invalid(server: TestServer) -> Result<(), Error> {
     assert_eq!(resp.headers().get("content-length").unwrap(), "0");
     Ok(())
 }
+
+#[rstest]
+fn get_file_multipart_range_too_many(server: TestServer) -> Result<(), Error> {
+    let ranges: Vec<String> = (0..17).map(|i| format!("{i}-{i}")).collect();
+    let resp = fetch!(b"GET", format!("{}api/index.html", server.url()))
+        .header("range", format!("bytes={}", ranges.join(",")))
+        .send()?;
+    assert_eq!(resp.status(), 416);
+
+    let resp = fetch!(b"GET", format!("{}api/index.html", server.url()))
+        .header("range", format!("bytes={}", ranges[..16].join(",")))
+        .send()?;
+    assert_eq!(resp.status(), 206);
+    let content_length: usize = resp.headers()["content-length"].to_str()?.parse()?;
+    assert_eq!(resp.bytes()?.len(), content_length);
+    Ok(())
+}
+
+#[rstest]
+fn get_file_range_throttled(
+    #[with(&["--max-bandwidth", "64K", "--max-range-streams", "1"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}api/large.bin", server.url());
+    let data = vec![7u8; 128 * 1024];
+    let resp = fetch!(b"PUT", &url).body(data.clone()).send()?;
+    assert_eq!(resp.status(), 201);
+
+    let start = std::time::Instant::now();
+    let resp = fetch!(b"GET", &url).send()?;
+    assert_eq!(resp.bytes()?.len(), data.len());
+    assert!(start.elapsed() >= std::time::Duration::from_secs(1));
+
+    let resp = fetch!(b"GET", &url)
+        .header("range", HeaderValue::from_static("bytes=100-199"))
+        .send()?;
+    assert_eq!(resp.status(), 206);
+    assert_eq!(resp.bytes()?.len(), 100);
+    Ok(())
+}
@@ -0,0 +1,40 @@
+mod digest_auth_util;
+mod fixtures;
+mod utils;
+
+use digest_auth_util::send_with_digest_auth;
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+
+#[rstest]
+fn ip_rate_limit(#[with(&["--rate-limit", "2"])] server: TestServer) -> Result<(), Error> {
+    for _ in 0..2 {
+        let resp = reqwest::blocking::get(server.api_url())?;
+        assert_eq!(resp.status(), 200);
+    }
+    let resp = reqwest::blocking::get(server.api_url())?;
+    assert_eq!(resp.status(), 429);
+    assert_eq!(resp.headers().get("retry-after").unwrap(), "1");
+
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    let resp = reqwest::blocking::get(server.api_url())?;
+    assert_eq!(resp.status(), 200);
+    Ok(())
+}
+
+#[rstest]
+fn user_rate_limit(
+    #[with(&["-a", "user:pass@/:rw", "--user-rate-limit", "1"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}index.html", server.url());
+    let resp = send_with_digest_auth(fetch!(b"GET", &url), "user", "pass")?;
+    assert_eq!(resp.status(), 200);
+    let resp = send_with_digest_auth(fetch!(b"GET", &url), "user", "pass")?;
+    assert_eq!(resp.status(), 429);
+    // Requests before authentication are only limited per IP
+    for _ in 0..3 {
+        let resp = fetch!(b"GET", &url).send()?;
+        assert_eq!(resp.status(), 401);
+    }
+    Ok(())
+}
@@ -0,0 +1,53 @@
+mod fixtures;
+mod utils;
+
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+
+fn put_readme(server: &TestServer, content: &str) -> Result<(), Error> {
+    let resp = fetch!(b"PUT", format!("{}dir1/README.md", server.url()))
+        .body(content.to_string())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    Ok(())
+}
+
+#[rstest]
+fn render_readme(#[with(&["--render-readme"])] server: TestServer) -> Result<(), Error> {
+    put_readme(
+        &server,
+        "# Reports\n\nQuarterly *numbers*.\n\n<script>alert(1)</script>",
+    )?;
+
+    let resp = reqwest::blocking::get(format!("{}dir1/?limit=1", server.api_url()))?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = resp.json()?;
+    assert_eq!(json["readme"]["name"], "README.md");
+    assert_eq!(json["readme"]["truncated"], false);
+    let html = json["readme"]["html"].as_str().unwrap();
+    assert!(html.starts_with("<h1>Reports</h1>\n<p>Quarterly <em>numbers</em>.</p>"));
+    assert!(!html.contains("script"));
+
+    let resp = reqwest::blocking::get(format!("{}dir1/?q=test", server.api_url()))?;
+    let json: Value = resp.json()?;
+    assert!(json.get("readme").is_none());
+    let resp = reqwest::blocking::get(format!("{}dir2/", server.api_url()))?;
+    let json: Value = resp.json()?;
+    assert!(json.get("readme").is_none());
+
+    put_readme(&server, &"a".repeat(100 * 1024))?;
+    let resp = reqwest::blocking::get(format!("{}dir1/", server.api_url()))?;
+    let json: Value = resp.json()?;
+    assert_eq!(json["readme"]["truncated"], true);
+    Ok(())
+}
+
+#[rstest]
+fn readme_not_rendered_by_default(server: TestServer) -> Result<(), Error> {
+    put_readme(&server, "# Reports")?;
+    let resp = reqwest::blocking::get(format!("{}dir1/", server.api_url()))?;
+    let json: Value = resp.json()?;
+    assert!(json.get("readme").is_none());
+    Ok(())
+}
@@ -0,0 +1,68 @@
+mod fixtures;
+mod utils;
+
+use fixtures::{server, Error, TestServer, BIN_FILE};
+use rstest::rstest;
+
+fn put_file(server: &TestServer, name: &str, content: &str) -> Result<(), Error> {
+    let resp = fetch!(b"PUT", format!("{}{name}", server.url()))
+        .body(content.to_string())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    Ok(())
+}
+
+#[rstest]
+fn render_markdown(server: TestServer) -> Result<(), Error> {
+    put_file(
+        &server,
+        "notes.md",
+        "# Notes\n\n- [x] done\n\n<img src=x onerror=alert(1)>",
+    )?;
+    let resp = reqwest::blocking::get(format!("{}notes.md?render", server.url()))?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "text/html; charset=utf-8");
+    assert!(resp.headers().get("x-preview-truncated").is_none());
+    let html = resp.text()?;
+    assert!(html.contains("<title>notes.md</title>"));
+    assert!(html.contains("<h1>Notes</h1>"));
+    assert!(!html.contains("onerror"));
+
+    let resp = reqwest::blocking::get(format!("{}notes.md?render", server.api_url()))?;
+    assert_eq!(resp.status(), 200);
+    let resp = fetch!(b"HEAD", format!("{}notes.md?render", server.url())).send()?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.text()?, "");
+    Ok(())
+}
+
+#[rstest]
+fn render_source(server: TestServer) -> Result<(), Error> {
+    put_file(&server, "main.rs", "fn main() { println!(\"<b>\"); }\n")?;
+    let resp = reqwest::blocking::get(format!("{}main.rs?render", server.url()))?;
+    assert_eq!(resp.status(), 200);
+    let html = resp.text()?;
+    assert!(html.contains("<pre style="));
+    assert!(html.contains("<span style="));
+    assert!(html.contains("&lt;b&gt;"));
+    assert!(!html.contains("<b>"));
+
+    // Unknown languages are shown as plain text
+    put_file(&server, "notes.unknown", "a < b\n")?;
+    let resp = reqwest::blocking::get(format!("{}notes.unknown?render", server.url()))?;
+    assert_eq!(resp.status(), 200);
+    assert!(resp.text()?.contains("<pre>a &lt; b\n</pre>"));
+
+    put_file(&server, "big.txt", &"a".repeat(600 * 1024))?;
+    let resp = reqwest::blocking::get(format!("{}big.txt?render", server.url()))?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["x-preview-truncated"], "true");
+    Ok(())
+}
+
+#[rstest]
+fn render_binary_file(server: TestServer) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(format!("{}{BIN_FILE}?render", server.url()))?;
+    assert_eq!(resp.status(), 415);
+    Ok(())
+}
@@ -0,0 +1,72 @@
+mod fixtures;
+mod utils;
+
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+
+#[rstest]
+fn listing_shows_retention(
+    #[with(&["--retention", "/dir1:30d"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(format!("{}dir1/", server.api_url()))?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    let item = json["paths"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|v| v["name"] == "dir1/test.txt")
+        .unwrap();
+    assert_eq!(item["retention"]["rule"], "/dir1");
+    let mtime = item["mtime"].as_u64().unwrap();
+    let expires_at = item["retention"]["expires_at"].as_u64().unwrap();
+    assert_eq!(expires_at - mtime, 30 * 24 * 60 * 60 * 1000);
+
+    let resp = reqwest::blocking::get(format!("{}dir2/", server.api_url()))?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    assert!(json["paths"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|v| v.get("retention").is_none()));
+    Ok(())
+}
+
+#[rstest]
+fn retention_moves_to_trash() -> Result<(), Error> {
+    let trash_dir = assert_fs::TempDir::new()?;
+    let server = server([
+        "--retention",
+        "/dir1:0s",
+        "--trash-dir",
+        trash_dir.path().to_str().unwrap(),
+    ]);
+    let trash_url = format!("{}__dufs__/trash", server.url());
+    let url = format!("{}dir1/test.txt", server.api_url());
+
+    // The sweeper runs once right after startup
+    let mut items = vec![];
+    for _ in 0..50 {
+        let json: Value = serde_json::from_str(&reqwest::blocking::get(&trash_url)?.text()?)?;
+        items = json["items"].as_array().unwrap().clone();
+        if !items.is_empty() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    let item = items
+        .iter()
+        .find(|v| v["path"] == "/dir1/test.txt")
+        .unwrap();
+    assert!(items
+        .iter()
+        .all(|v| v["path"].as_str().unwrap().starts_with("/dir1/")));
+    assert_eq!(reqwest::blocking::get(&url)?.status(), 404);
+
+    let id = item["id"].as_str().unwrap();
+    let resp = fetch!(b"POST", format!("{trash_url}?restore={id}")).send()?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(reqwest::blocking::get(&url)?.status(), 200);
+    Ok(())
+}
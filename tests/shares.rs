@@ -0,0 +1,246 @@
+mod fixtures;
+mod utils;
+
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+
+const SHARES_PATH: &str = "__dufs__/shares";
+
+fn create_share(server: &TestServer, file: &str) -> Result<String, Error> {
+    let resp = fetch!(b"POST", format!("{}{file}?share", server.api_url())).send()?;
+    let json: Value = resp.json()?;
+    Ok(json["share_id"].as_str().unwrap().to_string())
+}
+
+#[rstest]
+fn admin_manages_shares(server: TestServer) -> Result<(), Error> {
+    let share_id = create_share(&server, "dir1/test.txt")?;
+    for _ in 0..2 {
+        let resp = reqwest::blocking::get(format!("{}share/{share_id}/download", server.url()))?;
+        assert_eq!(resp.status(), 200);
+    }
+
+    let resp = reqwest::blocking::get(format!("{}{SHARES_PATH}", server.url()))?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = resp.json()?;
+    let share = json["shares"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|v| v["share_id"] == share_id.as_str())
+        .unwrap();
+    assert_eq!(share["path"], "/dir1/test.txt");
+    assert_eq!(share["downloads"], 2);
+
+    let resp = reqwest::blocking::get(format!(
+        "{}{SHARES_PATH}/{share_id}/downloads",
+        server.url()
+    ))?;
+    let json: Value = resp.json()?;
+    assert_eq!(json["downloads"].as_array().unwrap().len(), 2);
+
+    let url = format!("{}{SHARES_PATH}/{share_id}", server.url());
+    assert_eq!(fetch!(b"DELETE", &url).send()?.status(), 204);
+    assert_eq!(fetch!(b"DELETE", &url).send()?.status(), 404);
+    let resp = reqwest::blocking::get(format!("{}share/{share_id}/download", server.url()))?;
+    assert_eq!(resp.status(), 404);
+    let resp = reqwest::blocking::get(format!("{}{SHARES_PATH}", server.url()))?;
+    let json: Value = resp.json()?;
+    assert!(json["shares"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|v| v["share_id"] != share_id.as_str()));
+    Ok(())
+}
+
+#[rstest]
+fn share_limits(server: TestServer) -> Result<(), Error> {
+    let resp = fetch!(b"POST", format!("{}dir1/test.txt?share", server.api_url()))
+        .body(r#"{"max_downloads":1}"#)
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = resp.json()?;
+    assert_eq!(json["max_downloads"], 1);
+    let share_id = json["share_id"].as_str().unwrap();
+
+    let url = format!("{}share/{share_id}/download", server.url());
+    let resp = fetch!(b"HEAD", &url).send()?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(reqwest::blocking::get(&url)?.status(), 200);
+    assert_eq!(reqwest::blocking::get(&url)?.status(), 410);
+    let resp = reqwest::blocking::get(format!("{}share/{share_id}/info", server.url()))?;
+    assert_eq!(resp.status(), 410);
+
+    let resp = fetch!(b"POST", format!("{}dir1/test.txt?share", server.api_url()))
+        .body(r#"{"expires_in":"1s"}"#)
+        .send()?;
+    let json: Value = resp.json()?;
+    assert!(json["expires_at"].is_u64());
+    let share_id = json["share_id"].as_str().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(2100));
+    let resp = reqwest::blocking::get(format!("{}share/{share_id}/download", server.url()))?;
+    assert_eq!(resp.status(), 410);
+
+    for body in [
+        r#"{"expires_in":"soon"}"#,
+        r#"{"expires_at":1}"#,
+        r#"{"max_downloads":0}"#,
+        r#"{"expires_at":99999999999,"expires_in":"1d"}"#,
+    ] {
+        let resp = fetch!(b"POST", format!("{}dir1/test.txt?share", server.api_url()))
+            .body(body)
+            .send()?;
+        assert_eq!(resp.status(), 400, "{body}");
+    }
+    Ok(())
+}
+
+#[rstest]
+fn share_download_ranges(server: TestServer) -> Result<(), Error> {
+    let share_id = create_share(&server, "dir1/test.txt")?;
+    let url = format!("{}share/{share_id}/download", server.url());
+
+    let resp = fetch!(b"GET", &url).header("range", "bytes=5-6").send()?;
+    assert_eq!(resp.status(), 206);
+    assert_eq!(resp.headers()["content-range"], "bytes 5-6/21");
+    assert_eq!(resp.headers()["x-share-id"], share_id.as_str());
+    let etag = resp.headers()["etag"].clone();
+    assert_eq!(resp.text()?, "is");
+    let resp = fetch!(b"GET", &url).header("if-none-match", etag).send()?;
+    assert_eq!(resp.status(), 304);
+
+    let resp = fetch!(b"GET", &url).send()?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-length"], "21");
+    assert_eq!(resp.text()?, "This is dir1/test.txt");
+
+    // Only the response carrying the start of the file counts as a download
+    let resp = reqwest::blocking::get(format!(
+        "{}{SHARES_PATH}/{share_id}/downloads",
+        server.url()
+    ))?;
+    let json: Value = resp.json()?;
+    assert_eq!(json["downloads"].as_array().unwrap().len(), 1);
+
+    let url = format!("{}{SHARES_PATH}/{share_id}", server.url());
+    assert_eq!(fetch!(b"DELETE", &url).send()?.status(), 204);
+    Ok(())
+}
+
+#[rstest]
+fn share_landing_page(server: TestServer) -> Result<(), Error> {
+    let resp = fetch!(b"PUT", format!("{}dir1/a%3Cb%3E.txt", server.url()))
+        .body(b"markup".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let share_id = create_share(&server, "dir1/a%3Cb%3E.txt")?;
+    let url = format!("{}share/{share_id}", server.url());
+
+    let resp = reqwest::blocking::get(format!("{url}?info"))?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "text/html; charset=utf-8");
+    let html = resp.text()?;
+    assert!(html.contains("<h1>a&lt;b&gt;.txt</h1>"));
+    assert!(!html.contains("<b>"));
+    assert!(html.contains(&format!(r#"href="{share_id}/download""#)));
+
+    let resp = reqwest::blocking::get(format!("{url}?info=json"))?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = resp.json()?;
+    assert_eq!(json["name"], "a<b>.txt");
+    assert_eq!(json["size"], 6);
+    assert_eq!(json["signature_valid"], true);
+    assert_eq!(json["download_url"], format!("/share/{share_id}/download"));
+
+    // Looking at the share isn't downloading it
+    let resp = reqwest::blocking::get(format!(
+        "{}{SHARES_PATH}/{share_id}/downloads",
+        server.url()
+    ))?;
+    let json: Value = resp.json()?;
+    assert_eq!(json["downloads"].as_array().unwrap().len(), 0);
+
+    let url = format!("{}{SHARES_PATH}/{share_id}", server.url());
+    assert_eq!(fetch!(b"DELETE", &url).send()?.status(), 204);
+    let resp = reqwest::blocking::get(format!("{}share/{share_id}?info", server.url()))?;
+    assert_eq!(resp.status(), 404);
+    Ok(())
+}
+
+#[rstest]
+fn password_protected_share(
+    #[with(&["--auth", "admin:pass@/:rw"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"POST", format!("{}dir1/test.txt?share", server.api_url()))
+        .basic_auth("admin", Some("pass"))
+        .body(r#"{"password":"s3cret"}"#)
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = resp.json()?;
+    assert_eq!(json["password_protected"], true);
+    let share_id = json["share_id"].as_str().unwrap();
+
+    let url = format!("{}share/{share_id}/download", server.url());
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.status(), 401);
+    assert!(resp.headers().contains_key("www-authenticate"));
+    let resp = reqwest::blocking::get(format!("{url}?password=wrong"))?;
+    assert_eq!(resp.status(), 401);
+    let resp = reqwest::blocking::get(format!("{url}?password=s3cret"))?;
+    assert_eq!(resp.status(), 200);
+    // The user name doesn't matter, the recipient needs no account
+    let resp = fetch!(b"GET", &url)
+        .basic_auth("guest", Some("s3cret"))
+        .send()?;
+    assert_eq!(resp.status(), 200);
+
+    let info_url = format!("{}share/{share_id}/info", server.url());
+    assert_eq!(reqwest::blocking::get(&info_url)?.status(), 401);
+    let resp = reqwest::blocking::get(format!("{}share/{share_id}?info", server.url()))?;
+    assert_eq!(resp.status(), 401);
+    let resp = reqwest::blocking::get(format!(
+        "{}share/{share_id}?info&password=s3cret",
+        server.url()
+    ))?;
+    assert!(resp.text()?.contains("download?password=s3cret"));
+    let resp = fetch!(b"GET", &info_url).bearer_auth("s3cret").send()?;
+    assert_eq!(resp.status(), 200);
+    let text = resp.text()?;
+    assert!(!text.contains("argon2"));
+    let json: Value = serde_json::from_str(&text)?;
+    assert_eq!(json["password_protected"], true);
+
+    let resp = fetch!(b"POST", format!("{}dir1/test.txt?share", server.api_url()))
+        .basic_auth("admin", Some("pass"))
+        .body(r#"{"password":""}"#)
+        .send()?;
+    assert_eq!(resp.status(), 400);
+
+    let resp = fetch!(
+        b"DELETE",
+        format!("{}{SHARES_PATH}/{share_id}", server.url())
+    )
+    .basic_auth("admin", Some("pass"))
+    .send()?;
+    assert_eq!(resp.status(), 204);
+    Ok(())
+}
+
+#[rstest]
+fn shares_require_admin(
+    #[with(&["--auth", "admin:pass@/:rw", "--auth", "user:pass@/dir1:rw"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(format!("{}{SHARES_PATH}", server.url()))?;
+    assert_eq!(resp.status(), 401);
+    let resp = fetch!(b"GET", format!("{}{SHARES_PATH}", server.url()))
+        .basic_auth("user", Some("pass"))
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    let resp = fetch!(b"GET", format!("{}{SHARES_PATH}", server.url()))
+        .basic_auth("admin", Some("pass"))
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    Ok(())
+}
@@ -0,0 +1,87 @@
+#![cfg(unix)]
+
+mod fixtures;
+mod utils;
+
+use assert_cmd::prelude::*;
+use assert_fs::TempDir;
+use fixtures::{port, tmpdir, wait_for_port, Error};
+use rstest::rstest;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+#[rstest]
+fn shutdown_drains_uploads(tmpdir: TempDir, port: u16) -> Result<(), Error> {
+    let mut child = spawn_server(&tmpdir, port, &["--allow-upload"])?;
+
+    // Start an upload, then ask the server to stop halfway through it
+    let mut stream = TcpStream::connect(format!("localhost:{port}"))?;
+    stream.write_all(
+        format!("PUT /drained.txt HTTP/1.1\r\nHost: localhost:{port}\r\nContent-Length: 10\r\n\r\nHello")
+            .as_bytes(),
+    )?;
+    terminate(&child)?;
+    sleep(Duration::from_millis(500));
+
+    // No new connections, but the upload in flight completes
+    assert!(TcpStream::connect(format!("localhost:{port}")).is_err());
+    stream.write_all(b"World")?;
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp)?;
+    assert!(resp.starts_with("HTTP/1.1 201"), "{resp}");
+    assert_eq!(
+        std::fs::read_to_string(tmpdir.path().join("drained.txt"))?,
+        "HelloWorld"
+    );
+    assert!(child.wait()?.success());
+    Ok(())
+}
+
+#[rstest]
+fn shutdown_timeout(tmpdir: TempDir, port: u16) -> Result<(), Error> {
+    let mut child = spawn_server(
+        &tmpdir,
+        port,
+        &["--allow-upload", "--shutdown-timeout", "1"],
+    )?;
+
+    // An upload that never completes and an event stream that never ends
+    let mut stalled = TcpStream::connect(format!("localhost:{port}"))?;
+    stalled.write_all(
+        format!("PUT /stalled.txt HTTP/1.1\r\nHost: localhost:{port}\r\nContent-Length: 10\r\n\r\nHello")
+            .as_bytes(),
+    )?;
+    let mut events = TcpStream::connect(format!("localhost:{port}"))?;
+    events.write_all(
+        format!("GET /__dufs__/events HTTP/1.1\r\nHost: localhost:{port}\r\n\r\n").as_bytes(),
+    )?;
+    sleep(Duration::from_millis(200));
+
+    let start = Instant::now();
+    terminate(&child)?;
+    assert!(child.wait()?.success());
+    assert!(start.elapsed() < Duration::from_secs(5));
+    Ok(())
+}
+
+fn spawn_server(tmpdir: &TempDir, port: u16, args: &[&str]) -> Result<Child, Error> {
+    let child = Command::cargo_bin("node-drive")?
+        .arg(tmpdir.path())
+        .arg("-p")
+        .arg(port.to_string())
+        .args(args)
+        .stdout(Stdio::null())
+        .spawn()?;
+    wait_for_port(port);
+    Ok(child)
+}
+
+fn terminate(child: &Child) -> Result<(), Error> {
+    Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+mod digest_auth_util;
+mod fixtures;
+mod utils;
+
+use digest_auth_util::send_with_digest_auth;
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+
+const STATS_PATH: &str = "__dufs__/stats";
+
+#[rstest]
+fn stats_aggregates_storage_and_traffic(server: TestServer) -> Result<(), Error> {
+    let resp = fetch!(b"PUT", format!("{}dir1/report.bin", server.url()))
+        .body(vec![0u8; 1000])
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    for _ in 0..2 {
+        let resp = reqwest::blocking::get(format!("{}dir1/report.bin", server.api_url()))?;
+        assert_eq!(resp.status(), 200);
+    }
+
+    let resp = reqwest::blocking::get(format!("{}{STATS_PATH}?bucket=hour", server.url()))?;
+    assert_eq!(resp.status(), 200);
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+
+    let dir1 = json["storage"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|v| v["path"] == "/dir1")
+        .unwrap();
+    assert!(dir1["bytes"].as_u64().unwrap() >= 1000);
+
+    let top = &json["top_files"][0];
+    assert_eq!(top["path"], "/dir1/report.bin");
+    assert_eq!(top["uploaded"], 1000);
+    assert_eq!(top["downloaded"], 2000);
+    assert_eq!(top["downloads"], 2);
+
+    assert_eq!(json["traffic"]["bucket"], "hour");
+    let buckets = json["traffic"]["buckets"].as_array().unwrap();
+    let uploaded: u64 = buckets
+        .iter()
+        .map(|v| v["uploaded"].as_u64().unwrap())
+        .sum();
+    assert!(uploaded >= 1000);
+    assert_eq!(json["active_shares"], 0);
+
+    let resp = reqwest::blocking::get(format!("{}{STATS_PATH}?bucket=year", server.url()))?;
+    assert_eq!(resp.status(), 400);
+    Ok(())
+}
+
+#[rstest]
+fn stats_requires_admin(
+    #[with(&["--auth", "user:pass@/:rw", "--auth", "guest:pass@/dir1:rw"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}{STATS_PATH}", server.url());
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.status(), 401);
+    let resp = send_with_digest_auth(fetch!(b"GET", &url), "guest", "pass")?;
+    assert_eq!(resp.status(), 403);
+    let resp = send_with_digest_auth(fetch!(b"GET", &url), "user", "pass")?;
+    assert_eq!(resp.status(), 200);
+    Ok(())
+}
+
+#[rstest]
+fn metrics_count_requests(
+    #[with(&["--auth", "user:pass@/:rw", "--auth", "guest:pass@/dir1:rw"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}__dufs__/metrics", server.url());
+    let resp = send_with_digest_auth(
+        fetch!(b"PUT", format!("{}dir1/report.bin", server.api_url())),
+        "guest",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 201);
+    let resp = reqwest::blocking::get(format!("{}dir1/missing.bin", server.api_url()))?;
+    assert_eq!(resp.status(), 401);
+
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.status(), 401);
+    let resp = send_with_digest_auth(fetch!(b"GET", &url), "guest", "pass")?;
+    assert_eq!(resp.status(), 403);
+    let resp = send_with_digest_auth(fetch!(b"GET", &url), "user", "pass")?;
+    assert_eq!(resp.status(), 200);
+    let text = resp.text()?;
+    assert!(text
+        .contains(r#"node_drive_requests_total{route="api",operation="upload",status="201"} 1"#));
+    assert!(
+        text.contains(r#"node_drive_requests_total{route="api",operation="read",status="401"} 1"#)
+    );
+    assert!(text.contains("# TYPE node_drive_request_duration_seconds_sum counter"));
+    Ok(())
+}
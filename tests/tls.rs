@@ -0,0 +1,29 @@
+mod fixtures;
+mod utils;
+
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+
+#[rstest]
+#[case(server(&["--tls-cert", "tests/data/cert.pem", "--tls-key", "tests/data/key_pkcs8.pem"]))]
+#[case(server(&["--tls-cert", "tests/data/cert.pem", "--tls-key", "tests/data/key_pkcs1.pem"]))]
+#[case(server(&["--tls-cert", "tests/data/cert_ecdsa.pem", "--tls-key", "tests/data/key_ecdsa.pem"]))]
+fn tls_works(#[case] server: TestServer) -> Result<(), Error> {
+    let client = reqwest::blocking::ClientBuilder::new()
+        .danger_accept_invalid_certs(true)
+        .build()?;
+    let resp = client.get(server.api_url()).send()?.error_for_status()?;
+    assert!(utils::retrieve_index_paths(&resp.text()?).contains("index.html"));
+    Ok(())
+}
+
+#[rstest]
+fn tls_mismatched_key() -> Result<(), Error> {
+    let output = assert_cmd::Command::cargo_bin("node-drive")?
+        .args(["--tls-cert", "tests/data/cert.pem"])
+        .args(["--tls-key", "tests/data/key_ecdsa.pem"])
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid TLS certificate or key"));
+    Ok(())
+}
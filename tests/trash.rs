@@ -0,0 +1,91 @@
+mod fixtures;
+mod utils;
+
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+
+#[rstest]
+fn delete_moves_to_trash() -> Result<(), Error> {
+    let trash_dir = assert_fs::TempDir::new()?;
+    let server = server(["--trash-dir", trash_dir.path().to_str().unwrap()]);
+    let trash_url = format!("{}__dufs__/trash", server.url());
+
+    let url = format!("{}dir1/test.txt", server.api_url());
+    let resp = fetch!(b"DELETE", &url).send()?;
+    assert_eq!(resp.status(), 204);
+    assert_eq!(reqwest::blocking::get(&url)?.status(), 404);
+    let resp = fetch!(b"DELETE", format!("{}dir2", server.api_url())).send()?;
+    assert_eq!(resp.status(), 204);
+
+    let json: Value = serde_json::from_str(&reqwest::blocking::get(&trash_url)?.text()?)?;
+    let items = json["items"].as_array().unwrap();
+    assert_eq!(items.len(), 2);
+    let item = items
+        .iter()
+        .find(|v| v["path"] == "/dir1/test.txt")
+        .unwrap();
+    assert_eq!(item["is_dir"], false);
+    let id = item["id"].as_str().unwrap();
+
+    let resp = fetch!(b"POST", format!("{trash_url}?restore={id}")).send()?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(reqwest::blocking::get(&url)?.status(), 200);
+    let resp = fetch!(b"POST", format!("{trash_url}?restore={id}")).send()?;
+    assert_eq!(resp.status(), 404);
+
+    let resp = fetch!(b"DELETE", format!("{trash_url}?older_than=1d")).send()?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["purged"], 0);
+    let resp = fetch!(b"DELETE", &trash_url).send()?;
+    let json: Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["purged"], 1);
+    assert_eq!(std::fs::read_dir(trash_dir.path())?.count(), 0);
+    Ok(())
+}
+
+#[rstest]
+fn restore_conflict() -> Result<(), Error> {
+    let trash_dir = assert_fs::TempDir::new()?;
+    let server = server(["--trash-dir", trash_dir.path().to_str().unwrap()]);
+    let trash_url = format!("{}__dufs__/trash", server.url());
+
+    let url = format!("{}test.txt", server.api_url());
+    fetch!(b"DELETE", &url).send()?;
+    let resp = fetch!(b"PUT", &url).body(b"new".to_vec()).send()?;
+    assert_eq!(resp.status(), 201);
+
+    let json: Value = serde_json::from_str(&reqwest::blocking::get(&trash_url)?.text()?)?;
+    let id = json["items"][0]["id"].as_str().unwrap();
+    let resp = fetch!(b"POST", format!("{trash_url}?restore={id}")).send()?;
+    assert_eq!(resp.status(), 409);
+    Ok(())
+}
+
+#[rstest]
+fn trash_requires_admin(
+    #[with(&["--auth", "user:pass@/:rw", "--auth", "guest:pass@/dir1:rw"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = reqwest::blocking::get(format!("{}__dufs__/trash", server.url()))?;
+    assert_eq!(resp.status(), 401);
+    Ok(())
+}
+
+#[rstest]
+fn overwrite_moves_to_trash() -> Result<(), Error> {
+    let trash_dir = assert_fs::TempDir::new()?;
+    let server = server(["--trash-dir", trash_dir.path().to_str().unwrap()]);
+    let trash_url = format!("{}__dufs__/trash", server.url());
+
+    let resp = fetch!(b"MOVE", format!("{}test.html", server.url()))
+        .header("Destination", format!("{}test.txt", server.url()))
+        .header("Overwrite", "T")
+        .send()?;
+    assert_eq!(resp.status(), 204);
+
+    let json: Value = serde_json::from_str(&reqwest::blocking::get(&trash_url)?.text()?)?;
+    let items = json["items"].as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["path"], "/test.txt");
+    Ok(())
+}
@@ -0,0 +1,140 @@
+mod fixtures;
+mod utils;
+
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+
+fn zip_of(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    use async_zip::{base::write::ZipFileWriter, Compression, ZipEntryBuilder};
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let mut writer = ZipFileWriter::new(vec![]);
+        for (name, data) in entries {
+            let builder = ZipEntryBuilder::new((*name).into(), Compression::Deflate);
+            writer.write_entry_whole(builder, data).await.unwrap();
+        }
+        writer.close().await.unwrap()
+    })
+}
+
+fn tar_gz_of(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    use tokio::io::AsyncWriteExt;
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let mut builder = tokio_tar::Builder::new(vec![]);
+        for (name, data) in entries {
+            let mut header = tokio_tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data).await.unwrap();
+        }
+        let tar = builder.into_inner().await.unwrap();
+        let mut encoder = async_compression::tokio::write::GzipEncoder::new(vec![]);
+        encoder.write_all(&tar).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        encoder.into_inner()
+    })
+}
+
+#[rstest]
+fn unpack_zip(#[with(&["--allow-upload"])] server: TestServer) -> Result<(), Error> {
+    let body = zip_of(&[("a.txt", b"alpha"), ("sub/b.txt", b"beta")]);
+    let resp = fetch!(b"PUT", format!("{}imported/?unzip", server.url()))
+        .body(body)
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let json: Value = resp.json()?;
+    let files = json["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert!(files.iter().all(|v| v["event_hash"].is_string()));
+
+    let resp = reqwest::blocking::get(format!("{}imported/sub/b.txt", server.url()))?;
+    assert_eq!(resp.text()?, "beta");
+    let resp = reqwest::blocking::get(format!("{}imported/a.txt?manifest=json", server.api_url()))?;
+    assert_eq!(resp.status(), 200);
+    Ok(())
+}
+
+#[rstest]
+fn unpack_tar_gz_skips_existing(
+    #[with(&["--allow-upload"])] server: TestServer,
+) -> Result<(), Error> {
+    let body = tar_gz_of(&[("./dir1/test.txt", b"replaced"), ("./dir1/new.txt", b"new")]);
+    let resp = fetch!(b"PUT", format!("{}?unzip", server.url()))
+        .body(body)
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let json: Value = resp.json()?;
+    assert_eq!(json["files"].as_array().unwrap().len(), 1);
+    assert_eq!(json["skipped"][0], "/dir1/test.txt");
+
+    let resp = reqwest::blocking::get(format!("{}dir1/new.txt", server.url()))?;
+    assert_eq!(resp.text()?, "new");
+    let resp = reqwest::blocking::get(format!("{}dir1/test.txt", server.url()))?;
+    assert_ne!(resp.text()?, "replaced");
+
+    let body = tar_gz_of(&[("./dir1/test.txt", b"replaced")]);
+    let resp = fetch!(b"PUT", format!("{}?unzip&overwrite", server.url()))
+        .body(body)
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = reqwest::blocking::get(format!("{}dir1/test.txt", server.url()))?;
+    assert_eq!(resp.text()?, "replaced");
+    // Entries are written next to the file and renamed over it
+    let leftovers: Vec<_> = std::fs::read_dir(server.path().join("dir1"))?
+        .filter_map(|v| v.ok())
+        .filter(|v| v.file_name().to_string_lossy().ends_with(".unpack"))
+        .collect();
+    assert!(leftovers.is_empty());
+    Ok(())
+}
+
+#[rstest]
+fn unpack_rejects_zip_slip(#[with(&["--allow-upload"])] server: TestServer) -> Result<(), Error> {
+    let body = zip_of(&[("ok.txt", b"ok"), ("../evil.txt", b"evil")]);
+    let resp = fetch!(b"PUT", format!("{}dir1/?unzip", server.url()))
+        .body(body)
+        .send()?;
+    assert_eq!(resp.status(), 400);
+    assert!(!server.path().join("evil.txt").exists());
+    assert!(!server.path().join("dir1/ok.txt").exists());
+
+    let resp = fetch!(b"PUT", format!("{}dir1/?unzip", server.url()))
+        .body("not an archive")
+        .send()?;
+    assert_eq!(resp.status(), 400);
+    Ok(())
+}
+
+#[rstest]
+fn unpack_requires_write_access(
+    #[with(&["--auth", "user:pass@/dir1:ro,/dir2:rw"])] server: TestServer,
+) -> Result<(), Error> {
+    let body = zip_of(&[("a.txt", b"alpha")]);
+    let resp = fetch!(b"PUT", format!("{}dir1/?unzip", server.url()))
+        .basic_auth("user", Some("pass"))
+        .body(body.clone())
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    let resp = fetch!(b"PUT", format!("{}dir2/?unzip", server.url()))
+        .basic_auth("user", Some("pass"))
+        .body(body)
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    Ok(())
+}
+
+#[rstest]
+fn unpack_refuses_denied_type(
+    #[with(&["--allow-upload", "--deny-upload-types", "exe"])] server: TestServer,
+) -> Result<(), Error> {
+    let body = zip_of(&[("a.txt", b"alpha"), ("bin/tool.exe", b"MZ")]);
+    let resp = fetch!(b"PUT", format!("{}imported/?unzip", server.url()))
+        .body(body)
+        .send()?;
+    assert_eq!(resp.status(), 415);
+    assert!(!server.path().join("imported").exists());
+    Ok(())
+}
@@ -0,0 +1,103 @@
+mod digest_auth_util;
+mod fixtures;
+mod utils;
+
+use digest_auth_util::send_with_digest_auth;
+use fixtures::{server, Error, TestServer};
+use rstest::rstest;
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+const UPLOADS_PATH: &str = "__dufs__/uploads";
+const UPLOAD_ID: &str = "spa-0123456789abcdef";
+
+/// Start a PUT of 10 bytes, sending only the first 5
+fn start_upload(server: &TestServer) -> Result<TcpStream, Error> {
+    let mut stream = TcpStream::connect(format!("localhost:{}", server.port()))?;
+    stream.write_all(
+        format!(
+            "PUT /api/stuck.txt HTTP/1.1\r\nHost: localhost\r\nX-Upload-Id: {UPLOAD_ID}\r\nContent-Length: 10\r\n\r\nHello"
+        )
+        .as_bytes(),
+    )?;
+    Ok(stream)
+}
+
+fn upload_progress(server: &TestServer) -> Result<Value, Error> {
+    let url = format!("{}{UPLOADS_PATH}/{UPLOAD_ID}", server.url());
+    for _ in 0..50 {
+        let resp = reqwest::blocking::get(&url)?;
+        if resp.status() == 200 {
+            let json: Value = resp.json()?;
+            if json["bytes_received"] == 5 {
+                return Ok(json);
+            }
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    Err("upload never showed up".into())
+}
+
+#[rstest]
+fn admin_cancels_upload(#[with(&["--allow-upload"])] server: TestServer) -> Result<(), Error> {
+    let mut stream = start_upload(&server)?;
+    let progress = upload_progress(&server)?;
+    assert_eq!(progress["path"], "/stuck.txt");
+    assert_eq!(progress["client"], "127.0.0.1");
+
+    let resp = reqwest::blocking::get(format!("{}{UPLOADS_PATH}", server.url()))?;
+    let json: Value = resp.json()?;
+    assert_eq!(json["uploads"][0]["id"], UPLOAD_ID);
+
+    let url = format!("{}{UPLOADS_PATH}/{UPLOAD_ID}", server.url());
+    assert_eq!(fetch!(b"DELETE", &url).send()?.status(), 204);
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp)?;
+    assert!(resp.starts_with("HTTP/1.1 409"), "{resp}");
+    assert!(
+        resp.contains(&format!("x-upload-id: {UPLOAD_ID}")),
+        "{resp}"
+    );
+    assert!(!server.path().join("stuck.txt").exists());
+    assert!(!server.path().join(".stuck.txt.partial").exists());
+    assert_eq!(reqwest::blocking::get(&url)?.status(), 404);
+    Ok(())
+}
+
+#[rstest]
+fn upload_id_header(#[with(&["--allow-upload"])] server: TestServer) -> Result<(), Error> {
+    let resp = fetch!(b"PUT", format!("{}file1", server.api_url()))
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let id = resp.headers().get("x-upload-id").unwrap().to_str()?;
+    assert_eq!(id.len(), 36);
+    // Short IDs are easy to guess, so the server picks one instead
+    let resp = fetch!(b"PUT", format!("{}file2", server.api_url()))
+        .header("X-Upload-Id", "1")
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_ne!(resp.headers().get("x-upload-id").unwrap(), "1");
+    Ok(())
+}
+
+#[rstest]
+fn uploads_require_admin(
+    #[with(&["--auth", "user:pass@/:rw", "--auth", "guest:pass@/dir1:rw"])] server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}{UPLOADS_PATH}", server.url());
+    let resp = reqwest::blocking::get(&url)?;
+    assert_eq!(resp.status(), 401);
+    let resp = send_with_digest_auth(fetch!(b"GET", &url), "guest", "pass")?;
+    assert_eq!(resp.status(), 403);
+    let resp = send_with_digest_auth(
+        fetch!(b"DELETE", format!("{url}/{UPLOAD_ID}")),
+        "guest",
+        "pass",
+    )?;
+    assert_eq!(resp.status(), 403);
+    let resp = send_with_digest_auth(fetch!(b"GET", &url), "user", "pass")?;
+    assert_eq!(resp.status(), 200);
+    Ok(())
+}
@@ -47,7 +47,44 @@ fn propfind_dir_depth2(server: TestServer) -> Result<(), Error> {
         .send()?;
     assert_eq!(resp.status(), 400);
     let body = resp.text()?;
-    assert_eq!(body, "Invalid depth: only 0 and 1 are allowed.");
+    assert_eq!(body, "Invalid depth: only 0, 1 and infinity are allowed.");
+    Ok(())
+}
+
+#[rstest]
+fn propfind_dir_depth_infinity(
+    #[with(&["--allow-upload", "--propfind-max-entries", "10"])] server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"PUT", format!("{}dir1/sub/a.txt", server.api_url()))
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    let resp = fetch!(b"PROPFIND", format!("{}dir1", server.url()))
+        .header("depth", "infinity")
+        .send()?;
+    assert_eq!(resp.status(), 207);
+    let body = resp.text()?;
+    assert!(body.contains("<D:href>/dir1/</D:href>"));
+    assert!(body.contains("<D:href>/dir1/sub/</D:href>"));
+    assert!(body.contains("<D:href>/dir1/sub/a.txt</D:href>"));
+    assert!(body.trim_end().ends_with("</D:multistatus>"));
+
+    // Too many entries under the root
+    let resp = fetch!(b"PROPFIND", server.url())
+        .header("depth", "infinity")
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    assert!(resp.text()?.contains("<D:propfind-finite-depth/>"));
+    Ok(())
+}
+
+#[rstest]
+fn propfind_dir_depth_infinity_disabled(server: TestServer) -> Result<(), Error> {
+    let resp = fetch!(b"PROPFIND", format!("{}dir1", server.url()))
+        .header("depth", "infinity")
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    assert!(resp.text()?.contains("<D:propfind-finite-depth/>"));
     Ok(())
 }
 
@@ -81,15 +118,68 @@ fn propfind_file(server: TestServer) -> Result<(), Error> {
     Ok(())
 }
 
+const PROPERTYUPDATE: &str = r#"<?xml version="1.0" encoding="utf-8" ?>
+<D:propertyupdate xmlns:D="DAV:" xmlns:Z="urn:example">
+<D:set><D:prop>{set}</D:prop></D:set>
+</D:propertyupdate>"#;
+
 #[rstest]
 fn proppatch_file(
     #[with(&["--allow-upload", "--allow-delete", "--allow-search", "--allow-archive", "--allow-symlink"])]
     server: TestServer,
 ) -> Result<(), Error> {
-    let resp = fetch!(b"PROPPATCH", format!("{}test.html", server.url())).send()?;
+    let url = format!("{}test.html", server.url());
+    let resp = fetch!(b"PROPPATCH", &url)
+        .body(PROPERTYUPDATE.replace("{set}", "<Z:color>red &amp; blue</Z:color><plain>1</plain>"))
+        .send()?;
     assert_eq!(resp.status(), 207);
     let body = resp.text()?;
     assert!(body.contains("<D:href>/test.html</D:href>"));
+    assert!(body.contains("HTTP/1.1 200 OK"));
+
+    let resp = fetch!(b"PROPFIND", &url).header("Depth", "0").send()?;
+    let body = resp.text()?;
+    assert!(body.contains(r#"<P1:color xmlns:P1="urn:example">red &amp; blue</P1:color>"#));
+    assert!(body.contains(r#"<plain xmlns="">1</plain>"#));
+
+    let resp = fetch!(b"PROPPATCH", &url)
+        .body(PROPERTYUPDATE.replace("{set}", ""))
+        .send()?;
+    assert_eq!(resp.status(), 400);
+    let resp = fetch!(b"PROPPATCH", &url)
+        .body(r#"<D:propertyupdate xmlns:D="DAV:" xmlns:Z="urn:example"><D:remove><D:prop><Z:color/></D:prop></D:remove></D:propertyupdate>"#)
+        .send()?;
+    assert_eq!(resp.status(), 207);
+    let body = fetch!(b"PROPFIND", &url)
+        .header("Depth", "0")
+        .send()?
+        .text()?;
+    assert!(!body.contains("urn:example"));
+    assert!(body.contains(r#"<plain xmlns="">1</plain>"#));
+    Ok(())
+}
+
+#[rstest]
+fn proppatch_live_property(
+    #[with(&["--allow-upload", "--allow-delete", "--allow-search", "--allow-archive", "--allow-symlink"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}test.html", server.url());
+    let resp = fetch!(b"PROPPATCH", &url)
+        .body(PROPERTYUPDATE.replace(
+            "{set}",
+            "<D:getcontentlength>1</D:getcontentlength><Z:color>red</Z:color>",
+        ))
+        .send()?;
+    assert_eq!(resp.status(), 207);
+    let body = resp.text()?;
+    assert!(body.contains("HTTP/1.1 403 Forbidden"));
+    assert!(body.contains("HTTP/1.1 424 Failed Dependency"));
+    let body = fetch!(b"PROPFIND", &url)
+        .header("Depth", "0")
+        .send()?
+        .text()?;
+    assert!(!body.contains("urn:example"));
     Ok(())
 }
 
@@ -221,25 +311,168 @@ fn move_file_404(
     Ok(())
 }
 
+#[rstest]
+fn copy_dir(
+    #[with(&["--allow-upload", "--allow-delete", "--allow-search", "--allow-archive", "--allow-symlink"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"COPY", format!("{}dir1", server.url()))
+        .header("Destination", format!("{}dir1-copy", server.url()))
+        .send()?;
+    assert_eq!(resp.status(), 204);
+    for file in FILES {
+        assert!(server.path().join("dir1-copy").join(file).is_file());
+    }
+
+    let resp = fetch!(b"COPY", format!("{}dir1", server.url()))
+        .header("Destination", format!("{}dir1-empty", server.url()))
+        .header("Depth", "0")
+        .send()?;
+    assert_eq!(resp.status(), 204);
+    assert_eq!(
+        std::fs::read_dir(server.path().join("dir1-empty"))?.count(),
+        0
+    );
+
+    let resp = fetch!(b"COPY", format!("{}dir1", server.url()))
+        .header("Destination", format!("{}dir1/sub", server.url()))
+        .send()?;
+    assert_eq!(resp.status(), 403);
+    Ok(())
+}
+
+#[rstest]
+fn copy_overwrite(
+    #[with(&["--allow-upload", "--allow-delete", "--allow-search", "--allow-archive", "--allow-symlink"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"COPY", format!("{}test.txt", server.url()))
+        .header("Destination", format!("{}test.html", server.url()))
+        .header("Overwrite", "F")
+        .send()?;
+    assert_eq!(resp.status(), 412);
+
+    let resp = fetch!(b"COPY", format!("{}dir1", server.url()))
+        .header("Destination", format!("{}dir2", server.url()))
+        .header("Overwrite", "T")
+        .send()?;
+    assert_eq!(resp.status(), 204);
+    let resp = fetch!(b"MOVE", format!("{}test.txt", server.url()))
+        .header("Destination", format!("{}test.html", server.url()))
+        .send()?;
+    assert_eq!(resp.status(), 204);
+    let resp = reqwest::blocking::get(format!("{}test.html", server.api_url()))?;
+    assert_eq!(resp.text()?, "This is test.txt");
+    Ok(())
+}
+
+#[rstest]
+fn move_dir_with_provenance(
+    #[with(&["--allow-upload", "--allow-delete", "--allow-search", "--allow-archive", "--allow-symlink"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let resp = fetch!(b"PUT", format!("{}dir1/sub/a.txt", server.api_url()))
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+
+    let resp = fetch!(b"MOVE", format!("{}dir1/sub", server.url()))
+        .header("Destination", format!("{}dir2/moved", server.url()))
+        .header("Depth", "0")
+        .send()?;
+    assert_eq!(resp.status(), 400);
+    let resp = fetch!(b"MOVE", format!("{}dir1/sub", server.url()))
+        .header("Destination", format!("{}dir2/moved", server.url()))
+        .send()?;
+    assert_eq!(resp.status(), 204);
+
+    let resp = reqwest::blocking::get(format!(
+        "{}dir2/moved/a.txt?manifest=json",
+        server.api_url()
+    ))?;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(json["events"][0]["action"], "mint");
+    Ok(())
+}
+
+const LOCKINFO: &str = r#"<?xml version="1.0" encoding="utf-8" ?>
+<D:lockinfo xmlns:D="DAV:">
+<D:lockscope><D:exclusive/></D:lockscope>
+<D:locktype><D:write/></D:locktype>
+<D:owner><D:href>alice</D:href></D:owner>
+</D:lockinfo>"#;
+
 #[rstest]
 fn lock_file(
     #[with(&["--allow-upload", "--allow-delete", "--allow-search", "--allow-archive", "--allow-symlink"])]
     server: TestServer,
 ) -> Result<(), Error> {
-    let resp = fetch!(b"LOCK", format!("{}test.html", server.url())).send()?;
+    let resp = fetch!(b"LOCK", format!("{}test.html", server.url()))
+        .header("Timeout", "Second-60")
+        .body(LOCKINFO)
+        .send()?;
     assert_eq!(resp.status(), 200);
+    let token = resp.headers()["lock-token"].to_str()?.to_string();
+    assert!(token.starts_with("<opaquelocktoken:"));
     let body = resp.text()?;
     assert!(body.contains("<D:href>/test.html</D:href>"));
+    assert!(body.contains("<D:owner><D:href>alice</D:href></D:owner>"));
+    assert!(body.contains("<D:timeout>Second-60</D:timeout>"));
+
+    // Refreshing keeps the token
+    let resp = fetch!(b"LOCK", format!("{}test.html", server.url()))
+        .header("If", format!("({token})"))
+        .send()?;
+    assert_eq!(resp.status(), 200);
+    assert!(resp.text()?.contains(&token[1..token.len() - 1]));
+    let resp = fetch!(b"LOCK", format!("{}test.html", server.url())).send()?;
+    assert_eq!(resp.status(), 412);
     Ok(())
 }
 
 #[rstest]
-fn lock_file_404(
+fn lock_new_file(
     #[with(&["--allow-upload", "--allow-delete", "--allow-search", "--allow-archive", "--allow-symlink"])]
     server: TestServer,
 ) -> Result<(), Error> {
-    let resp = fetch!(b"LOCK", format!("{}404", server.url())).send()?;
-    assert_eq!(resp.status(), 404);
+    let resp = fetch!(b"LOCK", format!("{}new.docx", server.url()))
+        .body(LOCKINFO)
+        .send()?;
+    assert_eq!(resp.status(), 201);
+    assert!(server.path().join("new.docx").exists());
+    let resp = fetch!(b"LOCK", format!("{}404/new.docx", server.url()))
+        .body(LOCKINFO)
+        .send()?;
+    assert_eq!(resp.status(), 409);
+    Ok(())
+}
+
+#[rstest]
+fn locked_file_rejects_writes(
+    #[with(&["--allow-upload", "--allow-delete", "--allow-search", "--allow-archive", "--allow-symlink"])]
+    server: TestServer,
+) -> Result<(), Error> {
+    let url = format!("{}dir1/test.html", server.url());
+    let resp = fetch!(b"LOCK", &url).body(LOCKINFO).send()?;
+    let token = resp.headers()["lock-token"].to_str()?.to_string();
+
+    let resp = fetch!(b"LOCK", &url).body(LOCKINFO).send()?;
+    assert_eq!(resp.status(), 423);
+    let resp = fetch!(b"PUT", &url).body(b"abc".to_vec()).send()?;
+    assert_eq!(resp.status(), 423);
+    let resp = fetch!(b"DELETE", format!("{}dir1/", server.url())).send()?;
+    assert_eq!(resp.status(), 423);
+    let resp = fetch!(b"MOVE", format!("{}test.txt", server.url()))
+        .header("Destination", &url)
+        .send()?;
+    assert_eq!(resp.status(), 423);
+
+    let resp = fetch!(b"PUT", &url)
+        .header("If", format!("({token})"))
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
     Ok(())
 }
 
@@ -248,8 +481,19 @@ fn unlock_file(
     #[with(&["--allow-upload", "--allow-delete", "--allow-search", "--allow-archive", "--allow-symlink"])]
     server: TestServer,
 ) -> Result<(), Error> {
-    let resp = fetch!(b"LOCK", format!("{}test.html", server.url())).send()?;
-    assert_eq!(resp.status(), 200);
+    let url = format!("{}test.html", server.url());
+    let resp = fetch!(b"LOCK", &url).body(LOCKINFO).send()?;
+    let token = resp.headers()["lock-token"].to_str()?.to_string();
+    let resp = fetch!(b"UNLOCK", &url)
+        .header("Lock-Token", "<opaquelocktoken:other>")
+        .send()?;
+    assert_eq!(resp.status(), 409);
+    let resp = fetch!(b"UNLOCK", &url)
+        .header("Lock-Token", &token)
+        .send()?;
+    assert_eq!(resp.status(), 204);
+    let resp = fetch!(b"PUT", &url).body(b"abc".to_vec()).send()?;
+    assert_eq!(resp.status(), 201);
     Ok(())
 }
 
@@ -258,7 +502,47 @@ fn unlock_file_404(
     #[with(&["--allow-upload", "--allow-delete", "--allow-search", "--allow-archive", "--allow-symlink"])]
     server: TestServer,
 ) -> Result<(), Error> {
-    let resp = fetch!(b"LOCK", format!("{}404", server.url())).send()?;
+    let resp = fetch!(b"UNLOCK", format!("{}404", server.url()))
+        .header("Lock-Token", "<opaquelocktoken:other>")
+        .send()?;
+    assert_eq!(resp.status(), 404);
+    Ok(())
+}
+
+#[rstest]
+fn provenance_folder(server: TestServer) -> Result<(), Error> {
+    let resp = fetch!(b"PUT", format!("{}dir1/minted.txt", server.url()))
+        .body(b"abc".to_vec())
+        .send()?;
+    assert_eq!(resp.status(), 201);
+
+    let resp = fetch!(b"PROPFIND", format!("{}.provenance/dir1/", server.url())).send()?;
+    assert_eq!(resp.status(), 207);
+    let body = resp.text()?;
+    assert!(body.contains("<D:href>/.provenance/dir1/</D:href>"));
+    assert!(body.contains("<D:href>/.provenance/dir1/minted.txt.manifest.json</D:href>"));
+    assert!(!body.contains("test.txt.manifest.json"));
+
+    let resp = fetch!(b"PROPFIND", format!("{}.provenance/", server.url())).send()?;
+    assert!(resp.text()?.contains("<D:href>/.provenance/dir1/</D:href>"));
+
+    let url = format!("{}.provenance/dir1/minted.txt.manifest.json", server.url());
+    let resp = fetch!(b"GET", &url).send()?;
+    assert_eq!(resp.status(), 200);
+    let manifest: serde_json::Value = serde_json::from_str(&resp.text()?)?;
+    assert_eq!(
+        manifest["artifact"]["sha256_hex"],
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+
+    let resp = fetch!(
+        b"GET",
+        format!("{}.provenance/dir1/test.txt.manifest.json", server.url())
+    )
+    .send()?;
     assert_eq!(resp.status(), 404);
+    let resp = fetch!(b"DELETE", &url).send()?;
+    assert_eq!(resp.status(), 403);
+    assert!(server.path().join("dir1/minted.txt").exists());
     Ok(())
 }